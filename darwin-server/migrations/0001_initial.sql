@@ -0,0 +1,34 @@
+-- Times are Unix milliseconds; JSON columns hold serialized `serde_json::Value`s.
+
+-- Stored uploads, keyed like their storage objects (`{file_id}_{name}`)
+CREATE TABLE uploads (
+    file_id TEXT PRIMARY KEY NOT NULL,
+    storage_key TEXT NOT NULL UNIQUE,
+    original_name TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    sha256 TEXT NOT NULL,
+    format TEXT NOT NULL,
+    created_at_ms INTEGER NOT NULL
+);
+
+-- The latest analysis of each workspace
+CREATE TABLE workspaces (
+    workspace_id TEXT PRIMARY KEY NOT NULL,
+    file_path TEXT,
+    last_analysis TEXT NOT NULL,
+    updated_at_ms INTEGER NOT NULL
+);
+
+-- Every background job, finished or not
+CREATE TABLE jobs (
+    job_id TEXT PRIMARY KEY NOT NULL,
+    kind TEXT NOT NULL,
+    status TEXT NOT NULL,
+    submitted_at_ms INTEGER NOT NULL,
+    started_at_ms INTEGER,
+    finished_at_ms INTEGER,
+    result TEXT,
+    error TEXT
+);
+
+CREATE INDEX jobs_by_status ON jobs (status);
@@ -5,6 +5,10 @@ class DarwinAgentClient {
         this.ws = null;
         this.reconnectAttempts = 0;
         this.maxReconnectAttempts = 5;
+        this.sessionToken = sessionStorage.getItem('darwin-session-token');
+        // msg_ids must keep increasing for a resumed session, so the counter outlives reloads
+        this.lastMsgId = Number(sessionStorage.getItem('darwin-last-msg-id') || 0);
+        this.unacked = new Set();
         this.chatMessages = document.getElementById('chat-messages');
         this.chatInput = document.getElementById('chat-input');
         this.sendBtn = document.getElementById('send-btn');
@@ -28,6 +32,11 @@ class DarwinAgentClient {
                 this.updateStatus(true);
                 this.reconnectAttempts = 0;
                 this.addSystemMessage('Connected to Darwin agents ✓');
+
+                // Resume the previous session if we have a token for it
+                if (this.sessionToken) {
+                    this.ws.send(JSON.stringify({ type: 'session', token: this.sessionToken }));
+                }
             };
 
             this.ws.onmessage = (event) => {
@@ -40,9 +49,23 @@ class DarwinAgentClient {
                 this.addSystemMessage('Connection error. Retrying...');
             };
 
-            this.ws.onclose = () => {
-                console.log('WebSocket closed');
+            this.ws.onclose = (event) => {
+                console.log('WebSocket closed', event.code);
                 this.updateStatus(false);
+                // Close codes are documented on `agent_routes` in darwin-server/src/agents.rs
+                if (event.code === 1000) {
+                    return;
+                }
+                if (event.code === 1001) {
+                    this.addSystemMessage('Server is restarting. Reconnecting shortly...');
+                } else if (event.code === 1011 || event.code === 4029) {
+                    this.addSystemMessage(`${event.reason}. Reconnecting...`);
+                }
+                if (event.code === 4029) {
+                    const retryAfter = Number((event.reason.match(/retry after (\d+)s/) || [])[1] || 10);
+                    setTimeout(() => this.attemptReconnect(), retryAfter * 1000);
+                    return;
+                }
                 this.attemptReconnect();
             };
 
@@ -77,6 +100,11 @@ class DarwinAgentClient {
         this.chatInput.addEventListener('keypress', (e) => {
             if (e.key === 'Enter') this.sendMessage();
         });
+        this.agentSelect.addEventListener('change', () => {
+            if (this.ws && this.ws.readyState === WebSocket.OPEN) {
+                this.ws.send(JSON.stringify({ type: 'switch_agent', agent_type: this.agentSelect.value }));
+            }
+        });
     }
 
     sendMessage() {
@@ -86,11 +114,16 @@ class DarwinAgentClient {
         }
 
         const agentType = this.agentSelect.value;
+        this.lastMsgId += 1;
+        sessionStorage.setItem('darwin-last-msg-id', String(this.lastMsgId));
         const message = {
+            protocol_version: 1,
             agent_type: agentType,
             content: text,
-            timestamp: Date.now()
+            timestamp: Date.now(),
+            msg_id: String(this.lastMsgId)
         };
+        this.unacked.add(message.msg_id);
 
         // Display user message
         this.addUserMessage(text);
@@ -105,19 +138,35 @@ class DarwinAgentClient {
     handleMessage(message) {
         console.log('Received:', message);
 
-        if (message.type === 'system') {
+        if (message.type === 'ack') {
+            this.unacked.delete(message.msg_id);
+        } else if (message.type === 'session') {
+            this.sessionToken = message.token;
+            sessionStorage.setItem('darwin-session-token', message.token);
+            if (message.resumed) {
+                this.addSystemMessage('Session resumed ✓');
+            }
+            if (message.active_agent) {
+                this.agentSelect.value = message.active_agent;
+            }
+        } else if (message.type === 'active_agent') {
+            this.agentSelect.value = message.agent_type;
+            this.addSystemMessage(`Now talking to ${message.agent_name}`);
+        } else if (message.type === 'system') {
             this.addSystemMessage(message.content);
+        } else if (message.type === 'error') {
+            this.addSystemMessage(`⚠️ ${message.content}`);
+        } else if (message.type === 'rate_limited') {
+            const seconds = Math.ceil(message.retry_after_ms / 1000);
+            this.addSystemMessage(`⏳ Too many messages; that one was not sent. Try again in ${seconds}s.`);
+        } else if (message.type === 'tool_start') {
+            this.addSystemMessage(`🔧 Running ${message.tool_name}...`);
+        } else if (message.type === 'tool_result') {
+            this.addSystemMessage(`✓ ${message.tool_name} finished`);
         } else if (message.agent_name) {
-            // Agent response
+            // Agent response (tool calls were already shown as they ran)
             this.addAgentMessage(message.agent_name, message.response);
 
-            // Handle tool calls if any
-            if (message.tool_calls && message.tool_calls.length > 0) {
-                message.tool_calls.forEach(tool => {
-                    this.addSystemMessage(`🔧 Used tool: ${tool.tool_name}`);
-                });
-            }
-
             // Update metrics if available
             if (message.metrics) {
                 this.updateMetrics(message.metrics);
@@ -4,6 +4,15 @@ const state = {
     metrics: null
 };
 
+// Error bodies are `{error: "message"}`, or `{error: {kind, upstream_status}}` when
+// the Julia backend answered with something other than JSON
+function errorMessage(error) {
+    if (error && typeof error === 'object') {
+        return `Analysis backend returned an unexpected ${error.upstream_status} response (${error.kind})`;
+    }
+    return error;
+}
+
 // DOM Elements
 const dropZone = document.getElementById('drop-zone');
 const fileInput = document.getElementById('file-input');
@@ -50,14 +59,15 @@ async function handleFile(file) {
         });
 
         const data = await res.json();
-        state.filePath = data.file_path;
+        if (!res.ok) throw new Error(errorMessage(data.error));
+        state.filePath = data.files[0].file_path;
 
         // Auto-start analysis
         runAnalysis();
 
     } catch (err) {
         console.error(err);
-        alert('Upload failed');
+        alert(`Upload failed: ${err.message}`);
         dropZone.innerHTML = '<div class="icon">❌</div><h3>Error</h3>';
     }
 }
@@ -154,11 +164,14 @@ function goToOptimize() {
 }
 
 async function runOptimization() {
+    const porosity = parseFloat(document.getElementById('opt-porosity').value);
     const params = {
-        porosity: parseFloat(document.getElementById('opt-porosity').value),
+        porosity,
         pore_size: parseFloat(document.getElementById('opt-pore-size').value),
         method: document.getElementById('opt-method').value,
-        resolution: state.voxelSize
+        resolution: state.voxelSize,
+        // Candidates are ranked by how close they land to the requested porosity
+        objectives: [{ metric: 'porosity', direction: 'minimize', target: porosity, weight: 1 }]
     };
 
     try {
@@ -173,17 +186,19 @@ async function runOptimization() {
         });
 
         const data = await res.json();
+        if (!res.ok) throw new Error(errorMessage(data.error));
+        const best = data.candidates[0];
 
         // Show results
         document.getElementById('res-orig-porosity').textContent = (state.metrics.porosity * 100).toFixed(1) + '%';
-        document.getElementById('res-opt-porosity').textContent = (data.optimized_metrics.porosity * 100).toFixed(1) + '%';
+        document.getElementById('res-opt-porosity').textContent = (best.metrics.porosity * 100).toFixed(1) + '%';
 
         // Setup download
         // Note: In a real app, we'd serve the file from the backend
         // Here we assume the backend returns a path we can't directly access from browser
         // So we'd need a download endpoint. For now, just a placeholder.
         document.getElementById('download-link').href = '#';
-        document.getElementById('download-link').onclick = () => alert('Download simulated: ' + data.stl_path);
+        document.getElementById('download-link').onclick = () => alert('Download simulated: ' + best.stl_path);
 
         showStage('stage-results');
 
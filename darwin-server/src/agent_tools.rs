@@ -0,0 +1,169 @@
+//! Agent tools that run inside darwin-server instead of Julia.
+//!
+//! Julia learns the available tools from the `local_tools` list sent with every
+//! `/agents/chat/stream` request. When it wants one, it emits a `tool_start` for it
+//! and finishes the turn; `route_to_agent` runs the tool and re-sends the turn with
+//! the outcome under `tool_results` so the agent can continue.
+//!
+//! A `tool_start` may carry an `id` and a `depends_on` list of earlier ids. Calls
+//! with nothing outstanding to wait for run concurrently, up to
+//! `DARWIN_AGENT_TOOL_CONCURRENCY` (default 4) at a time; the others start once
+//! their dependencies have finished. Results go back to Julia in request order.
+
+use futures::future::BoxFuture;
+use serde_json::Value;
+use std::{collections::BTreeMap, future::Future, sync::Arc};
+use tokio::sync::Mutex;
+
+use crate::agents::AgentWorkspaceState;
+
+/// What a local tool may look at: the shared agent workspace and the calling session.
+#[derive(Clone)]
+pub struct ToolContext {
+    pub workspace: Arc<Mutex<AgentWorkspaceState>>,
+    pub session_id: String,
+}
+
+type ToolHandler = Arc<dyn Fn(Value, ToolContext) -> BoxFuture<'static, Result<Value, String>> + Send + Sync>;
+
+struct RegisteredTool {
+    description: String,
+    handler: ToolHandler,
+}
+
+/// `tool_name -> async fn(args) -> result` for tools executed without a Julia round-trip.
+#[derive(Default)]
+pub struct ToolRegistry {
+    tools: BTreeMap<String, RegisteredTool>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with the built-in tools (`convert_units`, `get_session_metrics`).
+    pub fn with_builtin_tools() -> Self {
+        let mut registry = Self::new();
+        registry.register(
+            "convert_units",
+            "Convert a length between m, mm, um and nm. Args: {value, from, to}",
+            |args, _| async move { convert_units(&args) },
+        );
+        registry.register(
+            "get_session_metrics",
+            "Metrics and scaffold files recorded in the current chat session. No args",
+            |_, ctx| async move { session_metrics(&ctx).await },
+        );
+        registry
+    }
+
+    /// Register (or replace) a tool.
+    pub fn register<F, Fut>(&mut self, name: &str, description: &str, handler: F)
+    where
+        F: Fn(Value, ToolContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value, String>> + Send + 'static,
+    {
+        let handler: ToolHandler = Arc::new(move |args, ctx| Box::pin(handler(args, ctx)));
+        self.tools.insert(
+            name.to_string(),
+            RegisteredTool {
+                description: description.to_string(),
+                handler,
+            },
+        );
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.tools.contains_key(name)
+    }
+
+    /// `[{name, description}]`, as advertised to Julia.
+    pub fn describe(&self) -> Vec<Value> {
+        self.tools
+            .iter()
+            .map(|(name, tool)| serde_json::json!({"name": name, "description": tool.description}))
+            .collect()
+    }
+
+    /// Run `name`; `None` if no such tool is registered. Failures become `{"error": ...}`
+    /// so the agent sees them as an ordinary result.
+    pub async fn call(&self, name: &str, args: Value, ctx: ToolContext) -> Option<Value> {
+        let handler = self.tools.get(name)?.handler.clone();
+        Some(handler(args, ctx).await.unwrap_or_else(|e| serde_json::json!({"error": e})))
+    }
+}
+
+fn millimetres_per(unit: &str) -> Option<f64> {
+    match unit.to_ascii_lowercase().as_str() {
+        "m" => Some(1000.0),
+        "mm" => Some(1.0),
+        "um" | "µm" | "micron" | "microns" => Some(1e-3),
+        "nm" => Some(1e-6),
+        _ => None,
+    }
+}
+
+fn convert_units(args: &Value) -> Result<Value, String> {
+    let value = args.get("value").and_then(Value::as_f64).ok_or("`value` must be a number")?;
+    let unit = |key: &str| {
+        args.get(key)
+            .and_then(Value::as_str)
+            .and_then(|u| millimetres_per(u).map(|f| (u.to_string(), f)))
+            .ok_or(format!("`{}` must be one of m, mm, um, nm", key))
+    };
+    let ((_, from), (to_name, to)) = (unit("from")?, unit("to")?);
+    Ok(serde_json::json!({"value": value * from / to, "unit": to_name}))
+}
+
+async fn session_metrics(ctx: &ToolContext) -> Result<Value, String> {
+    let ws = ctx.workspace.lock().await;
+    let session = ws.sessions.get(&ctx.session_id).ok_or("session not found")?;
+    Ok(serde_json::json!({
+        "metrics": session.metrics,
+        "scaffolds": session.scaffolds,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn context() -> ToolContext {
+        let mut ws = AgentWorkspaceState::new("http://127.0.0.1:1".to_string());
+        let session_id = ws.create_session();
+        ws.sessions.get_mut(&session_id).unwrap().metrics = json!({"porosity": 0.7});
+        ToolContext {
+            workspace: Arc::new(Mutex::new(ws)),
+            session_id,
+        }
+    }
+
+    #[tokio::test]
+    async fn builtin_tools_run_locally() {
+        let registry = ToolRegistry::with_builtin_tools();
+
+        let converted = registry
+            .call("convert_units", json!({"value": 350, "from": "um", "to": "mm"}), context())
+            .await
+            .unwrap();
+        assert!((converted["value"].as_f64().unwrap() - 0.35).abs() < 1e-12);
+        assert_eq!(converted["unit"], "mm");
+
+        let metrics = registry.call("get_session_metrics", json!({}), context()).await.unwrap();
+        assert_eq!(metrics["metrics"]["porosity"], 0.7);
+
+        assert_eq!(registry.call("mesh_scaffold", json!({}), context()).await, None);
+    }
+
+    #[tokio::test]
+    async fn tool_errors_become_results() {
+        let registry = ToolRegistry::with_builtin_tools();
+        let result = registry
+            .call("convert_units", json!({"value": 1, "from": "ft", "to": "mm"}), context())
+            .await
+            .unwrap();
+        assert_eq!(result, json!({"error": "`from` must be one of m, mm, um, nm"}));
+    }
+}
@@ -1,21 +1,191 @@
 use axum::{
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
         State,
     },
-    response::IntoResponse,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     routing::get,
 };
-use futures::{sink::SinkExt, stream::StreamExt};
+use futures::{
+    sink::{Sink, SinkExt},
+    stream::{FuturesUnordered, StreamExt},
+};
+use hmac::{Hmac, Mac};
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::agent_tools::{ToolContext, ToolRegistry};
+use crate::error::AppError;
+use crate::observability;
+
+/// How long a resume token stays valid once its session has disconnected.
+const RESUME_TOKEN_TTL_SECS: u64 = 30 * 60;
+
+/// Julia round-trips allowed for local tool calls within one user message.
+const MAX_LOCAL_TOOL_ROUNDS: usize = 4;
+
+/// Local tool calls run at once when none depends on another (see `DARWIN_AGENT_TOOL_CONCURRENCY`).
+pub const DEFAULT_TOOL_CONCURRENCY: usize = 4;
+
+/// Close code (application range) telling the client to back off before reconnecting.
+pub const CLOSE_RATE_LIMITED: u16 = 4029;
+
+/// `Sec-WebSocket-Protocol` values `/ws/agent-chat` speaks, preferred first.
+///
+/// * `darwin.agent.v1` - JSON text frames as documented on [`agent_routes`].
+///
+/// Clients that offer no subprotocol get `darwin.agent.v1` framing without one
+/// being echoed, so clients predating negotiation keep working.
+pub const AGENT_SUBPROTOCOLS: &[&str] = &["darwin.agent.v1"];
+
+/// Close reasons may not exceed this many bytes (RFC 6455 §5.5).
+const MAX_CLOSE_REASON_BYTES: usize = 123;
+
+/// An agent Julia can play; `agent_type` is what clients send.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct AgentInfo {
+    pub agent_type: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+/// Available agents. The first one is each new session's active agent.
+pub const AGENTS: &[AgentInfo] = &[
+    AgentInfo {
+        agent_type: "design",
+        name: "Design Agent",
+        description: "Scaffold design and optimisation: TPMS surfaces, porosity, pore size, mechanics",
+    },
+    AgentInfo {
+        agent_type: "analysis",
+        name: "Analysis Agent",
+        description: "Scaffold characterisation and validation against literature targets",
+    },
+    AgentInfo {
+        agent_type: "synthesis",
+        name: "Synthesis Agent",
+        description: "Fabrication methods, bioprinting and material selection",
+    },
+];
+
+pub fn find_agent(agent_type: &str) -> Option<&'static AgentInfo> {
+    AGENTS.iter().find(|agent| agent.agent_type == agent_type)
+}
+
+/// Version of the chat message envelope this server speaks.
+pub const PROTOCOL_VERSION: u64 = 1;
 
+/// A chat message from the client:
+///
+/// ```json
+/// {"protocol_version": 1, "agent_type": "design", "content": "...", "timestamp": 1700000000000, "msg_id": "42"}
+/// ```
+///
+/// `protocol_version` defaults to 1 and must equal [`PROTOCOL_VERSION`]; `agent_type`
+/// and `msg_id` are optional. Frames that don't fit are answered with
+/// `{"type":"error","content":"invalid message: ..."}`.
+///
+/// A message with a `msg_id` is acknowledged with `{"type":"ack","msg_id":...}` as
+/// soon as it is accepted, before the agent runs, and every frame of its reply
+/// carries the same `msg_id`. Ids must increase within a session (see
+/// [`compare_msg_ids`]); a repeated or older id is answered with an `error` frame
+/// whose `reason` is `duplicate` or `out_of_order`, and is not processed.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentMessage {
-    pub agent_type: String,  // "design", "analysis", "synthesis"
+    #[serde(default = "default_protocol_version")]
+    pub protocol_version: u64,
+    /// Falls back to the session's active agent when omitted
+    #[serde(default)]
+    pub agent_type: Option<String>,  // "design", "analysis", "synthesis"
     pub content: String,
     pub timestamp: u64,
+    /// Client-chosen id echoed on the ack and on every frame of the reply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub msg_id: Option<String>,
+}
+
+fn default_protocol_version() -> u64 {
+    PROTOCOL_VERSION
+}
+
+impl AgentMessage {
+    /// Parse a chat frame, checking the protocol version before the rest of the schema.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let value: serde_json::Value = serde_json::from_str(text).map_err(|e| format!("invalid message: {}", e))?;
+        match value.get("protocol_version") {
+            None => {}
+            Some(v) if v.as_u64() == Some(PROTOCOL_VERSION) => {}
+            Some(v) => {
+                return Err(format!(
+                    "invalid message: unsupported protocol_version {} (this server speaks {})",
+                    v, PROTOCOL_VERSION
+                ))
+            }
+        }
+        serde_json::from_value(value).map_err(|e| format!("invalid message: {}", e))
+    }
+}
+
+/// How `msg_id`s are ordered: numerically when both are unsigned integers, else
+/// as strings (so zero-padded counters and ULIDs work too).
+pub fn compare_msg_ids(a: &str, b: &str) -> std::cmp::Ordering {
+    match (a.parse::<u64>(), b.parse::<u64>()) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+/// Why a chat message's `msg_id` was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MsgIdRejection {
+    Duplicate,
+    OutOfOrder,
+}
+
+impl MsgIdRejection {
+    fn reason(self) -> &'static str {
+        match self {
+            Self::Duplicate => "duplicate",
+            Self::OutOfOrder => "out_of_order",
+        }
+    }
+
+    fn frame(self, msg_id: &str, last_seen: &str) -> serde_json::Value {
+        let content = match self {
+            Self::Duplicate => format!("msg_id {} was already received", msg_id),
+            Self::OutOfOrder => format!("msg_id {} arrived after {}", msg_id, last_seen),
+        };
+        serde_json::json!({
+            "type": "error",
+            "msg_id": msg_id,
+            "reason": self.reason(),
+            "content": content,
+        })
+    }
+}
+
+/// Add `"msg_id"` to an outgoing frame when the message being answered had one.
+fn tag_frame(frame: &mut serde_json::Value, msg_id: Option<&str>) {
+    if let (Some(msg_id), Some(obj)) = (msg_id, frame.as_object_mut()) {
+        obj.insert("msg_id".to_string(), msg_id.into());
+    }
+}
+
+/// Control frames a client may send instead of a chat message.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ControlFrame {
+    /// Make `agent_type` the session's active agent
+    SwitchAgent { agent_type: String },
+    ListAgents,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,43 +194,391 @@ pub struct AgentResponse {
     pub response: String,
     pub tool_calls: Vec<ToolCall>,
     pub status: String,  // "thinking", "using_tool", "complete"
+    /// The `msg_id` of the message this answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub msg_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolCall {
+    /// Julia's id for the call, which later calls may name in `depends_on`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     pub tool_name: String,
     pub args: serde_json::Value,
+    /// Ids of calls in the same turn that must finish before this one starts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
     pub result: Option<serde_json::Value>,
 }
 
-pub struct AgentWorkspaceState {
+/// Why an agent turn could not be answered; decides how the socket is closed.
+#[derive(Debug, Clone, PartialEq)]
+enum AgentError {
+    /// Julia could not be reached or failed the request
+    Backend(String),
+    /// Julia answered `429 Too Many Requests`
+    RateLimited { retry_after_secs: Option<u64> },
+}
+
+impl std::fmt::Display for AgentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Backend(e) => write!(f, "Agent backend unavailable: {}", e),
+            Self::RateLimited { retry_after_secs: Some(secs) } => write!(f, "Rate limited; retry after {}s", secs),
+            Self::RateLimited { retry_after_secs: None } => write!(f, "Rate limited; retry later"),
+        }
+    }
+}
+
+impl AgentError {
+    fn close_frame(&self) -> CloseFrame<'static> {
+        let code = match self {
+            Self::Backend(_) => close_code::ERROR,
+            Self::RateLimited { .. } => CLOSE_RATE_LIMITED,
+        };
+        CloseFrame { code, reason: close_reason(self.to_string()) }
+    }
+}
+
+/// `reason` cut on a char boundary to fit in a close frame.
+fn close_reason(reason: String) -> Cow<'static, str> {
+    if reason.len() <= MAX_CLOSE_REASON_BYTES {
+        return reason.into();
+    }
+    let mut end = MAX_CLOSE_REASON_BYTES;
+    while !reason.is_char_boundary(end) {
+        end -= 1;
+    }
+    reason[..end].to_string().into()
+}
+
+/// How many chat messages a session may send: `burst` at once, refilled evenly over `window`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MessageRateLimit {
+    pub burst: u32,
+    pub window: Duration,
+}
+
+impl Default for MessageRateLimit {
+    fn default() -> Self {
+        Self {
+            burst: 5,
+            window: Duration::from_secs(10),
+        }
+    }
+}
+
+impl MessageRateLimit {
+    /// From `DARWIN_AGENT_RATE_LIMIT_MESSAGES` and `DARWIN_AGENT_RATE_LIMIT_WINDOW_SECS`.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let burst = std::env::var("DARWIN_AGENT_RATE_LIMIT_MESSAGES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&n: &u32| n > 0)
+            .unwrap_or(defaults.burst);
+        let window = std::env::var("DARWIN_AGENT_RATE_LIMIT_WINDOW_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&secs: &u64| secs > 0)
+            .map(Duration::from_secs)
+            .unwrap_or(defaults.window);
+        Self { burst, window }
+    }
+}
+
+/// From `DARWIN_AGENT_TOOL_CONCURRENCY`, falling back to [`DEFAULT_TOOL_CONCURRENCY`].
+pub fn tool_concurrency_from_env() -> usize {
+    std::env::var("DARWIN_AGENT_TOOL_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&n: &usize| n > 0)
+        .unwrap_or(DEFAULT_TOOL_CONCURRENCY)
+}
+
+/// Token bucket enforcing a [`MessageRateLimit`].
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    limit: MessageRateLimit,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(limit: MessageRateLimit) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst as f64,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Spend one token, or return how long until one is available.
+    pub fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+        let per_sec = self.limit.burst as f64 / self.limit.window.as_secs_f64();
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * per_sec).min(self.limit.burst as f64);
+        self.refilled_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / per_sec))
+        }
+    }
+}
+
+/// Per-connection agent state, kept alive after a disconnect so it can be resumed.
+pub struct AgentSession {
     pub scaffolds: Vec<String>,  // Paths to scaffold files
     pub metrics: serde_json::Value,
     pub chat_history: Vec<(String, String)>,  // (role, content)
+    pub active_agent: String,  // Agent for messages without an `agent_type`
+    /// Chat messages allowed; survives reconnects so resuming doesn't reset it
+    pub message_budget: TokenBucket,
+    /// Highest `msg_id` accepted so far; later messages must carry a greater one
+    pub last_msg_id: Option<String>,
 }
 
-impl AgentWorkspaceState {
-    pub fn new() -> Self {
+impl AgentSession {
+    pub fn new(limit: MessageRateLimit) -> Self {
         Self {
             scaffolds: Vec::new(),
             metrics: serde_json::json!({}),
             chat_history: Vec::new(),
+            active_agent: AGENTS[0].agent_type.to_string(),
+            message_budget: TokenBucket::new(limit),
+            last_msg_id: None,
         }
     }
+
+    /// Refuse `msg_id` unless it is greater than every id accepted so far; the
+    /// refusal frame names the last accepted id.
+    fn check_msg_id(&self, msg_id: &str) -> Result<(), serde_json::Value> {
+        let Some(last) = self.last_msg_id.as_deref() else {
+            return Ok(());
+        };
+        let rejection = match compare_msg_ids(msg_id, last) {
+            std::cmp::Ordering::Greater => return Ok(()),
+            std::cmp::Ordering::Equal => MsgIdRejection::Duplicate,
+            std::cmp::Ordering::Less => MsgIdRejection::OutOfOrder,
+        };
+        Err(rejection.frame(msg_id, last))
+    }
 }
 
-/// WebSocket handler for agent chat
-pub async fn agent_chat_handler(
-    ws: WebSocketUpgrade,
-    State(workspace): State<Arc<Mutex<AgentWorkspaceState>>>,
-) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_agent_socket(socket, workspace))
+struct ResumeGrant {
+    session_id: String,
+    expires_at: u64,
+}
+
+pub struct AgentWorkspaceState {
+    pub julia_url: String,
+    pub sessions: HashMap<String, AgentSession>,
+    pub tools: Arc<ToolRegistry>,  // Tools run here instead of in Julia
+    pub message_limit: MessageRateLimit,  // Applied to sessions created afterwards
+    pub tool_concurrency: usize,  // Independent local tool calls run at once
+    attached: HashSet<String>,  // Sessions with a live connection
+    resume_tokens: HashMap<String, ResumeGrant>,  // token -> session
+    signing_key: Vec<u8>,
+}
+
+impl AgentWorkspaceState {
+    pub fn new(julia_url: String) -> Self {
+        let mut signing_key = Uuid::new_v4().as_bytes().to_vec();
+        signing_key.extend_from_slice(Uuid::new_v4().as_bytes());
+
+        Self {
+            julia_url,
+            sessions: HashMap::new(),
+            tools: Arc::new(ToolRegistry::with_builtin_tools()),
+            message_limit: MessageRateLimit::default(),
+            tool_concurrency: DEFAULT_TOOL_CONCURRENCY,
+            attached: HashSet::new(),
+            resume_tokens: HashMap::new(),
+            signing_key,
+        }
+    }
+
+    /// Create an empty session and return its id.
+    pub fn create_session(&mut self) -> String {
+        let session_id = Uuid::new_v4().to_string();
+        self.sessions.insert(session_id.clone(), AgentSession::new(self.message_limit));
+        self.attached.insert(session_id.clone());
+        session_id
+    }
+
+    /// Mark a session as disconnected; its tokens expire `RESUME_TOKEN_TTL_SECS` from now.
+    pub fn detach_session(&mut self, session_id: &str) {
+        self.attached.remove(session_id);
+        let expires_at = unix_now() + RESUME_TOKEN_TTL_SECS;
+        for grant in self.resume_tokens.values_mut() {
+            if grant.session_id == session_id {
+                grant.expires_at = expires_at;
+            }
+        }
+    }
+
+    /// Remove a session and every token pointing at it.
+    pub fn discard_session(&mut self, session_id: &str) {
+        self.sessions.remove(session_id);
+        self.attached.remove(session_id);
+        self.resume_tokens.retain(|_, grant| grant.session_id != session_id);
+    }
+
+    /// Issue a signed resume token for `session_id`, valid for `RESUME_TOKEN_TTL_SECS`.
+    pub fn issue_resume_token(&mut self, session_id: &str) -> String {
+        let expires_at = unix_now() + RESUME_TOKEN_TTL_SECS;
+        let payload = format!("{}.{}.{}", session_id, expires_at, Uuid::new_v4().simple());
+        let token = format!("{}.{}", payload, self.sign(&payload));
+
+        self.resume_tokens.insert(
+            token.clone(),
+            ResumeGrant {
+                session_id: session_id.to_string(),
+                expires_at,
+            },
+        );
+        token
+    }
+
+    /// Consume a resume token, returning the session it refers to.
+    ///
+    /// Tokens are single-use: a resumed connection gets a fresh one. Returns
+    /// `None` for tampered, unknown or expired tokens, or when the session is gone.
+    pub fn redeem_resume_token(&mut self, token: &str) -> Option<String> {
+        self.prune_expired();
+
+        let (payload, signature) = token.rsplit_once('.')?;
+        if self.sign(payload) != signature {
+            return None;
+        }
+
+        let grant = self.resume_tokens.remove(token)?;
+        if self.sessions.contains_key(&grant.session_id) {
+            self.attached.insert(grant.session_id.clone());
+            Some(grant.session_id)
+        } else {
+            None
+        }
+    }
+
+    /// Drop expired tokens and any detached session no longer reachable through a token.
+    ///
+    /// Tokens of attached sessions never expire while the connection is open.
+    pub fn prune_expired(&mut self) {
+        let now = unix_now();
+        let attached = &self.attached;
+        self.resume_tokens
+            .retain(|_, grant| grant.expires_at > now || attached.contains(&grant.session_id));
+
+        let live: HashSet<&String> = self.resume_tokens.values().map(|g| &g.session_id).collect();
+        self.sessions
+            .retain(|id, _| attached.contains(id) || live.contains(id));
+    }
+
+    fn sign(&self, payload: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.signing_key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(payload.as_bytes());
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn session_frame(token: &str, resumed: bool, session: Option<&AgentSession>) -> serde_json::Value {
+    let mut frame = serde_json::json!({
+        "type": "session",
+        "token": token,
+        "resumed": resumed,
+    });
+    if let Some(session) = session {
+        frame["chat_history"] = serde_json::json!(session
+            .chat_history
+            .iter()
+            .map(|(role, content)| serde_json::json!({"role": role, "content": content}))
+            .collect::<Vec<_>>());
+        frame["scaffolds"] = serde_json::json!(session.scaffolds);
+        frame["metrics"] = session.metrics.clone();
+        frame["active_agent"] = serde_json::json!(session.active_agent);
+    }
+    frame
+}
+
+/// Apply a control frame to the session and build the reply.
+fn handle_control_frame(frame: ControlFrame, session: Option<&mut AgentSession>) -> serde_json::Value {
+    let Some(session) = session else {
+        return serde_json::json!({"type": "system", "content": "Session not found."});
+    };
+    match frame {
+        ControlFrame::SwitchAgent { agent_type } => match find_agent(&agent_type) {
+            Some(agent) => {
+                session.active_agent = agent.agent_type.to_string();
+                serde_json::json!({
+                    "type": "active_agent",
+                    "agent_type": agent.agent_type,
+                    "agent_name": agent.name,
+                })
+            }
+            None => serde_json::json!({
+                "type": "system",
+                "content": format!("Unknown agent '{}'.", agent_type),
+            }),
+        },
+        ControlFrame::ListAgents => serde_json::json!({
+            "type": "agents",
+            "agents": AGENTS,
+            "active_agent": session.active_agent,
+        }),
+    }
+}
+
+/// Tell the client the server is going away, then close with `1001 Going Away`.
+async fn close_for_shutdown<S>(sender: &mut S)
+where
+    S: Sink<Message> + Unpin,
+{
+    let notice = serde_json::json!({
+        "type": "system",
+        "content": "server shutting down",
+    });
+    let _ = sender.send(Message::Text(notice.to_string())).await;
+    let _ = sender
+        .send(Message::Close(Some(CloseFrame {
+            code: close_code::AWAY,
+            reason: "server shutting down".into(),
+        })))
+        .await;
+}
+
+/// Report a failed turn as an `error` frame, then close with the matching code.
+async fn close_for_error<S>(sender: &mut S, error: &AgentError, msg_id: Option<&str>)
+where
+    S: Sink<Message> + Unpin,
+{
+    let mut frame = serde_json::json!({"type": "error", "content": error.to_string()});
+    tag_frame(&mut frame, msg_id);
+    let _ = sender.send(Message::Text(frame.to_string())).await;
+    let _ = sender.send(Message::Close(Some(error.close_frame()))).await;
 }
 
 async fn handle_agent_socket(
     socket: WebSocket,
     workspace: Arc<Mutex<AgentWorkspaceState>>,
+    shutdown: CancellationToken,
 ) {
+    let _connected = observability::GaugeGuard::new(observability::AGENT_WS_CONNECTIONS);
     let (mut sender, mut receiver) = socket.split();
 
     // Send welcome message
@@ -68,27 +586,136 @@ async fn handle_agent_socket(
         "type": "system",
         "content": "Darwin Research Hub initialized. Agents ready.",
     });
-    
+
     if sender.send(Message::Text(welcome.to_string())).await.is_err() {
         return;
     }
 
-    while let Some(Ok(msg)) = receiver.next().await {
+    // Every connection starts with a fresh session and a token to resume it later
+    let (mut session_id, token) = {
+        let mut ws = workspace.lock().await;
+        ws.prune_expired();
+        let session_id = ws.create_session();
+        let token = ws.issue_resume_token(&session_id);
+        (session_id, token)
+    };
+    let frame = session_frame(&token, false, None);
+    if sender.send(Message::Text(frame.to_string())).await.is_err() {
+        workspace.lock().await.discard_session(&session_id);
+        return;
+    }
+
+    let mut first_frame = true;
+    loop {
+        let msg = tokio::select! {
+            _ = shutdown.cancelled() => {
+                close_for_shutdown(&mut sender).await;
+                break;
+            }
+            msg = receiver.next() => match msg {
+                Some(Ok(msg)) => msg,
+                _ => break,
+            },
+        };
+
         if let Message::Text(text) = msg {
-            // Parse user message
-            let user_msg: Result<AgentMessage, _> = serde_json::from_str(&text);
-            
-            match user_msg {
-                Ok(agent_msg) => {
-                    // Add to chat history
-                    {
+            // A `{"type":"session","token":...}` first frame resumes a previous session
+            if std::mem::take(&mut first_frame) {
+                if let Some(token) = parse_resume_frame(&text) {
+                    let frame = {
+                        let mut ws = workspace.lock().await;
+                        match ws.redeem_resume_token(&token) {
+                            Some(resumed_id) => {
+                                ws.discard_session(&session_id);
+                                session_id = resumed_id;
+                                let token = ws.issue_resume_token(&session_id);
+                                session_frame(&token, true, ws.sessions.get(&session_id))
+                            }
+                            // Unknown or expired: keep the fresh session
+                            None => serde_json::json!({
+                                "type": "system",
+                                "content": "Session expired. Starting a new session.",
+                            }),
+                        }
+                    };
+                    if sender.send(Message::Text(frame.to_string())).await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+            }
+
+            if let Ok(control) = serde_json::from_str::<ControlFrame>(&text) {
+                let reply = handle_control_frame(control, workspace.lock().await.sessions.get_mut(&session_id));
+                if sender.send(Message::Text(reply.to_string())).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+
+            match AgentMessage::parse(&text) {
+                Ok(mut agent_msg) => {
+                    let msg_id = agent_msg.msg_id.clone();
+                    // Add to chat history, unless the id is stale or the session is over its message budget
+                    let refusal = {
                         let mut ws = workspace.lock().await;
-                        ws.chat_history.push(("user".to_string(), agent_msg.content.clone()));
+                        match ws.sessions.get_mut(&session_id) {
+                            Some(session) => match msg_id.as_deref().map(|id| session.check_msg_id(id)) {
+                                Some(Err(frame)) => Some(frame),
+                                _ => match session.message_budget.try_take(Instant::now()) {
+                                    Ok(()) => {
+                                        session.chat_history.push(("user".to_string(), agent_msg.content.clone()));
+                                        agent_msg.agent_type.get_or_insert_with(|| session.active_agent.clone());
+                                        if msg_id.is_some() {
+                                            session.last_msg_id = msg_id.clone();
+                                        }
+                                        None
+                                    }
+                                    Err(wait) => Some(serde_json::json!({
+                                        "type": "rate_limited",
+                                        "retry_after_ms": wait.as_millis().max(1) as u64,
+                                    })),
+                                },
+                            },
+                            None => None,
+                        }
+                    };
+                    // Refused messages are dropped, not queued; the connection stays open.
+                    // A throttled id is not recorded, so the client may retry it as is.
+                    if let Some(mut frame) = refusal {
+                        tag_frame(&mut frame, msg_id.as_deref());
+                        if sender.send(Message::Text(frame.to_string())).await.is_err() {
+                            break;
+                        }
+                        continue;
                     }
-                    
-                    // Route to appropriate agent (Julia backend)
-                    let response = route_to_agent(agent_msg, &workspace).await;
-                    
+
+                    // Acknowledge receipt before the (possibly slow) agent turn
+                    if let Some(msg_id) = &msg_id {
+                        let ack = serde_json::json!({"type": "ack", "msg_id": msg_id});
+                        if sender.send(Message::Text(ack.to_string())).await.is_err() {
+                            break;
+                        }
+                    }
+
+                    // Route to appropriate agent (Julia backend); tool frames stream out as they run.
+                    // A shutdown abandons the in-flight reply rather than waiting on Julia.
+                    let response = tokio::select! {
+                        response = route_to_agent(agent_msg, &workspace, &session_id, &mut sender) => response,
+                        _ = shutdown.cancelled() => {
+                            close_for_shutdown(&mut sender).await;
+                            break;
+                        }
+                    };
+                    // The session survives the close; the client resumes it when it reconnects
+                    let response = match response {
+                        Ok(response) => response,
+                        Err(e) => {
+                            close_for_error(&mut sender, &e, msg_id.as_deref()).await;
+                            break;
+                        }
+                    };
+
                     // Send response back
                     if let Ok(resp_json) = serde_json::to_string(&response) {
                         if sender.send(Message::Text(resp_json)).await.is_err() {
@@ -97,53 +724,953 @@ async fn handle_agent_socket(
                     }
                 }
                 Err(e) => {
-                    eprintln!("Failed to parse agent message: {}", e);
+                    let frame = serde_json::json!({"type": "error", "content": e});
+                    if sender.send(Message::Text(frame.to_string())).await.is_err() {
+                        break;
+                    }
                 }
             }
         }
     }
+
+    // Keep the session around so the client can resume it with its token
+    workspace.lock().await.detach_session(&session_id);
+}
+
+fn parse_resume_frame(text: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(text).ok()?;
+    if value.get("type")?.as_str()? != "session" {
+        return None;
+    }
+    value.get("token")?.as_str().map(str::to_string)
 }
 
-async fn route_to_agent(
+/// One line of the NDJSON stream returned by Julia's `/agents/chat/stream`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AgentStreamEvent {
+    ToolStart {
+        #[serde(default)]
+        id: Option<String>,
+        tool_name: String,
+        #[serde(default)]
+        args: serde_json::Value,
+        #[serde(default)]
+        depends_on: Vec<String>,
+    },
+    ToolResult {
+        #[serde(default)]
+        id: Option<String>,
+        tool_name: String,
+        #[serde(default)]
+        result: serde_json::Value,
+    },
+    Delta {
+        content: String,
+    },
+    Done {
+        response: Option<String>,
+    },
+}
+
+async fn route_to_agent<S>(
     msg: AgentMessage,
     workspace: &Arc<Mutex<AgentWorkspaceState>>,
-) -> AgentResponse {
-    // In real implementation, this would call the Julia backend
-    // For now, return a mock response
-    
-    let agent_name = match msg.agent_type.as_str() {
-        "design" => "Design Agent",
-        "analysis" => "Analysis Agent",
-        "synthesis" => "Synthesis Agent",
-        _ => "Unknown Agent",
+    session_id: &str,
+    sender: &mut S,
+) -> Result<AgentResponse, AgentError>
+where
+    S: Sink<Message> + Unpin,
+{
+    let agent_name = msg
+        .agent_type
+        .as_deref()
+        .and_then(find_agent)
+        .map_or("Unknown Agent", |agent| agent.name);
+
+    let (julia_url, history, tools, tool_concurrency) = {
+        let ws = workspace.lock().await;
+        let history = ws
+            .sessions
+            .get(session_id)
+            .map(|s| s.chat_history.clone())
+            .unwrap_or_default();
+        (ws.julia_url.clone(), history, ws.tools.clone(), ws.tool_concurrency)
     };
-    
-    // Simulate processing
-    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-    
-    AgentResponse {
+    let local_tools = tools.describe();
+    let ctx = ToolContext {
+        workspace: workspace.clone(),
+        session_id: session_id.to_string(),
+    };
+
+    let mut response = AgentResponse {
         agent_name: agent_name.to_string(),
-        response: format!("Processing your request: {}", msg.content),
+        response: String::new(),
         tool_calls: vec![],
         status: "complete".to_string(),
+        msg_id: msg.msg_id.clone(),
+    };
+
+    // Julia asks for local tools with a `tool_start` and ends its turn; run them here
+    // and send the turn again with their results until it answers without any.
+    let mut tool_results: Vec<serde_json::Value> = Vec::new();
+    for round in 0..=MAX_LOCAL_TOOL_ROUNDS {
+        let turn = JuliaTurn {
+            msg: &msg,
+            history: &history,
+            local_tools: &local_tools,
+            tool_results: &tool_results,
+        };
+        stream_from_julia(&julia_url, turn, &mut response, sender).await?;
+        response.status = "complete".to_string();
+
+        let pending: Vec<usize> = response
+            .tool_calls
+            .iter()
+            .enumerate()
+            .filter(|(_, call)| call.result.is_none() && tools.contains(&call.tool_name))
+            .map(|(i, _)| i)
+            .collect();
+        if pending.is_empty() || round == MAX_LOCAL_TOOL_ROUNDS {
+            break;
+        }
+
+        run_local_tools(&mut response.tool_calls, &pending, &tools, &ctx, tool_concurrency, msg.msg_id.as_deref(), sender).await;
+        // Fed back in request order, whatever order they finished in
+        for &i in &pending {
+            let call = &response.tool_calls[i];
+            tool_results.push(serde_json::json!({
+                "id": call.id,
+                "tool_name": call.tool_name,
+                "args": call.args,
+                "result": call.result,
+            }));
+        }
+    }
+
+    // Keep tool calls and the assistant reply so a resumed session can replay them
+    if let Some(session) = workspace.lock().await.sessions.get_mut(session_id) {
+        for call in &response.tool_calls {
+            if let Ok(record) = serde_json::to_string(call) {
+                session.chat_history.push(("tool".to_string(), record));
+            }
+        }
+        session.chat_history.push(("assistant".to_string(), response.response.clone()));
+    }
+
+    Ok(response)
+}
+
+/// Run the local tool calls at `pending` (indices into `calls`, in request order).
+///
+/// A call waits for every pending call whose `id` it lists in `depends_on`;
+/// ids of calls that are not pending (finished earlier, or unknown) are ignored.
+/// Calls that are free to run start in request order, at most `max_concurrency`
+/// at a time. Should the dependencies form a cycle, the earliest stalled call
+/// runs anyway. Each call sends a `tool_start` frame when it starts and a
+/// `tool_result` frame when it finishes, both marked `"local": true` and tagged
+/// with `msg_id`.
+async fn run_local_tools<S>(
+    calls: &mut [ToolCall],
+    pending: &[usize],
+    tools: &ToolRegistry,
+    ctx: &ToolContext,
+    max_concurrency: usize,
+    msg_id: Option<&str>,
+    sender: &mut S,
+) where
+    S: Sink<Message> + Unpin,
+{
+    async fn run(tools: &ToolRegistry, index: usize, call: ToolCall, ctx: ToolContext) -> (usize, serde_json::Value) {
+        (index, tools.call(&call.tool_name, call.args, ctx).await.unwrap_or_default())
+    }
+
+    let mut waiting: Vec<usize> = pending.to_vec();
+    let mut unfinished: HashSet<String> = pending.iter().filter_map(|&i| calls[i].id.clone()).collect();
+    let mut running = FuturesUnordered::new();
+    while !waiting.is_empty() || !running.is_empty() {
+        while running.len() < max_concurrency.max(1) {
+            let blocked = |call: &ToolCall| {
+                call.depends_on
+                    .iter()
+                    .any(|dep| call.id.as_ref() != Some(dep) && unfinished.contains(dep))
+            };
+            let next = match waiting.iter().position(|&i| !blocked(&calls[i])) {
+                Some(position) => position,
+                // A dependency cycle: nothing will unblock it, so go in request order
+                None if running.is_empty() && !waiting.is_empty() => 0,
+                None => break,
+            };
+
+            let index = waiting.remove(next);
+            let call = &calls[index];
+            let mut frame = serde_json::json!({
+                "type": "tool_start",
+                "id": call.id,
+                "tool_name": call.tool_name,
+                "args": call.args,
+                "local": true,
+            });
+            tag_frame(&mut frame, msg_id);
+            let _ = sender.send(Message::Text(frame.to_string())).await;
+            running.push(run(tools, index, call.clone(), ctx.clone()));
+        }
+
+        if let Some((index, result)) = running.next().await {
+            let call = &mut calls[index];
+            if let Some(id) = &call.id {
+                unfinished.remove(id);
+            }
+            let mut frame = serde_json::json!({
+                "type": "tool_result",
+                "id": call.id,
+                "tool_name": call.tool_name,
+                "result": result,
+                "local": true,
+            });
+            tag_frame(&mut frame, msg_id);
+            let _ = sender.send(Message::Text(frame.to_string())).await;
+            call.result = Some(result);
+        }
+    }
+}
+
+/// One request to Julia's `/agents/chat/stream`.
+struct JuliaTurn<'a> {
+    msg: &'a AgentMessage,
+    history: &'a [(String, String)],
+    local_tools: &'a [serde_json::Value],
+    /// Outcomes of local tools Julia asked for earlier in this turn
+    tool_results: &'a [serde_json::Value],
+}
+
+/// Consume the Julia agent stream, forwarding `tool_start`/`tool_result` frames to the
+/// client as they arrive and aggregating everything into `response`.
+async fn stream_from_julia<S>(
+    julia_url: &str,
+    turn: JuliaTurn<'_>,
+    response: &mut AgentResponse,
+    sender: &mut S,
+) -> Result<(), AgentError>
+where
+    S: Sink<Message> + Unpin,
+{
+    let history: Vec<serde_json::Value> = turn
+        .history
+        .iter()
+        .map(|(role, content)| serde_json::json!({"role": role, "content": content}))
+        .collect();
+
+    let res = reqwest::Client::new()
+        .post(format!("{}/agents/chat/stream", julia_url))
+        .json(&serde_json::json!({
+            "agent_type": turn.msg.agent_type,
+            "content": turn.msg.content,
+            "history": history,
+            "local_tools": turn.local_tools,
+            "tool_results": turn.tool_results,
+        }))
+        .send()
+        .await
+        .map_err(|e| AgentError::Backend(e.to_string()))?;
+
+    if res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        let retry_after_secs = res
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse().ok());
+        return Err(AgentError::RateLimited { retry_after_secs });
+    }
+    if res.status() == reqwest::StatusCode::NOT_FOUND {
+        // A backend without the stream route can still answer, just without tools
+        return chat_once(julia_url, turn.msg, response).await;
+    }
+    if !res.status().is_success() {
+        return Err(AgentError::Backend(format!("Julia returned {}", res.status())));
+    }
+
+    let mut body = res.bytes_stream();
+    let mut buffer: Vec<u8> = Vec::new();
+    loop {
+        let chunk = body.next().await;
+        let done = chunk.is_none();
+        if let Some(chunk) = chunk {
+            buffer.extend_from_slice(&chunk.map_err(|e| AgentError::Backend(e.to_string()))?);
+        }
+
+        // Process every complete line; on end of stream flush whatever is left
+        while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=pos).collect();
+            if apply_stream_line(&line, turn.msg.msg_id.as_deref(), response, sender).await {
+                return Ok(());
+            }
+        }
+        if done {
+            if !buffer.is_empty() {
+                apply_stream_line(&buffer, turn.msg.msg_id.as_deref(), response, sender).await;
+            }
+            return Ok(());
+        }
+    }
+}
+
+/// Ask Julia's one-shot `/agents/chat`, for backends that predate the stream route.
+/// It knows nothing of local tools, so its reply is final.
+async fn chat_once(julia_url: &str, msg: &AgentMessage, response: &mut AgentResponse) -> Result<(), AgentError> {
+    let res = reqwest::Client::new()
+        .post(format!("{}/agents/chat", julia_url))
+        .json(&serde_json::json!({
+            "agent": msg.agent_type.as_deref().unwrap_or("design"),
+            "message": msg.content,
+        }))
+        .send()
+        .await
+        .map_err(|e| AgentError::Backend(e.to_string()))?;
+    if !res.status().is_success() {
+        return Err(AgentError::Backend(format!("Julia returned {}", res.status())));
+    }
+    let body: serde_json::Value = res.json().await.map_err(|e| AgentError::Backend(e.to_string()))?;
+    response.response = body["response"].as_str().unwrap_or_default().to_string();
+    Ok(())
+}
+
+/// Apply one stream line, tagging forwarded frames with `msg_id`; returns `true`
+/// once the final event has been seen.
+async fn apply_stream_line<S>(line: &[u8], msg_id: Option<&str>, response: &mut AgentResponse, sender: &mut S) -> bool
+where
+    S: Sink<Message> + Unpin,
+{
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim();
+    if line.is_empty() {
+        return false;
+    }
+
+    let event: AgentStreamEvent = match serde_json::from_str(line) {
+        Ok(event) => event,
+        Err(e) => {
+            tracing::warn!("ignoring malformed agent stream line: {}", e);
+            return false;
+        }
+    };
+
+    match event {
+        AgentStreamEvent::ToolStart { id, tool_name, args, depends_on } => {
+            let mut frame = serde_json::json!({
+                "type": "tool_start",
+                "id": id,
+                "tool_name": tool_name,
+                "args": args,
+            });
+            tag_frame(&mut frame, msg_id);
+            let _ = sender.send(Message::Text(frame.to_string())).await;
+            response.status = "using_tool".to_string();
+            response.tool_calls.push(ToolCall {
+                id,
+                tool_name,
+                args,
+                depends_on,
+                result: None,
+            });
+        }
+        AgentStreamEvent::ToolResult { id, tool_name, result } => {
+            let mut frame = serde_json::json!({
+                "type": "tool_result",
+                "id": id,
+                "tool_name": tool_name,
+                "result": result,
+            });
+            tag_frame(&mut frame, msg_id);
+            let _ = sender.send(Message::Text(frame.to_string())).await;
+            // Attach to the call with that id, else the oldest still-pending call of the same tool
+            let pending = |c: &&mut ToolCall| c.tool_name == tool_name && c.result.is_none();
+            let call = match &id {
+                Some(id) => response.tool_calls.iter_mut().find(|c| c.id.as_ref() == Some(id) && c.result.is_none()),
+                None => response.tool_calls.iter_mut().find(pending),
+            };
+            if let Some(call) = call {
+                call.result = Some(result);
+            }
+        }
+        AgentStreamEvent::Delta { content } => {
+            response.response.push_str(&content);
+        }
+        AgentStreamEvent::Done { response: text } => {
+            if let Some(text) = text {
+                response.response = text;
+            }
+            return true;
+        }
+    }
+    false
+}
+
+/// The subprotocol to answer with: `None` when the client offered none, a `400
+/// unsupported_subprotocol` error when it offered only ones we do not speak.
+fn negotiate_subprotocol(headers: &HeaderMap) -> Result<Option<&'static str>, AppError> {
+    let offered: Vec<&str> = headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|protocol| !protocol.is_empty())
+        .collect();
+    if offered.is_empty() {
+        return Ok(None);
+    }
+    match AGENT_SUBPROTOCOLS.iter().find(|supported| offered.contains(supported)) {
+        Some(protocol) => Ok(Some(protocol)),
+        None => Err(AppError::rejected(
+            StatusCode::BAD_REQUEST,
+            "unsupported_subprotocol",
+            format!("none of the offered subprotocols ({}) is supported", offered.join(", ")),
+        )
+        .with_detail("supported", AGENT_SUBPROTOCOLS)),
     }
 }
 
 async fn agent_chat_handler_wrapper<S>(
+    headers: HeaderMap,
     ws: WebSocketUpgrade,
     State(states): State<(Arc<S>, Arc<Mutex<AgentWorkspaceState>>)>,
-) -> impl IntoResponse 
+    shutdown: CancellationToken,
+) -> Response
 where
     S: Clone + Send + Sync + 'static,
 {
+    let ws = match negotiate_subprotocol(&headers) {
+        Ok(Some(protocol)) => ws.protocols([protocol]),
+        Ok(None) => ws,
+        Err(error) => return error.into_response(),
+    };
     let workspace = states.1.clone();
-    ws.on_upgrade(move |socket| handle_agent_socket(socket, workspace))
+    ws.on_upgrade(move |socket| handle_agent_socket(socket, workspace, shutdown))
 }
 
-pub fn agent_routes<S>() -> axum::Router<(Arc<S>, Arc<Mutex<AgentWorkspaceState>>)> 
+/// Agent chat routes.
+///
+/// Clients may pick a message framing via `Sec-WebSocket-Protocol`; see
+/// [`AGENT_SUBPROTOCOLS`]. Offering only unknown ones fails the upgrade with `400`.
+///
+/// The server closes `/ws/agent-chat` with one of these codes, each with a readable reason:
+///
+/// | code   | when                                            | client should          |
+/// |--------|-------------------------------------------------|------------------------|
+/// | `1000` | echoing a close the client started              | stay closed            |
+/// | `1001` | `shutdown` was cancelled (server restarting)    | reconnect shortly      |
+/// | `1011` | Julia unreachable or failed an agent turn       | reconnect with backoff |
+/// | `4029` | Julia rate-limited the turn; reason may carry `retry after Ns` | wait, then reconnect |
+///
+/// Failed turns are also reported as an `error` frame just before the close. The
+/// session outlives the connection, so reconnecting with its resume token keeps the chat.
+pub fn agent_routes<S>(shutdown: CancellationToken) -> axum::Router<(Arc<S>, Arc<Mutex<AgentWorkspaceState>>)>
 where
     S: Clone + Send + Sync + 'static,
 {
-    axum::Router::new()
-        .route("/ws/agent-chat", get(agent_chat_handler_wrapper::<S>))
+    axum::Router::new().route(
+        "/ws/agent-chat",
+        get(move |headers, ws, state| agent_chat_handler_wrapper::<S>(headers, ws, state, shutdown.clone())),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Json};
+    use tokio_tungstenite::tungstenite::{protocol::frame::coding::CloseCode, Message as WsMessage};
+
+    /// A "Julia" that asks for `convert_units` once, then answers with whatever it got back.
+    async fn mock_agent_julia() -> String {
+        let app = axum::Router::new().route(
+            "/agents/chat/stream",
+            post(|Json(body): Json<serde_json::Value>| async move {
+                let results = body["tool_results"].as_array().cloned().unwrap_or_default();
+                if results.is_empty() {
+                    concat!(
+                        r#"{"type":"tool_start","tool_name":"convert_units","args":{"value":2,"from":"mm","to":"um"}}"#,
+                        "\n",
+                        r#"{"type":"done"}"#,
+                    )
+                    .to_string()
+                } else {
+                    let done = serde_json::json!({"type": "done", "response": format!("{} um", results[0]["result"]["value"])});
+                    format!("{}\n", done)
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn local_tools_run_without_julia_and_feed_back() {
+        let mut state = AgentWorkspaceState::new(mock_agent_julia().await);
+        let session_id = state.create_session();
+        let workspace = Arc::new(Mutex::new(state));
+        let (mut frames, received) = futures::channel::mpsc::unbounded::<Message>();
+
+        let msg = AgentMessage {
+            protocol_version: PROTOCOL_VERSION,
+            agent_type: Some("design".to_string()),
+            content: "2 mm in microns?".to_string(),
+            timestamp: 0,
+            msg_id: Some("7".to_string()),
+        };
+        let response = route_to_agent(msg, &workspace, &session_id, &mut frames).await.unwrap();
+
+        assert_eq!(response.status, "complete");
+        assert_eq!(response.response, "2000.0 um");
+        assert_eq!(response.tool_calls[0].result.as_ref().unwrap()["value"], 2000.0);
+        assert_eq!(response.msg_id.as_deref(), Some("7"));
+
+        drop(frames);
+        let frames: Vec<Message> = received.collect().await;
+        assert!(frames.iter().any(|f| matches!(f, Message::Text(t) if t.contains(r#""local":true"#))));
+        assert!(frames.iter().all(|f| matches!(f, Message::Text(t) if t.contains(r#""msg_id":"7""#))));
+    }
+
+    #[tokio::test]
+    async fn a_julia_without_the_stream_route_answers_one_shot() {
+        let julia = axum::Router::new().route(
+            "/agents/chat",
+            post(|Json(body): Json<serde_json::Value>| async move {
+                Json(serde_json::json!({"response": format!("{} to {}", body["message"], body["agent"]), "suggestions": []}))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let julia_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, julia).await.unwrap() });
+
+        let mut state = AgentWorkspaceState::new(julia_url);
+        let session_id = state.create_session();
+        let workspace = Arc::new(Mutex::new(state));
+        let msg = AgentMessage {
+            protocol_version: PROTOCOL_VERSION,
+            agent_type: Some("analysis".to_string()),
+            content: "hello".to_string(),
+            timestamp: 0,
+            msg_id: None,
+        };
+        let (mut frames, _received) = futures::channel::mpsc::unbounded::<Message>();
+        let response = route_to_agent(msg, &workspace, &session_id, &mut frames).await.unwrap();
+        assert_eq!((response.status.as_str(), response.response.as_str()), ("complete", r#""hello" to "analysis""#));
+        assert!(response.tool_calls.is_empty());
+    }
+
+    #[tokio::test]
+    async fn malformed_stream_lines_are_skipped() {
+        let mut response = AgentResponse {
+            agent_name: "design".to_string(),
+            response: String::new(),
+            tool_calls: vec![],
+            status: "complete".to_string(),
+            msg_id: None,
+        };
+        let (mut frames, received) = futures::channel::mpsc::unbounded::<Message>();
+        let lines = [
+            r#"{"type":"delta","content":"Porosity "}"#,
+            r#"{"type":"delta","content":"#,
+            r#"{"type":"no_such_event"}"#,
+            r#"{"type":"delta","content":"is 70%"}"#,
+        ];
+        for line in lines {
+            assert!(!apply_stream_line(line.as_bytes(), None, &mut response, &mut frames).await);
+        }
+        assert!(apply_stream_line(br#"{"type":"done"}"#, None, &mut response, &mut frames).await);
+
+        assert_eq!(response.response, "Porosity is 70%");
+        assert!(response.tool_calls.is_empty());
+        drop(frames);
+        assert!(received.collect::<Vec<_>>().await.is_empty());
+    }
+
+    /// When each labelled call started and finished
+    type Runs = Arc<std::sync::Mutex<HashMap<String, (Instant, Instant)>>>;
+
+    /// A registry whose `sleep` tool waits `ms` and logs when `label` ran.
+    fn timed_tools() -> (ToolRegistry, Runs) {
+        let runs = Arc::new(std::sync::Mutex::new(HashMap::new()));
+        let log = runs.clone();
+        let mut tools = ToolRegistry::new();
+        tools.register("sleep", "Wait. Args: {label, ms}", move |args, _| {
+            let log = log.clone();
+            async move {
+                let start = Instant::now();
+                tokio::time::sleep(Duration::from_millis(args["ms"].as_u64().unwrap())).await;
+                let label = args["label"].as_str().unwrap().to_string();
+                log.lock().unwrap().insert(label.clone(), (start, Instant::now()));
+                Ok(serde_json::json!({"label": label}))
+            }
+        });
+        (tools, runs)
+    }
+
+    fn sleep_call(label: &str, ms: u64, depends_on: &[&str]) -> ToolCall {
+        ToolCall {
+            id: Some(label.to_string()),
+            tool_name: "sleep".to_string(),
+            args: serde_json::json!({"label": label, "ms": ms}),
+            depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+            result: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn independent_tools_overlap_and_dependents_wait() {
+        let (tools, runs) = timed_tools();
+        let ctx = ToolContext {
+            workspace: Arc::new(Mutex::new(AgentWorkspaceState::new("http://127.0.0.1:1".to_string()))),
+            session_id: "s".to_string(),
+        };
+        let mut calls = vec![
+            sleep_call("a", 120, &[]),
+            sleep_call("b", 120, &[]),
+            sleep_call("c", 10, &["a"]),
+            sleep_call("d", 10, &["c", "b"]),
+        ];
+        let (mut frames, received) = futures::channel::mpsc::unbounded::<Message>();
+        run_local_tools(&mut calls, &[0, 1, 2, 3], &tools, &ctx, DEFAULT_TOOL_CONCURRENCY, None, &mut frames).await;
+
+        let runs = runs.lock().unwrap().clone();
+        let (a, b, c, d) = (runs["a"], runs["b"], runs["c"], runs["d"]);
+        assert!(a.0 < b.1 && b.0 < a.1, "independent calls ran one after the other");
+        assert!(c.0 >= a.1, "c started before its dependency a finished");
+        assert!(d.0 >= c.1 && d.0 >= b.1, "d started before its dependencies finished");
+        let labels: Vec<&str> = calls.iter().map(|c| c.result.as_ref().unwrap()["label"].as_str().unwrap()).collect();
+        assert_eq!(labels, ["a", "b", "c", "d"]);
+
+        drop(frames);
+        let frames: Vec<serde_json::Value> = received
+            .map(|f| match f {
+                Message::Text(t) => serde_json::from_str(&t).unwrap(),
+                other => panic!("unexpected frame {:?}", other),
+            })
+            .collect()
+            .await;
+        let kinds: Vec<String> = frames.iter().map(|f| format!("{}:{}", f["type"].as_str().unwrap(), f["id"].as_str().unwrap())).collect();
+        assert_eq!(kinds[..2], ["tool_start:a", "tool_start:b"]);
+        assert_eq!(kinds.len(), 8);
+        let position = |kind: &str| kinds.iter().position(|k| k == kind).unwrap();
+        assert!(position("tool_result:a") < position("tool_start:c"));
+        assert!(position("tool_result:c") < position("tool_start:d"));
+
+        // With one slot, even independent calls run sequentially in request order
+        let (tools, runs) = timed_tools();
+        let mut calls = vec![sleep_call("x", 30, &[]), sleep_call("y", 30, &[]), sleep_call("z", 10, &["z2"]), sleep_call("z2", 10, &["z"])];
+        let (mut frames, _received) = futures::channel::mpsc::unbounded::<Message>();
+        run_local_tools(&mut calls, &[0, 1, 2, 3], &tools, &ctx, 1, None, &mut frames).await;
+        let runs = runs.lock().unwrap().clone();
+        assert!(runs["y"].0 >= runs["x"].1);
+        // A dependency cycle still runs, earliest first
+        assert!(runs["z2"].0 >= runs["z"].1);
+        assert!(calls.iter().all(|c| c.result.is_some()));
+    }
+
+    #[tokio::test]
+    async fn subprotocols_are_negotiated() {
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+        let workspace = Arc::new(Mutex::new(AgentWorkspaceState::new("http://127.0.0.1:1".to_string())));
+        let app = agent_routes::<()>(CancellationToken::new()).with_state((Arc::new(()), workspace));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        let offering = |protocols: &str| {
+            let mut request = format!("ws://{}/ws/agent-chat", addr).into_client_request().unwrap();
+            request.headers_mut().insert(header::SEC_WEBSOCKET_PROTOCOL, protocols.parse().unwrap());
+            request
+        };
+
+        // A supported protocol among unknown ones is picked and echoed
+        let (mut client, response) =
+            tokio_tungstenite::connect_async(offering("darwin.agent.v9,darwin.agent.v1")).await.unwrap();
+        assert_eq!(response.headers()[header::SEC_WEBSOCKET_PROTOCOL], "darwin.agent.v1");
+        let welcome: serde_json::Value =
+            serde_json::from_str(client.next().await.unwrap().unwrap().to_text().unwrap()).unwrap();
+        assert_eq!(welcome["type"], "system");
+
+        // Clients offering nothing still connect, without a protocol in the handshake
+        let (_, response) = tokio_tungstenite::connect_async(format!("ws://{}/ws/agent-chat", addr)).await.unwrap();
+        assert!(response.headers().get(header::SEC_WEBSOCKET_PROTOCOL).is_none());
+
+        match tokio_tungstenite::connect_async(offering("darwin.agent.v9")).await {
+            Err(tokio_tungstenite::tungstenite::Error::Http(response)) => {
+                assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+                let body: serde_json::Value = serde_json::from_slice(response.body().as_deref().unwrap()).unwrap();
+                assert_eq!(body["error"]["code"], "unsupported_subprotocol");
+                assert_eq!(body["error"]["supported"], serde_json::json!(AGENT_SUBPROTOCOLS));
+            }
+            other => panic!("expected a 400, got {:?}", other.map(|(_, response)| response.status())),
+        }
+    }
+
+    #[tokio::test]
+    async fn shutdown_sends_notice_and_going_away_close() {
+        let workspace = Arc::new(Mutex::new(AgentWorkspaceState::new("http://127.0.0.1:1".to_string())));
+        let shutdown = CancellationToken::new();
+        let app = agent_routes::<()>(shutdown.clone()).with_state((Arc::new(()), workspace));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws/agent-chat", addr))
+            .await
+            .unwrap();
+        // Welcome + session frames
+        for _ in 0..2 {
+            client.next().await.unwrap().unwrap();
+        }
+
+        shutdown.cancel();
+
+        let notice = client.next().await.unwrap().unwrap();
+        let notice: serde_json::Value = serde_json::from_str(notice.to_text().unwrap()).unwrap();
+        assert_eq!(notice["type"], "system");
+        assert_eq!(notice["content"], "server shutting down");
+
+        match client.next().await.unwrap().unwrap() {
+            WsMessage::Close(Some(frame)) => assert_eq!(frame.code, CloseCode::Away),
+            other => panic!("expected a close frame, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_turns_close_with_a_matching_code() {
+        let busy = axum::Router::new().route(
+            "/agents/chat/stream",
+            post(|| async { (axum::http::StatusCode::TOO_MANY_REQUESTS, [("retry-after", "30")], "slow down") }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let busy_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, busy).await.unwrap() });
+
+        for (julia_url, code, reason) in [
+            (busy_url, CloseCode::Library(CLOSE_RATE_LIMITED), "Rate limited; retry after 30s"),
+            ("http://127.0.0.1:1".to_string(), CloseCode::Error, "Agent backend unavailable: "),
+        ] {
+            let workspace = Arc::new(Mutex::new(AgentWorkspaceState::new(julia_url)));
+            let app = agent_routes::<()>(CancellationToken::new()).with_state((Arc::new(()), workspace));
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+            let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws/agent-chat", addr))
+                .await
+                .unwrap();
+            for _ in 0..2 {
+                client.next().await.unwrap().unwrap();
+            }
+            client
+                .send(WsMessage::Text(r#"{"content":"design a gyroid","timestamp":0}"#.to_string()))
+                .await
+                .unwrap();
+
+            let error = client.next().await.unwrap().unwrap();
+            let error: serde_json::Value = serde_json::from_str(error.to_text().unwrap()).unwrap();
+            assert_eq!(error["type"], "error");
+            match client.next().await.unwrap().unwrap() {
+                WsMessage::Close(Some(frame)) => {
+                    assert_eq!(frame.code, code);
+                    assert!(frame.reason.starts_with(reason), "{}", frame.reason);
+                    assert!(frame.reason.len() <= MAX_CLOSE_REASON_BYTES);
+                }
+                other => panic!("expected a close frame, got {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn messages_are_acked_and_stale_ids_rejected() {
+        let julia = axum::Router::new().route("/agents/chat/stream", post(|| async { "{\"type\":\"done\",\"response\":\"ok\"}\n" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let julia_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, julia).await.unwrap() });
+
+        let workspace = Arc::new(Mutex::new(AgentWorkspaceState::new(julia_url)));
+        let app = agent_routes::<()>(CancellationToken::new()).with_state((Arc::new(()), workspace));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws/agent-chat", addr))
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            client.next().await.unwrap().unwrap();
+        }
+        for msg_id in ["9", "10", "10", "2"] {
+            let frame = serde_json::json!({"content": "hello", "timestamp": 0, "msg_id": msg_id});
+            client.send(WsMessage::Text(frame.to_string())).await.unwrap();
+        }
+
+        let mut frames = Vec::new();
+        for _ in 0..6 {
+            let frame = client.next().await.unwrap().unwrap();
+            frames.push(serde_json::from_str::<serde_json::Value>(frame.to_text().unwrap()).unwrap());
+        }
+        // The ack comes before the reply, and ids compare as numbers ("10" > "9")
+        assert_eq!(frames[0], serde_json::json!({"type": "ack", "msg_id": "9"}));
+        assert_eq!((frames[1]["response"].as_str(), frames[1]["msg_id"].as_str()), (Some("ok"), Some("9")));
+        assert_eq!(frames[2], serde_json::json!({"type": "ack", "msg_id": "10"}));
+        assert_eq!(frames[3]["msg_id"], "10");
+        assert_eq!((frames[4]["type"].as_str(), frames[4]["reason"].as_str()), (Some("error"), Some("duplicate")));
+        assert_eq!(frames[4]["msg_id"], "10");
+        assert_eq!(frames[5]["reason"], "out_of_order");
+        assert_eq!(frames[5]["content"], "msg_id 2 arrived after 10");
+
+        // Messages without an id are neither acked nor ordered
+        client.send(WsMessage::Text(r#"{"content":"hello","timestamp":0}"#.to_string())).await.unwrap();
+        let reply: serde_json::Value = serde_json::from_str(client.next().await.unwrap().unwrap().to_text().unwrap()).unwrap();
+        assert_eq!(reply["response"], "ok");
+        assert!(reply.get("msg_id").is_none());
+    }
+
+    #[test]
+    fn msg_ids_compare_numerically_then_as_strings() {
+        use std::cmp::Ordering;
+        assert_eq!(compare_msg_ids("10", "9"), Ordering::Greater);
+        assert_eq!(compare_msg_ids("01HZX3", "01HZX2"), Ordering::Greater);
+        assert_eq!(compare_msg_ids("a", "a"), Ordering::Equal);
+    }
+
+    #[test]
+    fn bucket_refills_evenly_over_the_window() {
+        let limit = MessageRateLimit { burst: 2, window: Duration::from_secs(10) };
+        let mut bucket = TokenBucket::new(limit);
+        let start = bucket.refilled_at;
+
+        assert!(bucket.try_take(start).is_ok());
+        assert!(bucket.try_take(start).is_ok());
+        assert_eq!(bucket.try_take(start), Err(Duration::from_secs(5)));
+        assert!(bucket.try_take(start + Duration::from_secs(5)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn chatty_client_is_throttled_but_kept() {
+        let hits = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = hits.clone();
+        let julia = axum::Router::new().route(
+            "/agents/chat/stream",
+            post(move || async move {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                "{\"type\":\"done\",\"response\":\"ok\"}\n"
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let julia_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, julia).await.unwrap() });
+
+        let mut state = AgentWorkspaceState::new(julia_url);
+        state.message_limit = MessageRateLimit { burst: 2, window: Duration::from_secs(60) };
+        let app = agent_routes::<()>(CancellationToken::new()).with_state((Arc::new(()), Arc::new(Mutex::new(state))));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws/agent-chat", addr))
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            client.next().await.unwrap().unwrap();
+        }
+
+        let mut replies = Vec::new();
+        for _ in 0..4 {
+            client
+                .send(WsMessage::Text(r#"{"content":"hello","timestamp":0}"#.to_string()))
+                .await
+                .unwrap();
+            let reply = client.next().await.unwrap().unwrap();
+            replies.push(serde_json::from_str::<serde_json::Value>(reply.to_text().unwrap()).unwrap());
+        }
+
+        assert_eq!(replies[0]["response"], "ok");
+        assert_eq!(replies[1]["response"], "ok");
+        for reply in &replies[2..] {
+            assert_eq!(reply["type"], "rate_limited");
+            let wait = reply["retry_after_ms"].as_u64().unwrap();
+            assert!(wait > 0 && wait <= 30_000, "{}", wait);
+        }
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        // Control frames are not counted
+        client.send(WsMessage::Text(r#"{"type":"list_agents"}"#.to_string())).await.unwrap();
+        let reply = client.next().await.unwrap().unwrap();
+        assert!(reply.to_text().unwrap().contains(r#""type":"agents""#));
+    }
+
+    #[tokio::test]
+    async fn switch_agent_sets_the_session_default() {
+        let workspace = Arc::new(Mutex::new(AgentWorkspaceState::new("http://127.0.0.1:1".to_string())));
+        let app = agent_routes::<()>(CancellationToken::new()).with_state((Arc::new(()), workspace));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws/agent-chat", addr))
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            client.next().await.unwrap().unwrap();
+        }
+        let mut replies = Vec::new();
+        for frame in [
+            r#"{"type":"switch_agent","agent_type":"synthesis"}"#,
+            r#"{"type":"list_agents"}"#,
+            r#"{"type":"switch_agent","agent_type":"review"}"#,
+        ] {
+            client.send(WsMessage::Text(frame.to_string())).await.unwrap();
+            let reply = client.next().await.unwrap().unwrap();
+            replies.push(serde_json::from_str::<serde_json::Value>(reply.to_text().unwrap()).unwrap());
+        }
+        let [switched, listed, unknown] = <[serde_json::Value; 3]>::try_from(replies).unwrap();
+
+        assert_eq!(switched["type"], "active_agent");
+        assert_eq!(switched["agent_name"], "Synthesis Agent");
+        assert_eq!(listed["active_agent"], "synthesis");
+        assert_eq!(listed["agents"].as_array().unwrap().len(), AGENTS.len());
+        assert_eq!(unknown["type"], "system");
+    }
+
+    #[test]
+    fn messages_are_checked_for_schema_and_version() {
+        let msg = AgentMessage::parse(r#"{"content":"hi","timestamp":1}"#).unwrap();
+        assert_eq!(msg.protocol_version, PROTOCOL_VERSION);
+
+        let err = AgentMessage::parse(r#"{"protocol_version":2,"content":"hi","timestamp":1}"#).unwrap_err();
+        assert!(err.contains("unsupported protocol_version 2"), "{}", err);
+        let err = AgentMessage::parse(r#"{"content":"hi"}"#).unwrap_err();
+        assert!(err.contains("missing field `timestamp`"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn malformed_frames_get_an_error_reply() {
+        let workspace = Arc::new(Mutex::new(AgentWorkspaceState::new("http://127.0.0.1:1".to_string())));
+        let app = agent_routes::<()>(CancellationToken::new()).with_state((Arc::new(()), workspace));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws/agent-chat", addr))
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            client.next().await.unwrap().unwrap();
+        }
+
+        for frame in ["{not json", r#"{"protocol_version":99,"content":"hi","timestamp":0}"#] {
+            client.send(WsMessage::Text(frame.to_string())).await.unwrap();
+            let reply = client.next().await.unwrap().unwrap();
+            let reply: serde_json::Value = serde_json::from_str(reply.to_text().unwrap()).unwrap();
+            assert_eq!(reply["type"], "error");
+            assert!(reply["content"].as_str().unwrap().starts_with("invalid message: "));
+        }
+    }
 }
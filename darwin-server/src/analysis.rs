@@ -0,0 +1,363 @@
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::Value;
+use std::{path::PathBuf, sync::Arc};
+
+use crate::{
+    analysis_cache::{self, CacheKey},
+    db::WorkspaceRecord,
+    error::AppError,
+    events, julia, negotiate, scan_metadata, storage, AppState,
+};
+
+/// Smallest integer stride `f` such that keeping every `f`-th voxel along each axis
+/// leaves at most `max_voxels` voxels.
+pub fn downsample_factor(dimensions: [u64; 3], max_voxels: u64) -> u64 {
+    let kept = |f: u64| dimensions.iter().map(|d| d.div_ceil(f)).product::<u64>();
+    let largest = dimensions.iter().copied().max().unwrap_or(1).max(1);
+    (1..=largest).find(|&f| kept(f) <= max_voxels).unwrap_or(largest)
+}
+
+/// Canonical path of `file_path`, provided it is a stored upload.
+async fn stored_upload(state: &AppState, file_path: &str) -> Option<PathBuf> {
+    let (Ok(path), Ok(upload_dir)) = (
+        tokio::fs::canonicalize(file_path).await,
+        tokio::fs::canonicalize(&state.upload_dir).await,
+    ) else {
+        return None;
+    };
+    path.starts_with(&upload_dir).then_some(path)
+}
+
+/// Header of `file_path`, provided it is a stored upload.
+async fn upload_metadata(state: &AppState, file_path: &str) -> Result<Option<scan_metadata::ScanMetadata>, AppError> {
+    let Some(path) = stored_upload(state, file_path).await else {
+        return Err(AppError::validation("`file_path` must point to an uploaded file"));
+    };
+
+    // Stored uploads are named `{file_id}_{original name}`, so the extension survives
+    let file_name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    Ok(tokio::task::spawn_blocking(move || scan_metadata::from_file(&path, &file_name))
+        .await
+        .ok()
+        .flatten())
+}
+
+/// Key under which the result for `payload` is cached; only stored uploads are cached.
+async fn cache_key(state: &AppState, payload: &Value) -> Option<CacheKey> {
+    if !state.analysis_cache.enabled() {
+        return None;
+    }
+    let path = stored_upload(state, payload.get("file_path")?.as_str()?).await?;
+    let file_sha256 = analysis_cache::file_sha256(&path).await.ok()?;
+    Some(CacheKey::new(file_sha256, payload))
+}
+
+/// `POST /api/analyze` - forward to Julia. With `max_voxels`, volumes whose header
+/// reports more voxels are downsampled by an integer `downsample_factor`, which is
+/// echoed in the result (`1` when no downsampling was needed). The result is
+/// MessagePack instead of JSON when the client sends `Accept: application/msgpack`.
+///
+/// Results for stored uploads are cached (see `analysis_cache`); a cached result
+/// is marked `"cached": true`. Uncached analyses of one workspace run one at a
+/// time (see `workspace_locks`).
+pub async fn analyze_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<Value>,
+) -> Result<Response, AppError> {
+    analyze_and_publish(&state, &headers, payload).await
+}
+
+/// `POST /api/reanalyze` - analyze an already stored upload again, typically with
+/// a corrected `voxel_size`, without uploading it twice.
+///
+/// Takes `{file_id, voxel_size}` (plus any other `/api/analyze` option) where
+/// `file_id` is the id `/api/upload` returned, and answers like `/api/analyze`.
+/// An id with no stored upload is `404`.
+pub async fn reanalyze_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(mut payload): Json<Value>,
+) -> Result<Response, AppError> {
+    let Some(file_id) = payload.get("file_id").and_then(Value::as_str).map(str::to_string) else {
+        return Err(AppError::validation("`file_id` is required"));
+    };
+    if !payload.get("voxel_size").and_then(Value::as_f64).is_some_and(|v| v > 0.0) {
+        return Err(AppError::validation("`voxel_size` must be a positive number"));
+    }
+    let found = storage::find_upload(state.storage.as_ref(), &file_id).await;
+    let Some(file_path) = found.map_err(|e| AppError::internal(format!("cannot look up upload {}: {}", file_id, e)))? else {
+        return Err(AppError::not_found(format!("no upload with id {}", file_id)));
+    };
+
+    if let Some(request) = payload.as_object_mut() {
+        request.remove("file_id");
+        request.insert("file_path".to_string(), serde_json::json!(file_path.to_string_lossy()));
+    }
+    analyze_and_publish(&state, &headers, payload).await
+}
+
+/// Run [`analyze_and_notify`] and encode the result for the client.
+async fn analyze_and_publish(state: &AppState, headers: &HeaderMap, payload: Value) -> Result<Response, AppError> {
+    let format = negotiate::Format::from_headers(headers);
+    let body = analyze_and_notify(state, headers, payload).await?;
+    Ok(negotiate::Negotiated(format, body).into_response())
+}
+
+/// Run [`analyze`] and announce the result on the event bus and to the analysis webhooks.
+pub async fn analyze_and_notify(state: &AppState, headers: &HeaderMap, payload: Value) -> Result<Value, AppError> {
+    let workspace_id = events::ServerEvent::workspace_id(&payload);
+    let requested_path = payload.get("file_path").and_then(Value::as_str).map(str::to_string);
+    let file_path = match &requested_path {
+        Some(file_path) if !state.webhooks.is_empty() => stored_upload(state, file_path).await,
+        _ => None,
+    };
+    let body = analyze(state, headers, payload).await?;
+    if let Some(workspace_id) = &workspace_id {
+        record_analysis(state, workspace_id, requested_path.as_deref(), &body).await;
+    }
+    state.webhooks.notify_analysis(workspace_id.clone(), file_path, body.clone());
+    state.events.publish(events::ServerEvent::AnalysisComplete {
+        workspace_id,
+        metrics: body.clone(),
+    });
+    Ok(body)
+}
+
+/// Keep `body` as the workspace's latest analysis. A failed write is logged; the
+/// analysis itself succeeded.
+pub async fn record_analysis(state: &AppState, workspace_id: &str, file_path: Option<&str>, body: &Value) {
+    if let Err(e) = state.db.record_analysis(workspace_id, file_path, body).await {
+        tracing::warn!(workspace_id, "failed to save the analysis: {}", e);
+    }
+}
+
+/// `GET /api/workspaces/:workspace_id` - the latest analysis run for a workspace
+/// (by `/api/analyze`, `/api/analyze/auto` or a job), kept across restarts.
+pub async fn workspace_handler(
+    State(state): State<Arc<AppState>>,
+    Path(workspace_id): Path<String>,
+) -> Result<Json<WorkspaceRecord>, AppError> {
+    state
+        .db
+        .workspace(&workspace_id)
+        .await
+        .map_err(AppError::internal)?
+        .map(Json)
+        .ok_or_else(|| AppError::not_found(format!("no analysis for workspace {}", workspace_id)))
+}
+
+/// The analysis behind [`analyze_handler`]: downsampling, the result cache and the
+/// Julia call.
+pub async fn analyze(state: &AppState, headers: &HeaderMap, mut payload: Value) -> Result<Value, AppError> {
+    let max_voxels = match payload.get("max_voxels") {
+        None | Some(Value::Null) => None,
+        Some(v) => match v.as_u64().filter(|&n| n > 0) {
+            Some(n) => Some(n),
+            None => return Err(AppError::validation("`max_voxels` must be a positive integer")),
+        },
+    };
+
+    let mut downsampling = None;
+    if let Some(max_voxels) = max_voxels {
+        let Some(file_path) = payload.get("file_path").and_then(Value::as_str) else {
+            return Err(AppError::validation("`file_path` is required"));
+        };
+        let metadata = upload_metadata(state, file_path).await?;
+        // Without a size in the header Julia picks the factor itself from `max_voxels`
+        if let Some(dimensions) = metadata.and_then(|m| m.dimensions) {
+            let factor = downsample_factor(dimensions, max_voxels);
+            payload["downsample_factor"] = serde_json::json!(factor);
+            downsampling = Some((factor, dimensions));
+        }
+    }
+
+    let cache_key = cache_key(state, &payload).await;
+    if let Some(mut body) = cache_key.as_ref().and_then(|key| state.analysis_cache.get(key)) {
+        if let Some(result) = body.as_object_mut() {
+            result.insert("cached".to_string(), Value::Bool(true));
+        }
+        return Ok(body);
+    }
+
+    let _workspace = state.workspace_locks.lock_for(&payload).await;
+    let mut body = julia::fetch_julia(state, "analyze", headers, payload).await?.into_body()?;
+    if let (Some((factor, dimensions)), Some(result)) = (downsampling, body.as_object_mut()) {
+        result.entry("downsample_factor").or_insert(serde_json::json!(factor));
+        result.insert("original_dimensions".to_string(), serde_json::json!(dimensions));
+    }
+    if let Some(key) = cache_key {
+        state.analysis_cache.insert(key, body.clone());
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::StatusCode, routing::post, Router};
+    use serde_json::json;
+
+    #[test]
+    fn factor_is_the_smallest_stride_that_fits() {
+        assert_eq!(downsample_factor([64, 64, 32], 1 << 20), 1);
+        assert_eq!(downsample_factor([64, 64, 32], 64 * 64 * 32 / 8), 2);
+        // 3x3x2 = 18 > 16 at f = 2, 2x2x1 = 4 at f = 3
+        assert_eq!(downsample_factor([5, 5, 3], 16), 3);
+        assert_eq!(downsample_factor([10, 10, 10], 1), 10);
+    }
+
+    #[tokio::test]
+    async fn oversized_upload_is_downsampled() {
+        // Echo the request back so the forwarded payload is visible in the reply
+        let app = Router::new().route("/analyze", post(|Json(body): Json<Value>| async move { Json(json!({"request": body})) }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let state = Arc::new(AppState::for_tests(&format!("http://{}", addr)));
+        std::fs::create_dir_all(&state.upload_dir).unwrap();
+        let file_path = state.upload_dir.join(format!("{}_scan.nii", uuid::Uuid::new_v4()));
+        std::fs::write(&file_path, include_bytes!("../tests/fixtures/header_um.nii")).unwrap();
+
+        let payload = json!({"file_path": file_path, "voxel_size": 10.0, "max_voxels": 10_000});
+        let response = analyze_handler(State(state.clone()), HeaderMap::new(), Json(payload)).await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["request"]["downsample_factor"], 3);
+        assert_eq!(body["downsample_factor"], 3);
+        assert_eq!(body["original_dimensions"], json!([64, 64, 32]));
+
+        let outside = json!({"file_path": "/etc/hostname", "max_voxels": 10_000});
+        let response = analyze_handler(State(state), HeaderMap::new(), Json(outside)).await.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        std::fs::remove_file(file_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reanalyze_resolves_the_stored_upload() {
+        let app = Router::new().route("/analyze", post(|Json(body): Json<Value>| async move { Json(json!({"request": body})) }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let state = Arc::new(AppState::for_tests(&format!("http://{}", addr)));
+        std::fs::create_dir_all(&state.upload_dir).unwrap();
+        let file_id = uuid::Uuid::new_v4();
+        let file_path = state.upload_dir.join(format!("{}_scan.tif", file_id));
+        std::fs::write(&file_path, b"II*\0").unwrap();
+
+        let reanalyze = |payload: Value| {
+            let state = state.clone();
+            async move { reanalyze_handler(State(state), HeaderMap::new(), Json(payload)).await.into_response() }
+        };
+        let response = reanalyze(json!({"file_id": file_id, "voxel_size": 7.5})).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        let canonical = std::fs::canonicalize(&file_path).unwrap();
+        assert_eq!(body["request"]["file_path"], canonical.to_string_lossy().as_ref());
+        assert_eq!(body["request"]["voxel_size"], 7.5);
+        assert_eq!(body["request"].get("file_id"), None);
+
+        let response = reanalyze(json!({"file_id": uuid::Uuid::new_v4(), "voxel_size": 7.5})).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = reanalyze(json!({"file_id": "../../etc/passwd", "voxel_size": 7.5})).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = reanalyze(json!({"file_id": file_id, "voxel_size": 0})).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        std::fs::remove_file(file_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn repeated_analysis_of_an_unchanged_upload_is_served_from_cache() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = calls.clone();
+        let app = Router::new().route(
+            "/analyze",
+            post(move || async move {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                Json(json!({"porosity": 0.82}))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let state = Arc::new(AppState::for_tests(&format!("http://{}", addr)));
+        std::fs::create_dir_all(&state.upload_dir).unwrap();
+        let file_path = state.upload_dir.join(format!("{}_scan.raw", uuid::Uuid::new_v4()));
+        std::fs::write(&file_path, [1u8; 64]).unwrap();
+
+        let analyze = |voxel_size: f64| {
+            let state = state.clone();
+            let payload = json!({"file_path": file_path, "voxel_size": voxel_size});
+            async move {
+                let response = analyze_handler(State(state), HeaderMap::new(), Json(payload)).await.into_response();
+                assert_eq!(response.status(), StatusCode::OK);
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                serde_json::from_slice::<Value>(&bytes).unwrap()
+            }
+        };
+
+        assert!(analyze(10.0).await.get("cached").is_none());
+        assert_eq!(analyze(10.0).await["cached"], true);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        // Other parameters or changed content miss
+        assert!(analyze(12.0).await.get("cached").is_none());
+        std::fs::write(&file_path, [2u8; 64]).unwrap();
+        assert!(analyze(10.0).await.get("cached").is_none());
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 3);
+        std::fs::remove_file(file_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn msgpack_and_json_results_decode_to_the_same_struct() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Metrics {
+            porosity: f64,
+            mean_pore_size_um: f64,
+            interconnectivity: f64,
+            n_pores: u64,
+        }
+
+        let app = Router::new().route(
+            "/analyze",
+            post(|| async {
+                Json(json!({"porosity": 0.82, "mean_pore_size_um": 231.5, "interconnectivity": 0.97, "n_pores": 1204}))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        let state = Arc::new(AppState::for_tests(&format!("http://{}", addr)));
+
+        let analyze = |accept: &'static str| {
+            let state = state.clone();
+            async move {
+                let mut headers = HeaderMap::new();
+                headers.insert(axum::http::header::ACCEPT, accept.parse().unwrap());
+                let response = analyze_handler(State(state), headers, Json(json!({"voxel_size": 10.0}))).await.into_response();
+                assert_eq!(response.status(), StatusCode::OK);
+                let content_type = response.headers()[axum::http::header::CONTENT_TYPE].to_str().unwrap().to_string();
+                (content_type, axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap())
+            }
+        };
+
+        let (json_type, json_bytes) = analyze("application/json").await;
+        let (msgpack_type, msgpack_bytes) = analyze("application/msgpack").await;
+        assert_eq!(json_type, "application/json");
+        assert_eq!(msgpack_type, negotiate::MSGPACK_CONTENT_TYPE);
+
+        let from_json: Metrics = serde_json::from_slice(&json_bytes).unwrap();
+        let from_msgpack: Metrics = rmp_serde::from_slice(&msgpack_bytes).unwrap();
+        assert_eq!(from_json, from_msgpack);
+        assert_eq!(from_msgpack.n_pores, 1204);
+    }
+}
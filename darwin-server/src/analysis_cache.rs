@@ -0,0 +1,193 @@
+//! Results of `POST /api/analyze` for uploads that were already analysed with the
+//! same parameters, so page reloads don't re-run Julia.
+//!
+//! Entries are keyed by the SHA-256 of the file rather than its path: a changed
+//! file simply misses. The cache holds at most `capacity` entries, evicting the
+//! least recently used, and entries older than `ttl` are never served.
+
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::io::AsyncReadExt;
+
+pub const DEFAULT_CAPACITY: usize = 128;
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Payload fields that don't change the result
+const IGNORED_FIELDS: &[&str] = &["file_path", "workspace_id"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    file_sha256: String,
+    /// Bit pattern, so the key can be `Eq`
+    voxel_size: Option<u64>,
+    /// Remaining request fields as canonical (key-sorted) JSON
+    analysis_params: String,
+}
+
+impl CacheKey {
+    pub fn new(file_sha256: String, payload: &Value) -> Self {
+        let mut params: Map<String, Value> = payload.as_object().cloned().unwrap_or_default();
+        for field in IGNORED_FIELDS {
+            params.remove(*field);
+        }
+        let voxel_size = params.remove("voxel_size").and_then(|v| v.as_f64()).map(f64::to_bits);
+        Self {
+            file_sha256,
+            voxel_size,
+            analysis_params: Value::Object(params).to_string(),
+        }
+    }
+}
+
+struct Entry {
+    result: Value,
+    inserted: Instant,
+    last_used: u64,
+}
+
+struct Inner {
+    entries: HashMap<CacheKey, Entry>,
+    /// Monotonic use counter; the entry with the lowest `last_used` is evicted first
+    clock: u64,
+}
+
+/// Cheap to clone; clones share the same entries.
+#[derive(Clone)]
+pub struct AnalysisCache {
+    inner: Arc<Mutex<Inner>>,
+    capacity: usize,
+    ttl: Duration,
+}
+
+impl AnalysisCache {
+    /// A `capacity` of 0 disables caching.
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner { entries: HashMap::new(), clock: 0 })),
+            capacity,
+            ttl,
+        }
+    }
+
+    /// Sized from `DARWIN_ANALYSIS_CACHE_ENTRIES` (0 disables) and `DARWIN_ANALYSIS_CACHE_TTL_SECS`.
+    pub fn from_env() -> Self {
+        let capacity = std::env::var("DARWIN_ANALYSIS_CACHE_ENTRIES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_CAPACITY);
+        let ttl = std::env::var("DARWIN_ANALYSIS_CACHE_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TTL);
+        Self::new(capacity, ttl)
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<Value> {
+        self.get_at(key, Instant::now())
+    }
+
+    pub fn insert(&self, key: CacheKey, result: Value) {
+        self.insert_at(key, result, Instant::now())
+    }
+
+    fn get_at(&self, key: &CacheKey, now: Instant) -> Option<Value> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.clock += 1;
+        let clock = inner.clock;
+        match inner.entries.get_mut(key) {
+            Some(entry) if now.duration_since(entry.inserted) < self.ttl => {
+                entry.last_used = clock;
+                Some(entry.result.clone())
+            }
+            Some(_) => {
+                inner.entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert_at(&self, key: CacheKey, result: Value, now: Instant) {
+        if !self.enabled() {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.clock += 1;
+        let last_used = inner.clock;
+        if !inner.entries.contains_key(&key) && inner.entries.len() >= self.capacity {
+            let ttl = self.ttl;
+            inner.entries.retain(|_, entry| now.duration_since(entry.inserted) < ttl);
+            if inner.entries.len() >= self.capacity {
+                let oldest = inner.entries.iter().min_by_key(|(_, entry)| entry.last_used).map(|(k, _)| k.clone());
+                if let Some(oldest) = oldest {
+                    inner.entries.remove(&oldest);
+                }
+            }
+        }
+        inner.entries.insert(key, Entry { result, inserted: now, last_used });
+    }
+}
+
+/// Hex SHA-256 of the file at `path`.
+pub async fn file_sha256(path: &Path) -> std::io::Result<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 256 * 1024];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn key(sha: &str, payload: Value) -> CacheKey {
+        CacheKey::new(sha.to_string(), &payload)
+    }
+
+    #[test]
+    fn key_ignores_path_and_workspace_but_not_parameters() {
+        let a = key("abc", json!({"file_path": "/a.tif", "workspace_id": "ws-1", "voxel_size": 10.0}));
+        let b = key("abc", json!({"file_path": "/b.tif", "voxel_size": 10.0}));
+        assert_eq!(a, b);
+        assert_ne!(a, key("abc", json!({"voxel_size": 12.0})));
+        assert_ne!(a, key("abc", json!({"voxel_size": 10.0, "downsample_factor": 2})));
+        assert_ne!(a, key("def", json!({"voxel_size": 10.0})));
+    }
+
+    #[test]
+    fn entries_expire_and_least_recently_used_is_evicted() {
+        let cache = AnalysisCache::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+        let (first, second, third) = (key("1", json!({})), key("2", json!({})), key("3", json!({})));
+
+        cache.insert_at(first.clone(), json!({"porosity": 0.1}), start);
+        cache.insert_at(second.clone(), json!({"porosity": 0.2}), start);
+        // Touch `first` so `second` becomes the eviction candidate
+        assert_eq!(cache.get_at(&first, start), Some(json!({"porosity": 0.1})));
+        cache.insert_at(third.clone(), json!({"porosity": 0.3}), start);
+        assert_eq!(cache.get_at(&second, start), None);
+        assert!(cache.get_at(&third, start).is_some());
+
+        assert!(cache.get_at(&first, start + Duration::from_secs(59)).is_some());
+        assert_eq!(cache.get_at(&first, start + Duration::from_secs(60)), None);
+    }
+}
@@ -0,0 +1,332 @@
+//! Append-only audit trail of who changed what, for regulated workflows.
+//!
+//! [`record_mutations`] turns every mutating `/api` request into an [`AuditEntry`]
+//! and hands it to a background writer over a channel, so the request never waits
+//! on the disk. The writer appends one JSON line per entry to `DARWIN_AUDIT_LOG`
+//! and fsyncs before acknowledging the batch. `GET /api/audit?since=<unix ms>`
+//! reads entries back.
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{MatchedPath, Query, Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{io::AsyncWriteExt, sync::mpsc};
+
+use crate::{db::now_ms, error::AppError, events::ServerEvent, jwt::AuthClaims, AppState};
+
+/// Recorded as the user when the request carried no verified JWT
+pub const ANONYMOUS: &str = "anonymous";
+/// Largest JSON body buffered for hashing, matching axum's default limit for `Json`
+const MAX_JSON_BODY_BYTES: usize = 2 * 1024 * 1024;
+pub const DEFAULT_QUERY_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// 2xx/3xx
+    Success,
+    /// 4xx: refused before anything changed
+    Rejected,
+    /// 5xx
+    Failed,
+}
+
+impl Outcome {
+    pub fn from_status(status: StatusCode) -> Self {
+        match status.as_u16() {
+            500.. => Self::Failed,
+            400.. => Self::Rejected,
+            _ => Self::Success,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// When the request finished, Unix milliseconds
+    pub timestamp: u64,
+    /// `sub` of the verified JWT, or [`ANONYMOUS`]
+    pub user: String,
+    /// Method and route, e.g. `POST /api/upload/:upload_id/complete`
+    pub operation: String,
+    pub workspace_id: Option<String>,
+    /// Hex SHA-256 of the JSON body in canonical (key-sorted) form, or of the
+    /// query string for other bodies; the parameters themselves are not kept
+    pub params_hash: String,
+    pub outcome: Outcome,
+    pub status: u16,
+}
+
+/// Cheap to clone; clones feed the same writer.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: Arc<PathBuf>,
+    sender: mpsc::UnboundedSender<AuditEntry>,
+}
+
+impl AuditLog {
+    /// Start the writer task appending to `path`; must be called inside the runtime.
+    /// The file is created on the first entry.
+    pub fn spawn(path: PathBuf) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(write_entries(path.clone(), receiver));
+        Self { path: Arc::new(path), sender }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Queue `entry` for the writer; never waits.
+    pub fn record(&self, entry: AuditEntry) {
+        if let Err(e) = self.sender.send(entry) {
+            tracing::error!(operation = %e.0.operation, "audit writer has stopped, entry lost");
+        }
+    }
+
+    /// Written entries newer than `since`, newest first, at most `limit` of them.
+    pub async fn query(&self, since: Option<u64>, limit: usize) -> Result<Vec<AuditEntry>, String> {
+        let text = match tokio::fs::read_to_string(self.path()).await {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("reading {}: {}", self.path().display(), e)),
+        };
+        let mut entries = Vec::new();
+        for (n, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let entry: AuditEntry = serde_json::from_str(line)
+                .map_err(|e| format!("{} line {} is corrupt: {}", self.path().display(), n + 1, e))?;
+            if since.is_none_or(|since| entry.timestamp > since) {
+                entries.push(entry);
+            }
+        }
+        entries.reverse();
+        entries.truncate(limit);
+        Ok(entries)
+    }
+}
+
+/// Append each batch of entries and fsync it. A failed write is logged and the
+/// file reopened for the next batch; entries are never rewritten.
+async fn write_entries(path: PathBuf, mut receiver: mpsc::UnboundedReceiver<AuditEntry>) {
+    let mut file: Option<tokio::fs::File> = None;
+    while let Some(entry) = receiver.recv().await {
+        let mut batch = vec![entry];
+        while let Ok(entry) = receiver.try_recv() {
+            batch.push(entry);
+        }
+        let mut lines = String::new();
+        for entry in &batch {
+            lines.push_str(&serde_json::to_string(entry).unwrap_or_default());
+            lines.push('\n');
+        }
+
+        let written = async {
+            if file.is_none() {
+                if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                    tokio::fs::create_dir_all(dir).await?;
+                }
+                file = Some(tokio::fs::OpenOptions::new().create(true).append(true).open(&path).await?);
+            }
+            let f = file.as_mut().unwrap();
+            f.write_all(lines.as_bytes()).await?;
+            f.sync_data().await
+        }
+        .await;
+        if let Err(e) = written {
+            tracing::error!(path = %path.display(), entries = batch.len(), "writing the audit log failed: {}", e);
+            file = None;
+        }
+    }
+}
+
+fn hex_sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn is_json(request: &Request) -> bool {
+    request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"))
+}
+
+/// Middleware for the authenticated `/api` routes, inside `auth::require_auth` so
+/// the caller's claims are known: records every request that is not a GET, HEAD
+/// or OPTIONS once its response is ready.
+pub async fn record_mutations(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    if matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(request).await;
+    }
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| request.uri().path().to_string(), |path| path.as_str().to_string());
+    let operation = format!("{} {}", request.method(), route);
+    let user = request
+        .extensions()
+        .get::<AuthClaims>()
+        .and_then(AuthClaims::user_id)
+        .unwrap_or(ANONYMOUS)
+        .to_string();
+
+    let (request, params, workspace_id) = if is_json(&request) {
+        let (parts, body) = request.into_parts();
+        let Ok(bytes) = axum::body::to_bytes(body, MAX_JSON_BODY_BYTES).await else {
+            let error = format!("JSON body exceeds {} bytes", MAX_JSON_BODY_BYTES);
+            return AppError::rejected(StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large", error).into_response();
+        };
+        let (params, workspace_id) = match serde_json::from_slice::<Value>(&bytes) {
+            Ok(value) => (Bytes::from(value.to_string()), ServerEvent::workspace_id(&value)),
+            Err(_) => (bytes.clone(), None),
+        };
+        (Request::from_parts(parts, Body::from(bytes)), params, workspace_id)
+    } else {
+        let query = Bytes::from(request.uri().query().unwrap_or_default().to_string());
+        (request, query, None)
+    };
+
+    let response = next.run(request).await;
+    state.audit.record(AuditEntry {
+        timestamp: now_ms(),
+        user,
+        operation,
+        workspace_id,
+        params_hash: hex_sha256(&params),
+        outcome: Outcome::from_status(response.status()),
+        status: response.status().as_u16(),
+    });
+    response
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditParams {
+    pub since: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+/// `GET /api/audit?since=<unix ms>&limit=` - audit entries newest first, as
+/// `{"entries": [...]}`. At most [`DEFAULT_QUERY_LIMIT`] unless `limit` says otherwise.
+pub async fn audit_handler(State(state): State<Arc<AppState>>, Query(params): Query<AuditParams>) -> Result<Json<Value>, AppError> {
+    let entries = state
+        .audit
+        .query(params.since, params.limit.unwrap_or(DEFAULT_QUERY_LIMIT))
+        .await
+        .map_err(AppError::internal)?;
+    Ok(Json(serde_json::json!({"entries": entries})))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{middleware, routing::post, Router};
+    use serde_json::json;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    fn temp_log() -> PathBuf {
+        std::env::temp_dir().join(format!("darwin-audit-test-{}", uuid::Uuid::new_v4())).join("audit.jsonl")
+    }
+
+    fn entry(timestamp: u64, operation: &str) -> AuditEntry {
+        AuditEntry {
+            timestamp,
+            user: ANONYMOUS.to_string(),
+            operation: operation.to_string(),
+            workspace_id: None,
+            params_hash: hex_sha256(b""),
+            outcome: Outcome::Success,
+            status: 200,
+        }
+    }
+
+    /// Entries reach the file asynchronously; wait until `count` are there
+    async fn written(log: &AuditLog, count: usize) -> Vec<AuditEntry> {
+        for _ in 0..100 {
+            let entries = log.query(None, usize::MAX).await.unwrap();
+            if entries.len() >= count {
+                return entries;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("audit log never reached {} entries", count);
+    }
+
+    #[tokio::test]
+    async fn entries_are_appended_and_queried_newest_first() {
+        let path = temp_log();
+        let log = AuditLog::spawn(path.clone());
+        for n in 1..=3 {
+            log.record(entry(n * 1000, &format!("POST /api/op{}", n)));
+        }
+        written(&log, 3).await;
+
+        // A restarted server appends to the same file
+        let reopened = AuditLog::spawn(path.clone());
+        reopened.record(entry(4000, "POST /api/op4"));
+        let entries = written(&reopened, 4).await;
+        let ops: Vec<&str> = entries.iter().map(|e| e.operation.as_str()).collect();
+        assert_eq!(ops, ["POST /api/op4", "POST /api/op3", "POST /api/op2", "POST /api/op1"]);
+
+        let recent = reopened.query(Some(2000), 10).await.unwrap();
+        assert_eq!(recent.len(), 2);
+        assert_eq!(reopened.query(None, 1).await.unwrap()[0].timestamp, 4000);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn mutating_requests_are_recorded_with_a_canonical_hash() {
+        let path = temp_log();
+        let state = Arc::new(AppState { audit: AuditLog::spawn(path.clone()), ..AppState::for_tests("http://127.0.0.1:1") });
+        let app = Router::new()
+            .route(
+                "/api/mesh/:kind",
+                post(|Json(body): Json<Value>| async move {
+                    match body["fail"].as_bool() {
+                        Some(true) => (StatusCode::BAD_GATEWAY, "julia down").into_response(),
+                        _ => Json(body).into_response(),
+                    }
+                })
+                .get(|| async { "read only" }),
+            )
+            .route_layer(middleware::from_fn_with_state(state.clone(), record_mutations))
+            .with_state(state.clone());
+        let send = |request: axum::http::request::Builder, body: &str| {
+            let app = app.clone();
+            let request = request.header("content-type", "application/json").body(Body::from(body.to_string())).unwrap();
+            async move { app.oneshot(request).await.unwrap() }
+        };
+
+        let response = send(Request::post("/api/mesh/raw"), r#"{"workspace_id": "ws-1", "quality": "high"}"#).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let echoed = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&echoed).unwrap()["quality"], "high");
+        send(Request::post("/api/mesh/raw"), r#"{"quality": "high", "workspace_id": "ws-1"}"#).await;
+        send(Request::post("/api/mesh/raw"), r#"{"workspace_id": "ws-2", "fail": true}"#).await;
+        send(Request::get("/api/mesh/raw"), "").await;
+
+        let entries = written(&state.audit, 3).await;
+        assert_eq!(entries.len(), 3);
+        let failed = &entries[0];
+        assert_eq!(failed.workspace_id.as_deref(), Some("ws-2"));
+        assert_eq!((failed.outcome, failed.status), (Outcome::Failed, 502));
+        let (second, first) = (&entries[1], &entries[2]);
+        assert_eq!(first.operation, "POST /api/mesh/:kind");
+        assert_eq!(first.user, ANONYMOUS);
+        assert_eq!(first.outcome, Outcome::Success);
+        // Key order does not change the hash
+        assert_eq!(first.params_hash, second.params_hash);
+        assert_eq!(first.params_hash, hex_sha256(json!({"workspace_id": "ws-1", "quality": "high"}).to_string().as_bytes()));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
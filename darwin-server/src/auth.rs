@@ -0,0 +1,105 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+
+use crate::{config::Config, error::AppError, jwt, AppState};
+
+/// Whether the request carries the configured API key, as `X-API-Key: <key>`
+/// or `Authorization: Bearer <key>`. Always false when no key is configured.
+pub fn has_valid_key(headers: &HeaderMap, config: &Config) -> bool {
+    let Some(expected) = config.api_key.as_deref() else {
+        return false;
+    };
+    let provided = headers
+        .get("x-api-key")
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| bearer_token(headers));
+    provided.is_some_and(|key| constant_time_eq(key.trim().as_bytes(), expected.as_bytes()))
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub fn unauthorized() -> AppError {
+    AppError::Unauthorized {
+        message: "missing or invalid API key".to_string(),
+        bearer_error: None,
+        details: Default::default(),
+    }
+}
+
+fn invalid_token(reason: String) -> AppError {
+    AppError::Unauthorized { message: "invalid token".to_string(), bearer_error: Some("invalid_token"), details: Default::default() }
+        .with_detail("reason", reason)
+}
+
+/// Check the credentials `headers` carry: the configured API key (`Ok(None)`), or
+/// a bearer JWT the configured JWKS verifies (`Ok(Some(claims))`). Fails when
+/// neither is configured.
+pub async fn authenticate(state: &AppState, headers: &HeaderMap) -> Result<Option<jwt::AuthClaims>, AppError> {
+    if has_valid_key(headers, &state.config) {
+        return Ok(None);
+    }
+    let (Some(verifier), Some(token)) = (&state.jwt, bearer_token(headers)) else {
+        return Err(unauthorized());
+    };
+    verifier.verify(token.trim()).await.map(Some).map_err(invalid_token)
+}
+
+/// Middleware for the `/api` routes: a no-op unless `DARWIN_API_KEY` or
+/// `DARWIN_JWT_JWKS_URL` is set. With a JWKS a valid bearer JWT is required, its
+/// claims landing in the request extensions as `jwt::AuthClaims`; a configured
+/// API key keeps working alongside it for scripts.
+pub async fn require_auth(State(state): State<Arc<AppState>>, mut request: Request, next: Next) -> Response {
+    if !state.config.auth_enabled() {
+        return next.run(request).await;
+    }
+    match authenticate(&state, request.headers()).await {
+        Ok(claims) => {
+            if let Some(claims) = claims {
+                if let Some(user) = claims.user_id() {
+                    tracing::Span::current().record("user", user);
+                }
+                request.extensions_mut().insert::<jwt::AuthClaims>(claims);
+            }
+            next.run(request).await
+        }
+        Err(error) => error.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn accepts_either_header_form() {
+        let config = Config { api_key: Some("k3y".to_string()), ..Config::default() };
+
+        let mut headers = HeaderMap::new();
+        assert!(!has_valid_key(&headers, &config));
+        headers.insert("x-api-key", HeaderValue::from_static("k3y"));
+        assert!(has_valid_key(&headers, &config));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer k3y"));
+        assert!(has_valid_key(&headers, &config));
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer k3"));
+        assert!(!has_valid_key(&headers, &config));
+
+        assert!(!has_valid_key(&headers, &Config::default()));
+    }
+}
@@ -0,0 +1,183 @@
+//! `POST /api/analyze/auto` - debounced analysis for live parameter tweaking.
+//!
+//! Each call supersedes the previous one for the same workspace: a pending call is
+//! dropped before it reaches Julia, and one already in flight is cancelled (its
+//! request to Julia is aborted). Only a call left alone for the debounce window
+//! (`DARWIN_AUTO_ANALYZE_DEBOUNCE_MS`) runs; its result is published on
+//! `/ws/events` as `analysis_complete`, or `auto_analyze_failed`, for that workspace.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{analysis, error::AppError, events::ServerEvent, limits, logging, AppState};
+
+#[derive(Debug)]
+struct Pending {
+    generation: u64,
+    cancel: CancellationToken,
+}
+
+/// The latest auto-analysis per workspace.
+#[derive(Debug, Clone, Default)]
+pub struct AutoAnalyze {
+    next_generation: Arc<AtomicU64>,
+    pending: Arc<Mutex<HashMap<String, Pending>>>,
+}
+
+impl AutoAnalyze {
+    /// Cancel whatever `workspace_id` has pending and register a new call.
+    fn supersede(&self, workspace_id: &str) -> (u64, CancellationToken) {
+        let generation = self.next_generation.fetch_add(1, Ordering::Relaxed) + 1;
+        let cancel = CancellationToken::new();
+        let previous = self.pending.lock().unwrap().insert(
+            workspace_id.to_string(),
+            Pending { generation, cancel: cancel.clone() },
+        );
+        if let Some(previous) = previous {
+            previous.cancel.cancel();
+        }
+        (generation, cancel)
+    }
+
+    /// Forget `generation` if it is still the latest; `false` when it was superseded.
+    fn finish(&self, workspace_id: &str, generation: u64) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        match pending.get(workspace_id) {
+            Some(latest) if latest.generation == generation => {
+                pending.remove(workspace_id);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// `POST /api/analyze/auto` - the `/api/analyze` body plus a required `workspace_id`.
+///
+/// Answers `202` with `{workspace_id, generation}` at once; the outcome arrives
+/// as an event.
+pub async fn auto_analyze_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<Value>,
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let Some(workspace_id) = ServerEvent::workspace_id(&payload) else {
+        return Err(AppError::validation("`workspace_id` is required"));
+    };
+
+    let (generation, cancel) = state.auto_analyze.supersede(&workspace_id);
+    // The failure event quotes the request id of the call that started the run
+    let run = run_when_settled(state.clone(), workspace_id.clone(), generation, cancel, headers, payload);
+    tokio::spawn(logging::with_request_id(logging::current_request_id().unwrap_or_default(), run));
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(serde_json::json!({"workspace_id": workspace_id, "generation": generation})),
+    ))
+}
+
+/// Wait out the debounce window, analyze, and publish the outcome unless superseded.
+async fn run_when_settled(
+    state: Arc<AppState>,
+    workspace_id: String,
+    generation: u64,
+    cancel: CancellationToken,
+    headers: HeaderMap,
+    payload: Value,
+) {
+    let run = async {
+        tokio::time::sleep(state.config.auto_analyze_debounce).await;
+        let Some(_permit) = state.julia_limiter.acquire().await else {
+            return Err(limits::busy());
+        };
+        let file_path = payload.get("file_path").and_then(Value::as_str).map(str::to_string);
+        analysis::analyze(&state, &headers, payload).await.map(|metrics| (file_path, metrics))
+    };
+    let outcome = tokio::select! {
+        outcome = run => outcome,
+        _ = cancel.cancelled() => return,
+    };
+    if !state.auto_analyze.finish(&workspace_id, generation) {
+        return;
+    }
+
+    let event = match outcome {
+        Ok((file_path, metrics)) => {
+            analysis::record_analysis(&state, &workspace_id, file_path.as_deref(), &metrics).await;
+            ServerEvent::AnalysisComplete { workspace_id: Some(workspace_id), metrics }
+        }
+        Err(error) => ServerEvent::AutoAnalyzeFailed {
+            workspace_id,
+            status: error.status().as_u16(),
+            error: error.body()["error"].take(),
+        },
+    };
+    state.events.publish(event);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Router};
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn rapid_calls_collapse_into_the_last_one() {
+        // "Julia" counts its calls and echoes the voxel size it was asked for
+        let calls = Arc::new(AtomicU64::new(0));
+        let counter = calls.clone();
+        let app = Router::new().route(
+            "/analyze",
+            post(move |Json(body): Json<Value>| async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Json(json!({"voxel_size": body["voxel_size"]}))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let config = crate::config::Config {
+            auto_analyze_debounce: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let state = Arc::new(AppState { config, ..AppState::for_tests(&format!("http://{}", addr)) });
+        let mut events = state.events.subscribe();
+
+        for voxel_size in [10.0, 11.0, 12.0] {
+            let payload = json!({"workspace_id": "ws-live", "voxel_size": voxel_size});
+            let response = auto_analyze_handler(State(state.clone()), HeaderMap::new(), Json(payload)).await.unwrap();
+            assert_eq!(response.0, StatusCode::ACCEPTED);
+        }
+        // Another workspace is debounced independently
+        let other = json!({"workspace_id": "ws-other", "voxel_size": 20.0});
+        let response = auto_analyze_handler(State(state.clone()), HeaderMap::new(), Json(other)).await.unwrap();
+        assert_eq!(response.0, StatusCode::ACCEPTED);
+
+        let mut results = HashMap::new();
+        while results.len() < 2 {
+            let event = serde_json::to_value(events.recv().await.unwrap()).unwrap();
+            assert_eq!(event["type"], "analysis_complete");
+            results.insert(event["workspace_id"].as_str().unwrap().to_string(), event["metrics"]["voxel_size"].clone());
+        }
+        assert_eq!(results["ws-live"], 12.0);
+        assert_eq!(results["ws-other"], 20.0);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let error = auto_analyze_handler(State(state), HeaderMap::new(), Json(json!({"voxel_size": 1.0}))).await.unwrap_err();
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+    }
+}
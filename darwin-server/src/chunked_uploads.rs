@@ -0,0 +1,690 @@
+//! Resumable chunked uploads for volumes too large to send in one request.
+//!
+//! 1. `POST /api/upload/init` with `{file_name, total_size, chunk_size?}` returns
+//!    an `upload_id`, the chunk size to use and the chunk count.
+//! 2. The bytes arrive one of two ways, not mixed within an upload:
+//!    - `PUT /api/upload/:upload_id/:chunk_index` stores one chunk (any order, retries allowed);
+//!    - `PATCH /api/upload/:upload_id`, tus-style, appends its body
+//!      (`Content-Type: application/offset+octet-stream`) at the `Upload-Offset` header.
+//!      What arrived before a dropped connection is kept, and `HEAD /api/upload/:upload_id`
+//!      answers the `Upload-Offset` to resume from.
+//! 3. `GET /api/upload/:upload_id/status` lists received and missing chunks to resume from.
+//! 4. `POST /api/upload/:upload_id/complete` with `{sha256}` assembles the file,
+//!    verifies the checksum and format and answers like `POST /api/upload`.
+//!
+//! Chunks and appended bytes live under `{upload_dir}/.partial/{upload_id}/` until
+//! completion; uploads idle for longer than `upload_ttl` (`DARWIN_UPLOAD_TTL_HOURS`)
+//! are removed by the sweeper.
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{rejection::BytesRejection, Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use uuid::Uuid;
+
+use crate::{
+    analysis_cache, storage,
+    uploads::{self, UploadError},
+    AppState,
+};
+
+pub const DEFAULT_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+pub const MIN_CHUNK_SIZE: u64 = 64 * 1024;
+pub const MAX_CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+pub const MAX_TOTAL_SIZE: u64 = 64 * 1024 * 1024 * 1024;
+
+/// Request and response headers of the `PATCH`/`HEAD` protocol (tus 1.0.0)
+pub const UPLOAD_OFFSET: &str = "upload-offset";
+pub const UPLOAD_LENGTH: &str = "upload-length";
+pub const TUS_RESUMABLE: &str = "tus-resumable";
+pub const TUS_VERSION: &str = "1.0.0";
+pub const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+#[derive(Debug)]
+struct PendingUpload {
+    file_name: String,
+    total_size: u64,
+    chunk_size: u64,
+    chunk_count: u32,
+    received: BTreeSet<u32>,
+    /// Bytes appended by `PATCH` so far
+    offset: u64,
+    /// A `PATCH` is writing; a second one would race it
+    patching: bool,
+    last_activity: Instant,
+}
+
+impl PendingUpload {
+    /// Exact byte length chunk `index` must have (the last one may be short).
+    fn expected_len(&self, index: u32) -> u64 {
+        let start = index as u64 * self.chunk_size;
+        self.chunk_size.min(self.total_size - start)
+    }
+
+    /// Chunks neither stored by `PUT` nor wholly covered by `PATCH`ed bytes
+    fn missing(&self) -> Vec<u32> {
+        (0..self.chunk_count)
+            .filter(|&i| !self.received.contains(&i) && i as u64 * self.chunk_size + self.expected_len(i) > self.offset)
+            .collect()
+    }
+
+    fn status(&self, upload_id: &Uuid) -> Value {
+        serde_json::json!({
+            "upload_id": upload_id.to_string(),
+            "file_name": self.file_name,
+            "total_size": self.total_size,
+            "chunk_size": self.chunk_size,
+            "chunk_count": self.chunk_count,
+            "received_chunks": self.received,
+            "missing_chunks": self.missing(),
+            "offset": self.offset,
+        })
+    }
+}
+
+/// In-progress chunked uploads, shared by the upload routes and the TTL sweeper.
+#[derive(Clone, Default)]
+pub struct ChunkedUploads {
+    pending: Arc<Mutex<HashMap<Uuid, PendingUpload>>>,
+}
+
+impl ChunkedUploads {
+    /// Forget uploads idle for longer than `ttl`, returning their ids so the
+    /// caller can delete the chunk directories.
+    pub fn sweep_expired(&self, ttl: Duration) -> Vec<Uuid> {
+        let mut pending = self.pending.lock().unwrap();
+        let expired: Vec<Uuid> = pending
+            .iter()
+            .filter(|(_, upload)| upload.last_activity.elapsed() > ttl)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &expired {
+            pending.remove(id);
+        }
+        expired
+    }
+}
+
+fn partial_dir(state: &AppState, upload_id: &Uuid) -> PathBuf {
+    state.upload_dir.join(".partial").join(upload_id.to_string())
+}
+
+fn chunk_path(state: &AppState, upload_id: &Uuid, index: u32) -> PathBuf {
+    partial_dir(state, upload_id).join(format!("{}.part", index))
+}
+
+/// Where `PATCH` appends
+fn stream_path(state: &AppState, upload_id: &Uuid) -> PathBuf {
+    partial_dir(state, upload_id).join("stream.part")
+}
+
+fn parse_upload_id(raw: &str) -> Result<Uuid, UploadError> {
+    Uuid::parse_str(raw).map_err(|_| UploadError::UnknownUpload(raw.to_string()))
+}
+
+/// Drop the chunks of uploads the sweeper expired.
+pub async fn remove_expired(state: &AppState) {
+    for upload_id in state.chunked_uploads.sweep_expired(state.config.upload_ttl) {
+        let _ = tokio::fs::remove_dir_all(partial_dir(state, &upload_id)).await;
+        tracing::info!("discarded abandoned chunked upload {}", upload_id);
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InitRequest {
+    file_name: String,
+    total_size: u64,
+    chunk_size: Option<u64>,
+}
+
+/// `POST /api/upload/init`
+pub async fn init_handler(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<InitRequest>,
+) -> Result<(StatusCode, Json<Value>), UploadError> {
+    let file_name = req.file_name.trim().to_string();
+    if file_name.is_empty() || file_name.contains(['/', '\\']) || file_name.starts_with('.') {
+        return Err(UploadError::InvalidRequest(format!("invalid file name `{}`", req.file_name)));
+    }
+    if !uploads::has_allowed_extension(&file_name) {
+        return Err(UploadError::InvalidExtension(file_name));
+    }
+    if req.total_size == 0 {
+        return Err(UploadError::InvalidRequest("`total_size` must be greater than 0".to_string()));
+    }
+    if req.total_size > MAX_TOTAL_SIZE {
+        return Err(UploadError::TooLarge(MAX_TOTAL_SIZE as usize));
+    }
+    let chunk_size = req.chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE);
+    if !(MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&chunk_size) {
+        return Err(UploadError::InvalidRequest(format!(
+            "`chunk_size` must be between {} and {} bytes",
+            MIN_CHUNK_SIZE, MAX_CHUNK_SIZE
+        )));
+    }
+
+    let upload_id = Uuid::new_v4();
+    tokio::fs::create_dir_all(partial_dir(&state, &upload_id))
+        .await
+        .map_err(|e| UploadError::WriteError(e.to_string()))?;
+
+    let upload = PendingUpload {
+        file_name,
+        total_size: req.total_size,
+        chunk_size,
+        chunk_count: req.total_size.div_ceil(chunk_size) as u32,
+        received: BTreeSet::new(),
+        offset: 0,
+        patching: false,
+        last_activity: Instant::now(),
+    };
+    let status = upload.status(&upload_id);
+    state.chunked_uploads.pending.lock().unwrap().insert(upload_id, upload);
+
+    Ok((StatusCode::CREATED, Json(status)))
+}
+
+/// `PUT /api/upload/:upload_id/:chunk_index` - the raw chunk bytes are the body.
+pub async fn chunk_handler(
+    State(state): State<Arc<AppState>>,
+    Path((upload_id, chunk_index)): Path<(String, String)>,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<Json<Value>, UploadError> {
+    let upload_id = parse_upload_id(&upload_id)?;
+    let body = body.map_err(|e| {
+        if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            UploadError::TooLarge(MAX_CHUNK_SIZE as usize)
+        } else {
+            UploadError::FieldReadError(e.body_text())
+        }
+    })?;
+
+    let index = {
+        let mut pending = state.chunked_uploads.pending.lock().unwrap();
+        let upload = pending
+            .get_mut(&upload_id)
+            .ok_or_else(|| UploadError::UnknownUpload(upload_id.to_string()))?;
+        let index = chunk_index
+            .parse::<u32>()
+            .ok()
+            .filter(|i| *i < upload.chunk_count)
+            .ok_or_else(|| {
+                UploadError::InvalidRequest(format!(
+                    "chunk index must be between 0 and {}",
+                    upload.chunk_count - 1
+                ))
+            })?;
+        if upload.offset > 0 || upload.patching {
+            return Err(UploadError::InvalidRequest("this upload is being sent with PATCH".to_string()));
+        }
+        let expected = upload.expected_len(index);
+        if body.len() as u64 != expected {
+            return Err(UploadError::InvalidRequest(format!(
+                "chunk {} must be {} bytes, got {}",
+                index,
+                expected,
+                body.len()
+            )));
+        }
+        upload.last_activity = Instant::now();
+        index
+    };
+
+    // Write then rename so a dropped connection never leaves a half chunk marked received
+    let path = chunk_path(&state, &upload_id, index);
+    let tmp = path.with_extension(format!("part.{}", Uuid::new_v4().simple()));
+    let written = async {
+        tokio::fs::write(&tmp, &body).await?;
+        tokio::fs::rename(&tmp, &path).await
+    }
+    .await;
+    if let Err(e) = written {
+        let _ = tokio::fs::remove_file(&tmp).await;
+        return Err(UploadError::WriteError(e.to_string()));
+    }
+
+    let mut pending = state.chunked_uploads.pending.lock().unwrap();
+    let upload = pending
+        .get_mut(&upload_id)
+        .ok_or_else(|| UploadError::UnknownUpload(upload_id.to_string()))?;
+    upload.received.insert(index);
+    Ok(Json(serde_json::json!({
+        "upload_id": upload_id.to_string(),
+        "chunk_index": index,
+        "received": upload.received.len(),
+        "chunk_count": upload.chunk_count,
+    })))
+}
+
+/// `Upload-Offset` (and `Upload-Length`) headers of a `PATCH`/`HEAD` answer
+fn offset_headers(offset: u64, total_size: u64) -> [(&'static str, String); 3] {
+    [
+        (UPLOAD_OFFSET, offset.to_string()),
+        (UPLOAD_LENGTH, total_size.to_string()),
+        (TUS_RESUMABLE, TUS_VERSION.to_string()),
+    ]
+}
+
+/// `HEAD /api/upload/:upload_id` - `Upload-Offset` is where the next `PATCH` starts.
+pub async fn offset_handler(
+    State(state): State<Arc<AppState>>,
+    Path(upload_id): Path<String>,
+) -> Result<Response, UploadError> {
+    let upload_id = parse_upload_id(&upload_id)?;
+    let pending = state.chunked_uploads.pending.lock().unwrap();
+    let upload = pending
+        .get(&upload_id)
+        .ok_or_else(|| UploadError::UnknownUpload(upload_id.to_string()))?;
+    let headers = offset_headers(upload.offset, upload.total_size);
+    Ok((StatusCode::OK, [(header::CACHE_CONTROL, "no-store")], headers).into_response())
+}
+
+/// `PATCH /api/upload/:upload_id` - append the body at `Upload-Offset`, answering
+/// `204` with the new `Upload-Offset`.
+///
+/// The body is streamed to disk. If the connection drops part way, the bytes
+/// that made it are kept and the client resumes from the offset `HEAD` reports.
+/// A `PATCH` that does not start at the current offset is `409 offset_mismatch`.
+pub async fn patch_handler(
+    State(state): State<Arc<AppState>>,
+    Path(upload_id): Path<String>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, UploadError> {
+    let upload_id = parse_upload_id(&upload_id)?;
+    let content_type = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    if !content_type.eq_ignore_ascii_case(OFFSET_CONTENT_TYPE) {
+        return Err(UploadError::InvalidRequest(format!("Content-Type must be {}", OFFSET_CONTENT_TYPE)));
+    }
+    let Some(client_offset) = headers.get(UPLOAD_OFFSET).and_then(|v| v.to_str().ok()).and_then(|v| v.parse::<u64>().ok())
+    else {
+        return Err(UploadError::InvalidRequest("Upload-Offset must be a byte offset".to_string()));
+    };
+
+    let (offset, total_size) = {
+        let mut pending = state.chunked_uploads.pending.lock().unwrap();
+        let upload = pending
+            .get_mut(&upload_id)
+            .ok_or_else(|| UploadError::UnknownUpload(upload_id.to_string()))?;
+        if !upload.received.is_empty() {
+            return Err(UploadError::InvalidRequest("this upload is being sent in chunks".to_string()));
+        }
+        if upload.patching || client_offset != upload.offset {
+            return Err(UploadError::OffsetMismatch(upload.offset));
+        }
+        upload.patching = true;
+        upload.last_activity = Instant::now();
+        (upload.offset, upload.total_size)
+    };
+
+    let (written, outcome) = append(&stream_path(&state, &upload_id), offset, total_size, body).await;
+
+    let mut pending = state.chunked_uploads.pending.lock().unwrap();
+    let upload = pending
+        .get_mut(&upload_id)
+        .ok_or_else(|| UploadError::UnknownUpload(upload_id.to_string()))?;
+    upload.offset += written;
+    upload.patching = false;
+    upload.last_activity = Instant::now();
+    outcome?;
+    Ok((StatusCode::NO_CONTENT, offset_headers(upload.offset, upload.total_size)).into_response())
+}
+
+/// Write `body` into `path` from `offset`, never past `total_size`. Returns the
+/// bytes that were durably written, even when the body broke off part way.
+async fn append(path: &std::path::Path, offset: u64, total_size: u64, body: Body) -> (u64, Result<(), UploadError>) {
+    let write_error = |e: std::io::Error| UploadError::WriteError(e.to_string());
+    let mut file = match tokio::fs::OpenOptions::new().create(true).write(true).truncate(false).open(path).await {
+        Ok(file) => file,
+        Err(e) => return (0, Err(write_error(e))),
+    };
+    // Drop anything a failed write left past the acknowledged offset
+    let positioned = async {
+        file.set_len(offset).await?;
+        file.seek(std::io::SeekFrom::Start(offset)).await
+    };
+    if let Err(e) = positioned.await {
+        return (0, Err(write_error(e)));
+    }
+
+    let mut written = 0u64;
+    let mut stream = body.into_data_stream();
+    let mut outcome = Ok(());
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                outcome = Err(UploadError::FieldReadError(e.to_string()));
+                break;
+            }
+        };
+        if offset + written + chunk.len() as u64 > total_size {
+            outcome = Err(UploadError::InvalidRequest(format!("body runs past the upload's {} bytes", total_size)));
+            break;
+        }
+        if let Err(e) = file.write_all(&chunk).await {
+            outcome = Err(write_error(e));
+            break;
+        }
+        written += chunk.len() as u64;
+    }
+    if let Err(e) = file.sync_data().await {
+        // Nothing is acknowledged that may not be on disk
+        return (0, Err(write_error(e)));
+    }
+    (written, outcome)
+}
+
+/// `GET /api/upload/:upload_id/status`
+pub async fn status_handler(
+    State(state): State<Arc<AppState>>,
+    Path(upload_id): Path<String>,
+) -> Result<Json<Value>, UploadError> {
+    let upload_id = parse_upload_id(&upload_id)?;
+    let pending = state.chunked_uploads.pending.lock().unwrap();
+    let upload = pending
+        .get(&upload_id)
+        .ok_or_else(|| UploadError::UnknownUpload(upload_id.to_string()))?;
+    Ok(Json(upload.status(&upload_id)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompleteRequest {
+    /// Hex SHA-256 of the whole file
+    sha256: String,
+}
+
+/// `POST /api/upload/:upload_id/complete`
+pub async fn complete_handler(
+    State(state): State<Arc<AppState>>,
+    Path(upload_id): Path<String>,
+    Json(req): Json<CompleteRequest>,
+) -> Result<Json<Value>, UploadError> {
+    let upload_id = parse_upload_id(&upload_id)?;
+
+    // Take the upload out of the table while assembling so chunks can't change under us
+    let upload = {
+        let mut pending = state.chunked_uploads.pending.lock().unwrap();
+        let upload = pending
+            .get(&upload_id)
+            .ok_or_else(|| UploadError::UnknownUpload(upload_id.to_string()))?;
+        let missing = upload.missing();
+        if !missing.is_empty() {
+            return Err(UploadError::Incomplete(missing));
+        }
+        pending.remove(&upload_id).unwrap()
+    };
+
+    let file_id = Uuid::new_v4();
+    let key = storage::upload_key(file_id, &upload.file_name);
+    let file_path = state.storage.local_path(&key);
+    let streamed = upload.offset == upload.total_size;
+    // Appended bytes are already one file: hash it where it is, move it once it checks out
+    let assembled = match streamed {
+        true => analysis_cache::file_sha256(&stream_path(&state, &upload_id)).await,
+        false => assemble(&state, &upload_id, &upload, &file_path).await,
+    };
+
+    let digest = match assembled {
+        Ok(digest) => digest,
+        Err(e) => {
+            if !streamed {
+                let _ = tokio::fs::remove_file(&file_path).await;
+            }
+            state.chunked_uploads.pending.lock().unwrap().insert(upload_id, upload);
+            return Err(UploadError::WriteError(e.to_string()));
+        }
+    };
+    if !digest.eq_ignore_ascii_case(req.sha256.trim()) {
+        // Keep the chunks: the client can re-send suspect ones and complete again
+        if !streamed {
+            let _ = tokio::fs::remove_file(&file_path).await;
+        }
+        state.chunked_uploads.pending.lock().unwrap().insert(upload_id, upload);
+        return Err(UploadError::ChecksumMismatch);
+    }
+
+    let source = if streamed { stream_path(&state, &upload_id) } else { file_path.clone() };
+    // The right bytes, but not a format we take: nothing to resume
+    let format = match uploads::detect_format(&source, &upload.file_name).await {
+        Ok(format) => format,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&file_path).await;
+            let _ = tokio::fs::remove_dir_all(partial_dir(&state, &upload_id)).await;
+            return Err(e);
+        }
+    };
+    let file_path = match state.storage.put(&key, &source).await {
+        Ok(file_path) => file_path,
+        Err(e) => {
+            if !streamed {
+                let _ = tokio::fs::remove_file(&file_path).await;
+            }
+            state.chunked_uploads.pending.lock().unwrap().insert(upload_id, upload);
+            return Err(UploadError::WriteError(e.to_string()));
+        }
+    };
+    let _ = tokio::fs::remove_dir_all(partial_dir(&state, &upload_id)).await;
+    uploads::record_upload(&state, &key, file_id, &upload.file_name, upload.total_size, &digest, format).await;
+    let mut response = uploads::stored_upload_response(file_path, file_id, upload.file_name).await;
+    response["format"] = serde_json::json!(format);
+    Ok(Json(response))
+}
+
+/// Concatenate the chunks in order into `dest`, returning the hex SHA-256 of the result.
+async fn assemble(
+    state: &AppState,
+    upload_id: &Uuid,
+    upload: &PendingUpload,
+    dest: &std::path::Path,
+) -> std::io::Result<String> {
+    let mut out = tokio::fs::File::create(dest).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 256 * 1024];
+
+    for index in 0..upload.chunk_count {
+        let mut chunk = tokio::fs::File::open(chunk_path(state, upload_id, index)).await?;
+        loop {
+            let n = chunk.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            out.write_all(&buf[..n]).await?;
+        }
+    }
+    out.flush().await?;
+
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        extract::DefaultBodyLimit,
+        http::Request,
+        routing::{get, patch, post, put},
+        Router,
+    };
+    use tower::ServiceExt;
+
+    fn router() -> (Router, Arc<AppState>) {
+        let state = Arc::new(AppState::for_tests("http://127.0.0.1:1"));
+        std::fs::create_dir_all(&state.upload_dir).unwrap();
+        let app = Router::new()
+            .route("/api/upload/init", post(init_handler))
+            .route("/api/upload/:upload_id/status", get(status_handler))
+            .route("/api/upload/:upload_id/complete", post(complete_handler))
+            .route("/api/upload/:upload_id", patch(patch_handler).head(offset_handler))
+            .route(
+                "/api/upload/:upload_id/:chunk_index",
+                put(chunk_handler).layer(DefaultBodyLimit::max(MAX_CHUNK_SIZE as usize)),
+            )
+            .with_state(state.clone());
+        (app, state)
+    }
+
+    async fn call(app: &Router, method: &str, uri: &str, body: Body, json: bool) -> (StatusCode, Value) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if json {
+            request = request.header("content-type", "application/json");
+        }
+        let response = app.clone().oneshot(request.body(body).unwrap()).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    /// `PATCH` at `offset`, or `HEAD` with `offset: None`; answers the status and `Upload-Offset`
+    async fn tus(app: &Router, id: &str, offset: Option<u64>, body: Body) -> (StatusCode, Option<u64>) {
+        let uri = format!("/api/upload/{}", id);
+        let request = match offset {
+            Some(offset) => Request::patch(uri)
+                .header("content-type", OFFSET_CONTENT_TYPE)
+                .header(UPLOAD_OFFSET, offset)
+                .body(body),
+            None => Request::head(uri).body(Body::empty()),
+        };
+        let response = app.clone().oneshot(request.unwrap()).await.unwrap();
+        let offset = response.headers().get(UPLOAD_OFFSET).map(|v| v.to_str().unwrap().parse().unwrap());
+        (response.status(), offset)
+    }
+
+    fn sha256_hex(data: &[u8]) -> String {
+        Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[tokio::test]
+    async fn resumes_and_assembles_chunks_in_order() {
+        let (app, _) = router();
+        let mut data: Vec<u8> = (0..(MIN_CHUNK_SIZE * 2 + 100)).map(|i| (i % 251) as u8).collect();
+        data[..4].copy_from_slice(b"II*\0");
+        let init = serde_json::json!({"file_name": "scan.tif", "total_size": data.len(), "chunk_size": MIN_CHUNK_SIZE});
+
+        let (status, body) = call(&app, "POST", "/api/upload/init", Body::from(init.to_string()), true).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["chunk_count"], 3);
+        let id = body["upload_id"].as_str().unwrap().to_string();
+
+        // Chunks 2 and 0 arrive; the connection "drops" before chunk 1
+        for index in [2usize, 0] {
+            let start = index * MIN_CHUNK_SIZE as usize;
+            let end = (start + MIN_CHUNK_SIZE as usize).min(data.len());
+            let uri = format!("/api/upload/{}/{}", id, index);
+            let (status, _) = call(&app, "PUT", &uri, Body::from(data[start..end].to_vec()), false).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+
+        let complete = serde_json::json!({"sha256": sha256_hex(&data)}).to_string();
+        let uri = format!("/api/upload/{}/complete", id);
+        let (status, body) = call(&app, "POST", &uri, Body::from(complete.clone()), true).await;
+        assert_eq!((status, body["error"]["missing_chunks"].clone()), (StatusCode::CONFLICT, serde_json::json!([1])));
+
+        let (_, status_body) = call(&app, "GET", &format!("/api/upload/{}/status", id), Body::empty(), false).await;
+        assert_eq!(status_body["missing_chunks"], serde_json::json!([1]));
+
+        let chunk = data[MIN_CHUNK_SIZE as usize..2 * MIN_CHUNK_SIZE as usize].to_vec();
+        call(&app, "PUT", &format!("/api/upload/{}/1", id), Body::from(chunk), false).await;
+
+        let (status, body) = call(&app, "POST", &uri, Body::from(complete), true).await;
+        assert_eq!((status, body["format"].as_str()), (StatusCode::OK, Some("tiff")));
+        let stored = tokio::fs::read(body["file_path"].as_str().unwrap()).await.unwrap();
+        assert_eq!(stored, data);
+        tokio::fs::remove_file(body["file_path"].as_str().unwrap()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_chunks_and_checksums() {
+        let (app, state) = router();
+        let init = serde_json::json!({"file_name": "scan.nii", "total_size": 10, "chunk_size": MIN_CHUNK_SIZE});
+        let (_, body) = call(&app, "POST", "/api/upload/init", Body::from(init.to_string()), true).await;
+        let id = body["upload_id"].as_str().unwrap().to_string();
+
+        let (status, _) = call(&app, "PUT", &format!("/api/upload/{}/0", id), Body::from(vec![0u8; 3]), false).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = call(&app, "PUT", &format!("/api/upload/{}/1", id), Body::from(vec![0u8; 10]), false).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = call(&app, "PUT", &format!("/api/upload/{}/0", id), Body::from(vec![0u8; 10]), false).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let uri = format!("/api/upload/{}/complete", id);
+        let wrong = serde_json::json!({"sha256": sha256_hex(b"something else")}).to_string();
+        let (status, body) = call(&app, "POST", &uri, Body::from(wrong), true).await;
+        assert_eq!((status, body["error"]["code"].as_str()), (StatusCode::UNPROCESSABLE_ENTITY, Some("checksum_mismatch")));
+
+        // Still resumable after a mismatch, until the sweeper expires it
+        assert_eq!(state.chunked_uploads.sweep_expired(Duration::ZERO).len(), 1);
+        let (status, _) = call(&app, "GET", &format!("/api/upload/{}/status", id), Body::empty(), false).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let _ = tokio::fs::remove_dir_all(partial_dir(&state, &Uuid::parse_str(&id).unwrap())).await;
+    }
+
+    #[tokio::test]
+    async fn patch_appends_at_the_offset_and_survives_a_dropped_connection() {
+        let (app, state) = router();
+        let mut data: Vec<u8> = (0..(MIN_CHUNK_SIZE + 100)).map(|i| (i % 251) as u8).collect();
+        data[..4].copy_from_slice(b"II*\0");
+        let init = serde_json::json!({"file_name": "scan.tif", "total_size": data.len(), "chunk_size": MIN_CHUNK_SIZE});
+        let (_, body) = call(&app, "POST", "/api/upload/init", Body::from(init.to_string()), true).await;
+        let id = body["upload_id"].as_str().unwrap().to_string();
+        assert_eq!(tus(&app, &id, None, Body::empty()).await, (StatusCode::OK, Some(0)));
+
+        let (status, offset) = tus(&app, &id, Some(0), Body::from(data[..1000].to_vec())).await;
+        assert_eq!((status, offset), (StatusCode::NO_CONTENT, Some(1000)));
+
+        // The connection drops after another 500 bytes: those are kept
+        let parts: Vec<Result<Bytes, std::io::Error>> =
+            vec![Ok(Bytes::from(data[1000..1500].to_vec())), Err(std::io::Error::other("connection reset"))];
+        let (status, _) = tus(&app, &id, Some(1000), Body::from_stream(futures::stream::iter(parts))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(tus(&app, &id, None, Body::empty()).await, (StatusCode::OK, Some(1500)));
+
+        // Resuming from a stale offset is refused with the one to use
+        let uri = format!("/api/upload/{}", id);
+        let request = Request::patch(&uri)
+            .header("content-type", OFFSET_CONTENT_TYPE)
+            .header(UPLOAD_OFFSET, 1000)
+            .body(Body::from(data[1000..].to_vec()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!((body["error"]["code"].as_str(), body["error"]["upload_offset"].as_u64()), (Some("offset_mismatch"), Some(1500)));
+
+        // Neither bytes past the declared size nor chunk PUTs are accepted
+        let (status, _) = tus(&app, &id, Some(1500), Body::from(vec![0u8; data.len()])).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = call(&app, "PUT", &format!("/api/upload/{}/1", id), Body::from(vec![0u8; 100]), false).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (_, status_body) = call(&app, "GET", &format!("/api/upload/{}/status", id), Body::empty(), false).await;
+        assert_eq!((status_body["offset"].as_u64(), status_body["missing_chunks"].clone()), (Some(1500), serde_json::json!([0, 1])));
+
+        let (status, offset) = tus(&app, &id, Some(1500), Body::from(data[1500..].to_vec())).await;
+        assert_eq!((status, offset), (StatusCode::NO_CONTENT, Some(data.len() as u64)));
+
+        let complete = serde_json::json!({"sha256": sha256_hex(&data)}).to_string();
+        let (status, body) = call(&app, "POST", &format!("/api/upload/{}/complete", id), Body::from(complete), true).await;
+        assert_eq!(status, StatusCode::OK);
+        let stored = tokio::fs::read(body["file_path"].as_str().unwrap()).await.unwrap();
+        assert_eq!(stored, data);
+        assert!(!partial_dir(&state, &Uuid::parse_str(&id).unwrap()).exists());
+        tokio::fs::remove_file(body["file_path"].as_str().unwrap()).await.unwrap();
+        assert_eq!(tus(&app, &id, None, Body::empty()).await.0, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,420 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, HeaderName},
+    response::Json,
+};
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+use crate::{auth, error::AppError, julia, jwt, storage, uploads, webhooks, AppState};
+
+pub const DEFAULT_UPLOAD_TTL_HOURS: u64 = 6;
+pub const DEFAULT_JULIA_URL: &str = "http://127.0.0.1:8081";
+pub const DEFAULT_UPLOAD_DIR: &str = "/tmp/darwin_uploads";
+pub const DEFAULT_STATIC_DIR: &str = "public";
+pub const DEFAULT_AUDIT_LOG: &str = "/tmp/darwin_audit.jsonl";
+pub const DEFAULT_WEBHOOKS_FILE: &str = "/tmp/darwin_webhooks.json";
+pub const DEFAULT_DATABASE: &str = "/tmp/darwin.db";
+pub const DEFAULT_BIND_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+pub const DEFAULT_PORT: u16 = 3000;
+pub const DEFAULT_JULIA_TIMEOUT_SECS: u64 = 600;
+pub const DEFAULT_AUTO_ANALYZE_DEBOUNCE_MS: u64 = 400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Environment {
+    Development,
+    Production,
+}
+
+impl Environment {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Development => "development",
+            Self::Production => "production",
+        }
+    }
+}
+
+/// Deployment settings read from the environment at startup.
+///
+/// | variable                   | default       | meaning                                   |
+/// |----------------------------|---------------|-------------------------------------------|
+/// | `DARWIN_ENV`               | `development` | `production` locks down `/api/config`     |
+/// | `DARWIN_API_KEY`           | unset         | when set, `/api/*` requires `X-API-Key`   |
+/// | `DARWIN_JWT_JWKS_URL`      | unset         | when set, `/api/*` requires a bearer JWT signed by a key from this JWKS |
+/// | `DARWIN_JWT_AUDIENCE`      | unset         | `aud` the JWT must carry                  |
+/// | `DARWIN_JWT_ISSUER`        | unset         | `iss` the JWT must carry                  |
+/// | `DARWIN_JWT_JWKS_REFRESH_SECS` | 3600      | refetch the JWKS after this               |
+/// | `DARWIN_ALLOWED_ORIGINS`   | unset (any)   | comma-separated CORS origins              |
+/// | `DARWIN_MAX_UPLOAD_BYTES`  | 1 GiB         | body limit of `POST /api/upload`          |
+/// | `DARWIN_UPLOAD_TTL_HOURS`  | 6             | idle time before chunked uploads expire   |
+/// | `DARWIN_JULIA_TIMEOUT_SECS`| 600           | give up on a Julia request after this     |
+/// | `DARWIN_MAX_JULIA_RESPONSE_BYTES` | 64 MiB | largest Julia reply held in memory; bigger ones fail with 413 (mesh replies stream instead) |
+/// | `DARWIN_FORWARD_HEADERS`   | unset         | comma-separated request headers passed on to Julia |
+/// | `DARWIN_AUTO_ANALYZE_DEBOUNCE_MS` | 400    | quiet time before `/api/analyze/auto` runs |
+/// | `DARWIN_JULIA_URL`         | `http://127.0.0.1:8081` | Julia backend base URL          |
+/// | `DARWIN_UPLOAD_DIR`        | `/tmp/darwin_uploads`   | where uploads are stored, or cached when they live in S3 |
+/// | `DARWIN_STORAGE`           | `local`       | `local` or `s3` (`--features s3`, settings in `s3_storage`) |
+/// | `DARWIN_STATIC_DIR`        | `public`      | frontend assets served at `/`             |
+/// | `DARWIN_AUDIT_LOG`         | `/tmp/darwin_audit.jsonl` | append-only JSONL audit trail of mutating requests |
+/// | `DARWIN_WEBHOOKS_FILE`     | `/tmp/darwin_webhooks.json` | registered analysis webhooks; their delivery queue is kept next to it as `*.deliveries.jsonl` |
+/// | `DARWIN_DATABASE`          | `/tmp/darwin.db` | SQLite file keeping upload metadata, workspace analyses and job history across restarts |
+/// | `DARWIN_WEBHOOK_HOSTS`     | `localhost,127.0.0.1,[::1]` | comma-separated hosts webhooks may target; `*.example.org` matches subdomains |
+/// | `DARWIN_BIND_ADDR`         | `0.0.0.0`     | IP address to listen on                   |
+/// | `DARWIN_PORT`              | 3000          | port to listen on                         |
+/// | `DARWIN_METRICS_ADDR`      | unset         | `ip:port` serving only `/metrics`; unset serves it on the main port |
+///
+/// The last six, `DARWIN_STORAGE` and the `DARWIN_JWT_*` URL and refresh are validated: a bad value
+/// stops the server at startup.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub environment: Environment,
+    pub julia_url: String,
+    pub upload_dir: PathBuf,
+    pub storage: storage::StorageSettings,
+    /// Without an `index.html` inside, `/` serves a built-in notice instead
+    pub static_dir: PathBuf,
+    pub audit_log: PathBuf,
+    pub webhooks_file: PathBuf,
+    pub database: PathBuf,
+    /// Lowercased
+    pub webhook_hosts: Vec<String>,
+    pub bind_addr: IpAddr,
+    pub port: u16,
+    pub api_key: Option<String>,
+    pub jwt: Option<jwt::JwtSettings>,
+    pub allowed_origins: Vec<String>,
+    pub max_upload_bytes: usize,
+    pub upload_ttl: Duration,
+    pub julia_timeout: Duration,
+    pub max_julia_response_bytes: usize,
+    /// Lowercased; never contains `julia::UNFORWARDABLE_HEADERS`
+    pub forward_headers: Vec<HeaderName>,
+    /// Separate internal listener for `GET /metrics`
+    pub metrics_addr: Option<SocketAddr>,
+    pub auto_analyze_debounce: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            environment: Environment::Development,
+            julia_url: DEFAULT_JULIA_URL.to_string(),
+            upload_dir: PathBuf::from(DEFAULT_UPLOAD_DIR),
+            storage: storage::StorageSettings::Local,
+            static_dir: PathBuf::from(DEFAULT_STATIC_DIR),
+            audit_log: PathBuf::from(DEFAULT_AUDIT_LOG),
+            webhooks_file: PathBuf::from(DEFAULT_WEBHOOKS_FILE),
+            database: PathBuf::from(DEFAULT_DATABASE),
+            webhook_hosts: webhooks::DEFAULT_WEBHOOK_HOSTS.iter().map(|h| h.to_string()).collect(),
+            bind_addr: DEFAULT_BIND_ADDR,
+            port: DEFAULT_PORT,
+            api_key: None,
+            jwt: None,
+            allowed_origins: Vec::new(),
+            max_upload_bytes: uploads::MAX_UPLOAD_BYTES,
+            upload_ttl: Duration::from_secs(DEFAULT_UPLOAD_TTL_HOURS * 3600),
+            julia_timeout: Duration::from_secs(DEFAULT_JULIA_TIMEOUT_SECS),
+            max_julia_response_bytes: julia::DEFAULT_MAX_JULIA_RESPONSE_BYTES,
+            forward_headers: Vec::new(),
+            metrics_addr: None,
+            auto_analyze_debounce: Duration::from_millis(DEFAULT_AUTO_ANALYZE_DEBOUNCE_MS),
+        }
+    }
+}
+
+impl Config {
+    /// Read and validate the environment, creating the upload dir.
+    /// `Err` names the offending variable.
+    pub fn from_env() -> Result<Self, String> {
+        let config = Self::from_vars(|name| std::env::var(name).ok())?;
+        std::fs::create_dir_all(&config.upload_dir)
+            .map_err(|e| format!("DARWIN_UPLOAD_DIR: cannot create {}: {}", config.upload_dir.display(), e))?;
+        Ok(config)
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let defaults = Self::default();
+        let var = |name: &str| var(name).map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+
+        let julia_url = match var("DARWIN_JULIA_URL") {
+            None => defaults.julia_url,
+            Some(url) => match reqwest::Url::parse(&url) {
+                Ok(parsed) if matches!(parsed.scheme(), "http" | "https") && parsed.has_host() => {
+                    url.trim_end_matches('/').to_string()
+                }
+                _ => return Err(format!("DARWIN_JULIA_URL: `{}` is not an http(s) URL", url)),
+            },
+        };
+        let forward_headers = match var("DARWIN_FORWARD_HEADERS") {
+            None => defaults.forward_headers,
+            Some(names) => names
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(|name| match HeaderName::from_bytes(name.as_bytes()) {
+                    Ok(header) if julia::UNFORWARDABLE_HEADERS.contains(&header.as_str()) => {
+                        Err(format!("DARWIN_FORWARD_HEADERS: `{}` cannot be forwarded", name))
+                    }
+                    Ok(header) => Ok(header),
+                    Err(_) => Err(format!("DARWIN_FORWARD_HEADERS: `{}` is not a header name", name)),
+                })
+                .collect::<Result<_, _>>()?,
+        };
+        let bind_addr = match var("DARWIN_BIND_ADDR") {
+            None => defaults.bind_addr,
+            Some(addr) => addr
+                .parse()
+                .map_err(|_| format!("DARWIN_BIND_ADDR: `{}` is not an IP address", addr))?,
+        };
+        let port = match var("DARWIN_PORT") {
+            None => defaults.port,
+            Some(port) => port
+                .parse()
+                .map_err(|_| format!("DARWIN_PORT: `{}` is not a port number", port))?,
+        };
+        let metrics_addr = match var("DARWIN_METRICS_ADDR") {
+            None => defaults.metrics_addr,
+            Some(addr) => Some(
+                addr.parse()
+                    .map_err(|_| format!("DARWIN_METRICS_ADDR: `{}` is not an ip:port address", addr))?,
+            ),
+        };
+
+        Ok(Self {
+            environment: match var("DARWIN_ENV").as_deref().map(str::to_ascii_lowercase).as_deref() {
+                Some("production" | "prod") => Environment::Production,
+                _ => Environment::Development,
+            },
+            julia_url,
+            upload_dir: var("DARWIN_UPLOAD_DIR").map(PathBuf::from).unwrap_or(defaults.upload_dir),
+            storage: storage::settings_from(var)?,
+            static_dir: var("DARWIN_STATIC_DIR").map(PathBuf::from).unwrap_or(defaults.static_dir),
+            audit_log: var("DARWIN_AUDIT_LOG").map(PathBuf::from).unwrap_or(defaults.audit_log),
+            webhooks_file: var("DARWIN_WEBHOOKS_FILE").map(PathBuf::from).unwrap_or(defaults.webhooks_file),
+            database: var("DARWIN_DATABASE").map(PathBuf::from).unwrap_or(defaults.database),
+            webhook_hosts: var("DARWIN_WEBHOOK_HOSTS")
+                .map(|hosts| {
+                    hosts
+                        .split(',')
+                        .map(|h| h.trim().to_ascii_lowercase())
+                        .filter(|h| !h.is_empty())
+                        .collect()
+                })
+                .unwrap_or(defaults.webhook_hosts),
+            bind_addr,
+            port,
+            api_key: var("DARWIN_API_KEY"),
+            jwt: jwt::settings_from(var)?,
+            allowed_origins: var("DARWIN_ALLOWED_ORIGINS")
+                .map(|origins| {
+                    origins
+                        .split(',')
+                        .map(|o| o.trim().trim_end_matches('/').to_string())
+                        .filter(|o| !o.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            max_upload_bytes: var("DARWIN_MAX_UPLOAD_BYTES")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.max_upload_bytes),
+            upload_ttl: var("DARWIN_UPLOAD_TTL_HOURS")
+                .and_then(|v| v.parse::<u64>().ok())
+                .map(|hours| Duration::from_secs(hours * 3600))
+                .unwrap_or(defaults.upload_ttl),
+            julia_timeout: var("DARWIN_JULIA_TIMEOUT_SECS")
+                .and_then(|v| v.parse::<u64>().ok())
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.julia_timeout),
+            max_julia_response_bytes: var("DARWIN_MAX_JULIA_RESPONSE_BYTES")
+                .and_then(|v| v.parse().ok())
+                .filter(|&bytes: &usize| bytes > 0)
+                .unwrap_or(defaults.max_julia_response_bytes),
+            forward_headers,
+            metrics_addr,
+            auto_analyze_debounce: var("DARWIN_AUTO_ANALYZE_DEBOUNCE_MS")
+                .and_then(|v| v.parse::<u64>().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.auto_analyze_debounce),
+        })
+    }
+
+    pub fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.bind_addr, self.port)
+    }
+
+    pub fn auth_enabled(&self) -> bool {
+        self.api_key.is_some() || self.jwt.is_some()
+    }
+
+    /// Any origin unless `allowed_origins` narrows it down.
+    pub fn cors_layer(&self) -> CorsLayer {
+        if self.allowed_origins.is_empty() {
+            return CorsLayer::permissive();
+        }
+        let origins: Vec<_> = self.allowed_origins.iter().filter_map(|o| o.parse().ok()).collect();
+        CorsLayer::new()
+            .allow_origin(AllowOrigin::list(origins))
+            .allow_methods(Any)
+            .allow_headers(Any)
+    }
+}
+
+/// `GET /api/config` - the settings this server resolved, with the API key redacted.
+///
+/// Open in development; in production it takes the same credentials as the other
+/// `/api` routes, the API key or a valid JWT (and with neither configured the
+/// endpoint stays closed).
+pub async fn config_handler(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Result<Json<serde_json::Value>, AppError> {
+    let config = &state.config;
+    if config.environment == Environment::Production {
+        auth::authenticate(&state, &headers).await?;
+    }
+
+    Ok(Json(serde_json::json!({
+        "environment": config.environment.as_str(),
+        "julia_url": state.julia_url,
+        "upload_dir": state.upload_dir.to_string_lossy(),
+        "storage": state.storage.kind(),
+        "static_dir": config.static_dir.to_string_lossy(),
+        "audit_log": config.audit_log.to_string_lossy(),
+        "webhooks_file": config.webhooks_file.to_string_lossy(),
+        "database": config.database.to_string_lossy(),
+        "webhook_hosts": config.webhook_hosts,
+        "bind_addr": config.socket_addr().to_string(),
+        "max_upload_bytes": config.max_upload_bytes,
+        "upload_ttl_hours": config.upload_ttl.as_secs() / 3600,
+        "julia_timeout_secs": config.julia_timeout.as_secs(),
+        "max_julia_response_bytes": config.max_julia_response_bytes,
+        "forward_headers": config.forward_headers.iter().map(HeaderName::as_str).collect::<Vec<_>>(),
+        "allowed_origins": config.allowed_origins,
+        "auth_enabled": config.auth_enabled(),
+        "jwt": config.jwt.as_ref().map(|jwt| serde_json::json!({
+            "jwks_url": jwt.jwks_url,
+            "audience": jwt.audience,
+            "issuer": jwt.issuer,
+            "jwks_refresh_secs": jwt.jwks_refresh.as_secs(),
+        })),
+        "metrics_addr": config.metrics_addr.map(|addr| addr.to_string()),
+        "auto_analyze_debounce_ms": config.auto_analyze_debounce.as_millis() as u64,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::{Request, StatusCode}, routing::get, Router};
+    use std::collections::HashMap;
+    use tower::ServiceExt;
+
+    fn config_from(vars: &[(&str, &str)]) -> Config {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Config::from_vars(|name| vars.get(name).cloned()).unwrap()
+    }
+
+    async fn get_config(config: Config, api_key: Option<&str>) -> (StatusCode, serde_json::Value) {
+        let state = Arc::new(AppState { config, ..AppState::for_tests("http://127.0.0.1:1") });
+        let app = Router::new().route("/api/config", get(config_handler)).with_state(state);
+
+        let mut request = Request::get("/api/config");
+        if let Some(key) = api_key {
+            request = request.header("x-api-key", key);
+        }
+        let response = app.oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[test]
+    fn parses_overrides_and_ignores_garbage() {
+        let config = config_from(&[
+            ("DARWIN_ENV", "Production"),
+            ("DARWIN_ALLOWED_ORIGINS", "https://studio.example.org/, http://localhost:5173"),
+            ("DARWIN_MAX_UPLOAD_BYTES", "not a number"),
+            ("DARWIN_UPLOAD_TTL_HOURS", "24"),
+            ("DARWIN_API_KEY", "  "),
+            ("DARWIN_AUTO_ANALYZE_DEBOUNCE_MS", "250"),
+        ]);
+        assert_eq!(config.environment, Environment::Production);
+        assert_eq!(config.allowed_origins, vec!["https://studio.example.org", "http://localhost:5173"]);
+        assert_eq!(config.max_upload_bytes, uploads::MAX_UPLOAD_BYTES);
+        assert_eq!(config.upload_ttl, Duration::from_secs(24 * 3600));
+        assert!(!config.auth_enabled());
+        assert_eq!(config.auto_analyze_debounce, Duration::from_millis(250));
+    }
+
+    #[test]
+    fn server_settings_default_and_override() {
+        let config = config_from(&[]);
+        assert_eq!(config.julia_url, DEFAULT_JULIA_URL);
+        assert_eq!(config.upload_dir, PathBuf::from(DEFAULT_UPLOAD_DIR));
+        assert_eq!(config.static_dir, PathBuf::from(DEFAULT_STATIC_DIR));
+        assert_eq!(config.audit_log, PathBuf::from(DEFAULT_AUDIT_LOG));
+        assert_eq!(config.database, PathBuf::from(DEFAULT_DATABASE));
+        assert_eq!(config.webhook_hosts, vec!["localhost", "127.0.0.1", "[::1]"]);
+        assert_eq!(config.socket_addr(), "0.0.0.0:3000".parse().unwrap());
+
+        let config = config_from(&[
+            ("DARWIN_JULIA_URL", "https://julia.internal:9000/"),
+            ("DARWIN_UPLOAD_DIR", "/srv/darwin/uploads"),
+            ("DARWIN_STATIC_DIR", "/opt/darwin/frontend"),
+            ("DARWIN_AUDIT_LOG", "/var/log/darwin/audit.jsonl"),
+            ("DARWIN_DATABASE", "/var/lib/darwin/darwin.db"),
+            ("DARWIN_WEBHOOK_HOSTS", "Post.Lab.example.org, *.internal"),
+            ("DARWIN_BIND_ADDR", "::1"),
+            ("DARWIN_PORT", "8080"),
+            ("DARWIN_FORWARD_HEADERS", "X-Tenant-Id, traceparent,"),
+            ("DARWIN_METRICS_ADDR", "127.0.0.1:9090"),
+        ]);
+        assert_eq!(config.julia_url, "https://julia.internal:9000");
+        assert_eq!(config.upload_dir, PathBuf::from("/srv/darwin/uploads"));
+        assert_eq!(config.static_dir, PathBuf::from("/opt/darwin/frontend"));
+        assert_eq!(config.audit_log, PathBuf::from("/var/log/darwin/audit.jsonl"));
+        assert_eq!(config.database, PathBuf::from("/var/lib/darwin/darwin.db"));
+        assert_eq!(config.webhook_hosts, vec!["post.lab.example.org", "*.internal"]);
+        assert_eq!(config.socket_addr(), "[::1]:8080".parse().unwrap());
+        assert_eq!(config.forward_headers, vec!["x-tenant-id", "traceparent"]);
+        assert_eq!(config.metrics_addr, Some("127.0.0.1:9090".parse().unwrap()));
+    }
+
+    #[test]
+    fn invalid_server_settings_are_rejected() {
+        let parse = |name: &str, value: &str| Config::from_vars(|n| (n == name).then(|| value.to_string()));
+        for (name, value) in [
+            ("DARWIN_JULIA_URL", "127.0.0.1:8081"),
+            ("DARWIN_JULIA_URL", "ftp://julia"),
+            ("DARWIN_BIND_ADDR", "localhost"),
+            ("DARWIN_PORT", "70000"),
+            ("DARWIN_FORWARD_HEADERS", "x-tenant-id, Connection"),
+            ("DARWIN_FORWARD_HEADERS", "bad header"),
+            ("DARWIN_METRICS_ADDR", "9090"),
+            ("DARWIN_STORAGE", "ftp"),
+        ] {
+            let error = parse(name, value).unwrap_err();
+            assert!(error.starts_with(name), "{}", error);
+        }
+    }
+
+    #[tokio::test]
+    async fn production_requires_the_api_key_and_never_echoes_it() {
+        let config = config_from(&[("DARWIN_ENV", "production"), ("DARWIN_API_KEY", "s3cret")]);
+
+        let (status, _) = get_config(config.clone(), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, body) = get_config(config, Some("s3cret")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["auth_enabled"], true);
+        assert!(!body.to_string().contains("s3cret"));
+
+        let (status, _) = get_config(Config::default(), None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
@@ -0,0 +1,146 @@
+//! `Content-Type` of every response: JSON is always `application/json; charset=utf-8`.
+//!
+//! Handlers return [`AppError`], but axum answers some failures itself before a
+//! handler runs: extractor rejections (malformed JSON, a missing multipart
+//! boundary), body limits and unmatched methods come back as `text/plain` or
+//! with no body at all. [`json_responses`] turns those into the usual
+//! `{"error": {code, message, request_id}}` body so clients can parse every error
+//! the same way. Non-JSON successes (downloads, SSE, metrics, the frontend) pass
+//! through untouched.
+
+use axum::{
+    extract::Request,
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::error::AppError;
+
+pub const JSON_CONTENT_TYPE: &str = "application/json; charset=utf-8";
+
+/// Plain-text error bodies longer than this are cut; axum's own are one line
+const MAX_PLAIN_ERROR_BYTES: usize = 4 * 1024;
+
+/// Middleware: add the charset to JSON responses and render plain-text errors as JSON.
+pub async fn json_responses(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    let content_type = response.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+    let essence = content_type.map(|ct| ct.split(';').next().unwrap_or("").trim().to_ascii_lowercase());
+    match essence.as_deref() {
+        Some("application/json") => with_json_content_type(response),
+        Some("text/plain") | None if is_error(response.status()) => plain_error_as_json(response).await,
+        _ => response,
+    }
+}
+
+fn is_error(status: StatusCode) -> bool {
+    status.is_client_error() || status.is_server_error()
+}
+
+fn with_json_content_type(mut response: Response) -> Response {
+    response.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static(JSON_CONTENT_TYPE));
+    response
+}
+
+/// The `code` an error axum raised gets, by status
+fn code_for(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+        StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+        status if status.is_server_error() => "internal",
+        _ => "invalid_request",
+    }
+}
+
+async fn plain_error_as_json(response: Response) -> Response {
+    let (parts, body) = response.into_parts();
+    let text = match axum::body::to_bytes(body, MAX_PLAIN_ERROR_BYTES).await {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).trim().to_string(),
+        Err(_) => String::new(),
+    };
+    let message = if text.is_empty() {
+        parts.status.canonical_reason().unwrap_or("request failed").to_string()
+    } else {
+        text
+    };
+
+    let mut json = AppError::rejected(parts.status, code_for(parts.status), message).into_response();
+    *json.status_mut() = parts.status;
+    for (name, value) in &parts.headers {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            json.headers_mut().insert(name, value.clone());
+        }
+    }
+    with_json_content_type(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        middleware,
+        routing::{get, post},
+        Json, Router,
+    };
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    fn app() -> Router {
+        Router::new()
+            .route("/json", get(|| async { Json(json!({"ok": true})) }))
+            .route("/echo", post(|Json(body): Json<Value>| async move { Json(body) }))
+            .route("/text", get(|| async { "plain success" }))
+            .route(
+                "/teapot",
+                get(|| async { (StatusCode::IM_A_TEAPOT, [(header::RETRY_AFTER, "3")], "short and stout") }),
+            )
+            .layer(middleware::from_fn(json_responses))
+    }
+
+    async fn send(request: Request<Body>) -> (StatusCode, String, Value) {
+        let response = app().oneshot(request).await.unwrap();
+        let status = response.status();
+        let content_type = response.headers()[header::CONTENT_TYPE].to_str().unwrap().to_string();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, content_type, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn json_gets_a_charset_and_plain_successes_are_kept() {
+        let (status, content_type, body) = send(Request::get("/json").body(Body::empty()).unwrap()).await;
+        assert_eq!((status, content_type.as_str()), (StatusCode::OK, JSON_CONTENT_TYPE));
+        assert_eq!(body["ok"], true);
+
+        let (_, content_type, _) = send(Request::get("/text").body(Body::empty()).unwrap()).await;
+        assert_eq!(content_type, "text/plain; charset=utf-8");
+    }
+
+    #[tokio::test]
+    async fn axum_rejections_become_json_errors() {
+        let malformed = Request::post("/echo").header("content-type", "application/json").body(Body::from("{nope")).unwrap();
+        let (status, content_type, body) = send(malformed).await;
+        assert_eq!((status, content_type.as_str()), (StatusCode::BAD_REQUEST, JSON_CONTENT_TYPE));
+        assert_eq!(body["error"]["code"], "invalid_request");
+        assert!(body["error"]["message"].as_str().unwrap().contains("Failed to parse the request body as JSON"));
+
+        let not_json = Request::post("/echo").body(Body::from("{}")).unwrap();
+        let (status, _, body) = send(not_json).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(body["error"]["code"], "unsupported_media_type");
+
+        // No body at all: the message falls back to the status reason
+        let (status, content_type, body) = send(Request::delete("/json").body(Body::empty()).unwrap()).await;
+        assert_eq!((status, content_type.as_str()), (StatusCode::METHOD_NOT_ALLOWED, JSON_CONTENT_TYPE));
+        assert_eq!(body["error"], json!({"code": "method_not_allowed", "message": "Method Not Allowed", "request_id": null}));
+
+        // Status and other headers survive the conversion
+        let response = app().oneshot(Request::get("/teapot").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "3");
+        assert_eq!(response.headers()[header::CONTENT_TYPE], JSON_CONTENT_TYPE);
+    }
+}
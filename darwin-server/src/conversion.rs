@@ -0,0 +1,276 @@
+//! `POST /api/convert` - convert a stored upload or export to another format via Julia.
+//!
+//! Which source → target pairs are possible is decided here, from [`CONVERSIONS`],
+//! so unsupported requests are refused without a Julia round trip.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{downloads, error::AppError, julia, storage, AppState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    Tiff,
+    Nifti,
+    Raw,
+    Stl,
+    Obj,
+    Ply,
+}
+
+/// Conversion matrix: every source format and the targets it converts to.
+///
+/// Raw volumes carry no dimensions, so they can be produced but not read back.
+pub const CONVERSIONS: &[(FileFormat, &[FileFormat])] = &[
+    (FileFormat::Tiff, &[FileFormat::Nifti, FileFormat::Raw, FileFormat::Stl]),
+    (FileFormat::Nifti, &[FileFormat::Tiff, FileFormat::Raw, FileFormat::Stl]),
+    (FileFormat::Raw, &[]),
+    (FileFormat::Stl, &[FileFormat::Obj, FileFormat::Ply]),
+    (FileFormat::Obj, &[FileFormat::Stl, FileFormat::Ply]),
+    (FileFormat::Ply, &[FileFormat::Stl, FileFormat::Obj]),
+];
+
+impl FileFormat {
+    /// Name used in requests and sent to Julia.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Tiff => "tiff",
+            Self::Nifti => "nifti",
+            Self::Raw => "raw",
+            Self::Stl => "stl",
+            Self::Obj => "obj",
+            Self::Ply => "ply",
+        }
+    }
+
+    /// Extension given to converted files.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Tiff => ".tif",
+            Self::Nifti => ".nii",
+            Self::Raw => ".raw",
+            Self::Stl => ".stl",
+            Self::Obj => ".obj",
+            Self::Ply => ".ply",
+        }
+    }
+
+    /// Case-insensitive; common extensions (`tif`, `nii`) are accepted as names too.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().trim_start_matches('.').to_ascii_lowercase().as_str() {
+            "tiff" | "tif" => Some(Self::Tiff),
+            "nifti" | "nii" => Some(Self::Nifti),
+            "raw" => Some(Self::Raw),
+            "stl" => Some(Self::Stl),
+            "obj" => Some(Self::Obj),
+            "ply" => Some(Self::Ply),
+            _ => None,
+        }
+    }
+
+    /// Format of a file and the length of the extension that identified it.
+    pub fn from_file_name(file_name: &str) -> Option<(Self, usize)> {
+        let lower = file_name.to_ascii_lowercase();
+        [
+            (".nii.gz", Self::Nifti),
+            (".nii", Self::Nifti),
+            (".tiff", Self::Tiff),
+            (".tif", Self::Tiff),
+            (".raw", Self::Raw),
+            (".stl", Self::Stl),
+            (".obj", Self::Obj),
+            (".ply", Self::Ply),
+        ]
+        .into_iter()
+        .find(|(ext, _)| lower.ends_with(ext))
+        .map(|(ext, format)| (format, ext.len()))
+    }
+
+    pub fn targets(self) -> &'static [FileFormat] {
+        CONVERSIONS.iter().find(|(source, _)| *source == self).map(|(_, targets)| *targets).unwrap_or(&[])
+    }
+}
+
+/// `Ok` if `source` converts to `target`, otherwise a message listing what it converts to.
+pub fn check_conversion(source: FileFormat, target: FileFormat) -> Result<(), String> {
+    if source.targets().contains(&target) {
+        return Ok(());
+    }
+    let possible: Vec<&str> = source.targets().iter().map(|f| f.name()).collect();
+    Err(if possible.is_empty() {
+        format!("{} files cannot be converted to anything", source.name())
+    } else {
+        format!(
+            "cannot convert {} to {}; {} converts to: {}",
+            source.name(),
+            target.name(),
+            source.name(),
+            possible.join(", ")
+        )
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConvertRequest {
+    pub file_id: String,
+    pub target_format: String,
+}
+
+/// `POST /api/convert` - `{file_id, target_format}` → `{file_id, file_name, ...}`.
+///
+/// The source may be an upload or an export; the converted file is stored as a new
+/// upload. Unsupported pairs are a `400` carrying the source's `supported_targets`.
+pub async fn convert_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<ConvertRequest>,
+) -> Result<Json<Value>, AppError> {
+    let upload = storage::find_upload(state.storage.as_ref(), &request.file_id).await;
+    let upload = upload.map_err(|e| AppError::internal(format!("cannot look up upload {}: {}", request.file_id, e)))?;
+    let source_path = match upload {
+        Some(path) => path,
+        None => match downloads::resolve_stored_file(&state.export_dir, &request.file_id).await {
+            Some(path) => path,
+            None => return Err(AppError::not_found(format!("no stored file with id {}", request.file_id))),
+        },
+    };
+    let source_name = downloads::display_name(&source_path);
+    let Some((source, ext_len)) = FileFormat::from_file_name(&source_name) else {
+        let message = format!("cannot tell the format of `{}`", source_name);
+        return Err(AppError::rejected(StatusCode::UNSUPPORTED_MEDIA_TYPE, "unknown_source_format", message));
+    };
+    let supported_targets: Vec<&str> = source.targets().iter().map(|f| f.name()).collect();
+    let Some(target) = FileFormat::parse(&request.target_format) else {
+        return Err(AppError::validation(format!("unknown target format `{}`", request.target_format))
+            .with_detail("supported_targets", supported_targets));
+    };
+    if let Err(message) = check_conversion(source, target) {
+        return Err(AppError::validation(message).with_detail("supported_targets", supported_targets));
+    }
+
+    let file_id = Uuid::new_v4();
+    let stem = &source_name[..source_name.len() - ext_len];
+    let file_name = format!("{}{}", stem, target.extension());
+    let key = storage::upload_key(file_id, &file_name);
+    let output_path = state.storage.local_path(&key);
+    let payload = serde_json::json!({
+        "input_path": source_path,
+        "output_path": output_path,
+        "source_format": source.name(),
+        "target_format": target.name(),
+    });
+
+    let converted = julia::fetch_julia(&state, "convert", &headers, payload).await.and_then(|reply| reply.into_body());
+    if let Err(error) = converted {
+        let _ = tokio::fs::remove_file(&output_path).await;
+        return Err(error);
+    }
+    let Ok(metadata) = tokio::fs::metadata(&output_path).await else {
+        return Err(AppError::upstream(StatusCode::OK, "no_output", "Julia reported success but wrote no file")
+            .with_detail("source", "julia"));
+    };
+    let output_path = match state.storage.put(&key, &output_path).await {
+        Ok(path) => path,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&output_path).await;
+            return Err(AppError::internal(format!("cannot store converted file: {}", e)));
+        }
+    };
+
+    Ok(Json(serde_json::json!({
+        "file_id": file_id.to_string(),
+        "file_name": file_name,
+        "file_path": output_path,
+        "size": metadata.len(),
+        "source_format": source.name(),
+        "target_format": target.name(),
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{response::IntoResponse, routing::post, Router};
+    use serde_json::json;
+
+    #[test]
+    fn matrix_allows_listed_pairs_and_explains_the_rest() {
+        assert_eq!(check_conversion(FileFormat::Tiff, FileFormat::Nifti), Ok(()));
+        assert_eq!(check_conversion(FileFormat::Stl, FileFormat::Obj), Ok(()));
+        assert_eq!(
+            check_conversion(FileFormat::Stl, FileFormat::Nifti).unwrap_err(),
+            "cannot convert stl to nifti; stl converts to: obj, ply"
+        );
+        assert!(check_conversion(FileFormat::Raw, FileFormat::Tiff).unwrap_err().contains("cannot be converted"));
+        // Every source in the matrix appears once and never targets itself
+        for (source, targets) in CONVERSIONS {
+            assert_eq!(CONVERSIONS.iter().filter(|(s, _)| s == source).count(), 1);
+            assert!(!targets.contains(source));
+        }
+    }
+
+    #[test]
+    fn formats_come_from_names_and_extensions() {
+        assert_eq!(FileFormat::parse("TIF"), Some(FileFormat::Tiff));
+        assert_eq!(FileFormat::parse(".nii"), Some(FileFormat::Nifti));
+        assert_eq!(FileFormat::parse("step"), None);
+        assert_eq!(FileFormat::from_file_name("femur.nii.gz"), Some((FileFormat::Nifti, 7)));
+        assert_eq!(FileFormat::from_file_name("scan.TIFF"), Some((FileFormat::Tiff, 5)));
+        assert_eq!(FileFormat::from_file_name("photo.jpg"), None);
+    }
+
+    #[tokio::test]
+    async fn converted_file_is_stored_as_a_new_upload() {
+        // "Julia" writes the requested output and echoes the request
+        let app = Router::new().route(
+            "/convert",
+            post(|Json(body): Json<Value>| async move {
+                tokio::fs::write(body["output_path"].as_str().unwrap(), b"ply").await.unwrap();
+                Json(json!({"request": body}))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let upload_dir = std::env::temp_dir().join(format!("darwin_convert_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&upload_dir).unwrap();
+        let source_id = Uuid::new_v4().to_string();
+        std::fs::write(upload_dir.join(format!("{}_lattice.stl", source_id)), b"solid").unwrap();
+        let state = Arc::new(AppState {
+            upload_dir: upload_dir.clone(),
+            storage: Arc::new(storage::LocalFsStorage::new(upload_dir.clone())),
+            ..AppState::for_tests(&format!("http://{}", addr))
+        });
+
+        let convert = |target: &str| {
+            let state = state.clone();
+            let request = ConvertRequest { file_id: source_id.clone(), target_format: target.to_string() };
+            async move {
+                let response = convert_handler(State(state), HeaderMap::new(), Json(request)).await.into_response();
+                let status = response.status();
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                (status, serde_json::from_slice::<Value>(&bytes).unwrap())
+            }
+        };
+
+        let (status, body) = convert("ply").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["file_name"], "lattice.ply");
+        let converted = downloads::resolve_stored_file(&upload_dir, body["file_id"].as_str().unwrap()).await.unwrap();
+        assert_eq!(std::fs::read(converted).unwrap(), b"ply");
+
+        let (status, body) = convert("nifti").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["supported_targets"], json!(["obj", "ply"]));
+
+        std::fs::remove_dir_all(upload_dir).unwrap();
+    }
+}
@@ -0,0 +1,339 @@
+//! SQLite persistence (`DARWIN_DATABASE`) for what should outlive a restart: the
+//! metadata of stored uploads, the latest analysis of each workspace and the
+//! history of background jobs.
+//!
+//! The schema is the SQL in `migrations/`, embedded at build time. Pending
+//! migrations run when the server starts, or on first use where nothing ran them.
+//! While the server runs, the in-memory tables stay authoritative; the database is
+//! written through alongside them and read where memory has nothing (a job that
+//! finished before the restart, say).
+
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteRow},
+    Row, SqlitePool,
+};
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::OnceCell;
+
+use crate::jobs::Job;
+
+static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("./migrations");
+
+/// How long a write waits on another connection's lock before failing
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Metadata of a stored upload, recorded once it is in storage.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UploadRecord {
+    pub file_id: String,
+    pub storage_key: String,
+    pub original_name: String,
+    pub size: u64,
+    pub sha256: String,
+    /// What `file_format` detected, e.g. `"tiff"`
+    pub format: String,
+    pub created_at_ms: u64,
+}
+
+/// The latest analysis of a workspace, as `GET /api/workspaces/:id` reports it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WorkspaceRecord {
+    pub workspace_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    pub last_analysis: Value,
+    pub updated_at_ms: u64,
+}
+
+/// Milliseconds since the Unix epoch, the unit of every stored time
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+/// A JSON column, `NULL` for `None`
+fn json_column(row: &SqliteRow, column: &str) -> Result<Option<Value>, sqlx::Error> {
+    let text: Option<String> = row.try_get(column)?;
+    text.map(|text| serde_json::from_str(&text).map_err(|e| sqlx::Error::Decode(Box::new(e))))
+        .transpose()
+}
+
+/// A pool on the database file, migrated at most once.
+#[derive(Clone)]
+pub struct Db {
+    pool: SqlitePool,
+    migrated: Arc<OnceCell<()>>,
+}
+
+impl Db {
+    /// Open (creating if needed) the database at `path`. Nothing is read until
+    /// first use; call [`Db::migrate`] to surface a bad path or schema at startup.
+    pub fn open(path: &Path) -> Self {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .busy_timeout(BUSY_TIMEOUT);
+        Self::with_pool(SqlitePoolOptions::new(), options)
+    }
+
+    /// A private in-memory database that lives as long as this `Db` and its clones.
+    /// Every connection would get its own, so the pool keeps exactly one, never
+    /// retired for age or idleness.
+    #[cfg(test)]
+    pub fn in_memory() -> Self {
+        let options = SqliteConnectOptions::new().in_memory(true);
+        Self::with_pool(SqlitePoolOptions::new().max_connections(1), options)
+    }
+
+    fn with_pool(pool: SqlitePoolOptions, options: SqliteConnectOptions) -> Self {
+        // No reaper task, so a pool can be built outside a runtime
+        let pool = pool.idle_timeout(None).max_lifetime(None).connect_lazy_with(options);
+        Self {
+            pool,
+            migrated: Arc::default(),
+        }
+    }
+
+    /// Apply pending migrations.
+    pub async fn migrate(&self) -> Result<(), sqlx::Error> {
+        self.pool().await.map(|_| ())
+    }
+
+    async fn pool(&self) -> Result<&SqlitePool, sqlx::Error> {
+        self.migrated
+            .get_or_try_init(|| async { MIGRATOR.run(&self.pool).await.map_err(sqlx::Error::from) })
+            .await?;
+        Ok(&self.pool)
+    }
+
+    pub async fn record_upload(&self, upload: &UploadRecord) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO uploads (file_id, storage_key, original_name, size, sha256, format, created_at_ms)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&upload.file_id)
+        .bind(&upload.storage_key)
+        .bind(&upload.original_name)
+        .bind(upload.size as i64)
+        .bind(&upload.sha256)
+        .bind(&upload.format)
+        .bind(upload.created_at_ms as i64)
+        .execute(self.pool().await?)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn forget_upload(&self, storage_key: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM uploads WHERE storage_key = ?")
+            .bind(storage_key)
+            .execute(self.pool().await?)
+            .await?;
+        Ok(())
+    }
+
+    /// Every recorded upload, oldest first.
+    pub async fn uploads(&self) -> Result<Vec<UploadRecord>, sqlx::Error> {
+        let rows = sqlx::query("SELECT * FROM uploads ORDER BY created_at_ms, file_id")
+            .fetch_all(self.pool().await?)
+            .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(UploadRecord {
+                    file_id: row.try_get("file_id")?,
+                    storage_key: row.try_get("storage_key")?,
+                    original_name: row.try_get("original_name")?,
+                    size: row.try_get::<i64, _>("size")? as u64,
+                    sha256: row.try_get("sha256")?,
+                    format: row.try_get("format")?,
+                    created_at_ms: row.try_get::<i64, _>("created_at_ms")? as u64,
+                })
+            })
+            .collect()
+    }
+
+    /// Replace the workspace's latest analysis.
+    pub async fn record_analysis(
+        &self,
+        workspace_id: &str,
+        file_path: Option<&str>,
+        analysis: &Value,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO workspaces (workspace_id, file_path, last_analysis, updated_at_ms)
+             VALUES (?, ?, ?, ?)",
+        )
+        .bind(workspace_id)
+        .bind(file_path)
+        .bind(analysis.to_string())
+        .bind(now_ms() as i64)
+        .execute(self.pool().await?)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn workspace(&self, workspace_id: &str) -> Result<Option<WorkspaceRecord>, sqlx::Error> {
+        let row = sqlx::query("SELECT * FROM workspaces WHERE workspace_id = ?")
+            .bind(workspace_id)
+            .fetch_optional(self.pool().await?)
+            .await?;
+        row.map(|row| {
+            Ok(WorkspaceRecord {
+                workspace_id: row.try_get("workspace_id")?,
+                file_path: row.try_get("file_path")?,
+                last_analysis: json_column(&row, "last_analysis")?.unwrap_or(Value::Null),
+                updated_at_ms: row.try_get::<i64, _>("updated_at_ms")? as u64,
+            })
+        })
+        .transpose()
+    }
+
+    /// Insert the job, or bring its row up to date.
+    pub async fn save_job(&self, job: &Job) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO jobs (job_id, kind, status, submitted_at_ms, started_at_ms, finished_at_ms, result, error)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT (job_id) DO UPDATE SET
+                 status = excluded.status,
+                 started_at_ms = excluded.started_at_ms,
+                 finished_at_ms = excluded.finished_at_ms,
+                 result = excluded.result,
+                 error = excluded.error",
+        )
+        .bind(&job.job_id)
+        .bind(job.kind.as_str())
+        .bind(job.status.as_str())
+        .bind(job.submitted_at_ms as i64)
+        .bind(job.started_at_ms.map(|ms| ms as i64))
+        .bind(job.finished_at_ms.map(|ms| ms as i64))
+        .bind(job.result.as_ref().map(Value::to_string))
+        .bind(job.error.as_ref().map(Value::to_string))
+        .execute(self.pool().await?)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn job(&self, job_id: &str) -> Result<Option<Job>, sqlx::Error> {
+        let row = sqlx::query("SELECT * FROM jobs WHERE job_id = ?")
+            .bind(job_id)
+            .fetch_optional(self.pool().await?)
+            .await?;
+        row.map(|row| {
+            let mut job = serde_json::json!({
+                "job_id": row.try_get::<String, _>("job_id")?,
+                "kind": row.try_get::<String, _>("kind")?,
+                "status": row.try_get::<String, _>("status")?,
+                "submitted_at_ms": row.try_get::<i64, _>("submitted_at_ms")?,
+                "started_at_ms": row.try_get::<Option<i64>, _>("started_at_ms")?,
+                "finished_at_ms": row.try_get::<Option<i64>, _>("finished_at_ms")?,
+            });
+            job["result"] = json_column(&row, "result")?.into();
+            job["error"] = json_column(&row, "error")?.into();
+            serde_json::from_value(job).map_err(|e| sqlx::Error::Decode(Box::new(e)))
+        })
+        .transpose()
+    }
+
+    /// Fail the jobs a previous run left queued or running: no worker will pick
+    /// them up again. Returns how many there were.
+    pub async fn fail_interrupted_jobs(&self) -> Result<u64, sqlx::Error> {
+        let error = serde_json::json!({
+            "code": "interrupted",
+            "message": "the server stopped before the job finished",
+            "status": 503,
+        });
+        let done = sqlx::query(
+            "UPDATE jobs SET status = 'failed', finished_at_ms = ?, error = ?
+             WHERE status IN ('queued', 'running')",
+        )
+        .bind(now_ms() as i64)
+        .bind(error.to_string())
+        .execute(self.pool().await?)
+        .await?;
+        Ok(done.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::{JobKind, JobStatus};
+
+    fn job(status: JobStatus) -> Job {
+        serde_json::from_value(serde_json::json!({
+            "job_id": uuid::Uuid::new_v4().to_string(),
+            "kind": "analyze",
+            "status": status,
+            "submitted_at_ms": 1_000,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn stored_names_match_the_serde_names() {
+        for kind in [JobKind::Analyze, JobKind::Optimize] {
+            assert_eq!(serde_json::json!(kind), kind.as_str());
+        }
+        for status in [JobStatus::Queued, JobStatus::Running, JobStatus::Succeeded, JobStatus::Failed] {
+            assert_eq!(serde_json::json!(status), status.as_str());
+        }
+    }
+
+    #[tokio::test]
+    async fn records_survive_reopening_the_database() {
+        let path = std::env::temp_dir().join(format!("darwin_db_test_{}.db", uuid::Uuid::new_v4()));
+        let db = Db::open(&path);
+        db.migrate().await.unwrap();
+
+        let upload = UploadRecord {
+            file_id: uuid::Uuid::new_v4().to_string(),
+            storage_key: "key_scan.tif".to_string(),
+            original_name: "scan.tif".to_string(),
+            size: 4,
+            sha256: "75a2a133".to_string(),
+            format: "tiff".to_string(),
+            created_at_ms: 1_000,
+        };
+        db.record_upload(&upload).await.unwrap();
+        db.record_analysis("ws-1", Some("/data/old.tif"), &serde_json::json!({"porosity": 0.5})).await.unwrap();
+        db.record_analysis("ws-1", None, &serde_json::json!({"porosity": 0.8})).await.unwrap();
+
+        let (mut finished, running) = (job(JobStatus::Running), job(JobStatus::Running));
+        db.save_job(&finished).await.unwrap();
+        finished.status = JobStatus::Succeeded;
+        finished.finished_at_ms = Some(2_000);
+        finished.result = Some(serde_json::json!({"porosity": 0.8}));
+        db.save_job(&finished).await.unwrap();
+        db.save_job(&running).await.unwrap();
+        drop(db);
+
+        // As after a restart; migrating again is a no-op
+        let db = Db::open(&path);
+        db.migrate().await.unwrap();
+        assert_eq!(db.uploads().await.unwrap(), vec![upload.clone()]);
+        db.forget_upload(&upload.storage_key).await.unwrap();
+        assert!(db.uploads().await.unwrap().is_empty());
+
+        let workspace = db.workspace("ws-1").await.unwrap().unwrap();
+        assert_eq!((workspace.file_path, workspace.last_analysis), (None, serde_json::json!({"porosity": 0.8})));
+        assert!(db.workspace("ws-2").await.unwrap().is_none());
+
+        let stored = db.job(&finished.job_id).await.unwrap().unwrap();
+        assert_eq!((stored.kind, stored.status, stored.result), (JobKind::Analyze, JobStatus::Succeeded, finished.result));
+        assert_eq!(db.fail_interrupted_jobs().await.unwrap(), 1);
+        let interrupted = db.job(&running.job_id).await.unwrap().unwrap();
+        assert_eq!(interrupted.status, JobStatus::Failed);
+        assert_eq!(interrupted.error.unwrap()["code"], "interrupted");
+        assert!(db.job(&uuid::Uuid::new_v4().to_string()).await.unwrap().is_none());
+
+        drop(db);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use std::{io::SeekFrom, path::PathBuf, sync::Arc};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::{error::AppError, AppState};
+
+/// Content type for an exported file, from its extension.
+pub fn content_type_for(file_name: &str) -> &'static str {
+    let ext = file_name.rsplit_once('.').map(|(_, e)| e.to_ascii_lowercase());
+    match ext.as_deref() {
+        Some("stl") => "model/stl",
+        Some("obj") => "model/obj",
+        Some("ply") => "application/ply",
+        Some("gcode") => "text/x-gcode",
+        Some("json") => "application/json",
+        Some("csv") => "text/csv",
+        Some("png") => "image/png",
+        Some("zip") => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Find the stored file for `file_id` inside `dir`.
+///
+/// Stored files are named `{uuid}_{original_name}` (or `{uuid}.{ext}`), so the id
+/// must be a UUID; that alone rules out path traversal, and the resolved path is
+/// additionally checked to still live under `dir`.
+pub async fn resolve_stored_file(dir: &std::path::Path, file_id: &str) -> Option<PathBuf> {
+    let id = Uuid::parse_str(file_id).ok()?.to_string();
+    let root = tokio::fs::canonicalize(dir).await.ok()?;
+
+    let mut entries = tokio::fs::read_dir(&root).await.ok()?;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let matches = name
+            .strip_prefix(&id)
+            .is_some_and(|rest| rest.starts_with('_') || rest.starts_with('.'));
+        if !matches {
+            continue;
+        }
+
+        let path = tokio::fs::canonicalize(entry.path()).await.ok()?;
+        let is_file = tokio::fs::metadata(&path).await.map(|m| m.is_file()).unwrap_or(false);
+        return (path.starts_with(&root) && is_file).then_some(path);
+    }
+    None
+}
+
+/// Original client-facing name of a stored file (strips the `{uuid}_` prefix).
+pub fn display_name(path: &std::path::Path) -> String {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    match name.split_once('_') {
+        Some((id, rest)) if Uuid::parse_str(id).is_ok() && !rest.is_empty() => rest.to_string(),
+        _ => name,
+    }
+}
+
+/// `GET /api/download/:file_id` - stream a previously exported mesh as an attachment.
+pub async fn download_handler(
+    State(state): State<Arc<AppState>>,
+    Path(file_id): Path<String>,
+) -> Result<Response, AppError> {
+    let (path, file, len) = open_export(&state, &file_id).await?;
+
+    let name = display_name(&path).replace('"', "");
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type_for(&name).to_string()),
+            (header::CONTENT_LENGTH, len.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", name)),
+        ],
+        Body::from_stream(ReaderStream::new(file)),
+    )
+        .into_response())
+}
+
+/// The stored export `file_id`, opened, with its length.
+async fn open_export(state: &AppState, file_id: &str) -> Result<(PathBuf, tokio::fs::File, u64), AppError> {
+    let not_found = || AppError::not_found(format!("no export with id {}", file_id));
+    let path = resolve_stored_file(&state.export_dir, file_id).await.ok_or_else(not_found)?;
+    let file = tokio::fs::File::open(&path).await.map_err(|_| not_found())?;
+    let len = file.metadata().await.map_err(|_| not_found())?.len();
+    Ok((path, file, len))
+}
+
+/// A request's `Range` resolved against a file of known length.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ByteRange {
+    /// No usable range: send the whole file
+    Full,
+    /// Inclusive byte offsets, already clamped to the file
+    Partial { start: u64, end: u64 },
+    Unsatisfiable,
+}
+
+/// Resolve a `Range` header against a file of `len` bytes.
+///
+/// Single `bytes=` ranges are honoured (`a-b`, `a-` and the suffix form `-n`).
+/// Malformed headers and multi-range requests get the full body, which RFC 9110
+/// permits for ranges a server chooses not to support.
+pub fn parse_range(header: Option<&str>, len: u64) -> ByteRange {
+    let Some(spec) = header.and_then(|h| h.trim().strip_prefix("bytes=")) else {
+        return ByteRange::Full;
+    };
+    let Some((first, last)) = spec.split_once('-').filter(|_| !spec.contains(',')) else {
+        return ByteRange::Full;
+    };
+    let (first, last) = (first.trim(), last.trim());
+
+    let (start, end) = match (first.parse::<u64>(), last.parse::<u64>()) {
+        (Ok(start), Ok(end)) if end >= start => (start, end),
+        (Ok(start), Err(_)) if last.is_empty() => (start, u64::MAX),
+        (Err(_), Ok(suffix)) if first.is_empty() => {
+            if suffix == 0 || len == 0 {
+                return ByteRange::Unsatisfiable;
+            }
+            (len.saturating_sub(suffix), u64::MAX)
+        }
+        _ => return ByteRange::Full,
+    };
+    if start >= len {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Partial { start, end: end.min(len - 1) }
+}
+
+/// `GET /api/files/:file_id` - serve an exported file inline, honouring `Range` so
+/// progressive viewers can fetch large meshes piecewise.
+///
+/// Answers `200` with the whole file when no (usable) range is asked for, `206`
+/// with `Content-Range` for a satisfiable one and `416` otherwise.
+pub async fn file_handler(
+    State(state): State<Arc<AppState>>,
+    Path(file_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let (path, mut file, len) = open_export(&state, &file_id).await?;
+
+    let name = display_name(&path).replace('"', "");
+    let representation = [
+        (header::CONTENT_TYPE, content_type_for(&name).to_string()),
+        (header::ACCEPT_RANGES, "bytes".to_string()),
+        (header::CONTENT_DISPOSITION, format!("inline; filename=\"{}\"", name)),
+    ];
+    let range = parse_range(headers.get(header::RANGE).and_then(|v| v.to_str().ok()), len);
+    Ok(match range {
+        ByteRange::Full => (
+            representation,
+            [(header::CONTENT_LENGTH, len.to_string())],
+            Body::from_stream(ReaderStream::new(file)),
+        )
+            .into_response(),
+        ByteRange::Partial { start, end } => {
+            file.seek(SeekFrom::Start(start)).await.map_err(AppError::internal)?;
+            let length = end - start + 1;
+            (
+                StatusCode::PARTIAL_CONTENT,
+                representation,
+                [
+                    (header::CONTENT_LENGTH, length.to_string()),
+                    (header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, len)),
+                ],
+                Body::from_stream(ReaderStream::new(file.take(length))),
+            )
+                .into_response()
+        }
+        ByteRange::Unsatisfiable => {
+            let message = format!("range not satisfiable for a {} byte file", len);
+            (
+                [(header::CONTENT_RANGE, format!("bytes */{}", len))],
+                AppError::rejected(StatusCode::RANGE_NOT_SATISFIABLE, "range_not_satisfiable", message),
+            )
+                .into_response()
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::Request, routing::get, Router};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn resolves_only_uuid_named_files_inside_dir() {
+        let dir = std::env::temp_dir().join(format!("darwin_downloads_{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let id = Uuid::new_v4().to_string();
+        tokio::fs::write(dir.join(format!("{}_scaffold.stl", id)), b"solid").await.unwrap();
+
+        let found = resolve_stored_file(&dir, &id).await.unwrap();
+        assert_eq!(display_name(&found), "scaffold.stl");
+
+        assert_eq!(resolve_stored_file(&dir, &Uuid::new_v4().to_string()).await, None);
+        assert_eq!(resolve_stored_file(&dir, "../../etc/passwd").await, None);
+        assert_eq!(resolve_stored_file(&dir, &id[..8]).await, None);
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[test]
+    fn content_type_follows_extension() {
+        assert_eq!(content_type_for("part.STL"), "model/stl");
+        assert_eq!(content_type_for("noext"), "application/octet-stream");
+    }
+
+    #[test]
+    fn ranges_resolve_against_the_file_length() {
+        use ByteRange::*;
+        let cases = [
+            (None, Full),
+            (Some("bytes=0-99"), Partial { start: 0, end: 99 }),
+            (Some("bytes=900-"), Partial { start: 900, end: 999 }),
+            (Some("bytes=-100"), Partial { start: 900, end: 999 }),
+            (Some("bytes=-5000"), Partial { start: 0, end: 999 }),
+            (Some("bytes=990-2000"), Partial { start: 990, end: 999 }),
+            (Some("bytes=1000-"), Unsatisfiable),
+            (Some("bytes=-0"), Unsatisfiable),
+            (Some("bytes=50-10"), Full),
+            (Some("bytes=0-1,5-9"), Full),
+            (Some("items=0-1"), Full),
+            (Some("bytes=abc"), Full),
+        ];
+        for (header, expected) in cases {
+            assert_eq!(parse_range(header, 1000), expected, "{:?}", header);
+        }
+        assert_eq!(parse_range(Some("bytes=0-"), 0), Unsatisfiable);
+    }
+
+    #[tokio::test]
+    async fn serves_partial_content_and_rejects_unsatisfiable_ranges() {
+        let dir = std::env::temp_dir().join(format!("darwin_files_{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let id = Uuid::new_v4().to_string();
+        let contents: Vec<u8> = (0..=255u8).cycle().take(1000).collect();
+        tokio::fs::write(dir.join(format!("{}_lattice.stl", id)), &contents).await.unwrap();
+
+        let state = Arc::new(AppState {
+            export_dir: dir.clone(),
+            ..AppState::for_tests("http://127.0.0.1:1")
+        });
+        let app = Router::new().route("/api/files/:file_id", get(file_handler)).with_state(state);
+        let fetch = |range: Option<&str>| {
+            let mut request = Request::get(format!("/api/files/{}", id));
+            if let Some(range) = range {
+                request = request.header(header::RANGE, range);
+            }
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+                let status = response.status();
+                let headers = response.headers().clone();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                (status, headers, body)
+            }
+        };
+
+        let (status, headers, body) = fetch(None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::ACCEPT_RANGES], "bytes");
+        assert_eq!(body.as_ref(), contents.as_slice());
+
+        let (status, headers, body) = fetch(Some("bytes=100-199")).await;
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(headers[header::CONTENT_RANGE], "bytes 100-199/1000");
+        assert_eq!(headers[header::CONTENT_LENGTH], "100");
+        assert_eq!(body.as_ref(), &contents[100..200]);
+
+        let (status, headers, body) = fetch(Some("bytes=-10")).await;
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(headers[header::CONTENT_RANGE], "bytes 990-999/1000");
+        assert_eq!(body.as_ref(), &contents[990..]);
+
+        let (status, headers, _) = fetch(Some("bytes=5000-")).await;
+        assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(headers[header::CONTENT_RANGE], "bytes */1000");
+
+        let (status, _, _) = fetch(Some("bytes=0-1,4-5")).await;
+        assert_eq!(status, StatusCode::OK);
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,228 @@
+//! [`AppError`], the one error type handlers return.
+//!
+//! Every variant renders as
+//! `{"error": {"code": ..., "message": ..., "request_id": ..., <details>}}` with the
+//! status of its failure class, so clients branch on `code` and quote `request_id`
+//! when reporting a problem. Variant-specific context (the Julia body, missing
+//! chunks, validation problems) sits next to those three fields.
+
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::{Map, Value};
+
+use crate::{logging, uploads::UploadError};
+
+#[derive(Debug)]
+pub enum AppError {
+    /// An upload route failed; status and code come from the [`UploadError`]
+    Upload(UploadError),
+    /// Julia could not be reached, did not answer in time or is saturated
+    Proxy { status: StatusCode, code: &'static str, message: String },
+    /// The request is malformed or asks for something impossible: 400, or a more
+    /// specific 4xx such as 413 or 416
+    Validation { status: StatusCode, code: &'static str, message: String, details: Map<String, Value> },
+    NotFound(String),
+    /// Julia answered, but with a failure or a reply that cannot be used
+    Upstream { status: StatusCode, code: &'static str, message: String, details: Map<String, Value> },
+    /// Missing or rejected credentials (401); `bearer_error` goes into `WWW-Authenticate`
+    Unauthorized { message: String, bearer_error: Option<&'static str>, details: Map<String, Value> },
+    Internal(String),
+}
+
+impl AppError {
+    /// `400 invalid_request`
+    pub fn validation(message: impl Into<String>) -> Self {
+        Self::Validation {
+            status: StatusCode::BAD_REQUEST,
+            code: "invalid_request",
+            message: message.into(),
+            details: Map::new(),
+        }
+    }
+
+    /// A [`AppError::Validation`] with a 4xx more specific than 400
+    pub fn rejected(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self::Validation { status, code, message: message.into(), details: Map::new() }
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::NotFound(message.into())
+    }
+
+    pub fn internal(error: impl std::fmt::Display) -> Self {
+        Self::Internal(error.to_string())
+    }
+
+    /// Julia answered with a failure; the status is passed on unless it was a 2xx
+    pub fn upstream(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        let status = if status.is_success() { StatusCode::BAD_GATEWAY } else { status };
+        Self::Upstream { status, code, message: message.into(), details: Map::new() }
+    }
+
+    /// Add `key: value` next to `code` and `message`; a no-op for variants without details.
+    pub fn with_detail(mut self, key: &str, value: impl Into<Value>) -> Self {
+        match &mut self {
+            Self::Validation { details, .. } | Self::Upstream { details, .. } | Self::Unauthorized { details, .. } => {
+                details.insert(key.to_string(), value.into());
+            }
+            _ => {}
+        }
+        self
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::Upload(e) => e.status(),
+            Self::Proxy { status, .. } | Self::Validation { status, .. } | Self::Upstream { status, .. } => *status,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Unauthorized { .. } => StatusCode::UNAUTHORIZED,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Upload(e) => e.code(),
+            Self::Proxy { code, .. } | Self::Validation { code, .. } | Self::Upstream { code, .. } => code,
+            Self::NotFound(_) => "not_found",
+            Self::Unauthorized { bearer_error: Some(_), .. } => "invalid_token",
+            Self::Unauthorized { .. } => "unauthorized",
+            Self::Internal(_) => "internal",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Self::Upload(e) => e.message(),
+            Self::Proxy { message, .. }
+            | Self::Validation { message, .. }
+            | Self::Upstream { message, .. }
+            | Self::Unauthorized { message, .. }
+            | Self::NotFound(message)
+            | Self::Internal(message) => message.clone(),
+        }
+    }
+
+    /// The `error` object of the response body.
+    pub fn body(&self) -> Value {
+        let mut error = match self {
+            Self::Upload(e) => e.details(),
+            Self::Validation { details, .. } | Self::Upstream { details, .. } | Self::Unauthorized { details, .. } => {
+                details.clone()
+            }
+            _ => Map::new(),
+        };
+        error.insert("code".to_string(), self.code().into());
+        error.insert("message".to_string(), self.message().into());
+        error.insert("request_id".to_string(), logging::current_request_id().into());
+        serde_json::json!({"error": error})
+    }
+}
+
+impl From<UploadError> for AppError {
+    fn from(e: UploadError) -> Self {
+        Self::Upload(e)
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message())
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        if self.status().is_server_error() {
+            tracing::warn!(code = self.code(), "{}", self.message());
+        }
+        let mut response = (self.status(), Json(self.body())).into_response();
+        if let Self::Unauthorized { bearer_error, .. } = &self {
+            let challenge = match bearer_error {
+                Some(error) => format!("Bearer error=\"{}\"", error),
+                None => "Bearer".to_string(),
+            };
+            if let Ok(value) = challenge.parse() {
+                response.headers_mut().insert(header::WWW_AUTHENTICATE, value);
+            }
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    async fn render(error: AppError) -> (StatusCode, Value) {
+        let response = logging::with_request_id("req-7".to_string(), async move { error.into_response() }).await;
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn every_variant_renders_code_message_and_request_id() {
+        let cases = [
+            (AppError::Upload(UploadError::ChecksumMismatch), 422, "checksum_mismatch"),
+            (
+                AppError::Proxy {
+                    status: StatusCode::GATEWAY_TIMEOUT,
+                    code: "julia_timeout",
+                    message: "Julia did not respond within 5s".to_string(),
+                },
+                504,
+                "julia_timeout",
+            ),
+            (AppError::validation("`voxel_size` must be a positive number"), 400, "invalid_request"),
+            (AppError::not_found("no export with id 42"), 404, "not_found"),
+            (AppError::upstream(StatusCode::OK, "julia_error", "mesh failed"), 502, "julia_error"),
+            (
+                AppError::Unauthorized { message: "missing or invalid API key".to_string(), bearer_error: None, details: Map::new() },
+                401,
+                "unauthorized",
+            ),
+            (AppError::internal("disk full"), 500, "internal"),
+        ];
+        for (error, status, code) in cases {
+            let message = error.message();
+            let (rendered, body) = render(error).await;
+            assert_eq!(rendered.as_u16(), status, "{}", code);
+            assert_eq!(body, json!({"error": {"code": code, "message": message, "request_id": "req-7"}}));
+        }
+    }
+
+    #[tokio::test]
+    async fn details_sit_next_to_the_code() {
+        let error = AppError::upstream(StatusCode::UNPROCESSABLE_ENTITY, "julia_error", "volume is empty")
+            .with_detail("source", "julia")
+            .with_detail("julia_body", json!({"error": "volume is empty"}));
+        let (status, body) = render(error).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"]["source"], "julia");
+        assert_eq!(body["error"]["julia_body"]["error"], "volume is empty");
+
+        let (status, body) = render(UploadError::Incomplete(vec![2, 5]).into()).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["error"]["missing_chunks"], json!([2, 5]));
+
+        let response = AppError::Unauthorized {
+            message: "invalid token".to_string(),
+            bearer_error: Some("invalid_token"),
+            details: Map::new(),
+        }
+        .into_response();
+        assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer error=\"invalid_token\"");
+    }
+
+    #[tokio::test]
+    async fn request_id_is_null_outside_a_request() {
+        let response = AppError::not_found("gone").into_response();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["error"]["request_id"], Value::Null);
+    }
+}
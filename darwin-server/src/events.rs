@@ -0,0 +1,217 @@
+//! Server-wide pub/sub: compute handlers publish completion events and the
+//! `progress` relay publishes Julia job progress; `GET /ws/events` relays them to
+//! every connected WebSocket.
+//!
+//! Subscribers only see events published after they connect; there is no replay.
+//! A subscriber that falls more than `EVENT_BUFFER` events behind gets a
+//! `{"type":"lagged","missed":n}` notice and continues with the newest events.
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+};
+use futures::{SinkExt, StreamExt};
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::AppState;
+
+/// Events buffered per subscriber before it is considered lagging
+pub const EVENT_BUFFER: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    AnalysisComplete {
+        workspace_id: Option<String>,
+        metrics: Value,
+    },
+    OptimizationComplete {
+        workspace_id: Option<String>,
+        candidates: usize,
+    },
+    MeshComplete {
+        workspace_id: Option<String>,
+    },
+    /// A debounced `/api/analyze/auto` run that failed; `error` is the `error` object
+    /// its response would have had (`code`, `message`, `request_id`, ...)
+    AutoAnalyzeFailed {
+        workspace_id: String,
+        status: u16,
+        error: Value,
+    },
+    /// One line of Julia's progress stream, as sent
+    Progress {
+        job_id: Option<String>,
+        progress: Value,
+    },
+    /// The Julia progress stream dropped; the relay retries after `retry_in_ms`
+    ProgressStreamReconnecting {
+        attempt: u32,
+        retry_in_ms: u64,
+    },
+    /// STL bytes received from Julia so far; `percent` is unknown when Julia sent no size
+    ExportProgress {
+        workspace_id: String,
+        percent: Option<u8>,
+        bytes_written: u64,
+    },
+    /// The export is stored and downloadable from `/api/download/{file_id}`
+    ExportComplete {
+        workspace_id: String,
+        file_id: String,
+        file_path: String,
+        size_bytes: u64,
+    },
+    /// The export failed before or during the transfer; no partial file is kept
+    ExportFailed {
+        workspace_id: String,
+        error: String,
+    },
+    /// Sent to one subscriber only, in place of the events it missed
+    Lagged {
+        missed: u64,
+    },
+}
+
+impl ServerEvent {
+    /// `workspace_id` of a compute request body, when the client sent one
+    pub fn workspace_id(payload: &Value) -> Option<String> {
+        payload.get("workspace_id").and_then(Value::as_str).map(str::to_string)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<ServerEvent>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self { sender }
+    }
+
+    /// Fire and forget; without subscribers the event is simply dropped.
+    pub fn publish(&self, event: ServerEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ServerEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(EVENT_BUFFER)
+    }
+}
+
+/// `GET /ws/events` - stream `ServerEvent`s as JSON text frames until the client leaves.
+pub async fn events_ws_handler(ws: WebSocketUpgrade, State(state): State<Arc<AppState>>) -> Response {
+    // Subscribe before the upgrade so nothing published in between is missed
+    let events = state.events.subscribe();
+    ws.on_upgrade(move |socket| relay_events(socket, events))
+}
+
+async fn relay_events(socket: WebSocket, mut events: broadcast::Receiver<ServerEvent>) {
+    let (mut sender, mut receiver) = socket.split();
+    loop {
+        let event = tokio::select! {
+            event = events.recv() => event,
+            // Clients have nothing to say; any close or error ends the subscription
+            msg = receiver.next() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+        let event = match event {
+            Ok(event) => event,
+            Err(RecvError::Lagged(missed)) => ServerEvent::Lagged { missed },
+            Err(RecvError::Closed) => break,
+        };
+        let Ok(text) = serde_json::to_string(&event) else { continue };
+        if sender.send(Message::Text(text)).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Router};
+    use serde_json::json;
+    use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+    async fn serve(state: Arc<AppState>) -> String {
+        let app = Router::new().route("/ws/events", get(events_ws_handler)).with_state(state);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("ws://{}/ws/events", addr)
+    }
+
+    async fn next_json<S>(client: &mut S) -> Value
+    where
+        S: futures::Stream<Item = Result<WsMessage, tokio_tungstenite::tungstenite::Error>> + Unpin,
+    {
+        match client.next().await.unwrap().unwrap() {
+            WsMessage::Text(text) => serde_json::from_str(&text).unwrap(),
+            other => panic!("unexpected frame {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn every_subscriber_gets_published_events() {
+        let state = Arc::new(AppState::for_tests("http://127.0.0.1:1"));
+        let url = serve(state.clone()).await;
+        let (mut first, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        let (mut second, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+
+        state.events.publish(ServerEvent::AnalysisComplete {
+            workspace_id: Some("ws-1".to_string()),
+            metrics: json!({"porosity": 0.8}),
+        });
+        for client in [&mut first, &mut second] {
+            let event = next_json(client).await;
+            assert_eq!(event, json!({"type": "analysis_complete", "workspace_id": "ws-1", "metrics": {"porosity": 0.8}}));
+        }
+    }
+
+    #[tokio::test]
+    async fn lagging_subscriber_is_told_and_kept() {
+        let state = Arc::new(AppState {
+            events: EventBus::new(2),
+            ..AppState::for_tests("http://127.0.0.1:1")
+        });
+        let url = serve(state.clone()).await;
+        let (mut client, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        // Let the server task reach its receive loop
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        // The test runtime is single-threaded: the relay cannot run between these sends
+        for _ in 0..5 {
+            state.events.publish(ServerEvent::MeshComplete { workspace_id: None });
+        }
+
+        let mut frames = Vec::new();
+        while frames.len() < 3 {
+            frames.push(next_json(&mut client).await);
+        }
+        let lagged: Vec<_> = frames.iter().filter(|f| f["type"] == "lagged").collect();
+        assert_eq!(lagged.len(), 1, "{:?}", frames);
+        assert_eq!(lagged[0]["missed"], 3);
+
+        // Still subscribed afterwards
+        state.events.publish(ServerEvent::OptimizationComplete { workspace_id: None, candidates: 4 });
+        let after = next_json(&mut client).await;
+        assert_eq!(after["type"], "optimization_complete");
+    }
+}
@@ -0,0 +1,275 @@
+//! `POST /api/export/stl` - export a workspace's mesh into the export dir.
+//!
+//! Julia streams the STL back, announcing its size in `X-Export-Size`; while the
+//! bytes arrive, `export_progress` events go out on `/ws/events`, then one
+//! `export_complete` or `export_failed`.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{path::Path, sync::Arc};
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+use crate::{error::AppError, events::ServerEvent, julia, AppState};
+
+pub const EXPORT_SIZE_HEADER: &str = "x-export-size";
+/// Without a size from Julia, progress is reported every this many bytes instead
+const PROGRESS_STEP_BYTES: u64 = 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+pub struct ExportRequest {
+    pub workspace_id: String,
+    #[serde(default)]
+    pub quality: Option<String>,
+    #[serde(default)]
+    pub mesh_revision: Option<u32>,
+    #[serde(default)]
+    pub binary: Option<bool>,
+}
+
+/// Decides which chunks are worth an `export_progress` event: one per percentage
+/// point when the total is known, one per [`PROGRESS_STEP_BYTES`] otherwise.
+#[derive(Debug)]
+pub struct ProgressTracker {
+    total: Option<u64>,
+    written: u64,
+    reported: u64,
+}
+
+impl ProgressTracker {
+    pub fn new(total: Option<u64>) -> Self {
+        Self { total: total.filter(|&t| t > 0), written: 0, reported: 0 }
+    }
+
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn percent(&self) -> Option<u8> {
+        self.total.map(|total| (self.written.min(total) * 100 / total) as u8)
+    }
+
+    /// Count `bytes` more; `Some(percent)` when an event is due.
+    pub fn advance(&mut self, bytes: u64) -> Option<Option<u8>> {
+        let before = self.percent();
+        self.written += bytes;
+        let due = match self.total {
+            Some(_) => self.percent() != before,
+            None => self.written - self.reported >= PROGRESS_STEP_BYTES,
+        };
+        if !due {
+            return None;
+        }
+        self.reported = self.written;
+        Some(self.percent())
+    }
+
+    /// `Err` when Julia announced more bytes than it sent
+    pub fn finish(&self) -> Result<(), String> {
+        match self.total {
+            Some(total) if self.written != total => {
+                Err(format!("Julia sent {} of {} bytes", self.written, total))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Write `res`'s body to `path`, publishing progress for `workspace_id`. Returns the size.
+async fn write_export(state: &AppState, workspace_id: &str, res: reqwest::Response, path: &Path) -> Result<u64, String> {
+    let total = res
+        .headers()
+        .get(EXPORT_SIZE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok());
+    let mut tracker = ProgressTracker::new(total);
+    let mut file = tokio::fs::File::create(path).await.map_err(|e| e.to_string())?;
+    let mut body = res.bytes_stream();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| format!("transfer from Julia failed: {}", e))?;
+        file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+        if let Some(percent) = tracker.advance(chunk.len() as u64) {
+            state.events.publish(ServerEvent::ExportProgress {
+                workspace_id: workspace_id.to_string(),
+                percent,
+                bytes_written: tracker.written(),
+            });
+        }
+    }
+    file.flush().await.map_err(|e| e.to_string())?;
+    tracker.finish()?;
+    Ok(tracker.written())
+}
+
+/// `POST /api/export/stl` - `{workspace_id, quality?, mesh_revision?, binary?}` →
+/// `{file_id, file_name, file_path, size_bytes}`, downloadable from `/api/download/{file_id}`.
+pub async fn export_stl_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<ExportRequest>,
+) -> Result<Json<Value>, AppError> {
+    let workspace_id = request.workspace_id;
+    let payload = serde_json::json!({
+        "workspace_id": workspace_id,
+        "quality": request.quality.unwrap_or_else(|| "medium".to_string()),
+        "mesh_revision": request.mesh_revision,
+        "binary": request.binary.unwrap_or(true),
+        "stream": true,
+    });
+    let failed = |error: String| {
+        state.events.publish(ServerEvent::ExportFailed { workspace_id: workspace_id.clone(), error });
+    };
+
+    let _workspace = state.workspace_locks.lock_for(&payload).await;
+    let res = match julia::open_julia_stream(&state, "export/stl", &headers, payload).await {
+        Ok(res) => res,
+        Err(error) => {
+            failed(error.message());
+            return Err(error);
+        }
+    };
+
+    let file_id = Uuid::new_v4().to_string();
+    let file_name = format!("scaffold_{}.stl", workspace_id);
+    let file_path = state.export_dir.join(format!("{}_{}", file_id, file_name));
+    // Not matched by `resolve_stored_file` until it is complete
+    let partial = state.export_dir.join(format!(".{}.part", file_id));
+    let written = match write_export(&state, &workspace_id, res, &partial).await {
+        Ok(size) => tokio::fs::rename(&partial, &file_path).await.map(|_| size).map_err(|e| e.to_string()),
+        Err(e) => Err(e),
+    };
+    let size_bytes = match written {
+        Ok(size) => size,
+        Err(error) => {
+            let _ = tokio::fs::remove_file(&partial).await;
+            failed(error.clone());
+            return Err(AppError::upstream(StatusCode::OK, "export_failed", error).with_detail("source", "julia"));
+        }
+    };
+
+    let file_path = file_path.to_string_lossy().to_string();
+    state.events.publish(ServerEvent::ExportComplete {
+        workspace_id,
+        file_id: file_id.clone(),
+        file_path: file_path.clone(),
+        size_bytes,
+    });
+    Ok(Json(serde_json::json!({
+        "file_id": file_id,
+        "file_name": file_name,
+        "file_path": file_path,
+        "size_bytes": size_bytes,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, response::IntoResponse, routing::post, Router};
+
+    #[test]
+    fn progress_is_reported_per_point_or_per_mebibyte() {
+        let mut tracker = ProgressTracker::new(Some(200));
+        assert_eq!(tracker.advance(1), None);
+        assert_eq!(tracker.advance(1), Some(Some(1)));
+        assert_eq!(tracker.advance(198), Some(Some(100)));
+        assert_eq!(tracker.finish(), Ok(()));
+
+        let mut short = ProgressTracker::new(Some(1000));
+        short.advance(400);
+        assert_eq!(short.finish().unwrap_err(), "Julia sent 400 of 1000 bytes");
+
+        let mut unknown = ProgressTracker::new(None);
+        assert_eq!(unknown.advance(PROGRESS_STEP_BYTES - 1), None);
+        assert_eq!(unknown.advance(1), Some(None));
+        assert_eq!(unknown.advance(10), None);
+        assert_eq!(unknown.finish(), Ok(()));
+    }
+
+    /// A "Julia" streaming `bytes` in 1 KiB chunks while claiming `announced` bytes
+    async fn streaming_julia(bytes: Vec<u8>, announced: usize) -> String {
+        let app = Router::new().route(
+            "/export/stl",
+            post(move |Json(body): Json<Value>| async move {
+                assert_eq!(body["stream"], true);
+                let chunks: Vec<Result<Vec<u8>, std::io::Error>> = bytes.chunks(1024).map(|c| Ok(c.to_vec())).collect();
+                (
+                    [("content-type", "model/stl".to_string()), (EXPORT_SIZE_HEADER, announced.to_string())],
+                    Body::from_stream(futures::stream::iter(chunks)),
+                )
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    async fn export(state: &Arc<AppState>) -> (StatusCode, Value) {
+        let request = ExportRequest { workspace_id: "ws-1".to_string(), quality: None, mesh_revision: None, binary: None };
+        let response = export_stl_handler(State(state.clone()), HeaderMap::new(), Json(request)).await.into_response();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    fn state_for(julia_url: &str) -> Arc<AppState> {
+        let export_dir = std::env::temp_dir().join(format!("darwin-export-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&export_dir).unwrap();
+        Arc::new(AppState { export_dir, ..AppState::for_tests(julia_url) })
+    }
+
+    #[tokio::test]
+    async fn streamed_export_is_stored_with_progress_events() {
+        let stl = vec![7u8; 10 * 1024];
+        let state = state_for(&streaming_julia(stl.clone(), stl.len()).await);
+        let mut events = state.events.subscribe();
+
+        let (status, body) = export(&state).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["size_bytes"], stl.len());
+        assert_eq!(std::fs::read(body["file_path"].as_str().unwrap()).unwrap(), stl);
+
+        let mut percents = Vec::new();
+        loop {
+            match events.recv().await.unwrap() {
+                ServerEvent::ExportProgress { percent, .. } => percents.push(percent.unwrap()),
+                ServerEvent::ExportComplete { file_id, size_bytes, .. } => {
+                    assert_eq!(file_id, body["file_id"]);
+                    assert_eq!(size_bytes, stl.len() as u64);
+                    break;
+                }
+                other => panic!("unexpected event {:?}", other),
+            }
+        }
+        // Chunks may be split or merged on the way, but progress only moves forward
+        assert!(percents.windows(2).all(|w| w[0] < w[1]), "{:?}", percents);
+        assert_eq!(percents.last(), Some(&100));
+        std::fs::remove_dir_all(&state.export_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn truncated_export_fails_and_leaves_nothing_behind() {
+        let state = state_for(&streaming_julia(vec![7u8; 4096], 8192).await);
+        let mut events = state.events.subscribe();
+
+        let (status, body) = export(&state).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(body["error"]["message"], "Julia sent 4096 of 8192 bytes");
+        loop {
+            if let ServerEvent::ExportFailed { workspace_id, error } = events.recv().await.unwrap() {
+                assert_eq!(workspace_id, "ws-1");
+                assert_eq!(error, "Julia sent 4096 of 8192 bytes");
+                break;
+            }
+        }
+        assert_eq!(std::fs::read_dir(&state.export_dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(&state.export_dir).unwrap();
+    }
+}
@@ -0,0 +1,181 @@
+//! Upload format detection from magic bytes (STL, TIFF, DICOM, NIfTI and the 2D
+//! image formats), so a stored file is what its name claims before anything
+//! downstream opens it.
+//!
+//! Headerless `.raw` volumes have nothing to sniff and are taken on their name.
+
+use flate2::read::GzDecoder;
+use serde::Serialize;
+use std::{fs::File, io::Read, path::Path};
+
+/// Enough for the DICOM preamble, a NIfTI-2 header and an ASCII STL's first facet
+const SNIFF_BYTES: u64 = 1024;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const PNG_MAGIC: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Binary STL: 80-byte header, u32 triangle count, 50 bytes per triangle
+const STL_HEADER_BYTES: u64 = 84;
+const STL_TRIANGLE_BYTES: u64 = 50;
+
+/// Recorded as `format` in the upload response so handlers can route on content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileFormat {
+    Stl,
+    Tiff,
+    Dicom,
+    Nifti,
+    Png,
+    Bmp,
+    Jpeg,
+    Raw,
+}
+
+/// Sniff `head`, the first bytes of a (decompressed) file that is `len` bytes long.
+pub fn sniff(head: &[u8], len: u64) -> Option<FileFormat> {
+    let at = |offset: usize, magic: &[u8]| head.get(offset..offset + magic.len()) == Some(magic);
+
+    if [b"II*\0", b"MM\0*", b"II+\0", b"MM\0+"].iter().any(|magic| at(0, *magic)) {
+        return Some(FileFormat::Tiff);
+    }
+    if at(128, b"DICM") {
+        return Some(FileFormat::Dicom);
+    }
+    if is_nifti(head) {
+        return Some(FileFormat::Nifti);
+    }
+    if at(0, PNG_MAGIC) {
+        return Some(FileFormat::Png);
+    }
+    if at(0, &[0xff, 0xd8, 0xff]) {
+        return Some(FileFormat::Jpeg);
+    }
+    // "BM" then the file size, little-endian
+    if at(0, b"BM") && head.get(2..6).map(|size| u32::from_le_bytes(size.try_into().unwrap()) as u64) == Some(len) {
+        return Some(FileFormat::Bmp);
+    }
+    if is_binary_stl(head, len) || is_ascii_stl(head) {
+        return Some(FileFormat::Stl);
+    }
+    None
+}
+
+/// NIfTI-1 (`n+1`/`ni1` at 344) or NIfTI-2 (`n+2`/`ni2` at 4), either endianness.
+fn is_nifti(head: &[u8]) -> bool {
+    let Some(raw) = head.get(..4) else { return false };
+    let raw: [u8; 4] = raw.try_into().unwrap();
+    let sizeof_hdr = [i32::from_le_bytes(raw), i32::from_be_bytes(raw)];
+    let magic = |offset: usize, magics: [&[u8]; 2]| magics.iter().any(|m| head.get(offset..offset + 4) == Some(*m));
+    (sizeof_hdr.contains(&348) && magic(344, [b"n+1\0", b"ni1\0"]))
+        || (sizeof_hdr.contains(&540) && magic(4, [b"n+2\0", b"ni2\0"]))
+}
+
+/// Binary STL has no magic; its triangle count must account for the whole file.
+fn is_binary_stl(head: &[u8], len: u64) -> bool {
+    let Some(count) = head.get(80..84) else { return false };
+    let triangles = u32::from_le_bytes(count.try_into().unwrap()) as u64;
+    len == STL_HEADER_BYTES + STL_TRIANGLE_BYTES * triangles
+}
+
+/// `solid name` followed by a facet (or an empty `endsolid`), all text.
+fn is_ascii_stl(head: &[u8]) -> bool {
+    let Ok(text) = std::str::from_utf8(head) else {
+        // The head may end mid-character; everything before it must be text
+        return std::str::from_utf8(&head[..head.len().saturating_sub(3)]).is_ok_and(looks_like_ascii_stl);
+    };
+    looks_like_ascii_stl(text)
+}
+
+fn looks_like_ascii_stl(text: &str) -> bool {
+    let text = text.trim_start().to_ascii_lowercase();
+    text.starts_with("solid") && (text.contains("facet") || text.contains("endsolid"))
+}
+
+/// Sniff a stored file. Gzipped files (`.nii.gz`) are sniffed on their contents.
+pub fn sniff_file(path: &Path, file_name: &str) -> std::io::Result<Option<FileFormat>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut head = Vec::new();
+    (&mut file).take(SNIFF_BYTES).read_to_end(&mut head)?;
+
+    let format = if head.starts_with(&GZIP_MAGIC) {
+        let mut inner = Vec::new();
+        // A truncated stream still yields its first bytes
+        let _ = GzDecoder::new(File::open(path)?).take(SNIFF_BYTES).read_to_end(&mut inner);
+        // The decompressed length is unknown, so binary STL cannot be told apart here
+        sniff(&inner, 0)
+    } else {
+        sniff(&head, len)
+    };
+    Ok(format.or_else(|| file_name.to_ascii_lowercase().ends_with(".raw").then_some(FileFormat::Raw)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binary_stl(triangles: u32) -> Vec<u8> {
+        let mut stl = vec![0u8; 80];
+        stl.extend_from_slice(&triangles.to_le_bytes());
+        stl.resize(84 + 50 * triangles as usize, 0);
+        stl
+    }
+
+    #[test]
+    fn recognises_each_format_by_its_magic() {
+        let fixture = |name: &str| std::fs::read(format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap();
+        let ascii_stl = b"solid cube\n  facet normal 0 0 1\n    outer loop\n".to_vec();
+        let cases = [
+            (b"II*\0rest".to_vec(), FileFormat::Tiff),
+            (b"MM\0*rest".to_vec(), FileFormat::Tiff),
+            (fixture("slice_explicit.dcm"), FileFormat::Dicom),
+            (fixture("header_um.nii"), FileFormat::Nifti),
+            (PNG_MAGIC.to_vec(), FileFormat::Png),
+            (vec![0xff, 0xd8, 0xff, 0xe0], FileFormat::Jpeg),
+            (ascii_stl, FileFormat::Stl),
+            (binary_stl(3), FileFormat::Stl),
+        ];
+        for (bytes, format) in cases {
+            assert_eq!(sniff(&bytes, bytes.len() as u64), Some(format));
+        }
+
+        let mut bmp = b"BM".to_vec();
+        bmp.extend_from_slice(&10u32.to_le_bytes());
+        bmp.extend_from_slice(&[0; 4]);
+        assert_eq!(sniff(&bmp, 10), Some(FileFormat::Bmp));
+    }
+
+    #[test]
+    fn rejects_lookalikes() {
+        // A binary STL whose count does not match its length, and an ASCII one without facets
+        let mut stl = binary_stl(3);
+        stl.push(0);
+        assert_eq!(sniff(&stl, stl.len() as u64), None);
+        assert_eq!(sniff(b"solid but not a mesh", 20), None);
+        // NIfTI size without the magic, and a DICOM preamble without `DICM`
+        let mut nifti = std::fs::read(format!("{}/tests/fixtures/header_um.nii", env!("CARGO_MANIFEST_DIR"))).unwrap();
+        nifti[344] = b'x';
+        assert_eq!(sniff(&nifti, nifti.len() as u64), None);
+        assert_eq!(sniff(&[0; 132], 132), None);
+        assert_eq!(sniff(b"MZ\x90\0", 4), None);
+    }
+
+    #[test]
+    fn sniffs_gzipped_contents_and_trusts_raw_names() {
+        let dir = std::env::temp_dir().join(format!("darwin_sniff_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let nifti = std::fs::read(format!("{}/tests/fixtures/header_um.nii", env!("CARGO_MANIFEST_DIR"))).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, &nifti).unwrap();
+        let gz = dir.join("scan.nii.gz");
+        std::fs::write(&gz, encoder.finish().unwrap()).unwrap();
+        assert_eq!(sniff_file(&gz, "scan.nii.gz").unwrap(), Some(FileFormat::Nifti));
+
+        let raw = dir.join("volume.raw");
+        std::fs::write(&raw, [7u8; 64]).unwrap();
+        assert_eq!(sniff_file(&raw, "volume.raw").unwrap(), Some(FileFormat::Raw));
+        assert_eq!(sniff_file(&raw, "volume.tif").unwrap(), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,83 @@
+//! Static frontend assets, with a built-in notice when they are not deployed.
+
+use axum::{
+    http::StatusCode,
+    response::{Html, IntoResponse},
+    Router,
+};
+use std::path::Path;
+use tower_http::services::ServeDir;
+
+/// Serves `static_dir` at `/`. When it has no `index.html` (a standalone API
+/// deployment, or a wrong `DARWIN_STATIC_DIR`) every non-API route answers with
+/// a short page saying so instead of an empty 404.
+pub fn router(static_dir: &Path) -> Router {
+    if static_dir.join("index.html").is_file() {
+        return Router::new().nest_service("/", ServeDir::new(static_dir));
+    }
+
+    tracing::warn!(
+        "no index.html in {}, serving the API without a frontend (set DARWIN_STATIC_DIR)",
+        static_dir.display()
+    );
+    let page = missing_frontend_page(static_dir);
+    Router::new().fallback(move || async move { (StatusCode::NOT_FOUND, Html(page)).into_response() })
+}
+
+fn missing_frontend_page(static_dir: &Path) -> String {
+    let dir = static_dir
+        .display()
+        .to_string()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Darwin Server</title></head>
+<body style="font-family: sans-serif; max-width: 40rem; margin: 3rem auto; line-height: 1.5">
+<h1>Darwin Server is running</h1>
+<p>The API is up, but the frontend assets were not found: <code>{dir}/index.html</code> does not exist.</p>
+<p>Point <code>DARWIN_STATIC_DIR</code> at the directory holding the frontend build and restart the server.</p>
+<ul>
+<li><a href="/api/health">/api/health</a> - liveness</li>
+<li><a href="/api/version">/api/version</a> - build information</li>
+</ul>
+</body>
+</html>
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    async fn get(app: Router, path: &str) -> (StatusCode, String) {
+        let response = app.oneshot(Request::get(path).body(Body::empty()).unwrap()).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(bytes.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn serves_assets_or_explains_they_are_missing() {
+        let dir = std::env::temp_dir().join(format!("darwin-static-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let (status, body) = get(router(&dir), "/").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(body.contains("frontend assets were not found"));
+        assert!(body.contains(r#"href="/api/health""#));
+        let (status, body) = get(router(&dir), "/editor.html").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(body.contains(&format!("{}/index.html", dir.display())));
+
+        std::fs::write(dir.join("index.html"), "<h1>studio</h1>").unwrap();
+        assert_eq!(get(router(&dir), "/").await, (StatusCode::OK, "<h1>studio</h1>".to_string()));
+        assert_eq!(get(router(&dir), "/missing.js").await.0, StatusCode::NOT_FOUND);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
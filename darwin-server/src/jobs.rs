@@ -0,0 +1,423 @@
+//! Background jobs for analyses and optimizations that take longer than a client
+//! (or a proxy in front of us) is willing to hold a request open.
+//!
+//! `POST /api/jobs` queues `{kind, payload}` and answers `202` right away; a fixed
+//! pool of workers (`DARWIN_JOB_WORKERS`, default [`DEFAULT_WORKERS`]) runs queued
+//! jobs in order through the same code as `/api/analyze` and `/api/optimize`,
+//! each taking a Julia permit like those routes do. `GET /api/jobs/:id` reports the
+//! status and, once the job is done, its result or the error the synchronous route
+//! would have answered. Finished jobs are dropped from memory [`RETENTION`] later
+//! by the TTL sweeper.
+//!
+//! Every change to a job is also saved to the database ([`crate::db`]), which keeps
+//! the history: `GET /api/jobs/:id` answers from there for jobs no longer in
+//! memory, and jobs a restart cut short are reported as failed (`interrupted`).
+
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::{analysis, db::{now_ms, Db}, error::AppError, optimization, AppState};
+
+pub const DEFAULT_WORKERS: usize = 2;
+/// Jobs waiting for a worker beyond this are refused with `503`
+pub const MAX_QUEUED_JOBS: usize = 256;
+/// How long a finished job's result stays available
+pub const RETENTION: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    Analyze,
+    Optimize,
+}
+
+impl JobKind {
+    /// The serde name, as stored in the `jobs.kind` column
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Analyze => "analyze",
+            Self::Optimize => "optimize",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl JobStatus {
+    /// The serde name, as stored in the `jobs.status` column
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Succeeded => "succeeded",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// A job as `GET /api/jobs/:id` reports it. Times are Unix milliseconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub job_id: String,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    pub submitted_at_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at_ms: Option<u64>,
+    /// What `/api/analyze` or `/api/optimize` would have answered
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    /// The `error` object those routes would have answered, plus its HTTP `status`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Value>,
+    #[serde(skip)]
+    finished: Option<Instant>,
+}
+
+/// Body of `POST /api/jobs`.
+#[derive(Debug, Deserialize)]
+pub struct JobRequest {
+    pub kind: JobKind,
+    /// The body `/api/analyze` or `/api/optimize` would get
+    pub payload: Value,
+}
+
+/// What a worker needs to run a job. The submitter's headers are kept so the
+/// configured ones are forwarded to Julia as for a synchronous request.
+struct QueuedJob {
+    id: Uuid,
+    kind: JobKind,
+    payload: Value,
+    headers: HeaderMap,
+}
+
+/// The job table and the queue feeding the workers.
+#[derive(Clone)]
+pub struct Jobs {
+    jobs: Arc<Mutex<HashMap<Uuid, Job>>>,
+    queue: mpsc::Sender<QueuedJob>,
+    /// Shared by the workers; whichever is idle takes the next job
+    receiver: Arc<tokio::sync::Mutex<mpsc::Receiver<QueuedJob>>>,
+    workers: usize,
+}
+
+impl Default for Jobs {
+    fn default() -> Self {
+        Self::new(DEFAULT_WORKERS, MAX_QUEUED_JOBS)
+    }
+}
+
+impl Jobs {
+    pub fn new(workers: usize, max_queued: usize) -> Self {
+        let (queue, receiver) = mpsc::channel(max_queued.max(1));
+        Self {
+            jobs: Arc::default(),
+            queue,
+            receiver: Arc::new(tokio::sync::Mutex::new(receiver)),
+            workers: workers.max(1),
+        }
+    }
+
+    /// Sized from `DARWIN_JOB_WORKERS`.
+    pub fn from_env() -> Self {
+        let workers = std::env::var("DARWIN_JOB_WORKERS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_WORKERS);
+        Self::new(workers, MAX_QUEUED_JOBS)
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        let id = Uuid::parse_str(id).ok()?;
+        self.jobs.lock().unwrap().get(&id).cloned()
+    }
+
+    /// Queue a job; `503 queue_full` when [`MAX_QUEUED_JOBS`] are already waiting.
+    async fn submit(&self, db: &Db, kind: JobKind, payload: Value, headers: HeaderMap) -> Result<Job, AppError> {
+        // Hold a slot while the job is saved, so no worker can save it as running first
+        let Ok(slot) = self.queue.try_reserve() else {
+            return Err(AppError::Proxy {
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                code: "queue_full",
+                message: format!("{} jobs are already queued, try again later", MAX_QUEUED_JOBS),
+            });
+        };
+        let id = Uuid::new_v4();
+        let job = Job {
+            job_id: id.to_string(),
+            kind,
+            status: JobStatus::Queued,
+            submitted_at_ms: now_ms(),
+            started_at_ms: None,
+            finished_at_ms: None,
+            result: None,
+            error: None,
+            finished: None,
+        };
+        // In the table before a worker can pick it up
+        self.jobs.lock().unwrap().insert(id, job.clone());
+        save(db, &job).await;
+        slot.send(QueuedJob { id, kind, payload, headers });
+        Ok(job)
+    }
+
+    /// Change a job in the table, returning it as changed.
+    fn update(&self, id: Uuid, change: impl FnOnce(&mut Job)) -> Option<Job> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.get_mut(&id)?;
+        change(job);
+        Some(job.clone())
+    }
+
+    fn finish(&self, id: Uuid, outcome: Result<Value, AppError>) -> Option<Job> {
+        self.update(id, |job| {
+            job.finished_at_ms = Some(now_ms());
+            job.finished = Some(Instant::now());
+            match outcome {
+                Ok(result) => {
+                    job.status = JobStatus::Succeeded;
+                    job.result = Some(result);
+                }
+                Err(error) => {
+                    job.status = JobStatus::Failed;
+                    let mut body = error.body()["error"].take();
+                    body["status"] = error.status().as_u16().into();
+                    job.error = Some(body);
+                }
+            }
+        })
+    }
+
+    /// Drop jobs that finished more than `retention` ago.
+    pub fn sweep_finished(&self, retention: Duration) {
+        self.jobs
+            .lock()
+            .unwrap()
+            .retain(|_, job| job.finished.is_none_or(|finished| finished.elapsed() < retention));
+    }
+
+    /// Start the worker pool; call once, with the state holding these jobs.
+    pub fn spawn_workers(&self, state: Arc<AppState>) {
+        for _ in 0..self.workers {
+            let state = state.clone();
+            let receiver = self.receiver.clone();
+            tokio::spawn(async move {
+                loop {
+                    let next = receiver.lock().await.recv().await;
+                    let Some(job) = next else { return };
+                    run(&state, job).await;
+                }
+            });
+        }
+    }
+}
+
+/// Write a job through to the database. A failed write only costs its history.
+async fn save(db: &Db, job: &Job) {
+    if let Err(e) = db.save_job(job).await {
+        tracing::warn!(job_id = %job.job_id, "failed to save job: {}", e);
+    }
+}
+
+async fn run(state: &AppState, job: QueuedJob) {
+    let _permit = state.julia_limiter.acquire_queued().await;
+    let running = state.jobs.update(job.id, |j| {
+        j.status = JobStatus::Running;
+        j.started_at_ms = Some(now_ms());
+    });
+    if let Some(running) = running {
+        save(&state.db, &running).await;
+    }
+    let outcome = match job.kind {
+        JobKind::Analyze => analysis::analyze_and_notify(state, &job.headers, job.payload).await,
+        JobKind::Optimize => optimization::optimize(state, &job.headers, job.payload).await,
+    };
+    if let Err(error) = &outcome {
+        tracing::warn!(job_id = %job.id, code = error.code(), "job failed: {}", error.message());
+    }
+    if let Some(finished) = state.jobs.finish(job.id, outcome) {
+        save(&state.db, &finished).await;
+    }
+}
+
+/// `POST /api/jobs` - queue an analysis or optimization, answering `202` with the
+/// queued job and its status URL in `Location`.
+///
+/// Optimization payloads are validated up front, so a malformed one is a `400`
+/// here rather than a failed job.
+pub async fn submit_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<JobRequest>,
+) -> Result<Response, AppError> {
+    if !request.payload.is_object() {
+        return Err(AppError::validation("`payload` must be a JSON object"));
+    }
+    if request.kind == JobKind::Optimize {
+        optimization::parse_request(&request.payload)?;
+    }
+    let job = state.jobs.submit(&state.db, request.kind, request.payload, headers).await?;
+    let location = format!("/api/jobs/{}", job.job_id);
+    Ok((StatusCode::ACCEPTED, [(header::LOCATION, location)], Json(job)).into_response())
+}
+
+/// `GET /api/jobs/:id` - status of a job, with its result or error once finished.
+/// Jobs swept from memory, or from before a restart, come from the database.
+pub async fn status_handler(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> Result<Json<Job>, AppError> {
+    if let Some(job) = state.jobs.get(&id) {
+        return Ok(Json(job));
+    }
+    state
+        .db
+        .job(&id)
+        .await
+        .map_err(AppError::internal)?
+        .map(Json)
+        .ok_or_else(|| AppError::not_found(format!("no job with id {}", id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, routing::{get, post}, Router};
+    use serde_json::json;
+    use tower::ServiceExt;
+    use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+
+    async fn send(app: &Router, request: Request<Body>) -> (StatusCode, HeaderMap, Value) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let (status, headers) = (response.status(), response.headers().clone());
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, headers, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+    }
+
+    fn submit(body: Value) -> Request<Body> {
+        Request::post("/api/jobs")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    /// Poll until the job is no longer queued or running
+    async fn finished(app: &Router, location: &str) -> Value {
+        for _ in 0..200 {
+            let (_, _, job) = send(app, Request::get(location).body(Body::empty()).unwrap()).await;
+            if job["status"] == "succeeded" || job["status"] == "failed" {
+                return job;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("job at {} never finished", location);
+    }
+
+    fn app(julia: &MockServer) -> (Router, Arc<AppState>) {
+        let state = Arc::new(AppState::for_tests(&julia.uri()));
+        state.jobs.spawn_workers(state.clone());
+        let app = Router::new()
+            .route("/api/jobs", post(submit_handler))
+            .route("/api/jobs/:id", get(status_handler))
+            .with_state(state.clone());
+        (app, state)
+    }
+
+    #[tokio::test]
+    async fn analyses_run_in_the_background_and_are_polled() {
+        let julia = MockServer::start().await;
+        Mock::given(matchers::path("/analyze"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"porosity": 0.82}))
+                    .set_delay(Duration::from_millis(100)),
+            )
+            .mount(&julia)
+            .await;
+        let (app, state) = app(&julia);
+
+        let (status, headers, job) = send(&app, submit(json!({"kind": "analyze", "payload": {"file_path": "/data/scan.tif", "voxel_size": 10.0}}))).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(job["status"], "queued");
+        let location = headers[header::LOCATION].to_str().unwrap().to_string();
+        assert_eq!(location, format!("/api/jobs/{}", job["job_id"].as_str().unwrap()));
+
+        let job = finished(&app, &location).await;
+        assert_eq!(job["status"], "succeeded");
+        assert_eq!(job["kind"], "analyze");
+        assert_eq!(job["result"]["porosity"], 0.82);
+        assert!(job["finished_at_ms"].as_u64().unwrap() >= job["started_at_ms"].as_u64().unwrap());
+
+        // Swept from memory, it is still answered from the database
+        state.jobs.sweep_finished(Duration::ZERO);
+        let (status, _, stored) = send(&app, Request::get(&location).body(Body::empty()).unwrap()).await;
+        assert_eq!((status, stored), (StatusCode::OK, job));
+
+        let (status, _, body) = send(&app, Request::get(format!("/api/jobs/{}", Uuid::new_v4())).body(Body::empty()).unwrap()).await;
+        assert_eq!((status, body["error"]["code"].as_str()), (StatusCode::NOT_FOUND, Some("not_found")));
+    }
+
+    #[tokio::test]
+    async fn failures_are_reported_on_the_job() {
+        let julia = MockServer::start().await;
+        Mock::given(matchers::path("/optimize"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"status": "success"})))
+            .mount(&julia)
+            .await;
+        let (app, _) = app(&julia);
+
+        let optimize = json!({
+            "porosity": 0.9, "pore_size": 150.0, "method": "freeze-casting", "resolution": 10.0,
+            "objectives": [{"metric": "porosity", "direction": "maximize"}],
+        });
+        let (status, headers, _) = send(&app, submit(json!({"kind": "optimize", "payload": optimize}))).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let job = finished(&app, headers[header::LOCATION].to_str().unwrap()).await;
+        assert_eq!(job["status"], "failed");
+        assert_eq!(job["error"]["code"], "no_candidates");
+        assert_eq!(job["error"]["status"], 502);
+
+        // Malformed work is refused before it is queued
+        let (status, _, body) = send(&app, submit(json!({"kind": "optimize", "payload": {"porosity": 0.9}}))).await;
+        assert_eq!((status, body["error"]["code"].as_str()), (StatusCode::BAD_REQUEST, Some("invalid_request")));
+        let (status, _, _) = send(&app, submit(json!({"kind": "mesh", "payload": {}}))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn a_full_queue_refuses_and_finished_jobs_expire() {
+        let jobs = Jobs::new(1, 1);
+        let db = AppState::for_tests("http://127.0.0.1:1").db;
+        let first = jobs.submit(&db, JobKind::Analyze, json!({}), HeaderMap::new()).await.unwrap();
+        let refused = jobs.submit(&db, JobKind::Analyze, json!({}), HeaderMap::new()).await.unwrap_err();
+        assert_eq!((refused.status(), refused.code()), (StatusCode::SERVICE_UNAVAILABLE, "queue_full"));
+        assert_eq!(jobs.jobs.lock().unwrap().len(), 1);
+
+        let id = Uuid::parse_str(&first.job_id).unwrap();
+        jobs.finish(id, Ok(json!({"porosity": 0.8})));
+        jobs.sweep_finished(RETENTION);
+        assert_eq!(jobs.get(&first.job_id).unwrap().status, JobStatus::Succeeded);
+        jobs.sweep_finished(Duration::ZERO);
+        assert!(jobs.get(&first.job_id).is_none());
+        // Queued is as far as the database saw it go
+        assert_eq!(db.job(&first.job_id).await.unwrap().unwrap().status, JobStatus::Queued);
+    }
+}
@@ -0,0 +1,600 @@
+use axum::{
+    body::{Body, Bytes},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use futures::StreamExt;
+use serde_json::Value;
+
+use crate::{error::AppError, logging::REQUEST_ID_HEADER, observability, AppState};
+
+pub const DEFAULT_ERROR_KEY: &str = "error";
+
+/// Sent on every Julia request so its logs show which server build called it.
+pub const SERVER_VERSION_HEADER: HeaderName = HeaderName::from_static("x-darwin-server-version");
+
+/// Connection-level headers (RFC 9110 §7.6.1) plus ones reqwest sets itself; never forwarded.
+pub const UNFORWARDABLE_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "host",
+    "content-length",
+    "content-type",
+];
+
+/// How much of a non-JSON upstream body is echoed back for diagnostics.
+const BODY_EXCERPT_BYTES: usize = 2048;
+
+/// Default for `DARWIN_MAX_JULIA_RESPONSE_BYTES`: replies are buffered and parsed up to this size.
+pub const DEFAULT_MAX_JULIA_RESPONSE_BYTES: usize = 64 * 1024 * 1024;
+
+/// [`proxy_to_julia`] starts streaming a successful reply once it outgrows this.
+const STREAM_THRESHOLD_BYTES: usize = 1024 * 1024;
+
+/// A parsed Julia reply, with failures reported inside a `200` body told apart from success.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JuliaResponse {
+    Success { status: StatusCode, body: Value },
+    /// Non-2xx status, or a 2xx body carrying a non-null top-level `error_key`
+    Failure { status: StatusCode, message: String, body: Value },
+}
+
+impl JuliaResponse {
+    /// The body of a successful reply; a failure becomes [`AppError::Upstream`].
+    pub fn into_body(self) -> Result<Value, AppError> {
+        match self {
+            Self::Success { body, .. } => Ok(body),
+            Self::Failure { status, message, body } => Err(AppError::upstream(status, "julia_error", message)
+                .with_detail("source", "julia")
+                .with_detail("julia_body", body)),
+        }
+    }
+
+    pub fn classify(status: StatusCode, body: Value, error_key: &str) -> Self {
+        let message = match body.get(error_key) {
+            None | Some(Value::Null) => None,
+            Some(Value::String(s)) => Some(s.clone()),
+            Some(other) => Some(other.to_string()),
+        };
+
+        match message {
+            None if status.is_success() => Self::Success { status, body },
+            None => Self::Failure {
+                status,
+                message: format!("Julia returned {}", status),
+                body,
+            },
+            Some(message) => Self::Failure { status, message, body },
+        }
+    }
+}
+
+impl IntoResponse for JuliaResponse {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Success { status, body } => (status, Json(body)).into_response(),
+            // Julia's own error statuses pass through; an error hidden in a 2xx becomes 502
+            failure => failure.into_body().map(Json).into_response(),
+        }
+    }
+}
+
+/// Key Julia uses for error messages in JSON bodies, from `DARWIN_JULIA_ERROR_KEY`
+/// (`error` by default; some handlers use `detail`).
+pub fn error_key_from_env() -> String {
+    std::env::var("DARWIN_JULIA_ERROR_KEY")
+        .ok()
+        .filter(|key| !key.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_ERROR_KEY.to_string())
+}
+
+/// First `max_bytes` of `body` as text, cut on a char boundary.
+fn body_excerpt(body: &[u8], max_bytes: usize) -> String {
+    let text = String::from_utf8_lossy(body);
+    if text.len() <= max_bytes {
+        return text.into_owned();
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &text[..end])
+}
+
+/// A reply that isn't JSON (e.g. an HTML error page from Julia or a proxy in front of it).
+fn non_json_upstream(status: reqwest::StatusCode, body: &[u8]) -> AppError {
+    AppError::upstream(StatusCode::BAD_GATEWAY, "non_json_upstream", format!("Julia replied {} without a JSON body", status))
+        .with_detail("source", "julia")
+        .with_detail("upstream_status", status.as_u16())
+        .with_detail("body_excerpt", body_excerpt(body, BODY_EXCERPT_BYTES))
+}
+
+fn julia_timeout(timeout: std::time::Duration) -> AppError {
+    AppError::Proxy {
+        status: StatusCode::GATEWAY_TIMEOUT,
+        code: "julia_timeout",
+        message: format!("Julia did not respond within {}s", timeout.as_secs()),
+    }
+}
+
+/// Headers for the outgoing Julia request: the `DARWIN_FORWARD_HEADERS` present on
+/// `incoming`, its `X-Request-Id`, and `X-Darwin-Server-Version`.
+pub fn forwarded_headers(state: &AppState, incoming: &HeaderMap) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for name in &state.config.forward_headers {
+        for value in incoming.get_all(name) {
+            headers.append(name.clone(), value.clone());
+        }
+    }
+    if let Some(request_id) = incoming.get(&REQUEST_ID_HEADER) {
+        headers.insert(REQUEST_ID_HEADER, request_id.clone());
+    }
+    headers.insert(SERVER_VERSION_HEADER, HeaderValue::from_static(env!("CARGO_PKG_VERSION")));
+    headers
+}
+
+/// Armed while a Julia call is in flight.
+///
+/// When the client disconnects, axum drops the handler future and with it the
+/// reqwest one, which closes the connection to Julia; that is the cancellation, as
+/// Julia has no job registry to cancel through. Dropped before [`Self::finish`],
+/// this counts the call as `cancelled`.
+struct InFlight {
+    endpoint: String,
+    finished: bool,
+}
+
+impl InFlight {
+    fn new(endpoint: &str) -> Self {
+        Self { endpoint: endpoint.to_string(), finished: false }
+    }
+
+    fn finish(mut self) {
+        self.finished = true;
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        observability::record_julia_outcome(&self.endpoint, "cancelled");
+        tracing::info!(endpoint = %self.endpoint, "client disconnected; Julia request cancelled");
+    }
+}
+
+/// POST `payload` to `{julia_url}/{endpoint}` and classify the reply. `incoming` are the
+/// headers of the client request, filtered through [`forwarded_headers`].
+///
+/// `Err` is a transport failure or a reply that could not be classified. Dropping the
+/// future cancels the Julia request (see [`InFlight`]).
+pub async fn fetch_julia(
+    state: &AppState,
+    endpoint: &str,
+    incoming: &HeaderMap,
+    payload: Value,
+) -> Result<JuliaResponse, AppError> {
+    let in_flight = InFlight::new(endpoint);
+    let (outcome, result) = send_to_julia(state, endpoint, incoming, payload).await;
+    in_flight.finish();
+    observability::record_julia_outcome(endpoint, outcome);
+    result
+}
+
+/// [`fetch_julia`] without the bookkeeping; also names the outcome for `julia_requests_total`.
+async fn send_to_julia(
+    state: &AppState,
+    endpoint: &str,
+    incoming: &HeaderMap,
+    payload: Value,
+) -> (&'static str, Result<JuliaResponse, AppError>) {
+    let res = match post_to_julia(state, endpoint, incoming, payload).await {
+        Ok(res) => res,
+        Err(failure) => return failure,
+    };
+    let upstream_status = res.status();
+    let is_json = is_json(&res);
+    match read_capped(res, state.config.max_julia_response_bytes, state.config.julia_timeout).await {
+        Ok(bytes) => classify_body(state, upstream_status, is_json, &bytes),
+        Err(failure) => failure,
+    }
+}
+
+type JuliaFailure = (&'static str, Result<JuliaResponse, AppError>);
+
+async fn post_to_julia(
+    state: &AppState,
+    endpoint: &str,
+    incoming: &HeaderMap,
+    payload: Value,
+) -> Result<reqwest::Response, JuliaFailure> {
+    let client = reqwest::Client::new();
+    let url = format!("{}/{}", state.julia_url, endpoint);
+
+    let timeout = state.config.julia_timeout;
+    let request = client.post(&url).headers(forwarded_headers(state, incoming)).json(&payload).timeout(timeout);
+    match request.send().await {
+        Ok(res) => Ok(res),
+        Err(e) => Err(transport_failure(e, timeout)),
+    }
+}
+
+fn transport_failure(e: reqwest::Error, timeout: std::time::Duration) -> JuliaFailure {
+    if e.is_timeout() {
+        return ("timeout", Err(julia_timeout(timeout)));
+    }
+    let error = AppError::Proxy { status: StatusCode::BAD_GATEWAY, code: "julia_unreachable", message: e.to_string() };
+    ("transport_error", Err(error))
+}
+
+fn is_json(res: &reqwest::Response) -> bool {
+    res.headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.split(';').next().unwrap_or("").trim().to_ascii_lowercase().ends_with("json"))
+}
+
+/// A reply bigger than `DARWIN_MAX_JULIA_RESPONSE_BYTES`, refused before it was buffered.
+fn upstream_too_large(status: reqwest::StatusCode, limit: usize) -> AppError {
+    let message = format!("Julia's reply exceeds the {} byte limit", limit);
+    AppError::upstream(StatusCode::PAYLOAD_TOO_LARGE, "upstream_too_large", message)
+        .with_detail("source", "julia")
+        .with_detail("upstream_status", status.as_u16())
+        .with_detail("limit_bytes", limit)
+}
+
+/// `res`'s body, giving up as soon as it would exceed `limit` (straight away when
+/// `Content-Length` already says so).
+async fn read_capped(
+    res: reqwest::Response,
+    limit: usize,
+    timeout: std::time::Duration,
+) -> Result<Vec<u8>, JuliaFailure> {
+    let status = res.status();
+    let too_large = || ("too_large", Err(upstream_too_large(status, limit)));
+    if res.content_length().is_some_and(|len| len > limit as u64) {
+        return Err(too_large());
+    }
+    let mut buffer = Vec::new();
+    let mut body = res.bytes_stream();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| transport_failure(e, timeout))?;
+        if buffer.len() + chunk.len() > limit {
+            return Err(too_large());
+        }
+        buffer.extend_from_slice(&chunk);
+    }
+    Ok(buffer)
+}
+
+fn classify_body(state: &AppState, upstream_status: reqwest::StatusCode, is_json: bool, bytes: &[u8]) -> JuliaFailure {
+    // A JSON content type with an unparsable body is just as opaque as an HTML page
+    match serde_json::from_slice::<Value>(bytes) {
+        Ok(body) if is_json => {
+            let status = StatusCode::from_u16(upstream_status.as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+            match JuliaResponse::classify(status, body, &state.julia_error_key) {
+                reply @ JuliaResponse::Success { .. } => ("success", Ok(reply)),
+                reply => ("julia_error", Ok(reply)),
+            }
+        }
+        _ => ("non_json", Err(non_json_upstream(upstream_status, bytes))),
+    }
+}
+
+/// POST `payload` to `{julia_url}/{endpoint}` and relay the reply.
+///
+/// Built for replies that may be huge (mesh data): successful JSON replies up to
+/// [`STREAM_THRESHOLD_BYTES`] are parsed and classified like [`fetch_julia`]'s, but
+/// past that the bytes go to the client as they arrive, never parsed or held in
+/// full. Anything else is read within `DARWIN_MAX_JULIA_RESPONSE_BYTES`.
+pub async fn proxy_to_julia(state: &AppState, endpoint: &str, incoming: &HeaderMap, payload: Value) -> Response {
+    let in_flight = InFlight::new(endpoint);
+    let (outcome, response) = relay(state, endpoint, incoming, payload).await;
+    in_flight.finish();
+    observability::record_julia_outcome(endpoint, outcome);
+    response
+}
+
+async fn relay(state: &AppState, endpoint: &str, incoming: &HeaderMap, payload: Value) -> (&'static str, Response) {
+    let into_response = |(outcome, result): JuliaFailure| match result {
+        Ok(reply) => (outcome, reply.into_response()),
+        Err(error) => (outcome, error.into_response()),
+    };
+    let res = match post_to_julia(state, endpoint, incoming, payload).await {
+        Ok(res) => res,
+        Err(failure) => return into_response(failure),
+    };
+    let upstream_status = res.status();
+    let is_json = is_json(&res);
+    let (limit, timeout) = (state.config.max_julia_response_bytes, state.config.julia_timeout);
+    if !(upstream_status.is_success() && is_json) {
+        return into_response(match read_capped(res, limit, timeout).await {
+            Ok(bytes) => classify_body(state, upstream_status, is_json, &bytes),
+            Err(failure) => failure,
+        });
+    }
+
+    // Error bodies are small, so only the head of a reply needs checking for one
+    let content_type = res.headers().get(reqwest::header::CONTENT_TYPE).cloned();
+    let mut body = res.bytes_stream();
+    let mut head = Vec::new();
+    while head.len() <= STREAM_THRESHOLD_BYTES {
+        match body.next().await {
+            Some(Ok(chunk)) => head.extend_from_slice(&chunk),
+            Some(Err(e)) => return into_response(transport_failure(e, timeout)),
+            None => return into_response(classify_body(state, upstream_status, is_json, &head)),
+        }
+    }
+
+    let status = StatusCode::from_u16(upstream_status.as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+    let rest = futures::stream::once(async { Ok::<_, reqwest::Error>(Bytes::from(head)) }).chain(body);
+    let mut response = (status, Body::from_stream(rest)).into_response();
+    if let Some(content_type) = content_type {
+        response.headers_mut().insert(axum::http::header::CONTENT_TYPE, content_type);
+    }
+    ("streamed", response)
+}
+
+/// POST `payload` to `{julia_url}/{endpoint}` for a file Julia streams back.
+///
+/// `Ok` is a successful non-JSON reply, its body still unread. JSON replies come
+/// back as `Err`: failures classified like [`fetch_julia`]'s, and a successful one
+/// as `not_streamed` (a backend that wrote the file itself instead of streaming it).
+pub async fn open_julia_stream(
+    state: &AppState,
+    endpoint: &str,
+    incoming: &HeaderMap,
+    payload: Value,
+) -> Result<reqwest::Response, AppError> {
+    let in_flight = InFlight::new(endpoint);
+    let result = start_julia_stream(state, endpoint, incoming, payload).await;
+    in_flight.finish();
+    result
+}
+
+async fn start_julia_stream(
+    state: &AppState,
+    endpoint: &str,
+    incoming: &HeaderMap,
+    payload: Value,
+) -> Result<reqwest::Response, AppError> {
+    let failure = |(outcome, result): JuliaFailure| {
+        observability::record_julia_outcome(endpoint, outcome);
+        match result.map(JuliaResponse::into_body) {
+            Ok(Ok(body)) => AppError::upstream(StatusCode::OK, "not_streamed", "Julia answered without streaming the file")
+                .with_detail("julia_body", body),
+            Ok(Err(error)) | Err(error) => error,
+        }
+    };
+    let res = post_to_julia(state, endpoint, incoming, payload).await.map_err(failure)?;
+    let upstream_status = res.status();
+    let is_json = is_json(&res);
+    if upstream_status.is_success() && !is_json {
+        observability::record_julia_outcome(endpoint, "streamed");
+        return Ok(res);
+    }
+    Err(failure(match read_capped(res, state.config.max_julia_response_bytes, state.config.julia_timeout).await {
+        Ok(bytes) => classify_body(state, upstream_status, is_json, &bytes),
+        Err(failure) => failure,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Router};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    /// Serve `body` with a 200 from a throwaway "Julia" on an ephemeral port.
+    async fn mock_julia(endpoint: &str, body: Value) -> String {
+        serve(Router::new().route(endpoint, post(move || async move { Json(body) }))).await
+    }
+
+    async fn serve(app: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn error_in_200_body_is_surfaced_as_bad_gateway() {
+        let state = Arc::new(AppState::for_tests(&mock_julia("/mesh", json!({"error": "mesh failed"})).await));
+
+        let response = proxy_to_julia(&state, "mesh", &HeaderMap::new(), json!({})).await;
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["error"]["message"], "mesh failed");
+        assert_eq!(body["error"]["code"], "julia_error");
+        assert_eq!(body["error"]["source"], "julia");
+    }
+
+    #[tokio::test]
+    async fn html_error_page_is_reported_as_non_json_upstream() {
+        let page = format!("<html><body><h1>500 Internal Server Error</h1>{}</body></html>", "x".repeat(4096));
+        let app = Router::new().route(
+            "/analyze",
+            post(move || async move {
+                (StatusCode::INTERNAL_SERVER_ERROR, [("content-type", "text/html; charset=utf-8")], page)
+            }),
+        );
+        let state = Arc::new(AppState::for_tests(&serve(app).await));
+
+        let response = proxy_to_julia(&state, "analyze", &HeaderMap::new(), json!({})).await;
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["error"]["code"], "non_json_upstream");
+        assert_eq!(body["error"]["upstream_status"], 500);
+        let excerpt = body["error"]["body_excerpt"].as_str().unwrap();
+        assert!(excerpt.starts_with("<html><body><h1>500"));
+        assert!(excerpt.len() <= BODY_EXCERPT_BYTES + 3);
+    }
+
+    /// `bytes` served with a JSON content type, chunked so no `Content-Length` gives the size away
+    async fn mock_chunked_julia(endpoint: &str, bytes: Vec<u8>) -> String {
+        let app = Router::new().route(
+            endpoint,
+            post(move || async move {
+                let chunks: Vec<Result<Vec<u8>, std::io::Error>> = bytes.chunks(64 * 1024).map(|c| Ok(c.to_vec())).collect();
+                ([("content-type", "application/json")], axum::body::Body::from_stream(futures::stream::iter(chunks)))
+            }),
+        );
+        serve(app).await
+    }
+
+    #[tokio::test]
+    async fn oversized_reply_is_refused_before_buffering() {
+        let big = json!({"voxels": "x".repeat(4096)});
+        let chunked = serde_json::to_vec(&big).unwrap();
+        for url in [mock_julia("/analyze", big).await, mock_chunked_julia("/analyze", chunked).await] {
+            let mut state = AppState::for_tests(&url);
+            state.config.max_julia_response_bytes = 1024;
+
+            let error = fetch_julia(&state, "analyze", &HeaderMap::new(), json!({})).await.unwrap_err();
+            assert_eq!(error.status(), StatusCode::PAYLOAD_TOO_LARGE);
+            let body = error.body();
+            assert_eq!(body["error"]["code"], "upstream_too_large");
+            assert_eq!(body["error"]["limit_bytes"], 1024);
+        }
+    }
+
+    #[tokio::test]
+    async fn large_mesh_replies_stream_through_unparsed() {
+        let mesh = serde_json::to_vec(&json!({"vertices": vec![0.5; 400_000]})).unwrap();
+        assert!(mesh.len() > STREAM_THRESHOLD_BYTES);
+        let mut state = AppState::for_tests(&mock_chunked_julia("/mesh", mesh.clone()).await);
+        state.config.max_julia_response_bytes = STREAM_THRESHOLD_BYTES;
+
+        let response = proxy_to_julia(&state, "mesh", &HeaderMap::new(), json!({})).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/json");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(bytes, mesh);
+    }
+
+    #[tokio::test]
+    async fn only_whitelisted_headers_reach_julia() {
+        // Echo the received headers back as the reply
+        let app = Router::new().route(
+            "/mesh",
+            post(|headers: HeaderMap| async move {
+                let received: serde_json::Map<String, Value> = headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), json!(value.to_str().unwrap())))
+                    .collect();
+                Json(Value::Object(received))
+            }),
+        );
+        let mut state = AppState::for_tests(&serve(app).await);
+        state.config.forward_headers = vec![HeaderName::from_static("x-tenant-id")];
+
+        let mut incoming = HeaderMap::new();
+        incoming.insert("x-tenant-id", HeaderValue::from_static("lab-7"));
+        incoming.insert(REQUEST_ID_HEADER, HeaderValue::from_static("req-42"));
+        incoming.insert("authorization", HeaderValue::from_static("Bearer s3cret"));
+        incoming.insert("x-api-key", HeaderValue::from_static("s3cret"));
+        incoming.insert("connection", HeaderValue::from_static("close"));
+
+        let response = proxy_to_julia(&state, "mesh", &incoming, json!({})).await;
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let received: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(received["x-tenant-id"], "lab-7");
+        assert_eq!(received["x-request-id"], "req-42");
+        assert_eq!(received["x-darwin-server-version"], env!("CARGO_PKG_VERSION"));
+        assert!(received.get("authorization").is_none());
+        assert!(received.get("x-api-key").is_none());
+        assert_ne!(received["connection"], "close");
+    }
+
+    #[tokio::test]
+    async fn client_disconnect_cancels_the_julia_request() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // A "Julia" that never answers: reports each request line, then when its connection closes
+        let julia = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let julia_url = format!("http://{}", julia.local_addr().unwrap());
+        let (seen, mut events) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (mut conn, _) = julia.accept().await.unwrap();
+                let seen = seen.clone();
+                tokio::spawn(async move {
+                    let mut buf = vec![0; 64 * 1024];
+                    let n = conn.read(&mut buf).await.unwrap();
+                    let request_line = String::from_utf8_lossy(&buf[..n]).lines().next().unwrap_or("").to_string();
+                    seen.send(request_line.clone()).unwrap();
+                    while conn.read(&mut buf).await.is_ok_and(|n| n > 0) {}
+                    let _ = seen.send(format!("closed: {}", request_line));
+                });
+            }
+        });
+
+        let state = Arc::new(AppState::for_tests(&julia_url));
+        let app = Router::new()
+            .route(
+                "/api/analyze",
+                post(|axum::extract::State(state): axum::extract::State<Arc<AppState>>, Json(payload): Json<Value>| async move {
+                    fetch_julia(&state, "analyze", &HeaderMap::new(), payload).await.map(|_| ())
+                }),
+            )
+            .with_state(state);
+        let server = serve(app).await;
+
+        let mut client = tokio::net::TcpStream::connect(server.trim_start_matches("http://")).await.unwrap();
+        let body = json!({"file_path": "/scans/femur.tif", "job_id": "job-9"}).to_string();
+        let request = format!(
+            "POST /api/analyze HTTP/1.1\r\nhost: darwin\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        client.write_all(request.as_bytes()).await.unwrap();
+
+        async fn next(events: &mut tokio::sync::mpsc::UnboundedReceiver<String>) -> String {
+            let event = tokio::time::timeout(std::time::Duration::from_secs(5), events.recv()).await;
+            event.expect("Julia saw nothing").unwrap()
+        }
+        assert_eq!(next(&mut events).await, "POST /analyze HTTP/1.1");
+        drop(client);
+
+        assert_eq!(next(&mut events).await, "closed: POST /analyze HTTP/1.1");
+        // Closing the connection is the whole cancellation; nothing else is sent
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn excerpt_never_splits_a_character() {
+        assert_eq!(body_excerpt("µµ".as_bytes(), 3), "µ...");
+        assert_eq!(body_excerpt(b"short", 10), "short");
+    }
+
+    #[test]
+    fn error_key_is_configurable() {
+        let body = json!({"detail": "bad voxel size", "error": null});
+        assert!(matches!(
+            JuliaResponse::classify(StatusCode::OK, body.clone(), "error"),
+            JuliaResponse::Success { .. }
+        ));
+        assert_eq!(
+            JuliaResponse::classify(StatusCode::OK, body.clone(), "detail"),
+            JuliaResponse::Failure {
+                status: StatusCode::OK,
+                message: "bad voxel size".to_string(),
+                body
+            }
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+};
+use futures::stream::{self, Stream, StreamExt};
+use serde::Deserialize;
+use std::{convert::Infallible, sync::Arc, time::Duration};
+
+use crate::{error::AppError, AppState};
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "debug" | "trace" => Some(Self::Debug),
+            "info" => Some(Self::Info),
+            "warn" | "warning" => Some(Self::Warn),
+            "error" | "err" | "fatal" => Some(Self::Error),
+            _ => None,
+        }
+    }
+
+    /// Best-effort level of a Julia log line.
+    ///
+    /// Accepts JSON lines with a `level` field as well as plain text in the
+    /// `Logging` style (`┌ Warning: ...`, `[ Info: ...`, `ERROR: ...`).
+    /// Lines without a recognisable level count as `Info`.
+    pub fn of_line(line: &str) -> Self {
+        if let Ok(value) = serde_json::from_str::<serde_json::Value>(line) {
+            if let Some(level) = value.get("level").and_then(|l| l.as_str()).and_then(Self::parse) {
+                return level;
+            }
+        }
+
+        let head: String = line.chars().take(24).collect::<String>().to_ascii_lowercase();
+        if head.contains("error") {
+            Self::Error
+        } else if head.contains("warn") {
+            Self::Warn
+        } else if head.contains("debug") {
+            Self::Debug
+        } else {
+            Self::Info
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogStreamParams {
+    level: Option<String>,
+}
+
+/// `GET /api/julia-logs/stream` - tail the Julia server logs as server-sent events.
+///
+/// Julia's `/logs/stream` sends its recent log records, then each new one, as
+/// JSON lines (`{time, level, module, message}`). Each line becomes one `data:` event; `?level=warn` drops anything
+/// below that level. A comment heartbeat is sent every 15s. When the client
+/// disconnects the SSE stream is dropped, which drops the upstream reqwest body
+/// and closes the connection to Julia.
+pub async fn julia_logs_stream_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<LogStreamParams>,
+) -> Result<Response, AppError> {
+    let min_level = match params.level.as_deref() {
+        None => LogLevel::Debug,
+        Some(level) => LogLevel::parse(level).ok_or_else(|| AppError::validation(format!("unknown log level: {}", level)))?,
+    };
+
+    let url = format!("{}/logs/stream", state.julia_url);
+    let upstream = match reqwest::Client::new().get(&url).send().await {
+        Ok(res) if res.status().is_success() => res,
+        Ok(res) => {
+            let message = format!("Julia log stream returned {}", res.status());
+            return Err(AppError::upstream(StatusCode::BAD_GATEWAY, "julia_error", message));
+        }
+        Err(e) => {
+            return Err(AppError::Proxy { status: StatusCode::BAD_GATEWAY, code: "julia_unreachable", message: e.to_string() })
+        }
+    };
+
+    // Julia sends a blank line now and then to notice a gone reader
+    let events = byte_lines(upstream.bytes_stream())
+        .filter(move |line| std::future::ready(!line.trim().is_empty() && LogLevel::of_line(line) >= min_level))
+        .map(|line| Ok::<_, Infallible>(Event::default().data(line)));
+
+    Ok(Sse::new(events)
+        .keep_alive(KeepAlive::new().interval(HEARTBEAT_INTERVAL).text("heartbeat"))
+        .into_response())
+}
+
+/// Split a byte stream into lines (without the trailing `\n` / `\r\n`).
+///
+/// Ends at the first transport error; a trailing unterminated line is flushed.
+pub fn byte_lines<S, B, E>(body: S) -> impl Stream<Item = String>
+where
+    S: Stream<Item = Result<B, E>> + Unpin,
+    B: AsRef<[u8]>,
+{
+    stream::unfold((body, Vec::<u8>::new(), false), |(mut body, mut buffer, mut done)| async move {
+        loop {
+            if let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&line).trim_end_matches(['\r', '\n']).to_string();
+                return Some((line, (body, buffer, done)));
+            }
+            if done {
+                if buffer.is_empty() {
+                    return None;
+                }
+                let line = String::from_utf8_lossy(&buffer).to_string();
+                buffer.clear();
+                return Some((line, (body, buffer, done)));
+            }
+            match body.next().await {
+                Some(Ok(chunk)) => buffer.extend_from_slice(chunk.as_ref()),
+                Some(Err(_)) | None => done = true,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Router};
+
+    /// Records as `src/server.jl` writes them, with a keep-alive among them
+    const JULIA_LOG_LINES: &str = concat!(
+        r#"{"time":"2026-10-17T09:00:00.000","level":"info","module":"Main","message":"Starting Darwin Scaffold Engine on port 8081"}"#,
+        "\n\n",
+        r#"{"time":"2026-10-17T09:00:01.500","level":"warn","module":"Main","message":"Slow segmentation"}"#,
+        "\n",
+        r#"{"time":"2026-10-17T09:00:02.250","level":"error","module":"Main","message":"Analysis failed","exception":"ArgumentError: empty volume"}"#,
+        "\n",
+    );
+
+    async fn events(level: Option<&str>) -> Vec<serde_json::Value> {
+        let app = Router::new().route("/logs/stream", get(|| async { JULIA_LOG_LINES }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let state = Arc::new(AppState::for_tests(&format!("http://{}", addr)));
+        let params = LogStreamParams { level: level.map(str::to_string) };
+        let response = julia_logs_stream_handler(State(state), Query(params)).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(body.to_vec())
+            .unwrap()
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn julia_log_records_are_relayed_without_keep_alives() {
+        let all = events(None).await;
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0]["message"], "Starting Darwin Scaffold Engine on port 8081");
+
+        let warnings = events(Some("warn")).await;
+        let levels: Vec<&str> = warnings.iter().map(|e| e["level"].as_str().unwrap()).collect();
+        assert_eq!(levels, ["warn", "error"]);
+        assert_eq!(warnings[1]["exception"], "ArgumentError: empty volume");
+    }
+
+    #[test]
+    fn levels_of_plain_text_lines() {
+        assert_eq!(LogLevel::of_line("┌ Warning: deprecated"), LogLevel::Warn);
+        assert_eq!(LogLevel::of_line("ERROR: LoadError"), LogLevel::Error);
+        assert_eq!(LogLevel::of_line("[ Info: listening"), LogLevel::Info);
+    }
+}
@@ -0,0 +1,157 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{error::AppError, observability, AppState};
+
+pub const DEFAULT_MAX_JULIA_CONCURRENCY: usize = 4;
+pub const DEFAULT_JULIA_QUEUE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Bounds how many requests may be in flight against the single Julia process.
+///
+/// Requests beyond the limit wait for a permit; if none frees up within
+/// `queue_timeout` they are rejected with `503` and a `Retry-After` header.
+#[derive(Clone)]
+pub struct JuliaLimiter {
+    permits: Arc<Semaphore>,
+    queue_timeout: Duration,
+}
+
+impl JuliaLimiter {
+    pub fn new(max_concurrency: usize, queue_timeout: Duration) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max_concurrency.max(1))),
+            queue_timeout,
+        }
+    }
+
+    /// Sized from `DARWIN_MAX_JULIA_CONCURRENCY` and `DARWIN_JULIA_QUEUE_TIMEOUT_SECS`.
+    pub fn from_env() -> Self {
+        let max_concurrency = std::env::var("DARWIN_MAX_JULIA_CONCURRENCY")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_JULIA_CONCURRENCY);
+        let queue_timeout = std::env::var("DARWIN_JULIA_QUEUE_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_JULIA_QUEUE_TIMEOUT);
+        Self::new(max_concurrency, queue_timeout)
+    }
+
+    /// Wait up to `queue_timeout` for a permit; `None` when none frees up in time.
+    pub async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        let _queued = observability::GaugeGuard::new(observability::JULIA_QUEUE_DEPTH);
+        match tokio::time::timeout(self.queue_timeout, self.permits.clone().acquire_owned()).await {
+            Ok(Ok(permit)) => Some(permit),
+            _ => None,
+        }
+    }
+
+    /// Wait for a permit however long it takes; for background work no client is waiting on.
+    pub async fn acquire_queued(&self) -> OwnedSemaphorePermit {
+        let _queued = observability::GaugeGuard::new(observability::JULIA_QUEUE_DEPTH);
+        self.permits.clone().acquire_owned().await.expect("the Julia semaphore is never closed")
+    }
+}
+
+/// `503 julia_busy`, for a request that got no permit
+pub fn busy() -> AppError {
+    AppError::Proxy {
+        status: StatusCode::SERVICE_UNAVAILABLE,
+        code: "julia_busy",
+        message: "Julia backend is busy, try again later".to_string(),
+    }
+}
+
+/// Middleware for the compute routes (`/api/analyze`, `/api/optimize`, `/api/mesh`).
+pub async fn limit_julia_concurrency(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let limiter = &state.julia_limiter;
+    match limiter.acquire().await {
+        Some(_permit) => next.run(request).await,
+        None => {
+            let retry_after = limiter.queue_timeout.as_secs().max(1).to_string();
+            ([(header::RETRY_AFTER, retry_after)], busy()).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::post, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower::ServiceExt;
+
+    #[derive(Default)]
+    struct Concurrency {
+        current: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    fn limited_router(limiter: JuliaLimiter, concurrency: Arc<Concurrency>) -> Router {
+        let state = Arc::new(AppState {
+            julia_limiter: limiter,
+            ..AppState::for_tests("http://127.0.0.1:1")
+        });
+
+        // Stands in for a proxied Julia call that takes a while
+        let handler = move || {
+            let concurrency = concurrency.clone();
+            async move {
+                let now = concurrency.current.fetch_add(1, Ordering::SeqCst) + 1;
+                concurrency.peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                concurrency.current.fetch_sub(1, Ordering::SeqCst);
+                StatusCode::OK
+            }
+        };
+
+        Router::new()
+            .route("/api/analyze", post(handler))
+            .route_layer(middleware::from_fn_with_state(state.clone(), limit_julia_concurrency))
+            .with_state(state)
+    }
+
+    fn analyze_request() -> Request {
+        Request::post("/api/analyze").body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn queues_requests_beyond_the_limit() {
+        let concurrency = Arc::new(Concurrency::default());
+        let app = limited_router(JuliaLimiter::new(2, Duration::from_secs(5)), concurrency.clone());
+
+        let requests = (0..4).map(|_| app.clone().oneshot(analyze_request()));
+        let responses = futures::future::join_all(requests).await;
+
+        assert!(responses.iter().all(|r| r.as_ref().unwrap().status() == StatusCode::OK));
+        assert_eq!(concurrency.peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn rejects_with_retry_after_when_queue_wait_expires() {
+        let concurrency = Arc::new(Concurrency::default());
+        let app = limited_router(JuliaLimiter::new(1, Duration::from_millis(20)), concurrency);
+
+        let (first, second) = tokio::join!(
+            app.clone().oneshot(analyze_request()),
+            app.clone().oneshot(analyze_request())
+        );
+        let mut statuses = [first.unwrap(), second.unwrap()];
+        statuses.sort_by_key(|r| r.status());
+
+        assert_eq!(statuses[0].status(), StatusCode::OK);
+        assert_eq!(statuses[1].status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(statuses[1].headers()[header::RETRY_AFTER], "1");
+    }
+}
@@ -0,0 +1,180 @@
+//! Log setup: `RUST_LOG` filtering, `DARWIN_LOG_FORMAT=json|pretty`, and a
+//! per-request span carrying the request ID.
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::time::Instant;
+use tracing::{Instrument, Subscriber};
+use tracing_subscriber::{fmt::MakeWriter, EnvFilter};
+use uuid::Uuid;
+
+use crate::request_log::{RequestLog, RequestRecord};
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Used when `RUST_LOG` is unset or invalid
+const DEFAULT_FILTER: &str = "info";
+
+tokio::task_local! {
+    /// `request_id` of the enclosing `request` span; spans cannot be read back, so it is kept here too
+    static REQUEST_ID: String;
+}
+
+/// The `request_id` of the request being handled, `None` outside [`request_span`]
+/// (and in tasks spawned from a handler).
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(String::clone).ok()
+}
+
+/// Run `future` with `request_id` as the [`current_request_id`].
+pub async fn with_request_id<F: std::future::Future>(request_id: String, future: F) -> F::Output {
+    REQUEST_ID.scope(request_id, future).await
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines (default)
+    Pretty,
+    /// One JSON object per event, including the fields of enclosing spans
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "pretty" => Ok(Self::Pretty),
+            "json" => Ok(Self::Json),
+            other => Err(format!("DARWIN_LOG_FORMAT must be `json` or `pretty`, got `{}`", other)),
+        }
+    }
+
+    pub fn from_env() -> Result<Self, String> {
+        Self::parse(&std::env::var("DARWIN_LOG_FORMAT").unwrap_or_default())
+    }
+}
+
+/// Subscriber writing to `writer` with the `RUST_LOG` filter.
+pub fn subscriber<W>(format: LogFormat, writer: W) -> Box<dyn Subscriber + Send + Sync>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(writer);
+    match format {
+        LogFormat::Pretty => Box::new(builder.finish()),
+        LogFormat::Json => Box::new(builder.json().with_current_span(true).with_span_list(true).finish()),
+    }
+}
+
+/// Install the global subscriber, logging to stdout.
+pub fn init(format: LogFormat) {
+    tracing::subscriber::set_global_default(subscriber(format, std::io::stdout))
+        .expect("logging initialised twice");
+}
+
+/// Middleware: run each request in a `request` span with `request_id`, `method` and
+/// `path` (plus `user` once a JWT has been verified). The ID is taken from `X-Request-Id` when the client sends one (so it can
+/// be correlated across services), otherwise generated, and echoed in the response.
+/// Each finished request is also added to `log`.
+pub async fn request_span(State(log): State<RequestLog>, mut request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= 128)
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let header_value = HeaderValue::from_str(&request_id).unwrap_or_else(|_| HeaderValue::from_static("invalid"));
+    request.headers_mut().insert(REQUEST_ID_HEADER, header_value.clone());
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %request.method(),
+        path = %request.uri().path(),
+        user = tracing::field::Empty,
+    );
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let started = Instant::now();
+    let current_id = request_id.clone();
+    let handled = async move {
+        let mut response = next.run(request).await;
+        let status = response.status().as_u16();
+        let latency_ms = started.elapsed().as_millis() as u64;
+        tracing::info!(status, latency_ms, "request finished");
+        log.record(RequestRecord::finished_now(request_id, method, path, status, latency_ms));
+        response.headers_mut().insert(REQUEST_ID_HEADER, header_value);
+        response
+    };
+    with_request_id(current_id, handled.instrument(span)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use serde_json::Value;
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    /// Collects everything the subscriber writes
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'w> MakeWriter<'w> for Capture {
+        type Writer = Capture;
+        fn make_writer(&'w self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn format_is_json_or_pretty() {
+        assert_eq!(LogFormat::parse("").unwrap(), LogFormat::Pretty);
+        assert_eq!(LogFormat::parse("JSON").unwrap(), LogFormat::Json);
+        assert!(LogFormat::parse("xml").is_err());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn json_events_carry_the_request_id() {
+        let capture = Capture::default();
+        let log = RequestLog::default();
+        let _guard = tracing::subscriber::set_default(subscriber(LogFormat::Json, capture.clone()));
+
+        let app = Router::new()
+            .route("/api/ping", get(|| async { tracing::info!("handling ping"); "pong" }))
+            .layer(middleware::from_fn_with_state(log.clone(), request_span));
+        let request = axum::http::Request::get("/api/ping")
+            .header(REQUEST_ID_HEADER, "req-42")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "req-42");
+
+        let output = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<Value> = output.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        let handled = events.iter().find(|e| e["fields"]["message"] == "handling ping").unwrap();
+        assert_eq!(handled["span"]["request_id"], "req-42");
+        assert_eq!(handled["span"]["path"], "/api/ping");
+        let finished = events.iter().find(|e| e["fields"]["message"] == "request finished").unwrap();
+        assert_eq!(finished["fields"]["status"], 200);
+
+        let recorded = log.query(&Default::default());
+        assert_eq!((recorded[0].request_id.as_str(), recorded[0].status), ("req-42", 200));
+    }
+}
@@ -1,131 +1,584 @@
 use axum::{
-    extract::{Multipart, State},
-    http::StatusCode,
-    response::{Html, IntoResponse, Json},
-    routing::{get, post},
+    extract::{DefaultBodyLimit, State},
+    http::HeaderMap,
+    middleware,
+    response::{IntoResponse, Json, Response},
+    routing::{delete, get, patch, post, put},
     Router,
 };
-use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::{net::SocketAddr, path::PathBuf, sync::Arc};
-use tower_http::{cors::CorsLayer, services::ServeDir};
-use uuid::Uuid;
+use std::{path::PathBuf, sync::Arc};
 use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 
+mod agent_tools;
 mod agents;
+mod analysis;
+mod analysis_cache;
+mod audit;
+mod auth;
+mod auto_analyze;
+mod chunked_uploads;
+mod config;
+mod content_type;
+mod conversion;
+mod db;
+mod downloads;
+mod error;
+mod events;
+mod exports;
+mod file_format;
+mod frontend;
+mod jobs;
+mod julia;
+mod julia_logs;
+mod jwt;
+mod limits;
+mod logging;
+mod mesh;
+mod negotiate;
+mod observability;
+mod optimization;
+mod pagination;
+mod progress;
+mod request_log;
+#[cfg(feature = "s3")]
+mod s3_storage;
+mod scan_metadata;
+mod storage;
+mod uploads;
+mod version;
+mod webhook_deliveries;
+mod webhooks;
+mod workspace_locks;
 use agents::{AgentWorkspaceState, agent_routes};
+use error::AppError;
+use julia::proxy_to_julia;
 
-#[derive(Deserialize, Serialize, Debug)]
-struct OptimizationRequest {
-    porosity: f64,
-    pore_size: f64,
-    method: String,
-    resolution: f64,
-    material: Option<String>,
-    use_case: Option<String>,
-}
+const TTL_SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
 
 #[derive(Clone)]
 struct AppState {
     julia_url: String,
     upload_dir: PathBuf,
+    /// Stored uploads; files Julia reads are kept under `upload_dir`
+    storage: Arc<dyn storage::Storage>,
+    export_dir: PathBuf,
+    julia_limiter: limits::JuliaLimiter,
+    chunked_uploads: chunked_uploads::ChunkedUploads,
+    /// Top-level key marking an error in a Julia JSON body
+    julia_error_key: String,
+    config: config::Config,
+    events: events::EventBus,
+    analysis_cache: analysis_cache::AnalysisCache,
+    auto_analyze: auto_analyze::AutoAnalyze,
+    workspace_locks: workspace_locks::WorkspaceLocks,
+    request_log: request_log::RequestLog,
+    /// Set when `DARWIN_JWT_JWKS_URL` is configured
+    jwt: Option<jwt::JwtVerifier>,
+    audit: audit::AuditLog,
+    webhooks: webhooks::AnalysisWebhooks,
+    jobs: jobs::Jobs,
+    /// Upload metadata, workspace analyses and job history that outlive a restart
+    db: db::Db,
+}
+
+#[cfg(test)]
+impl AppState {
+    fn for_tests(julia_url: &str) -> Self {
+        Self {
+            julia_url: julia_url.to_string(),
+            upload_dir: std::env::temp_dir().join("darwin_uploads_test"),
+            storage: Arc::new(storage::LocalFsStorage::new(std::env::temp_dir().join("darwin_uploads_test"))),
+            export_dir: std::env::temp_dir().join("darwin_exports_test"),
+            julia_limiter: limits::JuliaLimiter::new(
+                limits::DEFAULT_MAX_JULIA_CONCURRENCY,
+                limits::DEFAULT_JULIA_QUEUE_TIMEOUT,
+            ),
+            chunked_uploads: chunked_uploads::ChunkedUploads::default(),
+            julia_error_key: julia::DEFAULT_ERROR_KEY.to_string(),
+            config: config::Config::default(),
+            events: events::EventBus::default(),
+            analysis_cache: analysis_cache::AnalysisCache::new(
+                analysis_cache::DEFAULT_CAPACITY,
+                analysis_cache::DEFAULT_TTL,
+            ),
+            auto_analyze: auto_analyze::AutoAnalyze::default(),
+            workspace_locks: workspace_locks::WorkspaceLocks::default(),
+            request_log: request_log::RequestLog::default(),
+            jwt: None,
+            audit: audit::AuditLog::spawn(
+                std::env::temp_dir().join(format!("darwin_audit_test_{}.jsonl", uuid::Uuid::new_v4())),
+            ),
+            webhooks: webhooks::AnalysisWebhooks::load(
+                std::env::temp_dir().join(format!("darwin_webhooks_test_{}.json", uuid::Uuid::new_v4())),
+                webhooks::DEFAULT_WEBHOOK_HOSTS.iter().map(|h| h.to_string()).collect(),
+            ),
+            jobs: jobs::Jobs::default(),
+            db: db::Db::in_memory(),
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() {
-    // Initialize tracing
-    tracing_subscriber::fmt::init();
+    // Initialize tracing: RUST_LOG filter, DARWIN_LOG_FORMAT=json|pretty
+    let log_format = logging::LogFormat::from_env().unwrap_or_else(|e| {
+        eprintln!("Invalid configuration: {}", e);
+        std::process::exit(1);
+    });
+    logging::init(log_format);
+
+    let config = config::Config::from_env().unwrap_or_else(|e| {
+        eprintln!("Invalid configuration: {}", e);
+        std::process::exit(1);
+    });
+    let export_dir = PathBuf::from("/tmp/darwin_exports");
+    tokio::fs::create_dir_all(&export_dir).await.unwrap();
 
-    let upload_dir = PathBuf::from("/tmp/darwin_uploads");
-    tokio::fs::create_dir_all(&upload_dir).await.unwrap();
+    let jwt = config.jwt.clone().map(jwt::JwtVerifier::new);
+    if let Some(jwt) = &jwt {
+        // Not fatal: the identity provider may come up after us, and requests retry the fetch
+        if let Err(e) = jwt.refresh().await {
+            tracing::warn!("{}", e);
+        }
+    }
 
+    let audit = audit::AuditLog::spawn(config.audit_log.clone());
+    let webhooks = webhooks::AnalysisWebhooks::load(config.webhooks_file.clone(), config.webhook_hosts.clone());
+    webhooks.spawn_delivery_worker();
+    let db = db::Db::open(&config.database);
+    if let Err(e) = db.migrate().await {
+        eprintln!("Cannot open the database at {}: {}", config.database.display(), e);
+        std::process::exit(1);
+    }
+    match db.fail_interrupted_jobs().await {
+        Ok(0) => {}
+        Ok(interrupted) => tracing::warn!("{} job(s) were cut short by the last shutdown", interrupted),
+        Err(e) => tracing::warn!("failed to close out interrupted jobs: {}", e),
+    }
     let state = Arc::new(AppState {
-        julia_url: "http://127.0.0.1:8081".to_string(),
-        upload_dir,
+        julia_url: config.julia_url.clone(),
+        upload_dir: config.upload_dir.clone(),
+        storage: storage::from_settings(&config.storage, config.upload_dir.clone()),
+        export_dir,
+        julia_limiter: limits::JuliaLimiter::from_env(),
+        chunked_uploads: chunked_uploads::ChunkedUploads::default(),
+        julia_error_key: julia::error_key_from_env(),
+        config,
+        events: events::EventBus::default(),
+        analysis_cache: analysis_cache::AnalysisCache::from_env(),
+        auto_analyze: auto_analyze::AutoAnalyze::default(),
+        workspace_locks: workspace_locks::WorkspaceLocks::default(),
+        request_log: request_log::RequestLog::default(),
+        jwt,
+        audit,
+        webhooks,
+        jobs: jobs::Jobs::from_env(),
+        db,
     });
+    state.jobs.spawn_workers(state.clone());
 
     // Agent workspace (shared across WebSocket connections)
-    let agent_workspace = Arc::new(Mutex::new(AgentWorkspaceState::new()));
+    let mut agent_state = AgentWorkspaceState::new(state.julia_url.clone());
+    agent_state.message_limit = agents::MessageRateLimit::from_env();
+    agent_state.tool_concurrency = agents::tool_concurrency_from_env();
+    let agent_workspace = Arc::new(Mutex::new(agent_state));
+
+    spawn_ttl_sweeper(state.clone(), agent_workspace.clone());
+
+    // Cancelled on SIGINT/SIGTERM so open agent sockets can say goodbye
+    let shutdown = CancellationToken::new();
+
+    // One upstream progress subscription shared by every `/ws/events` client
+    progress::ProgressRelay::new(&state.julia_url, progress::DEFAULT_INITIAL_BACKOFF, progress::DEFAULT_MAX_BACKOFF)
+        .spawn(state.events.clone(), shutdown.clone());
+
+    // Install the metrics recorder before the first request is counted
+    observability::handle();
+    if let Some(metrics_addr) = state.config.metrics_addr {
+        let listener = tokio::net::TcpListener::bind(metrics_addr).await.unwrap_or_else(|e| {
+            eprintln!("Cannot listen on {}: {}", metrics_addr, e);
+            std::process::exit(1);
+        });
+        println!("📈 Metrics on http://{}/metrics", metrics_addr);
+        let metrics_app = Router::new().route("/metrics", get(observability::metrics_handler));
+        tokio::spawn(async move { axum::serve(listener, metrics_app).await });
+    }
+
+    let addr = state.config.socket_addr();
+    let app = app(state, agent_workspace, shutdown.clone());
 
+    println!("🚀 Darwin Server listening on {}", addr);
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap_or_else(|e| {
+        eprintln!("Cannot listen on {}: {}", addr, e);
+        std::process::exit(1);
+    });
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal(shutdown))
+        .await
+        .unwrap();
+}
+
+/// The complete HTTP surface; split from `main` so tests can drive it with `oneshot`.
+fn app(
+    state: Arc<AppState>,
+    agent_workspace: Arc<Mutex<AgentWorkspaceState>>,
+    shutdown: CancellationToken,
+) -> Router {
     // Create combined state
     let combined_state = (state.clone(), agent_workspace);
 
-    let app = Router::new()
-        .route("/api/upload", post(upload_handler))
-        .route("/api/analyze", post(analyze_handler))
-        .route("/api/optimize", post(optimize_handler))
+    // Compute routes share a bounded number of in-flight Julia requests
+    let compute_routes = Router::new()
+        .route("/api/analyze", post(analysis::analyze_handler))
+        .route("/api/reanalyze", post(analysis::reanalyze_handler))
+        .route("/api/optimize", post(optimization::optimize_handler))
         .route("/api/mesh", post(mesh_handler))
-        .with_state(state)
-        .merge(agent_routes().with_state(combined_state))  // Agent routes with combined state
-        .nest_service("/", ServeDir::new("public"))
-        .layer(CorsLayer::permissive());
+        .route("/api/mesh/raw", post(mesh_raw_handler))
+        .route("/api/convert", post(conversion::convert_handler))
+        .route("/api/export/stl", post(exports::export_stl_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), limits::limit_julia_concurrency));
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
-    println!("🚀 Darwin Server listening on {}", addr);
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    // Unauthenticated, so it stays on the main port only when no internal one is configured
+    let metrics_routes = match state.config.metrics_addr {
+        Some(_) => Router::new(),
+        None => Router::new().route("/metrics", get(observability::metrics_handler)),
+    };
+
+    let cors = state.config.cors_layer();
+    let request_log = state.request_log.clone();
+    let frontend = frontend::router(&state.config.static_dir);
+    Router::new()
+        .route(
+            "/api/upload",
+            post(uploads::upload_handler).layer(DefaultBodyLimit::max(state.config.max_upload_bytes)),
+        )
+        .route("/api/uploads", get(uploads::list_uploads_handler))
+        .route("/api/upload/init", post(chunked_uploads::init_handler))
+        .route("/api/upload/:upload_id/status", get(chunked_uploads::status_handler))
+        .route("/api/upload/:upload_id/complete", post(chunked_uploads::complete_handler))
+        // Streamed to disk and bounded by the upload's `total_size`, not `DefaultBodyLimit`
+        .route(
+            "/api/upload/:upload_id",
+            patch(chunked_uploads::patch_handler).head(chunked_uploads::offset_handler),
+        )
+        .route(
+            "/api/upload/:upload_id/:chunk_index",
+            put(chunked_uploads::chunk_handler)
+                .layer(DefaultBodyLimit::max(chunked_uploads::MAX_CHUNK_SIZE as usize)),
+        )
+        // Debounced; takes its Julia permit only once the debounce window has passed
+        .route("/api/analyze/auto", post(auto_analyze::auto_analyze_handler))
+        .route("/api/download/:file_id", get(downloads::download_handler))
+        .route("/api/files/:file_id", get(downloads::file_handler))
+        .route("/api/julia-logs/stream", get(julia_logs::julia_logs_stream_handler))
+        .route("/ws/events", get(events::events_ws_handler))
+        .route("/api/requests", get(request_log::requests_handler))
+        .route("/api/audit", get(audit::audit_handler))
+        .route("/api/webhooks", post(webhooks::register_handler))
+        .route("/api/webhooks/status", get(webhooks::status_handler))
+        .route("/api/webhooks/deliveries", get(webhooks::deliveries_handler))
+        .route("/api/webhooks/deliveries/:id/retry", post(webhooks::retry_delivery_handler))
+        .route("/api/webhooks/:id", delete(webhooks::remove_handler))
+        // Queued, so they answer at once and take their Julia permits in the workers
+        .route("/api/jobs", post(jobs::submit_handler))
+        .route("/api/jobs/:job_id", get(jobs::status_handler))
+        .route("/api/workspaces/:workspace_id", get(analysis::workspace_handler))
+        .merge(compute_routes)
+        // Inside `require_auth`, so entries carry the verified user
+        .route_layer(middleware::from_fn_with_state(state.clone(), audit::record_mutations))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::require_auth))
+        .route("/api/config", get(config::config_handler))
+        .route("/api/version", get(version::version_handler))
+        .route("/api/health", get(version::health_handler))
+        .merge(metrics_routes)
+        .with_state(state)
+        .merge(agent_routes(shutdown).with_state(combined_state))  // Agent routes with combined state
+        .merge(frontend)
+        .layer(middleware::from_fn(content_type::json_responses))
+        .layer(cors)
+        .layer(middleware::from_fn(observability::track_requests))
+        .layer(middleware::from_fn_with_state(request_log, logging::request_span))
 }
 
-async fn upload_handler(
-    State(state): State<Arc<AppState>>,
-    mut multipart: Multipart,
-) -> Result<Json<Value>, (StatusCode, String)> {
-    while let Some(field) = multipart.next_field().await.map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))? {
-        let name = field.name().unwrap().to_string();
-        
-        if name == "file" {
-            let file_name = field.file_name().unwrap_or("upload.dat").to_string();
-            let data = field.bytes().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-            
-            let file_id = Uuid::new_v4();
-            let file_path = state.upload_dir.join(format!("{}_{}", file_id, file_name));
-            
-            tokio::fs::write(&file_path, data).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-            
-            return Ok(Json(serde_json::json!({
-                "file_path": file_path.to_string_lossy(),
-                "file_id": file_id.to_string(),
-                "original_name": file_name
-            })));
+/// Resolves on Ctrl+C or SIGTERM, cancelling `shutdown` for long-lived connections.
+async fn shutdown_signal(shutdown: CancellationToken) {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
         }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
     }
-    
-    Err((StatusCode::BAD_REQUEST, "No file found".to_string()))
+    tracing::info!("shutdown requested, closing agent sessions");
+    shutdown.cancel();
 }
 
-async fn analyze_handler(
-    State(state): State<Arc<AppState>>,
-    Json(payload): Json<Value>,
-) -> impl IntoResponse {
-    proxy_to_julia(&state.julia_url, "analyze", payload).await
+/// Periodically drops expired state: abandoned chunked uploads, finished jobs, agent sessions
+/// whose resume tokens have lapsed, and histogram samples no scrape has collected.
+/// Also refreshes the JWKS once it is due.
+fn spawn_ttl_sweeper(state: Arc<AppState>, agent_workspace: Arc<Mutex<AgentWorkspaceState>>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TTL_SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            chunked_uploads::remove_expired(&state).await;
+            state.jobs.sweep_finished(jobs::RETENTION);
+            agent_workspace.lock().await.prune_expired();
+            observability::handle().run_upkeep();
+            if let Some(jwt) = &state.jwt {
+                jwt.refresh_if_stale().await;
+            }
+        }
+    });
 }
 
-async fn optimize_handler(
+async fn mesh_handler(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Json(payload): Json<Value>,
-) -> impl IntoResponse {
-    proxy_to_julia(&state.julia_url, "optimize", payload).await
+) -> Result<Response, AppError> {
+    let request = mesh::MeshRequest::from_value(&payload)
+        .map_err(|problems| AppError::validation("invalid mesh request").with_detail("problems", problems))?;
+
+    let workspace_id = events::ServerEvent::workspace_id(&payload);
+    let payload = serde_json::to_value(request).unwrap_or_default();
+    let _workspace = state.workspace_locks.lock_for(&payload).await;
+    let response = proxy_to_julia(&state, "mesh", &headers, payload).await;
+    if response.status().is_success() {
+        state.events.publish(events::ServerEvent::MeshComplete { workspace_id });
+    }
+    Ok(response)
 }
 
-async fn mesh_handler(
+/// Unvalidated escape hatch: forwards the body to Julia as-is.
+async fn mesh_raw_handler(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Json(payload): Json<Value>,
 ) -> impl IntoResponse {
-    proxy_to_julia(&state.julia_url, "mesh", payload).await
+    let _workspace = state.workspace_locks.lock_for(&payload).await;
+    proxy_to_julia(&state, "mesh", &headers, payload).await
 }
 
-async fn proxy_to_julia(base_url: &str, endpoint: &str, payload: Value) -> impl IntoResponse {
-    let client = reqwest::Client::new();
-    let url = format!("{}/{}", base_url, endpoint);
-    
-    match client.post(&url).json(&payload).send().await {
-        Ok(res) => {
-            let status = res.status();
-            match res.json::<Value>().await {
-                Ok(body) => (StatusCode::from_u16(status.as_u16()).unwrap(), Json(body)).into_response(),
-                Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e.to_string()}))).into_response(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::{Request, StatusCode}};
+    use serde_json::json;
+    use std::time::Duration;
+    use tower::ServiceExt;
+    use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+
+    const JULIA_TIMEOUT: Duration = Duration::from_millis(300);
+
+    /// A compute route, a body it accepts and what a healthy Julia answers
+    struct Endpoint {
+        path: &'static str,
+        julia_path: &'static str,
+        request: Value,
+        julia_ok: Value,
+    }
+
+    fn endpoints() -> Vec<Endpoint> {
+        vec![
+            Endpoint {
+                path: "/api/analyze",
+                julia_path: "/analyze",
+                request: json!({"file_path": "/data/scan.tif", "voxel_size": 10.0}),
+                julia_ok: json!({"porosity": 0.82, "mean_pore_size_um": 210.0}),
+            },
+            Endpoint {
+                path: "/api/optimize",
+                julia_path: "/optimize",
+                request: json!({
+                    "porosity": 0.9, "pore_size": 150.0, "method": "freeze-casting", "resolution": 10.0,
+                    "objectives": [{"metric": "porosity", "direction": "maximize"}]
+                }),
+                julia_ok: json!({"candidates": [{"parameters": {"porosity": 0.9}, "metrics": {"porosity": 0.88}}]}),
+            },
+            Endpoint {
+                path: "/api/mesh",
+                julia_path: "/mesh",
+                request: json!({"workspace_id": "ws-1", "algorithm": "marching_cubes"}),
+                julia_ok: json!({"stl_path": "/tmp/ws-1.stl", "triangles": 1024}),
+            },
+        ]
+    }
+
+    fn test_app(julia: &MockServer) -> Router {
+        let mut state = AppState::for_tests(&julia.uri());
+        state.config.julia_timeout = JULIA_TIMEOUT;
+        let agent_workspace = Arc::new(Mutex::new(AgentWorkspaceState::new(julia.uri())));
+        app(Arc::new(state), agent_workspace, CancellationToken::new())
+    }
+
+    /// Stand up a Julia answering `julia_path` with `response`, then call `endpoint` through the full router
+    async fn call(endpoint: &Endpoint, response: ResponseTemplate) -> (StatusCode, Value) {
+        let julia = MockServer::start().await;
+        Mock::given(matchers::method("POST"))
+            .and(matchers::path(endpoint.julia_path))
+            .respond_with(response)
+            .mount(&julia)
+            .await;
+
+        let request = Request::post(endpoint.path)
+            .header("content-type", "application/json")
+            .body(Body::from(endpoint.request.to_string()))
+            .unwrap();
+        let response = test_app(&julia).oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn success_is_relayed() {
+        for endpoint in endpoints() {
+            let (status, body) = call(&endpoint, ResponseTemplate::new(200).set_body_json(&endpoint.julia_ok)).await;
+            assert_eq!(status, StatusCode::OK, "{}: {}", endpoint.path, body);
+            match endpoint.path {
+                "/api/optimize" => assert_eq!(body["candidates"][0]["metrics"]["porosity"], 0.88),
+                _ => assert_eq!(body, endpoint.julia_ok, "{}", endpoint.path),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn julia_client_and_server_errors_keep_their_status() {
+        for endpoint in endpoints() {
+            for status in [422, 500] {
+                let reply = ResponseTemplate::new(status).set_body_json(json!({"error": "volume is empty"}));
+                let (relayed, body) = call(&endpoint, reply).await;
+                assert_eq!(relayed.as_u16(), status, "{}", endpoint.path);
+                assert_eq!(body["error"]["message"], "volume is empty", "{}", endpoint.path);
+                assert_eq!(body["error"]["source"], "julia");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_julia_times_out_with_504() {
+        for endpoint in endpoints() {
+            let reply = ResponseTemplate::new(200)
+                .set_body_json(&endpoint.julia_ok)
+                .set_delay(JULIA_TIMEOUT * 4);
+            let (status, body) = call(&endpoint, reply).await;
+            assert_eq!(status, StatusCode::GATEWAY_TIMEOUT, "{}: {}", endpoint.path, body);
+            assert_eq!(body["error"]["code"], "julia_timeout");
+            assert!(body["error"]["message"].as_str().unwrap().contains("did not respond"));
+        }
+    }
+
+    #[tokio::test]
+    async fn non_json_reply_becomes_502() {
+        for endpoint in endpoints() {
+            let reply = ResponseTemplate::new(200).set_body_raw("<html>Proxy error</html>", "text/html");
+            let (status, body) = call(&endpoint, reply).await;
+            assert_eq!(status, StatusCode::BAD_GATEWAY, "{}", endpoint.path);
+            assert_eq!(body["error"]["code"], "non_json_upstream");
+            assert_eq!(body["error"]["body_excerpt"], "<html>Proxy error</html>");
+        }
+    }
+
+    /// Every route answers JSON with a charset, whether it succeeds or fails
+    #[tokio::test]
+    async fn responses_are_utf8_json_on_every_route() {
+        let julia = MockServer::start().await;
+        Mock::given(matchers::any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"status": "ok", "porosity": 0.8})))
+            .mount(&julia)
+            .await;
+        let json_request = |method: &str, path: &str, body: &str| {
+            Request::builder()
+                .method(method)
+                .uri(path)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+        let get = |path: &str| Request::get(path).body(Body::empty()).unwrap();
+        let unknown_upload = format!("/api/upload/{}/status", uuid::Uuid::new_v4());
+
+        let successes = [
+            get("/api/uploads"),
+            json_request("POST", "/api/upload/init", r#"{"file_name": "scan.tif", "total_size": 10}"#),
+            json_request("POST", "/api/analyze", r#"{"file_path": "/data/scan.tif", "voxel_size": 10.0}"#),
+            json_request("POST", "/api/analyze/auto", r#"{"workspace_id": "ws-1", "voxel_size": 10.0}"#),
+            get("/api/requests"),
+            get("/api/audit"),
+            get("/api/webhooks/status"),
+            get("/api/webhooks/deliveries"),
+            json_request("POST", "/api/jobs", r#"{"kind": "analyze", "payload": {"file_path": "/data/scan.tif"}}"#),
+            get("/api/config"),
+            get("/api/version"),
+            get("/api/health"),
+        ];
+        let failures = [
+            // axum's own rejections: malformed JSON, wrong content type, no multipart
+            json_request("POST", "/api/analyze", "{not json"),
+            Request::post("/api/optimize").body(Body::from("{}")).unwrap(),
+            json_request("POST", "/api/upload", "{}"),
+            json_request("POST", "/api/mesh", r#"{"workspace_id": "ws-1", "algorithm": "voxel_soup"}"#),
+            json_request("POST", "/api/analyze/auto", r#"{"voxel_size": 10.0}"#),
+            get(&unknown_upload),
+            get("/api/upload/not-a-uuid/status"),
+            json_request("PATCH", &unknown_upload.replace("/status", ""), ""),
+            get("/api/download/missing"),
+            get("/api/files/missing"),
+            get("/ws/events"),
+            get("/ws/agent-chat"),
+            json_request("DELETE", "/api/uploads", ""),
+            json_request("DELETE", "/api/webhooks/unknown", ""),
+            json_request("POST", "/api/webhooks/deliveries/unknown/retry", ""),
+            json_request("POST", "/api/jobs", r#"{"kind": "analyze", "payload": []}"#),
+            get("/api/jobs/unknown"),
+            get("/api/workspaces/unknown"),
+        ];
+
+        for (request, should_succeed) in successes.into_iter().map(|r| (r, true)).chain(failures.into_iter().map(|r| (r, false))) {
+            let route = format!("{} {}", request.method(), request.uri());
+            let response = test_app(&julia).oneshot(request).await.unwrap();
+            let status = response.status();
+            assert_eq!(status.is_success(), should_succeed, "{}: {}", route, status);
+            assert_eq!(response.headers()[axum::http::header::CONTENT_TYPE], content_type::JSON_CONTENT_TYPE, "{}", route);
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body: Value = serde_json::from_slice(&bytes).unwrap_or_else(|e| panic!("{}: {}", route, e));
+            if !should_succeed {
+                assert!(body["error"]["code"].is_string(), "{}: {}", route, body);
+                assert!(body["error"]["request_id"].is_string(), "{}: {}", route, body);
             }
-        },
-        Err(e) => (StatusCode::BAD_GATEWAY, Json(serde_json::json!({"error": e.to_string()}))).into_response(),
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_requests_never_reach_julia() {
+        let julia = MockServer::start().await;
+        Mock::given(matchers::any()).respond_with(ResponseTemplate::new(200)).expect(0).mount(&julia).await;
+
+        for (path, body) in [
+            ("/api/mesh", json!({"workspace_id": "ws-1", "algorithm": "voxel_soup"})),
+            ("/api/optimize", json!({"porosity": 0.9})),
+        ] {
+            let request = Request::post(path)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let response = test_app(&julia).oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", path);
+        }
     }
 }
@@ -0,0 +1,193 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+pub const ALGORITHMS: &[&str] = &["marching_cubes", "dual_contouring", "surface_nets"];
+pub const MAX_SMOOTHING_ITERATIONS: u64 = 50;
+
+const FIELDS: &[&str] = &[
+    "workspace_id",
+    "file_path",
+    "voxel_size",
+    "algorithm",
+    "target_edge_length",
+    "smoothing_iterations",
+];
+
+/// Validated body of `POST /api/mesh`.
+///
+/// Accepted schema (unknown fields are rejected):
+///
+/// | field                  | type   | required | constraint                     |
+/// |------------------------|--------|----------|--------------------------------|
+/// | `workspace_id`         | string | yes      | non-empty                      |
+/// | `algorithm`            | string | yes      | one of [`ALGORITHMS`]          |
+/// | `file_path`            | string | no       |                                |
+/// | `voxel_size`           | number | no       | > 0                            |
+/// | `target_edge_length`   | number | no       | > 0                            |
+/// | `smoothing_iterations` | int    | no       | 0..=[`MAX_SMOOTHING_ITERATIONS`] |
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MeshRequest {
+    pub workspace_id: String,
+    pub algorithm: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voxel_size: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_edge_length: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smoothing_iterations: Option<u32>,
+}
+
+impl MeshRequest {
+    /// Validate a raw JSON body, collecting every problem instead of stopping at the first.
+    pub fn from_value(value: &Value) -> Result<Self, Vec<String>> {
+        let Some(obj) = value.as_object() else {
+            return Err(vec!["request body must be a JSON object".to_string()]);
+        };
+
+        let mut problems = Vec::new();
+        for key in obj.keys() {
+            if !FIELDS.contains(&key.as_str()) {
+                problems.push(format!("unknown field `{}`", key));
+            }
+        }
+
+        let workspace_id = string_field(obj, "workspace_id", true, &mut problems);
+        let algorithm = string_field(obj, "algorithm", true, &mut problems);
+        let file_path = string_field(obj, "file_path", false, &mut problems);
+        let voxel_size = number_field(obj, "voxel_size", &mut problems);
+        let target_edge_length = number_field(obj, "target_edge_length", &mut problems);
+        let smoothing_iterations = integer_field(obj, "smoothing_iterations", &mut problems);
+
+        if matches!(&workspace_id, Some(id) if id.trim().is_empty()) {
+            problems.push("`workspace_id` must not be empty".to_string());
+        }
+        if let Some(algorithm) = &algorithm {
+            if !ALGORITHMS.contains(&algorithm.as_str()) {
+                problems.push(format!(
+                    "`algorithm` must be one of {}, got `{}`",
+                    ALGORITHMS.join(", "),
+                    algorithm
+                ));
+            }
+        }
+        for (name, value) in [("voxel_size", voxel_size), ("target_edge_length", target_edge_length)] {
+            if matches!(value, Some(v) if v <= 0.0) {
+                problems.push(format!("`{}` must be greater than 0", name));
+            }
+        }
+        if matches!(smoothing_iterations, Some(n) if n > MAX_SMOOTHING_ITERATIONS) {
+            problems.push(format!(
+                "`smoothing_iterations` must be between 0 and {}",
+                MAX_SMOOTHING_ITERATIONS
+            ));
+        }
+
+        match (workspace_id, algorithm) {
+            (Some(workspace_id), Some(algorithm)) if problems.is_empty() => Ok(Self {
+                workspace_id,
+                algorithm,
+                file_path,
+                voxel_size,
+                target_edge_length,
+                smoothing_iterations: smoothing_iterations.map(|n| n as u32),
+            }),
+            _ => Err(problems),
+        }
+    }
+}
+
+fn string_field(
+    obj: &Map<String, Value>,
+    name: &str,
+    required: bool,
+    problems: &mut Vec<String>,
+) -> Option<String> {
+    match obj.get(name) {
+        None | Some(Value::Null) => {
+            if required {
+                problems.push(format!("missing required field `{}`", name));
+            }
+            None
+        }
+        Some(Value::String(s)) => Some(s.clone()),
+        Some(_) => {
+            problems.push(format!("`{}` must be a string", name));
+            None
+        }
+    }
+}
+
+fn number_field(obj: &Map<String, Value>, name: &str, problems: &mut Vec<String>) -> Option<f64> {
+    match obj.get(name) {
+        None | Some(Value::Null) => None,
+        Some(v) => v.as_f64().or_else(|| {
+            problems.push(format!("`{}` must be a number", name));
+            None
+        }),
+    }
+}
+
+fn integer_field(obj: &Map<String, Value>, name: &str, problems: &mut Vec<String>) -> Option<u64> {
+    match obj.get(name) {
+        None | Some(Value::Null) => None,
+        Some(v) => v.as_u64().or_else(|| {
+            problems.push(format!("`{}` must be a non-negative integer", name));
+            None
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn accepts_valid_request() {
+        let req = MeshRequest::from_value(&json!({
+            "workspace_id": "ws-1",
+            "algorithm": "marching_cubes",
+            "target_edge_length": 0.5,
+            "smoothing_iterations": 10
+        }))
+        .unwrap();
+
+        assert_eq!(req.workspace_id, "ws-1");
+        assert_eq!(req.smoothing_iterations, Some(10));
+        assert_eq!(req.file_path, None);
+    }
+
+    #[test]
+    fn lists_missing_and_unknown_fields() {
+        let problems = MeshRequest::from_value(&json!({
+            "algorithm": "marching_cubes",
+            "smoothing": 3
+        }))
+        .unwrap_err();
+
+        assert_eq!(problems.len(), 2);
+        assert!(problems.contains(&"unknown field `smoothing`".to_string()));
+        assert!(problems.contains(&"missing required field `workspace_id`".to_string()));
+    }
+
+    #[test]
+    fn rejects_out_of_range_smoothing_iterations() {
+        let problems = MeshRequest::from_value(&json!({
+            "workspace_id": "ws-1",
+            "algorithm": "marching_cubes",
+            "smoothing_iterations": 500
+        }))
+        .unwrap_err();
+        assert_eq!(problems, vec!["`smoothing_iterations` must be between 0 and 50".to_string()]);
+
+        let problems = MeshRequest::from_value(&json!({
+            "workspace_id": "ws-1",
+            "algorithm": "marching_cubes",
+            "smoothing_iterations": -1
+        }))
+        .unwrap_err();
+        assert_eq!(problems, vec!["`smoothing_iterations` must be a non-negative integer".to_string()]);
+    }
+}
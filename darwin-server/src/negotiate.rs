@@ -0,0 +1,74 @@
+//! Response content negotiation: JSON by default, MessagePack on `Accept: application/msgpack`.
+
+use axum::{
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Json, Response},
+};
+use serde::Serialize;
+
+use crate::error::AppError;
+
+pub const MSGPACK_CONTENT_TYPE: &str = "application/msgpack";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    MsgPack,
+}
+
+impl Format {
+    /// MessagePack only when the client lists it in `Accept` (and not with `q=0`);
+    /// everything else, including `*/*` and no header at all, gets JSON.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let wants_msgpack = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|range| {
+                let mut parts = range.split(';').map(str::trim);
+                let media_type = parts.next().unwrap_or_default();
+                let rejected = parts.any(|p| p.strip_prefix("q=").and_then(|q| q.parse::<f32>().ok()) == Some(0.0));
+                !rejected
+                    && (media_type.eq_ignore_ascii_case(MSGPACK_CONTENT_TYPE)
+                        || media_type.eq_ignore_ascii_case("application/x-msgpack"))
+            });
+        if wants_msgpack { Format::MsgPack } else { Format::Json }
+    }
+}
+
+/// A successful body encoded in the negotiated format. Error responses stay JSON.
+pub struct Negotiated<T>(pub Format, pub T);
+
+impl<T: Serialize> IntoResponse for Negotiated<T> {
+    fn into_response(self) -> Response {
+        match self.0 {
+            Format::Json => Json(self.1).into_response(),
+            Format::MsgPack => match rmp_serde::to_vec_named(&self.1) {
+                Ok(bytes) => ([(header::CONTENT_TYPE, HeaderValue::from_static(MSGPACK_CONTENT_TYPE))], bytes).into_response(),
+                Err(e) => AppError::internal(format!("msgpack encoding failed: {}", e)).into_response(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accept(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn msgpack_is_opt_in() {
+        assert_eq!(Format::from_headers(&HeaderMap::new()), Format::Json);
+        assert_eq!(Format::from_headers(&accept("*/*")), Format::Json);
+        assert_eq!(Format::from_headers(&accept("application/json")), Format::Json);
+        assert_eq!(Format::from_headers(&accept("application/msgpack")), Format::MsgPack);
+        assert_eq!(Format::from_headers(&accept("application/json;q=0.5, application/x-msgpack")), Format::MsgPack);
+        assert_eq!(Format::from_headers(&accept("application/msgpack;q=0")), Format::Json);
+    }
+}
@@ -0,0 +1,123 @@
+//! Prometheus metrics: `GET /metrics`, per-route request counters and latencies,
+//! Julia call outcomes, open agent sockets and the Julia queue depth.
+//!
+//! The recorder is process-global; [`handle`] installs it on first use.
+
+use axum::{
+    extract::{MatchedPath, Request},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use std::{sync::OnceLock, time::Instant};
+
+pub const REQUESTS_TOTAL: &str = "http_requests_total";
+pub const REQUEST_DURATION: &str = "http_request_duration_seconds";
+pub const JULIA_REQUESTS_TOTAL: &str = "julia_requests_total";
+pub const AGENT_WS_CONNECTIONS: &str = "agent_ws_connections";
+/// Compute requests waiting for a Julia permit (see `limits::JuliaLimiter`)
+pub const JULIA_QUEUE_DEPTH: &str = "julia_queue_depth";
+
+/// Seconds; Julia-backed routes run from milliseconds up to the 10 minute timeout.
+const DURATION_BUCKETS: &[f64] = &[0.005, 0.025, 0.1, 0.25, 1.0, 2.5, 10.0, 30.0, 120.0, 600.0];
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// The installed recorder, installing it if this is the first call.
+pub fn handle() -> &'static PrometheusHandle {
+    HANDLE.get_or_init(|| {
+        PrometheusBuilder::new()
+            .set_buckets_for_metric(Matcher::Full(REQUEST_DURATION.to_string()), DURATION_BUCKETS)
+            .and_then(|builder| builder.install_recorder())
+            .expect("metrics recorder installed twice")
+    })
+}
+
+/// Middleware: count every request by route, method and status, and time it.
+///
+/// The route label is the matched pattern (`/api/upload/:upload_id/status`), not the
+/// raw path, so ids don't blow up the label set.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = request.method().to_string();
+    let started = Instant::now();
+
+    let response = next.run(request).await;
+
+    let status = response.status().as_u16().to_string();
+    metrics::counter!(REQUESTS_TOTAL, "route" => route.clone(), "method" => method.clone(), "status" => status)
+        .increment(1);
+    metrics::histogram!(REQUEST_DURATION, "route" => route, "method" => method)
+        .record(started.elapsed().as_secs_f64());
+    response
+}
+
+/// How a call to Julia ended, for `julia_requests_total{endpoint,outcome}`.
+pub fn record_julia_outcome(endpoint: &str, outcome: &'static str) {
+    metrics::counter!(JULIA_REQUESTS_TOTAL, "endpoint" => endpoint.to_string(), "outcome" => outcome).increment(1);
+}
+
+/// Raises `gauge` while alive and lowers it again on drop.
+pub struct GaugeGuard(&'static str);
+
+impl GaugeGuard {
+    pub fn new(gauge: &'static str) -> Self {
+        metrics::gauge!(gauge).increment(1.0);
+        Self(gauge)
+    }
+}
+
+impl Drop for GaugeGuard {
+    fn drop(&mut self) {
+        metrics::gauge!(self.0).decrement(1.0);
+    }
+}
+
+/// `GET /metrics` - everything recorded so far, in Prometheus text format.
+pub async fn metrics_handler() -> Response {
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], handle().render()).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn requests_are_counted_by_route_pattern() {
+        handle();
+        let app = Router::new()
+            .route("/api/files/:file_id", get(|| async { "ok" }))
+            .route("/metrics", get(metrics_handler))
+            .layer(middleware::from_fn(track_requests));
+
+        let request = Request::get("/api/files/abc-123").body(Body::empty()).unwrap();
+        app.clone().oneshot(request).await.unwrap();
+        {
+            let _socket = GaugeGuard::new(AGENT_WS_CONNECTIONS);
+        }
+
+        let response = app.oneshot(Request::get("/metrics").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], CONTENT_TYPE);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(
+            text.lines().any(|line| line.starts_with(REQUESTS_TOTAL)
+                && line.contains(r#"route="/api/files/:file_id""#)
+                && line.contains(r#"status="200""#)),
+            "{}",
+            text
+        );
+        assert!(!text.contains("abc-123"));
+        assert!(text.contains(&format!("{}_bucket", REQUEST_DURATION)));
+        assert!(text.contains(AGENT_WS_CONNECTIONS));
+    }
+}
@@ -0,0 +1,272 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+
+use crate::{error::AppError, events, julia, AppState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Maximize,
+    Minimize,
+}
+
+/// One optimisation goal. With a `target`, the objective is the distance
+/// `|metric - target|` (normally minimised) instead of the raw metric.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Objective {
+    pub metric: String,
+    pub direction: Direction,
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<f64>,
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+/// Body of `POST /api/optimize`.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct OptimizationRequest {
+    pub porosity: f64,
+    pub pore_size: f64,
+    pub method: String,
+    pub resolution: f64,
+    pub material: Option<String>,
+    pub use_case: Option<String>,
+    #[serde(default)]
+    pub objectives: Vec<Objective>,
+}
+
+impl OptimizationRequest {
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if self.objectives.is_empty() {
+            problems.push("at least one objective is required".to_string());
+        }
+        for (i, objective) in self.objectives.iter().enumerate() {
+            if objective.metric.trim().is_empty() {
+                problems.push(format!("objectives[{}]: `metric` must not be empty", i));
+            }
+            if !objective.weight.is_finite() || objective.weight < 0.0 {
+                problems.push(format!("objectives[{}]: `weight` must be a non-negative number", i));
+            }
+        }
+        if !self.objectives.is_empty() && self.objectives.iter().all(|o| o.weight == 0.0) {
+            problems.push("at least one objective needs a positive weight".to_string());
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RankedCandidate {
+    pub rank: usize,
+    /// Weighted score in `[0, 1]`, higher is better
+    pub score: f64,
+    pub parameters: Value,
+    pub metrics: Map<String, Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub missing_metrics: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stl_path: Option<String>,
+}
+
+/// Candidates from a Julia reply: a Pareto front under `candidates`, or the legacy
+/// single result (`optimized_metrics`) attributed to the requested parameters.
+fn candidates_from(body: &Value, request: &OptimizationRequest) -> Vec<(Value, Map<String, Value>, Option<String>)> {
+    let stl_path = |v: &Value| v.get("stl_path").and_then(Value::as_str).map(str::to_string);
+
+    if let Some(candidates) = body.get("candidates").and_then(Value::as_array) {
+        return candidates
+            .iter()
+            .map(|c| {
+                let metrics = c.get("metrics").and_then(Value::as_object).cloned().unwrap_or_default();
+                (c.get("parameters").cloned().unwrap_or(Value::Null), metrics, stl_path(c))
+            })
+            .collect();
+    }
+
+    match body.get("optimized_metrics").and_then(Value::as_object) {
+        Some(metrics) => {
+            let parameters = serde_json::json!({
+                "porosity": request.porosity,
+                "pore_size": request.pore_size,
+                "method": request.method,
+                "resolution": request.resolution,
+            });
+            vec![(parameters, metrics.clone(), stl_path(body))]
+        }
+        None => Vec::new(),
+    }
+}
+
+/// Score candidates against `objectives` and sort best first.
+///
+/// Each objective is min-max normalised across the candidates (1 = best) so that
+/// metrics on different scales are comparable; the score is the weight-averaged
+/// sum. A candidate missing a metric scores 0 on that objective.
+pub fn rank(candidates: Vec<(Value, Map<String, Value>, Option<String>)>, objectives: &[Objective]) -> Vec<RankedCandidate> {
+    let objective_value = |metrics: &Map<String, Value>, o: &Objective| {
+        let v = metrics.get(&o.metric).and_then(Value::as_f64).filter(|v| v.is_finite())?;
+        Some(match o.target {
+            Some(target) => (v - target).abs(),
+            None => v,
+        })
+    };
+
+    let bounds: Vec<Option<(f64, f64)>> = objectives
+        .iter()
+        .map(|o| {
+            candidates
+                .iter()
+                .filter_map(|(_, metrics, _)| objective_value(metrics, o))
+                .fold(None, |acc: Option<(f64, f64)>, v| match acc {
+                    None => Some((v, v)),
+                    Some((lo, hi)) => Some((lo.min(v), hi.max(v))),
+                })
+        })
+        .collect();
+    let total_weight: f64 = objectives.iter().map(|o| o.weight).sum();
+
+    let mut ranked: Vec<RankedCandidate> = candidates
+        .into_iter()
+        .map(|(parameters, metrics, stl_path)| {
+            let mut missing_metrics = Vec::new();
+            let mut weighted = 0.0;
+            for (o, bounds) in objectives.iter().zip(&bounds) {
+                let (Some(v), Some((lo, hi))) = (objective_value(&metrics, o), bounds) else {
+                    missing_metrics.push(o.metric.clone());
+                    continue;
+                };
+                let normalised = if hi > lo {
+                    match o.direction {
+                        Direction::Maximize => (v - lo) / (hi - lo),
+                        Direction::Minimize => (hi - v) / (hi - lo),
+                    }
+                } else {
+                    1.0
+                };
+                weighted += o.weight * normalised;
+            }
+            RankedCandidate {
+                rank: 0,
+                score: if total_weight > 0.0 { weighted / total_weight } else { 0.0 },
+                parameters,
+                metrics,
+                missing_metrics,
+                stl_path,
+            }
+        })
+        .collect();
+
+    ranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+    for (i, candidate) in ranked.iter_mut().enumerate() {
+        candidate.rank = i + 1;
+    }
+    ranked
+}
+
+/// The body of an optimization request, validated.
+pub fn parse_request(payload: &Value) -> Result<OptimizationRequest, AppError> {
+    let invalid = |problems: Vec<String>| AppError::validation("invalid optimization request").with_detail("problems", problems);
+    let request: OptimizationRequest = serde_json::from_value(payload.clone()).map_err(|e| invalid(vec![e.to_string()]))?;
+    request.validate().map_err(invalid)?;
+    Ok(request)
+}
+
+/// `POST /api/optimize` - forward to Julia, then rank its candidates by the weighted objectives.
+pub async fn optimize_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<Value>,
+) -> Result<Json<Value>, AppError> {
+    optimize(&state, &headers, payload).await.map(Json)
+}
+
+/// The optimization behind [`optimize_handler`]: validation, the Julia call and the ranking.
+pub async fn optimize(state: &AppState, headers: &HeaderMap, payload: Value) -> Result<Value, AppError> {
+    let request = parse_request(&payload)?;
+    let workspace_id = events::ServerEvent::workspace_id(&payload);
+    let payload = serde_json::to_value(&request).unwrap_or_default();
+    let _workspace = state.workspace_locks.lock_for(&payload).await;
+    let body = julia::fetch_julia(state, "optimize", headers, payload).await?.into_body()?;
+
+    let candidates = candidates_from(&body, &request);
+    if candidates.is_empty() {
+        return Err(AppError::upstream(StatusCode::OK, "no_candidates", "Julia returned no optimization candidates")
+            .with_detail("source", "julia"));
+    }
+
+    state.events.publish(events::ServerEvent::OptimizationComplete {
+        workspace_id,
+        candidates: candidates.len(),
+    });
+    Ok(serde_json::json!({
+        "objectives": request.objectives,
+        "candidates": rank(candidates, &request.objectives),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn candidate(porosity: f64, modulus: f64) -> (Value, Map<String, Value>, Option<String>) {
+        let metrics = json!({"porosity": porosity, "elastic_modulus": modulus});
+        (json!({"porosity": porosity}), metrics.as_object().unwrap().clone(), None)
+    }
+
+    #[test]
+    fn ranks_by_weighted_normalised_score() {
+        let objectives = vec![
+            Objective { metric: "porosity".into(), direction: Direction::Maximize, weight: 1.0, target: None },
+            Objective { metric: "elastic_modulus".into(), direction: Direction::Minimize, weight: 3.0, target: Some(100.0) },
+        ];
+        let ranked = rank(vec![candidate(0.9, 400.0), candidate(0.7, 110.0), candidate(0.8, 100.0)], &objectives);
+
+        let order: Vec<f64> = ranked.iter().map(|c| c.metrics["porosity"].as_f64().unwrap()).collect();
+        assert_eq!(order, vec![0.8, 0.7, 0.9]);
+        assert_eq!(ranked[0].rank, 1);
+        assert!((ranked[0].score - (0.5 + 3.0) / 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn validation_requires_objectives_with_non_negative_weights() {
+        let request: OptimizationRequest = serde_json::from_value(json!({
+            "porosity": 0.9, "pore_size": 150.0, "method": "freeze-casting", "resolution": 10.0,
+            "objectives": [{"metric": "permeability", "direction": "maximize", "weight": -1}]
+        }))
+        .unwrap();
+        let problems = request.validate().unwrap_err();
+        assert!(problems.contains(&"objectives[0]: `weight` must be a non-negative number".to_string()));
+
+        let request = OptimizationRequest { objectives: vec![], ..request };
+        assert_eq!(request.validate().unwrap_err(), vec!["at least one objective is required".to_string()]);
+    }
+
+    #[test]
+    fn legacy_single_result_becomes_one_candidate() {
+        let request: OptimizationRequest = serde_json::from_value(json!({
+            "porosity": 0.9, "pore_size": 150.0, "method": "freeze-casting", "resolution": 10.0
+        }))
+        .unwrap();
+        let body = json!({"optimized_metrics": {"porosity": 0.88}, "stl_path": "/tmp/opt.stl", "status": "success"});
+
+        let candidates = candidates_from(&body, &request);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].2.as_deref(), Some("/tmp/opt.stl"));
+    }
+}
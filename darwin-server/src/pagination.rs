@@ -0,0 +1,64 @@
+//! Offset pagination for listing endpoints: `?limit=&cursor=` in, `{items, next_cursor}` out.
+//!
+//! The desktop app includes this file for its listing commands, so keep it free of
+//! anything but `serde`.
+
+use serde::Serialize;
+
+pub const DEFAULT_PAGE_LIMIT: usize = 50;
+pub const MAX_PAGE_LIMIT: usize = 500;
+
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Cut one page out of `items`, which must already be in listing order.
+/// `limit` defaults to `DEFAULT_PAGE_LIMIT` and is clamped to `1..=MAX_PAGE_LIMIT`;
+/// `cursor` is the `next_cursor` of the previous page, opaque to clients (currently
+/// the offset of the next item).
+pub fn paginate<T>(items: Vec<T>, limit: Option<usize>, cursor: Option<&str>) -> Result<Page<T>, String> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let offset = match cursor {
+        None | Some("") => 0,
+        Some(cursor) => cursor.parse::<usize>().map_err(|_| format!("invalid cursor '{}'", cursor))?,
+    };
+
+    let end = offset.saturating_add(limit);
+    let next_cursor = (end < items.len()).then(|| end.to_string());
+    let items = items.into_iter().skip(offset).take(limit).collect();
+    Ok(Page { items, next_cursor })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walks_pages_until_next_cursor_is_null() {
+        let items: Vec<u32> = (0..5).collect();
+
+        let first = paginate(items.clone(), Some(2), None).unwrap();
+        assert_eq!(first.items, vec![0, 1]);
+        assert_eq!(first.next_cursor.as_deref(), Some("2"));
+
+        let last = paginate(items.clone(), Some(2), Some("4")).unwrap();
+        assert_eq!(last.items, vec![4]);
+        assert_eq!(last.next_cursor, None);
+
+        // An exactly full last page has no next page either
+        let exact = paginate(items, Some(5), None).unwrap();
+        assert_eq!(exact.items.len(), 5);
+        assert_eq!(exact.next_cursor, None);
+    }
+
+    #[test]
+    fn limit_is_defaulted_and_capped() {
+        let items: Vec<u32> = (0..600).collect();
+        assert_eq!(paginate(items.clone(), None, None).unwrap().items.len(), DEFAULT_PAGE_LIMIT);
+        assert_eq!(paginate(items.clone(), Some(10_000), None).unwrap().items.len(), MAX_PAGE_LIMIT);
+        assert!(paginate(items, None, Some("abc")).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! One shared subscription to Julia's progress stream, rebroadcast on the event bus.
+//!
+//! Julia reports progress of long jobs as newline-delimited JSON on
+//! `GET /progress/stream`. Rather than every client holding its own upstream
+//! connection, a single task reads it and publishes each line as a
+//! `{"type":"progress","job_id":...}` event to all `/ws/events` subscribers.
+//! Publishing never waits on clients: the bus is bounded and a slow subscriber
+//! is told it lagged (see `events`).
+//!
+//! When the stream ends or cannot be opened the task publishes
+//! `{"type":"progress_stream_reconnecting"}` and retries with exponential backoff.
+//! A `404` means the backend has no progress stream at all; the task logs that
+//! once and stops instead.
+
+use futures::StreamExt;
+use serde_json::Value;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    events::{EventBus, ServerEvent},
+    julia_logs::byte_lines,
+};
+
+pub const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct ProgressRelay {
+    url: String,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl ProgressRelay {
+    pub fn new(julia_url: &str, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        Self {
+            url: format!("{}/progress/stream", julia_url),
+            initial_backoff,
+            max_backoff: max_backoff.max(initial_backoff),
+        }
+    }
+
+    /// Relay until `shutdown` is cancelled, or until Julia turns out not to stream progress.
+    pub fn spawn(self, events: EventBus, shutdown: CancellationToken) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = self.run(&events) => {}
+                _ = shutdown.cancelled() => {}
+            }
+        })
+    }
+
+    async fn run(&self, events: &EventBus) {
+        let client = reqwest::Client::new();
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 0u32;
+        loop {
+            match client.get(&self.url).send().await {
+                Ok(res) if res.status().is_success() => {
+                    tracing::info!(url = %self.url, "progress stream connected");
+                    backoff = self.initial_backoff;
+                    attempt = 0;
+                    let lines = byte_lines(res.bytes_stream());
+                    futures::pin_mut!(lines);
+                    while let Some(line) = lines.next().await {
+                        if let Some(event) = progress_event(&line) {
+                            events.publish(event);
+                        }
+                    }
+                    tracing::warn!(url = %self.url, "progress stream ended");
+                }
+                Ok(res) if res.status() == reqwest::StatusCode::NOT_FOUND => {
+                    tracing::info!(url = %self.url, "Julia has no progress stream; progress will not be relayed");
+                    return;
+                }
+                Ok(res) => tracing::warn!(url = %self.url, status = %res.status(), "progress stream refused"),
+                Err(e) => tracing::warn!(url = %self.url, error = %e, "progress stream unreachable"),
+            }
+
+            attempt += 1;
+            events.publish(ServerEvent::ProgressStreamReconnecting {
+                attempt,
+                retry_in_ms: backoff.as_millis() as u64,
+            });
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.max_backoff);
+        }
+    }
+}
+
+/// A progress line as an event; blank and non-JSON lines (keep-alives) yield `None`.
+fn progress_event(line: &str) -> Option<ServerEvent> {
+    let progress: Value = serde_json::from_str(line.trim()).ok()?;
+    if !progress.is_object() {
+        return None;
+    }
+    Some(ServerEvent::Progress {
+        job_id: progress.get("job_id").and_then(Value::as_str).map(str::to_string),
+        progress,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Router};
+    use serde_json::json;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    #[test]
+    fn lines_become_job_tagged_events() {
+        let event = progress_event(r#"{"job_id": "job-7", "stage": "meshing", "fraction": 0.5}"#).unwrap();
+        assert_eq!(
+            serde_json::to_value(event).unwrap(),
+            json!({
+                "type": "progress",
+                "job_id": "job-7",
+                "progress": {"job_id": "job-7", "stage": "meshing", "fraction": 0.5},
+            })
+        );
+        assert_eq!(progress_event(""), None);
+        assert_eq!(progress_event(": keep-alive"), None);
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_the_stream_drops() {
+        // Each connection sends one line for its own job, then closes
+        let counter = Arc::new(AtomicUsize::new(0));
+        let app = Router::new().route(
+            "/progress/stream",
+            get(move || {
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                async move { format!("{}\n", json!({"job_id": format!("job-{}", n), "fraction": 1.0})) }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let events = EventBus::default();
+        let mut received = events.subscribe();
+        let shutdown = CancellationToken::new();
+        let relay = ProgressRelay::new(&format!("http://{}", addr), Duration::from_millis(10), Duration::from_millis(20));
+        let task = relay.spawn(events, shutdown.clone());
+
+        let mut seen = Vec::new();
+        while seen.len() < 3 {
+            seen.push(serde_json::to_value(received.recv().await.unwrap()).unwrap());
+        }
+        assert_eq!(seen[0]["job_id"], "job-0");
+        assert_eq!(seen[1]["type"], "progress_stream_reconnecting");
+        assert_eq!(seen[1]["attempt"], 1);
+        assert_eq!(seen[2]["job_id"], "job-1");
+
+        shutdown.cancel();
+        task.await.unwrap();
+    }
+    #[tokio::test]
+    async fn a_julia_without_the_stream_is_not_retried() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let app = Router::new().fallback(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { axum::http::StatusCode::NOT_FOUND }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let events = EventBus::default();
+        let mut received = events.subscribe();
+        let relay = ProgressRelay::new(&format!("http://{}", addr), Duration::from_millis(10), Duration::from_millis(20));
+        relay.spawn(events, CancellationToken::new()).await.unwrap();
+
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert!(received.try_recv().is_err());
+    }
+}
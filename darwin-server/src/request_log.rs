@@ -0,0 +1,179 @@
+//! The server's own recent requests, kept in memory for quick diagnosis.
+//!
+//! `logging::request_span` records every finished request into a bounded ring
+//! buffer (oldest entries are evicted past [`DEFAULT_CAPACITY`]), which
+//! `GET /api/requests` queries without any external log tooling.
+
+use axum::{
+    extract::{Query, State},
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use crate::{db::now_ms, error::AppError, AppState};
+
+pub const DEFAULT_CAPACITY: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RequestRecord {
+    /// When the request finished, Unix milliseconds
+    pub timestamp: u64,
+    pub request_id: String,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub latency_ms: u64,
+}
+
+impl RequestRecord {
+    pub fn finished_now(request_id: String, method: String, path: String, status: u16, latency_ms: u64) -> Self {
+        Self { timestamp: now_ms(), request_id, method, path, status, latency_ms }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RequestLog {
+    entries: Arc<Mutex<VecDeque<RequestRecord>>>,
+    capacity: usize,
+}
+
+impl Default for RequestLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl RequestLog {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self { entries: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))), capacity }
+    }
+
+    pub fn record(&self, record: RequestRecord) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(record);
+    }
+
+    /// Matching records, newest first.
+    pub fn query(&self, filter: &RequestFilter) -> Vec<RequestRecord> {
+        self.entries.lock().unwrap().iter().rev().filter(|r| filter.matches(r)).cloned().collect()
+    }
+}
+
+/// Which statuses `?status=` selects: a class such as `5xx`, or one exact code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusFilter {
+    Class(u16),
+    Exact(u16),
+}
+
+impl StatusFilter {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim().to_ascii_lowercase();
+        let invalid = || format!("`status` must be a class like `5xx` or a code like `404`, got `{}`", value);
+        match value.strip_suffix("xx") {
+            Some(class) => match class.parse::<u16>() {
+                Ok(class @ 1..=5) => Ok(Self::Class(class)),
+                _ => Err(invalid()),
+            },
+            None => match value.parse::<u16>() {
+                Ok(code @ 100..=599) => Ok(Self::Exact(code)),
+                _ => Err(invalid()),
+            },
+        }
+    }
+
+    fn matches(self, status: u16) -> bool {
+        match self {
+            Self::Class(class) => status / 100 == class,
+            Self::Exact(code) => status == code,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RequestFilter {
+    pub status: Option<StatusFilter>,
+    /// Substring of the request path
+    pub path: Option<String>,
+    /// Only requests that finished after this Unix millisecond timestamp
+    pub since: Option<u64>,
+}
+
+impl RequestFilter {
+    fn matches(&self, record: &RequestRecord) -> bool {
+        self.status.is_none_or(|status| status.matches(record.status))
+            && self.path.as_deref().is_none_or(|path| record.path.contains(path))
+            && self.since.is_none_or(|since| record.timestamp > since)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RequestLogParams {
+    pub status: Option<String>,
+    pub path: Option<String>,
+    pub since: Option<u64>,
+}
+
+/// `GET /api/requests?status=5xx&path=/api/analyze&since=<unix ms>` - recent
+/// requests to this server, newest first, as `{"requests": [...]}`.
+pub async fn requests_handler(State(state): State<Arc<AppState>>, Query(params): Query<RequestLogParams>) -> Result<Json<serde_json::Value>, AppError> {
+    let status = params.status.as_deref().map(StatusFilter::parse).transpose().map_err(AppError::validation)?;
+    let filter = RequestFilter { status, path: params.path, since: params.since };
+    Ok(Json(serde_json::json!({"requests": state.request_log.query(&filter)})))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(n: u64, path: &str, status: u16) -> RequestRecord {
+        RequestRecord {
+            timestamp: 1_000 + n,
+            request_id: format!("req-{}", n),
+            method: "POST".to_string(),
+            path: path.to_string(),
+            status,
+            latency_ms: n,
+        }
+    }
+
+    #[test]
+    fn evicts_the_oldest_past_the_cap() {
+        let log = RequestLog::new(3);
+        for n in 0..5 {
+            log.record(record(n, "/api/analyze", 200));
+        }
+        let ids: Vec<String> = log.query(&RequestFilter::default()).into_iter().map(|r| r.request_id).collect();
+        assert_eq!(ids, ["req-4", "req-3", "req-2"]);
+    }
+
+    #[test]
+    fn filters_by_status_class_path_and_time() {
+        let log = RequestLog::default();
+        log.record(record(0, "/api/analyze", 502));
+        log.record(record(1, "/api/upload", 500));
+        log.record(record(2, "/api/analyze", 200));
+        log.record(record(3, "/api/analyze", 504));
+
+        let ids = |filter: RequestFilter| -> Vec<String> {
+            log.query(&filter).into_iter().map(|r| r.request_id).collect()
+        };
+        let server_errors = Some(StatusFilter::parse("5xx").unwrap());
+        assert_eq!(ids(RequestFilter { status: server_errors, ..Default::default() }), ["req-3", "req-1", "req-0"]);
+        assert_eq!(
+            ids(RequestFilter { status: server_errors, path: Some("analyze".to_string()), since: Some(1_000) }),
+            ["req-3"]
+        );
+        assert_eq!(ids(RequestFilter { status: Some(StatusFilter::parse("200").unwrap()), ..Default::default() }), ["req-2"]);
+        assert!(StatusFilter::parse("6xx").is_err());
+        assert!(StatusFilter::parse("ok").is_err());
+    }
+}
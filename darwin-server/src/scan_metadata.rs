@@ -0,0 +1,270 @@
+//! Voxel spacing and volume size extraction from medical image headers (NIfTI-1/2, DICOM).
+//!
+//! Only the header is read; anything unrecognised yields `None` so callers can
+//! simply omit the metadata.
+
+use flate2::read::GzDecoder;
+use std::{fs::File, io::Read, path::Path};
+
+/// DICOM headers can be long (private tags, embedded sequences) but sit before the pixel data.
+const MAX_HEADER_BYTES: u64 = 1024 * 1024;
+
+/// Explicit VRs whose length is a reserved u16 followed by a u32
+const LONG_VRS: &[&[u8]] = &[
+    b"OB", b"OD", b"OF", b"OL", b"OV", b"OW", b"SQ", b"SV", b"UC", b"UN", b"UR", b"UT", b"UV",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanMetadata {
+    /// Voxel spacing along x, y, z in millimetres
+    pub voxel_size_mm: [f64; 3],
+    /// Voxels along x, y, z, when the header records them
+    pub dimensions: Option<[u64; 3]>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Nifti,
+    NiftiGz,
+    Dicom,
+}
+
+fn format_of(file_name: &str) -> Option<Format> {
+    let name = file_name.to_ascii_lowercase();
+    if name.ends_with(".nii.gz") {
+        Some(Format::NiftiGz)
+    } else if name.ends_with(".nii") {
+        Some(Format::Nifti)
+    } else if name.ends_with(".dcm") || name.ends_with(".dicom") {
+        Some(Format::Dicom)
+    } else {
+        None
+    }
+}
+
+/// Read the header of a stored upload. `file_name` is the original client file name.
+pub fn from_file(path: &Path, file_name: &str) -> Option<ScanMetadata> {
+    let format = format_of(file_name)?;
+    let file = File::open(path).ok()?;
+
+    let mut header = Vec::new();
+    match format {
+        Format::NiftiGz => GzDecoder::new(file).take(MAX_HEADER_BYTES).read_to_end(&mut header),
+        _ => file.take(MAX_HEADER_BYTES).read_to_end(&mut header),
+    }
+    .ok()?;
+
+    match format {
+        Format::Nifti | Format::NiftiGz => parse_nifti(&header),
+        Format::Dicom => parse_dicom(&header),
+    }
+}
+
+/// NIfTI-1 (348-byte) or NIfTI-2 (540-byte) header, either endianness.
+pub fn parse_nifti(bytes: &[u8]) -> Option<ScanMetadata> {
+    let raw = bytes.get(..4)?.try_into().ok()?;
+    let (little, version) = match (i32::from_le_bytes(raw), i32::from_be_bytes(raw)) {
+        (348, _) => (true, 1),
+        (_, 348) => (false, 1),
+        (540, _) => (true, 2),
+        (_, 540) => (false, 2),
+        _ => return None,
+    };
+
+    let f32_at = |offset: usize| -> Option<f64> {
+        let raw = bytes.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if little { f32::from_le_bytes(raw) } else { f32::from_be_bytes(raw) } as f64)
+    };
+    let f64_at = |offset: usize| -> Option<f64> {
+        let raw = bytes.get(offset..offset + 8)?.try_into().ok()?;
+        Some(if little { f64::from_le_bytes(raw) } else { f64::from_be_bytes(raw) })
+    };
+    let int_at = |offset: usize, width: usize| -> Option<i64> {
+        let raw = bytes.get(offset..offset + width)?;
+        Some(match (width, little) {
+            (2, true) => i16::from_le_bytes(raw.try_into().ok()?) as i64,
+            (2, false) => i16::from_be_bytes(raw.try_into().ok()?) as i64,
+            (_, true) => i64::from_le_bytes(raw.try_into().ok()?),
+            (_, false) => i64::from_be_bytes(raw.try_into().ok()?),
+        })
+    };
+
+    // dim[1..=3]: i16 at 42 (NIfTI-1) or i64 at 24 (NIfTI-2); unused axes are 0 or 1
+    let (dim_offset, dim_width) = if version == 1 { (42, 2) } else { (24, 8) };
+    let dimensions = (0..3)
+        .map(|i| int_at(dim_offset + i * dim_width, dim_width).map(|d| d.max(1) as u64))
+        .collect::<Option<Vec<u64>>>()
+        .and_then(|d| d.try_into().ok());
+
+    // pixdim[1..=3] and the spatial bits of xyzt_units
+    let (spacing, units) = if version == 1 {
+        ([f32_at(80)?, f32_at(84)?, f32_at(88)?], *bytes.get(123)? as i32)
+    } else {
+        let raw = bytes.get(500..504)?.try_into().ok()?;
+        let units = if little { i32::from_le_bytes(raw) } else { i32::from_be_bytes(raw) };
+        ([f64_at(112)?, f64_at(120)?, f64_at(128)?], units)
+    };
+
+    let voxel_size_mm = spacing.map(|v| match units & 0x07 {
+        1 => v.abs() * 1000.0,  // metre
+        3 => v.abs() / 1000.0,  // micron
+        _ => v.abs(),           // millimetre, or unknown (mm by convention)
+    });
+    voxel_size_mm
+        .iter()
+        .all(|v| v.is_finite() && *v > 0.0)
+        .then_some(ScanMetadata { voxel_size_mm, dimensions })
+}
+
+/// DICOM Part 10 file: `PixelSpacing` (0028,0030) and `SliceThickness` (0018,0050),
+/// plus `Columns` x `Rows` x `NumberOfFrames` for the size.
+pub fn parse_dicom(bytes: &[u8]) -> Option<ScanMetadata> {
+    if bytes.get(128..132)? != b"DICM" {
+        return None;
+    }
+
+    let mut pixel_spacing: Option<[f64; 2]> = None;
+    let mut slice_thickness: Option<f64> = None;
+    let (mut rows, mut columns, mut frames): (Option<u64>, Option<u64>, Option<u64>) = (None, None, None);
+
+    // File meta group is always explicit VR; the transfer syntax decides the rest
+    let mut explicit_vr = true;
+    let mut pos = 132;
+    while pos + 8 <= bytes.len() {
+        let group = u16::from_le_bytes([bytes[pos], bytes[pos + 1]]);
+        let element = u16::from_le_bytes([bytes[pos + 2], bytes[pos + 3]]);
+
+        if group == 0x7FE0 && element == 0x0010 {
+            break; // Pixel data: the header is over
+        }
+
+        let (value_len, header_len, descend) = if group == 0xFFFE {
+            // Item / delimiters: no VR, walk into item contents
+            (u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().ok()?), 8, true)
+        } else if explicit_vr || group == 0x0002 {
+            let vr = &bytes[pos + 4..pos + 6];
+            if LONG_VRS.contains(&vr) {
+                let len = u32::from_le_bytes(bytes.get(pos + 8..pos + 12)?.try_into().ok()?);
+                (len, 12, vr == b"SQ")
+            } else {
+                (u16::from_le_bytes([bytes[pos + 6], bytes[pos + 7]]) as u32, 8, false)
+            }
+        } else {
+            let len = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().ok()?);
+            // Implicit VR has no type info: undefined length can only be a sequence
+            (len, 8, len == u32::MAX)
+        };
+
+        pos += header_len;
+        if descend || value_len == u32::MAX {
+            continue;
+        }
+
+        let end = pos.checked_add(value_len as usize)?;
+        let Some(value) = bytes.get(pos..end) else {
+            break;
+        };
+
+        match (group, element) {
+            (0x0002, 0x0010) => {
+                let uid = String::from_utf8_lossy(value);
+                explicit_vr = uid.trim_end_matches(['\0', ' ']) != "1.2.840.10008.1.2";
+            }
+            (0x0028, 0x0030) => {
+                let parts = decimal_strings(value);
+                if let [row, col] = parts[..] {
+                    pixel_spacing = Some([row, col]);
+                }
+            }
+            (0x0018, 0x0050) => slice_thickness = decimal_strings(value).first().copied(),
+            (0x0028, 0x0010) if value.len() == 2 => rows = Some(u16::from_le_bytes([value[0], value[1]]) as u64),
+            (0x0028, 0x0011) if value.len() == 2 => columns = Some(u16::from_le_bytes([value[0], value[1]]) as u64),
+            (0x0028, 0x0008) => frames = decimal_strings(value).first().map(|n| n.max(1.0) as u64),
+            _ => {}
+        }
+        pos = end;
+    }
+
+    // PixelSpacing is (row spacing = y, column spacing = x)
+    let [row, col] = pixel_spacing?;
+    let voxel_size_mm = [col, row, slice_thickness?];
+    let dimensions = match (columns, rows) {
+        (Some(columns), Some(rows)) => Some([columns, rows, frames.unwrap_or(1)]),
+        _ => None,
+    };
+    voxel_size_mm
+        .iter()
+        .all(|v| v.is_finite() && *v > 0.0)
+        .then_some(ScanMetadata { voxel_size_mm, dimensions })
+}
+
+/// Parse a DICOM `DS` (decimal string) value, which may be multi-valued (`\`).
+fn decimal_strings(value: &[u8]) -> Vec<f64> {
+    String::from_utf8_lossy(value)
+        .split('\\')
+        .filter_map(|s| s.trim_matches(|c: char| c == '\0' || c.is_whitespace()).parse().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NIFTI1_MICRONS: &[u8] = include_bytes!("../tests/fixtures/header_um.nii");
+    const DICOM_EXPLICIT: &[u8] = include_bytes!("../tests/fixtures/slice_explicit.dcm");
+    const DICOM_IMPLICIT: &[u8] = include_bytes!("../tests/fixtures/slice_implicit.dcm");
+
+    #[test]
+    fn nifti1_spacing_is_converted_to_mm() {
+        let meta = parse_nifti(NIFTI1_MICRONS).unwrap();
+        assert_eq!(meta.voxel_size_mm, [0.01, 0.01, 0.02]);
+        assert_eq!(meta.dimensions, Some([64, 64, 32]));
+    }
+
+    #[test]
+    fn nifti_rejects_non_nifti_bytes() {
+        assert_eq!(parse_nifti(b"not a nifti header at all"), None);
+        assert_eq!(parse_nifti(&NIFTI1_MICRONS[..100]), None);
+    }
+
+    #[test]
+    fn dicom_explicit_vr_spacing() {
+        let meta = parse_dicom(DICOM_EXPLICIT).unwrap();
+        assert_eq!(meta.voxel_size_mm, [0.25, 0.5, 1.5]);
+    }
+
+    #[test]
+    fn dicom_size_from_rows_columns_and_frames() {
+        // Insert Rows, Columns and NumberOfFrames right before the pixel data element
+        let pixel_data = DICOM_EXPLICIT.windows(4).position(|w| w == [0xE0, 0x7F, 0x10, 0x00]).unwrap();
+        let mut bytes = DICOM_EXPLICIT[..pixel_data].to_vec();
+        bytes.extend_from_slice(&[0x28, 0x00, 0x08, 0x00, b'I', b'S', 2, 0, b'4', b'0']);
+        bytes.extend_from_slice(&[0x28, 0x00, 0x10, 0x00, b'U', b'S', 2, 0, 0x00, 0x02]);
+        bytes.extend_from_slice(&[0x28, 0x00, 0x11, 0x00, b'U', b'S', 2, 0, 0x80, 0x01]);
+        bytes.extend_from_slice(&DICOM_EXPLICIT[pixel_data..]);
+
+        let meta = parse_dicom(&bytes).unwrap();
+        assert_eq!(meta.dimensions, Some([384, 512, 40]));
+        assert_eq!(parse_dicom(DICOM_EXPLICIT).unwrap().dimensions, None);
+    }
+
+    #[test]
+    fn dicom_implicit_vr_spacing() {
+        let meta = parse_dicom(DICOM_IMPLICIT).unwrap();
+        assert_eq!(meta.voxel_size_mm, [0.25, 0.5, 1.5]);
+    }
+
+    #[test]
+    fn dicom_without_spacing_tags_is_none() {
+        let mut truncated = DICOM_EXPLICIT[..132].to_vec();
+        truncated.extend_from_slice(&[0x08, 0x00, 0x60, 0x00, b'C', b'S', 2, 0, b'C', b'T']);
+        assert_eq!(parse_dicom(&truncated), None);
+        assert_eq!(parse_dicom(b"DICM"), None);
+    }
+
+    #[test]
+    fn unknown_extensions_are_skipped() {
+        assert_eq!(format_of("scan.tif"), None);
+        assert_eq!(format_of("Brain.NII.GZ"), Some(Format::NiftiGz));
+    }
+}
@@ -0,0 +1,373 @@
+//! Where stored uploads live: the local upload dir, or an S3 bucket (`--features s3`).
+//!
+//! Objects are keyed by a flat file name, `{file_id}_{original name}`. Julia reads
+//! volumes from disk, so every backend keeps the objects it serves in a local
+//! directory: a file is written at [`Storage::local_path`] and then handed to
+//! [`Storage::put`], and [`Storage::get`] makes sure the object is back at that
+//! path before returning it. For [`LocalFsStorage`] the local path *is* the store.
+//!
+//! | variable         | default | meaning                                          |
+//! |------------------|---------|--------------------------------------------------|
+//! | `DARWIN_STORAGE` | `local` | `local` keeps uploads in `DARWIN_UPLOAD_DIR`; `s3` stores them in a bucket (see `s3_storage`) and caches them there |
+
+use async_trait::async_trait;
+use serde::Serialize;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::UNIX_EPOCH,
+};
+use uuid::Uuid;
+
+/// One object in [`Storage::list`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StoredObject {
+    pub key: String,
+    pub size: u64,
+    /// Last modification, Unix seconds
+    pub modified: u64,
+}
+
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// `local` or `s3`, as reported by `/api/config`
+    fn kind(&self) -> &'static str;
+
+    /// Where `key` is written before [`put`](Storage::put) and readable after [`get`](Storage::get)
+    fn local_path(&self, key: &str) -> PathBuf;
+
+    /// Store the file at `source` under `key`, taking the file over; it is left at
+    /// [`local_path`](Storage::local_path), which is returned.
+    async fn put(&self, key: &str, source: &Path) -> io::Result<PathBuf>;
+
+    /// Local path of `key`, fetched first if the backend is remote; `None` if there is no such object
+    async fn get(&self, key: &str) -> io::Result<Option<PathBuf>>;
+
+    /// Remove `key`; `false` if there was nothing to remove
+    async fn delete(&self, key: &str) -> io::Result<bool>;
+
+    /// Objects whose key starts with `prefix`, in no particular order
+    async fn list(&self, prefix: &str) -> io::Result<Vec<StoredObject>>;
+
+    async fn exists(&self, key: &str) -> io::Result<bool>;
+}
+
+/// `DARWIN_STORAGE` and, for S3, the bucket settings.
+#[derive(Debug, Clone, Default)]
+pub enum StorageSettings {
+    #[default]
+    Local,
+    #[cfg(feature = "s3")]
+    S3(crate::s3_storage::S3Settings),
+}
+
+/// Read `DARWIN_STORAGE`; `s3` needs a server built with `--features s3`.
+pub fn settings_from(var: impl Fn(&str) -> Option<String>) -> Result<StorageSettings, String> {
+    match var("DARWIN_STORAGE").map(|kind| kind.to_ascii_lowercase()).as_deref() {
+        None | Some("local") => Ok(StorageSettings::Local),
+        #[cfg(feature = "s3")]
+        Some("s3") => crate::s3_storage::settings_from(var).map(StorageSettings::S3),
+        #[cfg(not(feature = "s3"))]
+        Some("s3") => Err("DARWIN_STORAGE: `s3` needs a server built with `--features s3`".to_string()),
+        Some(other) => Err(format!("DARWIN_STORAGE: `{}` is not `local` or `s3`", other)),
+    }
+}
+
+/// The backend `settings` select, keeping local copies in `upload_dir`.
+pub fn from_settings(settings: &StorageSettings, upload_dir: PathBuf) -> Arc<dyn Storage> {
+    match settings {
+        StorageSettings::Local => Arc::new(LocalFsStorage::new(upload_dir)),
+        #[cfg(feature = "s3")]
+        StorageSettings::S3(s3) => Arc::new(crate::s3_storage::S3Storage::new(s3.clone(), upload_dir)),
+    }
+}
+
+/// Keys are single file names: no separators, no `..`, nothing hidden.
+pub fn check_key(key: &str) -> io::Result<()> {
+    if key.is_empty() || key.starts_with('.') || key.contains(['/', '\\', '\0']) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid storage key `{}`", key)));
+    }
+    Ok(())
+}
+
+/// Key of a stored upload
+pub fn upload_key(file_id: Uuid, file_name: &str) -> String {
+    format!("{}_{}", file_id, file_name)
+}
+
+/// Local path of the stored upload with id `file_id`, if there is one.
+pub async fn find_upload(storage: &dyn Storage, file_id: &str) -> io::Result<Option<PathBuf>> {
+    let Ok(id) = Uuid::parse_str(file_id) else {
+        return Ok(None);
+    };
+    let prefix = format!("{}_", id);
+    match storage.list(&prefix).await?.into_iter().next() {
+        Some(object) => storage.get(&object.key).await,
+        None => Ok(None),
+    }
+}
+
+/// Move `source` to `dest`, copying when they are on different filesystems.
+pub async fn move_file(source: &Path, dest: &Path) -> io::Result<()> {
+    if source == dest {
+        return Ok(());
+    }
+    if tokio::fs::rename(source, dest).await.is_ok() {
+        return Ok(());
+    }
+    tokio::fs::copy(source, dest).await?;
+    tokio::fs::remove_file(source).await
+}
+
+/// Uploads kept as plain files in one directory, as the server always has.
+#[derive(Debug, Clone)]
+pub struct LocalFsStorage {
+    root: PathBuf,
+}
+
+impl LocalFsStorage {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+}
+
+#[async_trait]
+impl Storage for LocalFsStorage {
+    fn kind(&self) -> &'static str {
+        "local"
+    }
+
+    fn local_path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+
+    async fn put(&self, key: &str, source: &Path) -> io::Result<PathBuf> {
+        check_key(key)?;
+        let path = self.local_path(key);
+        move_file(source, &path).await?;
+        Ok(path)
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Option<PathBuf>> {
+        Ok(self.exists(key).await?.then(|| self.local_path(key)))
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<bool> {
+        check_key(key)?;
+        match tokio::fs::remove_file(self.local_path(key)).await {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> io::Result<Vec<StoredObject>> {
+        let mut entries = match tokio::fs::read_dir(&self.root).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut objects = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let key = entry.file_name().to_string_lossy().into_owned();
+            // Scratch space such as `.partial/` is not part of the store
+            if !key.starts_with(prefix) || check_key(&key).is_err() {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata().await else { continue };
+            if !metadata.is_file() {
+                continue;
+            }
+            objects.push(StoredObject {
+                key,
+                size: metadata.len(),
+                modified: metadata
+                    .modified()
+                    .ok()
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |d| d.as_secs()),
+            });
+        }
+        Ok(objects)
+    }
+
+    async fn exists(&self, key: &str) -> io::Result<bool> {
+        check_key(key)?;
+        match tokio::fs::metadata(self.local_path(key)).await {
+            Ok(metadata) => Ok(metadata.is_file()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Objects held in memory, standing in for a remote store in tests: local copies
+/// live in their own scratch dir and are only re-created by `get`.
+#[cfg(test)]
+#[derive(Debug)]
+pub struct MemoryStorage {
+    scratch: PathBuf,
+    objects: std::sync::Mutex<std::collections::HashMap<String, (Vec<u8>, u64)>>,
+}
+
+#[cfg(test)]
+impl MemoryStorage {
+    pub fn new() -> Self {
+        let scratch = std::env::temp_dir().join(format!("darwin_memory_storage_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&scratch).unwrap();
+        Self { scratch, objects: Default::default() }
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.objects.lock().unwrap().keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    /// Drop the local copies, as a fresh server instance would start without them
+    pub fn evict_local_copies(&self) {
+        let _ = std::fs::remove_dir_all(&self.scratch);
+        std::fs::create_dir_all(&self.scratch).unwrap();
+    }
+}
+
+#[cfg(test)]
+impl Drop for MemoryStorage {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.scratch);
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Storage for MemoryStorage {
+    fn kind(&self) -> &'static str {
+        "memory"
+    }
+
+    fn local_path(&self, key: &str) -> PathBuf {
+        self.scratch.join(key)
+    }
+
+    async fn put(&self, key: &str, source: &Path) -> io::Result<PathBuf> {
+        check_key(key)?;
+        let bytes = tokio::fs::read(source).await?;
+        let path = self.local_path(key);
+        move_file(source, &path).await?;
+        let modified = std::time::SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        self.objects.lock().unwrap().insert(key.to_string(), (bytes, modified));
+        Ok(path)
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Option<PathBuf>> {
+        check_key(key)?;
+        let Some(bytes) = self.objects.lock().unwrap().get(key).map(|(bytes, _)| bytes.clone()) else {
+            return Ok(None);
+        };
+        let path = self.local_path(key);
+        tokio::fs::write(&path, bytes).await?;
+        Ok(Some(path))
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<bool> {
+        check_key(key)?;
+        let _ = tokio::fs::remove_file(self.local_path(key)).await;
+        Ok(self.objects.lock().unwrap().remove(key).is_some())
+    }
+
+    async fn list(&self, prefix: &str) -> io::Result<Vec<StoredObject>> {
+        let objects = self.objects.lock().unwrap();
+        Ok(objects
+            .iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .map(|(key, (bytes, modified))| StoredObject {
+                key: key.clone(),
+                size: bytes.len() as u64,
+                modified: *modified,
+            })
+            .collect())
+    }
+
+    async fn exists(&self, key: &str) -> io::Result<bool> {
+        check_key(key)?;
+        Ok(self.objects.lock().unwrap().contains_key(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What every backend must do
+    async fn check_contract(storage: &dyn Storage) {
+        let id = Uuid::new_v4();
+        let key = upload_key(id, "scan.tif");
+        assert!(!storage.exists(&key).await.unwrap());
+        assert_eq!(storage.get(&key).await.unwrap(), None);
+
+        let staged = storage.local_path(&format!("{}.staged", key));
+        tokio::fs::write(&staged, b"II*\0").await.unwrap();
+        let path = storage.put(&key, &staged).await.unwrap();
+        assert_eq!(path, storage.local_path(&key));
+        assert!(!staged.exists());
+        assert!(storage.exists(&key).await.unwrap());
+
+        let listed = storage.list(&format!("{}_", id)).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!((listed[0].key.as_str(), listed[0].size), (key.as_str(), 4));
+        assert!(storage.list(&format!("{}_", Uuid::new_v4())).await.unwrap().is_empty());
+
+        let found = find_upload(storage, &id.to_string()).await.unwrap().unwrap();
+        assert_eq!(tokio::fs::read(found).await.unwrap(), b"II*\0");
+        assert_eq!(find_upload(storage, "not-a-uuid").await.unwrap(), None);
+
+        for bad in ["", "../escape.tif", "nested/scan.tif", ".partial"] {
+            assert_eq!(storage.exists(bad).await.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        }
+
+        assert!(storage.delete(&key).await.unwrap());
+        assert!(!storage.delete(&key).await.unwrap());
+        assert!(!storage.exists(&key).await.unwrap());
+        assert_eq!(find_upload(storage, &id.to_string()).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn local_storage_keeps_files_in_its_root() {
+        let root = std::env::temp_dir().join(format!("darwin_storage_local_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(root.join(".partial")).unwrap();
+        let storage = LocalFsStorage::new(root.clone());
+        check_contract(&storage).await;
+
+        // Directories and hidden files are not objects
+        std::fs::write(root.join(".hidden"), b"x").unwrap();
+        assert!(storage.list("").await.unwrap().is_empty());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn memory_storage_restores_local_copies_on_get() {
+        let storage = MemoryStorage::new();
+        check_contract(&storage).await;
+
+        let key = upload_key(Uuid::new_v4(), "scan.nii");
+        let staged = storage.local_path("staged.nii");
+        tokio::fs::write(&staged, b"nifti").await.unwrap();
+        storage.put(&key, &staged).await.unwrap();
+        storage.evict_local_copies();
+        assert!(!storage.local_path(&key).exists());
+        let path = storage.get(&key).await.unwrap().unwrap();
+        assert_eq!(tokio::fs::read(path).await.unwrap(), b"nifti");
+    }
+
+    #[test]
+    fn storage_is_chosen_by_darwin_storage() {
+        let parse = |value: &str| settings_from(|name| (name == "DARWIN_STORAGE").then(|| value.to_string()));
+        assert!(matches!(settings_from(|_| None), Ok(StorageSettings::Local)));
+        assert!(matches!(parse("Local"), Ok(StorageSettings::Local)));
+        assert_eq!(parse("ftp").unwrap_err(), "DARWIN_STORAGE: `ftp` is not `local` or `s3`");
+        #[cfg(not(feature = "s3"))]
+        assert!(parse("s3").unwrap_err().contains("--features s3"));
+    }
+}
@@ -0,0 +1,768 @@
+use async_compression::tokio::bufread::GzipDecoder;
+use axum::{
+    extract::{
+        multipart::{Field, MultipartError},
+        Multipart, Query, State,
+    },
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio_util::io::StreamReader;
+use uuid::Uuid;
+
+use crate::{
+    db::{now_ms, Db, UploadRecord},
+    error::AppError,
+    file_format::{self, FileFormat},
+    pagination::{self, Page},
+    scan_metadata,
+    storage::{self, Storage},
+    AppState,
+};
+
+/// Default upper bound on a single upload request (see `DARWIN_MAX_UPLOAD_BYTES`).
+pub const MAX_UPLOAD_BYTES: usize = 1024 * 1024 * 1024;
+
+/// Volume formats the Julia image loader understands, and STL meshes.
+/// `.raw.gz` is decompressed on upload and stored as `.raw`.
+pub const ALLOWED_EXTENSIONS: &[&str] = &[
+    ".tif", ".tiff", ".nii", ".nii.gz", ".dcm", ".dicom", ".raw", ".raw.gz", ".png", ".bmp", ".jpg", ".jpeg", ".stl",
+];
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Failure modes of `POST /api/upload` and the chunked `/api/upload/...` routes.
+///
+/// Rendered through [`AppError::Upload`], so `code` names the variant.
+#[derive(Debug)]
+pub enum UploadError {
+    /// The multipart body had no file field (400).
+    NoFileField,
+    /// The multipart body or the file field could not be read (400).
+    FieldReadError(String),
+    /// The file could not be stored on disk (500).
+    WriteError(String),
+    /// A gzipped `.raw` upload could not be decompressed (400).
+    InvalidGzip(String),
+    /// The request body exceeded the route's size limit, in bytes (413).
+    TooLarge(usize),
+    /// The file name does not end in one of [`ALLOWED_EXTENSIONS`] (415).
+    InvalidExtension(String),
+    /// The contents match none of the formats [`file_format`] recognises (415).
+    UnsupportedFormat(String),
+    /// A chunked upload request was malformed (400).
+    InvalidRequest(String),
+    /// No chunked upload with this id, or it expired (404).
+    UnknownUpload(String),
+    /// `complete` was called before every chunk arrived (409).
+    Incomplete(Vec<u32>),
+    /// A `PATCH` did not start where the upload stands, in bytes (409).
+    OffsetMismatch(u64),
+    /// The assembled file does not match the client's SHA-256 (422).
+    ChecksumMismatch,
+    /// The upload dir could not be listed (500).
+    ListError(String),
+}
+
+impl UploadError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::NoFileField | Self::FieldReadError(_) | Self::InvalidRequest(_) | Self::InvalidGzip(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            Self::WriteError(_) | Self::ListError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::InvalidExtension(_) | Self::UnsupportedFormat(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::UnknownUpload(_) => StatusCode::NOT_FOUND,
+            Self::Incomplete(_) | Self::OffsetMismatch(_) => StatusCode::CONFLICT,
+            Self::ChecksumMismatch => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NoFileField => "no_file_field",
+            Self::FieldReadError(_) => "field_read_error",
+            Self::WriteError(_) => "write_error",
+            Self::InvalidGzip(_) => "invalid_gzip",
+            Self::TooLarge(_) => "too_large",
+            Self::InvalidExtension(_) => "invalid_extension",
+            Self::UnsupportedFormat(_) => "unsupported_format",
+            Self::InvalidRequest(_) => "invalid_request",
+            Self::UnknownUpload(_) => "unknown_upload",
+            Self::Incomplete(_) => "incomplete",
+            Self::OffsetMismatch(_) => "offset_mismatch",
+            Self::ChecksumMismatch => "checksum_mismatch",
+            Self::ListError(_) => "list_error",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Self::NoFileField => "multipart body has no file field".to_string(),
+            Self::FieldReadError(e) => format!("failed to read upload: {}", e),
+            Self::WriteError(e) => format!("failed to store upload: {}", e),
+            Self::InvalidGzip(e) => format!("failed to decompress gzipped upload: {}", e),
+            Self::TooLarge(limit) => format!("upload exceeds the {} byte limit", limit),
+            Self::InvalidExtension(name) => format!(
+                "unsupported file type `{}`, expected one of {}",
+                name,
+                ALLOWED_EXTENSIONS.join(", ")
+            ),
+            Self::UnsupportedFormat(name) => {
+                format!("`{}` is not a recognised STL, TIFF, DICOM, NIfTI or image file", name)
+            }
+            Self::InvalidRequest(e) => e.clone(),
+            Self::UnknownUpload(id) => format!("no upload in progress with id {}", id),
+            Self::Incomplete(missing) => format!("{} chunk(s) still missing", missing.len()),
+            Self::OffsetMismatch(offset) => format!("upload continues at byte {}", offset),
+            Self::ChecksumMismatch => "assembled file does not match the provided sha256".to_string(),
+            Self::ListError(e) => format!("failed to list uploads: {}", e),
+        }
+    }
+}
+
+impl UploadError {
+    /// The body limit (`limit` bytes) surfaces as a multipart read error carrying 413.
+    fn from_multipart(e: MultipartError, limit: usize) -> Self {
+        if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            Self::TooLarge(limit)
+        } else {
+            Self::FieldReadError(e.body_text())
+        }
+    }
+}
+
+impl UploadError {
+    /// Context rendered next to `code` and `message`
+    pub fn details(&self) -> Map<String, Value> {
+        let mut details = Map::new();
+        match self {
+            Self::Incomplete(missing) => {
+                details.insert("missing_chunks".to_string(), serde_json::json!(missing));
+            }
+            Self::OffsetMismatch(offset) => {
+                details.insert("upload_offset".to_string(), serde_json::json!(offset));
+            }
+            _ => {}
+        }
+        details
+    }
+}
+
+impl IntoResponse for UploadError {
+    fn into_response(self) -> Response {
+        AppError::Upload(self).into_response()
+    }
+}
+
+pub fn has_allowed_extension(file_name: &str) -> bool {
+    let name = file_name.to_ascii_lowercase();
+    ALLOWED_EXTENSIONS.iter().any(|ext| name.ends_with(ext))
+}
+
+/// Sniff a stored file's format from its contents; anything unrecognised is refused.
+pub async fn detect_format(path: &Path, file_name: &str) -> Result<FileFormat, UploadError> {
+    let (path, name) = (path.to_path_buf(), file_name.to_string());
+    let sniffed = tokio::task::spawn_blocking(move || file_format::sniff_file(&path, &name))
+        .await
+        .map_err(|e| UploadError::WriteError(e.to_string()))?
+        .map_err(|e| UploadError::WriteError(e.to_string()))?;
+    sniffed.ok_or_else(|| UploadError::UnsupportedFormat(file_name.to_string()))
+}
+
+/// Save what was stored under `key` to the database. A failed write is logged: the
+/// file is stored either way, it only lists without its `sha256` and `format`.
+pub async fn record_upload(state: &AppState, key: &str, file_id: Uuid, original_name: &str, size: u64, sha256: &str, format: FileFormat) {
+    let record = UploadRecord {
+        file_id: file_id.to_string(),
+        storage_key: key.to_string(),
+        original_name: original_name.to_string(),
+        size,
+        sha256: sha256.to_string(),
+        format: serde_json::json!(format).as_str().unwrap_or_default().to_string(),
+        created_at_ms: now_ms(),
+    };
+    if let Err(e) = state.db.record_upload(&record).await {
+        tracing::warn!(file_id = %file_id, "failed to record upload: {}", e);
+    }
+}
+
+/// Response body for a stored upload, shared by the single-shot and chunked paths.
+pub async fn stored_upload_response(file_path: PathBuf, file_id: Uuid, file_name: String) -> Value {
+    let mut response = serde_json::json!({
+        "file_path": file_path.to_string_lossy(),
+        "file_id": file_id.to_string(),
+        "original_name": file_name
+    });
+
+    // NIfTI/DICOM carry their voxel spacing; let the client prefill voxel_size
+    let metadata = tokio::task::spawn_blocking(move || scan_metadata::from_file(&file_path, &file_name))
+        .await
+        .ok()
+        .flatten();
+    if let Some(metadata) = metadata {
+        response["voxel_size_mm"] = serde_json::json!(metadata.voxel_size_mm);
+    }
+    response
+}
+
+/// Copy `reader` into `file_path`, failing once more than `limit` bytes come out
+/// of it. Returns the number of bytes written and their hex SHA-256.
+async fn write_limited<R: AsyncRead + Unpin>(
+    reader: R,
+    file_path: &Path,
+    limit: usize,
+    read_error: impl Fn(std::io::Error) -> UploadError,
+) -> Result<(u64, String), UploadError> {
+    let mut file = tokio::fs::File::create(file_path)
+        .await
+        .map_err(|e| UploadError::WriteError(e.to_string()))?;
+    let mut reader = reader.take(limit as u64 + 1);
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut written = 0u64;
+    loop {
+        let n = reader.read(&mut buf).await.map_err(&read_error)?;
+        if n == 0 {
+            break;
+        }
+        written += n as u64;
+        if written > limit as u64 {
+            return Err(UploadError::TooLarge(limit));
+        }
+        hasher.update(&buf[..n]);
+        file.write_all(&buf[..n]).await.map_err(|e| UploadError::WriteError(e.to_string()))?;
+    }
+    file.flush().await.map_err(|e| UploadError::WriteError(e.to_string()))?;
+    Ok((written, hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()))
+}
+
+/// Stream one file field into storage and describe it: the
+/// [`stored_upload_response`] fields plus the stored `size` and `sha256`.
+/// Returns the storage key with the descriptor.
+async fn store_file(state: &AppState, field: Field<'_>, limit: usize) -> Result<(String, Value), UploadError> {
+    let file_name = field.file_name().unwrap_or("upload.dat").to_string();
+    if !has_allowed_extension(&file_name) {
+        return Err(UploadError::InvalidExtension(file_name));
+    }
+
+    // Count what arrives on the wire separately from what is written
+    let received = Arc::new(AtomicU64::new(0));
+    let counter = received.clone();
+    let stream = field
+        .inspect_ok(move |chunk| {
+            counter.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        })
+        .map_err(std::io::Error::other);
+    let mut reader = StreamReader::new(stream);
+
+    // Multipart failures (including the body limit) travel through io::Error
+    let read_error = |e: std::io::Error| match e.into_inner().map(|inner| inner.downcast::<MultipartError>()) {
+        Some(Ok(multipart_error)) => UploadError::from_multipart(*multipart_error, limit),
+        Some(Err(other)) => UploadError::FieldReadError(other.to_string()),
+        None => UploadError::FieldReadError("unexpected end of upload".to_string()),
+    };
+    let lower = file_name.to_ascii_lowercase();
+    let gzipped = lower.ends_with(".raw.gz")
+        || (lower.ends_with(".raw") && reader.fill_buf().await.map_err(read_error)?.starts_with(&GZIP_MAGIC));
+    let stored_name = match file_name.len().checked_sub(3) {
+        Some(end) if gzipped && lower.ends_with(".gz") => file_name[..end].to_string(),
+        _ => file_name.clone(),
+    };
+
+    let file_id = Uuid::new_v4();
+    let key = storage::upload_key(file_id, &stored_name);
+    let file_path = state.storage.local_path(&key);
+
+    let written = if gzipped {
+        let decoder = GzipDecoder::new(reader);
+        write_limited(decoder, &file_path, limit, |e| match e.kind() {
+            std::io::ErrorKind::InvalidData | std::io::ErrorKind::UnexpectedEof => {
+                UploadError::InvalidGzip(e.to_string())
+            }
+            _ => read_error(e),
+        })
+        .await
+    } else {
+        write_limited(reader, &file_path, limit, read_error).await
+    };
+    let sniffed = match written {
+        Ok(written) => detect_format(&file_path, &stored_name).await.map(|format| (written, format)),
+        Err(e) => Err(e),
+    };
+    let (written, format) = match sniffed {
+        Ok(sniffed) => sniffed,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&file_path).await;
+            return Err(e);
+        }
+    };
+    let stored = state.storage.put(&key, &file_path).await.map(|path| (path, written));
+    let (file_path, (written, sha256)) = match stored {
+        Ok(stored) => stored,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&file_path).await;
+            return Err(UploadError::WriteError(e.to_string()));
+        }
+    };
+
+    record_upload(state, &key, file_id, &file_name, written, &sha256, format).await;
+    let mut response = stored_upload_response(file_path, file_id, file_name).await;
+    response["size"] = serde_json::json!(written);
+    response["sha256"] = serde_json::json!(sha256);
+    response["format"] = serde_json::json!(format);
+    if gzipped {
+        response["stored_name"] = serde_json::json!(stored_name);
+        response["compressed_bytes"] = serde_json::json!(received.load(Ordering::Relaxed));
+        response["decompressed_bytes"] = serde_json::json!(written);
+    }
+    Ok((key, response))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UploadParams {
+    /// Store only the first file and answer with its descriptor alone, as
+    /// `/api/upload` did before it accepted several files
+    #[serde(default)]
+    pub single: bool,
+}
+
+/// `POST /api/upload[?single=true]` - stream every file field into the upload dir.
+///
+/// Fields are copied to disk 64 KiB at a time as they arrive, so a multi-GB stack
+/// never sits in memory; `size` is the number of bytes written. A file that goes
+/// past `max_upload_bytes` (`DARWIN_MAX_UPLOAD_BYTES`) stops the copy with `413`
+/// and its partial file is removed.
+///
+/// Each stored file is sniffed by its magic bytes (STL, TIFF, DICOM, NIfTI, PNG,
+/// BMP, JPEG; `.raw` by name) and refused with `415 unsupported_format` when it is
+/// none of them; the detected `format` is part of its descriptor.
+///
+/// Answers `{"files": [descriptor, ...], "fields": {name: text}}`: one descriptor
+/// (`file_id`, `original_name`, `file_path`, `size`, `sha256`, `format`) per `file` field in
+/// request order, and the other text fields (e.g. `voxel_size`) as strings. If any
+/// file is rejected the ones already stored are removed. With `single=true` only
+/// the first file is stored and its descriptor is the whole response.
+///
+/// Gzipped raw volumes (`.raw.gz`, or `.raw` starting with the gzip magic) are
+/// decompressed on the way and stored as `.raw`, with the decompressed size held to
+/// the same `max_upload_bytes` limit; the descriptor then also carries
+/// `compressed_bytes` and `decompressed_bytes`.
+pub async fn upload_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<UploadParams>,
+    mut multipart: Multipart,
+) -> Result<Json<Value>, UploadError> {
+    let limit = state.config.max_upload_bytes;
+    let mut files: Vec<Value> = Vec::new();
+    let mut keys: Vec<String> = Vec::new();
+    let mut fields = serde_json::Map::new();
+
+    let outcome = async {
+        while let Some(field) = multipart
+            .next_field()
+            .await
+            .map_err(|e| UploadError::from_multipart(e, limit))?
+        {
+            let Some(name) = field.name().map(str::to_string) else { continue };
+            if name != "file" {
+                // Files under other names are skipped, as they always were
+                if field.file_name().is_none() {
+                    let text = field.text().await.map_err(|e| UploadError::from_multipart(e, limit))?;
+                    fields.insert(name, Value::String(text));
+                }
+                continue;
+            }
+
+            let (key, file) = store_file(&state, field, limit).await?;
+            keys.push(key);
+            files.push(file);
+            if params.single {
+                break;
+            }
+        }
+        Ok(())
+    }
+    .await;
+
+    if let Err(e) = outcome {
+        for key in &keys {
+            let _ = state.storage.delete(key).await;
+            let _ = state.db.forget_upload(key).await;
+        }
+        return Err(e);
+    }
+    if files.is_empty() {
+        return Err(UploadError::NoFileField);
+    }
+
+    if params.single {
+        return Ok(Json(files.swap_remove(0)));
+    }
+    Ok(Json(serde_json::json!({"files": files, "fields": fields})))
+}
+
+/// One stored upload in `GET /api/uploads`.
+#[derive(Debug, Serialize)]
+pub struct StoredUpload {
+    pub file_id: String,
+    pub original_name: String,
+    pub file_path: String,
+    pub size: u64,
+    /// Last modification, Unix seconds
+    pub modified: u64,
+    /// From the database, for uploads stored through `/api/upload`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+}
+
+/// Stored uploads (`{file_id}_{original name}`), newest first, with what the
+/// database recorded about them. Objects that are not named like an upload are skipped.
+async fn stored_uploads(storage: &dyn Storage, db: &Db) -> Result<Vec<StoredUpload>, UploadError> {
+    let objects = storage.list("").await.map_err(|e| UploadError::ListError(e.to_string()))?;
+    let mut records: HashMap<String, UploadRecord> = db
+        .uploads()
+        .await
+        .map_err(|e| UploadError::ListError(e.to_string()))?
+        .into_iter()
+        .map(|record| (record.storage_key.clone(), record))
+        .collect();
+    let mut uploads: Vec<StoredUpload> = objects
+        .into_iter()
+        .filter_map(|object| {
+            let (file_id, original_name) = object.key.split_once('_')?;
+            let file_id = Uuid::parse_str(file_id).ok()?;
+            let record = records.remove(&object.key);
+            Some(StoredUpload {
+                file_id: file_id.to_string(),
+                original_name: original_name.to_string(),
+                file_path: storage.local_path(&object.key).to_string_lossy().into_owned(),
+                size: object.size,
+                modified: object.modified,
+                sha256: record.as_ref().map(|r| r.sha256.clone()),
+                format: record.map(|r| r.format),
+            })
+        })
+        .collect();
+    uploads.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| a.file_id.cmp(&b.file_id)));
+    Ok(uploads)
+}
+
+/// Query of [`list_uploads_handler`]
+#[derive(Debug, Default, Deserialize)]
+pub struct PageParams {
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+}
+
+/// `GET /api/uploads?limit=&cursor=` - stored uploads, most recently modified first.
+pub async fn list_uploads_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Page<StoredUpload>>, UploadError> {
+    let uploads = stored_uploads(state.storage.as_ref(), &state.db).await?;
+    pagination::paginate(uploads, params.limit, params.cursor.as_deref())
+        .map(Json)
+        .map_err(UploadError::InvalidRequest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, extract::DefaultBodyLimit, http::Request, routing::post, Router};
+    use tower::ServiceExt;
+
+    const BOUNDARY: &str = "darwin-test-boundary";
+
+    fn router(body_limit: usize) -> Router {
+        router_with_limit(body_limit, MAX_UPLOAD_BYTES)
+    }
+
+    fn router_with_limit(body_limit: usize, max_upload_bytes: usize) -> Router {
+        let mut state = AppState::for_tests("http://127.0.0.1:1");
+        state.config.max_upload_bytes = max_upload_bytes;
+        let state = Arc::new(state);
+        std::fs::create_dir_all(&state.upload_dir).unwrap();
+        Router::new()
+            .route("/api/upload", post(upload_handler))
+            .layer(DefaultBodyLimit::max(body_limit))
+            .with_state(state)
+    }
+
+    /// A multipart body of `(field name, file name, content)` parts; parts without
+    /// a file name are plain text fields.
+    fn multipart_parts(uri: &str, parts: &[(&str, Option<&str>, &[u8])]) -> Request<Body> {
+        let mut body = Vec::new();
+        for (field, file_name, content) in parts {
+            let disposition = match file_name {
+                Some(file_name) => format!(
+                    "Content-Disposition: form-data; name=\"{field}\"; filename=\"{file_name}\"\r\n\
+                     Content-Type: application/octet-stream"
+                ),
+                None => format!("Content-Disposition: form-data; name=\"{field}\""),
+            };
+            body.extend_from_slice(format!("--{}\r\n{}\r\n\r\n", BOUNDARY, disposition).as_bytes());
+            body.extend_from_slice(content);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+
+        Request::post(uri)
+            .header("content-type", format!("multipart/form-data; boundary={}", BOUNDARY))
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    /// A single-file upload answered in the `single=true` shape.
+    fn multipart(field: &str, file_name: &str, content: &[u8]) -> Request<Body> {
+        multipart_parts("/api/upload?single=true", &[(field, Some(file_name), content)])
+    }
+
+    async fn error_code(response: Response) -> (StatusCode, String) {
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        (status, body["error"]["code"].as_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn stores_allowed_file() {
+        let response = router(MAX_UPLOAD_BYTES).oneshot(multipart("file", "scan.tif", b"II*\0")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["original_name"], "scan.tif");
+        assert_eq!(body["format"], "tiff");
+        tokio::fs::remove_file(body["file_path"].as_str().unwrap()).await.unwrap();
+
+        let stl = b"solid cube\n facet normal 0 0 1\n  outer loop\n";
+        let response = router(MAX_UPLOAD_BYTES).oneshot(multipart("file", "cube.stl", stl)).await.unwrap();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["format"], "stl");
+        tokio::fs::remove_file(body["file_path"].as_str().unwrap()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn maps_each_failure_to_its_status() {
+        let cases = [
+            (multipart("other", "scan.tif", b"x"), MAX_UPLOAD_BYTES, StatusCode::BAD_REQUEST, "no_file_field"),
+            (multipart("file", "notes.exe", b"x"), MAX_UPLOAD_BYTES, StatusCode::UNSUPPORTED_MEDIA_TYPE, "invalid_extension"),
+            (multipart("file", "scan.tif", b"MZ\x90\0"), MAX_UPLOAD_BYTES, StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported_format"),
+            (multipart("file", "scan.tif", &[0; 4096]), 1024, StatusCode::PAYLOAD_TOO_LARGE, "too_large"),
+        ];
+        for (request, limit, status, code) in cases {
+            assert_eq!(error_code(router(limit).oneshot(request).await.unwrap()).await, (status, code.to_string()));
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_stream_is_cut_off_and_removed() {
+        let upload_dir = std::env::temp_dir().join(format!("darwin_uploads_limit_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&upload_dir).unwrap();
+        let mut state = AppState {
+            upload_dir: upload_dir.clone(),
+            storage: Arc::new(storage::LocalFsStorage::new(upload_dir.clone())),
+            ..AppState::for_tests("http://127.0.0.1:1")
+        };
+        state.config.max_upload_bytes = 100 * 1024;
+        let app = Router::new().route("/api/upload", post(upload_handler)).with_state(Arc::new(state));
+
+        // Several 64 KiB reads in, the copy stops at the limit
+        let response = app.clone().oneshot(multipart("file", "stack.tif", &[7; 300 * 1024])).await.unwrap();
+        assert_eq!(error_code(response).await, (StatusCode::PAYLOAD_TOO_LARGE, "too_large".to_string()));
+        assert_eq!(std::fs::read_dir(&upload_dir).unwrap().count(), 0);
+
+        let mut stack = vec![7; 100 * 1024];
+        stack[..4].copy_from_slice(b"II*\0");
+        let response = app.oneshot(multipart("file", "stack.tif", &stack)).await.unwrap();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["size"], 100 * 1024);
+        assert_eq!(std::fs::metadata(body["file_path"].as_str().unwrap()).unwrap().len(), 100 * 1024);
+
+        std::fs::remove_dir_all(upload_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn gzipped_raw_is_stored_decompressed() {
+        let gz = include_bytes!("../tests/fixtures/volume.raw.gz");
+        let response = router(MAX_UPLOAD_BYTES).oneshot(multipart("file", "volume.raw.gz", gz)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        let file_path = body["file_path"].as_str().unwrap();
+        assert!(file_path.ends_with("_volume.raw"));
+        assert_eq!(body["compressed_bytes"], gz.len());
+        assert_eq!(body["decompressed_bytes"], 1024);
+        let stored = tokio::fs::read(file_path).await.unwrap();
+        assert_eq!(stored, (0..1024).map(|i| (i * 7 % 256) as u8).collect::<Vec<u8>>());
+        tokio::fs::remove_file(file_path).await.unwrap();
+
+        // Detected by magic bytes even without the `.gz` suffix
+        let response = router(MAX_UPLOAD_BYTES).oneshot(multipart("file", "volume.raw", gz)).await.unwrap();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["decompressed_bytes"], 1024);
+        tokio::fs::remove_file(body["file_path"].as_str().unwrap()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn decompressed_size_is_held_to_the_upload_limit() {
+        let gz = include_bytes!("../tests/fixtures/volume.raw.gz");
+        let response = router_with_limit(MAX_UPLOAD_BYTES, 512).oneshot(multipart("file", "bomb.raw.gz", gz)).await.unwrap();
+        assert_eq!(error_code(response).await, (StatusCode::PAYLOAD_TOO_LARGE, "too_large".to_string()));
+
+        let response = router(MAX_UPLOAD_BYTES).oneshot(multipart("file", "broken.raw.gz", &gz[..100])).await.unwrap();
+        assert_eq!(error_code(response).await, (StatusCode::BAD_REQUEST, "invalid_gzip".to_string()));
+    }
+
+    #[tokio::test]
+    async fn stores_every_file_and_collects_text_fields() {
+        let request = multipart_parts(
+            "/api/upload",
+            &[
+                ("file", Some("scan.tif"), b"II*\0"),
+                ("voxel_size", None, b"10.5"),
+                ("file", Some("scan_meta.png"), b"\x89PNG\r\n\x1a\n"),
+            ],
+        );
+        let response = router(MAX_UPLOAD_BYTES).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["fields"], serde_json::json!({"voxel_size": "10.5"}));
+        let files = body["files"].as_array().unwrap();
+        let names: Vec<&str> = files.iter().map(|f| f["original_name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["scan.tif", "scan_meta.png"]);
+        assert_eq!(files[0]["size"], 4);
+        assert_eq!(files[0]["sha256"], "75a2a13326b2a4a0b2265dbc2d0a91bfc7b540f0b10b5b9abd2a3fc9d7b83016");
+        let formats: Vec<&str> = files.iter().map(|f| f["format"].as_str().unwrap()).collect();
+        assert_eq!(formats, ["tiff", "png"]);
+        for file in files {
+            tokio::fs::remove_file(file["file_path"].as_str().unwrap()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn one_rejected_file_discards_the_others() {
+        let upload_dir = std::env::temp_dir().join(format!("darwin_uploads_multi_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&upload_dir).unwrap();
+        let state = Arc::new(AppState {
+            upload_dir: upload_dir.clone(),
+            storage: Arc::new(storage::LocalFsStorage::new(upload_dir.clone())),
+            ..AppState::for_tests("http://127.0.0.1:1")
+        });
+        let app = Router::new().route("/api/upload", post(upload_handler)).with_state(state);
+
+        let request = multipart_parts(
+            "/api/upload",
+            &[("file", Some("scan.tif"), b"II*\0"), ("file", Some("notes.exe"), b"x")],
+        );
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(error_code(response).await, (StatusCode::UNSUPPORTED_MEDIA_TYPE, "invalid_extension".to_string()));
+        assert_eq!(std::fs::read_dir(&upload_dir).unwrap().count(), 0);
+
+        let request = multipart_parts("/api/upload", &[("voxel_size", None, b"10")]);
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(error_code(response).await, (StatusCode::BAD_REQUEST, "no_file_field".to_string()));
+
+        std::fs::remove_dir_all(upload_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn lists_uploads_newest_first_in_pages() {
+        let upload_dir = std::env::temp_dir().join(format!("darwin_uploads_list_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(upload_dir.join(".partial")).unwrap();
+        std::fs::write(upload_dir.join("notes.txt"), b"not an upload").unwrap();
+        let names = ["old.tif", "mid.nii", "new.png"];
+        for (age, name) in names.iter().rev().enumerate() {
+            let path = upload_dir.join(format!("{}_{}", Uuid::new_v4(), name));
+            let file = std::fs::File::create(&path).unwrap();
+            let modified = std::time::SystemTime::now() - std::time::Duration::from_secs(60 * age as u64);
+            file.set_modified(modified).unwrap();
+        }
+
+        let state = Arc::new(AppState {
+            upload_dir: upload_dir.clone(),
+            storage: Arc::new(storage::LocalFsStorage::new(upload_dir.clone())),
+            ..AppState::for_tests("http://127.0.0.1:1")
+        });
+        let app = Router::new()
+            .route("/api/uploads", axum::routing::get(list_uploads_handler))
+            .with_state(state);
+
+        let page = |uri: &str| {
+            let app = app.clone();
+            let request = Request::get(uri).body(Body::empty()).unwrap();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                serde_json::from_slice::<Value>(&bytes).unwrap()
+            }
+        };
+
+        let first = page("/api/uploads?limit=2").await;
+        let first_names: Vec<&str> = first["items"].as_array().unwrap().iter().map(|u| u["original_name"].as_str().unwrap()).collect();
+        assert_eq!(first_names, vec!["new.png", "mid.nii"]);
+        assert_eq!(first["next_cursor"], "2");
+
+        let last = page("/api/uploads?limit=2&cursor=2").await;
+        assert_eq!(last["items"][0]["original_name"], "old.tif");
+        assert_eq!(last["next_cursor"], Value::Null);
+
+        std::fs::remove_dir_all(upload_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn uploads_go_through_the_configured_storage() {
+        let storage = Arc::new(storage::MemoryStorage::new());
+        let state = Arc::new(AppState { storage: storage.clone(), ..AppState::for_tests("http://127.0.0.1:1") });
+        let app = Router::new()
+            .route("/api/upload", post(upload_handler))
+            .route("/api/uploads", axum::routing::get(list_uploads_handler))
+            .with_state(state);
+
+        let response = app.clone().oneshot(multipart("file", "scan.tif", b"II*\0")).await.unwrap();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        let key = format!("{}_scan.tif", body["file_id"].as_str().unwrap());
+        assert_eq!(storage.keys(), vec![key.clone()]);
+        assert_eq!(body["file_path"], storage.local_path(&key).to_string_lossy().as_ref());
+
+        let response = app.clone().oneshot(Request::get("/api/uploads").body(Body::empty()).unwrap()).await.unwrap();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let listed: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(listed["items"][0]["file_id"], body["file_id"]);
+        assert_eq!(listed["items"][0]["size"], 4);
+        assert_eq!((&listed["items"][0]["sha256"], &listed["items"][0]["format"]), (&body["sha256"], &body["format"]));
+
+        // A rejected batch is removed from storage, not just from disk
+        let request = multipart_parts(
+            "/api/upload",
+            &[("file", Some("scan.tif"), b"II*\0"), ("file", Some("notes.exe"), b"x")],
+        );
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(storage.keys(), vec![key]);
+    }
+}
@@ -0,0 +1,46 @@
+//! Build identification for support: `GET /api/version`, plus the `GET /api/health` liveness probe.
+
+use axum::response::Json;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// What was built, from where and with which toolchain (captured by `build.rs`).
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    pub build_timestamp: &'static str,
+    pub rust_version: &'static str,
+}
+
+pub const BUILD_INFO: BuildInfo = BuildInfo {
+    version: env!("CARGO_PKG_VERSION"),
+    git_sha: env!("DARWIN_GIT_SHA"),
+    build_timestamp: env!("DARWIN_BUILD_TIMESTAMP"),
+    rust_version: env!("DARWIN_RUSTC_VERSION"),
+};
+
+/// `GET /api/version` - open without an API key so support can always query it.
+pub async fn version_handler() -> Json<BuildInfo> {
+    Json(BUILD_INFO)
+}
+
+/// `GET /api/health` - answers as long as this server is up; says nothing about Julia.
+pub async fn health_handler() -> Json<Value> {
+    Json(json!({"status": "ok", "version": BUILD_INFO.version}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reports_every_field() {
+        let Json(info) = version_handler().await;
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        for value in [info.git_sha, info.build_timestamp, info.rust_version] {
+            assert!(!value.is_empty());
+        }
+        assert!(info.git_sha == "unknown" || info.git_sha.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+}
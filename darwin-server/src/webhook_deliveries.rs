@@ -0,0 +1,432 @@
+//! Durable queue of webhook deliveries, so a webhook target that is down gets its
+//! events once it is back, even across server restarts.
+//!
+//! Every change to a [`Delivery`] is appended to a JSONL file as the whole record;
+//! on load the last line per id wins and the file is compacted. A failed attempt
+//! is retried after an exponential [`backoff`]; after [`MAX_ATTEMPTS`] the delivery
+//! is dead-lettered and only a forced retry sends it again. Deliveries are keyed by
+//! `(event_id, webhook_id)`, so an event is queued once per webhook, and the target
+//! gets the event id to de-duplicate on its side too.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+pub const MAX_ATTEMPTS: u32 = 8;
+pub const DEFAULT_BASE_DELAY: Duration = Duration::from_secs(2);
+const MAX_DELAY: Duration = Duration::from_secs(60 * 60);
+/// Delivered records kept through a compaction, newest first; older ones only count in logs
+const MAX_SUCCEEDED_KEPT: usize = 1000;
+/// Compact once the file holds this many more lines than there are deliveries
+const COMPACT_SLACK_LINES: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryState {
+    Pending,
+    Succeeded,
+    /// Gave up after [`MAX_ATTEMPTS`], or the webhook was removed
+    DeadLetter,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Delivery {
+    pub id: String,
+    /// Same for every webhook told about one event; sent as `X-Darwin-Event-Id`
+    pub event_id: String,
+    pub webhook_id: String,
+    pub url: String,
+    pub body: Value,
+    pub state: DeliveryState,
+    pub attempts: u32,
+    /// Unix milliseconds; when a pending delivery is next tried
+    pub next_attempt: u64,
+    pub last_error: Option<String>,
+    pub created: u64,
+    pub updated: u64,
+}
+
+/// Wait after the `attempts`-th failed attempt: `base`, doubling each time, at most an hour
+pub fn backoff(attempts: u32, base: Duration) -> Duration {
+    let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+    base.saturating_mul(factor).min(MAX_DELAY)
+}
+
+impl Delivery {
+    pub fn new(event_id: &str, webhook_id: &str, url: &str, body: Value, now: u64) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            event_id: event_id.to_string(),
+            webhook_id: webhook_id.to_string(),
+            url: url.to_string(),
+            body,
+            state: DeliveryState::Pending,
+            attempts: 0,
+            next_attempt: now,
+            last_error: None,
+            created: now,
+            updated: now,
+        }
+    }
+
+    /// Apply the outcome of an attempt made at `now`.
+    pub fn record_attempt(&mut self, result: Result<(), String>, now: u64, base_delay: Duration) {
+        self.attempts += 1;
+        self.updated = now;
+        match result {
+            Ok(()) => {
+                self.state = DeliveryState::Succeeded;
+                self.last_error = None;
+            }
+            Err(e) if self.attempts >= MAX_ATTEMPTS => {
+                self.state = DeliveryState::DeadLetter;
+                self.last_error = Some(e);
+            }
+            Err(e) => {
+                self.next_attempt = now + backoff(self.attempts, base_delay).as_millis() as u64;
+                self.last_error = Some(e);
+            }
+        }
+    }
+
+    /// Dead-letter without trying again, e.g. because the webhook is gone.
+    pub fn abandon(&mut self, reason: &str, now: u64) {
+        self.state = DeliveryState::DeadLetter;
+        self.last_error = Some(reason.to_string());
+        self.updated = now;
+    }
+
+    /// Try again at `now`. A dead letter gets one more attempt; one that fails again
+    /// goes straight back to the dead-letter list.
+    pub fn force_retry(&mut self, now: u64) -> Result<(), RetryRefused> {
+        if self.state == DeliveryState::Succeeded {
+            return Err(RetryRefused::Delivered);
+        }
+        self.state = DeliveryState::Pending;
+        self.next_attempt = now;
+        self.updated = now;
+        Ok(())
+    }
+
+    /// Everything but the body, for listings
+    pub fn summary(&self) -> Value {
+        let mut summary = serde_json::to_value(self).unwrap_or_default();
+        if let Some(fields) = summary.as_object_mut() {
+            fields.remove("body");
+        }
+        summary
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryRefused {
+    Unknown,
+    Delivered,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DeliveryCounts {
+    pub pending: usize,
+    pub succeeded: usize,
+    /// Dead letters
+    pub failed: usize,
+}
+
+#[derive(Default)]
+struct Inner {
+    deliveries: HashMap<String, Delivery>,
+    /// `(event_id, webhook_id)` of every delivery
+    keys: HashSet<(String, String)>,
+    /// Taken by the worker and not completed yet
+    in_flight: HashSet<String>,
+    file: Option<File>,
+    lines: usize,
+}
+
+/// Cheap to clone; clones share the same queue.
+#[derive(Clone)]
+pub struct DeliveryQueue {
+    inner: Arc<Mutex<Inner>>,
+    path: Arc<PathBuf>,
+    wake: Arc<Notify>,
+    base_delay: Duration,
+}
+
+impl DeliveryQueue {
+    /// Load the queue persisted at `path` and compact the file. Corrupt lines are
+    /// skipped with a warning.
+    pub fn load(path: PathBuf) -> Self {
+        let queue = Self {
+            inner: Arc::default(),
+            path: Arc::new(path),
+            wake: Arc::default(),
+            base_delay: DEFAULT_BASE_DELAY,
+        };
+        let text = std::fs::read_to_string(queue.path.as_ref()).unwrap_or_default();
+        let mut inner = queue.inner.lock().unwrap();
+        for (n, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            match serde_json::from_str::<Delivery>(line) {
+                Ok(delivery) => {
+                    inner.keys.insert((delivery.event_id.clone(), delivery.webhook_id.clone()));
+                    inner.deliveries.insert(delivery.id.clone(), delivery);
+                }
+                Err(e) => tracing::warn!("{} line {} is corrupt, skipped: {}", queue.path.display(), n + 1, e),
+            }
+        }
+        if !text.is_empty() {
+            if let Err(e) = queue.compact(&mut inner) {
+                tracing::warn!("compacting {} failed: {}", queue.path.display(), e);
+            }
+        }
+        drop(inner);
+        queue
+    }
+
+    #[cfg(test)]
+    pub fn with_base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    /// Append `delivery` as it is now and fsync.
+    fn persist(&self, inner: &mut Inner, delivery: &Delivery) -> Result<(), String> {
+        let written = (|| {
+            if inner.file.is_none() {
+                if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                    std::fs::create_dir_all(dir)?;
+                }
+                inner.file = Some(std::fs::OpenOptions::new().create(true).append(true).open(self.path.as_ref())?);
+            }
+            let file = inner.file.as_mut().unwrap();
+            let mut line = serde_json::to_vec(delivery)?;
+            line.push(b'\n');
+            file.write_all(&line)?;
+            file.sync_data()
+        })();
+        if let Err(e) = written {
+            inner.file = None;
+            return Err(format!("writing {}: {}", self.path.display(), e));
+        }
+        inner.lines += 1;
+        if inner.lines > inner.deliveries.len() + COMPACT_SLACK_LINES {
+            self.compact(inner)?;
+        }
+        Ok(())
+    }
+
+    /// Rewrite the file with one line per delivery, dropping the oldest delivered ones
+    /// beyond [`MAX_SUCCEEDED_KEPT`].
+    fn compact(&self, inner: &mut Inner) -> Result<(), String> {
+        let mut succeeded: Vec<(u64, String)> = inner
+            .deliveries
+            .values()
+            .filter(|d| d.state == DeliveryState::Succeeded)
+            .map(|d| (d.updated, d.id.clone()))
+            .collect();
+        succeeded.sort_unstable_by(|a, b| b.cmp(a));
+        for (_, id) in succeeded.into_iter().skip(MAX_SUCCEEDED_KEPT) {
+            if let Some(dropped) = inner.deliveries.remove(&id) {
+                inner.keys.remove(&(dropped.event_id, dropped.webhook_id));
+            }
+        }
+
+        let mut deliveries: Vec<&Delivery> = inner.deliveries.values().collect();
+        deliveries.sort_by_key(|d| d.created);
+        let mut text = String::new();
+        for delivery in deliveries {
+            text.push_str(&serde_json::to_string(delivery).map_err(|e| e.to_string())?);
+            text.push('\n');
+        }
+        let partial = self.path.with_extension("jsonl.part");
+        std::fs::write(&partial, text)
+            .and_then(|_| std::fs::rename(&partial, self.path.as_ref()))
+            .map_err(|e| format!("rewriting {}: {}", self.path.display(), e))?;
+        inner.file = None;
+        inner.lines = inner.deliveries.len();
+        Ok(())
+    }
+
+    /// Queue `body` for `webhook_id`; `Ok(None)` when this event is already queued
+    /// (or was delivered) for that webhook.
+    pub fn enqueue(&self, event_id: &str, webhook_id: &str, url: &str, body: Value, now: u64) -> Result<Option<Delivery>, String> {
+        let mut inner = self.inner.lock().unwrap();
+        let key = (event_id.to_string(), webhook_id.to_string());
+        if inner.keys.contains(&key) {
+            return Ok(None);
+        }
+        let delivery = Delivery::new(event_id, webhook_id, url, body, now);
+        self.persist(&mut inner, &delivery)?;
+        inner.keys.insert(key);
+        inner.deliveries.insert(delivery.id.clone(), delivery.clone());
+        drop(inner);
+        self.wake.notify_one();
+        Ok(Some(delivery))
+    }
+
+    /// Pending deliveries due at `now`, marked in flight until [`complete`](Self::complete).
+    pub fn take_due(&self, now: u64) -> Vec<Delivery> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut due: Vec<Delivery> = inner
+            .deliveries
+            .values()
+            .filter(|d| d.state == DeliveryState::Pending && d.next_attempt <= now && !inner.in_flight.contains(&d.id))
+            .cloned()
+            .collect();
+        due.sort_by_key(|d| d.next_attempt);
+        inner.in_flight.extend(due.iter().map(|d| d.id.clone()));
+        due
+    }
+
+    /// How long until the next pending delivery is due; `None` when nothing waits.
+    pub fn next_due_in(&self, now: u64) -> Option<Duration> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .deliveries
+            .values()
+            .filter(|d| d.state == DeliveryState::Pending && !inner.in_flight.contains(&d.id))
+            .map(|d| Duration::from_millis(d.next_attempt.saturating_sub(now)))
+            .min()
+    }
+
+    /// Resolves when a delivery was queued, completed or forced to retry.
+    pub async fn changed(&self) {
+        self.wake.notified().await
+    }
+
+    /// Apply `update` to the in-flight delivery `id` and persist it; returns the result.
+    pub fn complete(&self, id: &str, update: impl FnOnce(&mut Delivery)) -> Option<Delivery> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.in_flight.remove(id);
+        let mut delivery = inner.deliveries.get(id)?.clone();
+        update(&mut delivery);
+        if let Err(e) = self.persist(&mut inner, &delivery) {
+            // Kept in memory; a restart repeats the attempt, which the event id makes harmless
+            tracing::error!(delivery = %id, "{}", e);
+        }
+        inner.deliveries.insert(delivery.id.clone(), delivery.clone());
+        drop(inner);
+        self.wake.notify_one();
+        Some(delivery)
+    }
+
+    /// Record an attempt made at `now`.
+    pub fn record_attempt(&self, id: &str, result: Result<(), String>, now: u64) -> Option<Delivery> {
+        let base_delay = self.base_delay;
+        self.complete(id, |delivery| delivery.record_attempt(result, now, base_delay))
+    }
+
+    /// Make delivery `id` due now.
+    pub fn retry(&self, id: &str, now: u64) -> Result<Delivery, RetryRefused> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut delivery = inner.deliveries.get(id).cloned().ok_or(RetryRefused::Unknown)?;
+        delivery.force_retry(now)?;
+        if let Err(e) = self.persist(&mut inner, &delivery) {
+            tracing::error!(delivery = %id, "{}", e);
+        }
+        inner.deliveries.insert(delivery.id.clone(), delivery.clone());
+        drop(inner);
+        self.wake.notify_one();
+        Ok(delivery)
+    }
+
+    pub fn counts(&self) -> DeliveryCounts {
+        let inner = self.inner.lock().unwrap();
+        let mut counts = DeliveryCounts::default();
+        for delivery in inner.deliveries.values() {
+            match delivery.state {
+                DeliveryState::Pending => counts.pending += 1,
+                DeliveryState::Succeeded => counts.succeeded += 1,
+                DeliveryState::DeadLetter => counts.failed += 1,
+            }
+        }
+        counts
+    }
+
+    /// Deliveries in `states`, newest first
+    pub fn list(&self, states: &[DeliveryState]) -> Vec<Delivery> {
+        let inner = self.inner.lock().unwrap();
+        let mut deliveries: Vec<Delivery> = inner.deliveries.values().filter(|d| states.contains(&d.state)).cloned().collect();
+        deliveries.sort_by_key(|d| std::cmp::Reverse(d.created));
+        deliveries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn temp_file() -> PathBuf {
+        std::env::temp_dir().join(format!("darwin-deliveries-test-{}", Uuid::new_v4())).join("deliveries.jsonl")
+    }
+
+    #[test]
+    fn failures_back_off_until_dead_lettered_and_forced_retries_get_one_attempt() {
+        let base = Duration::from_secs(2);
+        assert_eq!(backoff(1, base), Duration::from_secs(2));
+        assert_eq!(backoff(4, base), Duration::from_secs(16));
+        assert_eq!(backoff(30, base), MAX_DELAY);
+
+        let mut delivery = Delivery::new("evt-1", "hook-1", "http://localhost:9000/hook", json!({}), 0);
+        let mut now = 0;
+        for attempt in 1..MAX_ATTEMPTS {
+            delivery.record_attempt(Err("503 Service Unavailable".to_string()), now, base);
+            assert_eq!((delivery.state, delivery.attempts), (DeliveryState::Pending, attempt));
+            assert_eq!(delivery.next_attempt - now, backoff(attempt, base).as_millis() as u64);
+            now = delivery.next_attempt;
+        }
+        delivery.record_attempt(Err("connection refused".to_string()), now, base);
+        assert_eq!(delivery.state, DeliveryState::DeadLetter);
+        assert_eq!(delivery.last_error.as_deref(), Some("connection refused"));
+
+        delivery.force_retry(now + 5).unwrap();
+        assert_eq!((delivery.state, delivery.next_attempt), (DeliveryState::Pending, now + 5));
+        delivery.record_attempt(Err("connection refused".to_string()), now + 5, base);
+        assert_eq!(delivery.state, DeliveryState::DeadLetter);
+
+        delivery.force_retry(now + 10).unwrap();
+        delivery.record_attempt(Ok(()), now + 10, base);
+        assert_eq!((delivery.state, delivery.last_error.as_deref()), (DeliveryState::Succeeded, None));
+        assert_eq!(delivery.force_retry(now + 20), Err(RetryRefused::Delivered));
+    }
+
+    #[test]
+    fn queue_dedupes_events_and_survives_restarts() {
+        let path = temp_file();
+        let queue = DeliveryQueue::load(path.clone());
+        let first = queue.enqueue("evt-1", "hook-1", "http://localhost/a", json!({"n": 1}), 1000).unwrap().unwrap();
+        assert_eq!(queue.enqueue("evt-1", "hook-1", "http://localhost/a", json!({"n": 1}), 1001).unwrap(), None);
+        let other = queue.enqueue("evt-1", "hook-2", "http://localhost/b", json!({"n": 1}), 1002).unwrap().unwrap();
+
+        let due = queue.take_due(1500);
+        assert_eq!(due.len(), 2);
+        assert!(queue.take_due(1500).is_empty(), "in-flight deliveries are not handed out twice");
+        let failed = queue.record_attempt(&first.id, Err("timed out".to_string()), 1500).unwrap();
+        assert_eq!(failed.next_attempt, 1500 + DEFAULT_BASE_DELAY.as_millis() as u64);
+        queue.record_attempt(&other.id, Ok(()), 1500).unwrap();
+        assert_eq!(queue.counts(), DeliveryCounts { pending: 1, succeeded: 1, failed: 0 });
+        assert_eq!(queue.next_due_in(1500), Some(DEFAULT_BASE_DELAY));
+
+        // The other delivery was in flight when the server stopped: it is retried after a restart
+        queue.take_due(u64::MAX);
+        let reloaded = DeliveryQueue::load(path.clone());
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2, "compacted to one line per delivery");
+        assert_eq!(reloaded.counts(), queue.counts());
+        assert_eq!(reloaded.enqueue("evt-1", "hook-2", "http://localhost/b", json!({}), 2000).unwrap(), None);
+        let due = reloaded.take_due(1500 + DEFAULT_BASE_DELAY.as_millis() as u64);
+        assert_eq!((due.len(), due[0].attempts, due[0].body.clone()), (1, 1, json!({"n": 1})));
+
+        assert_eq!(reloaded.retry(&other.id, 3000).unwrap_err(), RetryRefused::Delivered);
+        assert_eq!(reloaded.retry("missing", 3000).unwrap_err(), RetryRefused::Unknown);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,497 @@
+//! Analysis webhooks: user-run post-processing services told about every completed analysis.
+//!
+//! `POST /api/webhooks` with `{url}` registers a URL (persisted to `DARWIN_WEBHOOKS_FILE`),
+//! `DELETE /api/webhooks/:id` removes it and `GET /api/webhooks/status` reports how
+//! deliveries went. After each successful `/api/analyze` every webhook is POSTed
+//! `{event, workspace_id, file_sha256, metrics}` in the background; a failing hook
+//! never delays or fails the analysis.
+//!
+//! Deliveries go through a [`DeliveryQueue`] persisted next to the webhooks file
+//! (`webhooks.json` → `webhooks.deliveries.jsonl`), so they are retried with backoff
+//! across restarts, up to [`MAX_ATTEMPTS`](crate::webhook_deliveries::MAX_ATTEMPTS) times. Each carries an
+//! `X-Darwin-Event-Id` header, the SHA-256 of the event body, which stays the same
+//! on retries for the target to de-duplicate on. `GET /api/webhooks/deliveries`
+//! lists the queue and `POST /api/webhooks/deliveries/:id/retry` sends one again.
+//!
+//! Only hosts listed in `DARWIN_WEBHOOK_HOSTS` can be registered, over `https`, or
+//! plain `http` for loopback hosts.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use uuid::Uuid;
+
+use crate::{
+    db::now_ms,
+    error::AppError,
+    webhook_deliveries::{Delivery, DeliveryQueue, DeliveryState, RetryRefused},
+    AppState,
+};
+
+pub const DEFAULT_WEBHOOK_HOSTS: &[&str] = &["localhost", "127.0.0.1", "[::1]"];
+pub const EVENT_ID_HEADER: &str = "x-darwin-event-id";
+/// The worker checks the queue at least this often, in case a wake-up was missed
+const MAX_WORKER_SLEEP: Duration = Duration::from_secs(60);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const LOOPBACK_HOSTS: &[&str] = &["localhost", "127.0.0.1", "[::1]"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    /// Unix milliseconds
+    pub created: u64,
+}
+
+/// How deliveries to one webhook went since the server started.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeliveryStatus {
+    pub delivered: u64,
+    /// Deliveries given up on after [`MAX_ATTEMPTS`](crate::webhook_deliveries::MAX_ATTEMPTS)
+    pub failed: u64,
+    /// Unix milliseconds
+    pub last_attempt: Option<u64>,
+    pub last_success: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Default)]
+struct Inner {
+    hooks: BTreeMap<String, Webhook>,
+    status: HashMap<String, DeliveryStatus>,
+}
+
+/// Cheap to clone; clones share the same registry.
+#[derive(Clone)]
+pub struct AnalysisWebhooks {
+    inner: Arc<Mutex<Inner>>,
+    path: Arc<PathBuf>,
+    allowed_hosts: Arc<Vec<String>>,
+    client: reqwest::Client,
+    deliveries: DeliveryQueue,
+}
+
+impl AnalysisWebhooks {
+    /// Load the webhooks persisted at `path` and their delivery queue, dropping any
+    /// webhook whose host is no longer allowed. Deliveries only start once
+    /// [`spawn_delivery_worker`](Self::spawn_delivery_worker) runs.
+    pub fn load(path: PathBuf, allowed_hosts: Vec<String>) -> Self {
+        let deliveries = DeliveryQueue::load(path.with_extension("deliveries.jsonl"));
+        let webhooks = Self {
+            inner: Arc::default(),
+            path: Arc::new(path),
+            allowed_hosts: Arc::new(allowed_hosts),
+            client: reqwest::Client::new(),
+            deliveries,
+        };
+        let hooks: Vec<Webhook> = match std::fs::read_to_string(webhooks.path.as_ref()) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+                tracing::warn!("{} is corrupt, starting without webhooks: {}", webhooks.path.display(), e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        let mut inner = webhooks.inner.lock().unwrap();
+        for hook in hooks {
+            match webhooks.validate(&hook.url) {
+                Ok(()) => {
+                    inner.hooks.insert(hook.id.clone(), hook);
+                }
+                Err(e) => tracing::warn!("dropping webhook {}: {}", hook.id, e),
+            }
+        }
+        drop(inner);
+        webhooks
+    }
+
+    #[cfg(test)]
+    fn with_base_delay(mut self, base_delay: Duration) -> Self {
+        self.deliveries = self.deliveries.with_base_delay(base_delay);
+        self
+    }
+
+    pub fn deliveries(&self) -> &DeliveryQueue {
+        &self.deliveries
+    }
+
+    /// `Err` explains why `url` may not be registered.
+    pub fn validate(&self, url: &str) -> Result<(), String> {
+        let parsed = reqwest::Url::parse(url).map_err(|e| format!("`{}` is not a URL: {}", url, e))?;
+        let host = parsed.host_str().unwrap_or_default().to_ascii_lowercase();
+        if !parsed.username().is_empty() || parsed.password().is_some() {
+            return Err("webhook URLs may not carry credentials".to_string());
+        }
+        match parsed.scheme() {
+            "https" => {}
+            "http" if LOOPBACK_HOSTS.contains(&host.as_str()) => {}
+            "http" => return Err(format!("`{}` must use https", host)),
+            other => return Err(format!("scheme `{}` is not allowed", other)),
+        }
+        let allowed = self.allowed_hosts.iter().any(|pattern| match pattern.strip_prefix("*.") {
+            Some(domain) => host.ends_with(&format!(".{}", domain)),
+            None => *pattern == host,
+        });
+        if !allowed {
+            return Err(format!("host `{}` is not in DARWIN_WEBHOOK_HOSTS", host));
+        }
+        Ok(())
+    }
+
+    fn save(&self, inner: &Inner) -> Result<(), String> {
+        let hooks: Vec<&Webhook> = inner.hooks.values().collect();
+        let json = serde_json::to_string_pretty(&hooks).map_err(|e| e.to_string())?;
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        std::fs::write(self.path.as_ref(), json).map_err(|e| format!("saving {}: {}", self.path.display(), e))
+    }
+
+    pub fn register(&self, url: &str) -> Result<Webhook, String> {
+        self.validate(url)?;
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(existing) = inner.hooks.values().find(|hook| hook.url == url) {
+            return Ok(existing.clone());
+        }
+        let hook = Webhook { id: Uuid::new_v4().to_string(), url: url.to_string(), created: now_ms() };
+        inner.hooks.insert(hook.id.clone(), hook.clone());
+        if let Err(e) = self.save(&inner) {
+            inner.hooks.remove(&hook.id);
+            return Err(e);
+        }
+        Ok(hook)
+    }
+
+    /// `Ok(false)` when there was no such webhook.
+    pub fn remove(&self, id: &str) -> Result<bool, String> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.hooks.remove(id).is_none() {
+            return Ok(false);
+        }
+        inner.status.remove(id);
+        self.save(&inner).map(|_| true)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.lock().unwrap().hooks.is_empty()
+    }
+
+    pub fn status(&self) -> Vec<(Webhook, DeliveryStatus)> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .hooks
+            .values()
+            .map(|hook| (hook.clone(), inner.status.get(&hook.id).cloned().unwrap_or_default()))
+            .collect()
+    }
+
+    fn update_status(&self, id: &str, update: impl FnOnce(&mut DeliveryStatus)) {
+        let mut inner = self.inner.lock().unwrap();
+        // Removed while the delivery was in flight
+        if inner.hooks.contains_key(id) {
+            update(inner.status.entry(id.to_string()).or_default());
+        }
+    }
+
+    /// POST the delivery's body once; `Err` is why it failed.
+    async fn send(&self, delivery: &Delivery) -> Result<(), String> {
+        self.client
+            .post(&delivery.url)
+            .timeout(DELIVERY_TIMEOUT)
+            .header(EVENT_ID_HEADER, &delivery.event_id)
+            .json(&delivery.body)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map(drop)
+            .map_err(|e| e.without_url().to_string())
+    }
+
+    /// Make one attempt at an in-flight delivery and record how it went.
+    async fn attempt(&self, delivery: Delivery) {
+        let registered = self.inner.lock().unwrap().hooks.contains_key(&delivery.webhook_id);
+        if !registered {
+            self.deliveries.complete(&delivery.id, |d| d.abandon("webhook was removed", now_ms()));
+            return;
+        }
+        self.update_status(&delivery.webhook_id, |status| status.last_attempt = Some(now_ms()));
+        let result = self.send(&delivery).await;
+        let Some(delivery) = self.deliveries.record_attempt(&delivery.id, result, now_ms()) else {
+            return;
+        };
+        match delivery.state {
+            DeliveryState::Succeeded => self.update_status(&delivery.webhook_id, |status| {
+                status.delivered += 1;
+                status.last_success = status.last_attempt;
+            }),
+            DeliveryState::DeadLetter => {
+                let error = delivery.last_error.clone().unwrap_or_default();
+                tracing::warn!(webhook = %delivery.webhook_id, url = %delivery.url, delivery = %delivery.id, "webhook delivery failed after {} attempts: {}", delivery.attempts, error);
+                self.update_status(&delivery.webhook_id, |status| {
+                    status.failed += 1;
+                    status.last_error = Some(error);
+                });
+            }
+            DeliveryState::Pending => {
+                tracing::debug!(webhook = %delivery.webhook_id, attempt = delivery.attempts, "webhook delivery failed, retrying: {}", delivery.last_error.as_deref().unwrap_or_default());
+            }
+        }
+    }
+
+    /// Start the background task sending due deliveries; must be called inside the runtime.
+    pub fn spawn_delivery_worker(&self) {
+        let webhooks = self.clone();
+        tokio::spawn(async move {
+            loop {
+                for delivery in webhooks.deliveries.take_due(now_ms()) {
+                    let webhooks = webhooks.clone();
+                    tokio::spawn(async move { webhooks.attempt(delivery).await });
+                }
+                let wait = webhooks.deliveries.next_due_in(now_ms()).unwrap_or(MAX_WORKER_SLEEP);
+                tokio::select! {
+                    _ = tokio::time::sleep(wait.min(MAX_WORKER_SLEEP)) => {}
+                    _ = webhooks.deliveries.changed() => {}
+                }
+            }
+        });
+    }
+
+    /// Queue a completed analysis for every webhook. `file_sha256` is computed here
+    /// from `file_path` (a stored upload) when given, off the request path.
+    pub fn notify_analysis(&self, workspace_id: Option<String>, file_path: Option<PathBuf>, metrics: Value) {
+        let hooks: Vec<Webhook> = self.inner.lock().unwrap().hooks.values().cloned().collect();
+        if hooks.is_empty() {
+            return;
+        }
+        let webhooks = self.clone();
+        tokio::spawn(async move {
+            let file_sha256 = match file_path {
+                Some(path) => crate::analysis_cache::file_sha256(&path).await.ok(),
+                None => None,
+            };
+            let body = serde_json::json!({
+                "event": "analysis_complete",
+                "workspace_id": workspace_id,
+                "file_sha256": file_sha256,
+                "metrics": metrics,
+            });
+            // Keys serialize sorted, so the same event always hashes the same
+            let event_id: String = Sha256::digest(body.to_string()).iter().map(|b| format!("{:02x}", b)).collect();
+            for hook in &hooks {
+                match webhooks.deliveries.enqueue(&event_id, &hook.id, &hook.url, body.clone(), now_ms()) {
+                    Ok(Some(_)) => {}
+                    Ok(None) => tracing::debug!(webhook = %hook.id, event = %event_id, "event already queued for this webhook"),
+                    Err(e) => tracing::error!(webhook = %hook.id, "queueing webhook delivery failed: {}", e),
+                }
+            }
+        });
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterRequest {
+    pub url: String,
+}
+
+/// `POST /api/webhooks` - `{url}` → `201 {id, url, created}`; an already registered URL
+/// answers with its existing id. A URL outside the allowlist is `400`.
+pub async fn register_handler(State(state): State<Arc<AppState>>, Json(request): Json<RegisterRequest>) -> Result<Response, AppError> {
+    let url = request.url.trim();
+    state.webhooks.validate(url).map_err(AppError::validation)?;
+    let hook = state.webhooks.register(url).map_err(AppError::internal)?;
+    Ok((StatusCode::CREATED, Json(hook)).into_response())
+}
+
+/// `DELETE /api/webhooks/:id` - `204`, or `404` for an unknown id.
+pub async fn remove_handler(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> Result<StatusCode, AppError> {
+    match state.webhooks.remove(&id).map_err(AppError::internal)? {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err(AppError::not_found(format!("no webhook with id {}", id))),
+    }
+}
+
+/// `GET /api/webhooks/status` - `{"webhooks": [{id, url, created, delivered, failed, last_attempt, last_success, last_error}]}`
+pub async fn status_handler(State(state): State<Arc<AppState>>) -> Json<Value> {
+    let webhooks: Vec<Value> = state
+        .webhooks
+        .status()
+        .into_iter()
+        .map(|(hook, status)| {
+            let mut entry = serde_json::to_value(hook).unwrap_or_default();
+            if let (Some(entry), Ok(Value::Object(status))) = (entry.as_object_mut(), serde_json::to_value(status)) {
+                entry.extend(status);
+            }
+            entry
+        })
+        .collect();
+    Json(serde_json::json!({"webhooks": webhooks}))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DeliveriesParams {
+    /// `pending`, `succeeded` or `dead_letter`; pending and dead letters when unset
+    pub state: Option<DeliveryState>,
+}
+
+/// `GET /api/webhooks/deliveries?state=` - `{"counts": {pending, succeeded, failed},
+/// "deliveries": [...]}`, deliveries newest first and without their body.
+pub async fn deliveries_handler(State(state): State<Arc<AppState>>, Query(params): Query<DeliveriesParams>) -> Json<Value> {
+    let queue = state.webhooks.deliveries();
+    let states = match params.state {
+        Some(only) => vec![only],
+        None => vec![DeliveryState::Pending, DeliveryState::DeadLetter],
+    };
+    let deliveries: Vec<Value> = queue.list(&states).iter().map(Delivery::summary).collect();
+    Json(serde_json::json!({"counts": queue.counts(), "deliveries": deliveries}))
+}
+
+/// `POST /api/webhooks/deliveries/:id/retry` - `202` with the delivery, due now.
+/// A dead letter gets one more attempt. `404` for an unknown id, `409` if it was delivered.
+pub async fn retry_delivery_handler(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> Result<Response, AppError> {
+    match state.webhooks.deliveries().retry(&id, now_ms()) {
+        Ok(delivery) => Ok((StatusCode::ACCEPTED, Json(delivery.summary())).into_response()),
+        Err(RetryRefused::Unknown) => Err(AppError::not_found(format!("no webhook delivery with id {}", id))),
+        Err(RetryRefused::Delivered) => Err(AppError::rejected(
+            StatusCode::CONFLICT,
+            "already_delivered",
+            format!("webhook delivery {} already succeeded", id),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webhook_deliveries::MAX_ATTEMPTS;
+    use serde_json::json;
+    use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+
+    fn temp_file() -> PathBuf {
+        std::env::temp_dir().join(format!("darwin-webhooks-test-{}", Uuid::new_v4())).join("webhooks.json")
+    }
+
+    fn hosts(hosts: &[&str]) -> Vec<String> {
+        hosts.iter().map(|h| h.to_string()).collect()
+    }
+
+    #[test]
+    fn urls_must_match_the_allowlist() {
+        let webhooks = AnalysisWebhooks::load(temp_file(), hosts(&["127.0.0.1", "*.lab.example.org"]));
+        assert!(webhooks.validate("http://127.0.0.1:9000/hook").is_ok());
+        assert!(webhooks.validate("https://post.lab.example.org/hook").is_ok());
+        for (url, reason) in [
+            ("http://post.lab.example.org/hook", "must use https"),
+            ("https://lab.example.org.evil.com/hook", "not in DARWIN_WEBHOOK_HOSTS"),
+            ("https://evil.com/hook", "not in DARWIN_WEBHOOK_HOSTS"),
+            ("ftp://127.0.0.1/hook", "scheme `ftp`"),
+            ("https://user:pw@post.lab.example.org/", "credentials"),
+            ("not a url", "is not a URL"),
+        ] {
+            let err = webhooks.validate(url).unwrap_err();
+            assert!(err.contains(reason), "{}: {}", url, err);
+        }
+    }
+
+    #[test]
+    fn registrations_persist_and_dedupe() {
+        let path = temp_file();
+        let webhooks = AnalysisWebhooks::load(path.clone(), hosts(DEFAULT_WEBHOOK_HOSTS));
+        let first = webhooks.register("http://localhost:9000/a").unwrap();
+        assert_eq!(webhooks.register("http://localhost:9000/a").unwrap().id, first.id);
+        let second = webhooks.register("http://localhost:9000/b").unwrap();
+
+        let reloaded = AnalysisWebhooks::load(path.clone(), hosts(DEFAULT_WEBHOOK_HOSTS));
+        assert_eq!(reloaded.status().len(), 2);
+        assert_eq!(reloaded.remove(&first.id), Ok(true));
+        assert_eq!(reloaded.remove(&first.id), Ok(false));
+
+        // A host dropped from the allowlist is not delivered to any more
+        let narrowed = AnalysisWebhooks::load(path.clone(), hosts(&["127.0.0.1"]));
+        assert!(narrowed.is_empty());
+        let kept = AnalysisWebhooks::load(path.clone(), hosts(DEFAULT_WEBHOOK_HOSTS));
+        assert_eq!(kept.status()[0].0, second);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    async fn wait_for(webhooks: &AnalysisWebhooks, done: impl Fn(&DeliveryStatus) -> bool) -> DeliveryStatus {
+        for _ in 0..200 {
+            let status = webhooks.status()[0].1.clone();
+            if done(&status) {
+                return status;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("delivery never finished");
+    }
+
+    #[tokio::test]
+    async fn deliveries_retry_and_report_failures() {
+        let service = MockServer::start().await;
+        Mock::given(matchers::method("POST"))
+            .and(matchers::path("/flaky"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .mount(&service)
+            .await;
+        Mock::given(matchers::method("POST"))
+            .and(matchers::path("/flaky"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&service)
+            .await;
+        Mock::given(matchers::method("POST"))
+            .and(matchers::path("/down"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&service)
+            .await;
+
+        let path = temp_file();
+        let webhooks = AnalysisWebhooks::load(path.clone(), hosts(DEFAULT_WEBHOOK_HOSTS))
+            .with_base_delay(Duration::from_millis(5));
+        webhooks.spawn_delivery_worker();
+        let flaky = webhooks.register(&format!("{}/flaky", service.uri())).unwrap();
+        webhooks.notify_analysis(Some("ws-1".to_string()), None, json!({"porosity": 0.8}));
+        let status = wait_for(&webhooks, |s| s.delivered == 1).await;
+        assert_eq!((status.failed, status.last_error), (0, None));
+
+        let received = service.received_requests().await.unwrap();
+        assert_eq!(received.len(), 2);
+        let body: Value = serde_json::from_slice(&received[1].body).unwrap();
+        assert_eq!(body, json!({"event": "analysis_complete", "workspace_id": "ws-1", "file_sha256": null, "metrics": {"porosity": 0.8}}));
+        // The retry carries the same event id, and the same event is not queued twice
+        let event_id = &received[0].headers[EVENT_ID_HEADER];
+        assert_eq!(event_id.len(), 64);
+        assert_eq!(&received[1].headers[EVENT_ID_HEADER], event_id);
+        webhooks.notify_analysis(Some("ws-1".to_string()), None, json!({"porosity": 0.8}));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(webhooks.deliveries().counts().succeeded, 1);
+
+        webhooks.remove(&flaky.id).unwrap();
+        webhooks.register(&format!("{}/down", service.uri())).unwrap();
+        webhooks.notify_analysis(None, None, json!({}));
+        let status = wait_for(&webhooks, |s| s.failed == 1).await;
+        assert!(status.last_error.unwrap().contains("500"));
+        assert_eq!(service.received_requests().await.unwrap().len(), 2 + MAX_ATTEMPTS as usize);
+
+        // Dead letters survive a restart and can be forced through once more
+        let reloaded = AnalysisWebhooks::load(path.clone(), hosts(DEFAULT_WEBHOOK_HOSTS));
+        reloaded.spawn_delivery_worker();
+        let dead = reloaded.deliveries().list(&[DeliveryState::DeadLetter]);
+        assert_eq!((dead.len(), dead[0].attempts), (1, MAX_ATTEMPTS));
+        reloaded.deliveries().retry(&dead[0].id, now_ms()).unwrap();
+        let status = wait_for(&reloaded, |s| s.failed == 1).await;
+        assert!(status.last_error.is_some());
+        assert_eq!(service.received_requests().await.unwrap().len(), 3 + MAX_ATTEMPTS as usize);
+        assert_eq!(reloaded.deliveries().counts(), crate::webhook_deliveries::DeliveryCounts { pending: 0, succeeded: 1, failed: 1 });
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
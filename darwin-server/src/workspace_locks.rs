@@ -0,0 +1,122 @@
+//! Per-workspace serialization of requests that change what Julia holds.
+//!
+//! These take the lock of the request body's `workspace_id` around their Julia
+//! call: `/api/analyze`, `/api/analyze/auto`, `/api/optimize`, `/api/mesh` and
+//! `/api/mesh/raw`. Two of them on one workspace run one after the other, while
+//! different workspaces, and requests without a `workspace_id`, run in parallel.
+//! Reads (`/api/files`, `/api/download`, `/api/uploads`) and cached analysis
+//! results never wait on a lock.
+
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::OwnedMutexGuard;
+
+use crate::events::ServerEvent;
+
+#[derive(Debug, Clone, Default)]
+pub struct WorkspaceLocks {
+    locks: Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>,
+}
+
+impl WorkspaceLocks {
+    /// Wait for exclusive use of `workspace_id`; it is released when the guard drops.
+    pub async fn lock(&self, workspace_id: &str) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.locks.lock().unwrap();
+            // Entries only the map refers to are neither held nor awaited
+            locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+            locks.entry(workspace_id.to_string()).or_default().clone()
+        };
+        lock.lock_owned().await
+    }
+
+    /// [`lock`](Self::lock) the workspace of a compute request body, if it names one.
+    pub async fn lock_for(&self, payload: &Value) -> Option<OwnedMutexGuard<()>> {
+        match ServerEvent::workspace_id(payload) {
+            Some(workspace_id) => Some(self.lock(&workspace_id).await),
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analysis, AppState};
+    use axum::{http::HeaderMap, routing::post, Json, Router};
+    use serde_json::json;
+    use std::time::{Duration, Instant};
+
+    /// Start a "Julia" whose `/analyze` takes 150ms and records when it ran.
+    async fn timed_julia() -> (String, Arc<Mutex<Vec<(String, Instant, Instant)>>>) {
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let recorded = runs.clone();
+        let app = Router::new().route(
+            "/analyze",
+            post(move |Json(body): Json<Value>| {
+                let recorded = recorded.clone();
+                async move {
+                    let start = Instant::now();
+                    tokio::time::sleep(Duration::from_millis(150)).await;
+                    let tag = body["tag"].as_str().unwrap().to_string();
+                    recorded.lock().unwrap().push((tag, start, Instant::now()));
+                    Json(json!({"porosity": 0.8}))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}", addr), runs)
+    }
+
+    async fn analyze_all(state: Arc<AppState>, payloads: Vec<Value>) {
+        let calls = payloads.into_iter().map(|payload| {
+            let state = state.clone();
+            tokio::spawn(async move { analysis::analyze(&state, &HeaderMap::new(), payload).await.unwrap() })
+        });
+        for call in calls.collect::<Vec<_>>() {
+            call.await.unwrap();
+        }
+    }
+
+    fn overlap(a: &(String, Instant, Instant), b: &(String, Instant, Instant)) -> bool {
+        a.1 < b.2 && b.1 < a.2
+    }
+
+    #[tokio::test]
+    async fn one_workspace_runs_serially_and_others_in_parallel() {
+        let (url, runs) = timed_julia().await;
+        let state = Arc::new(AppState::for_tests(&url));
+
+        analyze_all(
+            state.clone(),
+            vec![
+                json!({"workspace_id": "ws-1", "tag": "first"}),
+                json!({"workspace_id": "ws-1", "tag": "second"}),
+            ],
+        )
+        .await;
+        let same = std::mem::take(&mut *runs.lock().unwrap());
+        assert_eq!(same.len(), 2);
+        assert!(!overlap(&same[0], &same[1]), "analyses of one workspace overlapped");
+
+        analyze_all(
+            state.clone(),
+            vec![
+                json!({"workspace_id": "ws-1", "tag": "a"}),
+                json!({"workspace_id": "ws-2", "tag": "b"}),
+            ],
+        )
+        .await;
+        let different = runs.lock().unwrap().clone();
+        assert!(overlap(&different[0], &different[1]), "different workspaces were serialized");
+
+        // Released locks are pruned on the next acquisition
+        drop(state.workspace_locks.lock("ws-3").await);
+        assert_eq!(state.workspace_locks.locks.lock().unwrap().len(), 1);
+    }
+}
@@ -1,3 +1,80 @@
+// Build script - Tauri codegen, plus build info for `get_build_info`: `DARWIN_GIT_SHA`,
+// `DARWIN_BUILD_TIMESTAMP` and `DARWIN_RUSTC_VERSION`. Each is `"unknown"` when it
+// can't be determined (e.g. building from a release tarball without git).
+
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    let stdout = stdout.trim();
+    (output.status.success() && !stdout.is_empty()).then(|| stdout.to_string())
+}
+
+/// `secs` since the Unix epoch as `YYYY-MM-DDTHH:MM:SSZ`.
+fn rfc3339_utc(secs: u64) -> String {
+    let (days, rem) = ((secs / 86_400) as i64, secs % 86_400);
+    // Civil-from-days (Howard Hinnant), days counted from 1970-01-01
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+fn emit_build_info() {
+    let git_sha = command_output("git", &["rev-parse", "HEAD"]);
+    if let Some(git_dir) = command_output("git", &["rev-parse", "--git-dir"]) {
+        println!("cargo:rerun-if-changed={}/HEAD", git_dir);
+        println!("cargo:rerun-if-changed={}/refs", git_dir);
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    // Reproducible builds pin the time through SOURCE_DATE_EPOCH
+    let build_secs = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs())
+        });
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = command_output(&rustc, &["--version"]);
+
+    let unknown = || "unknown".to_string();
+    println!(
+        "cargo:rustc-env=DARWIN_GIT_SHA={}",
+        git_sha.unwrap_or_else(unknown)
+    );
+    println!(
+        "cargo:rustc-env=DARWIN_BUILD_TIMESTAMP={}",
+        build_secs.map(rfc3339_utc).unwrap_or_else(unknown)
+    );
+    println!(
+        "cargo:rustc-env=DARWIN_RUSTC_VERSION={}",
+        rustc_version.unwrap_or_else(unknown)
+    );
+}
+
 fn main() {
+    emit_build_info();
     tauri_build::build()
 }
@@ -0,0 +1,195 @@
+// Analysis estimates - runtime and memory of `analyze_scaffold` predicted from the volume size
+
+use crate::scaffold_info::ScaffoldInfo;
+use serde::{Deserialize, Serialize};
+
+/// `available_algorithms` entry of backends that estimate analyses themselves
+pub const BACKEND_ESTIMATE_ALGORITHM: &str = "analyze_estimate";
+
+/// Coefficients of the local model: `base + per_megavoxel * megavoxels^exponent`
+/// seconds and `base + per_megavoxel * megavoxels` MB. The defaults are fit to
+/// full analyses observed on a desktop CPU; refit them in the settings for other machines.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnalysisTimeModel {
+    /// Loading and setup, independent of the volume size
+    pub base_seconds: f64,
+    pub seconds_per_megavoxel: f64,
+    /// Above 1 because connectivity and pore-size passes grow faster than the voxel count
+    pub exponent: f64,
+    pub base_memory_mb: f64,
+    pub memory_mb_per_megavoxel: f64,
+    /// Observed runs fell within this factor of the model (0.5: between 1/1.5x and 1.5x)
+    pub relative_error: f64,
+}
+
+impl Default for AnalysisTimeModel {
+    fn default() -> Self {
+        Self {
+            base_seconds: 2.0,
+            seconds_per_megavoxel: 0.9,
+            exponent: 1.1,
+            base_memory_mb: 150.0,
+            memory_mb_per_megavoxel: 12.0,
+            relative_error: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnalysisEstimate {
+    /// Voxels analyzed, after any `max_voxels` downsampling
+    pub voxel_count: u64,
+    pub estimated_seconds: f64,
+    pub estimated_memory_mb: f64,
+    /// Low and high bounds of `estimated_seconds`
+    pub seconds_range: [f64; 2],
+    pub memory_mb_range: [f64; 2],
+    /// "backend" or "local"
+    #[serde(default = "backend_source")]
+    pub source: String,
+}
+
+fn backend_source() -> String {
+    "backend".to_string()
+}
+
+impl AnalysisTimeModel {
+    pub fn validate(&self) -> Result<(), String> {
+        let fields = [
+            ("base_seconds", self.base_seconds),
+            ("seconds_per_megavoxel", self.seconds_per_megavoxel),
+            ("exponent", self.exponent),
+            ("base_memory_mb", self.base_memory_mb),
+            ("memory_mb_per_megavoxel", self.memory_mb_per_megavoxel),
+            ("relative_error", self.relative_error),
+        ];
+        match fields.iter().find(|(_, v)| !(v.is_finite() && *v >= 0.0)) {
+            Some((name, value)) => Err(format!(
+                "analysis_time_model.{} must be a non-negative number, got {}",
+                name, value
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub fn estimate(&self, voxel_count: u64) -> AnalysisEstimate {
+        let megavoxels = voxel_count as f64 / 1e6;
+        let seconds =
+            self.base_seconds + self.seconds_per_megavoxel * megavoxels.powf(self.exponent);
+        let memory_mb = self.base_memory_mb + self.memory_mb_per_megavoxel * megavoxels;
+        let spread = 1.0 + self.relative_error;
+        AnalysisEstimate {
+            voxel_count,
+            estimated_seconds: seconds,
+            estimated_memory_mb: memory_mb,
+            seconds_range: [seconds / spread, seconds * spread],
+            memory_mb_range: [memory_mb / spread, memory_mb * spread],
+            source: "local".to_string(),
+        }
+    }
+}
+
+/// Smallest integer stride keeping at most `max_voxels` voxels, as `analyze_scaffold` applies it
+pub fn downsample_factor(dimensions: [u32; 3], max_voxels: u64) -> u64 {
+    let kept = |f: u64| {
+        dimensions
+            .iter()
+            .map(|&d| (d as u64).div_ceil(f))
+            .product::<u64>()
+    };
+    let largest = dimensions.iter().copied().max().unwrap_or(1).max(1) as u64;
+    (1..=largest)
+        .find(|&f| kept(f) <= max_voxels)
+        .unwrap_or(largest)
+}
+
+/// Voxels an analysis of the volume described by `info` goes through
+pub fn analyzed_voxels(info: &ScaffoldInfo, max_voxels: Option<u64>) -> Result<u64, String> {
+    let dimensions = info.dimensions_voxels.ok_or_else(|| {
+        format!(
+            "cannot estimate: the {} header does not report the volume size",
+            info.format
+        )
+    })?;
+    let factor = max_voxels.map_or(1, |max| downsample_factor(dimensions, max));
+    Ok(dimensions
+        .iter()
+        .map(|&d| (d as u64).div_ceil(factor))
+        .product())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(dimensions: Option<[u32; 3]>) -> ScaffoldInfo {
+        ScaffoldInfo {
+            format: "tiff".to_string(),
+            file_bytes: 1 << 20,
+            dimensions_voxels: dimensions,
+            voxel_size_mm: None,
+            bounding_box_mm: None,
+        }
+    }
+
+    #[test]
+    fn estimate_follows_the_model() {
+        let model = AnalysisTimeModel {
+            base_seconds: 1.0,
+            seconds_per_megavoxel: 2.0,
+            exponent: 1.0,
+            base_memory_mb: 100.0,
+            memory_mb_per_megavoxel: 10.0,
+            relative_error: 0.25,
+        };
+        let estimate = model.estimate(8_000_000);
+        assert_eq!(estimate.estimated_seconds, 17.0);
+        assert_eq!(estimate.estimated_memory_mb, 180.0);
+        assert_eq!(estimate.seconds_range, [13.6, 21.25]);
+        assert_eq!(estimate.memory_mb_range, [144.0, 225.0]);
+        assert_eq!(estimate.source, "local");
+
+        // Superlinear growth: 10x the voxels takes more than 10x the variable time
+        let default = AnalysisTimeModel::default();
+        let small = default.estimate(10_000_000).estimated_seconds - default.base_seconds;
+        let large = default.estimate(100_000_000).estimated_seconds - default.base_seconds;
+        assert!(large > 10.0 * small);
+    }
+
+    #[test]
+    fn voxel_count_comes_from_the_header_after_downsampling() {
+        let volume = info(Some([512, 512, 300]));
+        assert_eq!(analyzed_voxels(&volume, None).unwrap(), 78_643_200);
+        // Stride 3 keeps 171 x 171 x 100
+        assert_eq!(downsample_factor([512, 512, 300], 3_000_000), 3);
+        assert_eq!(
+            analyzed_voxels(&volume, Some(3_000_000)).unwrap(),
+            2_924_100
+        );
+        assert_eq!(
+            analyzed_voxels(&info(None), None).unwrap_err(),
+            "cannot estimate: the tiff header does not report the volume size"
+        );
+    }
+
+    #[test]
+    fn model_coefficients_are_validated_and_backend_replies_parse() {
+        assert!(AnalysisTimeModel::default().validate().is_ok());
+        let broken = AnalysisTimeModel {
+            exponent: f64::NAN,
+            ..Default::default()
+        };
+        assert!(broken
+            .validate()
+            .unwrap_err()
+            .starts_with("analysis_time_model.exponent"));
+
+        let reply: AnalysisEstimate = serde_json::from_str(
+            r#"{"voxel_count": 1000, "estimated_seconds": 3.5, "estimated_memory_mb": 200,
+                "seconds_range": [3, 5], "memory_mb_range": [150, 250]}"#,
+        )
+        .unwrap();
+        assert_eq!(reply.source, "backend");
+        assert_eq!(reply.seconds_range, [3.0, 5.0]);
+    }
+}
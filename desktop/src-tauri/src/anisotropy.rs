@@ -0,0 +1,128 @@
+// Pore anisotropy - mean-intercept-length fabric tensor of the scaffold's pore space
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+/// Orientation of the pore space from Julia's mean-intercept-length (MIL) analysis.
+///
+/// `eigenvalues` come sorted largest first and `primary_axis`, the eigenvector of
+/// the largest one, has unit length, so the UI can draw the ellipsoid and the
+/// axis without reordering anything.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnisotropyReport {
+    /// `1 - smallest / largest` eigenvalue: 0 for isotropic pores, towards 1 for aligned channels
+    pub degree_of_anisotropy: f64,
+    pub primary_axis: [f64; 3],
+    pub eigenvalues: [f64; 3],
+    /// Symmetric 3x3 tensor, row-major
+    pub fabric_tensor: [[f64; 3]; 3],
+}
+
+impl AnisotropyReport {
+    /// Sort the eigenvalues and normalize the axis as documented, rejecting NaNs and zero axes
+    fn normalized(mut self) -> Result<Self, String> {
+        let finite = self
+            .eigenvalues
+            .iter()
+            .chain(&self.primary_axis)
+            .chain(self.fabric_tensor.iter().flatten())
+            .chain([&self.degree_of_anisotropy])
+            .all(|v| v.is_finite());
+        if !finite {
+            return Err("invalid anisotropy: non-finite value in the backend reply".to_string());
+        }
+        let length = self.primary_axis.iter().map(|c| c * c).sum::<f64>().sqrt();
+        if length == 0.0 {
+            return Err("invalid anisotropy: primary axis has zero length".to_string());
+        }
+        self.primary_axis = self.primary_axis.map(|c| c / length);
+        self.eigenvalues.sort_by(|a, b| b.total_cmp(a));
+        Ok(self)
+    }
+}
+
+/// The report in Julia's reply to `GET /workspace/{id}/anisotropy`. A 404 with a
+/// JSON `error` means Julia has no geometry for the workspace yet (nothing loaded
+/// or generated); any other 404 means the backend lacks the route.
+pub fn from_reply(
+    workspace_id: &str,
+    status: StatusCode,
+    body: serde_json::Value,
+) -> Result<AnisotropyReport, String> {
+    if status == StatusCode::NOT_FOUND {
+        return Err(match body["error"].as_str() {
+            Some(_) => format!("workspace {} has no computed geometry yet", workspace_id),
+            None => "this backend does not support anisotropy analysis".to_string(),
+        });
+    }
+    match body["error"].as_str() {
+        Some(error) => Err(error.to_string()),
+        None if !status.is_success() => Err(format!("anisotropy analysis failed ({})", status)),
+        None => serde_json::from_value::<AnisotropyReport>(body)
+            .map_err(|e| format!("invalid anisotropy: {}", e))?
+            .normalized(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn julia_reply_deserializes_into_plot_order() {
+        let reply = json!({
+            "degree_of_anisotropy": 0.6,
+            "primary_axis": [0.0, 0.0, 2.0],
+            "eigenvalues": [0.2, 0.5, 0.3],
+            "fabric_tensor": [[0.2, 0.0, 0.0], [0.0, 0.3, 0.0], [0.0, 0.0, 0.5]],
+            "elapsed_ms": 412,
+        });
+        let report = from_reply("ws-1", StatusCode::OK, reply).unwrap();
+        assert_eq!(report.primary_axis, [0.0, 0.0, 1.0]);
+        assert_eq!(report.eigenvalues, [0.5, 0.3, 0.2]);
+        assert_eq!(report.fabric_tensor[2], [0.0, 0.0, 0.5]);
+
+        // Nested arrays must be exactly 3 long
+        let short = json!({
+            "degree_of_anisotropy": 0.1,
+            "primary_axis": [1.0, 0.0, 0.0],
+            "eigenvalues": [0.5, 0.3],
+            "fabric_tensor": [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        });
+        assert!(from_reply("ws-1", StatusCode::OK, short)
+            .unwrap_err()
+            .starts_with("invalid anisotropy"));
+    }
+
+    #[test]
+    fn missing_workspace_geometry_and_backend_errors_are_reported() {
+        assert_eq!(
+            from_reply(
+                "ws-empty",
+                StatusCode::NOT_FOUND,
+                json!({"error": "No volume data"})
+            )
+            .unwrap_err(),
+            "workspace ws-empty has no computed geometry yet"
+        );
+        // A 404 without Julia's JSON error comes from a backend lacking the route
+        assert_eq!(
+            from_reply("ws-1", StatusCode::NOT_FOUND, json!(null)).unwrap_err(),
+            "this backend does not support anisotropy analysis"
+        );
+        assert_eq!(
+            from_reply(
+                "ws-1",
+                StatusCode::UNPROCESSABLE_ENTITY,
+                json!({"error": "pore space is empty"})
+            )
+            .unwrap_err(),
+            "pore space is empty"
+        );
+        assert_eq!(
+            from_reply("ws-1", StatusCode::BAD_GATEWAY, json!(null)).unwrap_err(),
+            "anisotropy analysis failed (502 Bad Gateway)"
+        );
+    }
+}
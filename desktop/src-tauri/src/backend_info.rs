@@ -0,0 +1,145 @@
+// Backend capabilities - what the connected Julia server can do, as reported by `GET /info`
+
+use crate::surface_types::{self, SurfaceType};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackendInfo {
+    pub version: String,
+    #[serde(default)]
+    pub available_algorithms: Vec<String>,
+    #[serde(default)]
+    pub supported_export_formats: Vec<String>,
+    #[serde(default)]
+    pub supported_surface_types: Vec<String>,
+    /// Details of the surface types, from backends that send them
+    #[serde(default)]
+    pub surface_types: Vec<SurfaceType>,
+    /// True when the backend has no `/info` endpoint and these are the legacy defaults
+    #[serde(default)]
+    pub assumed: bool,
+}
+
+impl BackendInfo {
+    /// What every backend release can do, for servers predating `/info`
+    pub fn legacy() -> Self {
+        Self {
+            version: "unknown".to_string(),
+            available_algorithms: ["analyze", "optimize", "tpms_generate"]
+                .map(String::from)
+                .to_vec(),
+            supported_export_formats: vec!["stl".to_string()],
+            supported_surface_types: vec!["gyroid".to_string()],
+            surface_types: Vec::new(),
+            assumed: true,
+        }
+    }
+
+    /// Every supported surface type, with bundled details where the backend sent none
+    pub fn surface_types(&self) -> Vec<SurfaceType> {
+        let detailed = |id: &str| {
+            self.surface_types
+                .iter()
+                .find(|t| t.id.eq_ignore_ascii_case(id))
+                .cloned()
+        };
+        let mut types: Vec<SurfaceType> = self
+            .supported_surface_types
+            .iter()
+            .map(|id| detailed(id).unwrap_or_else(|| surface_types::bundled_or_generic(id)))
+            .collect();
+        for extra in &self.surface_types {
+            if !types.iter().any(|t| t.id.eq_ignore_ascii_case(&extra.id)) {
+                types.push(extra.clone());
+            }
+        }
+        types
+    }
+
+    pub fn ensure_surface_type(&self, surface_type: &str) -> Result<(), String> {
+        let ids: Vec<String> = self.surface_types().into_iter().map(|t| t.id).collect();
+        self.ensure("surface type", surface_type, &ids)
+    }
+
+    /// Pre-flight check of TPMS parameters: a supported surface type and a porosity it can reach
+    pub fn ensure_tpms(&self, surface_type: &str, porosity: f64) -> Result<(), String> {
+        self.ensure_surface_type(surface_type)?;
+        match self
+            .surface_types()
+            .iter()
+            .find(|t| t.id.eq_ignore_ascii_case(surface_type))
+        {
+            Some(surface_type) => surface_type.check_porosity(porosity),
+            None => Ok(()),
+        }
+    }
+
+    pub fn ensure_export_format(&self, format: &str) -> Result<(), String> {
+        self.ensure("export format", format, &self.supported_export_formats)
+    }
+
+    fn ensure(&self, what: &str, value: &str, supported: &[String]) -> Result<(), String> {
+        if supported.iter().any(|s| s.eq_ignore_ascii_case(value)) {
+            return Ok(());
+        }
+        Err(format!(
+            "{} '{}' is unsupported by backend {} (supported: {})",
+            what,
+            value,
+            self.version,
+            supported.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_info_and_tolerates_missing_lists() {
+        let info: BackendInfo = serde_json::from_str(
+            r#"{"version": "1.2.0", "supported_surface_types": ["gyroid", "diamond"], "extra": 1}"#,
+        )
+        .unwrap();
+        assert!(!info.assumed);
+        assert!(info.supported_export_formats.is_empty());
+        assert!(info.ensure_surface_type("Diamond").is_ok());
+    }
+
+    #[test]
+    fn legacy_backends_only_get_the_conservative_set() {
+        let info = BackendInfo::legacy();
+        assert!(info.ensure_export_format("stl").is_ok());
+        assert_eq!(
+            info.ensure_surface_type("neovius").unwrap_err(),
+            "surface type 'neovius' is unsupported by backend unknown (supported: gyroid)"
+        );
+        assert!(info.ensure_export_format("3mf").is_err());
+    }
+
+    #[test]
+    fn tpms_porosity_is_checked_against_the_backends_ranges() {
+        let info: BackendInfo = serde_json::from_str(
+            r#"{"version": "1.3.0", "supported_surface_types": ["gyroid", "neovius"],
+                "surface_types": [{"id": "neovius", "display_name": "Neovius", "porosity_range": [0.4, 0.6]},
+                                  {"id": "lidinoid", "display_name": "Lidinoid", "porosity_range": [0.5, 0.7]}]}"#,
+        )
+        .unwrap();
+        let ids: Vec<String> = info.surface_types().into_iter().map(|t| t.id).collect();
+        assert_eq!(ids, ["gyroid", "neovius", "lidinoid"]);
+
+        // Bundled range for gyroid, the backend's own for the others
+        assert!(info.ensure_tpms("gyroid", 0.9).is_ok());
+        assert!(info.ensure_tpms("Neovius", 0.5).is_ok());
+        assert_eq!(
+            info.ensure_tpms("neovius", 0.7).unwrap_err(),
+            "porosity 0.7 is out of reach for Neovius (achievable: 0.4 to 0.6)"
+        );
+        assert!(info.ensure_tpms("lidinoid", 0.45).is_err());
+        assert!(info
+            .ensure_tpms("schwarz_p", 0.5)
+            .unwrap_err()
+            .contains("unsupported by backend 1.3.0"));
+    }
+}
@@ -0,0 +1,155 @@
+// Single-flight cache - concurrent first requests for a key share one in-flight fetch
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+type Cell<V> = Arc<OnceCell<(V, Instant)>>;
+
+/// Values by key, each fetched at most once at a time: callers arriving while a
+/// fetch runs wait for its result instead of starting their own. Failed fetches
+/// are not cached, so the next caller retries. With a `ttl`, older values are
+/// fetched again.
+///
+/// Cheap to clone; clones share the same entries, so a cache can be taken out of
+/// the app state lock before awaiting.
+#[derive(Debug)]
+pub struct OnceCache<K, V> {
+    cells: Arc<Mutex<HashMap<K, Cell<V>>>>,
+    ttl: Option<Duration>,
+}
+
+impl<K, V> Clone for OnceCache<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            cells: self.cells.clone(),
+            ttl: self.ttl,
+        }
+    }
+}
+
+impl<K, V> Default for OnceCache<K, V> {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl<K, V> OnceCache<K, V> {
+    /// Values are kept until invalidated, or for `ttl` when given
+    pub fn new(ttl: Option<Duration>) -> Self {
+        Self {
+            cells: Arc::new(Mutex::new(HashMap::new())),
+            ttl,
+        }
+    }
+}
+
+impl<K: Eq + Hash, V: Clone> OnceCache<K, V> {
+    /// The value for `key`, running `fetch` unless it is cached or already being fetched
+    pub async fn get_or_try_init<F, Fut, E>(&self, key: K, fetch: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        let cell = {
+            let mut cells = self.cells.lock().unwrap();
+            let cell = cells.entry(key).or_default();
+            let expired = |fetched: &Instant| self.ttl.is_some_and(|ttl| fetched.elapsed() >= ttl);
+            if cell.get().is_some_and(|(_, fetched)| expired(fetched)) {
+                *cell = Cell::default();
+            }
+            cell.clone()
+        };
+        let (value, _) = cell
+            .get_or_try_init(|| async { Ok::<_, E>((fetch().await?, Instant::now())) })
+            .await?;
+        Ok(value.clone())
+    }
+
+    /// Drop the value for `key`; a fetch already running still completes for its waiters
+    pub fn invalidate(&self, key: &K) {
+        self.cells.lock().unwrap().remove(key);
+    }
+
+    pub fn clear(&self) {
+        self.cells.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Fetch `value` after a short delay, counting the calls
+    async fn slow_fetch(calls: &AtomicUsize, value: &str) -> Result<String, String> {
+        calls.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        Ok(value.to_string())
+    }
+
+    #[tokio::test]
+    async fn concurrent_first_calls_share_one_fetch() {
+        let cache: OnceCache<String, String> = OnceCache::default();
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let mut callers = tokio::task::JoinSet::new();
+        for _ in 0..10 {
+            let (cache, calls) = (cache.clone(), calls.clone());
+            callers.spawn(async move {
+                cache
+                    .get_or_try_init("http://localhost:8081".to_string(), || {
+                        slow_fetch(&calls, "1.4.0")
+                    })
+                    .await
+            });
+        }
+        while let Some(result) = callers.join_next().await {
+            assert_eq!(result.unwrap().unwrap(), "1.4.0");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Another key is fetched on its own
+        let other = cache
+            .get_or_try_init("http://lab:8081".to_string(), || {
+                slow_fetch(&calls, "1.3.0")
+            })
+            .await;
+        assert_eq!(other.unwrap(), "1.3.0");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn failures_are_retried_and_values_can_expire() {
+        let cache: OnceCache<(), String> = OnceCache::new(Some(Duration::from_millis(100)));
+        let calls = AtomicUsize::new(0);
+
+        let failed = cache
+            .get_or_try_init((), || async { Err::<String, _>("connection refused") })
+            .await;
+        assert_eq!(failed.unwrap_err(), "connection refused");
+        assert_eq!(
+            cache.get_or_try_init((), || slow_fetch(&calls, "a")).await,
+            Ok("a".to_string())
+        );
+        assert_eq!(
+            cache.get_or_try_init((), || slow_fetch(&calls, "b")).await,
+            Ok("a".to_string())
+        );
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(
+            cache.get_or_try_init((), || slow_fetch(&calls, "c")).await,
+            Ok("c".to_string())
+        );
+        cache.invalidate(&());
+        assert_eq!(
+            cache.get_or_try_init((), || slow_fetch(&calls, "d")).await,
+            Ok("d".to_string())
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
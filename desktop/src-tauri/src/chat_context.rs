@@ -0,0 +1,92 @@
+// Chat context - what the agent is told about the session alongside a chat message
+
+use crate::state::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Context sent to Julia with a chat message. Missing fields default, and fields
+/// this build doesn't know are passed through to Julia untouched.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChatContext {
+    pub workspace_id: Option<String>,
+    /// Metric names the user has highlighted, e.g. "porosity"
+    pub selected_metrics: Vec<String>,
+    /// Paths of files the user attached to the message
+    pub attached_files: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl ChatContext {
+    /// Check that the workspace is tracked by this session and the attached files exist.
+    pub fn validate(&self, state: &AppState) -> Result<(), String> {
+        if let Some(workspace_id) = &self.workspace_id {
+            state.require_workspace(workspace_id)?;
+        }
+        let missing: Vec<&str> = self
+            .attached_files
+            .iter()
+            .filter(|path| !std::path::Path::new(path).is_file())
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            return Err(format!("attached file not found: {}", missing.join(", ")));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(value: Value) -> ChatContext {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn partial_and_unknown_fields_deserialize() {
+        let context = context(serde_json::json!({
+            "selected_metrics": ["porosity"],
+            "material": "PCL",
+        }));
+        assert_eq!(context.workspace_id, None);
+        assert!(context.attached_files.is_empty());
+        assert_eq!(context.extra["material"], "PCL");
+        // Passed through to Julia as it came in
+        let sent = serde_json::to_value(&context).unwrap();
+        assert_eq!(sent["material"], "PCL");
+        assert_eq!(sent["selected_metrics"][0], "porosity");
+    }
+
+    #[test]
+    fn validates_workspace_and_file_references() {
+        let mut state = AppState::default();
+        let workspace_id = state.create_workspace(None, None).id.clone();
+        let scan = std::env::temp_dir().join(format!("chat-context-{}.tif", workspace_id));
+        std::fs::write(&scan, b"II*\0").unwrap();
+        let scan = scan.to_string_lossy().into_owned();
+
+        let valid = context(serde_json::json!({
+            "workspace_id": workspace_id,
+            "attached_files": [scan],
+        }));
+        assert_eq!(valid.validate(&state), Ok(()));
+
+        let dangling_workspace = context(serde_json::json!({"workspace_id": "ws-gone"}));
+        assert_eq!(
+            dangling_workspace.validate(&state).unwrap_err(),
+            "unknown workspace: ws-gone"
+        );
+
+        let dangling_file = context(serde_json::json!({
+            "attached_files": [scan, "/nonexistent/scan.tif"],
+        }));
+        assert_eq!(
+            dangling_file.validate(&state).unwrap_err(),
+            "attached file not found: /nonexistent/scan.tif"
+        );
+        std::fs::remove_file(scan).unwrap();
+    }
+}
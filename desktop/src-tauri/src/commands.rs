@@ -1,10 +1,47 @@
 // Tauri command handlers - bridge between frontend and backend
 
+use crate::analysis_estimate::{self, AnalysisEstimate};
+use crate::anisotropy::{self, AnisotropyReport};
+use crate::backend_info::BackendInfo;
+use crate::chat_context::ChatContext;
+use crate::comparison::{self, MetricsComparison};
+use crate::depth_profile::{self, DepthProfile};
+use crate::export_stream;
+use crate::history::HistoryEntry;
+use crate::http_client;
+use crate::job_queue::JobQueueStatus;
 use crate::julia_bridge;
-use crate::state::{AppSettings, AppState};
+use crate::julia_env;
+use crate::library::{self, LibraryEntry, LibraryHit, ScaffoldQuery};
+use crate::materials::{self, Material};
+use crate::merge::{self, MergeInput, MergePart};
+use crate::mesh_diff::{self, MeshDiff};
+use crate::metric_selection;
+use crate::metrics_export;
+use crate::pagination::{self, Page};
+use crate::param_history::ParamSnapshot;
+use crate::param_sweep::{self, ParamSweep, SweepResult};
+use crate::print_estimate::{self, PrintEstimate};
+use crate::printability::{self, PrintGeometry, PrintabilityReport};
+use crate::printer_profiles::{self, PrinterProfile};
+use crate::project::{self, ProjectManifest, ProjectSummary};
+use crate::report::{self, ReportFormat, Section, TargetRange};
+use crate::roi::Roi;
+use crate::scaffold_info::{self, ScaffoldInfo};
+use crate::scheduled_jobs::{self, ReprocessSummary, ReprocessTarget, ScheduledJobInfo};
+use crate::snapshots::{self, RestoreOutcome, SnapshotMeta};
+use crate::state::{AppSettings, AppState, ChatEntry, WorkspaceState};
+use crate::stiffness::{self, StiffnessReport};
+use crate::stl_writer::{self, Mesh};
+use crate::surface_types::SurfaceType;
+use crate::thumbnails;
+use crate::units;
 use serde::{Deserialize, Serialize};
 use std::sync::Mutex;
-use tauri::{AppHandle, State};
+use tauri::{AppHandle, Manager, State};
+
+const ANALYZE_MAX_ATTEMPTS: u32 = 2;
+const ANALYZE_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
 
 #[derive(Debug, Serialize)]
 pub struct JuliaStatus {
@@ -13,7 +50,7 @@ pub struct JuliaStatus {
     pub url: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScaffoldMetrics {
     pub porosity: f64,
     pub mean_pore_size_um: f64,
@@ -25,6 +62,48 @@ pub struct ScaffoldMetrics {
     pub permeability: f64,
 }
 
+impl ScaffoldMetrics {
+    /// Serialized field names, in `fields()` order
+    pub const FIELD_NAMES: [&'static str; 8] = [
+        "porosity",
+        "mean_pore_size_um",
+        "interconnectivity",
+        "tortuosity",
+        "specific_surface_area",
+        "elastic_modulus",
+        "yield_strength",
+        "permeability",
+    ];
+
+    /// Column headers for exports, in `fields()` order: the field names with
+    /// every unit spelled out
+    pub const COLUMN_NAMES: [&'static str; 8] = [
+        "porosity",
+        "mean_pore_size_um",
+        "interconnectivity",
+        "tortuosity",
+        "specific_surface_area_per_mm",
+        "elastic_modulus_mpa",
+        "yield_strength_mpa",
+        "permeability_m2",
+    ];
+
+    /// Field names paired with their values
+    pub fn fields(&self) -> [(&'static str, f64); 8] {
+        let values = [
+            self.porosity,
+            self.mean_pore_size_um,
+            self.interconnectivity,
+            self.tortuosity,
+            self.specific_surface_area,
+            self.elastic_modulus,
+            self.yield_strength,
+            self.permeability,
+        ];
+        std::array::from_fn(|i| (Self::FIELD_NAMES[i], values[i]))
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TPMSParams {
     pub surface_type: String,
@@ -33,11 +112,114 @@ pub struct TPMSParams {
     pub n_cells: [u32; 3],
 }
 
+/// Current parameters of a workspace after an undo/redo or a change
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkspaceParams {
+    pub workspace_id: String,
+    pub params: ParamSnapshot,
+    /// `false` when undo/redo had nothing to step to
+    pub changed: bool,
+    pub can_undo: bool,
+    pub can_redo: bool,
+}
+
+impl WorkspaceParams {
+    fn of(workspace: &WorkspaceState, changed: bool) -> Self {
+        Self {
+            workspace_id: workspace.id.clone(),
+            params: workspace.history.current().clone(),
+            changed,
+            can_undo: workspace.history.can_undo(),
+            can_redo: workspace.history.can_redo(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TPMSEstimate {
+    pub estimated_triangles: u64,
+    pub estimated_bytes: u64,
+    pub estimated_seconds: f64,
+    pub memory_mb: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeshReport {
+    pub manifold: bool,
+    pub watertight: bool,
+    pub degenerate_faces: u64,
+    pub self_intersections: u64,
+    pub bounding_box_mm: [f64; 3],
+}
+
+/// Outcome of `simplify_mesh`; the result is stored as `mesh_revision` in Julia
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeshSimplification {
+    pub workspace_id: String,
+    pub mesh_revision: u32,
+    pub triangles_before: u64,
+    pub triangles_after: u64,
+    pub achieved_reduction: f64,
+}
+
+/// How `export_stl` builds and checks the mesh it writes
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExportStlOptions {
+    pub quality: String,
+    /// Refuse to export a mesh that is not watertight
+    #[serde(default)]
+    pub validate: bool,
+    /// A mesh produced by `simplify_mesh` instead of the original
+    #[serde(default)]
+    pub mesh_revision: Option<u32>,
+    /// Warn when the mesh does not fit this printer's build volume
+    #[serde(default)]
+    pub printer_profile: Option<String>,
+}
+
+/// Payload of the periodic progress events of long Julia calls
+#[derive(Debug, Clone, Serialize)]
+struct StageProgress<'a> {
+    workspace_id: &'a str,
+    stage: &'a str,
+    elapsed_ms: u64,
+}
+
+/// Payload of `export-progress`; the final event also carries `output_path` and `size_bytes`
+#[derive(Debug, Clone, Serialize)]
+struct ExportProgress<'a> {
+    workspace_id: &'a str,
+    /// None when Julia did not announce the export size
+    percent: Option<u8>,
+    bytes_written: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_path: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ExportFailed<'a> {
+    workspace_id: &'a str,
+    output_path: &'a str,
+    error: &'a str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SweepProgress<'a> {
+    sweep_id: &'a str,
+    index: usize,
+    completed: usize,
+    total: usize,
+    ok: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChatMessage {
     pub message: String,
     pub agent: String,
-    pub context: Option<serde_json::Value>,
+    #[serde(default)]
+    pub context: Option<ChatContext>,
 }
 
 // Julia server status
@@ -51,6 +233,116 @@ pub fn get_julia_status(state: State<'_, Mutex<AppState>>) -> JuliaStatus {
     }
 }
 
+// Probe the configured Julia server now and measure its latency
+//
+// Unlike `get_julia_status`, which reports the cached flag, this performs a real
+// request; meant for polling from the status UI.
+#[tauri::command]
+pub async fn ping_julia(
+    state: State<'_, Mutex<AppState>>,
+) -> Result<julia_bridge::JuliaPing, String> {
+    let (url, client) = {
+        let state = state.lock().unwrap();
+        (
+            state.settings.julia_server_url.clone(),
+            state.http.client().clone(),
+        )
+    };
+    Ok(julia_bridge::ping(&client, &url).await)
+}
+
+// Check that a Julia server answers at `url` (default: the configured one)
+// before it is saved in settings
+#[tauri::command]
+pub async fn test_julia_connection(
+    url: Option<String>,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<JuliaStatus, String> {
+    let (url, pid, client) = {
+        let state = state.lock().unwrap();
+        let url = url.unwrap_or_else(|| state.settings.julia_server_url.clone());
+        // Only a server we spawned ourselves has a pid
+        let pid = if url == state.settings.julia_server_url && julia_bridge::is_local_url(&url) {
+            state.julia_pid
+        } else {
+            None
+        };
+        (url, pid, state.http.client().clone())
+    };
+
+    julia_bridge::check_health(&client, &url)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(JuliaStatus {
+        running: true,
+        pid,
+        url,
+    })
+}
+
+// Capabilities and version of the connected Julia backend
+//
+// Fetched from Julia's `/info` once and cached until `julia_server_url` changes;
+// concurrent first calls share a single request.
+// Backends without `/info` get the conservative `BackendInfo::legacy()` set,
+// flagged `assumed: true`. The UI uses this to hide unsupported options;
+// `generate_tpms`, `generate_tpms_sweep` and `export_stl` refuse them.
+#[tauri::command]
+pub async fn get_backend_info(state: State<'_, Mutex<AppState>>) -> Result<BackendInfo, String> {
+    backend_info(&state).await
+}
+
+// TPMS surface types the backend can generate, with the porosity range each can reach
+#[tauri::command]
+pub async fn list_surface_types(
+    state: State<'_, Mutex<AppState>>,
+) -> Result<Vec<SurfaceType>, String> {
+    Ok(backend_info(&state).await?.surface_types())
+}
+
+async fn backend_info(state: &State<'_, Mutex<AppState>>) -> Result<BackendInfo, String> {
+    let (base_url, cache, client) = {
+        let state = state.lock().unwrap();
+        (
+            state.settings.julia_server_url.clone(),
+            state.backend_info.clone(),
+            state.http.client().clone(),
+        )
+    };
+    // Keyed by URL, so a reply for a URL the settings moved away from is never served
+    cache
+        .get_or_try_init(base_url.clone(), || fetch_backend_info(client, base_url))
+        .await
+}
+
+async fn fetch_backend_info(
+    client: reqwest::Client,
+    base_url: String,
+) -> Result<BackendInfo, String> {
+    let response = client
+        .get(format!("{}/info", base_url))
+        .send()
+        .await
+        .map_err(http_client::describe)?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(BackendInfo::legacy());
+    }
+    let response = response.error_for_status().map_err(http_client::describe)?;
+    http_client::json(response)
+        .await
+        .map_err(|e| format!("invalid backend info: {}", e))
+}
+
+async fn find_material(
+    app: &AppHandle,
+    state: &State<'_, Mutex<AppState>>,
+    name: &str,
+) -> Option<Material> {
+    let cache = state.lock().unwrap().materials.clone();
+    materials::find_cached_material(app, &cache, name).await
+}
+
 // Start Julia server
 #[tauri::command]
 pub async fn start_julia_server(app: AppHandle) -> Result<(), String> {
@@ -59,6 +351,13 @@ pub async fn start_julia_server(app: AppHandle) -> Result<(), String> {
         .map_err(|e| e.to_string())
 }
 
+// Check that Julia is installed and the project's packages are instantiated
+#[tauri::command]
+pub async fn check_julia_environment() -> Result<julia_env::EnvReport, String> {
+    let project_root = julia_bridge::project_root().map_err(|e| e.to_string())?;
+    julia_env::check(&project_root).await
+}
+
 // Stop Julia server
 #[tauri::command]
 pub async fn stop_julia_server(app: AppHandle) -> Result<(), String> {
@@ -118,139 +417,2451 @@ pub async fn save_file_dialog(
     rx.recv().map_err(|e| e.to_string())
 }
 
-// Analyze scaffold via Julia API
+// Open a scaffold or exported file in the OS default application
+//
+// Only existing files under the app data directory (history, snapshots,
+// thumbnails, projects), the temp directory Julia writes generated meshes and
+// exports to, or the downloads directory may be opened, and never programs or
+// scripts.
 #[tauri::command]
-pub async fn analyze_scaffold(
+pub fn open_in_system(app: AppHandle, path: String) -> Result<(), String> {
+    let path = std::fs::canonicalize(&path).map_err(|e| format!("{}: {}", path, e))?;
+    let allowed_dirs: Vec<std::path::PathBuf> = [
+        app.path_resolver().app_data_dir(),
+        Some(std::env::temp_dir()),
+        tauri::api::path::download_dir(),
+    ]
+    .into_iter()
+    .flatten()
+    .filter_map(|dir| std::fs::canonicalize(dir).ok())
+    .collect();
+    check_openable(&path, &allowed_dirs)?;
+
+    open::that(&path).map_err(|e| {
+        let kind = path
+            .extension()
+            .map(|ext| format!(".{} files", ext.to_string_lossy()))
+            .unwrap_or_else(|| "this file".to_string());
+        format!("No application is registered to open {} ({})", kind, e)
+    })
+}
+
+// Extensions the OS would run rather than view
+const EXECUTABLE_EXTENSIONS: &[&str] = &[
+    "app", "appimage", "bat", "bash", "cmd", "com", "command", "cpl", "csh", "deb", "desktop",
+    "dmg", "exe", "fish", "hta", "jar", "js", "jse", "ksh", "lnk", "msc", "msi", "pkg", "pl",
+    "ps1", "psm1", "py", "rb", "reg", "rpm", "run", "scr", "sh", "vbe", "vbs", "workflow", "wsf",
+    "zsh",
+];
+
+// `path` (canonical) must be a non-executable file inside one of `allowed_dirs`
+fn check_openable(
+    path: &std::path::Path,
+    allowed_dirs: &[std::path::PathBuf],
+) -> Result<(), String> {
+    if !path.is_file() {
+        return Err(format!("{} is not a file", path.display()));
+    }
+    if !allowed_dirs.iter().any(|dir| path.starts_with(dir)) {
+        return Err(format!(
+            "{} is outside the directories the app may open",
+            path.display()
+        ));
+    }
+
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+    #[cfg(unix)]
+    let executable = {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::metadata(path).is_ok_and(|meta| meta.permissions().mode() & 0o111 != 0)
+    };
+    #[cfg(not(unix))]
+    let executable = false;
+    if executable || extension.is_some_and(|ext| EXECUTABLE_EXTENSIONS.contains(&ext.as_str())) {
+        return Err(format!(
+            "{} is a program or script and will not be opened",
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+// Size and voxel spacing of a volume file, read from its TIFF/NIfTI header only
+//
+// Cheap enough to validate a file before `analyze_scaffold`. Other formats
+// report just `file_bytes` with `format: "unknown"`.
+#[tauri::command]
+pub async fn get_scaffold_info(file_path: String) -> Result<ScaffoldInfo, String> {
+    tokio::task::spawn_blocking(move || scaffold_info::read_info(std::path::Path::new(&file_path)))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+// Predict how long `analyze_scaffold` takes on a volume and how much memory it needs
+//
+// Backends listing "analyze_estimate" in `available_algorithms` answer this
+// themselves; otherwise, or when they fail, the volume size from the file header
+// (after `max_voxels` downsampling, as the analysis would apply it) goes through
+// the `analysis_time_model` setting. `source` tells which one answered, and
+// `seconds_range` / `memory_mb_range` bound the estimate.
+#[tauri::command]
+pub async fn estimate_analysis_time(
     file_path: String,
     voxel_size: f64,
+    voxel_unit: Option<String>,
+    max_voxels: Option<u64>,
     state: State<'_, Mutex<AppState>>,
-) -> Result<serde_json::Value, String> {
-    let url = {
+) -> Result<AnalysisEstimate, String> {
+    if !(voxel_size.is_finite() && voxel_size > 0.0) {
+        return Err("voxel_size must be a positive number".to_string());
+    }
+    if max_voxels == Some(0) {
+        return Err("max_voxels must be a positive integer".to_string());
+    }
+    let (base_url, default_unit, model, client) = {
         let state = state.lock().unwrap();
-        format!("{}/analyze", state.settings.julia_server_url)
+        (
+            state.settings.julia_server_url.clone(),
+            state.settings.voxel_unit.clone(),
+            state.settings.analysis_time_model.clone(),
+            state.http.client().clone(),
+        )
     };
+    let voxel_unit = units::normalize_unit(&voxel_unit.unwrap_or(default_unit))?;
+    let voxel_size_um = units::convert_length(voxel_size, voxel_unit, units::CANONICAL_VOXEL_UNIT)?;
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post(&url)
-        .json(&serde_json::json!({
+    let backend_estimates = backend_info(&state).await.is_ok_and(|info| {
+        info.available_algorithms
+            .iter()
+            .any(|a| a == analysis_estimate::BACKEND_ESTIMATE_ALGORITHM)
+    });
+    if backend_estimates {
+        let request = serde_json::json!({
             "file_path": file_path,
-            "voxel_size": voxel_size
-        }))
+            "voxel_size": voxel_size_um,
+            "voxel_unit": units::CANONICAL_VOXEL_UNIT,
+            "max_voxels": max_voxels,
+        });
+        match fetch_analysis_estimate(&client, &base_url, &request).await {
+            Ok(estimate) => return Ok(estimate),
+            Err(e) => eprintln!(
+                "estimate_analysis_time: backend estimate failed, using the local model: {}",
+                e
+            ),
+        }
+    }
+
+    let path = std::path::PathBuf::from(&file_path);
+    let info = tokio::task::spawn_blocking(move || scaffold_info::read_info(&path))
+        .await
+        .map_err(|e| e.to_string())??;
+    Ok(model.estimate(analysis_estimate::analyzed_voxels(&info, max_voxels)?))
+}
+
+async fn fetch_analysis_estimate(
+    client: &reqwest::Client,
+    base_url: &str,
+    request: &serde_json::Value,
+) -> Result<AnalysisEstimate, String> {
+    let response = client
+        .post(format!("{}/analyze/estimate", base_url))
+        .json(request)
         .send()
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(http_client::describe)?;
+    http_client::json_or_error(response, "analysis estimate").await
+}
+
+// Analyze scaffold via Julia API
+//
+// The result is annotated with the properties of the default material.
+//
+// `idempotency_key` must be generated by the caller, one per logical analysis
+// request (e.g. a UUID), and reused when retrying that same request. It is sent
+// to Julia as `Idempotency-Key`, and once a call with that key has completed its
+// result is returned from cache instead of re-running the analysis. Connection
+// failures are retried once automatically.
+//
+// With `max_voxels`, larger volumes are downsampled by an integer stride before
+// analysis; the stride used is reported as `downsample_factor`. It is picked here
+// from the file header (or the roi) like darwin-server does, so the two agree.
+//
+// `roi` ([x0, y0, z0, x1, y1, z1] in voxels, upper bounds exclusive) crops the
+// volume before anything else. It must be non-empty and, when the file header
+// reports the volume size, inside the volume; the result echoes it as
+// `roi: {box, voxel_count}`.
+//
+// `metrics` limits the analysis to those ScaffoldMetrics fields (e.g.
+// ["porosity", "mean_pore_size_um"] for a quick check); the result then carries
+// just those under `metrics` and the rest under `skipped_metrics`.
+//
+// A successful analysis is tracked as `workspace_id` (a new workspace named after
+// the file unless an existing one is given), which the result carries.
+//
+// Runs through the shared job queue; pass a `job_id` to be able to
+// `cancel_job` it while it waits.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn analyze_scaffold(
+    app: AppHandle,
+    file_path: String,
+    voxel_size: f64,
+    voxel_unit: Option<String>,
+    max_voxels: Option<u64>,
+    idempotency_key: Option<String>,
+    job_id: Option<String>,
+    workspace_id: Option<String>,
+    roi: Option<[u32; 6]>,
+    metrics: Option<Vec<String>>,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<serde_json::Value, String> {
+    if max_voxels == Some(0) {
+        return Err("max_voxels must be a positive integer".to_string());
+    }
+    let roi = roi.map(Roi::new).transpose()?;
+    let metrics = metrics
+        .as_deref()
+        .map(metric_selection::validate)
+        .transpose()?;
+    let mut dimensions = None;
+    if roi.is_some() || max_voxels.is_some() {
+        let path = std::path::PathBuf::from(&file_path);
+        let info = tokio::task::spawn_blocking(move || scaffold_info::read_info(&path))
+            .await
+            .map_err(|e| e.to_string())?;
+        dimensions = match (info, roi) {
+            (Ok(info), _) => info.dimensions_voxels,
+            (Err(e), Some(_)) => return Err(e),
+            (Err(_), None) => None,
+        };
+    }
+    if let (Some(roi), Some(dimensions)) = (roi, dimensions) {
+        roi.check_within(dimensions)?;
+    }
+    // Julia crops to the roi before downsampling. Without a known size it picks
+    // the factor itself from `max_voxels`, as it does for darwin-server.
+    let downsample_factor = max_voxels
+        .zip(roi.map(|roi| roi.dimensions()).or(dimensions))
+        .map(|(max_voxels, dimensions)| {
+            analysis_estimate::downsample_factor(dimensions, max_voxels)
+        });
+    let key = idempotency_key.clone();
+    analyze_once(&state, key.as_deref(), || async {
+        let (url, material_name, default_unit, job_queue, client) = {
+            let state = state.lock().unwrap();
+            if let Some(workspace_id) = &workspace_id {
+                state.require_workspace(workspace_id)?;
+            }
+            (
+                format!("{}/analyze", state.settings.julia_server_url),
+                state.settings.default_material.clone(),
+                state.settings.voxel_unit.clone(),
+                state.job_queue.clone(),
+                state.http.client().clone(),
+            )
+        };
+
+        let voxel_unit = units::normalize_unit(&voxel_unit.unwrap_or_else(|| {
+            eprintln!(
+                "analyze_scaffold: voxel_unit not specified, assuming '{}'",
+                default_unit
+            );
+            default_unit
+        }))?;
+        let voxel_size_um =
+            units::convert_length(voxel_size, voxel_unit, units::CANONICAL_VOXEL_UNIT)?;
+
+        let _job = job_queue.acquire(job_id.as_deref()).await?;
+        let mut body = serde_json::json!({
+            "file_path": file_path,
+            "voxel_size": voxel_size_um,
+            "voxel_unit": units::CANONICAL_VOXEL_UNIT,
+            "max_voxels": max_voxels,
+            "roi": roi,
+            "metrics": metrics
+        });
+        if let Some(factor) = downsample_factor {
+            body["downsample_factor"] = serde_json::json!(factor);
+        }
+        let response = send_analysis(
+            || {
+                let request = client.post(&url).json(&body);
+                match &idempotency_key {
+                    Some(key) => request.header("Idempotency-Key", key),
+                    None => request,
+                }
+            },
+            ANALYZE_MAX_ATTEMPTS,
+            ANALYZE_RETRY_DELAY,
+        )
+        .await?;
+
+        let succeeded = response.status().is_success();
+        let mut result: serde_json::Value = http_client::json(response).await?;
+        if let (true, Some(metrics)) = (succeeded, &metrics) {
+            metric_selection::restrict(&mut result, metrics);
+        }
+        if let Some(obj) = result.as_object_mut() {
+            obj.insert(
+                "voxel_size".to_string(),
+                serde_json::json!({
+                    "value": voxel_size,
+                    "unit": voxel_unit,
+                    "value_um": voxel_size_um,
+                }),
+            );
+            if let Some(roi) = roi {
+                obj.insert(
+                    "roi".to_string(),
+                    serde_json::json!({ "box": roi, "voxel_count": roi.voxel_count() }),
+                );
+            }
+        }
+        let material = find_material(&app, &state, &material_name).await;
+        if let (Some(obj), Some(material)) = (result.as_object_mut(), material) {
+            obj.insert(
+                "material".to_string(),
+                serde_json::to_value(material).map_err(|e| e.to_string())?,
+            );
+        }
+
+        if succeeded {
+            let mut state = state.lock().unwrap();
+            let workspace = match &workspace_id {
+                Some(id) => state.require_workspace_mut(id)?,
+                None => {
+                    let name = std::path::Path::new(&file_path)
+                        .file_stem()
+                        .map(|stem| stem.to_string_lossy().into_owned());
+                    state.create_workspace(name, None)
+                }
+            };
+            workspace.file_path = Some(file_path);
+            workspace.modified = true;
+            if let Some(obj) = result.as_object_mut() {
+                obj.insert("workspace_id".to_string(), workspace.id.clone().into());
+            }
+        }
+        Ok((result, succeeded))
+    })
+    .await
+}
+
+// Run `analyze` unless an analysis made with `idempotency_key` already completed,
+// in which case its result is returned instead. `analyze` reports whether it
+// succeeded; only a success is kept for the key.
+async fn analyze_once<F, Fut>(
+    state: &Mutex<AppState>,
+    idempotency_key: Option<&str>,
+    analyze: F,
+) -> Result<serde_json::Value, String>
+where
+    F: FnOnce() -> Fut,
+    Fut: std::future::Future<Output = Result<(serde_json::Value, bool), String>>,
+{
+    let cached = cached_analysis(&mut state.lock().unwrap(), idempotency_key);
+    if let Some(cached) = cached {
+        return Ok(cached);
+    }
+    let (result, succeeded) = analyze().await?;
+    if let (true, Some(key)) = (succeeded, idempotency_key) {
+        state
+            .lock()
+            .unwrap()
+            .idempotency_cache
+            .insert(key.to_string(), result.clone());
+    }
+    Ok(result)
+}
+
+// The result of an earlier analysis made with `idempotency_key`, if it completed
+fn cached_analysis(
+    state: &mut AppState,
+    idempotency_key: Option<&str>,
+) -> Option<serde_json::Value> {
+    idempotency_key.and_then(|key| state.idempotency_cache.get(key))
+}
 
-    response.json().await.map_err(|e| e.to_string())
+// Send the request `build` makes, building and sending it again after a
+// connection failure until `max_attempts` attempts have been made
+async fn send_analysis(
+    build: impl Fn() -> reqwest::RequestBuilder,
+    max_attempts: u32,
+    retry_delay: std::time::Duration,
+) -> Result<reqwest::Response, String> {
+    let mut attempt = 1;
+    loop {
+        match build().send().await {
+            Ok(response) => return Ok(response),
+            Err(e) if e.is_connect() && attempt < max_attempts => {
+                attempt += 1;
+                tokio::time::sleep(retry_delay).await;
+            }
+            Err(e) => return Err(http_client::describe(e)),
+        }
+    }
 }
 
 // Generate TPMS scaffold via Julia API
+//
+// The parameters are recorded in the undo history of `workspace_id`, or of a new
+// workspace when none is given, once generation succeeds; the result carries its
+// `workspace_id`. Runs through the shared job queue like `analyze_scaffold`.
 #[tauri::command]
 pub async fn generate_tpms(
     params: TPMSParams,
+    workspace_id: Option<String>,
+    job_id: Option<String>,
     state: State<'_, Mutex<AppState>>,
 ) -> Result<serde_json::Value, String> {
-    let url = {
+    backend_info(&state)
+        .await?
+        .ensure_tpms(&params.surface_type, params.porosity)?;
+    let (url, job_queue, client) = {
         let state = state.lock().unwrap();
-        format!("{}/tpms/generate", state.settings.julia_server_url)
+        if let Some(workspace_id) = &workspace_id {
+            state.require_workspace(workspace_id)?;
+        }
+        (
+            format!("{}/tpms/generate", state.settings.julia_server_url),
+            state.job_queue.clone(),
+            state.http.client().clone(),
+        )
     };
 
-    let client = reqwest::Client::new();
+    let _job = job_queue.acquire(job_id.as_deref()).await?;
     let response = client
         .post(&url)
         .json(&params)
         .send()
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(http_client::describe)?;
 
-    response.json().await.map_err(|e| e.to_string())
+    let succeeded = response.status().is_success();
+    let mut result: serde_json::Value = http_client::json(response).await?;
+
+    if succeeded {
+        let changes = match serde_json::to_value(&params).map_err(|e| e.to_string())? {
+            serde_json::Value::Object(fields) => fields.into_iter().collect(),
+            _ => ParamSnapshot::new(),
+        };
+        let mut state = state.lock().unwrap();
+        let workspace_id = match workspace_id {
+            Some(id) => id,
+            None => state.create_workspace(None, None).id.clone(),
+        };
+        record_params(&mut state, &workspace_id, changes)?;
+        if let Some(obj) = result.as_object_mut() {
+            obj.insert("workspace_id".to_string(), workspace_id.into());
+        }
+    }
+    Ok(result)
 }
 
-// Get metrics for workspace
+// Generate one TPMS scaffold per combination of the `sweeps` values applied to `base`
+//
+// Sweeps list `values` or an inclusive `start`/`stop`/`step` range for one
+// parameter; at most `MAX_SWEEP_COMBINATIONS` variants are allowed. Variants go
+// through the shared job queue as jobs `{sweep_id}/{index}` (cancellable with
+// `cancel_job`) and each successful one gets its own workspace. A
+// `tpms-sweep-progress` event ({sweep_id, index, completed, total, ok}) is emitted
+// as each variant finishes; a failed variant does not stop the others.
 #[tauri::command]
-pub async fn get_metrics(
-    workspace_id: String,
+pub async fn generate_tpms_sweep(
+    app: AppHandle,
+    base: TPMSParams,
+    sweeps: Vec<ParamSweep>,
+    sweep_id: Option<String>,
     state: State<'_, Mutex<AppState>>,
-) -> Result<ScaffoldMetrics, String> {
-    let url = {
+) -> Result<Vec<SweepResult>, String> {
+    let base: ParamSnapshot = match serde_json::to_value(&base).map_err(|e| e.to_string())? {
+        serde_json::Value::Object(fields) => fields.into_iter().collect(),
+        _ => ParamSnapshot::new(),
+    };
+    let variants = param_sweep::expand(&base, &sweeps)?;
+    // Catch mistyped values (e.g. a string porosity) before anything is queued
+    let backend = backend_info(&state).await?;
+    for variant in &variants {
+        let value = serde_json::to_value(variant).map_err(|e| e.to_string())?;
+        let params = serde_json::from_value::<TPMSParams>(value)
+            .map_err(|e| format!("invalid sweep value: {}", e))?;
+        backend.ensure_tpms(&params.surface_type, params.porosity)?;
+    }
+
+    let (url, job_queue, client) = {
         let state = state.lock().unwrap();
-        format!("{}/workspace/{}/metrics", state.settings.julia_server_url, workspace_id)
+        (
+            format!("{}/tpms/generate", state.settings.julia_server_url),
+            state.job_queue.clone(),
+            state.http.client().clone(),
+        )
     };
+    let sweep_id = sweep_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let mut tasks = tokio::task::JoinSet::new();
+    for (index, variant) in variants.iter().cloned().enumerate() {
+        let (url, job_queue, client) = (url.clone(), job_queue.clone(), client.clone());
+        let job_id = format!("{}/{}", sweep_id, index);
+        tasks.spawn(async move {
+            let outcome = match job_queue.acquire(Some(&job_id)).await {
+                Ok(_job) => generate_variant(&client, &url, &variant).await,
+                Err(e) => Err(e),
+            };
+            (index, outcome)
+        });
+    }
+
+    let total = variants.len();
+    let mut outcomes = vec![None; total];
+    let mut completed = 0;
+    while let Some(joined) = tasks.join_next().await {
+        let (index, outcome) = joined.map_err(|e| e.to_string())?;
+        completed += 1;
+        let _ = app.emit_all(
+            "tpms-sweep-progress",
+            SweepProgress {
+                sweep_id: &sweep_id,
+                index,
+                completed,
+                total,
+                ok: outcome.is_ok(),
+            },
+        );
+        outcomes[index] = Some(outcome);
+    }
+
+    let mut state = state.lock().unwrap();
+    Ok(variants
+        .into_iter()
+        .zip(outcomes)
+        .map(|(params, outcome)| {
+            match outcome.unwrap_or_else(|| Err("variant did not run".into())) {
+                Ok(body) => {
+                    let workspace = state.create_workspace(None, None);
+                    workspace.history.record(params.clone());
+                    workspace.modified = true;
+                    let workspace_id = workspace.id.clone();
+                    SweepResult {
+                        params,
+                        workspace_id: Some(workspace_id),
+                        mesh_url: body["mesh_url"].as_str().map(str::to_string),
+                        error: None,
+                    }
+                }
+                Err(error) => SweepResult {
+                    params,
+                    workspace_id: None,
+                    mesh_url: None,
+                    error: Some(error),
+                },
+            }
+        })
+        .collect())
+}
 
-    let client = reqwest::Client::new();
+async fn generate_variant(
+    client: &reqwest::Client,
+    url: &str,
+    params: &ParamSnapshot,
+) -> Result<serde_json::Value, String> {
     let response = client
-        .get(&url)
+        .post(url)
+        .json(params)
         .send()
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(http_client::describe)?;
+    let status = response.status();
+    let body: serde_json::Value = http_client::json(response).await?;
+    match body["error"].as_str() {
+        Some(error) => Err(error.to_string()),
+        None if !status.is_success() => Err(format!("TPMS generation failed ({})", status)),
+        None => Ok(body),
+    }
+}
 
-    response.json().await.map_err(|e| e.to_string())
+fn record_params(
+    state: &mut AppState,
+    workspace_id: &str,
+    changes: ParamSnapshot,
+) -> Result<WorkspaceParams, String> {
+    let workspace = state.require_workspace_mut(workspace_id)?;
+    workspace.history.record(changes);
+    workspace.modified = true;
+    Ok(WorkspaceParams::of(workspace, true))
 }
 
-// Export to STL
+// Start a new, empty workspace; its id is a fresh UUID
 #[tauri::command]
-pub async fn export_stl(
+pub fn create_workspace(
+    name: Option<String>,
+    file_path: Option<String>,
+    state: State<'_, Mutex<AppState>>,
+) -> WorkspaceState {
+    let mut state = state.lock().unwrap();
+    state.create_workspace(name, file_path).clone()
+}
+
+// Change some of a workspace's parameters, recording an undo step
+#[tauri::command]
+pub fn set_workspace_params(
     workspace_id: String,
-    output_path: String,
-    quality: String,
+    params: ParamSnapshot,
     state: State<'_, Mutex<AppState>>,
-) -> Result<serde_json::Value, String> {
-    let url = {
+) -> Result<WorkspaceParams, String> {
+    let mut state = state.lock().unwrap();
+    record_params(&mut state, &workspace_id, params)
+}
+
+// Step a workspace's parameters back one change
+//
+// At the oldest change this is a no-op reported as `changed: false`.
+#[tauri::command]
+pub fn undo_workspace(
+    workspace_id: String,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<WorkspaceParams, String> {
+    let mut state = state.lock().unwrap();
+    let workspace = state.require_workspace_mut(&workspace_id)?;
+    let changed = workspace.history.undo();
+    Ok(WorkspaceParams::of(workspace, changed))
+}
+
+// Re-apply the last undone parameter change of a workspace
+//
+// With nothing to redo this is a no-op reported as `changed: false`.
+#[tauri::command]
+pub fn redo_workspace(
+    workspace_id: String,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<WorkspaceParams, String> {
+    let mut state = state.lock().unwrap();
+    let workspace = state.require_workspace_mut(&workspace_id)?;
+    let changed = workspace.history.redo();
+    Ok(WorkspaceParams::of(workspace, changed))
+}
+
+// Estimate TPMS output size and runtime without building the mesh
+#[tauri::command]
+pub async fn estimate_tpms(
+    params: TPMSParams,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<TPMSEstimate, String> {
+    let (url, client) = {
         let state = state.lock().unwrap();
-        format!("{}/export/stl", state.settings.julia_server_url)
+        (
+            format!("{}/tpms/estimate", state.settings.julia_server_url),
+            state.http.client().clone(),
+        )
     };
 
-    let client = reqwest::Client::new();
     let response = client
         .post(&url)
-        .json(&serde_json::json!({
-            "workspace_id": workspace_id,
-            "output_path": output_path,
-            "quality": quality
-        }))
+        .json(&params)
         .send()
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(http_client::describe)?;
 
-    response.json().await.map_err(|e| e.to_string())
+    http_client::json_or_error(response, "TPMS estimate").await
 }
 
-// Chat with AI agent
+// Fetch metrics for a workspace; `None` when Julia has none computed yet
+async fn fetch_metrics(
+    client: &reqwest::Client,
+    base_url: &str,
+    workspace_id: &str,
+) -> Result<Option<ScaffoldMetrics>, String> {
+    let url = format!("{}/workspace/{}/metrics", base_url, workspace_id);
+
+    let response = client
+        .get(&url)
+        .send()
+        .await
+        .map_err(http_client::describe)?;
+
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    http_client::json(response).await.map(Some)
+}
+
+// Get metrics for workspace, optionally appending them to its history
 #[tauri::command]
-pub async fn chat_with_agent(
-    message: ChatMessage,
+pub async fn get_metrics(
+    app: AppHandle,
+    workspace_id: String,
+    record: Option<bool>,
     state: State<'_, Mutex<AppState>>,
-) -> Result<serde_json::Value, String> {
-    let url = {
+) -> Result<ScaffoldMetrics, String> {
+    let (base_url, client) = {
+        let state = state.lock().unwrap();
+        state.require_workspace(&workspace_id)?;
+        (
+            state.settings.julia_server_url.clone(),
+            state.http.client().clone(),
+        )
+    };
+
+    let metrics = fetch_metrics(&client, &base_url, &workspace_id)
+        .await?
+        .ok_or_else(|| format!("no metrics computed for workspace {}", workspace_id))?;
+
+    if record.unwrap_or(false) {
+        let dir = history_dir(&app)?;
+        let history = state.lock().unwrap().metrics_history.clone();
+        history.record(&dir, &workspace_id, metrics.clone())?;
+    }
+    Ok(metrics)
+}
+
+fn history_dir(app: &AppHandle) -> Result<std::path::PathBuf, String> {
+    app.path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join("history"))
+        .ok_or_else(|| "could not resolve the app data directory".to_string())
+}
+
+// Append a metrics snapshot to a workspace's history
+#[tauri::command]
+pub fn record_metrics(
+    app: AppHandle,
+    workspace_id: String,
+    metrics: ScaffoldMetrics,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<HistoryEntry, String> {
+    let dir = history_dir(&app)?;
+    let history = state.lock().unwrap().metrics_history.clone();
+    history.record(&dir, &workspace_id, metrics)
+}
+
+// Get a page of a workspace's metrics history, newest first
+//
+// `limit` defaults to 50 (max 500); pass the returned `next_cursor` as `cursor`
+// to fetch the next page. `next_cursor` is null on the last page.
+#[tauri::command]
+pub fn get_metrics_history(
+    app: AppHandle,
+    workspace_id: String,
+    limit: Option<usize>,
+    cursor: Option<String>,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<Page<HistoryEntry>, String> {
+    let dir = history_dir(&app)?;
+    let history = state.lock().unwrap().metrics_history.clone();
+    let mut entries = history.entries(&dir, &workspace_id)?;
+    entries.reverse();
+    pagination::paginate(entries, limit, cursor.as_deref())
+}
+
+// Render (or reuse) a PNG thumbnail of a workspace's scaffold; returns its path
+//
+// Thumbnails are cached per workspace revision, so repeated calls only hit Julia
+// after an edit. `view` is "top", "iso" (default) or "front". A workspace without
+// a scaffold yet fails with `{"error": ..., "placeholder": true}` so the UI can
+// show a placeholder instead.
+#[tauri::command]
+pub async fn generate_thumbnail(
+    app: AppHandle,
+    workspace_id: String,
+    size_px: u32,
+    view: Option<String>,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<String, String> {
+    let view = thumbnails::ThumbnailView::parse(view.as_deref().unwrap_or("iso"))?;
+    let size_px = size_px.clamp(thumbnails::MIN_THUMBNAIL_PX, thumbnails::MAX_THUMBNAIL_PX);
+    let dir = app
+        .path_resolver()
+        .app_cache_dir()
+        .map(|dir| dir.join("thumbnails"))
+        .ok_or_else(|| "could not resolve the app cache directory".to_string())?;
+    let (base_url, client) = {
         let state = state.lock().unwrap();
-        format!("{}/agents/chat", state.settings.julia_server_url)
+        state.require_workspace(&workspace_id)?;
+        (
+            state.settings.julia_server_url.clone(),
+            state.http.client().clone(),
+        )
     };
 
-    let client = reqwest::Client::new();
     let response = client
-        .post(&url)
-        .json(&message)
+        .get(format!("{}/workspace/{}/revision", base_url, workspace_id))
         .send()
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(http_client::describe)?;
+    let revision: serde_json::Value = http_client::json(response).await?;
+    let Some(revision) = revision.get("revision").and_then(|r| r.as_str()) else {
+        return Err(serde_json::json!({
+            "error": format!("workspace {} has no scaffold to preview yet", workspace_id),
+            "placeholder": true,
+        })
+        .to_string());
+    };
+
+    let key = thumbnails::ThumbnailKey {
+        workspace_id: &workspace_id,
+        revision,
+        view,
+        size_px,
+    };
+    if let Some(path) = thumbnails::cached(&dir, &key)? {
+        return Ok(path.to_string_lossy().to_string());
+    }
+
+    let response = client
+        .get(format!("{}/workspace/{}/thumbnail", base_url, workspace_id))
+        .query(&[
+            ("size", size_px.to_string()),
+            ("view", view.as_str().to_string()),
+        ])
+        .send()
+        .await
+        .map_err(http_client::describe)?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Julia could not render the thumbnail ({})",
+            response.status()
+        ));
+    }
+    // Key by the revision actually rendered, in case of an edit in between
+    let rendered_revision = response
+        .headers()
+        .get("X-Workspace-Revision")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or(revision)
+        .to_string();
+    let png = http_client::read_body(response, http_client::MAX_RESPONSE_BYTES).await?;
 
-    response.json().await.map_err(|e| e.to_string())
+    let key = thumbnails::ThumbnailKey {
+        revision: &rendered_revision,
+        ..key
+    };
+    let path = thumbnails::store(&dir, &key, &png)?;
+    Ok(path.to_string_lossy().to_string())
 }
 
-// Get application settings
+// Export metrics of several workspaces to a CSV file; returns the rows written
+//
+// Workspaces without metrics (or whose fetch failed) get empty metric cells and
+// the reason in the `note` column.
 #[tauri::command]
-pub fn get_app_settings(state: State<'_, Mutex<AppState>>) -> AppSettings {
-    let state = state.lock().unwrap();
-    state.settings.clone()
+pub async fn export_metrics_csv(
+    workspace_ids: Vec<String>,
+    output_path: String,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<usize, String> {
+    let (base_url, client, names) = {
+        let state = state.lock().unwrap();
+        for workspace_id in &workspace_ids {
+            state.require_workspace(workspace_id)?;
+        }
+        let names: std::collections::HashMap<String, String> = state
+            .workspaces
+            .values()
+            .map(|ws| (ws.id.clone(), ws.name.clone()))
+            .collect();
+        (
+            state.settings.julia_server_url.clone(),
+            state.http.client().clone(),
+            names,
+        )
+    };
+
+    let mut rows = Vec::with_capacity(workspace_ids.len());
+    for workspace_id in workspace_ids {
+        let (metrics, note) = match fetch_metrics(&client, &base_url, &workspace_id).await {
+            Ok(Some(metrics)) => (Some(metrics), String::new()),
+            Ok(None) => (None, "no metrics computed".to_string()),
+            Err(e) => (None, format!("failed to fetch metrics: {}", e)),
+        };
+        rows.push(metrics_export::ExportRow {
+            name: names.get(&workspace_id).cloned().unwrap_or_default(),
+            workspace_id,
+            metrics,
+            note,
+        });
+    }
+
+    let file = std::fs::File::create(&output_path).map_err(|e| e.to_string())?;
+    metrics_export::write_metrics_csv(std::io::BufWriter::new(file), &rows)
 }
 
-// Set application settings
+// Porosity, surface-to-volume ratio and pore size of `bins` slabs along `axis` ("x", "y" or "z")
+//
+// `bins` must be between 1 and 1024. Voxels are taken to be the default voxel
+// size from settings.
 #[tauri::command]
-pub fn set_app_settings(
-    settings: AppSettings,
+pub async fn get_depth_profile(
+    workspace_id: String,
+    axis: String,
+    bins: u32,
     state: State<'_, Mutex<AppState>>,
-) -> Result<(), String> {
-    let mut state = state.lock().unwrap();
-    state.settings = settings;
-    Ok(())
+) -> Result<DepthProfile, String> {
+    let axis = depth_profile::validate(&axis, bins)?;
+    let (base_url, client, voxel_size, voxel_unit) = {
+        let state = state.lock().unwrap();
+        state.require_workspace(&workspace_id)?;
+        (
+            state.settings.julia_server_url.clone(),
+            state.http.client().clone(),
+            state.settings.default_voxel_size,
+            state.settings.voxel_unit.clone(),
+        )
+    };
+    let voxel_unit = units::normalize_unit(&voxel_unit)?;
+    let voxel_size_um = units::convert_length(voxel_size, voxel_unit, units::CANONICAL_VOXEL_UNIT)?;
+
+    let response = client
+        .get(format!(
+            "{}/workspace/{}/depth_profile",
+            base_url, workspace_id
+        ))
+        .query(&[
+            ("axis", axis.as_str().to_string()),
+            ("bins", bins.to_string()),
+            ("voxel_size", voxel_size_um.to_string()),
+        ])
+        .send()
+        .await
+        .map_err(http_client::describe)?;
+    let status = response.status();
+    let body: serde_json::Value = http_client::json(response).await?;
+    match body["error"].as_str() {
+        Some(error) => Err(error.to_string()),
+        None if !status.is_success() => Err(format!("depth profile failed ({})", status)),
+        None => serde_json::from_value(body).map_err(|e| e.to_string()),
+    }
+}
+
+// Degree of anisotropy, principal pore direction and fabric tensor of a workspace
+//
+// Proxied from Julia's mean-intercept-length analysis. Fails if the workspace
+// has no geometry yet (load or generate a scaffold first).
+#[tauri::command]
+pub async fn get_anisotropy(
+    workspace_id: String,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<AnisotropyReport, String> {
+    let (base_url, client) = {
+        let state = state.lock().unwrap();
+        state.require_workspace(&workspace_id)?;
+        (
+            state.settings.julia_server_url.clone(),
+            state.http.client().clone(),
+        )
+    };
+
+    let response = client
+        .get(format!(
+            "{}/workspace/{}/anisotropy",
+            base_url, workspace_id
+        ))
+        .send()
+        .await
+        .map_err(http_client::describe)?;
+    let status = response.status();
+    let body = if status == reqwest::StatusCode::NOT_FOUND {
+        // Julia's own 404 is JSON; a router's may be anything
+        http_client::json(response)
+            .await
+            .unwrap_or(serde_json::Value::Null)
+    } else {
+        http_client::json(response).await?
+    };
+    anisotropy::from_reply(&workspace_id, status, body)
+}
+
+// Write a report of a workspace's metrics, material, preview and metadata; returns its path
+//
+// `format` is "html" or "pdf". Metrics are checked against bone scaffold target
+// ranges, which `targets` overrides per metric, e.g. {"porosity": {"min": 0.6}}.
+// `material` defaults to the one in settings. Whatever is not available yet
+// (metrics, material, preview) is marked missing in the report instead of failing.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn generate_report(
+    app: AppHandle,
+    workspace_id: String,
+    format: String,
+    output_path: String,
+    material: Option<String>,
+    targets: Option<std::collections::BTreeMap<String, TargetRange>>,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<String, String> {
+    let format = ReportFormat::parse(&format)?;
+    let targets = report::targets_with(targets)?;
+    let (workspace_name, source_file, material_name, base_url, client) = {
+        let state = state.lock().unwrap();
+        let workspace = state.require_workspace(&workspace_id)?;
+        (
+            workspace.name.clone(),
+            workspace.file_path.clone(),
+            material.unwrap_or_else(|| state.settings.default_material.clone()),
+            state.settings.julia_server_url.clone(),
+            state.http.client().clone(),
+        )
+    };
+
+    let metrics = match fetch_metrics(&client, &base_url, &workspace_id).await {
+        Ok(Some(metrics)) => Section::Present(report::metric_rows(&metrics, &targets)),
+        Ok(None) => Section::Missing("no metrics computed yet".to_string()),
+        Err(e) => Section::Missing(format!("could not fetch metrics: {}", e)),
+    };
+    let material = match find_material(&app, &state, &material_name).await {
+        Some(material) => Section::Present(material),
+        None => Section::Missing(format!("material {} is not in the database", material_name)),
+    };
+    let thumbnail = match generate_thumbnail(
+        app.clone(),
+        workspace_id.clone(),
+        report::THUMBNAIL_PX,
+        None,
+        state,
+    )
+    .await
+    {
+        Ok(path) => match std::fs::read(&path) {
+            Ok(png) => Section::Present(png),
+            Err(e) => Section::Missing(format!("could not read the preview: {}", e)),
+        },
+        // Placeholder errors are JSON carrying the reason under "error"
+        Err(e) => Section::Missing(
+            serde_json::from_str::<serde_json::Value>(&e)
+                .ok()
+                .and_then(|v| v.get("error").and_then(|e| e.as_str()).map(str::to_string))
+                .unwrap_or(e),
+        ),
+    };
+
+    let generated = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let report = report::Report {
+        workspace_id,
+        workspace_name,
+        source_file,
+        generated_utc: report::format_utc(generated),
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        metrics,
+        material,
+        thumbnail,
+    };
+    let bytes = match format {
+        ReportFormat::Html => report::render_html(&report)?.into_bytes(),
+        ReportFormat::Pdf => report::render_pdf(&report)?,
+    };
+    std::fs::write(&output_path, bytes).map_err(|e| e.to_string())?;
+    Ok(output_path)
+}
+
+// Compare metrics of two workspaces (e.g. designed vs scanned scaffold)
+#[tauri::command]
+pub async fn compare_metrics(
+    workspace_a: String,
+    workspace_b: String,
+    threshold_pct: Option<f64>,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<MetricsComparison, String> {
+    let (base_url, client) = {
+        let state = state.lock().unwrap();
+        state.require_workspace(&workspace_a)?;
+        state.require_workspace(&workspace_b)?;
+        (
+            state.settings.julia_server_url.clone(),
+            state.http.client().clone(),
+        )
+    };
+
+    let (a, b) = tokio::try_join!(
+        fetch_metrics(&client, &base_url, &workspace_a),
+        fetch_metrics(&client, &base_url, &workspace_b)
+    )?;
+    let a =
+        a.ok_or_else(|| format!("workspace A ({}) has no computed metrics yet", workspace_a))?;
+    let b =
+        b.ok_or_else(|| format!("workspace B ({}) has no computed metrics yet", workspace_b))?;
+
+    Ok(comparison::compare(
+        &workspace_a,
+        &a,
+        &workspace_b,
+        &b,
+        threshold_pct.unwrap_or(comparison::DEFAULT_THRESHOLD_PCT),
+    ))
+}
+
+// Fetch a mesh topology report from Julia
+async fn fetch_mesh_report(
+    client: &reqwest::Client,
+    base_url: &str,
+    workspace_id: &str,
+) -> Result<MeshReport, String> {
+    let response = client
+        .post(format!("{}/mesh/validate", base_url))
+        .json(&serde_json::json!({ "workspace_id": workspace_id }))
+        .send()
+        .await
+        .map_err(http_client::describe)?;
+
+    http_client::json_or_error(response, "mesh validation").await
+}
+
+// Validate mesh before export (manifold, watertight, degenerate faces)
+#[tauri::command]
+pub async fn validate_mesh(
+    workspace_id: String,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<MeshReport, String> {
+    let (base_url, client) = {
+        let state = state.lock().unwrap();
+        state.require_workspace(&workspace_id)?;
+        (
+            state.settings.julia_server_url.clone(),
+            state.http.client().clone(),
+        )
+    };
+
+    fetch_mesh_report(&client, &base_url, &workspace_id).await
+}
+
+// Check whether a workspace's scaffold can be printed on `printer_profile`
+//
+// Combines Julia's mesh report (watertight, manifold) and printability geometry
+// (thinnest feature, steepest overhang) with the printer's build volume and
+// nozzle. Each check is pass, warn or fail with a message; `verdict` is the worst.
+#[tauri::command]
+pub async fn check_printability(
+    app: AppHandle,
+    workspace_id: String,
+    printer_profile: String,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<PrintabilityReport, String> {
+    let profile = printer_profiles::find_printer_profile(&app, &printer_profile)?;
+    let (base_url, client) = {
+        let state = state.lock().unwrap();
+        state.require_workspace(&workspace_id)?;
+        (
+            state.settings.julia_server_url.clone(),
+            state.http.client().clone(),
+        )
+    };
+
+    let geometry = async {
+        let response = client
+            .post(format!("{}/mesh/printability", base_url))
+            .json(&serde_json::json!({ "workspace_id": workspace_id }))
+            .send()
+            .await
+            .map_err(http_client::describe)?;
+        http_client::json_or_error::<PrintGeometry>(response, "printability analysis").await
+    };
+    let (mesh, geometry) = tokio::try_join!(
+        fetch_mesh_report(&client, &base_url, &workspace_id),
+        geometry
+    )?;
+    Ok(printability::evaluate(
+        &workspace_id,
+        &mesh,
+        &geometry,
+        &profile,
+    ))
+}
+
+// Estimate mass, filament use, print time and cost of a workspace's scaffold
+//
+// The solid volume comes from Julia; `material` is looked up in the materials
+// database and `printer_profile` among the bundled and user printer profiles.
+#[tauri::command]
+pub async fn estimate_print(
+    app: AppHandle,
+    workspace_id: String,
+    material: String,
+    infill: f64,
+    printer_profile: String,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<PrintEstimate, String> {
+    let material = find_material(&app, &state, &material)
+        .await
+        .ok_or_else(|| format!("unknown material '{}'", material))?;
+    let profile = printer_profiles::find_printer_profile(&app, &printer_profile)?;
+    let (base_url, client) = {
+        let state = state.lock().unwrap();
+        state.require_workspace(&workspace_id)?;
+        (
+            state.settings.julia_server_url.clone(),
+            state.http.client().clone(),
+        )
+    };
+
+    let response = client
+        .post(format!("{}/mesh/volume", base_url))
+        .json(&serde_json::json!({ "workspace_id": workspace_id }))
+        .send()
+        .await
+        .map_err(http_client::describe)?;
+    let body: serde_json::Value = http_client::json_or_error(response, "mesh volume").await?;
+    let volume_mm3 = body["volume_mm3"]
+        .as_f64()
+        .ok_or("Julia returned no mesh volume")?;
+
+    print_estimate::estimate(volume_mm3, &material, infill, &profile)
+}
+
+const STAGE_PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+// Decimate a workspace's mesh, removing about `target_reduction` of its triangles
+//
+// The simplified mesh is kept by Julia as a new mesh revision next to the
+// original; pass `mesh_revision` to `export_stl` to export it. While Julia works,
+// `mesh-simplify-progress` events ({workspace_id, stage, elapsed_ms}) are emitted
+// every second, then once more with stage "done". Runs through the shared job
+// queue like `analyze_scaffold`.
+#[tauri::command]
+pub async fn simplify_mesh(
+    app: AppHandle,
+    workspace_id: String,
+    target_reduction: f64,
+    preserve_boundaries: bool,
+    job_id: Option<String>,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<MeshSimplification, String> {
+    if !(target_reduction > 0.0 && target_reduction < 1.0) {
+        return Err(format!(
+            "target_reduction must be between 0 and 1 (exclusive), got {}",
+            target_reduction
+        ));
+    }
+    let (base_url, job_queue, client) = {
+        let state = state.lock().unwrap();
+        state.require_workspace(&workspace_id)?;
+        (
+            state.settings.julia_server_url.clone(),
+            state.job_queue.clone(),
+            state.http.client().clone(),
+        )
+    };
+
+    let _job = job_queue.acquire(job_id.as_deref()).await?;
+    let request = client
+        .post(format!("{}/mesh/simplify", base_url))
+        .json(&serde_json::json!({
+            "workspace_id": workspace_id,
+            "target_reduction": target_reduction,
+            "preserve_boundaries": preserve_boundaries,
+        }))
+        .send();
+    tokio::pin!(request);
+
+    let started = std::time::Instant::now();
+    let progress = |stage: &str| {
+        let _ = app.emit_all(
+            "mesh-simplify-progress",
+            StageProgress {
+                workspace_id: &workspace_id,
+                stage,
+                elapsed_ms: started.elapsed().as_millis() as u64,
+            },
+        );
+    };
+    let mut ticker = tokio::time::interval(STAGE_PROGRESS_INTERVAL);
+    ticker.tick().await;
+    let response = loop {
+        tokio::select! {
+            response = &mut request => break response.map_err(http_client::describe)?,
+            _ = ticker.tick() => progress("simplifying"),
+        }
+    };
+    progress("done");
+
+    http_client::json_or_error(response, "mesh simplification").await
+}
+
+// Homogenized 6x6 stiffness matrix, anisotropic moduli and Poisson ratios of a workspace
+//
+// `material` must be in the materials database; its Young's modulus is the solid
+// modulus Julia homogenizes and is returned as `base_modulus_mpa`. Runs through
+// the job queue; `stiffness-progress` events ({workspace_id, stage, elapsed_ms})
+// are emitted every second, then once more with stage "done" or "cancelled".
+// With a `job_id`, `cancel_job` stops it while queued or running.
+#[tauri::command]
+pub async fn compute_stiffness_tensor(
+    app: AppHandle,
+    workspace_id: String,
+    material: String,
+    job_id: Option<String>,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<StiffnessReport, String> {
+    let (base_url, job_queue, client) = {
+        let state = state.lock().unwrap();
+        state.require_workspace(&workspace_id)?;
+        (
+            state.settings.julia_server_url.clone(),
+            state.job_queue.clone(),
+            state.http.client().clone(),
+        )
+    };
+    let material = find_material(&app, &state, &material)
+        .await
+        .ok_or_else(|| format!("unknown material: {}", material))?;
+
+    let _job = job_queue.acquire(job_id.as_deref()).await?;
+    let running = job_id
+        .as_deref()
+        .map(|job_id| job_queue.running(job_id))
+        .transpose()?;
+    let cancelled = async {
+        match &running {
+            Some(running) => running.cancelled().await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(cancelled);
+    let request = client
+        .post(format!("{}/mechanics/homogenize", base_url))
+        .json(&serde_json::json!({
+            "workspace_id": workspace_id,
+            "job_id": job_id,
+            "base_modulus_mpa": material.youngs_modulus_mpa,
+        }))
+        .send();
+    tokio::pin!(request);
+
+    let started = std::time::Instant::now();
+    let progress = |stage: &str| {
+        let _ = app.emit_all(
+            "stiffness-progress",
+            StageProgress {
+                workspace_id: &workspace_id,
+                stage,
+                elapsed_ms: started.elapsed().as_millis() as u64,
+            },
+        );
+    };
+    let mut ticker = tokio::time::interval(STAGE_PROGRESS_INTERVAL);
+    ticker.tick().await;
+    let response = loop {
+        tokio::select! {
+            response = &mut request => break response.map_err(http_client::describe)?,
+            _ = &mut cancelled => {
+                progress("cancelled");
+                // Dropping the request closes the connection, which is all Julia can be told
+                return Err(format!(
+                    "job {} was cancelled",
+                    job_id.as_deref().unwrap_or_default()
+                ));
+            }
+            _ = ticker.tick() => progress("homogenizing"),
+        }
+    };
+    progress("done");
+
+    let status = response.status();
+    let body = if status == reqwest::StatusCode::NOT_FOUND {
+        serde_json::Value::Null
+    } else {
+        http_client::json(response).await?
+    };
+    stiffness::from_reply(
+        &workspace_id,
+        &material.name,
+        material.youngs_modulus_mpa,
+        status,
+        body,
+    )
+}
+
+// Export to STL
+//
+// When `options.validate` is set the mesh is checked first and the export is
+// refused if it is not watertight; the error then carries the full `MeshReport`
+// as JSON. `options.mesh_revision` exports a mesh produced by `simplify_mesh`
+// instead of the original. With `options.printer_profile`, a mesh larger than that
+// printer's build volume is still exported, but the result gets a `warnings` list
+// saying so.
+// Julia streams the file to `output_path`, emitting `export-progress` events
+// ({workspace_id, percent, bytes_written}); the last one adds `output_path` and
+// `size_bytes`. A failure emits `export-failed` and removes the partial file.
+#[tauri::command]
+pub async fn export_stl(
+    app: AppHandle,
+    workspace_id: String,
+    output_path: String,
+    options: ExportStlOptions,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<serde_json::Value, String> {
+    backend_info(&state).await?.ensure_export_format("stl")?;
+    let ExportStlOptions {
+        quality,
+        validate,
+        mesh_revision,
+        printer_profile,
+    } = options;
+    let printer = printer_profile
+        .map(|name| printer_profiles::find_printer_profile(&app, &name))
+        .transpose()?;
+    let (base_url, client) = {
+        let state = state.lock().unwrap();
+        state.require_workspace(&workspace_id)?;
+        (
+            state.settings.julia_server_url.clone(),
+            state.http.client().clone(),
+        )
+    };
+
+    let report = if validate || printer.is_some() {
+        Some(fetch_mesh_report(&client, &base_url, &workspace_id).await?)
+    } else {
+        None
+    };
+    if let Some(report) = report.as_ref().filter(|_| validate) {
+        if !report.watertight {
+            return Err(serde_json::json!({
+                "error": "mesh is not watertight, refusing to export",
+                "report": report,
+            })
+            .to_string());
+        }
+    }
+    let warnings: Vec<String> = match (&printer, &report) {
+        (Some(printer), Some(report)) => printer
+            .build_volume_warning(report.bounding_box_mm)
+            .into_iter()
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let failed = |error: String| {
+        let _ = app.emit_all(
+            "export-failed",
+            ExportFailed {
+                workspace_id: &workspace_id,
+                output_path: &output_path,
+                error: &error,
+            },
+        );
+        error
+    };
+    let url = format!("{}/export/stl", base_url);
+    let response = client
+        .post(&url)
+        .json(&serde_json::json!({
+            "workspace_id": workspace_id,
+            "output_path": output_path,
+            "quality": quality,
+            "mesh_revision": mesh_revision,
+            "stream": true
+        }))
+        .send()
+        .await
+        .map_err(|e| failed(http_client::describe(e)))?;
+
+    let streamed = response.status().is_success()
+        && !response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|ct| ct.contains("json"));
+    let mut result = if streamed {
+        let progress = |percent, bytes_written| {
+            let _ = app.emit_all(
+                "export-progress",
+                ExportProgress {
+                    workspace_id: &workspace_id,
+                    percent,
+                    bytes_written,
+                    output_path: None,
+                    size_bytes: None,
+                },
+            );
+        };
+        let size_bytes =
+            export_stream::write_streamed(response, std::path::Path::new(&output_path), progress)
+                .await
+                .map_err(failed)?;
+        let _ = app.emit_all(
+            "export-progress",
+            ExportProgress {
+                workspace_id: &workspace_id,
+                percent: Some(100),
+                bytes_written: size_bytes,
+                output_path: Some(&output_path),
+                size_bytes: Some(size_bytes),
+            },
+        );
+        serde_json::json!({"file_path": output_path, "size_bytes": size_bytes})
+    } else {
+        // Backends that cannot stream write the file themselves and answer with JSON
+        let status = response.status();
+        let body: serde_json::Value = http_client::json(response).await.map_err(failed)?;
+        if let Some(error) = body["error"].as_str() {
+            return Err(failed(error.to_string()));
+        }
+        if !status.is_success() {
+            return Err(failed(format!("STL export failed ({})", status)));
+        }
+        body
+    };
+    if let (false, Some(object)) = (warnings.is_empty(), result.as_object_mut()) {
+        object.insert("warnings".to_string(), serde_json::json!(warnings));
+    }
+    Ok(result)
+}
+
+// Export a mesh the frontend already holds to STL without going through Julia
+//
+// Fallback for when Julia's exporter is unavailable. `binary` picks binary
+// or ASCII STL. Returns {file_path, size_bytes} like `export_stl`.
+#[tauri::command]
+pub async fn export_stl_local(
+    mesh: Mesh,
+    output_path: String,
+    binary: bool,
+) -> Result<serde_json::Value, String> {
+    let path = std::path::PathBuf::from(&output_path);
+    let size_bytes =
+        tokio::task::spawn_blocking(move || stl_writer::write_file(&mesh, &path, binary))
+            .await
+            .map_err(|e| e.to_string())??;
+    Ok(serde_json::json!({"file_path": output_path, "size_bytes": size_bytes}))
+}
+
+// Compare two STL files geometrically (symmetric Hausdorff distance, computed by Julia)
+//
+// `within_tolerance` is true when the largest deviation is at most `tolerance_mm`.
+#[tauri::command]
+pub async fn compare_meshes(
+    path_a: String,
+    path_b: String,
+    tolerance_mm: f64,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<MeshDiff, String> {
+    if !(tolerance_mm.is_finite() && tolerance_mm >= 0.0) {
+        return Err(format!(
+            "tolerance_mm must be a non-negative number, got {}",
+            tolerance_mm
+        ));
+    }
+    mesh_diff::validate_stl(std::path::Path::new(&path_a))?;
+    mesh_diff::validate_stl(std::path::Path::new(&path_b))?;
+
+    let (url, client) = {
+        let state = state.lock().unwrap();
+        (
+            format!("{}/mesh/compare", state.settings.julia_server_url),
+            state.http.client().clone(),
+        )
+    };
+
+    let response = client
+        .post(&url)
+        .json(&serde_json::json!({ "path_a": path_a, "path_b": path_b }))
+        .send()
+        .await
+        .map_err(http_client::describe)?;
+    let status = response.status().as_u16();
+    let body = http_client::read_body(response, http_client::MAX_RESPONSE_BYTES).await?;
+    let body = String::from_utf8_lossy(&body);
+    mesh_diff::parse_response(status, &body, tolerance_mm)
+}
+
+// Chat with AI agent
+//
+// A `context` naming a workspace this session doesn't track, or attached files
+// that don't exist, is refused before anything reaches the agent.
+#[tauri::command]
+pub async fn chat_with_agent(
+    message: ChatMessage,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<serde_json::Value, String> {
+    let (url, client) = {
+        let state = state.lock().unwrap();
+        if let Some(context) = &message.context {
+            context.validate(&state)?;
+        }
+        (
+            format!("{}/agents/chat", state.settings.julia_server_url),
+            state.http.client().clone(),
+        )
+    };
+
+    let response = client
+        .post(&url)
+        .json(&message)
+        .send()
+        .await
+        .map_err(http_client::describe)?;
+
+    let reply: serde_json::Value = http_client::json(response).await?;
+    let reply_text = reply["response"]
+        .as_str()
+        .map(str::to_string)
+        .unwrap_or_else(|| reply.to_string());
+    let mut state = state.lock().unwrap();
+    state.record_chat(ChatEntry {
+        role: "user".to_string(),
+        agent: message.agent.clone(),
+        content: message.message,
+    });
+    state.record_chat(ChatEntry {
+        role: "agent".to_string(),
+        agent: message.agent,
+        content: reply_text,
+    });
+    Ok(reply)
+}
+
+fn library_path(app: &AppHandle) -> Result<std::path::PathBuf, String> {
+    app.path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join(library::LIBRARY_FILE))
+        .ok_or_else(|| "could not resolve the app data directory".to_string())
+}
+
+// Add a scaffold to the library, or replace its entry
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub fn add_to_library(
+    app: AppHandle,
+    file_id: String,
+    name: String,
+    tags: Option<Vec<String>>,
+    material: Option<String>,
+    tissue: Option<String>,
+    metrics_summary: Option<ScaffoldMetrics>,
+    file_path: Option<String>,
+    voxel_size_um: Option<f64>,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<LibraryEntry, String> {
+    let path = library_path(&app)?;
+    let created = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let entry = LibraryEntry {
+        name,
+        tags: tags.unwrap_or_default(),
+        material,
+        tissue,
+        created,
+        metrics_summary,
+        file_path,
+        voxel_size_um,
+    };
+    let mut state = state.lock().unwrap();
+    state.library.add(&path, &file_id, entry)
+}
+
+// Replace the tags of a scaffold in the library
+#[tauri::command]
+pub fn tag_scaffold(
+    app: AppHandle,
+    file_id: String,
+    tags: Vec<String>,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<LibraryEntry, String> {
+    let path = library_path(&app)?;
+    let mut state = state.lock().unwrap();
+    state.library.tag(&path, &file_id, &tags)
+}
+
+// Search the library by tag, name, material, tissue and porosity range, best matches first
+#[tauri::command]
+pub fn search_scaffolds(
+    app: AppHandle,
+    query: ScaffoldQuery,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<Vec<LibraryHit>, String> {
+    let path = library_path(&app)?;
+    let mut state = state.lock().unwrap();
+    state.library.search(&path, &query)
+}
+
+// Remove a scaffold from the library; false if it was not there
+#[tauri::command]
+pub fn remove_from_library(
+    app: AppHandle,
+    file_id: String,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<bool, String> {
+    let path = library_path(&app)?;
+    let mut state = state.lock().unwrap();
+    state.library.remove(&path, &file_id)
+}
+
+// Stitch library scaffolds into one composite, e.g. layers of different porosity
+//
+// Each input is a library `file_id` with a `[tx, ty, tz, rx, ry, rz]` transform
+// (mm, then degrees about x, y, z). Inputs must have a file on disk, share a
+// voxel size and touch once placed. Julia writes the composite; it is added to
+// the library and opened in a new workspace, and its file id is returned. If
+// inputs overlap in ways Julia cannot reconcile, the error is its conflict report
+// as JSON ({error, conflicts}).
+#[tauri::command]
+pub async fn merge_scaffolds(
+    app: AppHandle,
+    inputs: Vec<MergeInput>,
+    output_name: String,
+    job_id: Option<String>,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<String, String> {
+    merge::validate_inputs(&inputs, &output_name)?;
+    let path = library_path(&app)?;
+    let (entries, base_url, job_queue, client) = {
+        let mut state = state.lock().unwrap();
+        let entries = inputs
+            .iter()
+            .map(|input| {
+                state
+                    .library
+                    .get(&path, &input.file_id)?
+                    .ok_or_else(|| format!("scaffold {} is not in the library", input.file_id))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        (
+            entries,
+            state.settings.julia_server_url.clone(),
+            state.job_queue.clone(),
+            state.http.client().clone(),
+        )
+    };
+
+    let mut parts = Vec::with_capacity(inputs.len());
+    for (input, entry) in inputs.iter().zip(&entries) {
+        let file_path = entry
+            .file_path
+            .clone()
+            .ok_or_else(|| format!("scaffold {} has no file_path in the library", input.file_id))?;
+        let header_path = std::path::PathBuf::from(&file_path);
+        let info = tokio::task::spawn_blocking(move || scaffold_info::read_info(&header_path))
+            .await
+            .map_err(|e| e.to_string())??;
+        parts.push(MergePart::new(input, file_path, &info)?);
+    }
+    merge::check_compatible(&parts)?;
+
+    let _job = job_queue.acquire(job_id.as_deref()).await?;
+    let response = client
+        .post(format!("{}/scaffold/merge", base_url))
+        .json(&serde_json::json!({"output_name": output_name, "parts": parts}))
+        .send()
+        .await
+        .map_err(http_client::describe)?;
+    let status = response.status().as_u16();
+    let body: serde_json::Value = http_client::json(response).await?;
+    let merged = merge::from_reply(status, body)?;
+
+    let file_id = uuid::Uuid::new_v4().to_string();
+    let entry = LibraryEntry {
+        name: output_name.clone(),
+        tags: vec!["merged".to_string()],
+        material: entries.iter().find_map(|e| e.material.clone()),
+        tissue: entries.iter().find_map(|e| e.tissue.clone()),
+        created: scheduled_jobs::now_millis(),
+        metrics_summary: None,
+        file_path: Some(merged.file_path.clone()),
+        voxel_size_um: Some(parts[0].voxel_size_mm[0] * 1000.0),
+    };
+    let mut state = state.lock().unwrap();
+    state.library.add(&path, &file_id, entry)?;
+    state.create_workspace(Some(output_name), Some(merged.file_path));
+    Ok(file_id)
+}
+
+fn scheduled_jobs_path(app: &AppHandle) -> Result<std::path::PathBuf, String> {
+    app.path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join(scheduled_jobs::SCHEDULED_JOBS_FILE))
+        .ok_or_else(|| "could not resolve the app data directory".to_string())
+}
+
+fn job_info(job: scheduled_jobs::ScheduledJob) -> ScheduledJobInfo {
+    let next_run = scheduled_jobs::parse_cron(&job.cron_expr)
+        .ok()
+        .and_then(|schedule| scheduled_jobs::next_run(&schedule, &chrono::Local::now()));
+    ScheduledJobInfo { job, next_run }
+}
+
+// Re-analyze the library scaffolds matching `scaffold_filter` every time `cron_expr` fires
+//
+// `cron_expr` is in local time: `min hour day month weekday` ("30 2 * * *" is
+// nightly at 02:30), the same with leading seconds, or a shortcut like "@daily".
+// Each trigger re-analyzes the matching scaffolds that have a `file_path`, a few
+// at a time through the job queue, and appends the new metrics to the history of
+// their `file_id`. Jobs are persisted and resume when the app starts.
+#[tauri::command]
+pub fn schedule_reprocess(
+    app: AppHandle,
+    cron_expr: String,
+    scaffold_filter: Option<ScaffoldQuery>,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<ScheduledJobInfo, String> {
+    let path = scheduled_jobs_path(&app)?;
+    let job = {
+        let mut state = state.lock().unwrap();
+        state
+            .scheduled_jobs
+            .add(&path, &cron_expr, scaffold_filter.unwrap_or_default())?
+    };
+    spawn_scheduled_job(app, job.id.clone());
+    Ok(job_info(job))
+}
+
+// Scheduled reprocessing jobs with their last outcome and next trigger, oldest first
+#[tauri::command]
+pub fn list_scheduled_jobs(
+    app: AppHandle,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<Vec<ScheduledJobInfo>, String> {
+    let path = scheduled_jobs_path(&app)?;
+    let jobs = state.lock().unwrap().scheduled_jobs.list(&path)?;
+    Ok(jobs.into_iter().map(job_info).collect())
+}
+
+// Delete a scheduled job; false if there was none. A trigger already running finishes.
+#[tauri::command]
+pub fn cancel_scheduled_job(
+    app: AppHandle,
+    id: String,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<bool, String> {
+    let path = scheduled_jobs_path(&app)?;
+    let mut state = state.lock().unwrap();
+    state.scheduled_jobs.remove(&path, &id)
+}
+
+// Start the trigger loops of the persisted scheduled jobs
+pub fn resume_scheduled_jobs(app: &AppHandle) -> Result<(), String> {
+    let path = scheduled_jobs_path(app)?;
+    let jobs = {
+        let state = app.state::<Mutex<AppState>>();
+        let mut state = state.lock().unwrap();
+        state.scheduled_jobs.list(&path)?
+    };
+    for job in jobs {
+        spawn_scheduled_job(app.clone(), job.id);
+    }
+    Ok(())
+}
+
+/// Longest single sleep of a trigger loop, so clock changes and suspends are noticed
+const SCHEDULE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+// Run the triggers of `job_id` until it is cancelled
+fn spawn_scheduled_job(app: AppHandle, job_id: String) {
+    let cancelled = {
+        let state = app.state::<Mutex<AppState>>();
+        let mut state = state.lock().unwrap();
+        match state.scheduled_jobs.start(&job_id) {
+            Some(cancelled) => cancelled,
+            None => return,
+        }
+    };
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = run_scheduled_job(&app, &job_id, &cancelled).await {
+            eprintln!("scheduled job {} stopped: {}", job_id, e);
+        }
+        let state = app.state::<Mutex<AppState>>();
+        state.lock().unwrap().scheduled_jobs.stopped(&job_id);
+    });
+}
+
+async fn run_scheduled_job(
+    app: &AppHandle,
+    job_id: &str,
+    cancelled: &tokio::sync::Notify,
+) -> Result<(), String> {
+    let path = scheduled_jobs_path(app)?;
+    loop {
+        let job = {
+            let state = app.state::<Mutex<AppState>>();
+            let mut state = state.lock().unwrap();
+            state.scheduled_jobs.get(&path, job_id)?
+        };
+        let Some(job) = job else {
+            return Ok(());
+        };
+        let schedule = scheduled_jobs::parse_cron(&job.cron_expr)?;
+        let Some(next) = scheduled_jobs::next_run(&schedule, &chrono::Local::now()) else {
+            return Ok(());
+        };
+        loop {
+            let remaining = next.saturating_sub(scheduled_jobs::now_millis());
+            if remaining == 0 {
+                break;
+            }
+            let wait = std::time::Duration::from_millis(remaining).min(SCHEDULE_POLL_INTERVAL);
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = cancelled.notified() => return Ok(()),
+            }
+        }
+
+        let summary = reprocess_library(app, &job.filter).await?;
+        {
+            let state = app.state::<Mutex<AppState>>();
+            let mut state = state.lock().unwrap();
+            state.scheduled_jobs.record_run(&path, job_id, summary)?;
+        }
+    }
+}
+
+// Re-analyze the library scaffolds matching `filter`
+async fn reprocess_library(
+    app: &AppHandle,
+    filter: &ScaffoldQuery,
+) -> Result<ReprocessSummary, String> {
+    let started = scheduled_jobs::now_millis();
+    let library_path = library_path(app)?;
+    let hits = {
+        let state = app.state::<Mutex<AppState>>();
+        let mut state = state.lock().unwrap();
+        state.library.search(&library_path, filter)?
+    };
+    let (targets, skipped) = scheduled_jobs::select_targets(hits);
+    let mut summary = ReprocessSummary::new(started, skipped);
+    let results =
+        scheduled_jobs::reprocess(targets, scheduled_jobs::REPROCESS_CONCURRENCY, |target| {
+            reanalyze(app.clone(), target)
+        })
+        .await;
+    for (file_id, result) in results {
+        summary.add(file_id, &result);
+    }
+    Ok(summary)
+}
+
+// Analyze a library scaffold again and append the metrics to its history
+async fn reanalyze(app: AppHandle, target: ReprocessTarget) -> Result<ScaffoldMetrics, String> {
+    let (url, default_voxel_size_um, job_queue, client) = {
+        let state = app.state::<Mutex<AppState>>();
+        let state = state.lock().unwrap();
+        (
+            format!("{}/analyze", state.settings.julia_server_url),
+            units::convert_length(
+                state.settings.default_voxel_size,
+                &state.settings.voxel_unit,
+                units::CANONICAL_VOXEL_UNIT,
+            ),
+            state.job_queue.clone(),
+            state.http.client().clone(),
+        )
+    };
+    let voxel_size_um = match target.voxel_size_um {
+        Some(voxel_size_um) => voxel_size_um,
+        None => default_voxel_size_um?,
+    };
+
+    let _job = job_queue.acquire(None).await?;
+    let response = client
+        .post(&url)
+        .json(&serde_json::json!({
+            "file_path": target.file_path,
+            "voxel_size": voxel_size_um,
+            "voxel_unit": units::CANONICAL_VOXEL_UNIT,
+        }))
+        .send()
+        .await
+        .map_err(http_client::describe)?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("analysis failed ({})", status));
+    }
+    let mut reply: serde_json::Value = http_client::json(response).await?;
+    let metrics: ScaffoldMetrics = serde_json::from_value(reply["metrics"].take())
+        .map_err(|e| format!("unexpected analysis reply: {}", e))?;
+
+    let dir = history_dir(&app)?;
+    let history = app
+        .state::<Mutex<AppState>>()
+        .lock()
+        .unwrap()
+        .metrics_history
+        .clone();
+    history.record(&dir, &target.file_id, metrics.clone())?;
+    Ok(metrics)
+}
+
+fn snapshot_dir(app: &AppHandle) -> Result<std::path::PathBuf, String> {
+    app.path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join(snapshots::SNAPSHOT_DIR))
+        .ok_or_else(|| "could not resolve the app data directory".to_string())
+}
+
+// Save a workspace's Julia-side state to disk so it can be restored later; returns the snapshot id
+//
+// The snapshot records the backend and Julia versions it was taken on, since
+// Julia's serialization format only round-trips on the same Julia version.
+#[tauri::command]
+pub async fn snapshot_workspace(
+    app: AppHandle,
+    workspace_id: String,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<String, String> {
+    let dir = snapshot_dir(&app)?;
+    let (base_url, client, workspace_name) = {
+        let state = state.lock().unwrap();
+        let workspace = state.require_workspace(&workspace_id)?;
+        (
+            state.settings.julia_server_url.clone(),
+            state.http.client().clone(),
+            workspace.name.clone(),
+        )
+    };
+    let backend_version = backend_info(&state).await?.version;
+
+    let response = client
+        .get(format!("{}/workspace/{}/snapshot", base_url, workspace_id))
+        .send()
+        .await
+        .map_err(http_client::describe)?;
+    let status = response.status();
+    if !status.is_success() {
+        let body: serde_json::Value = http_client::json(response).await.unwrap_or_default();
+        return Err(match body["error"].as_str() {
+            Some(error) => error.to_string(),
+            None => format!("snapshot failed ({})", status),
+        });
+    }
+    let julia_version = response
+        .headers()
+        .get("X-Julia-Version")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    let snapshot_id = uuid::Uuid::new_v4().to_string();
+    let size_bytes =
+        snapshots::write_blob(response, &snapshots::blob_path(&dir, &snapshot_id)).await?;
+    let meta = SnapshotMeta {
+        snapshot_id: snapshot_id.clone(),
+        workspace_id,
+        workspace_name,
+        size_bytes,
+        created: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0),
+        backend_version,
+        julia_version,
+    };
+    let mut state = state.lock().unwrap();
+    state.snapshots.record(&dir, meta)?;
+    Ok(snapshot_id)
+}
+
+// Load a snapshot back into Julia and track its workspace again
+//
+// Restoring onto a different backend or Julia version is attempted anyway; the
+// mismatch is reported in `warnings`, and Julia refuses state it cannot read.
+#[tauri::command]
+pub async fn restore_workspace(
+    app: AppHandle,
+    snapshot_id: String,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<RestoreOutcome, String> {
+    let dir = snapshot_dir(&app)?;
+    let (meta, base_url, client) = {
+        let mut state = state.lock().unwrap();
+        let meta = state.snapshots.get(&dir, &snapshot_id)?;
+        (
+            meta,
+            state.settings.julia_server_url.clone(),
+            state.http.client().clone(),
+        )
+    };
+    let blob = tokio::fs::read(snapshots::blob_path(&dir, &snapshot_id))
+        .await
+        .map_err(|e| format!("snapshot {} has no data on disk: {}", snapshot_id, e))?;
+    let backend_version = backend_info(&state).await?.version;
+
+    let mut request = client
+        .post(format!("{}/workspace/restore", base_url))
+        .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+        .body(blob);
+    if let Some(julia_version) = &meta.julia_version {
+        request = request.header("X-Julia-Version", julia_version);
+    }
+    let response = request.send().await.map_err(http_client::describe)?;
+    let status = response.status();
+    let body: serde_json::Value = http_client::json(response).await?;
+    if let Some(error) = body["error"].as_str() {
+        return Err(error.to_string());
+    }
+    if !status.is_success() {
+        return Err(format!("restore failed ({})", status));
+    }
+
+    let workspace_id = body["workspace_id"]
+        .as_str()
+        .unwrap_or(&meta.workspace_id)
+        .to_string();
+    let warnings =
+        snapshots::compatibility_warnings(&meta, &backend_version, body["julia_version"].as_str());
+    let mut state = state.lock().unwrap();
+    state
+        .workspaces
+        .entry(workspace_id.clone())
+        .or_insert_with(|| {
+            let mut workspace = WorkspaceState::new(&workspace_id);
+            workspace.name = meta.workspace_name.clone();
+            workspace
+        });
+    Ok(RestoreOutcome {
+        snapshot_id,
+        workspace_id,
+        warnings,
+    })
+}
+
+// List saved workspace snapshots, newest first
+#[tauri::command]
+pub fn list_snapshots(
+    app: AppHandle,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<Vec<SnapshotMeta>, String> {
+    let dir = snapshot_dir(&app)?;
+    let mut state = state.lock().unwrap();
+    state.snapshots.list(&dir)
+}
+
+// List bundled and user-defined materials
+#[tauri::command]
+pub async fn list_materials(
+    app: AppHandle,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<Vec<Material>, String> {
+    let cache = state.lock().unwrap().materials.clone();
+    Ok(materials::cached_materials(&app, &cache).await)
+}
+
+// Look up a material by name (case-insensitive)
+#[tauri::command]
+pub async fn get_material(
+    app: AppHandle,
+    name: String,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<Option<Material>, String> {
+    Ok(find_material(&app, &state, &name).await)
+}
+
+// Add or replace a user-defined material, persisted in the app config dir
+#[tauri::command]
+pub fn add_material(
+    app: AppHandle,
+    material: Material,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<(), String> {
+    materials::add_user_material(&app, material)?;
+    state.lock().unwrap().materials.invalidate(&());
+    Ok(())
+}
+
+// List bundled and user-defined printer profiles
+#[tauri::command]
+pub fn list_printer_profiles(app: AppHandle) -> Vec<PrinterProfile> {
+    printer_profiles::all_printer_profiles(&app)
+}
+
+// Add or replace a user-defined printer profile, persisted in the app config dir
+#[tauri::command]
+pub fn add_printer_profile(app: AppHandle, profile: PrinterProfile) -> Result<(), String> {
+    printer_profiles::add_user_printer_profile(&app, profile)
+}
+
+// Delete a user-defined printer profile (bundled profiles cannot be deleted)
+#[tauri::command]
+pub fn delete_printer_profile(app: AppHandle, name: String) -> Result<(), String> {
+    printer_profiles::delete_user_printer_profile(&app, &name)
+}
+
+#[derive(Debug, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    pub build_timestamp: &'static str,
+    pub rust_version: &'static str,
+}
+
+// Get build identification (captured by build.rs; "unknown" where unavailable)
+#[tauri::command]
+pub fn get_build_info() -> BuildInfo {
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("DARWIN_GIT_SHA"),
+        build_timestamp: env!("DARWIN_BUILD_TIMESTAMP"),
+        rust_version: env!("DARWIN_RUSTC_VERSION"),
+    }
+}
+
+// Get application settings
+#[tauri::command]
+pub fn get_app_settings(state: State<'_, Mutex<AppState>>) -> AppSettings {
+    let state = state.lock().unwrap();
+    state.settings.clone()
+}
+
+// Set application settings
+#[tauri::command]
+pub fn set_app_settings(
+    mut settings: AppSettings,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<(), String> {
+    settings.voxel_unit = units::normalize_unit(&settings.voxel_unit)?.to_string();
+    if settings.max_concurrent_jobs == 0 {
+        return Err("max_concurrent_jobs must be at least 1".to_string());
+    }
+    if settings.request_timeout_secs == 0 {
+        return Err("request_timeout_secs must be at least 1".to_string());
+    }
+    settings.analysis_time_model.validate()?;
+    let mut state = state.lock().unwrap();
+    state
+        .job_queue
+        .set_max_concurrency(settings.max_concurrent_jobs);
+    if settings.julia_server_url != state.settings.julia_server_url {
+        // Refetch when switching back, the backend there may have been upgraded meanwhile
+        state.backend_info.clear();
+    }
+    if settings.request_timeout_secs != state.http.timeout_secs() {
+        state.http = http_client::HttpClient::new(settings.request_timeout_secs);
+    }
+    state.settings = settings;
+    Ok(())
+}
+
+// Compute jobs currently running against Julia and waiting for a slot
+#[tauri::command]
+pub fn get_job_queue_status(state: State<'_, Mutex<AppState>>) -> JobQueueStatus {
+    state.lock().unwrap().job_queue.status()
+}
+
+// Cancel a compute job by the `job_id` it was started with
+//
+// Queued jobs are cancelled before they start; of running jobs, only those that
+// can stop midway (`compute_stiffness_tensor`) are. Returns false if no such job
+// could be cancelled (it already started or finished).
+#[tauri::command]
+pub fn cancel_job(job_id: String, state: State<'_, Mutex<AppState>>) -> bool {
+    state.lock().unwrap().job_queue.cancel(&job_id)
+}
+
+// Save the session as a `.darwin` project bundle
+//
+// The bundle holds the workspaces (with their undo history), a snapshot of the
+// settings, the agent chat history and copies of the scaffold files the
+// workspaces point at. Files that no longer exist are skipped and reported in
+// `missing_files`.
+#[tauri::command]
+pub async fn save_project(
+    path: String,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<ProjectSummary, String> {
+    let manifest = {
+        let state = state.lock().unwrap();
+        let mut workspaces: Vec<WorkspaceState> = state.workspaces.values().cloned().collect();
+        workspaces.sort_by(|a, b| a.id.cmp(&b.id));
+        ProjectManifest {
+            format_version: project::PROJECT_FORMAT_VERSION,
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            saved_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            settings: state.settings.clone(),
+            workspaces,
+            current_workspace: state.current_workspace.clone(),
+            chat_history: state.chat_history.clone(),
+            files: Vec::new(),
+        }
+    };
+    let saved_ids: Vec<String> = manifest.workspaces.iter().map(|w| w.id.clone()).collect();
+
+    let summary = tokio::task::spawn_blocking(move || {
+        project::write_bundle(std::path::Path::new(&path), manifest)
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+
+    let mut state = state.lock().unwrap();
+    for id in saved_ids {
+        if let Some(workspace) = state.workspaces.get_mut(&id) {
+            workspace.modified = false;
+        }
+    }
+    Ok(summary)
+}
+
+// Open a `.darwin` project bundle, replacing the open workspaces and chat history
+//
+// Bundled files are extracted under the app data dir and the workspaces are
+// pointed at the extracted copies. The saved settings are returned in the
+// manifest but not applied, so opening a shared project keeps local settings.
+#[tauri::command]
+pub async fn load_project(
+    app: AppHandle,
+    path: String,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<ProjectManifest, String> {
+    let stem = std::path::Path::new(&path)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "project".to_string());
+    let extract_dir = app
+        .path_resolver()
+        .app_data_dir()
+        .map(|dir| {
+            dir.join("projects")
+                .join(format!("{}-{}", stem, uuid::Uuid::new_v4().simple()))
+        })
+        .ok_or_else(|| "could not resolve the app data directory".to_string())?;
+
+    let manifest = tokio::task::spawn_blocking(move || {
+        project::read_bundle(std::path::Path::new(&path), &extract_dir)
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+
+    let mut state = state.lock().unwrap();
+    state.workspaces = manifest
+        .workspaces
+        .iter()
+        .map(|w| (w.id.clone(), w.clone()))
+        .collect();
+    state.current_workspace = manifest
+        .current_workspace
+        .clone()
+        .filter(|id| state.workspaces.contains_key(id));
+    state.chat_history = manifest.chat_history.clone();
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    /// A "Julia" answering every request with `{"porosity": 0.7}`; returns its URL
+    /// and how many requests it has served.
+    fn mock_julia() -> (String, Arc<AtomicU32>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/analyze", listener.local_addr().unwrap());
+        let served = Arc::new(AtomicU32::new(0));
+        let counter = served.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = [0u8; 4096];
+                let _ = stream.read(&mut request);
+                counter.fetch_add(1, Ordering::SeqCst);
+                let body = r#"{"porosity": 0.7}"#;
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+            }
+        });
+        (url, served)
+    }
+
+    #[test]
+    fn only_data_files_in_allowed_dirs_may_be_opened() {
+        let dir = std::env::temp_dir().join(format!("darwin_open_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = std::fs::canonicalize(dir).unwrap();
+        let write = |name: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, b"data").unwrap();
+            path
+        };
+        let allowed = [dir.clone()];
+
+        assert!(check_openable(&write("scaffold.stl"), &allowed).is_ok());
+        assert!(check_openable(&write("report.PDF"), &allowed).is_ok());
+        for name in ["install.sh", "setup.EXE", "run.bat", "tool.py"] {
+            let error = check_openable(&write(name), &allowed).unwrap_err();
+            assert!(error.contains("program or script"), "{}", error);
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let binary = write("scaffold");
+            std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+            assert!(check_openable(&binary, &allowed).is_err());
+        }
+
+        let elsewhere = [dir.join("exports")];
+        let error = check_openable(&write("mesh.stl"), &elsewhere).unwrap_err();
+        assert!(error.contains("outside the directories"), "{}", error);
+        assert!(check_openable(&dir, &allowed).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn repeated_keys_are_served_from_the_cache() {
+        let (url, served) = mock_julia();
+        let client = reqwest::Client::new();
+        let state = Mutex::new(AppState::default());
+        let analyze = || async {
+            let response = send_analysis(
+                || client.post(&url),
+                ANALYZE_MAX_ATTEMPTS,
+                ANALYZE_RETRY_DELAY,
+            )
+            .await?;
+            let succeeded = response.status().is_success();
+            Ok((http_client::json(response).await?, succeeded))
+        };
+
+        let first = analyze_once(&state, Some("key-1"), analyze).await.unwrap();
+        let second = analyze_once(&state, Some("key-1"), analyze).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+
+        analyze_once(&state, Some("key-2"), analyze).await.unwrap();
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+        analyze_once(&state, None, analyze).await.unwrap();
+        analyze_once(&state, None, analyze).await.unwrap();
+        assert_eq!(served.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn connection_failures_are_retried_up_to_max_attempts() {
+        // A port nothing listens on refuses every connection
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let url = format!("http://127.0.0.1:{}/analyze", port);
+        let client = reqwest::Client::new();
+        let attempts = AtomicU32::new(0);
+        let build = || {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            client.post(&url)
+        };
+
+        assert!(send_analysis(build, 3, std::time::Duration::ZERO)
+            .await
+            .is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        // A request that gets through is sent once
+        let (url, served) = mock_julia();
+        let attempts = AtomicU32::new(0);
+        let build = || {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            client.post(&url)
+        };
+        assert!(send_analysis(build, 3, std::time::Duration::ZERO)
+            .await
+            .is_ok());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+    }
 }
@@ -0,0 +1,129 @@
+// Metrics comparison - per-field diff between two workspaces' ScaffoldMetrics
+
+use crate::commands::ScaffoldMetrics;
+use serde::Serialize;
+
+pub const DEFAULT_THRESHOLD_PCT: f64 = 10.0;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldDiff {
+    pub field: String,
+    pub value_a: f64,
+    pub value_b: f64,
+    pub abs_diff: f64,
+    /// Relative to `value_a`; `None` when `value_a` is zero
+    pub pct_diff: Option<f64>,
+    pub exceeds_threshold: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsComparison {
+    pub workspace_a: String,
+    pub workspace_b: String,
+    pub threshold_pct: f64,
+    pub fields: Vec<FieldDiff>,
+    pub flagged: Vec<String>,
+}
+
+pub fn compare(
+    workspace_a: &str,
+    a: &ScaffoldMetrics,
+    workspace_b: &str,
+    b: &ScaffoldMetrics,
+    threshold_pct: f64,
+) -> MetricsComparison {
+    let fields: Vec<FieldDiff> = a
+        .fields()
+        .into_iter()
+        .zip(b.fields())
+        .map(|((field, value_a), (_, value_b))| {
+            let abs_diff = (value_b - value_a).abs();
+            let pct_diff = (value_a != 0.0).then(|| (value_b - value_a) / value_a.abs() * 100.0);
+            let exceeds_threshold = match pct_diff {
+                Some(pct) => pct.abs() > threshold_pct,
+                None => abs_diff > 0.0,
+            };
+            FieldDiff {
+                field: field.to_string(),
+                value_a,
+                value_b,
+                abs_diff,
+                pct_diff,
+                exceeds_threshold,
+            }
+        })
+        .collect();
+
+    let flagged = fields
+        .iter()
+        .filter(|f| f.exceeds_threshold)
+        .map(|f| f.field.clone())
+        .collect();
+
+    MetricsComparison {
+        workspace_a: workspace_a.to_string(),
+        workspace_b: workspace_b.to_string(),
+        threshold_pct,
+        fields,
+        flagged,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(porosity: f64, elastic_modulus: f64) -> ScaffoldMetrics {
+        ScaffoldMetrics {
+            porosity,
+            mean_pore_size_um: 300.0,
+            interconnectivity: 0.95,
+            tortuosity: 1.2,
+            specific_surface_area: 12.0,
+            elastic_modulus,
+            yield_strength: 5.0,
+            permeability: 1e-9,
+        }
+    }
+
+    #[test]
+    fn identical_metrics_flag_nothing() {
+        let m = metrics(0.7, 100.0);
+        let cmp = compare("a", &m, "b", &m, DEFAULT_THRESHOLD_PCT);
+        assert_eq!(cmp.fields.len(), 8);
+        assert!(cmp.flagged.is_empty());
+        assert!(cmp.fields.iter().all(|f| f.abs_diff == 0.0));
+    }
+
+    #[test]
+    fn flags_fields_above_threshold() {
+        let cmp = compare(
+            "designed",
+            &metrics(0.70, 100.0),
+            "scanned",
+            &metrics(0.65, 150.0),
+            DEFAULT_THRESHOLD_PCT,
+        );
+        assert_eq!(cmp.flagged, vec!["elastic_modulus".to_string()]);
+
+        let modulus = cmp
+            .fields
+            .iter()
+            .find(|f| f.field == "elastic_modulus")
+            .unwrap();
+        assert_eq!(modulus.abs_diff, 50.0);
+        assert_eq!(modulus.pct_diff, Some(50.0));
+
+        let porosity = cmp.fields.iter().find(|f| f.field == "porosity").unwrap();
+        assert!((porosity.pct_diff.unwrap() + 7.142857).abs() < 1e-4);
+        assert!(!porosity.exceeds_threshold);
+    }
+
+    #[test]
+    fn zero_baseline_has_no_percentage() {
+        let cmp = compare("a", &metrics(0.0, 100.0), "b", &metrics(0.5, 100.0), 10.0);
+        let porosity = cmp.fields.iter().find(|f| f.field == "porosity").unwrap();
+        assert_eq!(porosity.pct_diff, None);
+        assert!(porosity.exceeds_threshold);
+    }
+}
@@ -0,0 +1,106 @@
+// Depth profiles - porosity, surface-to-volume ratio and pore size per slab along one axis
+
+use serde::{Deserialize, Serialize};
+
+pub const MIN_BINS: u32 = 1;
+pub const MAX_BINS: u32 = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    pub fn parse(axis: &str) -> Result<Self, String> {
+        match axis.trim().to_ascii_lowercase().as_str() {
+            "x" => Ok(Self::X),
+            "y" => Ok(Self::Y),
+            "z" => Ok(Self::Z),
+            other => Err(format!("unknown axis '{}', expected x, y or z", other)),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::X => "x",
+            Self::Y => "y",
+            Self::Z => "z",
+        }
+    }
+}
+
+/// Check the parameters of `get_depth_profile` before asking Julia
+pub fn validate(axis: &str, bins: u32) -> Result<Axis, String> {
+    let axis = Axis::parse(axis)?;
+    if !(MIN_BINS..=MAX_BINS).contains(&bins) {
+        return Err(format!(
+            "bins must be between {} and {}, got {}",
+            MIN_BINS, MAX_BINS, bins
+        ));
+    }
+    Ok(axis)
+}
+
+/// One slab of the scaffold, `bin` 0 being nearest the origin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DepthBin {
+    pub bin: u32,
+    /// Centre of the slab along the axis, in micrometres
+    pub depth_um: f64,
+    pub porosity: f64,
+    /// Solid surface area over solid volume, in 1/mm
+    pub sa_to_v: f64,
+    /// Mean in-plane pore chord, in micrometres
+    pub pore_size: f64,
+}
+
+/// Bins in depth order, ready to plot against `depth_um`. A scaffold thinner than
+/// the requested number of bins gets one bin per voxel slice.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DepthProfile {
+    pub workspace_id: String,
+    pub axis: Axis,
+    pub voxel_size_um: f64,
+    pub bins: Vec<DepthBin>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn axis_and_bins_are_validated() {
+        assert_eq!(validate("Z", 32), Ok(Axis::Z));
+        assert_eq!(validate("x", MIN_BINS), Ok(Axis::X));
+        assert_eq!(validate(" y ", MAX_BINS), Ok(Axis::Y));
+        assert_eq!(
+            validate("w", 32).unwrap_err(),
+            "unknown axis 'w', expected x, y or z"
+        );
+        assert_eq!(
+            validate("z", 0).unwrap_err(),
+            "bins must be between 1 and 1024, got 0"
+        );
+        assert!(validate("z", MAX_BINS + 1).is_err());
+    }
+
+    #[test]
+    fn julia_reply_deserializes() {
+        let reply = serde_json::json!({
+            "workspace_id": "ws-1",
+            "axis": "z",
+            "voxel_size_um": 10.0,
+            "bins": [
+                {"bin": 0, "depth_um": 25.0, "porosity": 0.8, "sa_to_v": 40.0, "pore_size": 210.0},
+                {"bin": 1, "depth_um": 75.0, "porosity": 0.7, "sa_to_v": 35.5, "pore_size": 180.0},
+            ],
+        });
+        let profile: DepthProfile = serde_json::from_value(reply).unwrap();
+        assert_eq!(profile.axis, Axis::Z);
+        assert_eq!(profile.bins.len(), 2);
+        assert_eq!(profile.bins[1].pore_size, 180.0);
+    }
+}
@@ -0,0 +1,133 @@
+// Export streaming - write a file Julia streams back, reporting progress as it arrives
+
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+
+/// Julia announces the size of a streamed export in this header
+pub const EXPORT_SIZE_HEADER: &str = "X-Export-Size";
+/// Without an announced size, progress is reported every this many bytes instead
+const PROGRESS_STEP_BYTES: u64 = 1024 * 1024;
+
+/// Decides which chunks are worth a progress event: one per percentage point
+/// when the total is known, one per `PROGRESS_STEP_BYTES` otherwise.
+#[derive(Debug)]
+pub struct ProgressTracker {
+    total: Option<u64>,
+    written: u64,
+    reported: u64,
+}
+
+impl ProgressTracker {
+    pub fn new(total: Option<u64>) -> Self {
+        Self {
+            total: total.filter(|&t| t > 0),
+            written: 0,
+            reported: 0,
+        }
+    }
+
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn percent(&self) -> Option<u8> {
+        self.total
+            .map(|total| (self.written.min(total) * 100 / total) as u8)
+    }
+
+    /// Count `bytes` more; `Some(percent)` when an event is due
+    pub fn advance(&mut self, bytes: u64) -> Option<Option<u8>> {
+        let before = self.percent();
+        self.written += bytes;
+        let due = match self.total {
+            Some(_) => self.percent() != before,
+            None => self.written - self.reported >= PROGRESS_STEP_BYTES,
+        };
+        if !due {
+            return None;
+        }
+        self.reported = self.written;
+        Some(self.percent())
+    }
+
+    /// Err when Julia announced more bytes than it sent
+    pub fn finish(&self) -> Result<(), String> {
+        match self.total {
+            Some(total) if self.written != total => Err(format!(
+                "export stream ended after {} of {} bytes",
+                self.written, total
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Stream `response` into `path` through a `.part` file, calling `progress(percent,
+/// bytes_written)` when an update is due; returns the size. On failure nothing is
+/// left at `path` or next to it.
+pub async fn write_streamed(
+    mut response: reqwest::Response,
+    path: &Path,
+    mut progress: impl FnMut(Option<u8>, u64),
+) -> Result<u64, String> {
+    let total = response
+        .headers()
+        .get(EXPORT_SIZE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok());
+    let partial = path.with_extension("part");
+    let result = async {
+        let mut tracker = ProgressTracker::new(total);
+        let mut file = tokio::fs::File::create(&partial)
+            .await
+            .map_err(|e| e.to_string())?;
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(crate::http_client::describe)?
+        {
+            file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+            if let Some(percent) = tracker.advance(chunk.len() as u64) {
+                progress(percent, tracker.written());
+            }
+        }
+        file.flush().await.map_err(|e| e.to_string())?;
+        tracker.finish()?;
+        tokio::fs::rename(&partial, path)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(tracker.written())
+    }
+    .await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&partial).await;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_is_reported_per_point_or_per_mebibyte() {
+        let mut tracker = ProgressTracker::new(Some(200));
+        assert_eq!(tracker.advance(1), None);
+        assert_eq!(tracker.advance(1), Some(Some(1)));
+        assert_eq!(tracker.advance(198), Some(Some(100)));
+        assert_eq!(tracker.finish(), Ok(()));
+
+        let mut short = ProgressTracker::new(Some(1000));
+        short.advance(400);
+        assert_eq!(
+            short.finish().unwrap_err(),
+            "export stream ended after 400 of 1000 bytes"
+        );
+
+        let mut unknown = ProgressTracker::new(None);
+        assert_eq!(unknown.advance(PROGRESS_STEP_BYTES - 1), None);
+        assert_eq!(unknown.advance(1), Some(None));
+        assert_eq!(unknown.percent(), None);
+        assert_eq!(unknown.finish(), Ok(()));
+    }
+}
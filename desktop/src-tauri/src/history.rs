@@ -0,0 +1,217 @@
+// Metrics history - timestamped ScaffoldMetrics per workspace, persisted as JSONL
+
+use crate::commands::ScaffoldMetrics;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Most recent entries kept in memory per workspace; older ones stay on disk
+pub const HISTORY_MEMORY_CAP: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Unix time in milliseconds
+    pub timestamp: u64,
+    pub metrics: ScaffoldMetrics,
+}
+
+#[derive(Debug, Default)]
+struct WorkspaceHistory {
+    recent: VecDeque<HistoryEntry>,
+    /// Whether the file holds entries that fell out of `recent`
+    truncated: bool,
+}
+
+#[derive(Debug, Default)]
+struct Loaded {
+    workspaces: HashMap<String, WorkspaceHistory>,
+    /// Bumped by every `record`, so a read racing one does not cache a stale tail
+    revision: u64,
+}
+
+/// Per-workspace metrics history: a bounded in-memory tail backed by
+/// `{dir}/{workspace_id}.jsonl`, loaded lazily the first time a workspace is read.
+///
+/// Cheap to clone; clones share the same tails. File IO runs without holding any
+/// lock, so commands clone this out of `AppState` before calling it.
+#[derive(Debug, Clone)]
+pub struct MetricsHistory {
+    capacity: usize,
+    loaded: Arc<Mutex<Loaded>>,
+}
+
+impl MetricsHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            loaded: Arc::default(),
+        }
+    }
+
+    /// Append a timestamped entry to the workspace history and its file
+    pub fn record(
+        &self,
+        dir: &Path,
+        workspace_id: &str,
+        metrics: ScaffoldMetrics,
+    ) -> Result<HistoryEntry, String> {
+        let path = history_path(dir, workspace_id)?;
+        let entry = HistoryEntry {
+            timestamp: now_millis(),
+            metrics,
+        };
+
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let mut line = serde_json::to_string(&entry).map_err(|e| e.to_string())?;
+        line.push('\n');
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .map_err(|e| e.to_string())?;
+
+        let mut loaded = self.loaded.lock().unwrap();
+        loaded.revision += 1;
+        // A workspace not loaded yet picks the entry up from the file when it is
+        if let Some(history) = loaded.workspaces.get_mut(workspace_id) {
+            history.recent.push_back(entry.clone());
+            if history.recent.len() > self.capacity {
+                history.recent.pop_front();
+                history.truncated = true;
+            }
+        }
+        Ok(entry)
+    }
+
+    /// Full history, oldest first. Served from memory unless older entries were evicted.
+    pub fn entries(&self, dir: &Path, workspace_id: &str) -> Result<Vec<HistoryEntry>, String> {
+        let path = history_path(dir, workspace_id)?;
+        let revision = {
+            let loaded = self.loaded.lock().unwrap();
+            match loaded.workspaces.get(workspace_id) {
+                Some(history) if !history.truncated => {
+                    return Ok(history.recent.iter().cloned().collect())
+                }
+                _ => loaded.revision,
+            }
+        };
+
+        let all = read_entries(&path)?;
+        let mut loaded = self.loaded.lock().unwrap();
+        if loaded.revision == revision && !loaded.workspaces.contains_key(workspace_id) {
+            let recent = all[all.len().saturating_sub(self.capacity)..].to_vec();
+            loaded.workspaces.insert(
+                workspace_id.to_string(),
+                WorkspaceHistory {
+                    recent: recent.into(),
+                    truncated: all.len() > self.capacity,
+                },
+            );
+        }
+        Ok(all)
+    }
+}
+
+impl Default for MetricsHistory {
+    fn default() -> Self {
+        Self::new(HISTORY_MEMORY_CAP)
+    }
+}
+
+fn history_path(dir: &Path, workspace_id: &str) -> Result<PathBuf, String> {
+    let valid = !workspace_id.is_empty()
+        && workspace_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(format!("invalid workspace id: {:?}", workspace_id));
+    }
+    Ok(dir.join(format!("{}.jsonl", workspace_id)))
+}
+
+// Missing file means empty history; unparseable lines (e.g. a torn write) are skipped
+fn read_entries(path: &Path) -> Result<Vec<HistoryEntry>, String> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.to_string()),
+    };
+    Ok(BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect())
+}
+
+fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(porosity: f64) -> ScaffoldMetrics {
+        ScaffoldMetrics {
+            porosity,
+            mean_pore_size_um: 300.0,
+            interconnectivity: 0.95,
+            tortuosity: 1.2,
+            specific_surface_area: 10.0,
+            elastic_modulus: 100.0,
+            yield_strength: 5.0,
+            permeability: 1e-9,
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("darwin_history_{}_{}", name, now_millis()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn history_survives_restart() {
+        let dir = temp_dir("restart");
+        let history = MetricsHistory::default();
+        history.record(&dir, "ws-1", metrics(0.6)).unwrap();
+        history.record(&dir, "ws-1", metrics(0.7)).unwrap();
+
+        let entries = MetricsHistory::default().entries(&dir, "ws-1").unwrap();
+        let porosities: Vec<f64> = entries.iter().map(|e| e.metrics.porosity).collect();
+        assert_eq!(porosities, vec![0.6, 0.7]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn evicted_entries_are_read_back_from_disk() {
+        let dir = temp_dir("evict");
+        let history = MetricsHistory::new(2);
+        history.record(&dir, "ws-1", metrics(0.1)).unwrap();
+        assert_eq!(history.entries(&dir, "ws-1").unwrap().len(), 1);
+        for p in [0.2, 0.3] {
+            history.record(&dir, "ws-1", metrics(p)).unwrap();
+        }
+
+        let recent = history.loaded.lock().unwrap().workspaces["ws-1"]
+            .recent
+            .len();
+        assert_eq!(recent, 2);
+        assert_eq!(history.entries(&dir, "ws-1").unwrap().len(), 3);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_path_like_workspace_ids() {
+        let dir = temp_dir("ids");
+        let history = MetricsHistory::default();
+        assert!(history.record(&dir, "../escape", metrics(0.5)).is_err());
+        assert!(history.entries(&dir, "").is_err());
+    }
+}
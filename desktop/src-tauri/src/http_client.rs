@@ -0,0 +1,189 @@
+// HTTP client - the shared Julia client, with a request timeout and a response size cap
+
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+
+pub const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 600;
+/// Largest Julia response read into memory; bigger ones are refused, not truncated
+pub const MAX_RESPONSE_BYTES: usize = 64 * 1024 * 1024;
+/// Error for requests that hit the timeout, so the UI can offer a retry
+pub const TIMEOUT_ERROR: &str = "request timed out";
+
+/// Cheap to clone; clones share one connection pool.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    timeout_secs: u64,
+}
+
+impl HttpClient {
+    pub fn new(timeout_secs: u64) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(timeout_secs))
+            .build()
+            .expect("HTTP client builds with a timeout only");
+        Self {
+            client,
+            timeout_secs,
+        }
+    }
+
+    pub fn timeout_secs(&self) -> u64 {
+        self.timeout_secs
+    }
+
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new(DEFAULT_REQUEST_TIMEOUT_SECS)
+    }
+}
+
+/// `TIMEOUT_ERROR` for timeouts, the reqwest message otherwise.
+pub fn describe(error: reqwest::Error) -> String {
+    if error.is_timeout() {
+        TIMEOUT_ERROR.to_string()
+    } else {
+        error.to_string()
+    }
+}
+
+/// Read the body, refusing it once it grows past `max_bytes`.
+pub async fn read_body(
+    mut response: reqwest::Response,
+    max_bytes: usize,
+) -> Result<Vec<u8>, String> {
+    let too_large = || format!("response exceeds the {} byte limit", max_bytes);
+    if response
+        .content_length()
+        .is_some_and(|len| len > max_bytes as u64)
+    {
+        return Err(too_large());
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(describe)? {
+        if body.len() + chunk.len() > max_bytes {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// `read_body` capped at `MAX_RESPONSE_BYTES`, parsed as JSON.
+pub async fn json<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, String> {
+    let body = read_body(response, MAX_RESPONSE_BYTES).await?;
+    serde_json::from_slice(&body).map_err(|e| e.to_string())
+}
+
+/// `json` for a success status; otherwise Julia's `{"error": ...}` message, or
+/// `"{what} failed ({status})"` when the body carries none.
+pub async fn json_or_error<T: DeserializeOwned>(
+    response: reqwest::Response,
+    what: &str,
+) -> Result<T, String> {
+    let status = response.status();
+    if status.is_success() {
+        return json(response).await;
+    }
+    let body: serde_json::Value = json(response).await.unwrap_or_default();
+    Err(body["error"]
+        .as_str()
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{} failed ({})", what, status)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// Answer each connection on an ephemeral port with `reply` after `delay`.
+    fn serve(reply: Vec<u8>, delay: Duration) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = [0u8; 4096];
+                let _ = stream.read(&mut request);
+                std::thread::sleep(delay);
+                let _ = stream.write_all(&reply);
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    fn http_reply(body: &str) -> Vec<u8> {
+        http_reply_with_status("200 OK", body)
+    }
+
+    fn http_reply_with_status(status: &str, body: &str) -> Vec<u8> {
+        format!(
+            "HTTP/1.1 {}\r\nconnection: close\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+        .into_bytes()
+    }
+
+    #[tokio::test]
+    async fn timeouts_get_a_distinct_message() {
+        let url = serve(http_reply("{}"), Duration::from_secs(2));
+        let error = HttpClient::new(1)
+            .client()
+            .get(&url)
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(describe(error), TIMEOUT_ERROR);
+    }
+
+    #[tokio::test]
+    async fn bodies_past_the_cap_are_refused() {
+        let body = format!("[{}]", vec!["1"; 100].join(","));
+        let url = serve(http_reply(&body), Duration::ZERO);
+        let client = HttpClient::default();
+
+        let response = client.client().get(&url).send().await.unwrap();
+        let error = read_body(response, 64).await.unwrap_err();
+        assert_eq!(error, "response exceeds the 64 byte limit");
+
+        let response = client.client().get(&url).send().await.unwrap();
+        let numbers: Vec<u32> = json(response).await.unwrap();
+        assert_eq!(numbers.len(), 100);
+    }
+
+    #[tokio::test]
+    async fn error_statuses_surface_the_julia_message() {
+        let client = HttpClient::default();
+        let url = serve(
+            http_reply_with_status(
+                "400 Bad Request",
+                r#"{"error":"resolution must be positive"}"#,
+            ),
+            Duration::ZERO,
+        );
+        let response = client.client().get(&url).send().await.unwrap();
+        let error = json_or_error::<serde_json::Value>(response, "TPMS estimate")
+            .await
+            .unwrap_err();
+        assert_eq!(error, "resolution must be positive");
+
+        let url = serve(
+            http_reply_with_status("502 Bad Gateway", "<html></html>"),
+            Duration::ZERO,
+        );
+        let response = client.client().get(&url).send().await.unwrap();
+        let error = json_or_error::<serde_json::Value>(response, "TPMS estimate")
+            .await
+            .unwrap_err();
+        assert_eq!(error, "TPMS estimate failed (502 Bad Gateway)");
+    }
+}
@@ -0,0 +1,294 @@
+// Job queue - bounds how many compute commands hit the single Julia process at once
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+
+pub const DEFAULT_MAX_CONCURRENT_JOBS: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct JobQueueStatus {
+    pub running: usize,
+    pub queued: usize,
+    pub max_concurrency: usize,
+}
+
+/// Cheap to clone; clones share the same queue
+#[derive(Debug, Clone)]
+pub struct JobQueue {
+    permits: Arc<Semaphore>,
+    limit: Arc<Mutex<usize>>,
+    running: Arc<AtomicUsize>,
+    queued: Arc<AtomicUsize>,
+    /// Permits still to be retired after the limit was lowered below `running`
+    surplus: Arc<AtomicUsize>,
+    /// Jobs waiting for a permit that were given an id, so they can be cancelled
+    waiting: Arc<Mutex<HashMap<String, Arc<Notify>>>>,
+    /// Running jobs that listen for `cancel` through a `RunningJob`
+    cancellable: Arc<Mutex<HashMap<String, Arc<Notify>>>>,
+}
+
+/// Held while a job runs; dropping it lets the next queued job start
+#[derive(Debug)]
+pub struct JobPermit {
+    permit: Option<OwnedSemaphorePermit>,
+    running: Arc<AtomicUsize>,
+    surplus: Arc<AtomicUsize>,
+}
+
+impl Drop for JobPermit {
+    fn drop(&mut self) {
+        self.running.fetch_sub(1, Ordering::SeqCst);
+        let retire = self
+            .surplus
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        if let (true, Some(permit)) = (retire, self.permit.take()) {
+            permit.forget();
+        }
+    }
+}
+
+/// A running job `cancel` can stop; commands that can abort their work midway
+/// hold one next to their `JobPermit` and race the work against `cancelled`.
+#[derive(Debug)]
+pub struct RunningJob {
+    job_id: String,
+    cancelled: Arc<Notify>,
+    cancellable: Arc<Mutex<HashMap<String, Arc<Notify>>>>,
+}
+
+impl RunningJob {
+    /// Resolves once `cancel` was called for this job, including before this was awaited
+    pub async fn cancelled(&self) {
+        self.cancelled.notified().await
+    }
+}
+
+impl Drop for RunningJob {
+    fn drop(&mut self) {
+        // `cancel` already removed it, and the id may have been registered again since
+        let mut cancellable = self.cancellable.lock().unwrap();
+        let ours = cancellable.get(&self.job_id);
+        if ours.is_some_and(|current| Arc::ptr_eq(current, &self.cancelled)) {
+            cancellable.remove(&self.job_id);
+        }
+    }
+}
+
+/// Undoes the queued bookkeeping however `acquire` ends (permit, cancel, or dropped future)
+struct Waiting<'a> {
+    queue: &'a JobQueue,
+    job_id: Option<&'a str>,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.queue.queued.fetch_sub(1, Ordering::SeqCst);
+        if let Some(job_id) = self.job_id {
+            self.queue.waiting.lock().unwrap().remove(job_id);
+        }
+    }
+}
+
+impl JobQueue {
+    pub fn new(max_concurrency: usize) -> Self {
+        let max_concurrency = max_concurrency.max(1);
+        Self {
+            permits: Arc::new(Semaphore::new(max_concurrency)),
+            limit: Arc::new(Mutex::new(max_concurrency)),
+            running: Arc::new(AtomicUsize::new(0)),
+            queued: Arc::new(AtomicUsize::new(0)),
+            surplus: Arc::new(AtomicUsize::new(0)),
+            waiting: Arc::new(Mutex::new(HashMap::new())),
+            cancellable: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn status(&self) -> JobQueueStatus {
+        JobQueueStatus {
+            running: self.running.load(Ordering::SeqCst),
+            queued: self.queued.load(Ordering::SeqCst),
+            max_concurrency: *self.limit.lock().unwrap(),
+        }
+    }
+
+    /// Wait for a free slot. With a `job_id`, `cancel` can abort the wait.
+    pub async fn acquire(&self, job_id: Option<&str>) -> Result<JobPermit, String> {
+        let cancelled = Arc::new(Notify::new());
+        if let Some(job_id) = job_id {
+            let mut waiting = self.waiting.lock().unwrap();
+            if waiting.contains_key(job_id) {
+                return Err(format!("job {} is already queued", job_id));
+            }
+            waiting.insert(job_id.to_string(), cancelled.clone());
+        }
+        self.queued.fetch_add(1, Ordering::SeqCst);
+        let _waiting = Waiting {
+            queue: self,
+            job_id,
+        };
+
+        tokio::select! {
+            permit = self.permits.clone().acquire_owned() => {
+                let permit = permit.map_err(|e| e.to_string())?;
+                self.running.fetch_add(1, Ordering::SeqCst);
+                Ok(JobPermit {
+                    permit: Some(permit),
+                    running: self.running.clone(),
+                    surplus: self.surplus.clone(),
+                })
+            }
+            _ = cancelled.notified() => Err(format!("job {} was cancelled", job_id.unwrap_or_default())),
+        }
+    }
+
+    /// Make the running job `job_id` cancellable until the returned guard is dropped
+    pub fn running(&self, job_id: &str) -> Result<RunningJob, String> {
+        let mut cancellable = self.cancellable.lock().unwrap();
+        if cancellable.contains_key(job_id) {
+            return Err(format!("job {} is already running", job_id));
+        }
+        let cancelled = Arc::new(Notify::new());
+        cancellable.insert(job_id.to_string(), cancelled.clone());
+        Ok(RunningJob {
+            job_id: job_id.to_string(),
+            cancelled,
+            cancellable: self.cancellable.clone(),
+        })
+    }
+
+    /// Cancel a queued job, or a running one registered with `running`; `false` if
+    /// no such job is known
+    pub fn cancel(&self, job_id: &str) -> bool {
+        let cancelled = self.waiting.lock().unwrap().remove(job_id);
+        let cancelled = cancelled.or_else(|| self.cancellable.lock().unwrap().remove(job_id));
+        match cancelled {
+            Some(cancelled) => {
+                cancelled.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Change the concurrency limit. Lowering it takes effect as running jobs finish.
+    pub fn set_max_concurrency(&self, max_concurrency: usize) {
+        let max_concurrency = max_concurrency.max(1);
+        let mut limit = self.limit.lock().unwrap();
+        if max_concurrency > *limit {
+            // Permits not yet retired count towards the increase first
+            let mut grow = max_concurrency - *limit;
+            let _ = self
+                .surplus
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                    let cancelled = n.min(grow);
+                    grow -= cancelled;
+                    Some(n - cancelled)
+                });
+            self.permits.add_permits(grow);
+        } else {
+            let mut shrink = *limit - max_concurrency;
+            while shrink > 0 {
+                let Ok(idle) = self.permits.clone().try_acquire_owned() else {
+                    break;
+                };
+                idle.forget();
+                shrink -= 1;
+            }
+            self.surplus.fetch_add(shrink, Ordering::SeqCst);
+        }
+        *limit = max_concurrency;
+    }
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_CONCURRENT_JOBS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    async fn settle() {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    #[tokio::test]
+    async fn jobs_beyond_the_limit_wait_and_can_be_cancelled() {
+        let queue = JobQueue::new(1);
+        let first = queue.acquire(None).await.unwrap();
+
+        let waiting = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.acquire(Some("job-2")).await.map(drop) }
+        });
+        settle().await;
+        assert_eq!(
+            queue.status(),
+            JobQueueStatus {
+                running: 1,
+                queued: 1,
+                max_concurrency: 1
+            }
+        );
+
+        assert!(queue.cancel("job-2"));
+        assert!(waiting.await.unwrap().is_err());
+        assert!(!queue.cancel("job-2"));
+        assert_eq!(queue.status().queued, 0);
+
+        drop(first);
+        assert_eq!(queue.status().running, 0);
+    }
+
+    #[tokio::test]
+    async fn registered_running_jobs_can_be_cancelled() {
+        let queue = JobQueue::new(1);
+        let _permit = queue.acquire(Some("job-1")).await.unwrap();
+        assert!(!queue.cancel("job-1"));
+
+        let running = queue.running("job-1").unwrap();
+        assert!(queue.running("job-1").is_err());
+        assert!(queue.cancel("job-1"));
+        // The cancellation is kept until the job looks for it
+        tokio::time::timeout(Duration::from_millis(50), running.cancelled())
+            .await
+            .unwrap();
+        assert!(!queue.cancel("job-1"));
+
+        drop(running);
+        let again = queue.running("job-1").unwrap();
+        drop(again);
+        assert!(!queue.cancel("job-1"));
+    }
+
+    #[tokio::test]
+    async fn raising_the_limit_starts_queued_jobs_and_lowering_waits_for_running_ones() {
+        let queue = JobQueue::new(1);
+        let first = queue.acquire(None).await.unwrap();
+
+        let second = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.acquire(Some("job-2")).await }
+        });
+        settle().await;
+        queue.set_max_concurrency(2);
+        let second = second.await.unwrap().unwrap();
+        assert_eq!(queue.status().running, 2);
+
+        queue.set_max_concurrency(1);
+        drop(first);
+        drop(second);
+        settle().await;
+        let _only = queue.acquire(None).await.unwrap();
+        let blocked = tokio::time::timeout(Duration::from_millis(50), queue.acquire(None)).await;
+        assert!(blocked.is_err());
+        assert_eq!(queue.status().queued, 0);
+    }
+}
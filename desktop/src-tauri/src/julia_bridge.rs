@@ -1,7 +1,13 @@
 // Julia server bridge - manages Julia process lifecycle
 
+use crate::http_client::{self, HttpClient};
+use crate::julia_startup::{self, PrecompileTracker, STARTUP_PROGRESS_EVENT};
+use serde::Serialize;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tauri::{AppHandle, Manager};
 use thiserror::Error;
 
@@ -17,26 +23,189 @@ pub enum JuliaError {
 
 static JULIA_PROCESS: Mutex<Option<Child>> = Mutex::new(None);
 
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+const REMOTE_HEALTH_ATTEMPTS: u32 = 3;
+/// Seconds a spawned server gets to answer, extended while it is still precompiling
+const LOCAL_HEALTH_ATTEMPTS: u32 = 35;
+/// Kept short so a status badge polling `ping` never stalls
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Outcome of an on-demand `GET /health` probe
+#[derive(Debug, Clone, Serialize)]
+pub struct JuliaPing {
+    pub reachable: bool,
+    /// Time until the response headers arrived
+    pub latency_ms: Option<u64>,
+    pub http_status: Option<u16>,
+    pub error: Option<String>,
+}
+
+/// Whether `url` points at this machine, i.e. a server we can spawn ourselves
+pub fn is_local_url(url: &str) -> bool {
+    let Ok(url) = reqwest::Url::parse(url) else {
+        return false;
+    };
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Single `GET {base_url}/health` probe
+pub async fn check_health(client: &reqwest::Client, base_url: &str) -> Result<(), JuliaError> {
+    let url = format!("{}/health", base_url.trim_end_matches('/'));
+    let response = client
+        .get(&url)
+        .timeout(HEALTH_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| JuliaError::ConnectionError(http_client::describe(e)))?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(JuliaError::ConnectionError(format!(
+            "{} returned {}",
+            url,
+            response.status()
+        )))
+    }
+}
+
+/// Timed `GET {base_url}/health`; `reachable` only for a 2xx answer
+pub async fn ping(client: &reqwest::Client, base_url: &str) -> JuliaPing {
+    let url = format!("{}/health", base_url.trim_end_matches('/'));
+    let started = Instant::now();
+    let result = client.get(&url).timeout(PING_TIMEOUT).send().await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    match result {
+        Ok(response) => {
+            let status = response.status();
+            JuliaPing {
+                reachable: status.is_success(),
+                latency_ms: Some(latency_ms),
+                http_status: Some(status.as_u16()),
+                error: (!status.is_success()).then(|| format!("{} returned {}", url, status)),
+            }
+        }
+        Err(e) => JuliaPing {
+            reachable: false,
+            latency_ms: None,
+            http_status: None,
+            error: Some(if e.is_timeout() {
+                format!("no answer from {} within {:?}", url, PING_TIMEOUT)
+            } else {
+                e.to_string()
+            }),
+        },
+    }
+}
+
+/// The app's shared client, or a fresh one with the default timeout before the state exists
+fn shared_client(app: &AppHandle) -> reqwest::Client {
+    app.try_state::<Mutex<crate::state::AppState>>()
+        .map(|state| state.lock().unwrap().http.client().clone())
+        .unwrap_or_else(|| HttpClient::default().client().clone())
+}
+
+fn set_running(app: &AppHandle, running: bool, pid: Option<u32>) {
+    if let Some(state) = app.try_state::<Mutex<crate::state::AppState>>() {
+        let mut state = state.lock().unwrap();
+        state.julia_running = running;
+        state.julia_pid = pid;
+    }
+}
+
+// A remote backend is not ours to spawn: just check that it answers
+async fn connect_remote_server(app: &AppHandle, base_url: &str) -> Result<(), JuliaError> {
+    let client = shared_client(app);
+    let mut result = check_health(&client, base_url).await;
+    for _ in 1..REMOTE_HEALTH_ATTEMPTS {
+        if result.is_ok() {
+            break;
+        }
+        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        result = check_health(&client, base_url).await;
+    }
+
+    set_running(app, result.is_ok(), None);
+    if result.is_ok() {
+        println!("Using remote Julia server at {}", base_url);
+        emit_ready(app, &mut PrecompileTracker::default());
+    }
+    result
+}
+
+fn emit_ready(app: &AppHandle, tracker: &mut PrecompileTracker) {
+    if let Some(ready) = tracker.ready() {
+        let _ = app.emit_all(STARTUP_PROGRESS_EVENT, ready);
+    }
+}
+
+/// Echo one of Julia's output streams to ours, reporting precompilation progress found in it
+fn spawn_log_reader(
+    app: AppHandle,
+    stream: impl Read + Send + 'static,
+    stderr: bool,
+    tracker: Arc<Mutex<PrecompileTracker>>,
+) {
+    std::thread::spawn(move || {
+        for line in BufReader::new(stream).lines().map_while(Result::ok) {
+            if stderr {
+                eprintln!("[julia] {}", line);
+            } else {
+                println!("[julia] {}", line);
+            }
+            let progress = tracker.lock().unwrap().observe(&line);
+            if let Some(progress) = progress {
+                let _ = app.emit_all(STARTUP_PROGRESS_EVENT, progress);
+            }
+        }
+    });
+}
+
+/// Packages that may need precompiling, from the project's Manifest.toml
+fn manifest_packages(project_root: &Path) -> Option<u32> {
+    std::fs::read_to_string(project_root.join("Manifest.toml"))
+        .ok()
+        .map(|manifest| julia_startup::manifest_package_count(&manifest))
+}
+
+/// The project root (parent of desktop/), where Project.toml lives
+pub fn project_root() -> std::io::Result<PathBuf> {
+    let cwd = std::env::current_dir()?;
+    Ok(cwd.parent().map(|p| p.to_path_buf()).unwrap_or(cwd))
+}
+
 pub async fn start_julia_server(app: &AppHandle) -> Result<(), JuliaError> {
+    let base_url = app
+        .try_state::<Mutex<crate::state::AppState>>()
+        .map(|state| state.lock().unwrap().settings.julia_server_url.clone())
+        .unwrap_or_else(|| crate::state::AppSettings::default().julia_server_url);
+
+    if !is_local_url(&base_url) {
+        return connect_remote_server(app, &base_url).await;
+    }
+
     // Check if already running and start process - release lock before any await
-    let pid = {
+    let (pid, tracker) = {
         let mut process_guard = JULIA_PROCESS.lock().unwrap();
 
         if process_guard.is_some() {
             return Ok(()); // Already running
         }
 
-        // Get the project root (parent of desktop/)
-        let project_root = std::env::current_dir()
-            .map_err(|e| JuliaError::StartError(e.to_string()))?
-            .parent()
-            .map(|p| p.to_path_buf())
-            .unwrap_or_else(|| std::env::current_dir().unwrap());
+        let project_root = project_root().map_err(|e| JuliaError::StartError(e.to_string()))?;
 
         println!("Starting Julia server from: {:?}", project_root);
 
         // Start Julia server
-        let child = Command::new("julia")
+        let mut child = Command::new("julia")
             .args([
                 "--project=.",
                 "-e",
@@ -55,33 +224,39 @@ pub async fn start_julia_server(app: &AppHandle) -> Result<(), JuliaError> {
             .spawn()
             .map_err(|e| JuliaError::StartError(e.to_string()))?;
 
+        // Read both pipes so Julia never blocks on a full one; Pkg reports on stderr
+        let tracker = Arc::new(Mutex::new(PrecompileTracker::new(manifest_packages(
+            &project_root,
+        ))));
+        if let Some(stdout) = child.stdout.take() {
+            spawn_log_reader(app.clone(), stdout, false, tracker.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            spawn_log_reader(app.clone(), stderr, true, tracker.clone());
+        }
+
         let pid = child.id();
         *process_guard = Some(child);
-        pid
+        (pid, tracker)
     }; // MutexGuard released here
 
     // Update app state (separate lock scope)
-    if let Some(state) = app.try_state::<Mutex<crate::state::AppState>>() {
-        let mut state = state.lock().unwrap();
-        state.julia_running = true;
-        state.julia_pid = Some(pid);
-    }
+    set_running(app, true, Some(pid));
 
-    // Wait for server to be ready (no lock held)
-    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-
-    // Check if server is responding
-    let client = reqwest::Client::new();
-    for _ in 0..30 {
-        match client.get("http://localhost:8081/health").send().await {
-            Ok(response) if response.status().is_success() => {
-                println!("Julia server is ready");
-                return Ok(());
-            }
-            _ => {
-                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-            }
+    // Wait for server to be ready (no lock held); a first launch may precompile for minutes
+    let client = shared_client(app);
+    let mut attempts = 0;
+    loop {
+        if check_health(&client, &base_url).await.is_ok() {
+            println!("Julia server is ready");
+            emit_ready(app, &mut tracker.lock().unwrap());
+            return Ok(());
+        }
+        attempts += 1;
+        if attempts >= LOCAL_HEALTH_ATTEMPTS && !tracker.lock().unwrap().precompiling() {
+            break;
         }
+        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
     }
 
     Err(JuliaError::ConnectionError("Server did not respond within timeout".to_string()))
@@ -95,11 +270,7 @@ pub async fn stop_julia_server(app: &AppHandle) -> Result<(), JuliaError> {
     }
 
     // Update app state
-    if let Some(state) = app.try_state::<Mutex<crate::state::AppState>>() {
-        let mut state = state.lock().unwrap();
-        state.julia_running = false;
-        state.julia_pid = None;
-    }
+    set_running(app, false, None);
 
     Ok(())
 }
@@ -108,3 +279,18 @@ pub fn is_julia_running() -> bool {
     let process_guard = JULIA_PROCESS.lock().unwrap();
     process_guard.is_some()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loopback_hosts_are_local() {
+        assert!(is_local_url("http://localhost:8081"));
+        assert!(is_local_url("http://127.0.0.1:8081"));
+        assert!(is_local_url("http://[::1]:8081"));
+        assert!(!is_local_url("http://julia.lab.internal:8081"));
+        assert!(!is_local_url("http://10.0.0.5:8081"));
+        assert!(!is_local_url("not a url"));
+    }
+}
@@ -0,0 +1,200 @@
+// Julia environment check - is Julia on PATH and is the project instantiated, before starting the server
+
+use serde::Serialize;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+/// Loading Pkg and resolving a large project can take a while on a cold machine
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Prints the Julia version on the first line, then the project status
+const CHECK_SCRIPT: &str = "println(VERSION); using Pkg; Pkg.status()";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EnvReport {
+    pub julia_found: bool,
+    pub julia_version: Option<String>,
+    /// Dependencies in Project.toml that are not installed
+    pub missing_packages: Vec<String>,
+    pub project_instantiated: bool,
+    /// What to do next, when something is wrong
+    pub guidance: Option<String>,
+}
+
+impl EnvReport {
+    fn julia_not_found() -> Self {
+        Self {
+            julia_found: false,
+            julia_version: None,
+            missing_packages: Vec::new(),
+            project_instantiated: false,
+            guidance: Some(
+                "Julia was not found on PATH; install it from https://julialang.org/downloads/ \
+                 and restart the app"
+                    .to_string(),
+            ),
+        }
+    }
+}
+
+/// Names listed under `[deps]` in a Project.toml
+pub fn required_packages(project_toml: &str) -> Vec<String> {
+    let mut in_deps = false;
+    let mut names = Vec::new();
+    for line in project_toml.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_deps = line == "[deps]";
+        } else if in_deps {
+            if let Some((name, _)) = line.split_once('=') {
+                names.push(name.trim().trim_matches('"').to_string());
+            }
+        }
+    }
+    names
+}
+
+/// Build the report from the output of `CHECK_SCRIPT`.
+///
+/// A package counts as installed when `Pkg.status()` lists it with a version and
+/// without the `→` "not downloaded" marker.
+pub fn parse_report(stdout: &str, required: &[String]) -> EnvReport {
+    let mut lines = stdout.lines();
+    let julia_version = lines
+        .next()
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string);
+
+    let installed: Vec<&str> = lines
+        .filter_map(|line| {
+            let (marker, entry) = line.split_once('[')?;
+            let (_uuid, rest) = entry.split_once(']')?;
+            let mut fields = rest.split_whitespace();
+            let name = fields.next()?;
+            let has_version = fields.next().is_some_and(|v| v.starts_with('v'));
+            (has_version && !marker.contains('→')).then_some(name)
+        })
+        .collect();
+
+    let missing_packages: Vec<String> = required
+        .iter()
+        .filter(|name| !installed.contains(&name.as_str()))
+        .cloned()
+        .collect();
+    let project_instantiated = missing_packages.is_empty();
+    let guidance = (!project_instantiated).then(|| {
+        format!(
+            "{} package(s) are not installed; run `julia --project=. -e 'using Pkg; Pkg.instantiate()'` \
+             in the project folder",
+            missing_packages.len()
+        )
+    });
+
+    EnvReport {
+        julia_found: true,
+        julia_version,
+        missing_packages,
+        project_instantiated,
+        guidance,
+    }
+}
+
+/// Run `CHECK_SCRIPT` in `project_root` and report on the environment.
+///
+/// `Err` only for failures other than Julia being absent: a timeout, or Julia
+/// exiting with an error.
+pub async fn check(project_root: &Path) -> Result<EnvReport, String> {
+    let project_toml = std::fs::read_to_string(project_root.join("Project.toml")).map_err(|e| {
+        format!(
+            "cannot read Project.toml in {}: {}",
+            project_root.display(),
+            e
+        )
+    })?;
+    let required = required_packages(&project_toml);
+
+    let child = tokio::process::Command::new("julia")
+        .args(["--project=.", "--startup-file=no", "-e", CHECK_SCRIPT])
+        .current_dir(project_root)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn();
+    let child = match child {
+        Ok(child) => child,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(EnvReport::julia_not_found())
+        }
+        Err(e) => return Err(format!("failed to run julia: {}", e)),
+    };
+
+    let output = tokio::time::timeout(CHECK_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| {
+            format!(
+                "julia did not finish checking the environment within {}s",
+                CHECK_TIMEOUT.as_secs()
+            )
+        })?
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "julia exited with {}: {}",
+            output.status,
+            stderr.trim()
+        ));
+    }
+    Ok(parse_report(
+        &String::from_utf8_lossy(&output.stdout),
+        &required,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROJECT: &str = r#"
+name = "DarwinScaffoldStudio"
+
+[deps]
+HTTP = "cd3eb016-35fb-5094-929b-558a96fad6f3"
+NIfTI = "a3a9e032-41b5-5fc4-967a-a6b7a19844d3"
+Oxygen = "df9a0d86-3283-4920-82dc-4555fc0d1d8b"
+
+[compat]
+julia = "1.9"
+"#;
+
+    #[test]
+    fn reads_deps_but_not_compat() {
+        assert_eq!(required_packages(PROJECT), ["HTTP", "NIfTI", "Oxygen"]);
+    }
+
+    #[test]
+    fn undownloaded_and_unresolved_packages_are_missing() {
+        let stdout = "1.10.4\n\
+            Project DarwinScaffoldStudio v3.4.0\n\
+            Status `~/darwin/Project.toml`\n  \
+            [cd3eb016] HTTP v1.10.8\n\
+            → [a3a9e032] NIfTI v0.6.0\n  \
+            [df9a0d86] Oxygen\n\
+            Info Packages marked with → are not downloaded, use `instantiate` to download\n";
+        let report = parse_report(stdout, &required_packages(PROJECT));
+        assert!(report.julia_found);
+        assert_eq!(report.julia_version.as_deref(), Some("1.10.4"));
+        assert_eq!(report.missing_packages, ["NIfTI", "Oxygen"]);
+        assert!(!report.project_instantiated);
+        assert!(report.guidance.unwrap().contains("Pkg.instantiate()"));
+    }
+
+    #[test]
+    fn fully_installed_project_is_instantiated() {
+        let stdout = "1.10.4\nStatus `Project.toml`\n  [cd3eb016] HTTP v1.10.8\n⌃ [a3a9e032] NIfTI v0.6.0\n  [df9a0d86] Oxygen v1.5.0\n";
+        let report = parse_report(stdout, &required_packages(PROJECT));
+        assert!(report.project_instantiated);
+        assert_eq!(report.guidance, None);
+    }
+}
@@ -0,0 +1,206 @@
+// Julia startup progress - precompilation reported from the server's console output
+
+use serde::Serialize;
+
+/// Tauri event carrying a `StartupProgress`
+pub const STARTUP_PROGRESS_EVENT: &str = "julia-startup-progress";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StartupPhase {
+    Precompiling,
+    /// The server answers; sent right away when nothing needed precompiling
+    Ready,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StartupProgress {
+    pub phase: StartupPhase,
+    /// Package being compiled, or the one that just finished
+    pub package: Option<String>,
+    pub done: u32,
+    pub total: Option<u32>,
+    /// `done / total`, held below 100 until Julia says precompilation finished
+    pub percent: Option<u8>,
+}
+
+/// Follows Pkg's precompilation output line by line.
+///
+/// `total` starts as the number of packages in Manifest.toml, an upper bound since
+/// some are already compiled, and is replaced by Pkg's own `done/total` counter
+/// when it prints one.
+#[derive(Debug, Default)]
+pub struct PrecompileTracker {
+    done: u32,
+    total: Option<u32>,
+    package: Option<String>,
+    started: bool,
+    finished: bool,
+    ready_sent: bool,
+}
+
+impl PrecompileTracker {
+    pub fn new(total: Option<u32>) -> Self {
+        Self {
+            total: total.filter(|&t| t > 0),
+            ..Default::default()
+        }
+    }
+
+    /// Precompilation has started and not finished, so startup may take minutes
+    pub fn precompiling(&self) -> bool {
+        self.started && !self.finished
+    }
+
+    /// Progress to report for `line`, if it is precompilation output
+    pub fn observe(&mut self, line: &str) -> Option<StartupProgress> {
+        let line = strip_ansi(line);
+        let line = line.trim();
+        if line.contains("successfully precompiled") || line.contains("already precompiled") {
+            self.started = true;
+            self.finished = true;
+            self.total = Some(self.total.unwrap_or(0).max(self.done));
+        } else if let Some(rest) = line.strip_prefix("✓").or_else(|| line.strip_prefix("✗")) {
+            self.started = true;
+            self.done += 1;
+            self.package = Some(rest.trim().to_string());
+        } else if let Some((done, total)) = progress_counter(line) {
+            self.started = true;
+            self.done = done;
+            self.total = Some(total);
+        } else if let Some(rest) = line
+            .strip_prefix("[ Info: Precompiling")
+            .or_else(|| line.strip_prefix("Precompiling"))
+        {
+            self.started = true;
+            // "Precompiling project..." names no package; "Precompiling CSV [336ed68f-...]" does
+            let name = rest.split_whitespace().next().unwrap_or("");
+            let name = name.trim_end_matches('.');
+            if !name.is_empty() && !matches!(name, "project" | "packages") {
+                self.package = Some(name.to_string());
+            }
+        } else {
+            return None;
+        }
+        Some(self.progress(StartupPhase::Precompiling))
+    }
+
+    /// The `Ready` event, once; `None` if it was already sent
+    pub fn ready(&mut self) -> Option<StartupProgress> {
+        if self.ready_sent {
+            return None;
+        }
+        self.ready_sent = true;
+        self.finished = true;
+        Some(self.progress(StartupPhase::Ready))
+    }
+
+    fn progress(&self, phase: StartupPhase) -> StartupProgress {
+        let percent = match (phase, self.finished, self.total) {
+            (StartupPhase::Ready, _, _) | (_, true, _) => Some(100),
+            (_, false, Some(total)) => Some((self.done * 100 / total).min(99) as u8),
+            (_, false, None) => None,
+        };
+        StartupProgress {
+            phase,
+            package: self.package.clone(),
+            done: self.done,
+            total: self.total,
+            percent,
+        }
+    }
+}
+
+/// Packages in a Manifest.toml (`[[deps.Name]]` entries, or `[[Name]]` in the old format)
+pub fn manifest_package_count(manifest_toml: &str) -> u32 {
+    manifest_toml
+        .lines()
+        .filter(|line| line.trim_start().starts_with("[["))
+        .count() as u32
+}
+
+/// Pkg's progress bar ends in a `done/total` counter, e.g. `[=====>    ]  12/85`
+fn progress_counter(line: &str) -> Option<(u32, u32)> {
+    if !line.starts_with('[') {
+        return None;
+    }
+    let (done, total) = line.split_whitespace().last()?.split_once('/')?;
+    let (done, total) = (done.parse().ok()?, total.parse().ok()?);
+    (total > 0 && done <= total).then_some((done, total))
+}
+
+/// Drop terminal color codes (`ESC [ ... letter`) Julia adds when it thinks it has a tty
+fn strip_ansi(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pkg_output_becomes_progress() {
+        let mut tracker = PrecompileTracker::new(Some(4));
+        assert_eq!(tracker.observe("Julia server starting"), None);
+
+        let start = tracker.observe("Precompiling project...").unwrap();
+        assert_eq!(start.phase, StartupPhase::Precompiling);
+        assert_eq!((start.package, start.percent), (None, Some(0)));
+        assert!(tracker.precompiling());
+
+        let first = tracker.observe("  \u{1b}[32m✓ \u{1b}[39mCSV").unwrap();
+        assert_eq!(first.package.as_deref(), Some("CSV"));
+        assert_eq!((first.done, first.percent), (1, Some(25)));
+
+        let loading = tracker
+            .observe("[ Info: Precompiling Oxygen [df9a0d86-3283-4920-82dc-4555fc0d1d8b]")
+            .unwrap();
+        assert_eq!(loading.package.as_deref(), Some("Oxygen"));
+
+        // Pkg's own counter replaces the Manifest.toml estimate
+        let bar = tracker.observe("  [======>          ]  3/10").unwrap();
+        assert_eq!((bar.done, bar.total, bar.percent), (3, Some(10), Some(30)));
+
+        let done = tracker
+            .observe(
+                "  3 dependencies successfully precompiled in 95 seconds. 7 already precompiled.",
+            )
+            .unwrap();
+        assert_eq!(done.percent, Some(100));
+        assert!(!tracker.precompiling());
+
+        assert_eq!(tracker.ready().unwrap().phase, StartupPhase::Ready);
+        assert_eq!(tracker.ready(), None);
+    }
+
+    #[test]
+    fn already_compiled_projects_are_ready_at_once() {
+        let mut tracker = PrecompileTracker::new(None);
+        assert_eq!(tracker.observe("Julia server started on port 8081"), None);
+        let ready = tracker.ready().unwrap();
+        assert_eq!(ready.phase, StartupPhase::Ready);
+        assert_eq!((ready.done, ready.percent), (0, Some(100)));
+        assert!(!tracker.precompiling());
+    }
+
+    #[test]
+    fn manifest_packages_are_counted_in_both_formats() {
+        let v2 = "julia_version = \"1.10.0\"\nmanifest_format = \"2.0\"\n\n[[deps.CSV]]\ndeps = [\"Dates\"]\n\n[[deps.Dates]]\nuuid = \"ade2ca70\"\n";
+        assert_eq!(manifest_package_count(v2), 2);
+        let v1 = "[[CSV]]\nuuid = \"336ed68f\"\n\n[[HTTP]]\n[[Oxygen]]\n";
+        assert_eq!(manifest_package_count(v1), 3);
+    }
+}
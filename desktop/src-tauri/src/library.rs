@@ -0,0 +1,348 @@
+// Scaffold library - tagged scaffolds with their material, tissue and metrics, persisted as library.json
+
+use crate::commands::ScaffoldMetrics;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub const LIBRARY_FILE: &str = "library.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryEntry {
+    pub name: String,
+    /// Lowercase, sorted, without duplicates
+    pub tags: Vec<String>,
+    pub material: Option<String>,
+    pub tissue: Option<String>,
+    /// Unix time in milliseconds
+    pub created: u64,
+    pub metrics_summary: Option<ScaffoldMetrics>,
+    /// Volume the scaffold was analyzed from, needed to re-analyze it
+    #[serde(default)]
+    pub file_path: Option<String>,
+    #[serde(default)]
+    pub voxel_size_um: Option<f64>,
+}
+
+/// Search filters; every given one must match. `tags` match when the entry has
+/// at least one of them, and results with more matching tags rank higher.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScaffoldQuery {
+    pub tags: Vec<String>,
+    /// Case-insensitive substring of the name
+    pub text: Option<String>,
+    pub material: Option<String>,
+    pub tissue: Option<String>,
+    pub porosity_min: Option<f64>,
+    pub porosity_max: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LibraryHit {
+    pub file_id: String,
+    pub score: u32,
+    #[serde(flatten)]
+    pub entry: LibraryEntry,
+}
+
+/// `file_id -> entry`, loaded from `library.json` the first time it is used
+#[derive(Debug, Default)]
+pub struct ScaffoldLibrary {
+    entries: Option<BTreeMap<String, LibraryEntry>>,
+}
+
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut tags: Vec<String> = tags
+        .iter()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+impl ScaffoldQuery {
+    /// `None` when `entry` is filtered out, else how well it matches
+    fn score(&self, entry: &LibraryEntry) -> Option<u32> {
+        let same = |wanted: &Option<String>, actual: &Option<String>| match wanted {
+            None => true,
+            Some(wanted) => actual
+                .as_deref()
+                .is_some_and(|a| a.eq_ignore_ascii_case(wanted.trim())),
+        };
+        if !same(&self.material, &entry.material) || !same(&self.tissue, &entry.tissue) {
+            return None;
+        }
+
+        if self.porosity_min.is_some() || self.porosity_max.is_some() {
+            let porosity = entry.metrics_summary.as_ref()?.porosity;
+            if self.porosity_min.is_some_and(|min| porosity < min)
+                || self.porosity_max.is_some_and(|max| porosity > max)
+            {
+                return None;
+            }
+        }
+
+        let mut score = 0;
+        if let Some(text) = self
+            .text
+            .as_deref()
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+        {
+            if !entry.name.to_lowercase().contains(&text.to_lowercase()) {
+                return None;
+            }
+            score += 1;
+        }
+        let wanted = normalize_tags(&self.tags);
+        if !wanted.is_empty() {
+            let matched = wanted.iter().filter(|t| entry.tags.contains(t)).count() as u32;
+            if matched == 0 {
+                return None;
+            }
+            score += matched;
+        }
+        Some(score)
+    }
+}
+
+impl ScaffoldLibrary {
+    fn load(&mut self, path: &Path) -> Result<&mut BTreeMap<String, LibraryEntry>, String> {
+        if self.entries.is_none() {
+            let entries = match std::fs::read_to_string(path) {
+                Ok(text) => serde_json::from_str(&text)
+                    .map_err(|e| format!("{} is corrupt: {}", path.display(), e))?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+                Err(e) => return Err(e.to_string()),
+            };
+            self.entries = Some(entries);
+        }
+        Ok(self.entries.as_mut().unwrap())
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_string_pretty(&self.entries).map_err(|e| e.to_string())?;
+        std::fs::write(path, json).map_err(|e| e.to_string())
+    }
+
+    /// Add or replace the entry for `file_id`
+    pub fn add(
+        &mut self,
+        path: &Path,
+        file_id: &str,
+        mut entry: LibraryEntry,
+    ) -> Result<LibraryEntry, String> {
+        if file_id.trim().is_empty() {
+            return Err("file_id must not be empty".to_string());
+        }
+        if entry.name.trim().is_empty() {
+            return Err("scaffold name must not be empty".to_string());
+        }
+        entry.tags = normalize_tags(&entry.tags);
+        self.load(path)?.insert(file_id.to_string(), entry.clone());
+        self.save(path)?;
+        Ok(entry)
+    }
+
+    pub fn get(&mut self, path: &Path, file_id: &str) -> Result<Option<LibraryEntry>, String> {
+        Ok(self.load(path)?.get(file_id).cloned())
+    }
+
+    /// Replace the tags of a scaffold already in the library
+    pub fn tag(
+        &mut self,
+        path: &Path,
+        file_id: &str,
+        tags: &[String],
+    ) -> Result<LibraryEntry, String> {
+        let entry = self
+            .load(path)?
+            .get_mut(file_id)
+            .ok_or_else(|| format!("scaffold {} is not in the library", file_id))?;
+        entry.tags = normalize_tags(tags);
+        let entry = entry.clone();
+        self.save(path)?;
+        Ok(entry)
+    }
+
+    /// `false` if `file_id` was not in the library
+    pub fn remove(&mut self, path: &Path, file_id: &str) -> Result<bool, String> {
+        let removed = self.load(path)?.remove(file_id).is_some();
+        if removed {
+            self.save(path)?;
+        }
+        Ok(removed)
+    }
+
+    /// Matching entries, best first; ties go to the newest
+    pub fn search(
+        &mut self,
+        path: &Path,
+        query: &ScaffoldQuery,
+    ) -> Result<Vec<LibraryHit>, String> {
+        let mut hits: Vec<LibraryHit> = self
+            .load(path)?
+            .iter()
+            .filter_map(|(file_id, entry)| {
+                query.score(entry).map(|score| LibraryHit {
+                    file_id: file_id.clone(),
+                    score,
+                    entry: entry.clone(),
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then(b.entry.created.cmp(&a.entry.created))
+                .then(a.file_id.cmp(&b.file_id))
+        });
+        Ok(hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(porosity: f64) -> ScaffoldMetrics {
+        ScaffoldMetrics {
+            porosity,
+            mean_pore_size_um: 250.0,
+            interconnectivity: 0.95,
+            tortuosity: 1.2,
+            specific_surface_area: 10.0,
+            elastic_modulus: 100.0,
+            yield_strength: 5.0,
+            permeability: 1e-9,
+        }
+    }
+
+    fn entry(
+        name: &str,
+        tags: &[&str],
+        material: &str,
+        porosity: Option<f64>,
+        created: u64,
+    ) -> LibraryEntry {
+        LibraryEntry {
+            name: name.to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            material: Some(material.to_string()),
+            tissue: Some("bone".to_string()),
+            created,
+            metrics_summary: porosity.map(metrics),
+            file_path: None,
+            voxel_size_um: None,
+        }
+    }
+
+    fn library() -> (ScaffoldLibrary, std::path::PathBuf) {
+        let path = std::env::temp_dir()
+            .join(format!("library-test-{}", uuid::Uuid::new_v4()))
+            .join(LIBRARY_FILE);
+        let mut library = ScaffoldLibrary::default();
+        let entries = [
+            (
+                "gyroid",
+                entry("Gyroid 80%", &["TPMS", "bone", "bone"], "PCL", Some(0.8), 1),
+            ),
+            (
+                "diamond",
+                entry("Diamond 65%", &["tpms"], "PLA", Some(0.65), 2),
+            ),
+            (
+                "scan",
+                entry("Femur scan", &["microct", "bone"], "PCL", None, 3),
+            ),
+        ];
+        for (file_id, entry) in entries {
+            library.add(&path, file_id, entry).unwrap();
+        }
+        (library, path)
+    }
+
+    fn ids(hits: Vec<LibraryHit>) -> Vec<String> {
+        hits.into_iter().map(|h| h.file_id).collect()
+    }
+
+    #[test]
+    fn tags_are_normalized_and_rank_results() {
+        let (mut library, path) = library();
+        let all = library.search(&path, &ScaffoldQuery::default()).unwrap();
+        assert_eq!(ids(all.clone()), ["scan", "diamond", "gyroid"]);
+        assert_eq!(all[2].entry.tags, ["bone", "tpms"]);
+
+        let query = ScaffoldQuery {
+            tags: vec!["Bone".to_string(), "tpms".to_string()],
+            ..Default::default()
+        };
+        let hits = library.search(&path, &query).unwrap();
+        assert_eq!(ids(hits.clone()), ["gyroid", "scan", "diamond"]);
+        assert_eq!(hits[0].score, 2);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn filters_combine() {
+        let (mut library, path) = library();
+        let query = ScaffoldQuery {
+            material: Some("pcl".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(library.search(&path, &query).unwrap()),
+            ["scan", "gyroid"]
+        );
+
+        // Entries without metrics never match a porosity range
+        let query = ScaffoldQuery {
+            material: Some("PCL".to_string()),
+            porosity_min: Some(0.7),
+            ..Default::default()
+        };
+        assert_eq!(ids(library.search(&path, &query).unwrap()), ["gyroid"]);
+
+        let query = ScaffoldQuery {
+            tags: vec!["tpms".to_string()],
+            porosity_max: Some(0.7),
+            tissue: Some("bone".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(library.search(&path, &query).unwrap()), ["diamond"]);
+
+        let query = ScaffoldQuery {
+            text: Some("SCAN".to_string()),
+            tags: vec!["tpms".to_string()],
+            ..Default::default()
+        };
+        assert!(library.search(&path, &query).unwrap().is_empty());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn tagging_and_removal_persist() {
+        let (mut library, path) = library();
+        library
+            .tag(&path, "diamond", &["Cartilage".to_string()])
+            .unwrap();
+        assert!(library.remove(&path, "scan").unwrap());
+        assert!(!library.remove(&path, "scan").unwrap());
+        assert_eq!(
+            library.tag(&path, "scan", &[]).unwrap_err(),
+            "scaffold scan is not in the library"
+        );
+
+        let mut reloaded = ScaffoldLibrary::default();
+        let hits = reloaded.search(&path, &ScaffoldQuery::default()).unwrap();
+        assert_eq!(ids(hits.clone()), ["diamond", "gyroid"]);
+        assert_eq!(hits[0].entry.tags, ["cartilage"]);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
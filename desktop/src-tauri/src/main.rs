@@ -6,9 +6,46 @@
     windows_subsystem = "windows"
 )]
 
+mod analysis_estimate;
+mod anisotropy;
+mod backend_info;
+mod cache;
+mod chat_context;
 mod commands;
+mod comparison;
+mod depth_profile;
+mod export_stream;
+mod history;
+mod http_client;
+mod job_queue;
 mod julia_bridge;
+mod julia_env;
+mod julia_startup;
+mod library;
+mod materials;
+mod merge;
+mod mesh_diff;
+mod metric_selection;
+mod metrics_export;
+#[path = "../../../darwin-server/src/pagination.rs"]
+mod pagination;
+mod param_history;
+mod param_sweep;
+mod print_estimate;
+mod printability;
+mod printer_profiles;
+mod project;
+mod report;
+mod roi;
+mod scaffold_info;
+mod scheduled_jobs;
+mod snapshots;
 mod state;
+mod stiffness;
+mod stl_writer;
+mod surface_types;
+mod thumbnails;
+mod units;
 
 use state::AppState;
 use std::sync::Mutex;
@@ -23,6 +60,10 @@ fn main() {
             // Set window title with version
             window.set_title("Darwin Scaffold Studio v1.0.0").unwrap();
 
+            if let Err(e) = commands::resume_scheduled_jobs(&app.handle()) {
+                eprintln!("Failed to resume scheduled jobs: {}", e);
+            }
+
             // Start Julia server in background
             let app_handle = app.handle();
             tauri::async_runtime::spawn(async move {
@@ -35,17 +76,68 @@ fn main() {
         })
         .invoke_handler(tauri::generate_handler![
             commands::get_julia_status,
+            commands::ping_julia,
+            commands::get_backend_info,
+            commands::list_surface_types,
             commands::start_julia_server,
+            commands::check_julia_environment,
             commands::stop_julia_server,
+            commands::test_julia_connection,
             commands::open_file_dialog,
             commands::save_file_dialog,
+            commands::open_in_system,
+            commands::get_scaffold_info,
             commands::analyze_scaffold,
+            commands::estimate_analysis_time,
             commands::generate_tpms,
+            commands::generate_tpms_sweep,
+            commands::estimate_tpms,
+            commands::create_workspace,
+            commands::set_workspace_params,
+            commands::undo_workspace,
+            commands::redo_workspace,
             commands::get_metrics,
+            commands::record_metrics,
+            commands::get_metrics_history,
+            commands::export_metrics_csv,
+            commands::generate_thumbnail,
+            commands::generate_report,
+            commands::get_depth_profile,
+            commands::get_anisotropy,
+            commands::compare_metrics,
+            commands::validate_mesh,
+            commands::check_printability,
+            commands::estimate_print,
+            commands::simplify_mesh,
+            commands::compute_stiffness_tensor,
             commands::export_stl,
+            commands::export_stl_local,
+            commands::compare_meshes,
             commands::chat_with_agent,
+            commands::list_materials,
+            commands::get_material,
+            commands::add_material,
+            commands::add_to_library,
+            commands::tag_scaffold,
+            commands::search_scaffolds,
+            commands::remove_from_library,
+            commands::merge_scaffolds,
+            commands::schedule_reprocess,
+            commands::list_scheduled_jobs,
+            commands::cancel_scheduled_job,
+            commands::snapshot_workspace,
+            commands::restore_workspace,
+            commands::list_snapshots,
+            commands::list_printer_profiles,
+            commands::add_printer_profile,
+            commands::delete_printer_profile,
             commands::get_app_settings,
             commands::set_app_settings,
+            commands::get_job_queue_status,
+            commands::cancel_job,
+            commands::save_project,
+            commands::load_project,
+            commands::get_build_info,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
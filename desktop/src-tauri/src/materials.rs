@@ -0,0 +1,111 @@
+// Materials database - bundled mechanical properties plus user-defined materials
+
+use crate::cache::OnceCache;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+const BUNDLED_MATERIALS: &str = include_str!("../resources/materials.json");
+const USER_MATERIALS_FILE: &str = "materials.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Material {
+    pub name: String,
+    pub youngs_modulus_mpa: f64,
+    pub density_g_cm3: f64,
+    /// `None` for materials that do not meaningfully degrade in vivo
+    pub degradation_months: Option<f64>,
+    pub biocompatibility_class: String,
+}
+
+impl Material {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("material name must not be empty".to_string());
+        }
+        if !self.youngs_modulus_mpa.is_finite() || self.youngs_modulus_mpa <= 0.0 {
+            return Err(format!("{}: Young's modulus must be positive", self.name));
+        }
+        if !self.density_g_cm3.is_finite() || self.density_g_cm3 <= 0.0 {
+            return Err(format!("{}: density must be positive", self.name));
+        }
+        if matches!(self.degradation_months, Some(m) if m < 0.0) {
+            return Err(format!("{}: degradation time must not be negative", self.name));
+        }
+        Ok(())
+    }
+}
+
+pub fn bundled_materials() -> Vec<Material> {
+    serde_json::from_str(BUNDLED_MATERIALS).expect("bundled materials.json is valid")
+}
+
+fn user_materials_path(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path_resolver()
+        .app_config_dir()
+        .map(|dir| dir.join(USER_MATERIALS_FILE))
+        .ok_or_else(|| "could not resolve the app config directory".to_string())
+}
+
+pub fn load_user_materials(app: &AppHandle) -> Vec<Material> {
+    let Ok(path) = user_materials_path(app) else {
+        return Vec::new();
+    };
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+/// Bundled materials followed by user-defined ones; a user material with the
+/// same name replaces the bundled entry.
+pub fn all_materials(app: &AppHandle) -> Vec<Material> {
+    let mut materials = bundled_materials();
+    for user in load_user_materials(app) {
+        match materials
+            .iter_mut()
+            .find(|m| m.name.eq_ignore_ascii_case(&user.name))
+        {
+            Some(existing) => *existing = user,
+            None => materials.push(user),
+        }
+    }
+    materials
+}
+
+/// [`all_materials`], read once and shared until invalidated after a user material is added
+pub type MaterialsCache = OnceCache<(), Vec<Material>>;
+
+pub async fn cached_materials(app: &AppHandle, cache: &MaterialsCache) -> Vec<Material> {
+    cache
+        .get_or_try_init((), || async { Ok::<_, Infallible>(all_materials(app)) })
+        .await
+        .unwrap_or_else(|never| match never {})
+}
+
+pub async fn find_cached_material(
+    app: &AppHandle,
+    cache: &MaterialsCache,
+    name: &str,
+) -> Option<Material> {
+    cached_materials(app, cache)
+        .await
+        .into_iter()
+        .find(|m| m.name.eq_ignore_ascii_case(name))
+}
+
+pub fn add_user_material(app: &AppHandle, material: Material) -> Result<(), String> {
+    material.validate()?;
+
+    let mut materials = load_user_materials(app);
+    materials.retain(|m| !m.name.eq_ignore_ascii_case(&material.name));
+    materials.push(material);
+
+    let path = user_materials_path(app)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let json = serde_json::to_string_pretty(&materials).map_err(|e| e.to_string())?;
+    std::fs::write(path, json).map_err(|e| e.to_string())
+}
@@ -0,0 +1,276 @@
+// Scaffold merging - stitch library scaffolds into one composite, e.g. a porosity gradient
+
+use crate::scaffold_info::ScaffoldInfo;
+use serde::{Deserialize, Serialize};
+
+/// Voxel sizes of merged scaffolds may differ by this fraction; Julia merges them on one grid
+const VOXEL_SIZE_TOLERANCE: f64 = 0.01;
+
+/// One scaffold placed in the composite. `transform` is `[tx, ty, tz, rx, ry, rz]`:
+/// a translation in mm, then rotations in degrees about x, y and z (applied in
+/// that order, around the scaffold's origin corner).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MergeInput {
+    pub file_id: String,
+    pub transform: [f64; 6],
+}
+
+/// What Julia gets for each input
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MergePart {
+    pub file_id: String,
+    pub file_path: String,
+    pub transform: [f64; 6],
+    pub voxel_size_mm: [f64; 3],
+    pub bounding_box_mm: [f64; 3],
+}
+
+impl MergePart {
+    /// `input` with the volume size its file header reports
+    pub fn new(input: &MergeInput, file_path: String, info: &ScaffoldInfo) -> Result<Self, String> {
+        match (info.voxel_size_mm, info.bounding_box_mm) {
+            (Some(voxel_size_mm), Some(bounding_box_mm)) => Ok(Self {
+                file_id: input.file_id.clone(),
+                file_path,
+                transform: input.transform,
+                voxel_size_mm,
+                bounding_box_mm,
+            }),
+            _ => Err(format!(
+                "scaffold {}: the {} header does not report its size and voxel spacing",
+                input.file_id, info.format
+            )),
+        }
+    }
+
+    /// Axis-aligned box `[min, max]` the transformed scaffold occupies, in mm
+    fn placed_box(&self) -> [[f64; 3]; 2] {
+        let [tx, ty, tz, rx, ry, rz] = self.transform;
+        let (sx, cx) = rx.to_radians().sin_cos();
+        let (sy, cy) = ry.to_radians().sin_cos();
+        let (sz, cz) = rz.to_radians().sin_cos();
+        let place = |[x, y, z]: [f64; 3]| {
+            let (y, z) = (y * cx - z * sx, y * sx + z * cx);
+            let (x, z) = (x * cy + z * sy, -x * sy + z * cy);
+            let (x, y) = (x * cz - y * sz, x * sz + y * cz);
+            [x + tx, y + ty, z + tz]
+        };
+        let [bx, by, bz] = self.bounding_box_mm;
+        let mut min = [f64::INFINITY; 3];
+        let mut max = [f64::NEG_INFINITY; 3];
+        for corner in (0..8).map(|i| {
+            [
+                if i & 1 == 0 { 0.0 } else { bx },
+                if i & 2 == 0 { 0.0 } else { by },
+                if i & 4 == 0 { 0.0 } else { bz },
+            ]
+        }) {
+            let p = place(corner);
+            for axis in 0..3 {
+                min[axis] = min[axis].min(p[axis]);
+                max[axis] = max[axis].max(p[axis]);
+            }
+        }
+        [min, max]
+    }
+}
+
+/// Check the arguments of `merge_scaffolds` before touching the library
+pub fn validate_inputs(inputs: &[MergeInput], output_name: &str) -> Result<(), String> {
+    if output_name.trim().is_empty() {
+        return Err("output_name must not be empty".to_string());
+    }
+    if inputs.len() < 2 {
+        return Err(format!(
+            "merging needs at least 2 scaffolds, got {}",
+            inputs.len()
+        ));
+    }
+    if let Some(input) = inputs
+        .iter()
+        .find(|input| input.transform.iter().any(|v| !v.is_finite()))
+    {
+        return Err(format!(
+            "scaffold {}: transform must be 6 finite numbers",
+            input.file_id
+        ));
+    }
+    Ok(())
+}
+
+/// Scaffolds can be merged when they share a voxel size and every one touches or
+/// overlaps another once placed, so the composite is a single piece.
+pub fn check_compatible(parts: &[MergePart]) -> Result<(), String> {
+    let Some(first) = parts.first() else {
+        return Ok(());
+    };
+    for part in &parts[1..] {
+        let differs = (0..3).any(|axis| {
+            let (a, b) = (first.voxel_size_mm[axis], part.voxel_size_mm[axis]);
+            (a - b).abs() > VOXEL_SIZE_TOLERANCE * a.max(b)
+        });
+        if differs {
+            return Err(format!(
+                "scaffold {} has voxel size {:?} mm but {} has {:?} mm; merged scaffolds must share one",
+                part.file_id, part.voxel_size_mm, first.file_id, first.voxel_size_mm
+            ));
+        }
+    }
+
+    // Grow the piece connected to the first scaffold until nothing else touches it
+    let boxes: Vec<_> = parts.iter().map(MergePart::placed_box).collect();
+    let gap = first.voxel_size_mm.iter().copied().fold(0.0, f64::max);
+    let touches = |a: &[[f64; 3]; 2], b: &[[f64; 3]; 2]| {
+        (0..3).all(|axis| a[0][axis] <= b[1][axis] + gap && b[0][axis] <= a[1][axis] + gap)
+    };
+    let mut connected = vec![false; parts.len()];
+    connected[0] = true;
+    let mut grew = true;
+    while grew {
+        grew = false;
+        for i in 0..parts.len() {
+            if !connected[i]
+                && (0..parts.len()).any(|j| connected[j] && touches(&boxes[i], &boxes[j]))
+            {
+                connected[i] = true;
+                grew = true;
+            }
+        }
+    }
+    match connected.iter().position(|&c| !c) {
+        Some(i) => Err(format!(
+            "scaffold {} does not touch the others once transformed; move it next to them",
+            parts[i].file_id
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Julia's `/scaffold/merge` answer on success
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MergedScaffold {
+    pub file_path: String,
+}
+
+/// The merged scaffold from Julia's reply. A 409 carries the overlapping regions
+/// Julia could not reconcile; the error is then `{error, conflicts}` as JSON.
+pub fn from_reply(status: u16, body: serde_json::Value) -> Result<MergedScaffold, String> {
+    let error = body["error"].as_str().map(str::to_string);
+    if status == 409 {
+        return Err(serde_json::json!({
+            "error": error.unwrap_or_else(|| "merged scaffolds overlap".to_string()),
+            "conflicts": body["conflicts"],
+        })
+        .to_string());
+    }
+    match error {
+        Some(error) => Err(error),
+        None if !(200..300).contains(&status) => Err(format!("scaffold merge failed ({})", status)),
+        None => serde_json::from_value(body).map_err(|e| format!("invalid merge reply: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn part(file_id: &str, transform: [f64; 6], voxel_mm: f64) -> MergePart {
+        MergePart {
+            file_id: file_id.to_string(),
+            file_path: format!("/scans/{}.tif", file_id),
+            transform,
+            voxel_size_mm: [voxel_mm; 3],
+            bounding_box_mm: [5.0, 5.0, 2.0],
+        }
+    }
+
+    #[test]
+    fn inputs_are_validated() {
+        let input = |id: &str| MergeInput {
+            file_id: id.to_string(),
+            transform: [0.0; 6],
+        };
+        assert!(validate_inputs(&[input("a"), input("b")], "gradient").is_ok());
+        assert_eq!(
+            validate_inputs(&[input("a"), input("b")], " ").unwrap_err(),
+            "output_name must not be empty"
+        );
+        assert_eq!(
+            validate_inputs(&[input("a")], "gradient").unwrap_err(),
+            "merging needs at least 2 scaffolds, got 1"
+        );
+        let mut broken = input("b");
+        broken.transform[4] = f64::NAN;
+        assert_eq!(
+            validate_inputs(&[input("a"), broken], "gradient").unwrap_err(),
+            "scaffold b: transform must be 6 finite numbers"
+        );
+
+        let unknown = ScaffoldInfo {
+            format: "unknown".to_string(),
+            file_bytes: 10,
+            dimensions_voxels: None,
+            voxel_size_mm: None,
+            bounding_box_mm: None,
+        };
+        assert_eq!(
+            MergePart::new(&input("a"), "/scans/a.raw".to_string(), &unknown).unwrap_err(),
+            "scaffold a: the unknown header does not report its size and voxel spacing"
+        );
+    }
+
+    #[test]
+    fn stacked_layers_are_compatible_but_gaps_and_mixed_voxels_are_not() {
+        // Three 2 mm layers stacked along z: a porosity gradient
+        let stack = [
+            part("dense", [0.0, 0.0, 0.0, 0.0, 0.0, 0.0], 0.01),
+            part("medium", [0.0, 0.0, 2.0, 0.0, 0.0, 0.0], 0.01),
+            part("open", [0.0, 0.0, 4.0, 0.0, 0.0, 0.0], 0.01),
+        ];
+        assert!(check_compatible(&stack).is_ok());
+
+        // Rotated 90 degrees about x, the 5 x 5 x 2 layer spans z 0..5 next to "dense"
+        let rotated = part("side", [5.0, 2.0, 0.0, 90.0, 0.0, 0.0], 0.01);
+        let [min, max] = rotated.placed_box();
+        assert!((min[1] - 0.0).abs() < 1e-9 && (max[1] - 2.0).abs() < 1e-9);
+        assert!((max[2] - 5.0).abs() < 1e-9);
+        assert!(check_compatible(&[stack[0].clone(), rotated]).is_ok());
+
+        let far = part("far", [20.0, 0.0, 0.0, 0.0, 0.0, 0.0], 0.01);
+        assert_eq!(
+            check_compatible(&[stack[0].clone(), far]).unwrap_err(),
+            "scaffold far does not touch the others once transformed; move it next to them"
+        );
+        let coarse = part("coarse", [0.0, 0.0, 2.0, 0.0, 0.0, 0.0], 0.02);
+        assert!(check_compatible(&[stack[0].clone(), coarse])
+            .unwrap_err()
+            .starts_with("scaffold coarse has voxel size"));
+    }
+
+    #[test]
+    fn julia_conflicts_are_surfaced() {
+        let merged = from_reply(200, json!({"file_path": "/scans/gradient.tif"})).unwrap();
+        assert_eq!(merged.file_path, "/scans/gradient.tif");
+
+        let conflict = from_reply(
+            409,
+            json!({
+                "error": "inputs overlap with different solid fractions",
+                "conflicts": [{"file_ids": ["dense", "medium"], "overlap_mm3": 1.5}],
+            }),
+        )
+        .unwrap_err();
+        let conflict: serde_json::Value = serde_json::from_str(&conflict).unwrap();
+        assert_eq!(
+            conflict["error"],
+            "inputs overlap with different solid fractions"
+        );
+        assert_eq!(conflict["conflicts"][0]["overlap_mm3"], 1.5);
+
+        assert_eq!(
+            from_reply(500, json!({})).unwrap_err(),
+            "scaffold merge failed (500)"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+// Mesh diff - geometric comparison of two STL files via Julia's Hausdorff distance
+
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MeshDiff {
+    pub max_deviation_mm: f64,
+    pub mean_deviation_mm: f64,
+    /// `max_deviation_mm <= tolerance_mm`
+    pub within_tolerance: bool,
+    pub vertex_count_a: u64,
+    pub vertex_count_b: u64,
+}
+
+/// What Julia's `/mesh/compare` reports
+#[derive(Debug, Deserialize)]
+struct JuliaMeshDiff {
+    max_deviation_mm: f64,
+    mean_deviation_mm: f64,
+    vertex_count_a: u64,
+    vertex_count_b: u64,
+}
+
+/// Check that `path` is an existing `.stl` file with a binary or ASCII STL header
+pub fn validate_stl(path: &Path) -> Result<(), String> {
+    let is_stl = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("stl"));
+    if !is_stl {
+        return Err(format!("{} is not an .stl file", path.display()));
+    }
+
+    let mut file = std::fs::File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let size = file.metadata().map_err(|e| e.to_string())?.len();
+    let mut header = [0u8; 84];
+    let read = file.read(&mut header).map_err(|e| e.to_string())?;
+
+    let ascii = header[..read].trim_ascii_start().starts_with(b"solid");
+    let binary = read == 84 && {
+        let triangles = u32::from_le_bytes([header[80], header[81], header[82], header[83]]);
+        size == 84 + 50 * triangles as u64
+    };
+    if ascii || binary {
+        Ok(())
+    } else {
+        Err(format!("{} is not a valid STL file", path.display()))
+    }
+}
+
+/// Turn Julia's reply into a `MeshDiff`. Non-2xx replies (e.g. 422 for an empty
+/// mesh) become their `error` message.
+pub fn parse_response(status: u16, body: &str, tolerance_mm: f64) -> Result<MeshDiff, String> {
+    if !(200..300).contains(&status) {
+        let message = serde_json::from_str::<serde_json::Value>(body)
+            .ok()
+            .and_then(|v| v.get("error").and_then(|e| e.as_str()).map(str::to_string))
+            .unwrap_or_else(|| body.trim().to_string());
+        return Err(format!(
+            "meshes could not be compared (Julia {}): {}",
+            status, message
+        ));
+    }
+
+    let diff: JuliaMeshDiff =
+        serde_json::from_str(body).map_err(|e| format!("unexpected reply from Julia: {}", e))?;
+    Ok(MeshDiff {
+        max_deviation_mm: diff.max_deviation_mm,
+        mean_deviation_mm: diff.mean_deviation_mm,
+        within_tolerance: diff.max_deviation_mm <= tolerance_mm,
+        vertex_count_a: diff.vertex_count_a,
+        vertex_count_b: diff.vertex_count_b,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn julia_reply_is_judged_against_the_tolerance() {
+        let body = r#"{"max_deviation_mm": 0.04, "mean_deviation_mm": 0.01,
+                       "vertex_count_a": 1200, "vertex_count_b": 1180}"#;
+        let diff = parse_response(200, body, 0.05).unwrap();
+        assert!(diff.within_tolerance);
+        assert_eq!((diff.vertex_count_a, diff.vertex_count_b), (1200, 1180));
+        assert!(!parse_response(200, body, 0.01).unwrap().within_tolerance);
+    }
+
+    #[test]
+    fn julia_errors_are_surfaced() {
+        let err =
+            parse_response(422, r#"{"error": "mesh at path_b has no vertices"}"#, 0.1).unwrap_err();
+        assert!(
+            err.contains("422") && err.contains("path_b has no vertices"),
+            "{}",
+            err
+        );
+        assert!(parse_response(500, "Internal Server Error", 0.1)
+            .unwrap_err()
+            .contains("Internal Server Error"));
+        assert!(parse_response(200, r#"{"status": "ok"}"#, 0.1).is_err());
+    }
+
+    #[test]
+    fn only_stl_files_pass_validation() {
+        let dir = std::env::temp_dir().join(format!("darwin_mesh_diff_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let ascii = dir.join("part.stl");
+        std::fs::write(&ascii, "solid part\nendsolid part\n").unwrap();
+        assert!(validate_stl(&ascii).is_ok());
+
+        let mut binary = vec![0u8; 80];
+        binary.extend(1u32.to_le_bytes());
+        binary.extend([0u8; 50]);
+        let binary_path = dir.join("part_bin.STL");
+        std::fs::write(&binary_path, &binary).unwrap();
+        assert!(validate_stl(&binary_path).is_ok());
+
+        let truncated = dir.join("truncated.stl");
+        std::fs::write(&truncated, &binary[..100]).unwrap();
+        assert!(validate_stl(&truncated).is_err());
+
+        let obj = dir.join("part.obj");
+        std::fs::write(&obj, "solid").unwrap();
+        assert!(validate_stl(&obj).is_err());
+        assert!(validate_stl(&dir.join("missing.stl")).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+// Metric selection - compute only some ScaffoldMetrics fields in an analysis
+
+use crate::commands::ScaffoldMetrics;
+use serde_json::Value;
+
+/// Check `requested` against the ScaffoldMetrics field names; returns them in
+/// field order without duplicates
+pub fn validate(requested: &[String]) -> Result<Vec<&'static str>, String> {
+    if requested.is_empty() {
+        return Err("metrics must name at least one metric".to_string());
+    }
+    let unknown: Vec<&str> = requested
+        .iter()
+        .map(String::as_str)
+        .filter(|name| !ScaffoldMetrics::FIELD_NAMES.contains(name))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(format!(
+            "unknown metric(s): {} (expected any of {})",
+            unknown.join(", "),
+            ScaffoldMetrics::FIELD_NAMES.join(", ")
+        ));
+    }
+    Ok(ScaffoldMetrics::FIELD_NAMES
+        .into_iter()
+        .filter(|name| requested.iter().any(|r| r == name))
+        .collect())
+}
+
+/// Keep only the `selected` fields under `metrics` in an analysis result and list
+/// the other ScaffoldMetrics fields under `skipped_metrics`
+pub fn restrict(result: &mut Value, selected: &[&str]) {
+    let Some(obj) = result.as_object_mut() else {
+        return;
+    };
+    if let Some(metrics) = obj.get_mut("metrics").and_then(Value::as_object_mut) {
+        metrics.retain(|name, _| selected.contains(&name.as_str()));
+    }
+    let skipped: Vec<&str> = ScaffoldMetrics::FIELD_NAMES
+        .into_iter()
+        .filter(|name| !selected.contains(name))
+        .collect();
+    obj.insert("skipped_metrics".to_string(), skipped.into());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn known_names_come_back_in_field_order() {
+        let selected = validate(&names(&["mean_pore_size_um", "porosity", "porosity"])).unwrap();
+        assert_eq!(selected, ["porosity", "mean_pore_size_um"]);
+    }
+
+    #[test]
+    fn unknown_and_empty_selections_are_rejected() {
+        let error = validate(&names(&["porosity", "pore_size", "stiffness"])).unwrap_err();
+        assert!(error
+            .starts_with("unknown metric(s): pore_size, stiffness (expected any of porosity, "));
+        assert!(validate(&[]).is_err());
+    }
+
+    #[test]
+    fn partial_result_lists_what_was_skipped() {
+        let mut result = json!({
+            "metrics": {"porosity": 0.82, "mean_pore_size_um": 240.0, "ai_viability_score": 0.9},
+            "status": "success",
+        });
+        restrict(&mut result, &["porosity", "mean_pore_size_um"]);
+        assert_eq!(
+            result,
+            json!({
+                "metrics": {"porosity": 0.82, "mean_pore_size_um": 240.0},
+                "skipped_metrics": [
+                    "interconnectivity",
+                    "tortuosity",
+                    "specific_surface_area",
+                    "elastic_modulus",
+                    "yield_strength",
+                    "permeability",
+                ],
+                "status": "success",
+            })
+        );
+    }
+}
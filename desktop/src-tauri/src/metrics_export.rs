@@ -0,0 +1,98 @@
+// Metrics export - one CSV row of ScaffoldMetrics per workspace, for Excel/R
+
+use crate::commands::ScaffoldMetrics;
+use std::io::Write;
+
+/// One workspace's line in the export
+#[derive(Debug, Clone)]
+pub struct ExportRow {
+    pub workspace_id: String,
+    pub name: String,
+    /// `None` leaves the metric cells empty; `note` should say why
+    pub metrics: Option<ScaffoldMetrics>,
+    pub note: String,
+}
+
+fn header() -> Vec<&'static str> {
+    let mut header = vec!["workspace_id", "name"];
+    header.extend(ScaffoldMetrics::COLUMN_NAMES);
+    header.push("note");
+    header
+}
+
+/// Write a header row plus one row per workspace; returns the number of data rows
+pub fn write_metrics_csv<W: Write>(writer: W, rows: &[ExportRow]) -> Result<usize, String> {
+    let mut csv = csv::Writer::from_writer(writer);
+    csv.write_record(header()).map_err(|e| e.to_string())?;
+
+    for row in rows {
+        let mut record = vec![row.workspace_id.clone(), row.name.clone()];
+        match &row.metrics {
+            Some(metrics) => record.extend(metrics.fields().iter().map(|(_, v)| v.to_string())),
+            None => record.extend(ScaffoldMetrics::COLUMN_NAMES.map(|_| String::new())),
+        }
+        record.push(row.note.clone());
+        csv.write_record(&record).map_err(|e| e.to_string())?;
+    }
+
+    csv.flush().map_err(|e| e.to_string())?;
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics() -> ScaffoldMetrics {
+        ScaffoldMetrics {
+            porosity: 0.85,
+            mean_pore_size_um: 210.5,
+            interconnectivity: 0.97,
+            tortuosity: 1.12,
+            specific_surface_area: 12.0,
+            elastic_modulus: 45.0,
+            yield_strength: 2.5,
+            permeability: 1e-9,
+        }
+    }
+
+    fn export(rows: &[ExportRow]) -> String {
+        let mut out = Vec::new();
+        assert_eq!(write_metrics_csv(&mut out, rows).unwrap(), rows.len());
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn writes_header_and_quotes_names() {
+        let csv = export(&[ExportRow {
+            workspace_id: "ws-1".to_string(),
+            name: "Gyroid, 85% \"final\"".to_string(),
+            metrics: Some(metrics()),
+            note: String::new(),
+        }]);
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "workspace_id,name,porosity,mean_pore_size_um,interconnectivity,tortuosity,\
+             specific_surface_area_per_mm,elastic_modulus_mpa,yield_strength_mpa,permeability_m2,note"
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            "ws-1,\"Gyroid, 85% \"\"final\"\"\",0.85,210.5,0.97,1.12,12,45,2.5,0.000000001,"
+        );
+    }
+
+    #[test]
+    fn missing_metrics_leave_empty_cells_and_a_note() {
+        let csv = export(&[ExportRow {
+            workspace_id: "ws-2".to_string(),
+            name: "Draft".to_string(),
+            metrics: None,
+            note: "no metrics computed".to_string(),
+        }]);
+        assert_eq!(
+            csv.lines().nth(1).unwrap(),
+            "ws-2,Draft,,,,,,,,,no metrics computed"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+// Parameter history - per-workspace undo/redo of TPMS and analysis parameter changes
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Undo steps kept per workspace; older snapshots are dropped
+pub const MAX_UNDO_DEPTH: usize = 50;
+
+/// Every parameter of a workspace at one point in time, by name
+pub type ParamSnapshot = BTreeMap<String, serde_json::Value>;
+
+/// Linear history with a cursor: `snapshots[cursor]` is the current state,
+/// entries before it can be undone to and entries after it redone to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParamHistory {
+    snapshots: Vec<ParamSnapshot>,
+    cursor: usize,
+}
+
+impl Default for ParamHistory {
+    fn default() -> Self {
+        Self {
+            snapshots: vec![ParamSnapshot::new()],
+            cursor: 0,
+        }
+    }
+}
+
+impl ParamHistory {
+    pub fn current(&self) -> &ParamSnapshot {
+        &self.snapshots[self.cursor]
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.cursor > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.cursor + 1 < self.snapshots.len()
+    }
+
+    /// Apply `changes` on top of the current state as a new snapshot.
+    /// Anything that could have been redone is discarded.
+    pub fn record(&mut self, changes: ParamSnapshot) {
+        let mut next = self.current().clone();
+        next.extend(changes);
+        if next == *self.current() {
+            return;
+        }
+
+        self.snapshots.truncate(self.cursor + 1);
+        self.snapshots.push(next);
+        if self.snapshots.len() > MAX_UNDO_DEPTH + 1 {
+            self.snapshots.remove(0);
+        }
+        self.cursor = self.snapshots.len() - 1;
+    }
+
+    /// Step back; `false` (and no change) at the oldest snapshot
+    pub fn undo(&mut self) -> bool {
+        if !self.can_undo() {
+            return false;
+        }
+        self.cursor -= 1;
+        true
+    }
+
+    /// Step forward; `false` (and no change) at the newest snapshot
+    pub fn redo(&mut self) -> bool {
+        if !self.can_redo() {
+            return false;
+        }
+        self.cursor += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn change(key: &str, value: serde_json::Value) -> ParamSnapshot {
+        ParamSnapshot::from([(key.to_string(), value)])
+    }
+
+    #[test]
+    fn undo_redo_and_new_changes_move_the_cursor() {
+        let mut history = ParamHistory::default();
+        assert!(!history.undo());
+
+        history.record(change("porosity", json!(0.7)));
+        history.record(change("porosity", json!(0.8)));
+        history.record(change("surface_type", json!("gyroid")));
+        assert_eq!(history.current()["porosity"], json!(0.8));
+
+        assert!(history.undo());
+        assert!(history.undo());
+        assert_eq!(history.current()["porosity"], json!(0.7));
+        assert!(!history.current().contains_key("surface_type"));
+
+        assert!(history.redo());
+        assert_eq!(history.current()["porosity"], json!(0.8));
+
+        // A new change after undo drops the redo branch
+        history.record(change("unit_cell_size", json!(2.0)));
+        assert!(!history.redo());
+        assert_eq!(history.current()["porosity"], json!(0.8));
+        assert!(!history.current().contains_key("surface_type"));
+
+        assert!(history.undo());
+        assert!(history.undo());
+        assert!(history.undo());
+        assert!(history.current().is_empty());
+        assert!(!history.undo());
+    }
+
+    #[test]
+    fn depth_is_bounded_and_no_op_changes_are_skipped() {
+        let mut history = ParamHistory::default();
+        for i in 0..(MAX_UNDO_DEPTH + 10) {
+            history.record(change("porosity", json!(i)));
+        }
+        history.record(change("porosity", json!(MAX_UNDO_DEPTH + 9)));
+
+        let mut undone = 0;
+        while history.undo() {
+            undone += 1;
+        }
+        assert_eq!(undone, MAX_UNDO_DEPTH);
+        assert_eq!(history.current()["porosity"], json!(9));
+    }
+}
@@ -0,0 +1,189 @@
+// Parameter sweeps - expand a base parameter set into the grid of variants to generate
+
+use crate::param_history::ParamSnapshot;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Most variants one sweep may expand to
+pub const MAX_SWEEP_COMBINATIONS: usize = 64;
+
+/// Swept values are rounded to this many decimals so 0.6 + 3 * 0.05 is 0.75
+const RANGE_DECIMALS: i32 = 9;
+
+/// The values one parameter takes, listed or as an inclusive range
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum SweepValues {
+    List { values: Vec<Value> },
+    Range { start: f64, stop: f64, step: f64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ParamSweep {
+    pub param: String,
+    #[serde(flatten)]
+    pub values: SweepValues,
+}
+
+/// One generated variant: its workspace on success, otherwise why it failed
+#[derive(Debug, Clone, Serialize)]
+pub struct SweepResult {
+    pub params: ParamSnapshot,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workspace_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mesh_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl SweepValues {
+    fn expand(&self, param: &str) -> Result<Vec<Value>, String> {
+        let values = match self {
+            Self::List { values } => values.clone(),
+            Self::Range { start, stop, step } => {
+                if !(start.is_finite() && stop.is_finite() && *step > 0.0 && step.is_finite()) {
+                    return Err(format!(
+                        "sweep of `{}`: start and stop must be numbers and step positive",
+                        param
+                    ));
+                }
+                if stop < start {
+                    return Err(format!("sweep of `{}`: stop is below start", param));
+                }
+                // Tolerate float error so that 0.6..=0.9 step 0.05 includes 0.9
+                let count = ((stop - start) / step + 1e-9).floor() + 1.0;
+                if count > MAX_SWEEP_COMBINATIONS as f64 {
+                    return Err(too_many(count as usize));
+                }
+                let scale = 10f64.powi(RANGE_DECIMALS);
+                (0..count as usize)
+                    .map(|i| Value::from(((start + i as f64 * step) * scale).round() / scale))
+                    .collect()
+            }
+        };
+        if values.is_empty() {
+            return Err(format!("sweep of `{}` has no values", param));
+        }
+        Ok(values)
+    }
+}
+
+fn too_many(count: usize) -> String {
+    format!(
+        "sweep expands to {} combinations; at most {} are allowed",
+        count, MAX_SWEEP_COMBINATIONS
+    )
+}
+
+/// Every combination of the swept values applied to `base`, the first sweep varying slowest.
+///
+/// Swept parameters must already exist in `base` and may be swept only once.
+pub fn expand(base: &ParamSnapshot, sweeps: &[ParamSweep]) -> Result<Vec<ParamSnapshot>, String> {
+    if sweeps.is_empty() {
+        return Err("at least one sweep is required".to_string());
+    }
+
+    let mut axes = Vec::with_capacity(sweeps.len());
+    let mut total: usize = 1;
+    for (i, sweep) in sweeps.iter().enumerate() {
+        if !base.contains_key(&sweep.param) {
+            return Err(format!("unknown parameter `{}`", sweep.param));
+        }
+        if sweeps[..i].iter().any(|s| s.param == sweep.param) {
+            return Err(format!("parameter `{}` is swept twice", sweep.param));
+        }
+        let values = sweep.values.expand(&sweep.param)?;
+        total = total.saturating_mul(values.len());
+        if total > MAX_SWEEP_COMBINATIONS {
+            let count = sweeps[i + 1..]
+                .iter()
+                .filter_map(|s| s.values.expand(&s.param).ok())
+                .fold(total, |n, values| n.saturating_mul(values.len()));
+            return Err(too_many(count));
+        }
+        axes.push((sweep.param.as_str(), values));
+    }
+
+    let mut variants = vec![base.clone()];
+    for (param, values) in axes {
+        variants = variants
+            .into_iter()
+            .flat_map(|variant| {
+                values.iter().map(move |value| {
+                    let mut variant = variant.clone();
+                    variant.insert(param.to_string(), value.clone());
+                    variant
+                })
+            })
+            .collect();
+    }
+    Ok(variants)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn base() -> ParamSnapshot {
+        serde_json::from_value(json!({
+            "surface_type": "gyroid",
+            "porosity": 0.75,
+            "unit_cell_size": 2.0,
+            "n_cells": [3, 3, 3],
+        }))
+        .unwrap()
+    }
+
+    fn sweeps(value: Value) -> Vec<ParamSweep> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn expands_the_cartesian_product_with_exact_range_steps() {
+        let sweeps = sweeps(json!([
+            {"param": "surface_type", "values": ["gyroid", "diamond"]},
+            {"param": "porosity", "start": 0.6, "stop": 0.9, "step": 0.05},
+        ]));
+        let variants = expand(&base(), &sweeps).unwrap();
+
+        assert_eq!(variants.len(), 14);
+        let porosities: Vec<f64> = variants[..7]
+            .iter()
+            .map(|v| v["porosity"].as_f64().unwrap())
+            .collect();
+        assert_eq!(porosities, vec![0.6, 0.65, 0.7, 0.75, 0.8, 0.85, 0.9]);
+        assert!(variants[..7].iter().all(|v| v["surface_type"] == "gyroid"));
+        assert_eq!(variants[7]["surface_type"], "diamond");
+        assert_eq!(variants[13]["unit_cell_size"], 2.0);
+    }
+
+    #[test]
+    fn rejects_unknown_repeated_and_oversized_sweeps() {
+        let error = expand(&base(), &sweeps(json!([{"param": "wall", "values": [1]}])));
+        assert_eq!(error.unwrap_err(), "unknown parameter `wall`");
+
+        let repeated = sweeps(json!([
+            {"param": "porosity", "values": [0.7]},
+            {"param": "porosity", "values": [0.8]},
+        ]));
+        assert!(expand(&base(), &repeated)
+            .unwrap_err()
+            .contains("swept twice"));
+
+        let oversized = sweeps(json!([
+            {"param": "porosity", "start": 0.5, "stop": 0.9, "step": 0.01},
+            {"param": "unit_cell_size", "values": [1.0, 2.0]},
+        ]));
+        assert_eq!(
+            expand(&base(), &oversized).unwrap_err(),
+            "sweep expands to 82 combinations; at most 64 are allowed"
+        );
+
+        let huge = sweeps(json!([{"param": "porosity", "start": 0.0, "stop": 1.0, "step": 1e-12}]));
+        assert!(expand(&base(), &huge)
+            .unwrap_err()
+            .starts_with("sweep expands to"));
+    }
+}
@@ -0,0 +1,122 @@
+// Print estimates - mass, material use, print time and cost of a scaffold on a printer profile
+
+use crate::materials::Material;
+use crate::printer_profiles::PrinterProfile;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrintEstimate {
+    /// Solid volume of the scaffold mesh
+    pub volume_mm3: f64,
+    /// Deposited material: the solid volume scaled by `infill`
+    pub mass_g: f64,
+    pub filament_length_m: Option<f64>,
+    pub estimated_print_hours: f64,
+    /// Material plus machine time, in the currency of the profile's costs
+    pub cost_estimate: f64,
+}
+
+/// Estimate printing `volume_mm3` of `material` at `infill` (0-1] on `profile`.
+///
+/// Print time assumes the nozzle deposits `line_width * layer_height * speed`
+/// cubic millimetres per second without travel moves, so it is a lower bound.
+pub fn estimate(
+    volume_mm3: f64,
+    material: &Material,
+    infill: f64,
+    profile: &PrinterProfile,
+) -> Result<PrintEstimate, String> {
+    if !volume_mm3.is_finite() || volume_mm3 < 0.0 {
+        return Err(format!(
+            "mesh volume must be a non-negative number, got {}",
+            volume_mm3
+        ));
+    }
+    if !(infill > 0.0 && infill <= 1.0) {
+        return Err(format!("infill must be in (0, 1], got {}", infill));
+    }
+    let deposit_rate_mm3_s =
+        profile.line_width_mm * profile.layer_height_mm * profile.print_speed_mm_s;
+    if !(deposit_rate_mm3_s > 0.0 && deposit_rate_mm3_s.is_finite()) {
+        return Err(format!(
+            "{}: line width, layer height and speed must be positive",
+            profile.name
+        ));
+    }
+
+    let deposited_mm3 = volume_mm3 * infill;
+    let mass_g = deposited_mm3 / 1000.0 * material.density_g_cm3;
+    let filament_length_m = profile.filament_diameter_mm.map(|diameter| {
+        let area_mm2 = std::f64::consts::PI * (diameter / 2.0).powi(2);
+        deposited_mm3 / area_mm2 / 1000.0
+    });
+    let estimated_print_hours = deposited_mm3 / deposit_rate_mm3_s / 3600.0;
+    let cost_estimate = mass_g / 1000.0 * profile.material_cost_per_kg
+        + estimated_print_hours * profile.machine_cost_per_hour;
+
+    Ok(PrintEstimate {
+        volume_mm3,
+        mass_g,
+        filament_length_m,
+        estimated_print_hours,
+        cost_estimate,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer_profiles::{bundled_printer_profiles, find_in};
+
+    fn find_printer_profile(name: &str) -> Result<PrinterProfile, String> {
+        find_in(&bundled_printer_profiles(), name)
+    }
+
+    fn pcl() -> Material {
+        Material {
+            name: "PCL".to_string(),
+            youngs_modulus_mpa: 400.0,
+            density_g_cm3: 1.145,
+            degradation_months: Some(30.0),
+            biocompatibility_class: "bioresorbable polymer".to_string(),
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9 * expected.abs().max(1.0),
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn arithmetic_follows_density_filament_and_deposit_rate() {
+        let profile = find_printer_profile("fdm 0.4 MM").unwrap();
+        let estimate = estimate(2000.0, &pcl(), 0.5, &profile).unwrap();
+
+        // 1000 mm3 deposited = 1 cm3
+        assert_close(estimate.mass_g, 1.145);
+        let area = std::f64::consts::PI * 0.875 * 0.875;
+        assert_close(estimate.filament_length_m.unwrap(), 1000.0 / area / 1000.0);
+        // 0.4 * 0.2 * 40 = 3.2 mm3/s
+        let hours = 1000.0 / 3.2 / 3600.0;
+        assert_close(estimate.estimated_print_hours, hours);
+        assert_close(estimate.cost_estimate, 1.145e-3 * 25.0 + hours * 1.5);
+
+        let syringe = find_printer_profile("Pneumatic bioprinter").unwrap();
+        let estimate = super::estimate(2000.0, &pcl(), 1.0, &syringe).unwrap();
+        assert_eq!(estimate.filament_length_m, None);
+    }
+
+    #[test]
+    fn rejects_bad_infill_and_unknown_profiles() {
+        let profile = find_printer_profile("FDM 0.4 mm").unwrap();
+        for infill in [0.0, 1.5, f64::NAN] {
+            assert!(estimate(100.0, &pcl(), infill, &profile).is_err());
+        }
+        let error = find_printer_profile("resin").unwrap_err();
+        assert!(error.contains("FDM 0.4 mm"), "{}", error);
+    }
+}
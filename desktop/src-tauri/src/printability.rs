@@ -0,0 +1,294 @@
+// Printability - mesh topology, size, feature and overhang checks against a printer profile
+
+use crate::commands::MeshReport;
+use crate::printer_profiles::PrinterProfile;
+use serde::{Deserialize, Serialize};
+
+/// Overhangs up to this angle from vertical print without support
+const SAFE_OVERHANG_DEG: f64 = 45.0;
+/// Beyond this, overhangs sag or fail unless they are tiny
+const MAX_OVERHANG_DEG: f64 = 60.0;
+/// Steep overhangs covering less than this fraction of the surface only warn
+const MINOR_OVERHANG_AREA: f64 = 0.01;
+/// Features thinner than this many nozzle widths print as a single, fragile line
+const SOLID_FEATURE_NOZZLES: f64 = 2.0;
+
+/// Ordered from best to worst, so the overall verdict is the worst check's
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrintabilityCheck {
+    /// "topology", "build_volume", "feature_size" or "overhang"
+    pub check: String,
+    pub verdict: Verdict,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrintabilityReport {
+    pub workspace_id: String,
+    pub printer_profile: String,
+    pub verdict: Verdict,
+    pub checks: Vec<PrintabilityCheck>,
+}
+
+/// Geometry features from Julia's `/mesh/printability`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrintGeometry {
+    /// Thinnest strut or wall
+    pub min_feature_mm: f64,
+    /// Steepest downward-facing surface, degrees from vertical (90 is a flat ceiling)
+    pub max_overhang_deg: f64,
+    /// Fraction of the surface steeper than 45 degrees
+    pub overhang_area_fraction: f64,
+}
+
+fn check(name: &str, verdict: Verdict, message: String) -> PrintabilityCheck {
+    PrintabilityCheck {
+        check: name.to_string(),
+        verdict,
+        message,
+    }
+}
+
+fn topology(mesh: &MeshReport) -> PrintabilityCheck {
+    let mut problems = Vec::new();
+    if !mesh.watertight {
+        problems.push("not watertight".to_string());
+    }
+    if !mesh.manifold {
+        problems.push("not manifold".to_string());
+    }
+    if !problems.is_empty() {
+        let message = format!("mesh is {}; slicers cannot fill it", problems.join(" and "));
+        return check("topology", Verdict::Fail, message);
+    }
+    if mesh.degenerate_faces > 0 || mesh.self_intersections > 0 {
+        let message = format!(
+            "mesh is watertight and manifold but has {} degenerate faces and {} self-intersections",
+            mesh.degenerate_faces, mesh.self_intersections
+        );
+        return check("topology", Verdict::Warn, message);
+    }
+    let message = "mesh is watertight and manifold".to_string();
+    check("topology", Verdict::Pass, message)
+}
+
+fn build_volume(mesh: &MeshReport, profile: &PrinterProfile) -> PrintabilityCheck {
+    let Some(warning) = profile.build_volume_warning(mesh.bounding_box_mm) else {
+        let message = format!("mesh fits the build volume of {}", profile.name);
+        return check("build_volume", Verdict::Pass, message);
+    };
+    // Laid out with its longest side along the printer's longest axis, it may still fit
+    let sorted = |mut sides: [f64; 3]| {
+        sides.sort_by(f64::total_cmp);
+        sides
+    };
+    let (mesh_sides, volume_sides) = (
+        sorted(mesh.bounding_box_mm),
+        sorted(profile.build_volume_mm),
+    );
+    if mesh_sides
+        .iter()
+        .zip(volume_sides)
+        .all(|(&side, limit)| side <= limit)
+    {
+        let message = format!("{}; it fits when rotated", warning);
+        return check("build_volume", Verdict::Warn, message);
+    }
+    check("build_volume", Verdict::Fail, warning)
+}
+
+fn feature_size(geometry: &PrintGeometry, profile: &PrinterProfile) -> PrintabilityCheck {
+    let (feature, nozzle) = (geometry.min_feature_mm, profile.nozzle_diameter_mm);
+    if feature < nozzle {
+        let message = format!(
+            "thinnest feature ({} mm) is below the {} mm nozzle and will not print",
+            feature, nozzle
+        );
+        return check("feature_size", Verdict::Fail, message);
+    }
+    if feature < SOLID_FEATURE_NOZZLES * nozzle {
+        let message = format!(
+            "thinnest feature ({} mm) is under {} nozzle widths and prints as a single line",
+            feature, SOLID_FEATURE_NOZZLES
+        );
+        return check("feature_size", Verdict::Warn, message);
+    }
+    let message = format!("thinnest feature is {} mm", feature);
+    check("feature_size", Verdict::Pass, message)
+}
+
+fn overhang(geometry: &PrintGeometry) -> PrintabilityCheck {
+    let (angle, area) = (geometry.max_overhang_deg, geometry.overhang_area_fraction);
+    let share = format!("{:.1}% of the surface", area * 100.0);
+    if angle <= SAFE_OVERHANG_DEG {
+        let message = format!("steepest overhang is {} degrees", angle);
+        return check("overhang", Verdict::Pass, message);
+    }
+    if angle <= MAX_OVERHANG_DEG || area < MINOR_OVERHANG_AREA {
+        let message = format!(
+            "overhangs up to {} degrees on {} may need support or a fan",
+            angle, share
+        );
+        return check("overhang", Verdict::Warn, message);
+    }
+    let message = format!(
+        "overhangs up to {} degrees on {} need support material",
+        angle, share
+    );
+    check("overhang", Verdict::Fail, message)
+}
+
+/// Apply every rule; the report's verdict is the worst of them
+pub fn evaluate(
+    workspace_id: &str,
+    mesh: &MeshReport,
+    geometry: &PrintGeometry,
+    profile: &PrinterProfile,
+) -> PrintabilityReport {
+    let checks = vec![
+        topology(mesh),
+        build_volume(mesh, profile),
+        feature_size(geometry, profile),
+        overhang(geometry),
+    ];
+    PrintabilityReport {
+        workspace_id: workspace_id.to_string(),
+        printer_profile: profile.name.clone(),
+        verdict: checks
+            .iter()
+            .map(|c| c.verdict)
+            .max()
+            .unwrap_or(Verdict::Pass),
+        checks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fdm() -> PrinterProfile {
+        PrinterProfile {
+            name: "FDM 0.4 mm".to_string(),
+            build_volume_mm: [220.0, 220.0, 250.0],
+            nozzle_diameter_mm: 0.4,
+            max_speed_mm_s: 150.0,
+            filament_diameter_mm: Some(1.75),
+            line_width_mm: 0.4,
+            layer_height_mm: 0.2,
+            print_speed_mm_s: 40.0,
+            machine_cost_per_hour: 1.5,
+            material_cost_per_kg: 25.0,
+        }
+    }
+
+    fn clean_mesh(bounding_box_mm: [f64; 3]) -> MeshReport {
+        MeshReport {
+            manifold: true,
+            watertight: true,
+            degenerate_faces: 0,
+            self_intersections: 0,
+            bounding_box_mm,
+        }
+    }
+
+    fn geometry(
+        min_feature_mm: f64,
+        max_overhang_deg: f64,
+        overhang_area_fraction: f64,
+    ) -> PrintGeometry {
+        PrintGeometry {
+            min_feature_mm,
+            max_overhang_deg,
+            overhang_area_fraction,
+        }
+    }
+
+    fn verdicts(report: &PrintabilityReport) -> Vec<(&str, Verdict)> {
+        report
+            .checks
+            .iter()
+            .map(|c| (c.check.as_str(), c.verdict))
+            .collect()
+    }
+
+    #[test]
+    fn a_clean_scaffold_passes_every_check() {
+        let report = evaluate(
+            "ws-1",
+            &clean_mesh([10.0, 10.0, 5.0]),
+            &geometry(1.0, 30.0, 0.0),
+            &fdm(),
+        );
+        assert_eq!(report.verdict, Verdict::Pass);
+        assert_eq!(
+            verdicts(&report),
+            [
+                ("topology", Verdict::Pass),
+                ("build_volume", Verdict::Pass),
+                ("feature_size", Verdict::Pass),
+                ("overhang", Verdict::Pass),
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&report).unwrap()["verdict"],
+            serde_json::json!("pass")
+        );
+    }
+
+    #[test]
+    fn marginal_geometry_warns() {
+        let mut mesh = clean_mesh([240.0, 10.0, 200.0]);
+        mesh.degenerate_faces = 3;
+        let report = evaluate("ws-1", &mesh, &geometry(0.6, 55.0, 0.2), &fdm());
+        assert_eq!(report.verdict, Verdict::Warn);
+        assert!(report.checks.iter().all(|c| c.verdict == Verdict::Warn));
+        assert!(report.checks[1].message.ends_with("it fits when rotated"));
+        assert!(report.checks[2].message.contains("single line"));
+
+        // A steep overhang on a sliver of the surface only warns
+        assert_eq!(overhang(&geometry(1.0, 80.0, 0.005)).verdict, Verdict::Warn);
+    }
+
+    #[test]
+    fn unprintable_scaffolds_fail_with_reasons() {
+        let mut mesh = clean_mesh([300.0, 300.0, 10.0]);
+        mesh.watertight = false;
+        mesh.manifold = false;
+        let report = evaluate("ws-1", &mesh, &geometry(0.3, 85.0, 0.15), &fdm());
+        assert_eq!(report.verdict, Verdict::Fail);
+        assert!(report.checks.iter().all(|c| c.verdict == Verdict::Fail));
+        assert_eq!(
+            report.checks[0].message,
+            "mesh is not watertight and not manifold; slicers cannot fill it"
+        );
+        assert!(report.checks[1]
+            .message
+            .contains("exceeds the build volume"));
+        assert_eq!(
+            report.checks[2].message,
+            "thinnest feature (0.3 mm) is below the 0.4 mm nozzle and will not print"
+        );
+        assert_eq!(
+            report.checks[3].message,
+            "overhangs up to 85 degrees on 15.0% of the surface need support material"
+        );
+
+        // One failing check is enough
+        let report = evaluate(
+            "ws-1",
+            &clean_mesh([10.0, 10.0, 5.0]),
+            &geometry(0.2, 30.0, 0.0),
+            &fdm(),
+        );
+        assert_eq!(report.verdict, Verdict::Fail);
+    }
+}
@@ -0,0 +1,254 @@
+// Printer profiles - bundled printers and slicer settings plus user-defined profiles
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+const BUNDLED_PRINTER_PROFILES: &str = include_str!("../resources/printer_profiles.json");
+const USER_PRINTER_PROFILES_FILE: &str = "printer_profiles.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrinterProfile {
+    pub name: String,
+    /// Printable width, depth and height
+    pub build_volume_mm: [f64; 3],
+    pub nozzle_diameter_mm: f64,
+    /// Fastest the printer can move while extruding
+    pub max_speed_mm_s: f64,
+    /// `None` for syringe- or pellet-fed printers, which report no filament length
+    pub filament_diameter_mm: Option<f64>,
+    pub line_width_mm: f64,
+    pub layer_height_mm: f64,
+    /// Speed used for estimates; at most `max_speed_mm_s`
+    pub print_speed_mm_s: f64,
+    pub machine_cost_per_hour: f64,
+    /// Cost of the feedstock, filament or otherwise
+    #[serde(alias = "filament_cost_per_kg")]
+    pub material_cost_per_kg: f64,
+}
+
+impl PrinterProfile {
+    pub fn validate(&self) -> Result<(), String> {
+        let positive = |value: f64| value.is_finite() && value > 0.0;
+        if self.name.trim().is_empty() {
+            return Err("printer profile name must not be empty".to_string());
+        }
+        if !self.build_volume_mm.iter().all(|&side| positive(side)) {
+            return Err(format!(
+                "{}: build volume must be positive in every dimension",
+                self.name
+            ));
+        }
+        if !positive(self.max_speed_mm_s) {
+            return Err(format!("{}: max speed must be positive", self.name));
+        }
+        if !positive(self.nozzle_diameter_mm)
+            || !positive(self.line_width_mm)
+            || !positive(self.layer_height_mm)
+            || matches!(self.filament_diameter_mm, Some(d) if !positive(d))
+        {
+            return Err(format!(
+                "{}: nozzle, filament, line width and layer height must be positive",
+                self.name
+            ));
+        }
+        if !positive(self.print_speed_mm_s) || self.print_speed_mm_s > self.max_speed_mm_s {
+            return Err(format!(
+                "{}: print speed must be positive and at most the max speed ({} mm/s)",
+                self.name, self.max_speed_mm_s
+            ));
+        }
+        if !(self.machine_cost_per_hour >= 0.0 && self.material_cost_per_kg >= 0.0) {
+            return Err(format!("{}: costs must not be negative", self.name));
+        }
+        Ok(())
+    }
+
+    /// A warning when a mesh with this bounding box does not fit the build volume
+    /// as oriented; `None` when it fits.
+    pub fn build_volume_warning(&self, bounding_box_mm: [f64; 3]) -> Option<String> {
+        let fits = bounding_box_mm
+            .iter()
+            .zip(self.build_volume_mm)
+            .all(|(&size, limit)| size <= limit);
+        if fits {
+            return None;
+        }
+        let [x, y, z] = bounding_box_mm;
+        let [bx, by, bz] = self.build_volume_mm;
+        Some(format!(
+            "mesh ({} x {} x {} mm) exceeds the build volume of {} ({} x {} x {} mm)",
+            x, y, z, self.name, bx, by, bz
+        ))
+    }
+}
+
+pub fn bundled_printer_profiles() -> Vec<PrinterProfile> {
+    serde_json::from_str(BUNDLED_PRINTER_PROFILES).expect("bundled printer_profiles.json is valid")
+}
+
+fn is_bundled(name: &str) -> bool {
+    bundled_printer_profiles()
+        .iter()
+        .any(|p| p.name.eq_ignore_ascii_case(name))
+}
+
+/// `bundled` followed by `user`; a user profile with the same name replaces the bundled one.
+pub fn merge(mut bundled: Vec<PrinterProfile>, user: Vec<PrinterProfile>) -> Vec<PrinterProfile> {
+    for profile in user {
+        match bundled
+            .iter_mut()
+            .find(|p| p.name.eq_ignore_ascii_case(&profile.name))
+        {
+            Some(existing) => *existing = profile,
+            None => bundled.push(profile),
+        }
+    }
+    bundled
+}
+
+/// Case-insensitive lookup; the error lists the known profiles.
+pub fn find_in(profiles: &[PrinterProfile], name: &str) -> Result<PrinterProfile, String> {
+    match profiles.iter().find(|p| p.name.eq_ignore_ascii_case(name)) {
+        Some(profile) => Ok(profile.clone()),
+        None => Err(format!(
+            "unknown printer profile '{}' (available: {})",
+            name,
+            profiles
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+fn user_printer_profiles_path(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path_resolver()
+        .app_config_dir()
+        .map(|dir| dir.join(USER_PRINTER_PROFILES_FILE))
+        .ok_or_else(|| "could not resolve the app config directory".to_string())
+}
+
+pub fn load_user_printer_profiles(app: &AppHandle) -> Vec<PrinterProfile> {
+    let Ok(path) = user_printer_profiles_path(app) else {
+        return Vec::new();
+    };
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn save_user_printer_profiles(app: &AppHandle, profiles: &[PrinterProfile]) -> Result<(), String> {
+    let path = user_printer_profiles_path(app)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let json = serde_json::to_string_pretty(profiles).map_err(|e| e.to_string())?;
+    std::fs::write(path, json).map_err(|e| e.to_string())
+}
+
+pub fn all_printer_profiles(app: &AppHandle) -> Vec<PrinterProfile> {
+    merge(bundled_printer_profiles(), load_user_printer_profiles(app))
+}
+
+pub fn find_printer_profile(app: &AppHandle, name: &str) -> Result<PrinterProfile, String> {
+    find_in(&all_printer_profiles(app), name)
+}
+
+pub fn add_user_printer_profile(app: &AppHandle, profile: PrinterProfile) -> Result<(), String> {
+    profile.validate()?;
+
+    let mut profiles = load_user_printer_profiles(app);
+    profiles.retain(|p| !p.name.eq_ignore_ascii_case(&profile.name));
+    profiles.push(profile);
+    save_user_printer_profiles(app, &profiles)
+}
+
+/// Remove a user profile. Bundled profiles cannot be deleted, but a user profile
+/// overriding one can, which restores the bundled settings.
+pub fn delete_user_printer_profile(app: &AppHandle, name: &str) -> Result<(), String> {
+    let mut profiles = load_user_printer_profiles(app);
+    let before = profiles.len();
+    profiles.retain(|p| !p.name.eq_ignore_ascii_case(name));
+    if profiles.len() == before {
+        return Err(if is_bundled(name) {
+            format!(
+                "printer profile '{}' is bundled and cannot be deleted",
+                name
+            )
+        } else {
+            format!("unknown printer profile '{}'", name)
+        });
+    }
+    save_user_printer_profiles(app, &profiles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_profiles_are_valid() {
+        for profile in bundled_printer_profiles() {
+            assert_eq!(profile.validate(), Ok(()), "{}", profile.name);
+        }
+    }
+
+    #[test]
+    fn rejects_non_positive_build_volume_and_speed() {
+        let fdm = find_in(&bundled_printer_profiles(), "FDM 0.4 mm").unwrap();
+
+        let flat = PrinterProfile {
+            build_volume_mm: [220.0, 220.0, 0.0],
+            ..fdm.clone()
+        };
+        assert!(flat.validate().unwrap_err().contains("build volume"));
+        let stalled = PrinterProfile {
+            max_speed_mm_s: -1.0,
+            ..fdm.clone()
+        };
+        assert!(stalled.validate().unwrap_err().contains("max speed"));
+        let too_fast = PrinterProfile {
+            print_speed_mm_s: fdm.max_speed_mm_s * 2.0,
+            ..fdm
+        };
+        assert!(too_fast.validate().unwrap_err().contains("print speed"));
+    }
+
+    #[test]
+    fn user_profiles_override_bundled_ones_and_accept_filament_cost() {
+        let custom: PrinterProfile = serde_json::from_value(serde_json::json!({
+            "name": "fdm 0.4 MM",
+            "build_volume_mm": [300.0, 300.0, 400.0],
+            "nozzle_diameter_mm": 0.4,
+            "max_speed_mm_s": 300.0,
+            "filament_diameter_mm": 1.75,
+            "line_width_mm": 0.45,
+            "layer_height_mm": 0.2,
+            "print_speed_mm_s": 120.0,
+            "machine_cost_per_hour": 2.0,
+            "filament_cost_per_kg": 30.0,
+        }))
+        .unwrap();
+        assert_eq!(custom.material_cost_per_kg, 30.0);
+
+        let bundled = bundled_printer_profiles();
+        let merged = merge(bundled.clone(), vec![custom.clone()]);
+        assert_eq!(merged.len(), bundled.len());
+        assert_eq!(find_in(&merged, "FDM 0.4 mm").unwrap(), custom);
+    }
+
+    #[test]
+    fn warns_when_a_mesh_exceeds_the_build_volume() {
+        let profile = find_in(&bundled_printer_profiles(), "FDM 0.4 mm").unwrap();
+        assert_eq!(profile.build_volume_warning([10.0, 10.0, 10.0]), None);
+        let warning = profile.build_volume_warning([10.0, 10.0, 1000.0]).unwrap();
+        assert!(
+            warning.contains("exceeds the build volume of FDM 0.4 mm"),
+            "{}",
+            warning
+        );
+    }
+}
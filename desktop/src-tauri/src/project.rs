@@ -0,0 +1,280 @@
+// Project bundles - a whole session saved as one `.darwin` zip: a manifest plus
+// copies of the scaffold files its workspaces reference
+
+use crate::state::{AppSettings, ChatEntry, WorkspaceState};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Manifest layout written by this build; bundles up to this version can be read
+pub const PROJECT_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_NAME: &str = "manifest.json";
+const FILES_DIR: &str = "files";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundledFile {
+    /// Where the file was when the project was saved
+    pub original_path: String,
+    /// Entry name inside the bundle
+    pub archive_path: String,
+}
+
+/// `manifest.json` of a bundle. Everything but `format_version` defaults when
+/// missing, and unknown fields are ignored, so older and newer bundles of the
+/// same format version still open.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectManifest {
+    pub format_version: u32,
+    /// Version of the app that saved the bundle
+    #[serde(default)]
+    pub app_version: String,
+    /// Unix time in milliseconds
+    #[serde(default)]
+    pub saved_at: u64,
+    /// Settings at save time; informational, never applied on load
+    #[serde(default)]
+    pub settings: AppSettings,
+    #[serde(default)]
+    pub workspaces: Vec<WorkspaceState>,
+    #[serde(default)]
+    pub current_workspace: Option<String>,
+    #[serde(default)]
+    pub chat_history: Vec<ChatEntry>,
+    #[serde(default)]
+    pub files: Vec<BundledFile>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProjectSummary {
+    pub path: String,
+    pub workspaces: usize,
+    pub files: usize,
+    /// Referenced files that no longer existed and were left out
+    pub missing_files: Vec<String>,
+}
+
+/// Write `manifest` and every existing workspace file to a zip at `path`.
+///
+/// The bundle is written next to `path` first and renamed into place, so a
+/// failed save never leaves a truncated project behind.
+pub fn write_bundle(path: &Path, mut manifest: ProjectManifest) -> Result<ProjectSummary, String> {
+    manifest.format_version = PROJECT_FORMAT_VERSION;
+    manifest.files.clear();
+    let mut missing_files = Vec::new();
+    for file_path in manifest
+        .workspaces
+        .iter()
+        .filter_map(|w| w.file_path.as_deref())
+    {
+        if manifest.files.iter().any(|f| f.original_path == file_path) {
+            continue;
+        }
+        if !Path::new(file_path).is_file() {
+            missing_files.push(file_path.to_string());
+            continue;
+        }
+        let name = Path::new(file_path)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "scaffold".to_string());
+        manifest.files.push(BundledFile {
+            original_path: file_path.to_string(),
+            archive_path: format!("{}/{}_{}", FILES_DIR, manifest.files.len(), name),
+        });
+    }
+
+    let tmp_path = path.with_extension("darwin.partial");
+    let written = write_zip(&tmp_path, &manifest).and_then(|()| {
+        std::fs::rename(&tmp_path, path)
+            .map_err(|e| format!("cannot save {}: {}", path.display(), e))
+    });
+    if written.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    written?;
+
+    Ok(ProjectSummary {
+        path: path.to_string_lossy().into_owned(),
+        workspaces: manifest.workspaces.len(),
+        files: manifest.files.len(),
+        missing_files,
+    })
+}
+
+fn write_zip(path: &Path, manifest: &ProjectManifest) -> Result<(), String> {
+    let file =
+        File::create(path).map_err(|e| format!("cannot create {}: {}", path.display(), e))?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    zip.start_file(MANIFEST_NAME, options)
+        .map_err(|e| e.to_string())?;
+    serde_json::to_writer_pretty(&mut zip, manifest).map_err(|e| e.to_string())?;
+
+    for bundled in &manifest.files {
+        let mut source = File::open(&bundled.original_path)
+            .map_err(|e| format!("cannot read {}: {}", bundled.original_path, e))?;
+        let large = source
+            .metadata()
+            .map(|m| m.len() >= u32::MAX as u64)
+            .unwrap_or(false);
+        zip.start_file(bundled.archive_path.as_str(), options.large_file(large))
+            .map_err(|e| e.to_string())?;
+        std::io::copy(&mut source, &mut zip)
+            .map_err(|e| format!("cannot bundle {}: {}", bundled.original_path, e))?;
+    }
+    zip.finish().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Open the bundle at `path`, extract its files into `extract_dir` and return the
+/// manifest with workspace file paths pointing at the extracted copies.
+pub fn read_bundle(path: &Path, extract_dir: &Path) -> Result<ProjectManifest, String> {
+    let file = File::open(path).map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+    let mut zip = ZipArchive::new(file).map_err(|_| "not a Darwin project bundle".to_string())?;
+
+    let manifest: serde_json::Value = {
+        let entry = zip
+            .by_name(MANIFEST_NAME)
+            .map_err(|_| "not a Darwin project bundle (no manifest.json)".to_string())?;
+        serde_json::from_reader(entry).map_err(|e| format!("invalid project manifest: {}", e))?
+    };
+    match manifest.get("format_version").and_then(|v| v.as_u64()) {
+        None => return Err("invalid project manifest: missing format_version".to_string()),
+        Some(version) if version > PROJECT_FORMAT_VERSION as u64 => {
+            return Err(format!(
+                "project was saved by a newer version of the app (format {}, this version reads up to {})",
+                version, PROJECT_FORMAT_VERSION
+            ))
+        }
+        Some(_) => {}
+    }
+    let mut manifest: ProjectManifest =
+        serde_json::from_value(manifest).map_err(|e| format!("invalid project manifest: {}", e))?;
+
+    std::fs::create_dir_all(extract_dir).map_err(|e| e.to_string())?;
+    let mut remapped: HashMap<String, PathBuf> = HashMap::new();
+    for bundled in &manifest.files {
+        let mut entry = zip
+            .by_name(&bundled.archive_path)
+            .map_err(|_| format!("project bundle is missing {}", bundled.archive_path))?;
+        // Only the final name is used, so entries cannot escape `extract_dir`
+        let name = entry
+            .enclosed_name()
+            .and_then(|p| p.file_name().map(|n| n.to_owned()))
+            .ok_or_else(|| {
+                format!(
+                    "unsafe file name in project bundle: {}",
+                    bundled.archive_path
+                )
+            })?;
+        let dest = extract_dir.join(name);
+        let mut out =
+            File::create(&dest).map_err(|e| format!("cannot extract {}: {}", dest.display(), e))?;
+        std::io::copy(&mut entry, &mut out)
+            .map_err(|e| format!("cannot extract {}: {}", dest.display(), e))?;
+        remapped.insert(bundled.original_path.clone(), dest);
+    }
+
+    for workspace in &mut manifest.workspaces {
+        if let Some(dest) = workspace.file_path.as_ref().and_then(|p| remapped.get(p)) {
+            workspace.file_path = Some(dest.to_string_lossy().into_owned());
+        }
+    }
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn scratch_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("darwin-project-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn manifest(workspaces: Vec<WorkspaceState>) -> ProjectManifest {
+        ProjectManifest {
+            format_version: PROJECT_FORMAT_VERSION,
+            app_version: "0.1.0".to_string(),
+            saved_at: 0,
+            settings: AppSettings::default(),
+            workspaces,
+            current_workspace: Some("ws-1".to_string()),
+            chat_history: vec![ChatEntry {
+                role: "user".to_string(),
+                agent: "design".to_string(),
+                content: "make it stiffer".to_string(),
+            }],
+            files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn round_trip_remaps_files_into_the_extract_dir() {
+        let dir = scratch_dir();
+        let scan = dir.join("scan.tif");
+        std::fs::write(&scan, b"voxels").unwrap();
+        let mut with_file = WorkspaceState::new("ws-1");
+        with_file.file_path = Some(scan.to_string_lossy().into_owned());
+        let mut missing = WorkspaceState::new("ws-2");
+        missing.file_path = Some(dir.join("gone.stl").to_string_lossy().into_owned());
+
+        let bundle = dir.join("study.darwin");
+        let summary = write_bundle(&bundle, manifest(vec![with_file, missing])).unwrap();
+        assert_eq!(summary.files, 1);
+        assert_eq!(summary.missing_files.len(), 1);
+
+        let loaded = read_bundle(&bundle, &dir.join("opened")).unwrap();
+        let path = loaded.workspaces[0].file_path.clone().unwrap();
+        assert!(
+            path.starts_with(dir.join("opened").to_str().unwrap()),
+            "{}",
+            path
+        );
+        assert_eq!(std::fs::read(path).unwrap(), b"voxels");
+        assert!(loaded.workspaces[1]
+            .file_path
+            .as_ref()
+            .unwrap()
+            .ends_with("gone.stl"));
+        assert_eq!(loaded.chat_history[0].content, "make it stiffer");
+        assert_eq!(loaded.current_workspace.as_deref(), Some("ws-1"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn newer_or_foreign_bundles_are_refused() {
+        let dir = scratch_dir();
+        let write = |name: &str, manifest: &str| {
+            let path = dir.join(name);
+            let mut zip = ZipWriter::new(File::create(&path).unwrap());
+            zip.start_file(MANIFEST_NAME, SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(manifest.as_bytes()).unwrap();
+            zip.finish().unwrap();
+            path
+        };
+
+        let newer = write("newer.darwin", r#"{"format_version": 2, "workspaces": []}"#);
+        let error = read_bundle(&newer, &dir).unwrap_err();
+        assert!(error.contains("newer version"), "{}", error);
+
+        // Fields this build doesn't know about are fine within the same format
+        let extra = write(
+            "extra.darwin",
+            r#"{"format_version": 1, "notebook": "ignored"}"#,
+        );
+        assert!(read_bundle(&extra, &dir).unwrap().workspaces.is_empty());
+
+        std::fs::write(dir.join("plain.darwin"), b"not a zip").unwrap();
+        assert!(read_bundle(&dir.join("plain.darwin"), &dir).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,540 @@
+// Workspace reports - metrics against target ranges, material, preview and metadata as HTML or PDF
+
+use crate::commands::ScaffoldMetrics;
+use crate::materials::Material;
+use askama::Template;
+use printpdf::{
+    BuiltinFont, Color, Image, ImageTransform, IndirectFontRef, Mm, PdfDocument, PdfLayerReference,
+    Rgb,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Edge length of the preview rendered into reports
+pub const THUMBNAIL_PX: u32 = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Html,
+    Pdf,
+}
+
+impl ReportFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format.trim().to_ascii_lowercase().as_str() {
+            "html" => Ok(Self::Html),
+            "pdf" => Ok(Self::Pdf),
+            other => Err(format!(
+                "unknown report format \"{}\" (expected \"html\" or \"pdf\")",
+                other
+            )),
+        }
+    }
+}
+
+/// Acceptable values of one metric; an open end is unbounded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TargetRange {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl TargetRange {
+    fn contains(&self, value: f64) -> bool {
+        value.is_finite()
+            && self.min.is_none_or(|min| value >= min)
+            && self.max.is_none_or(|max| value <= max)
+    }
+
+    fn describe(&self) -> String {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => format!("{} - {}", format_value(min), format_value(max)),
+            (Some(min), None) => format!(">= {}", format_value(min)),
+            (None, Some(max)) => format!("<= {}", format_value(max)),
+            (None, None) => "any".to_string(),
+        }
+    }
+}
+
+/// Bone scaffold targets, as in Ontology/ScaffoldRecommendations.jl
+pub fn default_targets() -> BTreeMap<String, TargetRange> {
+    let range = |min, max| TargetRange { min, max };
+    BTreeMap::from([
+        ("porosity".to_string(), range(Some(0.70), Some(0.95))),
+        (
+            "mean_pore_size_um".to_string(),
+            range(Some(100.0), Some(300.0)),
+        ),
+        ("interconnectivity".to_string(), range(Some(0.80), None)),
+        (
+            "elastic_modulus".to_string(),
+            range(Some(100.0), Some(20000.0)),
+        ),
+    ])
+}
+
+/// The default targets with `overrides` applied per metric
+pub fn targets_with(
+    overrides: Option<BTreeMap<String, TargetRange>>,
+) -> Result<BTreeMap<String, TargetRange>, String> {
+    let mut targets = default_targets();
+    for (name, range) in overrides.unwrap_or_default() {
+        if !ScaffoldMetrics::FIELD_NAMES.contains(&name.as_str()) {
+            return Err(format!("unknown metric in targets: {}", name));
+        }
+        if let (Some(min), Some(max)) = (range.min, range.max) {
+            if min > max {
+                return Err(format!("target for {}: min is greater than max", name));
+            }
+        }
+        targets.insert(name, range);
+    }
+    Ok(targets)
+}
+
+fn label(field: &str) -> &'static str {
+    match field {
+        "porosity" => "Porosity",
+        "mean_pore_size_um" => "Mean pore size",
+        "interconnectivity" => "Interconnectivity",
+        "tortuosity" => "Tortuosity",
+        "specific_surface_area" => "Specific surface area",
+        "elastic_modulus" => "Elastic modulus",
+        "yield_strength" => "Yield strength",
+        "permeability" => "Permeability",
+        _ => "Unknown",
+    }
+}
+
+/// Unit of a ScaffoldMetrics field, in plain ASCII so the PDF fonts can show it;
+/// empty for fractions and ratios
+pub fn unit(field: &str) -> &'static str {
+    match field {
+        "mean_pore_size_um" => "um",
+        "specific_surface_area" => "1/mm",
+        "elastic_modulus" | "yield_strength" => "MPa",
+        "permeability" => "m^2",
+        _ => "",
+    }
+}
+
+/// Up to 3 decimals, or scientific notation for very small and large values
+pub fn format_value(value: f64) -> String {
+    if value != 0.0 && (value.abs() < 1e-3 || value.abs() >= 1e5) {
+        return format!("{:.2e}", value);
+    }
+    let text = format!("{:.3}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Pass,
+    Fail,
+    NoTarget,
+}
+
+impl Verdict {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pass => "pass",
+            Self::Fail => "fail",
+            Self::NoTarget => "no target",
+        }
+    }
+
+    fn css_class(&self) -> &'static str {
+        match self {
+            Self::Pass => "pass",
+            Self::Fail => "fail",
+            Self::NoTarget => "no-target",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MetricRow {
+    pub label: &'static str,
+    pub value: String,
+    pub unit: &'static str,
+    /// Empty when the metric has no target
+    pub target: String,
+    pub verdict: Verdict,
+}
+
+pub fn metric_rows(
+    metrics: &ScaffoldMetrics,
+    targets: &BTreeMap<String, TargetRange>,
+) -> Vec<MetricRow> {
+    metrics
+        .fields()
+        .into_iter()
+        .map(|(name, value)| {
+            let target = targets.get(name);
+            let verdict = match target {
+                None => Verdict::NoTarget,
+                Some(range) if range.contains(value) => Verdict::Pass,
+                Some(_) => Verdict::Fail,
+            };
+            MetricRow {
+                label: label(name),
+                value: format_value(value),
+                unit: unit(name),
+                target: target.map(TargetRange::describe).unwrap_or_default(),
+                verdict,
+            }
+        })
+        .collect()
+}
+
+/// A report section, or why it could not be filled in
+#[derive(Debug, Clone)]
+pub enum Section<T> {
+    Present(T),
+    Missing(String),
+}
+
+#[derive(Debug, Template)]
+#[template(path = "report.html")]
+pub struct Report {
+    pub workspace_id: String,
+    pub workspace_name: String,
+    pub source_file: Option<String>,
+    /// e.g. "2024-05-01 13:45 UTC"
+    pub generated_utc: String,
+    pub app_version: String,
+    pub metrics: Section<Vec<MetricRow>>,
+    pub material: Section<Material>,
+    /// PNG bytes
+    pub thumbnail: Section<Vec<u8>>,
+}
+
+mod filters {
+    use base64::Engine;
+
+    pub fn number(value: &f64) -> askama::Result<String> {
+        Ok(super::format_value(*value))
+    }
+
+    pub fn base64(png: &[u8]) -> askama::Result<String> {
+        Ok(base64::engine::general_purpose::STANDARD.encode(png))
+    }
+}
+
+/// `YYYY-MM-DD HH:MM UTC` for a Unix time in seconds
+pub fn format_utc(unix_secs: u64) -> String {
+    let days = (unix_secs / 86_400) as i64;
+    let secs_of_day = unix_secs % 86_400;
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60
+    )
+}
+
+pub fn render_html(report: &Report) -> Result<String, String> {
+    report.render().map_err(|e| e.to_string())
+}
+
+const PAGE_WIDTH_MM: f32 = 210.0;
+const PAGE_HEIGHT_MM: f32 = 297.0;
+const MARGIN_MM: f32 = 20.0;
+const PREVIEW_MM: f32 = 70.0;
+
+/// Text cursor moving down an A4 page
+struct PdfPage {
+    layer: PdfLayerReference,
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+    y: f32,
+}
+
+impl PdfPage {
+    fn text(&mut self, text: &str, size: f32, x: f32, bold: bool) {
+        let font = if bold { &self.bold } else { &self.regular };
+        self.layer.use_text(text, size, Mm(x), Mm(self.y), font);
+    }
+
+    fn line(&mut self, text: &str, size: f32, bold: bool) {
+        self.text(text, size, MARGIN_MM, bold);
+        self.y -= size * 0.5;
+    }
+
+    fn heading(&mut self, text: &str) {
+        self.y -= 4.0;
+        self.line(text, 14.0, true);
+        self.y -= 1.0;
+    }
+
+    fn missing(&mut self, reason: &str) {
+        self.layer
+            .set_fill_color(Color::Rgb(Rgb::new(0.54, 0.38, 0.0, None)));
+        self.line(&format!("Missing: {}", reason), 10.0, false);
+        self.layer
+            .set_fill_color(Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+    }
+}
+
+/// The same content as the HTML report, laid out on one A4 page with the
+/// built-in Helvetica fonts
+pub fn render_pdf(report: &Report) -> Result<Vec<u8>, String> {
+    let title = format!("Scaffold report - {}", report.workspace_name);
+    let (doc, page, layer) =
+        PdfDocument::new(&title, Mm(PAGE_WIDTH_MM), Mm(PAGE_HEIGHT_MM), "Report");
+    let layer = doc.get_page(page).get_layer(layer);
+    let regular = doc
+        .add_builtin_font(BuiltinFont::Helvetica)
+        .map_err(|e| e.to_string())?;
+    let bold = doc
+        .add_builtin_font(BuiltinFont::HelveticaBold)
+        .map_err(|e| e.to_string())?;
+    let mut pdf = PdfPage {
+        layer,
+        regular,
+        bold,
+        y: PAGE_HEIGHT_MM - MARGIN_MM,
+    };
+
+    pdf.line(&report.workspace_name, 20.0, true);
+    pdf.line(&format!("Workspace {}", report.workspace_id), 10.0, false);
+    if let Some(path) = &report.source_file {
+        pdf.line(path, 10.0, false);
+    }
+    pdf.line(
+        &format!(
+            "Generated {} by Darwin Scaffold Studio {}",
+            report.generated_utc, report.app_version
+        ),
+        10.0,
+        false,
+    );
+
+    pdf.heading("Preview");
+    match &report.thumbnail {
+        Section::Present(png) => {
+            let decoder = printpdf::image_crate::codecs::png::PngDecoder::new(
+                std::io::Cursor::new(png.as_slice()),
+            )
+            .map_err(|e| format!("thumbnail is not a valid PNG: {}", e))?;
+            let image = Image::try_from(decoder).map_err(|e| e.to_string())?;
+            let width_px = image.image.width.0 as f32;
+            // Width in points at the chosen dpi is width_px * 72 / dpi
+            let dpi = width_px * 25.4 / PREVIEW_MM;
+            pdf.y -= PREVIEW_MM;
+            image.add_to_layer(
+                pdf.layer.clone(),
+                ImageTransform {
+                    translate_x: Some(Mm(MARGIN_MM)),
+                    translate_y: Some(Mm(pdf.y)),
+                    dpi: Some(dpi),
+                    ..Default::default()
+                },
+            );
+            pdf.y -= 6.0;
+        }
+        Section::Missing(reason) => pdf.missing(reason),
+    }
+
+    pdf.heading("Material");
+    match &report.material {
+        Section::Present(material) => {
+            let degradation = material
+                .degradation_months
+                .map(|m| format!("{} months", format_value(m)))
+                .unwrap_or_else(|| "non-degrading".to_string());
+            for (name, value) in [
+                ("Name", material.name.clone()),
+                (
+                    "Young's modulus",
+                    format!("{} MPa", format_value(material.youngs_modulus_mpa)),
+                ),
+                (
+                    "Density",
+                    format!("{} g/cm^3", format_value(material.density_g_cm3)),
+                ),
+                ("Degradation", degradation),
+                ("Biocompatibility", material.biocompatibility_class.clone()),
+            ] {
+                pdf.text(name, 10.0, MARGIN_MM, true);
+                pdf.line(&value, 10.0, false);
+                pdf.y -= 1.0;
+            }
+        }
+        Section::Missing(reason) => pdf.missing(reason),
+    }
+
+    pdf.heading("Metrics");
+    match &report.metrics {
+        Section::Present(rows) => {
+            let columns = [MARGIN_MM, 70.0, 100.0, 120.0, 160.0];
+            for (x, header) in columns
+                .iter()
+                .zip(["Metric", "Value", "Unit", "Target", "Status"])
+            {
+                pdf.text(header, 10.0, *x, true);
+            }
+            pdf.y -= 6.0;
+            for row in rows {
+                for (x, cell) in columns
+                    .iter()
+                    .zip([row.label, &row.value, row.unit, &row.target])
+                {
+                    pdf.text(cell, 10.0, *x, false);
+                }
+                let color = match row.verdict {
+                    Verdict::Pass => Rgb::new(0.12, 0.48, 0.23, None),
+                    Verdict::Fail => Rgb::new(0.71, 0.14, 0.09, None),
+                    Verdict::NoTarget => Rgb::new(0.36, 0.39, 0.46, None),
+                };
+                pdf.layer.set_fill_color(Color::Rgb(color));
+                pdf.text(row.verdict.as_str(), 10.0, columns[4], true);
+                pdf.layer
+                    .set_fill_color(Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+                pdf.y -= 6.0;
+            }
+        }
+        Section::Missing(reason) => pdf.missing(reason),
+    }
+
+    doc.save_to_bytes().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics() -> ScaffoldMetrics {
+        ScaffoldMetrics {
+            porosity: 0.82,
+            mean_pore_size_um: 450.0,
+            interconnectivity: 0.97,
+            tortuosity: 1.15,
+            specific_surface_area: 12.5,
+            elastic_modulus: 350.0,
+            yield_strength: 4.2,
+            permeability: 2.5e-10,
+        }
+    }
+
+    fn report(metrics: Section<Vec<MetricRow>>) -> Report {
+        Report {
+            workspace_id: "ws-1".to_string(),
+            workspace_name: "Gyroid <80%>".to_string(),
+            source_file: None,
+            generated_utc: format_utc(0),
+            app_version: "1.0.0".to_string(),
+            metrics,
+            material: Section::Missing("material PEEK is not in the database".to_string()),
+            thumbnail: Section::Missing(
+                "workspace ws-1 has no scaffold to preview yet".to_string(),
+            ),
+        }
+    }
+
+    #[test]
+    fn rows_are_checked_against_targets() {
+        let rows = metric_rows(&metrics(), &default_targets());
+        let verdicts: Vec<(&str, &str, Verdict)> =
+            rows.iter().map(|r| (r.label, r.unit, r.verdict)).collect();
+        assert_eq!(
+            verdicts,
+            [
+                ("Porosity", "", Verdict::Pass),
+                ("Mean pore size", "um", Verdict::Fail),
+                ("Interconnectivity", "", Verdict::Pass),
+                ("Tortuosity", "", Verdict::NoTarget),
+                ("Specific surface area", "1/mm", Verdict::NoTarget),
+                ("Elastic modulus", "MPa", Verdict::Pass),
+                ("Yield strength", "MPa", Verdict::NoTarget),
+                ("Permeability", "m^2", Verdict::NoTarget),
+            ]
+        );
+        assert_eq!(rows[1].target, "100 - 300");
+        assert_eq!(rows[2].target, ">= 0.8");
+        assert_eq!(rows[7].value, "2.50e-10");
+
+        let overrides = BTreeMap::from([(
+            "mean_pore_size_um".to_string(),
+            TargetRange {
+                min: Some(300.0),
+                max: Some(600.0),
+            },
+        )]);
+        let rows = metric_rows(&metrics(), &targets_with(Some(overrides)).unwrap());
+        assert_eq!(rows[1].verdict, Verdict::Pass);
+    }
+
+    #[test]
+    fn invalid_targets_are_rejected() {
+        let overrides = |name: &str, min, max| {
+            Some(BTreeMap::from([(
+                name.to_string(),
+                TargetRange { min, max },
+            )]))
+        };
+        assert_eq!(
+            targets_with(overrides("stiffness", None, Some(1.0))).unwrap_err(),
+            "unknown metric in targets: stiffness"
+        );
+        assert!(targets_with(overrides("porosity", Some(0.9), Some(0.5))).is_err());
+    }
+
+    #[test]
+    fn html_marks_missing_sections() {
+        let html = render_html(&report(Section::Missing(
+            "no metrics computed yet".to_string(),
+        )))
+        .unwrap();
+        assert!(html.contains("<h1>Gyroid &lt;80%&gt;</h1>"));
+        assert!(html.contains("generated 1970-01-01 00:00 UTC"));
+        assert!(html.contains("Missing: no metrics computed yet"));
+        assert!(html.contains("Missing: material PEEK is not in the database"));
+
+        let rows = metric_rows(&metrics(), &default_targets());
+        let html = render_html(&report(Section::Present(rows))).unwrap();
+        assert!(html.contains(r#"<td class="status fail">fail</td>"#));
+        assert!(!html.contains("Missing: no metrics"));
+    }
+
+    #[test]
+    fn pdf_renders_with_and_without_sections() {
+        let pdf = render_pdf(&report(Section::Missing(
+            "no metrics computed yet".to_string(),
+        )))
+        .unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+
+        let mut png = Vec::new();
+        printpdf::image_crate::RgbImage::new(8, 8)
+            .write_to(
+                &mut std::io::Cursor::new(&mut png),
+                printpdf::image_crate::ImageOutputFormat::Png,
+            )
+            .unwrap();
+        let mut full = report(Section::Present(metric_rows(
+            &metrics(),
+            &default_targets(),
+        )));
+        full.thumbnail = Section::Present(png);
+        assert!(render_pdf(&full).unwrap().starts_with(b"%PDF-"));
+    }
+
+    #[test]
+    fn utc_dates() {
+        assert_eq!(format_utc(1_714_571_100), "2024-05-01 13:45 UTC");
+        assert_eq!(format_utc(951_782_400), "2000-02-29 00:00 UTC");
+    }
+}
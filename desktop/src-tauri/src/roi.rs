@@ -0,0 +1,108 @@
+// Region of interest - a voxel-space box that analysis is cropped to
+
+use serde::Serialize;
+
+const AXES: [&str; 3] = ["x", "y", "z"];
+
+/// Half-open voxel box `[x0, x1) x [y0, y1) x [z0, z1)`, as sent by the frontend
+/// in the order `[x0, y0, z0, x1, y1, z1]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(into = "[u32; 6]")]
+pub struct Roi {
+    pub min: [u32; 3],
+    pub max: [u32; 3],
+}
+
+impl From<Roi> for [u32; 6] {
+    fn from(roi: Roi) -> Self {
+        let [x0, y0, z0] = roi.min;
+        let [x1, y1, z1] = roi.max;
+        [x0, y0, z0, x1, y1, z1]
+    }
+}
+
+impl Roi {
+    /// Check that every axis has an upper bound past its lower bound.
+    pub fn new(coords: [u32; 6]) -> Result<Self, String> {
+        let [x0, y0, z0, x1, y1, z1] = coords;
+        let roi = Self {
+            min: [x0, y0, z0],
+            max: [x1, y1, z1],
+        };
+        for (axis, (lo, hi)) in AXES.iter().zip(roi.min.into_iter().zip(roi.max)) {
+            if hi < lo {
+                return Err(format!(
+                    "roi is inverted along {}: {}1 = {} is below {}0 = {}",
+                    axis, axis, hi, axis, lo
+                ));
+            }
+            if hi == lo {
+                return Err(format!(
+                    "roi is empty along {}: {}0 and {}1 are both {}",
+                    axis, axis, axis, lo
+                ));
+            }
+        }
+        Ok(roi)
+    }
+
+    /// Check that the box lies inside a volume of `dimensions` voxels.
+    pub fn check_within(&self, dimensions: [u32; 3]) -> Result<(), String> {
+        for (axis, (hi, size)) in AXES.iter().zip(self.max.into_iter().zip(dimensions)) {
+            if hi > size {
+                return Err(format!(
+                    "roi is out of bounds along {}: {}1 = {} exceeds the volume size of {} voxels",
+                    axis, axis, hi, size
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Size of the box in voxels along each axis.
+    pub fn dimensions(&self) -> [u32; 3] {
+        [0, 1, 2].map(|i| self.max[i] - self.min[i])
+    }
+
+    pub fn voxel_count(&self) -> u64 {
+        (0..3).map(|i| (self.max[i] - self.min[i]) as u64).product()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_box_counts_its_voxels_and_serializes_flat() {
+        let roi = Roi::new([10, 0, 5, 20, 4, 6]).unwrap();
+        assert_eq!(roi.dimensions(), [10, 4, 1]);
+        assert_eq!(roi.voxel_count(), 10 * 4);
+        assert_eq!(roi.check_within([20, 4, 6]), Ok(()));
+        assert_eq!(
+            serde_json::to_value(roi).unwrap(),
+            serde_json::json!([10, 0, 5, 20, 4, 6])
+        );
+    }
+
+    #[test]
+    fn inverted_and_degenerate_boxes_are_rejected() {
+        assert_eq!(
+            Roi::new([0, 8, 0, 10, 2, 10]).unwrap_err(),
+            "roi is inverted along y: y1 = 2 is below y0 = 8"
+        );
+        assert_eq!(
+            Roi::new([0, 0, 3, 10, 10, 3]).unwrap_err(),
+            "roi is empty along z: z0 and z1 are both 3"
+        );
+    }
+
+    #[test]
+    fn boxes_past_the_volume_are_out_of_bounds() {
+        let roi = Roi::new([0, 0, 0, 64, 65, 10]).unwrap();
+        assert_eq!(
+            roi.check_within([64, 64, 32]).unwrap_err(),
+            "roi is out of bounds along y: y1 = 65 exceeds the volume size of 64 voxels"
+        );
+    }
+}
@@ -0,0 +1,399 @@
+// Scaffold info - volume size and voxel spacing from TIFF/NIfTI headers, without loading voxels
+
+use flate2::read::GzDecoder;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Stop walking TIFF page chains that are corrupt or looping
+const MAX_TIFF_PAGES: u32 = 100_000;
+/// ImageJ descriptions are a few lines; anything longer is not worth reading
+const MAX_DESCRIPTION_BYTES: u32 = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScaffoldInfo {
+    /// "tiff", "nifti" or "unknown"
+    pub format: String,
+    pub file_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dimensions_voxels: Option<[u32; 3]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voxel_size_mm: Option<[f64; 3]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bounding_box_mm: Option<[f64; 3]>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Header {
+    dimensions: Option<[u32; 3]>,
+    voxel_size_mm: Option<[f64; 3]>,
+}
+
+/// Read what the header of `path` says about the volume. Unknown formats and
+/// unreadable headers still report the file size, with `format` "unknown".
+pub fn read_info(path: &Path) -> Result<ScaffoldInfo, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let file_bytes = file.metadata().map_err(|e| e.to_string())?.len();
+
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    let parsed = if name.ends_with(".tif") || name.ends_with(".tiff") {
+        read_tiff(&mut BufReader::new(file)).map(|h| ("tiff", h))
+    } else if name.ends_with(".nii.gz") {
+        read_nifti(&mut GzDecoder::new(file)).map(|h| ("nifti", h))
+    } else if name.ends_with(".nii") {
+        read_nifti(&mut BufReader::new(file)).map(|h| ("nifti", h))
+    } else {
+        None
+    };
+
+    let (format, header) = parsed.unwrap_or(("unknown", Header::default()));
+    let bounding_box_mm = match (header.dimensions, header.voxel_size_mm) {
+        (Some(d), Some(v)) => Some([0, 1, 2].map(|i| d[i] as f64 * v[i])),
+        _ => None,
+    };
+    Ok(ScaffoldInfo {
+        format: format.to_string(),
+        file_bytes,
+        dimensions_voxels: header.dimensions,
+        voxel_size_mm: header.voxel_size_mm,
+        bounding_box_mm,
+    })
+}
+
+/// NIfTI-1 header: `dim[1..=3]` and `pixdim[1..=3]` scaled by `xyzt_units`
+fn read_nifti<R: Read>(reader: &mut R) -> Option<Header> {
+    let mut bytes = [0u8; 348];
+    reader.read_exact(&mut bytes).ok()?;
+    let little = match (
+        i32::from_le_bytes(bytes[..4].try_into().ok()?),
+        i32::from_be_bytes(bytes[..4].try_into().ok()?),
+    ) {
+        (348, _) => true,
+        (_, 348) => false,
+        _ => return None,
+    };
+
+    let i16_at = |offset: usize| {
+        let raw = [bytes[offset], bytes[offset + 1]];
+        if little {
+            i16::from_le_bytes(raw)
+        } else {
+            i16::from_be_bytes(raw)
+        }
+    };
+    let f32_at = |offset: usize| {
+        let raw = [
+            bytes[offset],
+            bytes[offset + 1],
+            bytes[offset + 2],
+            bytes[offset + 3],
+        ];
+        if little {
+            f32::from_le_bytes(raw)
+        } else {
+            f32::from_be_bytes(raw)
+        }
+    };
+
+    // Unused axes are recorded as 0 or 1
+    let dimensions = [42, 44, 46].map(|offset| i16_at(offset).max(1) as u32);
+    let scale = match bytes[123] & 0x07 {
+        1 => 1000.0, // metre
+        3 => 0.001,  // micron
+        _ => 1.0,    // millimetre, or unknown (mm by convention)
+    };
+    let spacing = [80, 84, 88].map(|offset| f32_at(offset).abs() as f64 * scale);
+    let voxel_size_mm = spacing
+        .iter()
+        .all(|v| v.is_finite() && *v > 0.0)
+        .then_some(spacing);
+
+    Some(Header {
+        dimensions: Some(dimensions),
+        voxel_size_mm,
+    })
+}
+
+struct TiffReader<'a, R> {
+    reader: &'a mut R,
+    little: bool,
+}
+
+impl<R: Read + Seek> TiffReader<'_, R> {
+    fn u16_at(&mut self, offset: u64) -> Option<u16> {
+        let mut raw = [0u8; 2];
+        self.reader.seek(SeekFrom::Start(offset)).ok()?;
+        self.reader.read_exact(&mut raw).ok()?;
+        Some(if self.little {
+            u16::from_le_bytes(raw)
+        } else {
+            u16::from_be_bytes(raw)
+        })
+    }
+
+    fn u32_at(&mut self, offset: u64) -> Option<u32> {
+        let mut raw = [0u8; 4];
+        self.reader.seek(SeekFrom::Start(offset)).ok()?;
+        self.reader.read_exact(&mut raw).ok()?;
+        Some(if self.little {
+            u32::from_le_bytes(raw)
+        } else {
+            u32::from_be_bytes(raw)
+        })
+    }
+
+    fn bytes_at(&mut self, offset: u64, len: usize) -> Option<Vec<u8>> {
+        let mut raw = vec![0u8; len];
+        self.reader.seek(SeekFrom::Start(offset)).ok()?;
+        self.reader.read_exact(&mut raw).ok()?;
+        Some(raw)
+    }
+}
+
+/// Fields of the first page that describe the stack
+#[derive(Debug, Default)]
+struct TiffPage {
+    width: Option<u32>,
+    height: Option<u32>,
+    x_resolution: Option<f64>,
+    resolution_unit: Option<u16>,
+    description: Option<String>,
+}
+
+/// Classic (not Big) TIFF: size of the first page times the page count, and
+/// spacing from XResolution or an ImageJ description (`unit=`, `spacing=`)
+fn read_tiff<R: Read + Seek>(reader: &mut R) -> Option<Header> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic).ok()?;
+    let little = match magic {
+        [b'I', b'I', 42, 0] => true,
+        [b'M', b'M', 0, 42] => false,
+        _ => return None,
+    };
+    let mut tiff = TiffReader { reader, little };
+
+    let first_ifd = tiff.u32_at(4)? as u64;
+    let mut page = TiffPage::default();
+    let entries = tiff.u16_at(first_ifd)?;
+    for i in 0..entries as u64 {
+        let entry = first_ifd + 2 + i * 12;
+        let tag = tiff.u16_at(entry)?;
+        let field_type = tiff.u16_at(entry + 2)?;
+        let count = tiff.u32_at(entry + 4)?;
+        // SHORT values sit left-justified in the 4-byte value field
+        let short_or_long = |tiff: &mut TiffReader<R>| match field_type {
+            3 => tiff.u16_at(entry + 8).map(u32::from),
+            _ => tiff.u32_at(entry + 8),
+        };
+        match tag {
+            256 => page.width = short_or_long(&mut tiff),
+            257 => page.height = short_or_long(&mut tiff),
+            282 => {
+                let offset = tiff.u32_at(entry + 8)? as u64;
+                let (num, den) = (tiff.u32_at(offset)?, tiff.u32_at(offset + 4)?);
+                page.x_resolution = (den != 0).then(|| num as f64 / den as f64);
+            }
+            296 => page.resolution_unit = tiff.u16_at(entry + 8),
+            270 => {
+                let offset = if count <= 4 {
+                    entry + 8
+                } else {
+                    tiff.u32_at(entry + 8)? as u64
+                };
+                let raw = tiff.bytes_at(offset, count.min(MAX_DESCRIPTION_BYTES) as usize)?;
+                page.description = Some(String::from_utf8_lossy(&raw).into_owned());
+            }
+            _ => {}
+        }
+    }
+
+    let imagej = page.description.as_deref().and_then(|d| {
+        d.starts_with("ImageJ=").then(|| {
+            d.lines()
+                .filter_map(|line| line.split_once('='))
+                .collect::<Vec<_>>()
+        })
+    });
+    let imagej_value = |key: &str| {
+        imagej
+            .as_ref()
+            .and_then(|fields| fields.iter().find(|(k, _)| *k == key))
+            .map(|(_, v)| v.trim())
+    };
+
+    // ImageJ stacks over 4 GB keep only the first IFD, so trust `images=` first
+    let depth = match imagej_value("images").and_then(|n| n.parse::<u32>().ok()) {
+        Some(n) => n.max(1),
+        None => {
+            let mut pages = 1;
+            let mut ifd = first_ifd;
+            while pages < MAX_TIFF_PAGES {
+                let entries = tiff.u16_at(ifd)? as u64;
+                let next = tiff.u32_at(ifd + 2 + entries * 12)? as u64;
+                if next == 0 || next <= ifd {
+                    break;
+                }
+                ifd = next;
+                pages += 1;
+            }
+            pages
+        }
+    };
+
+    let dimensions = match (page.width, page.height) {
+        (Some(width), Some(height)) => Some([width, height, depth]),
+        _ => None,
+    };
+
+    let unit_mm = match imagej_value("unit") {
+        Some("mm") => Some(1.0),
+        Some("micron") | Some("um") | Some("\u{b5}m") => Some(0.001),
+        Some("nm") => Some(1e-6),
+        Some("cm") => Some(10.0),
+        Some(_) => None,
+        None => match page.resolution_unit.unwrap_or(2) {
+            2 => Some(25.4), // inch
+            3 => Some(10.0), // centimetre
+            _ => None,
+        },
+    };
+    let voxel_size_mm = match (unit_mm, page.x_resolution) {
+        (Some(unit_mm), Some(resolution)) if resolution > 0.0 => {
+            let xy = unit_mm / resolution;
+            let z = imagej_value("spacing")
+                .and_then(|s| s.parse::<f64>().ok())
+                .map_or(xy, |s| s * unit_mm);
+            Some([xy, xy, z]).filter(|v| v.iter().all(|s| s.is_finite() && *s > 0.0))
+        }
+        _ => None,
+    };
+
+    Some(Header {
+        dimensions,
+        voxel_size_mm,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Little-endian TIFF with `pages` 1x1 pages; the first carries size,
+    /// resolution and description
+    fn tiff(width: u16, height: u16, pages: u32, resolution: u32, description: &str) -> Vec<u8> {
+        let mut out = b"II\x2a\x00".to_vec();
+        out.extend(8u32.to_le_bytes());
+
+        let description = format!("{}\0", description);
+        let entries: u16 = 5;
+        let ifd_len = 2 + entries as u32 * 12 + 4;
+        let resolution_offset = 8 + ifd_len;
+        let description_offset = resolution_offset + 8;
+        let next_ifd = description_offset + description.len() as u32;
+
+        let entry = |out: &mut Vec<u8>, tag: u16, field_type: u16, count: u32, value: u32| {
+            out.extend(tag.to_le_bytes());
+            out.extend(field_type.to_le_bytes());
+            out.extend(count.to_le_bytes());
+            out.extend(value.to_le_bytes());
+        };
+        out.extend(entries.to_le_bytes());
+        entry(&mut out, 256, 3, 1, width as u32);
+        entry(&mut out, 257, 3, 1, height as u32);
+        entry(
+            &mut out,
+            270,
+            2,
+            description.len() as u32,
+            description_offset,
+        );
+        entry(&mut out, 282, 5, 1, resolution_offset);
+        entry(&mut out, 296, 3, 1, 1);
+        out.extend((if pages > 1 { next_ifd } else { 0 }).to_le_bytes());
+        out.extend(resolution.to_le_bytes());
+        out.extend(1u32.to_le_bytes());
+        out.extend(description.as_bytes());
+
+        for page in 1..pages {
+            let offset = out.len() as u32;
+            out.extend(0u16.to_le_bytes());
+            let next = if page + 1 < pages { offset + 6 } else { 0 };
+            out.extend(next.to_le_bytes());
+        }
+        out
+    }
+
+    fn nifti(dims: [i16; 3], pixdim: [f32; 3], units: u8) -> Vec<u8> {
+        let mut out = vec![0u8; 352];
+        out[..4].copy_from_slice(&348i32.to_le_bytes());
+        for (i, d) in dims.iter().enumerate() {
+            out[42 + i * 2..44 + i * 2].copy_from_slice(&d.to_le_bytes());
+        }
+        for (i, p) in pixdim.iter().enumerate() {
+            out[80 + i * 4..84 + i * 4].copy_from_slice(&p.to_le_bytes());
+        }
+        out[123] = units;
+        out
+    }
+
+    fn assert_close(actual: Option<[f64; 3]>, expected: [f64; 3]) {
+        let actual = actual.expect("voxel size");
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-12, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn imagej_tiff_stack_size_and_spacing() {
+        let bytes = tiff(200, 100, 3, 100, "ImageJ=1.53t\nunit=micron\nspacing=20\n");
+        let header = read_tiff(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(header.dimensions, Some([200, 100, 3]));
+        assert_close(header.voxel_size_mm, [1e-5, 1e-5, 0.02]);
+
+        let declared = tiff(64, 64, 1, 1, "ImageJ=1.53t\nimages=250\nunit=mm\n");
+        let header = read_tiff(&mut Cursor::new(declared)).unwrap();
+        assert_eq!(header.dimensions, Some([64, 64, 250]));
+        assert_eq!(header.voxel_size_mm, Some([1.0, 1.0, 1.0]));
+
+        // No unit anywhere (ResolutionUnit = none): size only
+        let plain = tiff(8, 8, 2, 1, "scan");
+        let header = read_tiff(&mut Cursor::new(plain)).unwrap();
+        assert_eq!(header.dimensions, Some([8, 8, 2]));
+        assert_eq!(header.voxel_size_mm, None);
+    }
+
+    #[test]
+    fn nifti_header_in_microns() {
+        let header = read_nifti(&mut Cursor::new(nifti([64, 32, 16], [10.0, 10.0, 20.0], 3)));
+        let header = header.unwrap();
+        assert_eq!(header.dimensions, Some([64, 32, 16]));
+        assert_close(header.voxel_size_mm, [0.01, 0.01, 0.02]);
+        assert!(read_nifti(&mut Cursor::new(vec![0u8; 348])).is_none());
+    }
+
+    #[test]
+    fn unknown_format_reports_only_size() {
+        let dir = std::env::temp_dir().join(format!("darwin_info_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let raw = dir.join("scan.raw");
+        std::fs::write(&raw, [0u8; 1000]).unwrap();
+        let info = read_info(&raw).unwrap();
+        assert_eq!(info.format, "unknown");
+        assert_eq!(info.file_bytes, 1000);
+        assert_eq!(info.dimensions_voxels, None);
+
+        let nii = dir.join("scan.nii");
+        std::fs::write(&nii, nifti([10, 20, 30], [0.5, 0.5, 1.0], 2)).unwrap();
+        let info = read_info(&nii).unwrap();
+        assert_eq!(info.format, "nifti");
+        assert_eq!(info.bounding_box_mm, Some([5.0, 10.0, 30.0]));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
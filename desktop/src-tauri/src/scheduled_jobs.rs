@@ -0,0 +1,447 @@
+// Scheduled reprocessing - cron-triggered re-analysis of library scaffolds, persisted as scheduled_jobs.json
+
+use crate::commands::ScaffoldMetrics;
+use crate::library::{LibraryHit, ScaffoldQuery};
+use chrono::{DateTime, TimeZone};
+use cron::Schedule;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+pub const SCHEDULED_JOBS_FILE: &str = "scheduled_jobs.json";
+/// Scaffolds one trigger re-analyzes at once
+pub const REPROCESS_CONCURRENCY: usize = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledJob {
+    pub id: String,
+    /// As given to `schedule_reprocess`
+    pub cron_expr: String,
+    pub filter: ScaffoldQuery,
+    /// Unix time in milliseconds
+    pub created: u64,
+    pub last_run: Option<ReprocessSummary>,
+}
+
+/// What one trigger did
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReprocessSummary {
+    /// Unix time in milliseconds
+    pub started: u64,
+    pub analyzed: usize,
+    /// `file_id -> error` for matching scaffolds that could not be re-analyzed
+    pub failed: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduledJobInfo {
+    #[serde(flatten)]
+    pub job: ScheduledJob,
+    /// Unix time in milliseconds of the next trigger, in local time
+    pub next_run: Option<u64>,
+}
+
+/// A library scaffold a trigger re-analyzes
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReprocessTarget {
+    pub file_id: String,
+    pub file_path: String,
+    pub voxel_size_um: Option<f64>,
+}
+
+/// `id -> job`, loaded from `scheduled_jobs.json` the first time it is used, plus
+/// a cancel signal for each job whose trigger loop is running
+#[derive(Debug, Default)]
+pub struct ScheduledJobs {
+    jobs: Option<BTreeMap<String, ScheduledJob>>,
+    running: HashMap<String, Arc<Notify>>,
+}
+
+/// Parse `sec min hour day month weekday [year]`, the classic five fields
+/// (`min hour day month weekday`, firing at second 0) or a shortcut like `@daily`.
+/// Weekdays are safest by name (`MON-FRI`): numbered ones count from 1 = Sunday.
+pub fn parse_cron(expr: &str) -> Result<Schedule, String> {
+    let expr = expr.trim();
+    let full = if expr.split_whitespace().count() == 5 {
+        format!("0 {}", expr)
+    } else {
+        expr.to_string()
+    };
+    Schedule::from_str(&full).map_err(|e| format!("invalid cron expression '{}': {}", expr, e))
+}
+
+/// First trigger strictly after `after`, as Unix milliseconds
+pub fn next_run<Tz: TimeZone>(schedule: &Schedule, after: &DateTime<Tz>) -> Option<u64> {
+    schedule
+        .after(after)
+        .next()
+        .map(|t| t.timestamp_millis() as u64)
+}
+
+pub fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Split library hits into scaffolds that can be re-analyzed and, by `file_id`,
+/// the reason the others cannot
+pub fn select_targets(hits: Vec<LibraryHit>) -> (Vec<ReprocessTarget>, BTreeMap<String, String>) {
+    let mut targets = Vec::new();
+    let mut skipped = BTreeMap::new();
+    for hit in hits {
+        match hit.entry.file_path {
+            Some(file_path) => targets.push(ReprocessTarget {
+                file_id: hit.file_id,
+                file_path,
+                voxel_size_um: hit.entry.voxel_size_um,
+            }),
+            None => {
+                skipped.insert(hit.file_id, "no file_path in the library entry".to_string());
+            }
+        }
+    }
+    (targets, skipped)
+}
+
+/// Run `analyze` on every target, at most `concurrency` at a time; results come
+/// back in completion order
+pub async fn reprocess<F, Fut>(
+    targets: Vec<ReprocessTarget>,
+    concurrency: usize,
+    analyze: F,
+) -> Vec<(String, Result<ScaffoldMetrics, String>)>
+where
+    F: Fn(ReprocessTarget) -> Fut,
+    Fut: Future<Output = Result<ScaffoldMetrics, String>> + Send + 'static,
+{
+    let mut pending = targets.into_iter();
+    let mut running = tokio::task::JoinSet::new();
+    let mut file_ids = HashMap::new();
+    let mut results = Vec::new();
+    loop {
+        while running.len() < concurrency.max(1) {
+            let Some(target) = pending.next() else { break };
+            let handle = running.spawn(analyze(target.clone()));
+            file_ids.insert(handle.id(), target.file_id);
+        }
+        let Some(joined) = running.join_next_with_id().await else {
+            return results;
+        };
+        match joined {
+            Ok((id, result)) => results.push((file_ids.remove(&id).unwrap_or_default(), result)),
+            Err(e) => results.push((
+                file_ids.remove(&e.id()).unwrap_or_default(),
+                Err(format!("analysis task failed: {}", e)),
+            )),
+        }
+    }
+}
+
+impl ReprocessSummary {
+    pub fn new(started: u64, skipped: BTreeMap<String, String>) -> Self {
+        Self {
+            started,
+            analyzed: 0,
+            failed: skipped,
+        }
+    }
+
+    pub fn add(&mut self, file_id: String, result: &Result<ScaffoldMetrics, String>) {
+        match result {
+            Ok(_) => self.analyzed += 1,
+            Err(e) => {
+                self.failed.insert(file_id, e.clone());
+            }
+        }
+    }
+}
+
+impl ScheduledJobs {
+    fn load(&mut self, path: &Path) -> Result<&mut BTreeMap<String, ScheduledJob>, String> {
+        if self.jobs.is_none() {
+            let jobs = match std::fs::read_to_string(path) {
+                Ok(text) => serde_json::from_str(&text)
+                    .map_err(|e| format!("{} is corrupt: {}", path.display(), e))?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+                Err(e) => return Err(e.to_string()),
+            };
+            self.jobs = Some(jobs);
+        }
+        Ok(self.jobs.as_mut().unwrap())
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_string_pretty(&self.jobs).map_err(|e| e.to_string())?;
+        std::fs::write(path, json).map_err(|e| e.to_string())
+    }
+
+    /// Persist a new job; fails on an invalid `cron_expr`
+    pub fn add(
+        &mut self,
+        path: &Path,
+        cron_expr: &str,
+        filter: ScaffoldQuery,
+    ) -> Result<ScheduledJob, String> {
+        parse_cron(cron_expr)?;
+        let job = ScheduledJob {
+            id: uuid::Uuid::new_v4().to_string(),
+            cron_expr: cron_expr.trim().to_string(),
+            filter,
+            created: now_millis(),
+            last_run: None,
+        };
+        self.load(path)?.insert(job.id.clone(), job.clone());
+        self.save(path)?;
+        Ok(job)
+    }
+
+    pub fn get(&mut self, path: &Path, id: &str) -> Result<Option<ScheduledJob>, String> {
+        Ok(self.load(path)?.get(id).cloned())
+    }
+
+    /// All jobs, oldest first
+    pub fn list(&mut self, path: &Path) -> Result<Vec<ScheduledJob>, String> {
+        let mut jobs: Vec<ScheduledJob> = self.load(path)?.values().cloned().collect();
+        jobs.sort_by(|a, b| a.created.cmp(&b.created).then(a.id.cmp(&b.id)));
+        Ok(jobs)
+    }
+
+    /// Store the outcome of a trigger; a job cancelled meanwhile stays gone
+    pub fn record_run(
+        &mut self,
+        path: &Path,
+        id: &str,
+        summary: ReprocessSummary,
+    ) -> Result<(), String> {
+        match self.load(path)?.get_mut(id) {
+            Some(job) => job.last_run = Some(summary),
+            None => return Ok(()),
+        }
+        self.save(path)
+    }
+
+    /// Delete the job and stop its trigger loop; `false` if there was no such job
+    pub fn remove(&mut self, path: &Path, id: &str) -> Result<bool, String> {
+        let removed = self.load(path)?.remove(id).is_some();
+        if removed {
+            self.save(path)?;
+        }
+        if let Some(cancelled) = self.running.remove(id) {
+            cancelled.notify_one();
+        }
+        Ok(removed)
+    }
+
+    /// Mark the trigger loop of `id` as running; the signal fires when the job is
+    /// cancelled. `None` if a loop for it is already running.
+    pub fn start(&mut self, id: &str) -> Option<Arc<Notify>> {
+        if self.running.contains_key(id) {
+            return None;
+        }
+        let cancelled = Arc::new(Notify::new());
+        self.running.insert(id.to_string(), cancelled.clone());
+        Some(cancelled)
+    }
+
+    /// The trigger loop of `id` has exited
+    pub fn stopped(&mut self, id: &str) {
+        self.running.remove(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::{LibraryEntry, ScaffoldLibrary, LIBRARY_FILE};
+    use chrono::Utc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn metrics(porosity: f64) -> ScaffoldMetrics {
+        ScaffoldMetrics {
+            porosity,
+            mean_pore_size_um: 250.0,
+            interconnectivity: 0.95,
+            tortuosity: 1.2,
+            specific_surface_area: 10.0,
+            elastic_modulus: 100.0,
+            yield_strength: 5.0,
+            permeability: 1e-9,
+        }
+    }
+
+    fn at(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn millis(rfc3339: &str) -> Option<u64> {
+        Some(at(rfc3339).timestamp_millis() as u64)
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir()
+            .join(format!("scheduled-jobs-{}-{}", name, uuid::Uuid::new_v4()))
+            .join(SCHEDULED_JOBS_FILE)
+    }
+
+    #[test]
+    fn cron_expressions_accept_five_or_six_fields() {
+        let nightly = parse_cron(" 30 2 * * * ").unwrap();
+        assert_eq!(
+            next_run(&nightly, &at("2026-03-01T12:00:00Z")),
+            millis("2026-03-02T02:30:00Z")
+        );
+
+        let weekdays = parse_cron("0 0 3 * * MON-FRI").unwrap();
+        // 2026-03-06 is a Friday
+        assert_eq!(
+            next_run(&weekdays, &at("2026-03-06T04:00:00Z")),
+            millis("2026-03-09T03:00:00Z")
+        );
+
+        assert_eq!(
+            next_run(&parse_cron("@daily").unwrap(), &at("2026-03-01T00:00:00Z")),
+            millis("2026-03-02T00:00:00Z")
+        );
+
+        let error = parse_cron("every night").unwrap_err();
+        assert!(
+            error.starts_with("invalid cron expression 'every night'"),
+            "{}",
+            error
+        );
+        assert!(parse_cron("61 * * * *").is_err());
+    }
+
+    #[test]
+    fn only_matching_scaffolds_with_a_file_are_selected() {
+        let library_path = temp_path("library").with_file_name(LIBRARY_FILE);
+        let mut library = ScaffoldLibrary::default();
+        let entry = |name: &str, tags: &[&str], file_path: Option<&str>| LibraryEntry {
+            name: name.to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            material: Some("PCL".to_string()),
+            tissue: Some("bone".to_string()),
+            created: 1,
+            metrics_summary: Some(metrics(0.8)),
+            file_path: file_path.map(str::to_string),
+            voxel_size_um: Some(8.0),
+        };
+        let entries = [
+            (
+                "gyroid",
+                entry("Gyroid", &["tpms"], Some("/scans/gyroid.raw")),
+            ),
+            ("diamond", entry("Diamond", &["tpms"], None)),
+            (
+                "femur",
+                entry("Femur", &["microct"], Some("/scans/femur.tif")),
+            ),
+        ];
+        for (file_id, entry) in entries {
+            library.add(&library_path, file_id, entry).unwrap();
+        }
+
+        let query = ScaffoldQuery {
+            tags: vec!["TPMS".to_string()],
+            ..Default::default()
+        };
+        let (targets, skipped) = select_targets(library.search(&library_path, &query).unwrap());
+        assert_eq!(
+            targets,
+            [ReprocessTarget {
+                file_id: "gyroid".to_string(),
+                file_path: "/scans/gyroid.raw".to_string(),
+                voxel_size_um: Some(8.0),
+            }]
+        );
+        assert_eq!(skipped.keys().collect::<Vec<_>>(), ["diamond"]);
+
+        let (all, _) = select_targets(
+            library
+                .search(&library_path, &ScaffoldQuery::default())
+                .unwrap(),
+        );
+        assert_eq!(all.len(), 2);
+        std::fs::remove_dir_all(library_path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reprocessing_is_bounded_and_reports_failures() {
+        let targets: Vec<ReprocessTarget> = (0..6)
+            .map(|i| ReprocessTarget {
+                file_id: format!("scan-{}", i),
+                file_path: format!("/scans/{}.tif", i),
+                voxel_size_um: None,
+            })
+            .collect();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let results = reprocess(targets, 2, |target| {
+            let (in_flight, peak) = (in_flight.clone(), peak.clone());
+            async move {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                if target.file_id == "scan-3" {
+                    Err("volume is empty".to_string())
+                } else {
+                    Ok(metrics(0.7))
+                }
+            }
+        })
+        .await;
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        let mut summary = ReprocessSummary::new(0, BTreeMap::new());
+        for (file_id, result) in &results {
+            summary.add(file_id.clone(), result);
+        }
+        assert_eq!(summary.analyzed, 5);
+        assert_eq!(summary.failed["scan-3"], "volume is empty");
+    }
+
+    #[tokio::test]
+    async fn jobs_survive_restart_until_cancelled() {
+        let path = temp_path("restart");
+        let mut jobs = ScheduledJobs::default();
+        let filter = ScaffoldQuery {
+            material: Some("PCL".to_string()),
+            ..Default::default()
+        };
+        let job = jobs.add(&path, "0 3 * * *", filter).unwrap();
+        assert!(jobs
+            .add(&path, "whenever", ScaffoldQuery::default())
+            .is_err());
+        let cancelled = jobs.start(&job.id).unwrap();
+        assert!(jobs.start(&job.id).is_none());
+
+        let summary = ReprocessSummary::new(5, BTreeMap::new());
+        jobs.record_run(&path, &job.id, summary.clone()).unwrap();
+
+        let mut reloaded = ScheduledJobs::default();
+        let listed = reloaded.list(&path).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].filter.material.as_deref(), Some("PCL"));
+        assert_eq!(listed[0].last_run, Some(summary));
+
+        assert!(jobs.remove(&path, &job.id).unwrap());
+        assert!(!jobs.remove(&path, &job.id).unwrap());
+        // The permit is stored, so a loop that is not waiting yet still sees it
+        cancelled.notified().await;
+        assert!(ScheduledJobs::default().list(&path).unwrap().is_empty());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
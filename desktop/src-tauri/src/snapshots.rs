@@ -0,0 +1,213 @@
+// Workspace snapshots - Julia-side workspace state parked on disk and restored later
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+pub const SNAPSHOT_DIR: &str = "snapshots";
+const INDEX_FILE: &str = "index.json";
+/// Snapshots stream to disk, so this only guards against a runaway backend
+pub const MAX_SNAPSHOT_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotMeta {
+    pub snapshot_id: String,
+    pub workspace_id: String,
+    pub workspace_name: String,
+    pub size_bytes: u64,
+    /// Unix time in milliseconds
+    pub created: u64,
+    /// `BackendInfo::version` of the backend that took the snapshot
+    pub backend_version: String,
+    /// Julia version that serialized the state; its format only round-trips on the same one
+    pub julia_version: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreOutcome {
+    pub snapshot_id: String,
+    pub workspace_id: String,
+    /// Version mismatches between the snapshot and the backend it was restored on
+    pub warnings: Vec<String>,
+}
+
+/// `snapshot_id -> metadata`, loaded from `snapshots/index.json` the first time it is used.
+/// Each snapshot's data sits next to the index as `<snapshot_id>.bin`.
+#[derive(Debug, Default)]
+pub struct WorkspaceSnapshots {
+    entries: Option<BTreeMap<String, SnapshotMeta>>,
+}
+
+pub fn blob_path(dir: &Path, snapshot_id: &str) -> PathBuf {
+    dir.join(format!("{}.bin", snapshot_id))
+}
+
+impl WorkspaceSnapshots {
+    fn load(&mut self, dir: &Path) -> Result<&mut BTreeMap<String, SnapshotMeta>, String> {
+        if self.entries.is_none() {
+            let path = dir.join(INDEX_FILE);
+            let entries = match std::fs::read_to_string(&path) {
+                Ok(text) => serde_json::from_str(&text)
+                    .map_err(|e| format!("{} is corrupt: {}", path.display(), e))?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+                Err(e) => return Err(e.to_string()),
+            };
+            self.entries = Some(entries);
+        }
+        Ok(self.entries.as_mut().unwrap())
+    }
+
+    fn save(&self, dir: &Path) -> Result<(), String> {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let json = serde_json::to_string_pretty(&self.entries).map_err(|e| e.to_string())?;
+        std::fs::write(dir.join(INDEX_FILE), json).map_err(|e| e.to_string())
+    }
+
+    /// Track a snapshot whose data was already written to `blob_path`
+    pub fn record(&mut self, dir: &Path, meta: SnapshotMeta) -> Result<(), String> {
+        self.load(dir)?.insert(meta.snapshot_id.clone(), meta);
+        self.save(dir)
+    }
+
+    pub fn get(&mut self, dir: &Path, snapshot_id: &str) -> Result<SnapshotMeta, String> {
+        self.load(dir)?
+            .get(snapshot_id)
+            .cloned()
+            .ok_or_else(|| format!("unknown snapshot: {}", snapshot_id))
+    }
+
+    /// Newest first
+    pub fn list(&mut self, dir: &Path) -> Result<Vec<SnapshotMeta>, String> {
+        let mut snapshots: Vec<SnapshotMeta> = self.load(dir)?.values().cloned().collect();
+        snapshots.sort_by(|a, b| {
+            b.created
+                .cmp(&a.created)
+                .then(a.snapshot_id.cmp(&b.snapshot_id))
+        });
+        Ok(snapshots)
+    }
+}
+
+/// Stream a snapshot body to `path`; returns its size. Nothing is left behind on failure.
+pub async fn write_blob(mut response: reqwest::Response, path: &Path) -> Result<u64, String> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    let partial = path.with_extension("part");
+    let result = async {
+        let mut file = tokio::fs::File::create(&partial)
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut size = 0u64;
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(crate::http_client::describe)?
+        {
+            size += chunk.len() as u64;
+            if size > MAX_SNAPSHOT_BYTES {
+                return Err(format!(
+                    "snapshot exceeds the {} byte limit",
+                    MAX_SNAPSHOT_BYTES
+                ));
+            }
+            file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+        }
+        file.flush().await.map_err(|e| e.to_string())?;
+        tokio::fs::rename(&partial, path)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(size)
+    }
+    .await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&partial).await;
+    }
+    result
+}
+
+/// Warnings for restoring `meta` on a backend at `backend_version` running `julia_version`.
+/// Unknown versions on either side are not reported.
+pub fn compatibility_warnings(
+    meta: &SnapshotMeta,
+    backend_version: &str,
+    julia_version: Option<&str>,
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let known = |v: &str| !v.is_empty() && v != "unknown";
+    if known(&meta.backend_version)
+        && known(backend_version)
+        && meta.backend_version != backend_version
+    {
+        warnings.push(format!(
+            "snapshot was taken on backend {}, restored on {}",
+            meta.backend_version, backend_version
+        ));
+    }
+    if let (Some(taken), Some(running)) = (meta.julia_version.as_deref(), julia_version) {
+        if taken != running {
+            warnings.push(format!(
+                "snapshot was serialized by Julia {}, restored on Julia {}",
+                taken, running
+            ));
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(snapshot_id: &str, created: u64) -> SnapshotMeta {
+        SnapshotMeta {
+            snapshot_id: snapshot_id.to_string(),
+            workspace_id: "ws-1".to_string(),
+            workspace_name: "Gyroid".to_string(),
+            size_bytes: 1024,
+            created,
+            backend_version: "1.0.0".to_string(),
+            julia_version: Some("1.10.4".to_string()),
+        }
+    }
+
+    #[test]
+    fn snapshots_persist_newest_first() {
+        let dir = std::env::temp_dir()
+            .join(format!("snapshots-test-{}", uuid::Uuid::new_v4()))
+            .join(SNAPSHOT_DIR);
+        let mut snapshots = WorkspaceSnapshots::default();
+        snapshots.record(&dir, meta("a", 1)).unwrap();
+        snapshots.record(&dir, meta("b", 2)).unwrap();
+
+        let mut reloaded = WorkspaceSnapshots::default();
+        let ids: Vec<String> = reloaded
+            .list(&dir)
+            .unwrap()
+            .into_iter()
+            .map(|m| m.snapshot_id)
+            .collect();
+        assert_eq!(ids, ["b", "a"]);
+        assert_eq!(reloaded.get(&dir, "a").unwrap(), meta("a", 1));
+        assert_eq!(reloaded.get(&dir, "c").unwrap_err(), "unknown snapshot: c");
+        assert_eq!(blob_path(&dir, "a"), dir.join("a.bin"));
+        std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn version_mismatches_are_warned_about() {
+        let snapshot = meta("a", 1);
+        assert!(compatibility_warnings(&snapshot, "1.0.0", Some("1.10.4")).is_empty());
+        assert!(compatibility_warnings(&snapshot, "unknown", None).is_empty());
+        assert_eq!(
+            compatibility_warnings(&snapshot, "1.1.0", Some("1.11.0")),
+            [
+                "snapshot was taken on backend 1.0.0, restored on 1.1.0",
+                "snapshot was serialized by Julia 1.10.4, restored on Julia 1.11.0",
+            ]
+        );
+    }
+}
@@ -1,7 +1,23 @@
 // Application state management
 
+use crate::analysis_estimate::AnalysisTimeModel;
+use crate::backend_info::BackendInfo;
+use crate::cache::OnceCache;
+use crate::history::MetricsHistory;
+use crate::http_client::{HttpClient, DEFAULT_REQUEST_TIMEOUT_SECS};
+use crate::job_queue::{JobQueue, DEFAULT_MAX_CONCURRENT_JOBS};
+use crate::library::ScaffoldLibrary;
+use crate::materials::MaterialsCache;
+use crate::param_history::ParamHistory;
+use crate::scheduled_jobs::ScheduledJobs;
+use crate::snapshots::WorkspaceSnapshots;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+
+const IDEMPOTENCY_CACHE_CAPACITY: usize = 64;
+
+/// Agent chat turns kept in memory; older ones are dropped
+pub const CHAT_HISTORY_CAP: usize = 500;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppSettings {
@@ -11,6 +27,30 @@ pub struct AppSettings {
     pub default_material: String,
     pub default_tissue: String,
     pub default_voxel_size: f64,
+    /// Unit of `default_voxel_size` and of voxel sizes entered in the UI ("um" | "mm")
+    #[serde(default = "default_voxel_unit")]
+    pub voxel_unit: String,
+    /// Compute commands (analyze, TPMS generation, mesh simplification) sent to Julia at once
+    #[serde(default = "default_max_concurrent_jobs")]
+    pub max_concurrent_jobs: usize,
+    /// Julia requests taking longer than this fail with "request timed out"
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+    /// Coefficients `estimate_analysis_time` predicts with when the backend can't
+    #[serde(default)]
+    pub analysis_time_model: AnalysisTimeModel,
+}
+
+fn default_voxel_unit() -> String {
+    crate::units::CANONICAL_VOXEL_UNIT.to_string()
+}
+
+fn default_max_concurrent_jobs() -> usize {
+    DEFAULT_MAX_CONCURRENT_JOBS
+}
+
+fn default_request_timeout_secs() -> u64 {
+    DEFAULT_REQUEST_TIMEOUT_SECS
 }
 
 impl Default for AppSettings {
@@ -22,6 +62,10 @@ impl Default for AppSettings {
             default_material: "PCL".to_string(),
             default_tissue: "bone".to_string(),
             default_voxel_size: 10.0,
+            voxel_unit: default_voxel_unit(),
+            max_concurrent_jobs: default_max_concurrent_jobs(),
+            request_timeout_secs: default_request_timeout_secs(),
+            analysis_time_model: AnalysisTimeModel::default(),
         }
     }
 }
@@ -32,6 +76,81 @@ pub struct WorkspaceState {
     pub name: String,
     pub file_path: Option<String>,
     pub modified: bool,
+    /// Undo/redo stack of parameter changes
+    #[serde(default)]
+    pub history: ParamHistory,
+}
+
+impl WorkspaceState {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            name: id.to_string(),
+            file_path: None,
+            modified: false,
+            history: ParamHistory::default(),
+        }
+    }
+}
+
+/// One agent chat turn, kept so a saved project can reopen the conversation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatEntry {
+    /// "user" or "agent"
+    pub role: String,
+    pub agent: String,
+    pub content: String,
+}
+
+/// Bounded LRU of completed analyze results keyed by caller-supplied idempotency key
+#[derive(Debug)]
+pub struct IdempotencyCache {
+    capacity: usize,
+    entries: HashMap<String, serde_json::Value>,
+    order: VecDeque<String>, // least recently used first
+}
+
+impl IdempotencyCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<serde_json::Value> {
+        let value = self.entries.get(key)?.clone();
+        self.touch(key);
+        Some(value)
+    }
+
+    pub fn insert(&mut self, key: String, value: serde_json::Value) {
+        if self.entries.insert(key.clone(), value).is_some() {
+            self.touch(&key);
+            return;
+        }
+        self.order.push_back(key);
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+
+    fn touch(&mut self, key: &str) {
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            if let Some(k) = self.order.remove(pos) {
+                self.order.push_back(k);
+            }
+        }
+    }
+}
+
+impl Default for IdempotencyCache {
+    fn default() -> Self {
+        Self::new(IDEMPOTENCY_CACHE_CAPACITY)
+    }
 }
 
 #[derive(Debug, Default)]
@@ -41,4 +160,76 @@ pub struct AppState {
     pub settings: AppSettings,
     pub workspaces: HashMap<String, WorkspaceState>,
     pub current_workspace: Option<String>,
+    pub idempotency_cache: IdempotencyCache,
+    pub metrics_history: MetricsHistory,
+    pub job_queue: JobQueue,
+    pub chat_history: Vec<ChatEntry>,
+    /// Capabilities of each backend URL, fetched once however many commands ask at the same time
+    pub backend_info: OnceCache<String, BackendInfo>,
+    /// Bundled plus user materials, read once until `add_material` changes them
+    pub materials: MaterialsCache,
+    /// Client for Julia requests, built with `settings.request_timeout_secs`
+    pub http: HttpClient,
+    pub library: ScaffoldLibrary,
+    pub snapshots: WorkspaceSnapshots,
+    pub scheduled_jobs: ScheduledJobs,
+}
+
+impl AppState {
+    /// Start tracking a new workspace under a fresh UUID
+    pub fn create_workspace(
+        &mut self,
+        name: Option<String>,
+        file_path: Option<String>,
+    ) -> &mut WorkspaceState {
+        let id = uuid::Uuid::new_v4().to_string();
+        let mut workspace = WorkspaceState::new(&id);
+        if let Some(name) = name {
+            workspace.name = name;
+        }
+        workspace.file_path = file_path;
+        self.workspaces.entry(id).or_insert(workspace)
+    }
+
+    /// The workspace `id`, or `"unknown workspace: <id>"` if this session doesn't track it
+    pub fn require_workspace(&self, id: &str) -> Result<&WorkspaceState, String> {
+        self.workspaces
+            .get(id)
+            .ok_or_else(|| format!("unknown workspace: {}", id))
+    }
+
+    pub fn require_workspace_mut(&mut self, id: &str) -> Result<&mut WorkspaceState, String> {
+        self.workspaces
+            .get_mut(id)
+            .ok_or_else(|| format!("unknown workspace: {}", id))
+    }
+
+    pub fn record_chat(&mut self, entry: ChatEntry) {
+        self.chat_history.push(entry);
+        if self.chat_history.len() > CHAT_HISTORY_CAP {
+            let excess = self.chat_history.len() - CHAT_HISTORY_CAP;
+            self.chat_history.drain(..excess);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn created_workspaces_get_uuids_and_unknown_ids_are_refused() {
+        let mut state = AppState::default();
+        let id = state
+            .create_workspace(None, Some("/scans/femur.tif".to_string()))
+            .id
+            .clone();
+        assert!(uuid::Uuid::parse_str(&id).is_ok());
+        assert_eq!(state.require_workspace(&id).unwrap().name, id);
+
+        assert_eq!(
+            state.require_workspace("ws-typo").unwrap_err(),
+            "unknown workspace: ws-typo"
+        );
+    }
 }
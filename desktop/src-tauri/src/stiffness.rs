@@ -0,0 +1,218 @@
+// Effective stiffness - homogenized elastic tensor of a scaffold's unit cell
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+/// Entries `C[i][j]` and `C[j][i]` may differ by this fraction of the largest entry;
+/// FE homogenization is only symmetric up to solver tolerance.
+const SYMMETRY_TOLERANCE: f64 = 1e-6;
+
+/// Poisson ratios `nu_ij`: contraction along `j` under uniaxial load along `i`.
+/// For an anisotropic scaffold `nu_ij / E_i == nu_ji / E_j`, not `nu_ij == nu_ji`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PoissonRatios {
+    pub nu_12: f64,
+    pub nu_13: f64,
+    pub nu_23: f64,
+    pub nu_21: f64,
+    pub nu_31: f64,
+    pub nu_32: f64,
+}
+
+/// What Julia's `/mechanics/homogenize` returns
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct Homogenized {
+    stiffness_matrix_mpa: [[f64; 6]; 6],
+    youngs_moduli_mpa: [f64; 3],
+    shear_moduli_mpa: [f64; 3],
+    poisson_ratios: PoissonRatios,
+}
+
+/// Effective elastic properties of a workspace's scaffold made of `material`.
+///
+/// `stiffness_matrix_mpa` is in Voigt order (11, 22, 33, 23, 13, 12) and exactly
+/// symmetric: Julia's small numerical asymmetry is averaged out.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StiffnessReport {
+    pub workspace_id: String,
+    pub material: String,
+    /// Young's modulus of the solid material, from the materials database
+    pub base_modulus_mpa: f64,
+    pub stiffness_matrix_mpa: [[f64; 6]; 6],
+    /// `[E1, E2, E3]`
+    pub youngs_moduli_mpa: [f64; 3],
+    /// `[G23, G13, G12]`
+    pub shear_moduli_mpa: [f64; 3],
+    pub poisson_ratios: PoissonRatios,
+    /// `youngs_moduli_mpa / base_modulus_mpa`: how much stiffness the porosity leaves
+    pub relative_moduli: [f64; 3],
+}
+
+/// Check that `matrix` is finite and symmetric, then make it exactly symmetric
+pub fn symmetrized(mut matrix: [[f64; 6]; 6]) -> Result<[[f64; 6]; 6], String> {
+    if matrix.iter().flatten().any(|v| !v.is_finite()) {
+        return Err("invalid stiffness matrix: non-finite entry".to_string());
+    }
+    let largest = matrix
+        .iter()
+        .flatten()
+        .fold(0.0, |m: f64, v| m.max(v.abs()));
+    for (i, j) in (0..6).flat_map(|i| (i + 1..6).map(move |j| (i, j))) {
+        let (a, b) = (matrix[i][j], matrix[j][i]);
+        if (a - b).abs() > SYMMETRY_TOLERANCE * largest {
+            return Err(format!(
+                "invalid stiffness matrix: not symmetric, C{}{} = {} but C{}{} = {}",
+                i + 1,
+                j + 1,
+                a,
+                j + 1,
+                i + 1,
+                b
+            ));
+        }
+        let mean = (a + b) / 2.0;
+        matrix[i][j] = mean;
+        matrix[j][i] = mean;
+    }
+    Ok(matrix)
+}
+
+/// The report in Julia's reply, annotated with the material it was computed for.
+/// A 404 means Julia has no geometry for the workspace yet.
+pub fn from_reply(
+    workspace_id: &str,
+    material: &str,
+    base_modulus_mpa: f64,
+    status: StatusCode,
+    body: serde_json::Value,
+) -> Result<StiffnessReport, String> {
+    if status == StatusCode::NOT_FOUND {
+        return Err(format!(
+            "workspace {} has no computed geometry yet",
+            workspace_id
+        ));
+    }
+    if let Some(error) = body["error"].as_str() {
+        return Err(error.to_string());
+    }
+    if !status.is_success() {
+        return Err(format!("stiffness homogenization failed ({})", status));
+    }
+    let reply: Homogenized =
+        serde_json::from_value(body).map_err(|e| format!("invalid stiffness reply: {}", e))?;
+    let p = reply.poisson_ratios;
+    let derived = reply
+        .youngs_moduli_mpa
+        .iter()
+        .chain(&reply.shear_moduli_mpa)
+        .chain(&[p.nu_12, p.nu_13, p.nu_23, p.nu_21, p.nu_31, p.nu_32])
+        .all(|v| v.is_finite());
+    if !derived {
+        return Err("invalid stiffness reply: non-finite modulus or Poisson ratio".to_string());
+    }
+
+    Ok(StiffnessReport {
+        workspace_id: workspace_id.to_string(),
+        material: material.to_string(),
+        base_modulus_mpa,
+        stiffness_matrix_mpa: symmetrized(reply.stiffness_matrix_mpa)?,
+        youngs_moduli_mpa: reply.youngs_moduli_mpa,
+        shear_moduli_mpa: reply.shear_moduli_mpa,
+        poisson_ratios: reply.poisson_ratios,
+        relative_moduli: reply.youngs_moduli_mpa.map(|e| e / base_modulus_mpa),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Orthotropic scaffold, stiffer along z (the print direction)
+    fn orthotropic() -> serde_json::Value {
+        json!({
+            "stiffness_matrix_mpa": [
+                [120.0, 40.0, 35.0, 0.0, 0.0, 0.0],
+                [40.0, 120.0, 35.0, 0.0, 0.0, 0.0],
+                [35.0, 35.0, 300.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 30.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0, 30.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0, 0.0, 25.0],
+            ],
+            "youngs_moduli_mpa": [100.0, 100.0, 280.0],
+            "shear_moduli_mpa": [30.0, 30.0, 25.0],
+            "poisson_ratios": {
+                "nu_12": 0.3, "nu_13": 0.1, "nu_23": 0.1,
+                "nu_21": 0.3, "nu_31": 0.28, "nu_32": 0.28,
+            },
+            "elapsed_ms": 48210,
+        })
+    }
+
+    #[test]
+    fn julia_tensor_deserializes_and_round_trips() {
+        let report = from_reply("ws-1", "PCL", 400.0, StatusCode::OK, orthotropic()).unwrap();
+        assert_eq!(report.stiffness_matrix_mpa[2][2], 300.0);
+        assert_eq!(report.stiffness_matrix_mpa[0][2], 35.0);
+        assert_eq!(report.poisson_ratios.nu_31, 0.28);
+        assert_eq!(report.relative_moduli, [0.25, 0.25, 0.7]);
+        assert_eq!(
+            (report.material.as_str(), report.base_modulus_mpa),
+            ("PCL", 400.0)
+        );
+
+        // The matrix serializes as 6 rows of 6 numbers and reads back unchanged
+        let value = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            value["stiffness_matrix_mpa"][5],
+            json!([0.0, 0.0, 0.0, 0.0, 0.0, 25.0])
+        );
+        let back: StiffnessReport = serde_json::from_value(value).unwrap();
+        assert_eq!(back, report);
+
+        let mut short = orthotropic();
+        short["stiffness_matrix_mpa"][3] = json!([0.0, 0.0, 0.0, 30.0, 0.0]);
+        assert!(from_reply("ws-1", "PCL", 400.0, StatusCode::OK, short)
+            .unwrap_err()
+            .starts_with("invalid stiffness reply"));
+    }
+
+    #[test]
+    fn asymmetric_matrices_are_rejected_and_solver_noise_is_averaged() {
+        let mut matrix = [[0.0; 6]; 6];
+        for (i, row) in matrix.iter_mut().enumerate() {
+            row[i] = 100.0;
+        }
+        matrix[0][1] = 40.0;
+        matrix[1][0] = 40.0 + 1e-5;
+        let exact = symmetrized(matrix).unwrap();
+        assert_eq!(exact[0][1], exact[1][0]);
+
+        matrix[4][0] = 12.0;
+        assert_eq!(
+            symmetrized(matrix).unwrap_err(),
+            "invalid stiffness matrix: not symmetric, C15 = 0 but C51 = 12"
+        );
+        matrix[4][0] = f64::NAN;
+        assert!(symmetrized(matrix).unwrap_err().contains("non-finite"));
+    }
+
+    #[test]
+    fn missing_geometry_and_backend_errors_are_reported() {
+        assert_eq!(
+            from_reply("ws-empty", "PCL", 400.0, StatusCode::NOT_FOUND, json!({})).unwrap_err(),
+            "workspace ws-empty has no computed geometry yet"
+        );
+        assert_eq!(
+            from_reply(
+                "ws-1",
+                "PCL",
+                400.0,
+                StatusCode::UNPROCESSABLE_ENTITY,
+                json!({"error": "unit cell is not periodic"})
+            )
+            .unwrap_err(),
+            "unit cell is not periodic"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+// Local STL writer - exports a mesh the frontend already holds when Julia's exporter is unavailable
+
+use serde::{Deserialize, Serialize};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Triangle mesh as returned by marching cubes: faces index into `vertices`,
+/// counter-clockwise seen from outside.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mesh {
+    pub vertices: Vec<[f32; 3]>,
+    pub faces: Vec<[u32; 3]>,
+}
+
+impl Mesh {
+    /// Refuse meshes without faces, with non-finite coordinates or with out-of-range indices
+    pub fn validate(&self) -> Result<(), String> {
+        if self.faces.is_empty() {
+            return Err("mesh has no faces, nothing to export".to_string());
+        }
+        if let Some(i) = self
+            .vertices
+            .iter()
+            .position(|v| v.iter().any(|c| !c.is_finite()))
+        {
+            return Err(format!("vertex {} has a non-finite coordinate", i));
+        }
+        let count = self.vertices.len();
+        for (i, face) in self.faces.iter().enumerate() {
+            if let Some(index) = face.iter().find(|&&index| index as usize >= count) {
+                return Err(format!(
+                    "face {} references vertex {}, but the mesh has {} vertices",
+                    i, index, count
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Corners of each face, with its unit normal (zero for degenerate faces)
+    fn triangles(&self) -> impl Iterator<Item = ([f32; 3], [[f32; 3]; 3])> + '_ {
+        self.faces.iter().map(|face| {
+            let corners = face.map(|index| self.vertices[index as usize]);
+            (normal(corners), corners)
+        })
+    }
+}
+
+fn normal([a, b, c]: [[f32; 3]; 3]) -> [f32; 3] {
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let n = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    if length > 0.0 {
+        n.map(|c| c / length)
+    } else {
+        [0.0; 3]
+    }
+}
+
+/// 80-byte header, triangle count, then 50 bytes per triangle (little-endian)
+pub fn write_binary(mesh: &Mesh, out: &mut impl Write) -> std::io::Result<()> {
+    let mut header = [0u8; 80];
+    let label = b"binary STL exported by Darwin Scaffold Studio";
+    header[..label.len()].copy_from_slice(label);
+    out.write_all(&header)?;
+    out.write_all(&(mesh.faces.len() as u32).to_le_bytes())?;
+    for (normal, corners) in mesh.triangles() {
+        for value in normal.iter().chain(corners.iter().flatten()) {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        out.write_all(&[0, 0])?;
+    }
+    Ok(())
+}
+
+pub fn write_ascii(mesh: &Mesh, out: &mut impl Write) -> std::io::Result<()> {
+    writeln!(out, "solid scaffold")?;
+    for (n, corners) in mesh.triangles() {
+        writeln!(out, "  facet normal {:e} {:e} {:e}", n[0], n[1], n[2])?;
+        writeln!(out, "    outer loop")?;
+        for [x, y, z] in corners {
+            writeln!(out, "      vertex {:e} {:e} {:e}", x, y, z)?;
+        }
+        writeln!(out, "    endloop")?;
+        writeln!(out, "  endfacet")?;
+    }
+    writeln!(out, "endsolid scaffold")
+}
+
+/// Validate `mesh` and write it to `path` through a `.part` file; returns the size.
+/// On failure nothing is left at `path` or next to it.
+pub fn write_file(mesh: &Mesh, path: &Path, binary: bool) -> Result<u64, String> {
+    mesh.validate()?;
+    let partial = path.with_extension("part");
+    let result = (|| {
+        let mut out = BufWriter::new(std::fs::File::create(&partial)?);
+        if binary {
+            write_binary(mesh, &mut out)?;
+        } else {
+            write_ascii(mesh, &mut out)?;
+        }
+        out.into_inner()?.sync_all()?;
+        std::fs::rename(&partial, path)?;
+        std::fs::metadata(path).map(|m| m.len())
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result.map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tetrahedron() -> Mesh {
+        Mesh {
+            vertices: vec![
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [0.0, 0.0, 1.0],
+            ],
+            faces: vec![[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]],
+        }
+    }
+
+    #[test]
+    fn binary_stl_has_the_expected_layout() {
+        let mut bytes = Vec::new();
+        write_binary(&tetrahedron(), &mut bytes).unwrap();
+        assert_eq!(bytes.len(), 84 + 4 * 50);
+        assert_eq!(u32::from_le_bytes(bytes[80..84].try_into().unwrap()), 4);
+
+        // First facet: normal of (0,0,0) (0,1,0) (1,0,0) points down -z
+        let floats: Vec<f32> = bytes[84..132]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(&floats[..3], &[0.0, 0.0, -1.0]);
+        assert_eq!(
+            &floats[3..12],
+            &[0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn ascii_stl_lists_every_facet() {
+        let mut bytes = Vec::new();
+        write_ascii(&tetrahedron(), &mut bytes).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        assert!(text.starts_with("solid scaffold\n"));
+        assert!(text.trim_end().ends_with("endsolid scaffold"));
+        assert_eq!(text.matches("facet normal").count(), 4);
+        assert_eq!(text.matches("vertex ").count(), 12);
+        assert!(text.contains("facet normal 0e0 0e0 -1e0"));
+    }
+
+    #[test]
+    fn invalid_meshes_are_refused_without_leaving_files() {
+        let dir = std::env::temp_dir().join(format!("darwin_stl_writer_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("scaffold.stl");
+
+        let empty = Mesh {
+            vertices: Vec::new(),
+            faces: Vec::new(),
+        };
+        assert_eq!(
+            write_file(&empty, &path, true).unwrap_err(),
+            "mesh has no faces, nothing to export"
+        );
+        let mut broken = tetrahedron();
+        broken.faces.push([1, 2, 4]);
+        assert_eq!(
+            write_file(&broken, &path, false).unwrap_err(),
+            "face 4 references vertex 4, but the mesh has 4 vertices"
+        );
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        assert_eq!(write_file(&tetrahedron(), &path, true).unwrap(), 284);
+        assert!(crate::mesh_diff::validate_stl(&path).is_ok());
+        let ascii = dir.join("scaffold_ascii.stl");
+        write_file(&tetrahedron(), &ascii, false).unwrap();
+        assert!(crate::mesh_diff::validate_stl(&ascii).is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
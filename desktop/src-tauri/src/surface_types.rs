@@ -0,0 +1,112 @@
+// TPMS surface types - what `generate_tpms` accepts and the porosity each can reach
+
+use serde::{Deserialize, Serialize};
+
+/// Assumed for surface types the backend names but this build has no details for
+pub const FALLBACK_POROSITY_RANGE: [f64; 2] = [0.1, 0.9];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SurfaceType {
+    pub id: String,
+    pub display_name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Lowest and highest porosity the level-set threshold can produce, inclusive
+    pub porosity_range: [f64; 2],
+}
+
+impl SurfaceType {
+    fn new(id: &str, display_name: &str, description: &str, porosity_range: [f64; 2]) -> Self {
+        Self {
+            id: id.to_string(),
+            display_name: display_name.to_string(),
+            description: description.to_string(),
+            porosity_range,
+        }
+    }
+
+    pub fn check_porosity(&self, porosity: f64) -> Result<(), String> {
+        let [min, max] = self.porosity_range;
+        if porosity.is_finite() && (min..=max).contains(&porosity) {
+            return Ok(());
+        }
+        Err(format!(
+            "porosity {} is out of reach for {} (achievable: {} to {})",
+            porosity, self.display_name, min, max
+        ))
+    }
+}
+
+/// Details of the surface types Julia has shipped, for backends whose `/info`
+/// lists surface types by id only
+pub fn bundled() -> Vec<SurfaceType> {
+    vec![
+        SurfaceType::new(
+            "gyroid",
+            "Gyroid",
+            "Smooth, fully interconnected channels; the usual bone scaffold choice",
+            [0.3, 0.95],
+        ),
+        SurfaceType::new(
+            "diamond",
+            "Diamond (Schwarz D)",
+            "Stiff tetrahedral network with narrow channels",
+            [0.25, 0.9],
+        ),
+        SurfaceType::new(
+            "schwarz_p",
+            "Schwarz Primitive",
+            "Cubic network of large pores joined by necks",
+            [0.3, 0.85],
+        ),
+        SurfaceType::new(
+            "schwarz_d",
+            "Schwarz Diamond",
+            "Diamond surface with the Schwarz parameterisation",
+            [0.25, 0.9],
+        ),
+        SurfaceType::new(
+            "neovius",
+            "Neovius",
+            "Thick-walled cubic cells, high stiffness at low porosity",
+            [0.2, 0.8],
+        ),
+        SurfaceType::new(
+            "iwp",
+            "I-WP",
+            "Body-centred cells with two interpenetrating pore systems",
+            [0.25, 0.85],
+        ),
+    ]
+}
+
+/// The bundled details of `id`, or a generic entry with `FALLBACK_POROSITY_RANGE`
+pub fn bundled_or_generic(id: &str) -> SurfaceType {
+    bundled()
+        .into_iter()
+        .find(|t| t.id.eq_ignore_ascii_case(id))
+        .unwrap_or_else(|| SurfaceType::new(id, id, "", FALLBACK_POROSITY_RANGE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn porosity_must_be_within_the_types_range() {
+        let gyroid = bundled_or_generic("Gyroid");
+        assert_eq!(gyroid.porosity_range, [0.3, 0.95]);
+        assert!(gyroid.check_porosity(0.3).is_ok());
+        assert!(gyroid.check_porosity(0.95).is_ok());
+        assert_eq!(
+            gyroid.check_porosity(0.97).unwrap_err(),
+            "porosity 0.97 is out of reach for Gyroid (achievable: 0.3 to 0.95)"
+        );
+        assert!(gyroid.check_porosity(0.1).is_err());
+        assert!(gyroid.check_porosity(f64::NAN).is_err());
+
+        let custom = bundled_or_generic("lidinoid");
+        assert_eq!(custom.porosity_range, FALLBACK_POROSITY_RANGE);
+        assert!(custom.check_porosity(0.92).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+// Thumbnails - PNG previews rendered by Julia, cached on disk per workspace revision
+
+use std::path::{Path, PathBuf};
+
+pub const MIN_THUMBNAIL_PX: u32 = 16;
+pub const MAX_THUMBNAIL_PX: u32 = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThumbnailView {
+    Top,
+    Iso,
+    Front,
+}
+
+impl ThumbnailView {
+    pub fn parse(view: &str) -> Result<Self, String> {
+        match view.trim().to_ascii_lowercase().as_str() {
+            "top" => Ok(Self::Top),
+            "iso" => Ok(Self::Iso),
+            "front" => Ok(Self::Front),
+            other => Err(format!(
+                "unknown thumbnail view '{}', expected top, iso or front",
+                other
+            )),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Top => "top",
+            Self::Iso => "iso",
+            Self::Front => "front",
+        }
+    }
+}
+
+/// One rendering of one workspace revision
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThumbnailKey<'a> {
+    pub workspace_id: &'a str,
+    pub revision: &'a str,
+    pub view: ThumbnailView,
+    pub size_px: u32,
+}
+
+fn is_safe_component(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+impl ThumbnailKey<'_> {
+    /// `{dir}/{workspace_id}/{revision}_{view}_{size}.png`
+    pub fn path(&self, dir: &Path) -> Result<PathBuf, String> {
+        if !is_safe_component(self.workspace_id) {
+            return Err(format!("invalid workspace id: {:?}", self.workspace_id));
+        }
+        if !is_safe_component(self.revision) {
+            return Err(format!("invalid workspace revision: {:?}", self.revision));
+        }
+        Ok(dir.join(self.workspace_id).join(format!(
+            "{}_{}_{}.png",
+            self.revision,
+            self.view.as_str(),
+            self.size_px
+        )))
+    }
+}
+
+/// Path of the cached rendering, if there is one
+pub fn cached(dir: &Path, key: &ThumbnailKey) -> Result<Option<PathBuf>, String> {
+    let path = key.path(dir)?;
+    Ok(path.is_file().then_some(path))
+}
+
+/// Write a rendering and drop the workspace's thumbnails of older revisions
+pub fn store(dir: &Path, key: &ThumbnailKey, png: &[u8]) -> Result<PathBuf, String> {
+    let path = key.path(dir)?;
+    let workspace_dir = path.parent().unwrap_or(dir);
+    std::fs::create_dir_all(workspace_dir).map_err(|e| e.to_string())?;
+
+    if let Ok(entries) = std::fs::read_dir(workspace_dir) {
+        let current = format!("{}_", key.revision);
+        for entry in entries.flatten() {
+            if !entry.file_name().to_string_lossy().starts_with(&current) {
+                let _ = std::fs::remove_file(entry.path());
+            }
+        }
+    }
+
+    // Write then rename so a concurrent reader never sees a partial PNG
+    let tmp = path.with_extension("png.tmp");
+    std::fs::write(&tmp, png).map_err(|e| e.to_string())?;
+    std::fs::rename(&tmp, &path).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key<'a>(revision: &'a str, view: ThumbnailView) -> ThumbnailKey<'a> {
+        ThumbnailKey {
+            workspace_id: "ws-1",
+            revision,
+            view,
+            size_px: 128,
+        }
+    }
+
+    #[test]
+    fn new_revision_replaces_old_thumbnails() {
+        let dir = std::env::temp_dir().join(format!("darwin_thumbs_{}", uuid::Uuid::new_v4()));
+
+        let old = store(&dir, &key("a1", ThumbnailView::Iso), b"old").unwrap();
+        assert_eq!(
+            cached(&dir, &key("a1", ThumbnailView::Iso)).unwrap(),
+            Some(old.clone())
+        );
+        assert_eq!(cached(&dir, &key("a1", ThumbnailView::Top)).unwrap(), None);
+
+        store(&dir, &key("b2", ThumbnailView::Top), b"new").unwrap();
+        assert!(!old.exists());
+        assert!(cached(&dir, &key("b2", ThumbnailView::Top))
+            .unwrap()
+            .is_some());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_unsafe_ids_and_unknown_views() {
+        let mut bad = key("a1", ThumbnailView::Iso);
+        bad.workspace_id = "../etc";
+        assert!(bad.path(Path::new("/tmp")).is_err());
+        assert!(ThumbnailView::parse("side").is_err());
+        assert_eq!(ThumbnailView::parse(" ISO ").unwrap(), ThumbnailView::Iso);
+    }
+}
@@ -0,0 +1,59 @@
+// Length units - voxel sizes travel to Julia in micrometres
+
+/// Unit the Julia backend expects `voxel_size` in
+pub const CANONICAL_VOXEL_UNIT: &str = "um";
+
+/// Canonical spelling of a length unit (`"µm"`, `"micron"` -> `"um"`)
+pub fn normalize_unit(unit: &str) -> Result<&'static str, String> {
+    match unit.trim().to_ascii_lowercase().as_str() {
+        "m" => Ok("m"),
+        "mm" => Ok("mm"),
+        "um" | "µm" | "μm" | "micron" | "microns" => Ok("um"),
+        "nm" => Ok("nm"),
+        other => Err(format!(
+            "unknown length unit '{}', expected one of m, mm, um, nm",
+            other
+        )),
+    }
+}
+
+fn metres_per(unit: &str) -> f64 {
+    match unit {
+        "m" => 1.0,
+        "mm" => 1e-3,
+        "um" => 1e-6,
+        _ => 1e-9,
+    }
+}
+
+/// Convert `value` between length units
+pub fn convert_length(value: f64, from: &str, to: &str) -> Result<f64, String> {
+    let (from, to) = (normalize_unit(from)?, normalize_unit(to)?);
+    if from == to {
+        return Ok(value);
+    }
+    Ok(value * (metres_per(from) / metres_per(to)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-9 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn converts_between_voxel_units() {
+        assert!(close(convert_length(0.01, "mm", "um").unwrap(), 10.0));
+        assert!(close(convert_length(10.0, "µm", "mm").unwrap(), 0.01));
+        assert!(close(convert_length(2.0, "m", "nm").unwrap(), 2e9));
+        assert_eq!(convert_length(7.5, "um", "micron").unwrap(), 7.5);
+    }
+
+    #[test]
+    fn rejects_unknown_units() {
+        assert!(convert_length(1.0, "inch", "mm").is_err());
+        assert_eq!(normalize_unit(" MM ").unwrap(), "mm");
+    }
+}
@@ -342,7 +342,7 @@ using .Types: ScaffoldMetrics, OptimizationResults, ScaffoldParameters
 using .ImageLoader: load_image
 using .Preprocessing: preprocess_image
 using .Segmentation: segment_scaffold
-using .Metrics: compute_metrics
+using .Metrics: compute_metrics, compute_selected_metrics, METRIC_NAMES
 using .ScaffoldOptimizer: Optimizer, optimize_scaffold, detect_problems
 using .Mesh3D: create_mesh, create_mesh_simple
 using .MarchingCubes: march_cubes, extract_isosurface, MarchingCubesResult
@@ -363,6 +363,8 @@ export
     load_image,
     preprocess_image,
     compute_metrics,
+    compute_selected_metrics,
+    METRIC_NAMES,
     segment_scaffold,
     ScaffoldMetrics,
     # Optimization
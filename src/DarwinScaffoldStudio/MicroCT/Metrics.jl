@@ -12,7 +12,7 @@ using Statistics
 using LinearAlgebra
 using StatsBase
 
-export compute_metrics
+export compute_metrics, compute_selected_metrics, METRIC_NAMES
 
 """
     compute_metrics(binary::AbstractArray{Bool, 3}, voxel_size_um::Real) -> ScaffoldMetrics
@@ -67,6 +67,62 @@ function compute_metrics(
     )
 end
 
+"""
+Names of the `ScaffoldMetrics` fields, in declaration order.
+"""
+const METRIC_NAMES = [
+    "porosity",
+    "mean_pore_size_um",
+    "interconnectivity",
+    "tortuosity",
+    "specific_surface_area",
+    "elastic_modulus",
+    "yield_strength",
+    "permeability",
+]
+
+"""
+    compute_selected_metrics(binary::AbstractArray{Bool, 3}, voxel_size_um::Real,
+                             names::AbstractVector{<:AbstractString}) -> Dict{String, Float64}
+
+Compute only the metrics in `names` (see `METRIC_NAMES`), skipping the expensive
+pore-size and connectivity passes unless something requested depends on them.
+"""
+function compute_selected_metrics(
+    binary::AbstractArray{Bool, 3},
+    voxel_size_um::Real,
+    names::AbstractVector{<:AbstractString}
+)::Dict{String, Float64}
+    unknown = setdiff(names, METRIC_NAMES)
+    isempty(unknown) || throw(ArgumentError("unknown metrics: $(join(unknown, ", "))"))
+
+    result = Dict{String, Float64}()
+    relative_density = compute_relative_density(binary)
+    porosity = 1.0 - relative_density
+    # Permeability needs the pore size too
+    needs_pore_size = "mean_pore_size_um" in names || "permeability" in names
+    mean_pore_size_um = needs_pore_size ? compute_mean_pore_size(binary, voxel_size_um) : 0.0
+
+    "porosity" in names && (result["porosity"] = porosity)
+    "mean_pore_size_um" in names && (result["mean_pore_size_um"] = mean_pore_size_um)
+    "interconnectivity" in names && (result["interconnectivity"] = compute_interconnectivity(binary))
+    "tortuosity" in names && (result["tortuosity"] = compute_tortuosity(binary))
+    if "specific_surface_area" in names
+        surface_area_mm2 = Utils.compute_surface_area(binary, voxel_size_um)
+        volume_mm3 = Utils.compute_volume_mm3(size(binary), voxel_size_um)
+        total_volume_mm3 = volume_mm3[1] * volume_mm3[2] * volume_mm3[3]
+        result["specific_surface_area"] = surface_area_mm2 / max(total_volume_mm3, 1e-10)
+    end
+    if "elastic_modulus" in names || "yield_strength" in names
+        elastic_modulus, yield_strength = compute_mechanical_properties(relative_density)
+        "elastic_modulus" in names && (result["elastic_modulus"] = elastic_modulus)
+        "yield_strength" in names && (result["yield_strength"] = yield_strength)
+    end
+    "permeability" in names && (result["permeability"] = compute_permeability(porosity, mean_pore_size_um))
+
+    return result
+end
+
 """
     compute_mean_pore_size(binary::AbstractArray{Bool, 3}, voxel_size_um::Real;
                            min_size::Int=500, method::Symbol=:feret) -> Float64
@@ -3,6 +3,12 @@ using HTTP
 using JSON
 using UUIDs
 using Dates
+using FileIO
+using Images: Gray, imresize
+using LinearAlgebra: Diagonal, Symmetric, eigen
+using Base.CoreLogging: CoreLogging, AbstractLogger, global_logger
+using NIfTI
+using Serialization
 using DarwinScaffoldStudio
 
 # Enable CORS
@@ -77,11 +83,147 @@ end
 # Middleware
 serveparallel(false) # Disable parallel serving for now to avoid issues
 
+# ============================================================================
+# Line Feeds (/progress/stream, /logs/stream)
+# ============================================================================
+
+# Lines queued per reader; a reader further behind loses lines rather than
+# holding up the work that produces them
+const FEED_BUFFER = 256
+# A blank line after this long without news, so a gone reader is noticed
+const FEED_KEEPALIVE_S = 15.0
+
+"""
+Newline-delimited JSON fanned out to every connected reader. The last `backlog`
+lines are kept and sent first to a new reader.
+"""
+struct LineFeed
+    readers::Set{Channel{Dict{String, Any}}}
+    recent::Vector{Dict{String, Any}}
+    backlog::Int
+    lock::ReentrantLock
+end
+
+LineFeed(backlog::Int=0) = LineFeed(Set{Channel{Dict{String, Any}}}(), Dict{String, Any}[], backlog, ReentrantLock())
+
+function publish!(feed::LineFeed, line::Dict{String, Any})
+    lock(feed.lock) do
+        if feed.backlog > 0
+            push!(feed.recent, line)
+            length(feed.recent) > feed.backlog && popfirst!(feed.recent)
+        end
+        for reader in feed.readers
+            Base.n_avail(reader) < FEED_BUFFER && put!(reader, line)
+        end
+    end
+end
+
+"""Write `feed` to `stream` until the reader disconnects."""
+function stream_feed(stream::HTTP.Stream, feed::LineFeed)
+    reader = Channel{Dict{String, Any}}(FEED_BUFFER)
+    lock(feed.lock) do
+        foreach(line -> put!(reader, line), feed.recent)
+        push!(feed.readers, reader)
+    end
+    try
+        HTTP.setheader(stream, "Content-Type" => "application/x-ndjson")
+        HTTP.startwrite(stream)
+        while true
+            timedwait(() -> isready(reader), FEED_KEEPALIVE_S)
+            write(stream, isready(reader) ? JSON.json(take!(reader)) * "\n" : "\n")
+        end
+    catch
+        # Writing to a reader that disconnected is how a feed normally ends; no
+        # logging here, as that would feed /logs/stream from inside a reader
+    finally
+        lock(() -> delete!(feed.readers, reader), feed.lock)
+    end
+end
+
+const PROGRESS_FEED = LineFeed()
+
+"""
+Send a progress line to every `/progress/stream` reader. `request` is the body
+of the request being worked on; its `job_id`, `workspace_id` and `file_path`,
+where present, tag the line so readers can tell whose work it is.
+"""
+function report_progress(request, stage::String, fraction::Real)
+    line = Dict{String, Any}("stage" => stage, "fraction" => Float64(fraction))
+    for key in ("job_id", "workspace_id", "file_path")
+        value = get(request, key, nothing)
+        isnothing(value) || (line[key] = string(value))
+    end
+    publish!(PROGRESS_FEED, line)
+end
+
+# One line per `report_progress`, until the reader leaves
+@stream "/progress/stream" function(stream::HTTP.Stream)
+    stream_feed(stream, PROGRESS_FEED)
+end
+
+# Log records of this server, as {time, level, module, message[, exception]} lines
+const LOG_FEED = LineFeed(100)
+
+"""Passes every record on to `inner` and copies it to `LOG_FEED`."""
+struct FeedLogger{L<:AbstractLogger} <: AbstractLogger
+    inner::L
+end
+
+CoreLogging.min_enabled_level(logger::FeedLogger) = CoreLogging.min_enabled_level(logger.inner)
+CoreLogging.shouldlog(logger::FeedLogger, args...) = CoreLogging.shouldlog(logger.inner, args...)
+CoreLogging.catch_exceptions(logger::FeedLogger) = CoreLogging.catch_exceptions(logger.inner)
+
+function CoreLogging.handle_message(logger::FeedLogger, level, message, _module, group, id, file, line; kwargs...)
+    CoreLogging.handle_message(logger.inner, level, message, _module, group, id, file, line; kwargs...)
+    record = Dict{String, Any}(
+        "time" => string(now()),
+        "level" => lowercase(string(level)),
+        "module" => string(_module),
+        "message" => string(message)
+    )
+    if haskey(kwargs, :exception)
+        exception = kwargs[:exception]
+        record["exception"] = sprint(showerror, exception isa Tuple ? first(exception) : exception)
+    end
+    publish!(LOG_FEED, record)
+end
+
+global_logger(FeedLogger(global_logger()))
+
+# The last 100 log records, then every new one, until the reader leaves
+@stream "/logs/stream" function(stream::HTTP.Stream)
+    stream_feed(stream, LOG_FEED)
+end
+
 # Health check
 @get "/health" function()
     return Dict("status" => "ok", "version" => "1.0.0")
 end
 
+# TPMS surface types and the porosity range the level-set threshold can reach for each
+const SURFACE_TYPES = [
+    Dict("id" => "gyroid", "display_name" => "Gyroid", "porosity_range" => [0.3, 0.95]),
+    Dict("id" => "diamond", "display_name" => "Diamond (Schwarz D)", "porosity_range" => [0.25, 0.9]),
+    Dict("id" => "schwarz_p", "display_name" => "Schwarz Primitive", "porosity_range" => [0.3, 0.85]),
+    Dict("id" => "schwarz_d", "display_name" => "Schwarz Diamond", "porosity_range" => [0.25, 0.9]),
+    Dict("id" => "neovius", "display_name" => "Neovius", "porosity_range" => [0.2, 0.8]),
+    Dict("id" => "iwp", "display_name" => "I-WP", "porosity_range" => [0.25, 0.85]),
+]
+
+# Capabilities, so clients can hide options this backend doesn't support
+@get "/info" function()
+    return Dict(
+        "version" => "1.0.0",
+        "available_algorithms" => ["analyze", "analyze_estimate", "optimize",
+                                   "tpms_generate", "tpms_preview", "tpms_estimate",
+                                   "mesh_simplify", "mesh_compare", "mesh_volume", "mesh_validate", "mesh_printability",
+                                   "anisotropy", "scaffold_merge", "mechanics_homogenize", "gcode"],
+        "supported_export_formats" => ["stl", "gcode"],
+        "supported_surface_types" => [t["id"] for t in SURFACE_TYPES],
+        "surface_types" => SURFACE_TYPES
+    )
+end
+
 # Analyze Scaffold
 @post "/analyze" function(req::HTTP.Request)
     try
@@ -90,13 +232,55 @@ end
         voxel_size = get(data, "voxel_size", 10.0)
         
         # Load image
+        report_progress(data, "loading", 0.0)
         volume = load_image(file_path)
         
+        # Optional region of interest: [x0, y0, z0, x1, y1, z1], 0-based with exclusive upper bounds
+        roi = get(data, "roi", nothing)
+        if roi !== nothing
+            lo = Int.(roi[1:3]) .+ 1
+            hi = Int.(roi[4:6])
+            if any(hi .< lo) || any(hi .> size(volume)[1:3])
+                error("roi $(roi) does not fit a volume of size $(size(volume))")
+            end
+            volume = volume[lo[1]:hi[1], lo[2]:hi[2], lo[3]:hi[3]]
+        end
+        
+        # Optional downsampling (factor chosen by darwin-server from the header);
+        # each kept voxel now spans `factor` original voxels along every axis
+        factor = Int(get(data, "downsample_factor", 1))
+        max_voxels = get(data, "max_voxels", nothing)
+        if factor == 1 && max_voxels !== nothing
+            # Header carried no size (e.g. TIFF stacks): pick the factor from the loaded volume
+            factor = downsample_factor(size(volume), max_voxels)
+        end
+        if factor > 1
+            volume = volume[ntuple(d -> 1:factor:size(volume, d), ndims(volume))...]
+            voxel_size *= factor
+        end
+        
         # Preprocess
+        report_progress(data, "preprocessing", 0.2)
         volume_clean = preprocess_image(volume)
         
         # Segment
+        report_progress(data, "segmenting", 0.4)
         binary = segment_scaffold(volume_clean)
+        report_progress(data, "metrics", 0.6)
+        
+        # Only some metrics requested: skip the rest, and the thesis extras
+        selected = get(data, "metrics", nothing)
+        if selected !== nothing
+            metrics = compute_selected_metrics(binary, voxel_size, String.(selected))
+            report_progress(data, "done", 1.0)
+            return Dict(
+                "metrics" => metrics,
+                "skipped_metrics" => setdiff(METRIC_NAMES, keys(metrics)),
+                "volume_shape" => size(volume),
+                "downsample_factor" => factor,
+                "status" => "success"
+            )
+        end
         
         # Compute metrics
         basic_metrics = compute_metrics(binary, voxel_size)
@@ -115,11 +299,13 @@ end
         # Detect problems
         optimizer = Optimizer(voxel_size)
         problems = detect_problems(optimizer, basic_metrics)
+        report_progress(data, "done", 1.0)
         
         return Dict(
             "metrics" => metrics,
             "problems" => problems,
             "volume_shape" => size(volume),
+            "downsample_factor" => factor,
             "status" => "success"
         )
     catch e
@@ -128,6 +314,66 @@ end
     end
 end
 
+"""Smallest stride keeping at most `max_voxels` of a volume of size `dims`."""
+function downsample_factor(dims, max_voxels)
+    factor = 1
+    while prod(cld.(dims, factor)) > max_voxels
+        factor += 1
+    end
+    return factor
+end
+
+# Edge of the crop `/analyze/estimate` times the analysis on
+const ESTIMATE_SAMPLE_EDGE = 48
+# Runtime grows a little faster than the voxel count (connectivity, pore sizes)
+const ESTIMATE_EXPONENT = 1.1
+# Full analyses fell within this factor of the extrapolation
+const ESTIMATE_RELATIVE_ERROR = 0.5
+
+"""Preprocessing, segmentation and every metric, as `/analyze` runs them."""
+function run_analysis_pipeline(volume, voxel_size)
+    binary = segment_scaffold(preprocess_image(volume))
+    compute_metrics(binary, voxel_size)
+    compute_kec_metrics(binary, voxel_size)
+    compute_percolation_metrics(binary, voxel_size)
+    predict_viability(binary)
+end
+
+# Predict runtime and memory of `/analyze` on a file: the full pipeline runs on a
+# crop, and its time and allocations are scaled up to the analyzed voxel count
+@post "/analyze/estimate" function(req::HTTP.Request)
+    try
+        data = json(req)
+        voxel_size = get(data, "voxel_size", 10.0)
+        max_voxels = get(data, "max_voxels", nothing)
+
+        load_seconds = @elapsed volume = load_image(data["file_path"])
+        factor = isnothing(max_voxels) ? 1 : downsample_factor(size(volume)[1:3], max_voxels)
+        voxel_count = prod(cld.(size(volume)[1:3], factor))
+
+        sample = volume[ntuple(d -> 1:factor:min(size(volume, d), factor * ESTIMATE_SAMPLE_EDGE), 3)...]
+        # Compile first, so the timing is of the analysis and not of the JIT
+        run_analysis_pipeline(sample[ntuple(d -> 1:min(size(sample, d), 8), 3)...], voxel_size * factor)
+        stats = @timed run_analysis_pipeline(sample, voxel_size * factor)
+
+        scale = voxel_count / length(sample)
+        seconds = load_seconds + stats.time * scale^ESTIMATE_EXPONENT
+        memory_mb = (Base.summarysize(volume) + stats.bytes * scale) / 2^20
+        spread = 1 + ESTIMATE_RELATIVE_ERROR
+        return Dict(
+            "voxel_count" => voxel_count,
+            "estimated_seconds" => seconds,
+            "estimated_memory_mb" => memory_mb,
+            "seconds_range" => [seconds / spread, seconds * spread],
+            "memory_mb_range" => [memory_mb / spread, memory_mb * spread],
+            "source" => "backend"
+        )
+    catch e
+        @error "Analysis estimate failed" exception=(e, catch_backtrace())
+        return HTTP.Response(500, JSON.json(Dict("error" => string(e))))
+    end
+end
+
 # Optimize Scaffold
 @post "/optimize" function(req::HTTP.Request)
     try
@@ -154,12 +400,15 @@ end
         original_vol = zeros(Bool, 100, 100, 100) # Placeholder
         
         # Use new thesis optimization
+        report_progress(data, "optimizing", 0.0)
         results = optimize_scaffold_thesis(optimizer, original_vol, params, material, use_case)
+        report_progress(data, "meshing", 0.8)
         
         # Save optimized result to temp file
         output_path = "/tmp/optimized_scaffold_$(time()).stl"
         mesh = create_mesh(results.optimized_volume, params.resolution_um)
         export_stl(mesh, output_path)
+        report_progress(data, "done", 1.0)
         
         # Calculate improvements (Thesis metrics)
         # For demo, we just return the metrics directly
@@ -433,6 +682,51 @@ end
     end
 end
 
+# Rough voxel meshing and STL export throughput, and mesh memory per triangle
+# (three Int face indices plus about half a Float64 vertex)
+const TPMS_VOXELS_PER_SECOND = 5.0e7
+const TPMS_TRIANGLES_PER_SECOND = 2.0e6
+const TPMS_MESH_BYTES_PER_TRIANGLE = 3 * 8 + 12
+
+"""
+    exposed_faces(volume) -> Int
+
+Voxel faces between solid and void, counting outside the volume as void.
+"""
+function exposed_faces(volume::AbstractArray{Bool,3})
+    padded = falses(size(volume) .+ 2)
+    padded[2:end-1, 2:end-1, 2:end-1] .= volume
+    return sum(count(!=(0), diff(padded; dims=d)) for d in 1:3)
+end
+
+@post "/tpms/estimate" function(req::HTTP.Request)
+    try
+        data = json(req)
+
+        surface_type = Symbol(get(data, "surface_type", "gyroid"))
+        porosity = get(data, "porosity", 0.75)
+        unit_cell_size = get(data, "unit_cell_size", 2.0)
+        resolution = get(data, "grid_resolution", 64)
+        iso_value = get(data, "iso_value", 0.0)
+
+        # Sample at preview resolution and scale up: the surface grows with resolution^2
+        sample = min(resolution, 32)
+        volume = generate_tpms_scaffold(surface_type, porosity, unit_cell_size, sample, iso_value)
+        triangles = round(Int, 2 * exposed_faces(volume) * (resolution / sample)^2)
+        voxels = resolution^3
+
+        return Dict(
+            "estimated_triangles" => triangles,
+            "estimated_bytes" => 84 + 50 * triangles,  # Binary STL
+            "estimated_seconds" => voxels / TPMS_VOXELS_PER_SECOND + triangles / TPMS_TRIANGLES_PER_SECOND,
+            "memory_mb" => (voxels + triangles * TPMS_MESH_BYTES_PER_TRIANGLE) / 1e6
+        )
+    catch e
+        @error "TPMS estimate failed" exception=(e, catch_backtrace())
+        return HTTP.Response(500, JSON.json(Dict("error" => string(e))))
+    end
+end
+
 # ============================================================================
 # Phase 2: Heatmap Endpoints
 # ============================================================================
@@ -474,6 +768,270 @@ end
     end
 end
 
+# ============================================================================
+# Thumbnail Endpoints
+# ============================================================================
+
+# Content hash of the current volume; changes on every edit, undo or redo
+workspace_revision(ws::WorkspaceState) = string(hash(ws.volume); base=16)
+
+@get "/workspace/{id}/revision" function(req::HTTP.Request, id::String)
+    ws = get_workspace(id)
+    if isnothing(ws)
+        return HTTP.Response(404, JSON.json(Dict("error" => "Workspace not found")))
+    end
+    if isnothing(ws.volume)
+        return Dict("workspace_id" => id, "has_volume" => false, "revision" => nothing)
+    end
+    return Dict("workspace_id" => id, "has_volume" => true, "revision" => workspace_revision(ws))
+end
+
+"""
+Grayscale projection of a binary volume: `top` looks down z, `front` down y and
+`iso` stacks sheared z-slices so upper layers overlap lower ones, shaded by height.
+"""
+function render_thumbnail(volume::Array{Bool,3}, projection::String, size_px::Int)
+    img = if projection == "top"
+        dropdims(maximum(volume; dims=3); dims=3) .* 1.0
+    elseif projection == "front"
+        dropdims(maximum(volume; dims=2); dims=2) .* 1.0
+    else
+        nx, ny, nz = size(volume)
+        acc = zeros(nx + nz, ny + nz)
+        for z in 1:nz
+            offset = nz - z
+            shade = 0.3 + 0.7 * z / nz
+            region = view(acc, offset+1:offset+nx, offset+1:offset+ny)
+            region[volume[:, :, z]] .= shade
+        end
+        acc
+    end
+    return Gray.(clamp.(imresize(img, (size_px, size_px)), 0.0, 1.0))
+end
+
+@get "/workspace/{id}/thumbnail" function(req::HTTP.Request, id::String)
+    try
+        ws = get_workspace(id)
+        if isnothing(ws) || isnothing(ws.volume)
+            return HTTP.Response(404, JSON.json(Dict("error" => "No volume data")))
+        end
+
+        params = HTTP.queryparams(HTTP.URI(req.target))
+        size_px = clamp(parse(Int, get(params, "size", "128")), 16, 1024)
+        projection = get(params, "view", "iso")
+
+        io = IOBuffer()
+        save(Stream{format"PNG"}(io), render_thumbnail(ws.volume, projection, size_px))
+        return HTTP.Response(200, [
+            "Content-Type" => "image/png",
+            "X-Workspace-Revision" => workspace_revision(ws)
+        ], take!(io))
+    catch e
+        @error "Thumbnail rendering failed" exception=(e, catch_backtrace())
+        return HTTP.Response(500, JSON.json(Dict("error" => string(e))))
+    end
+end
+
+# ============================================================================
+# Depth Profile Endpoints
+# ============================================================================
+
+const DEPTH_AXES = Dict("x" => 1, "y" => 2, "z" => 3)
+
+"""
+Mean length, in voxels, of the runs of `true` in `mask` along dimension `dim`.
+"""
+function mean_run_length(mask::AbstractArray{Bool,3}, dim::Int)
+    m = dim == 1 ? mask : permutedims(mask, dim == 2 ? (2, 1, 3) : (3, 2, 1))
+    total = 0
+    runs = 0
+    for k in axes(m, 3), j in axes(m, 2)
+        inside = false
+        for i in axes(m, 1)
+            if m[i, j, k]
+                total += 1
+                inside || (runs += 1)
+            end
+            inside = m[i, j, k]
+        end
+    end
+    return runs == 0 ? 0.0 : total / runs
+end
+
+"""
+Porosity, solid surface-to-volume ratio (1/mm) and mean in-plane pore chord (µm)
+of `bins` consecutive slabs of `volume` along dimension `axis`. A volume thinner
+than `bins` voxels gets one bin per slice.
+"""
+function depth_profile(volume::Array{Bool,3}, axis::Int, bins::Int, voxel_size_um::Float64)
+    n = size(volume, axis)
+    bins = clamp(bins, 1, n)
+    edges = round.(Int, range(0, n; length=bins + 1))
+    voxel_mm = voxel_size_um * 1e-3
+    in_plane = filter(!=(axis), 1:3)
+    return map(1:bins) do b
+        slab = selectdim(volume, axis, edges[b]+1:edges[b+1])
+        solid = count(slab)
+        # Solid/pore interfaces inside the slab
+        faces = sum(d -> size(slab, d) > 1 ? count(!=(0), diff(Int8.(slab); dims=d)) : 0, 1:3)
+        Dict(
+            "bin" => b - 1,
+            "depth_um" => (edges[b] + edges[b+1]) / 2 * voxel_size_um,
+            "porosity" => 1.0 - solid / length(slab),
+            "sa_to_v" => solid == 0 ? 0.0 : faces * voxel_mm^2 / (solid * voxel_mm^3),
+            "pore_size" => sum(d -> mean_run_length(.!slab, d), in_plane) / 2 * voxel_size_um,
+        )
+    end
+end
+
+@get "/workspace/{id}/depth_profile" function(req::HTTP.Request, id::String)
+    try
+        ws = get_workspace(id)
+        if isnothing(ws) || isnothing(ws.volume)
+            return HTTP.Response(404, JSON.json(Dict("error" => "No volume data")))
+        end
+
+        params = HTTP.queryparams(HTTP.URI(req.target))
+        axis = get(params, "axis", "z")
+        if !haskey(DEPTH_AXES, axis)
+            return HTTP.Response(400, JSON.json(Dict("error" => "axis must be x, y or z")))
+        end
+        bins = clamp(parse(Int, get(params, "bins", "32")), 1, 1024)
+        voxel_size = parse(Float64, get(params, "voxel_size", "10.0"))
+
+        return Dict(
+            "workspace_id" => id,
+            "axis" => axis,
+            "voxel_size_um" => voxel_size,
+            "bins" => depth_profile(ws.volume, DEPTH_AXES[axis], bins, voxel_size),
+        )
+    catch e
+        @error "Depth profile failed" exception=(e, catch_backtrace())
+        return HTTP.Response(500, JSON.json(Dict("error" => string(e))))
+    end
+end
+
+# ============================================================================
+# Anisotropy Endpoints
+# ============================================================================
+
+# One direction of each opposite pair in the 26-neighbourhood
+const MIL_DIRECTIONS = [(1, 0, 0), (0, 1, 0), (0, 0, 1),
+                        (1, 1, 0), (1, -1, 0), (1, 0, 1), (1, 0, -1), (0, 1, 1), (0, 1, -1),
+                        (1, 1, 1), (1, 1, -1), (1, -1, 1), (-1, 1, 1)]
+
+"""
+Mean intercept length, in voxels, of `pores` along `step`: the pore length that
+lines in that direction cross per pore/solid interface. Lines through voxel
+centres advance one `step` per voxel, so each pore voxel adds `norm(step)`.
+"""
+function mean_intercept_length(pores::AbstractArray{Bool,3}, step::NTuple{3,Int})
+    crossings = 0
+    for i in CartesianIndices(pores)
+        pores[i] || continue
+        next = Tuple(i) .+ step
+        if checkbounds(Bool, pores, next...) && !pores[next...]
+            crossings += 1
+        end
+    end
+    # A direction with no interface is bounded by the volume itself
+    return count(pores) * sqrt(sum(abs2, step)) / max(crossings, 1)
+end
+
+"""
+Fabric tensor of the pore space from mean intercept lengths: fits the ellipsoid
+`1 / MIL(n)^2 = n' M n` over `MIL_DIRECTIONS` and returns `M^(-1/2)`, whose
+eigenvalues are the principal intercept lengths in voxels.
+"""
+function mil_fabric_tensor(pores::AbstractArray{Bool,3})
+    rows = map(MIL_DIRECTIONS) do step
+        n = collect(step) ./ sqrt(sum(abs2, step))
+        [n[1]^2, n[2]^2, n[3]^2, 2n[1]*n[2], 2n[1]*n[3], 2n[2]*n[3]]
+    end
+    A = permutedims(reduce(hcat, rows))
+    b = [1 / mean_intercept_length(pores, step)^2 for step in MIL_DIRECTIONS]
+    m = A \ b
+    M = Symmetric([m[1] m[4] m[5]; m[4] m[2] m[6]; m[5] m[6] m[3]])
+    F = eigen(M)
+    # Noise can push a fitted eigenvalue to zero or below; treat it as very long
+    lengths = 1 ./ sqrt.(max.(F.values, 1e-12))
+    return F.vectors * Diagonal(lengths) * F.vectors', lengths, F.vectors
+end
+
+@get "/workspace/{id}/anisotropy" function(req::HTTP.Request, id::String)
+    try
+        ws = get_workspace(id)
+        if isnothing(ws) || isnothing(ws.volume)
+            return HTTP.Response(404, JSON.json(Dict("error" => "No volume data")))
+        end
+        pores = .!ws.volume
+        if !any(pores) || all(pores)
+            return HTTP.Response(422, JSON.json(Dict("error" => "pore space is empty or has no walls")))
+        end
+
+        started = time()
+        tensor, lengths, vectors = mil_fabric_tensor(pores)
+        largest = argmax(lengths)
+        return Dict(
+            "degree_of_anisotropy" => 1 - minimum(lengths) / maximum(lengths),
+            "primary_axis" => vectors[:, largest],
+            "eigenvalues" => sort(lengths; rev=true),
+            "fabric_tensor" => [tensor[i, :] for i in 1:3],
+            "elapsed_ms" => round(Int, (time() - started) * 1000)
+        )
+    catch e
+        @error "Anisotropy analysis failed" exception=(e, catch_backtrace())
+        return HTTP.Response(500, JSON.json(Dict("error" => string(e))))
+    end
+end
+
+# ============================================================================
+# Workspace Snapshot Endpoints
+# ============================================================================
+
+# Serialization's format is only guaranteed to round-trip on the same Julia
+# version, so snapshots carry the version that wrote them.
+@get "/workspace/{id}/snapshot" function(req::HTTP.Request, id::String)
+    try
+        ws = get_workspace(id)
+        if isnothing(ws)
+            return HTTP.Response(404, JSON.json(Dict("error" => "Workspace not found")))
+        end
+
+        io = IOBuffer()
+        serialize(io, ws)
+        return HTTP.Response(200, [
+            "Content-Type" => "application/octet-stream",
+            "X-Julia-Version" => string(VERSION)
+        ], take!(io))
+    catch e
+        @error "Workspace snapshot failed" exception=(e, catch_backtrace())
+        return HTTP.Response(500, JSON.json(Dict("error" => string(e))))
+    end
+end
+
+@post "/workspace/restore" function(req::HTTP.Request)
+    snapshot_version = HTTP.header(req, "X-Julia-Version", "")
+    ws = try
+        deserialize(IOBuffer(req.body))
+    catch e
+        msg = isempty(snapshot_version) || snapshot_version == string(VERSION) ?
+            "snapshot could not be read: $(e)" :
+            "snapshot from Julia $snapshot_version cannot be read by Julia $(VERSION): $(e)"
+        return HTTP.Response(422, JSON.json(Dict("error" => msg)))
+    end
+    if !(ws isa WorkspaceState)
+        return HTTP.Response(422, JSON.json(Dict("error" => "snapshot does not hold a workspace")))
+    end
+
+    WORKSPACES[ws.id] = ws
+    return Dict(
+        "workspace_id" => ws.id,
+        "has_volume" => !isnothing(ws.volume),
+        "julia_version" => string(VERSION),
+    )
+end
+
 # ============================================================================
 # Phase 2: Image Import Endpoints
 # ============================================================================
@@ -649,6 +1207,67 @@ end
     end
 end
 
+"""
+Ollama messages for a streamed agent turn: the agent's system prompt, the chat
+so far (which already ends with `content`), then any tool calls made for it
+with their results. `history` holds `{role, content}` pairs; tool records in it
+are darwin-server's own and are skipped.
+"""
+function agent_stream_messages(agent_type::String, content::String, history, tool_results)
+    messages = [Dict("role" => "system", "content" => build_agent_system_prompt(agent_type, Dict()))]
+    for entry in history
+        role = string(entry["role"])
+        role in ("user", "assistant") && push!(messages, Dict("role" => role, "content" => string(entry["content"])))
+    end
+    if isempty(history) || messages[end]["content"] != content
+        push!(messages, Dict("role" => "user", "content" => content))
+    end
+    for r in tool_results
+        push!(messages, Dict("role" => "assistant", "content" => "Calling $(r["tool_name"]) with $(JSON.json(r["args"]))"))
+        push!(messages, Dict("role" => "tool", "content" => JSON.json(r["result"])))
+    end
+    return messages
+end
+
+# One NDJSON line per event. A `tool_start` asks darwin-server to run one of the
+# `local_tools` it listed; the turn then ends, and comes back with the outcomes
+# under `tool_results`. Ollama answers in one piece, so the body is written whole.
+@post "/agents/chat/stream" function(req::HTTP.Request)
+    try
+        data = json(req)
+        agent_type = string(something(get(data, "agent_type", nothing), "design"))
+        local_tools = something(get(data, "local_tools", nothing), [])
+        messages = agent_stream_messages(agent_type, string(data["content"]),
+                                         something(get(data, "history", nothing), []),
+                                         something(get(data, "tool_results", nothing), []))
+
+        tools = Dict[Dict("type" => "function",
+                          "function" => Dict("name" => string(t["name"]),
+                                             "description" => string(t["description"]),
+                                             "parameters" => Dict("type" => "object")))
+                     for t in local_tools]
+        model = DarwinScaffoldStudio.OllamaClient.OllamaModel("qwen2.5:7b")
+        reply = DarwinScaffoldStudio.OllamaClient.chat(model, messages; tools=isempty(tools) ? nothing : tools)
+
+        known = Set(string(t["name"]) for t in local_tools)
+        events = Dict{String, Any}[]
+        for call in something(get(reply, "tool_calls", nothing), [])
+            name = string(call["function"]["name"])
+            name in known || continue
+            push!(events, Dict("type" => "tool_start", "id" => string(uuid4()), "tool_name" => name,
+                               "args" => something(get(call["function"], "arguments", nothing), Dict())))
+        end
+        response = isempty(events) ? get(reply, "content", "I apologize, I couldn't generate a response.") : nothing
+        push!(events, Dict("type" => "done", "response" => response))
+
+        return HTTP.Response(200, ["Content-Type" => "application/x-ndjson"],
+                             join(JSON.json(event) * "\n" for event in events))
+    catch e
+        @error "Agent chat stream failed" exception=(e, catch_backtrace())
+        return HTTP.Response(500, JSON.json(Dict("error" => string(e))))
+    end
+end
+
 @post "/agents/text-to-scaffold" function(req::HTTP.Request)
     try
         data = json(req)
@@ -685,10 +1304,650 @@ end
     end
 end
 
+# ============================================================================
+# Mesh Simplification Endpoints
+# ============================================================================
+
+# Simplified meshes per workspace; revision n is MESH_REVISIONS[id][n]. The
+# voxel surface itself is never replaced, so exports can still use the original.
+const MESH_REVISIONS = Dict{String, Vector{Tuple{Matrix{Float64}, Matrix{Int}}}}()
+
+"""
+Vertex-clustering decimation: vertices are snapped to a grid and merged per cell,
+and triangles that collapse are dropped. The cell size grows until at least
+`target_reduction` of the triangles are gone (or the mesh stops shrinking).
+With `preserve_boundaries`, vertices on open edges are never merged.
+"""
+function simplify_mesh(vertices::AbstractMatrix{Float64}, faces::AbstractMatrix{Int},
+                        target_reduction::Float64; preserve_boundaries::Bool=true)
+    n_faces = size(faces, 1)
+    n_faces == 0 && return (vertices, faces)
+    target_faces = max(1, round(Int, n_faces * (1 - target_reduction)))
+
+    # Open edges belong to exactly one triangle
+    edge_count = Dict{Tuple{Int,Int}, Int}()
+    for f in eachrow(faces), (a, b) in ((f[1], f[2]), (f[2], f[3]), (f[3], f[1]))
+        key = minmax(a, b)
+        edge_count[key] = get(edge_count, key, 0) + 1
+    end
+    boundary = falses(size(vertices, 1))
+    if preserve_boundaries
+        for ((a, b), count) in edge_count
+            if count == 1
+                boundary[a] = boundary[b] = true
+            end
+        end
+    end
+
+    lo = vec(minimum(vertices; dims=1))
+    extent = maximum(vec(maximum(vertices; dims=1)) .- lo)
+    cell = extent / 256
+    best = (vertices, faces)
+    while cell <= extent
+        cluster_of = Dict{Any, Int}()
+        remap = Vector{Int}(undef, size(vertices, 1))
+        sums = Vector{Vector{Float64}}()
+        counts = Int[]
+        for i in 1:size(vertices, 1)
+            key = boundary[i] ? (:boundary, i) : Tuple(floor.(Int, (vertices[i, :] .- lo) ./ cell))
+            c = get!(cluster_of, key) do
+                push!(sums, zeros(3)); push!(counts, 0)
+                length(counts)
+            end
+            sums[c] .+= vertices[i, :]
+            counts[c] += 1
+            remap[i] = c
+        end
+
+        kept = Set{NTuple{3,Int}}()
+        for f in eachrow(faces)
+            a, b, c = remap[f[1]], remap[f[2]], remap[f[3]]
+            if a != b && b != c && a != c
+                push!(kept, (a, b, c))
+            end
+        end
+
+        new_vertices = reduce(vcat, (s ./ n)' for (s, n) in zip(sums, counts))
+        new_faces = isempty(kept) ? zeros(Int, 0, 3) : reduce(vcat, [collect(t)' for t in kept])
+        best = (new_vertices, new_faces)
+        size(new_faces, 1) <= target_faces && break
+        cell *= 1.5
+    end
+    return best
+end
+
+@post "/mesh/simplify" function(req::HTTP.Request)
+    try
+        data = json(req)
+        workspace_id = data["workspace_id"]
+        target_reduction = Float64(data["target_reduction"])
+        preserve_boundaries = get(data, "preserve_boundaries", true)
+        if !(0 < target_reduction < 1)
+            return HTTP.Response(400, JSON.json(Dict("error" => "target_reduction must be in (0, 1)")))
+        end
+
+        ws = get_workspace(workspace_id)
+        if isnothing(ws) || isnothing(ws.volume)
+            return HTTP.Response(404, JSON.json(Dict("error" => "No volume data")))
+        end
+
+        vertices, faces = create_mesh_simple(ws.volume, 10.0)
+        new_vertices, new_faces = simplify_mesh(Float64.(vertices), faces, target_reduction;
+                                                preserve_boundaries=preserve_boundaries)
+
+        revisions = get!(MESH_REVISIONS, workspace_id, Tuple{Matrix{Float64}, Matrix{Int}}[])
+        push!(revisions, (new_vertices, new_faces))
+
+        before, after = size(faces, 1), size(new_faces, 1)
+        return Dict(
+            "workspace_id" => workspace_id,
+            "mesh_revision" => length(revisions),
+            "triangles_before" => before,
+            "triangles_after" => after,
+            "achieved_reduction" => before == 0 ? 0.0 : 1 - after / before
+        )
+    catch e
+        @error "Mesh simplification failed" exception=(e, catch_backtrace())
+        return HTTP.Response(500, JSON.json(Dict("error" => string(e))))
+    end
+end
+
+@post "/mesh/volume" function(req::HTTP.Request)
+    try
+        data = json(req)
+        workspace_id = data["workspace_id"]
+
+        ws = get_workspace(workspace_id)
+        if isnothing(ws) || isnothing(ws.volume)
+            return HTTP.Response(404, JSON.json(Dict("error" => "No volume data")))
+        end
+
+        # Exported meshes are built from the same 10 um voxels, so the solid voxels
+        # give the enclosed mesh volume exactly
+        voxel_size_mm = 10.0 / 1000
+        return Dict(
+            "workspace_id" => workspace_id,
+            "volume_mm3" => count(ws.volume) * voxel_size_mm^3,
+            "bounding_box_mm" => collect(size(ws.volume)) .* voxel_size_mm
+        )
+    catch e
+        @error "Mesh volume failed" exception=(e, catch_backtrace())
+        return HTTP.Response(500, JSON.json(Dict("error" => string(e))))
+    end
+end
+
+cross3(x, y) = (x[2] * y[3] - x[3] * y[2], x[3] * y[1] - x[1] * y[3], x[1] * y[2] - x[2] * y[1])
+
+"""
+    segment_crosses_triangle(p, q, a, b, c) -> Bool
+
+Whether the open segment p-q passes through the interior of triangle abc
+(Moller-Trumbore). Touching at an edge or corner does not count.
+"""
+function segment_crosses_triangle(p, q, a, b, c; tol=1e-9)
+    d = q .- p
+    e1, e2 = b .- a, c .- a
+    h = cross3(d, e2)
+    det = sum(e1 .* h)
+    abs(det) < 1e-12 && return false  # parallel or coplanar
+    s = p .- a
+    u = sum(s .* h) / det
+    tol < u < 1 - tol || return false
+    r = cross3(s, e1)
+    v = sum(d .* r) / det
+    (v > tol && u + v < 1 - tol) || return false
+    t = sum(e2 .* r) / det
+    return tol < t < 1 - tol
+end
+
+"""
+    count_self_intersections(vertices, faces) -> Int
+
+Pairs of triangles sharing no vertex whose surfaces cross. Triangles are binned
+on a uniform grid by bounding box, so only nearby pairs are tested.
+"""
+function count_self_intersections(vertices::AbstractMatrix{Float64}, faces::AbstractMatrix{Int})
+    n = size(faces, 1)
+    n < 2 && return 0
+    point(i) = (vertices[i, 1], vertices[i, 2], vertices[i, 3])
+    lo = vec(minimum(vertices; dims=1))
+    extent = maximum(vec(maximum(vertices; dims=1)) .- lo)
+    cell = max(extent / max(cbrt(n), 1), eps())
+    grid = Dict{NTuple{3,Int}, Vector{Int}}()
+    for t in 1:n
+        corners = vertices[faces[t, :], :]
+        from = floor.(Int, (vec(minimum(corners; dims=1)) .- lo) ./ cell)
+        to = floor.(Int, (vec(maximum(corners; dims=1)) .- lo) ./ cell)
+        for x in from[1]:to[1], y in from[2]:to[2], z in from[3]:to[3]
+            push!(get!(grid, (x, y, z), Int[]), t)
+        end
+    end
+
+    edges(f) = ((point(f[1]), point(f[2])), (point(f[2]), point(f[3])), (point(f[3]), point(f[1])))
+    crosses(f, g) = any(segment_crosses_triangle(p, q, point(g[1]), point(g[2]), point(g[3])) for (p, q) in edges(f))
+    crossing = Set{Tuple{Int,Int}}()
+    for members in values(grid), i in 1:length(members), j in i+1:length(members)
+        pair = minmax(members[i], members[j])
+        pair in crossing && continue
+        f, g = faces[pair[1], :], faces[pair[2], :]
+        isempty(intersect(f, g)) || continue
+        if crosses(f, g) || crosses(g, f)
+            push!(crossing, pair)
+        end
+    end
+    return length(crossing)
+end
+
+"""
+    mesh_report(vertices, faces) -> Dict
+
+Topology checks run before export: every edge shared by at most two triangles
+(manifold) or exactly two (watertight), zero-area triangles, and crossing triangles.
+"""
+function mesh_report(vertices::AbstractMatrix{Float64}, faces::AbstractMatrix{Int})
+    edge_count = Dict{Tuple{Int,Int}, Int}()
+    for f in eachrow(faces), (a, b) in ((f[1], f[2]), (f[2], f[3]), (f[3], f[1]))
+        key = minmax(a, b)
+        edge_count[key] = get(edge_count, key, 0) + 1
+    end
+    counts = collect(values(edge_count))
+    point(i) = (vertices[i, 1], vertices[i, 2], vertices[i, 3])
+    area(f) = sqrt(sum(abs2, cross3(point(f[2]) .- point(f[1]), point(f[3]) .- point(f[1])))) / 2
+    return Dict{String, Any}(
+        "manifold" => all(<=(2), counts),
+        "watertight" => !isempty(counts) && all(==(2), counts),
+        "degenerate_faces" => count(f -> length(unique(f)) < 3 || area(f) < 1e-12, eachrow(faces)),
+        "self_intersections" => count_self_intersections(vertices, faces)
+    )
+end
+
+@post "/mesh/validate" function(req::HTTP.Request)
+    try
+        data = json(req)
+        workspace_id = data["workspace_id"]
+
+        ws = get_workspace(workspace_id)
+        if isnothing(ws) || isnothing(ws.volume)
+            return HTTP.Response(404, JSON.json(Dict("error" => "No volume data")))
+        end
+
+        # The mesh STL export writes, from the same 10 um voxels
+        vertices, faces = create_mesh_simple(ws.volume, 10.0)
+        report = mesh_report(Float64.(vertices), faces)
+        report["bounding_box_mm"] = collect(size(ws.volume)) .* (10.0 / 1000)
+        return report
+    catch e
+        @error "Mesh validation failed" exception=(e, catch_backtrace())
+        return HTTP.Response(500, JSON.json(Dict("error" => string(e))))
+    end
+end
+
+# Percentile of local thickness taken as the thinnest feature, so single-voxel
+# staircase corners do not count as struts
+const MIN_FEATURE_PERCENTILE = 0.05
+
+"""
+Per solid voxel, the length in voxels of the shortest of its three axis-aligned
+solid runs: a local wall or strut thickness. Void voxels stay at `typemax(Int)`.
+"""
+function local_thickness(volume::Array{Bool,3})
+    thickness = fill(typemax(Int), size(volume))
+    for dim in 1:3
+        n = size(volume, dim)
+        step = CartesianIndex(ntuple(d -> d == dim ? 1 : 0, 3))
+        for start in CartesianIndices(ntuple(d -> d == dim ? (1:1) : axes(volume, d), 3))
+            at(k) = start + (k - 1) * step
+            i = 1
+            while i <= n
+                if !volume[at(i)]
+                    i += 1
+                    continue
+                end
+                j = i
+                while j < n && volume[at(j + 1)]
+                    j += 1
+                end
+                for k in i:j
+                    thickness[at(k)] = min(thickness[at(k)], j - i + 1)
+                end
+                i = j + 1
+            end
+        end
+    end
+    return thickness
+end
+
+"""
+Overhang angle, in degrees from vertical, of each exposed solid voxel off the
+volume boundary, with z as the build direction. Normals come from the solid in
+the 3x3x3 neighbourhood, so voxel staircases read as the slope they approximate.
+Upward-facing surfaces get 0.
+"""
+function overhang_angles(volume::Array{Bool,3})
+    nx, ny, nz = size(volume)
+    angles = Float64[]
+    for k in 2:nz-1, j in 2:ny-1, i in 2:nx-1
+        volume[i, j, k] || continue
+        exposed = !volume[i-1, j, k] || !volume[i+1, j, k] || !volume[i, j-1, k] ||
+                  !volume[i, j+1, k] || !volume[i, j, k-1] || !volume[i, j, k+1]
+        exposed || continue
+        g = (0.0, 0.0, 0.0)
+        for dk in -1:1, dj in -1:1, di in -1:1
+            volume[i+di, j+dj, k+dk] && (g = g .+ (di, dj, dk))
+        end
+        len = sqrt(sum(abs2, g))
+        # `g` points into the solid, so the surface faces down when g[3] > 0
+        push!(angles, len == 0 ? 0.0 : max(0.0, asind(g[3] / len)))
+    end
+    return angles
+end
+
+@post "/mesh/printability" function(req::HTTP.Request)
+    try
+        data = json(req)
+        workspace_id = data["workspace_id"]
+
+        ws = get_workspace(workspace_id)
+        if isnothing(ws) || isnothing(ws.volume)
+            return HTTP.Response(404, JSON.json(Dict("error" => "No volume data")))
+        end
+        thickness = sort(local_thickness(ws.volume)[ws.volume])
+        if isempty(thickness)
+            return HTTP.Response(422, JSON.json(Dict("error" => "workspace has no solid to print")))
+        end
+
+        voxel_size_mm = 0.01
+        angles = overhang_angles(ws.volume)
+        return Dict(
+            "min_feature_mm" => thickness[ceil(Int, MIN_FEATURE_PERCENTILE * length(thickness))] * voxel_size_mm,
+            "max_overhang_deg" => isempty(angles) ? 0.0 : maximum(angles),
+            "overhang_area_fraction" => isempty(angles) ? 0.0 : count(>(45), angles) / length(angles)
+        )
+    catch e
+        @error "Printability analysis failed" exception=(e, catch_backtrace())
+        return HTTP.Response(500, JSON.json(Dict("error" => string(e))))
+    end
+end
+
+"""
+    read_stl_vertices(path) -> Matrix{Float64}
+
+Unique vertices (n x 3) of a binary or ASCII STL file.
+"""
+function read_stl_vertices(path::String)
+    bytes = read(path)
+    points = NTuple{3,Float64}[]
+    n_binary = length(bytes) >= 84 ? reinterpret(UInt32, bytes[81:84])[1] : 0
+    if length(bytes) == 84 + 50 * Int(n_binary)
+        for t in 0:Int(n_binary)-1, v in 0:2
+            offset = 84 + 50t + 12 + 12v
+            xyz = reinterpret(Float32, bytes[offset+1:offset+12])
+            push!(points, (Float64(xyz[1]), Float64(xyz[2]), Float64(xyz[3])))
+        end
+    else
+        for line in eachline(IOBuffer(bytes))
+            parts = split(strip(line))
+            if length(parts) == 4 && parts[1] == "vertex"
+                push!(points, Tuple(parse.(Float64, parts[2:4])))
+            end
+        end
+    end
+    unique!(points)
+    return isempty(points) ? zeros(0, 3) : reduce(vcat, [collect(p)' for p in points])
+end
+
+"""
+    nearest_distances(from, to) -> Vector{Float64}
+
+Distance from each vertex of `from` to the closest vertex of `to`, using a uniform
+grid over `to` so large meshes stay tractable.
+"""
+function nearest_distances(from::AbstractMatrix{Float64}, to::AbstractMatrix{Float64})
+    lo = vec(minimum(to; dims=1))
+    extent = maximum(vec(maximum(to; dims=1)) .- lo)
+    cell = max(extent / max(cbrt(size(to, 1)), 1), eps())
+    grid = Dict{NTuple{3,Int}, Vector{Int}}()
+    for i in 1:size(to, 1)
+        push!(get!(grid, Tuple(floor.(Int, (to[i, :] .- lo) ./ cell)), Int[]), i)
+    end
+
+    distances = Vector{Float64}(undef, size(from, 1))
+    for i in 1:size(from, 1)
+        p = from[i, :]
+        center = floor.(Int, (p .- lo) ./ cell)
+        best = Inf
+        radius = 0
+        # Grow the searched shell until no unsearched cell can hold a closer vertex
+        while best > (radius - 1) * cell
+            for dx in -radius:radius, dy in -radius:radius, dz in -radius:radius
+                maximum(abs, (dx, dy, dz)) == radius || continue
+                for j in get(grid, (center[1] + dx, center[2] + dy, center[3] + dz), Int[])
+                    best = min(best, sqrt(sum(abs2, p .- to[j, :])))
+                end
+            end
+            radius += 1
+            radius > 2 + ceil(Int, extent / cell) + maximum(abs, center) && break
+        end
+        distances[i] = best
+    end
+    return distances
+end
+
+@post "/mesh/compare" function(req::HTTP.Request)
+    try
+        data = json(req)
+        a = read_stl_vertices(data["path_a"])
+        b = read_stl_vertices(data["path_b"])
+        if size(a, 1) == 0 || size(b, 1) == 0
+            empty = size(a, 1) == 0 ? "path_a" : "path_b"
+            return HTTP.Response(422, JSON.json(Dict("error" => "mesh at $empty has no vertices")))
+        end
+
+        # Symmetric Hausdorff distance over the vertices of both meshes
+        d_ab = nearest_distances(a, b)
+        d_ba = nearest_distances(b, a)
+        return Dict(
+            "max_deviation_mm" => max(maximum(d_ab), maximum(d_ba)),
+            "mean_deviation_mm" => (sum(d_ab) + sum(d_ba)) / (length(d_ab) + length(d_ba)),
+            "vertex_count_a" => size(a, 1),
+            "vertex_count_b" => size(b, 1)
+        )
+    catch e
+        @error "Mesh comparison failed" exception=(e, catch_backtrace())
+        return HTTP.Response(500, JSON.json(Dict("error" => string(e))))
+    end
+end
+
+# ============================================================================
+# Mechanics Endpoints
+# ============================================================================
+
+# Poisson ratio assumed for the solid; scaffold polymers and ceramics sit near it
+const SOLID_POISSON_RATIO = 0.3
+
+"""
+Young's modulus along `axis` relative to the solid's: the slices across the axis
+act in series, each stiff as its solid area fraction squared (Gibson-Ashby).
+Zero when some slice holds no solid.
+"""
+function axial_relative_modulus(volume::Array{Bool,3}, axis::Int)
+    fractions = [count(slice) / length(slice) for slice in eachslice(volume; dims=axis)]
+    any(iszero, fractions) && return 0.0
+    return length(fractions) / sum(f -> 1 / f^2, fractions)
+end
+
+"""
+    homogenized_stiffness(volume, base_modulus_mpa) -> Dict
+
+Orthotropic estimate of the effective stiffness: axial moduli from
+`axial_relative_modulus`, `nu_ij = nu * sqrt(E_i / E_j)` and
+`G_ij = sqrt(E_i * E_j) / (2 * (1 + nu))` (Huber), so the compliance, and the
+6x6 stiffness in Voigt order (11, 22, 33, 23, 13, 12) inverted from it, are symmetric.
+"""
+function homogenized_stiffness(volume::Array{Bool,3}, base_modulus_mpa::Float64)
+    E = [base_modulus_mpa * axial_relative_modulus(volume, axis) for axis in 1:3]
+    nu = SOLID_POISSON_RATIO
+    poisson(i, j) = nu * sqrt(E[i] / E[j])
+    shear(i, j) = sqrt(E[i] * E[j]) / (2 * (1 + nu))
+    G = [shear(2, 3), shear(1, 3), shear(1, 2)]
+
+    S = zeros(6, 6)
+    for i in 1:3, j in 1:3
+        S[i, j] = i == j ? 1 / E[i] : -poisson(i, j) / E[i]
+    end
+    for k in 1:3
+        S[3 + k, 3 + k] = 1 / G[k]
+    end
+    C = inv(S)
+
+    return Dict{String, Any}(
+        "stiffness_matrix_mpa" => [C[i, :] for i in 1:6],
+        "youngs_moduli_mpa" => E,
+        "shear_moduli_mpa" => G,
+        "poisson_ratios" => Dict("nu_$(i)$(j)" => poisson(i, j) for i in 1:3 for j in 1:3 if i != j)
+    )
+end
+
+@post "/mechanics/homogenize" function(req::HTTP.Request)
+    try
+        data = json(req)
+        workspace_id = data["workspace_id"]
+        base_modulus_mpa = Float64(data["base_modulus_mpa"])
+
+        ws = get_workspace(workspace_id)
+        if isnothing(ws) || isnothing(ws.volume)
+            return HTTP.Response(404, JSON.json(Dict("error" => "No volume data")))
+        end
+
+        # A slice without solid leaves that axis, and the compliance, singular
+        for (axis, name) in enumerate(("x", "y", "z"))
+            if axial_relative_modulus(ws.volume, axis) == 0
+                return HTTP.Response(422, JSON.json(Dict(
+                    "error" => "the solid does not span the workspace along $(name)")))
+            end
+        end
+
+        started = time()
+        result = homogenized_stiffness(ws.volume, base_modulus_mpa)
+        result["elapsed_ms"] = round(Int, (time() - started) * 1000)
+        return result
+    catch e
+        @error "Stiffness homogenization failed" exception=(e, catch_backtrace())
+        return HTTP.Response(500, JSON.json(Dict("error" => string(e))))
+    end
+end
+
+# ============================================================================
+# Scaffold Merge Endpoints
+# ============================================================================
+
+# Overlapping parts that disagree on more than this fraction of the shared
+# voxels are reported as conflicts instead of merged
+const MERGE_CONFLICT_FRACTION = 0.1
+
+"""
+Rotation of a merge `transform` (`[tx, ty, tz, rx, ry, rz]`, degrees): about x,
+then y, then z, the order the desktop places parts in.
+"""
+function merge_rotation(transform)
+    rx, ry, rz = deg2rad.(Float64.(transform[4:6]))
+    Rx = [1 0 0; 0 cos(rx) -sin(rx); 0 sin(rx) cos(rx)]
+    Ry = [cos(ry) 0 sin(ry); 0 1 0; -sin(ry) 0 cos(ry)]
+    Rz = [cos(rz) -sin(rz) 0; sin(rz) cos(rz) 0; 0 0 1]
+    return Rz * Ry * Rx
+end
+
+"""
+    merge_volumes(volumes, voxel_sizes, transforms, grid_mm) -> (merged, origin_mm, conflicts)
+
+Resample the placed binary volumes onto one grid of `grid_mm` voxels spanning
+them all. A voxel is solid if any part covering it is; `conflicts` are the
+`(a, b, overlap_mm3)` pairs whose overlap disagrees beyond `MERGE_CONFLICT_FRACTION`.
+"""
+function merge_volumes(volumes, voxel_sizes, transforms, grid_mm::Float64)
+    n = length(volumes)
+    rotations = [merge_rotation(t) for t in transforms]
+    offsets = [Tuple(Float64.(t[1:3])) for t in transforms]
+
+    lo = fill(Inf, 3)
+    hi = fill(-Inf, 3)
+    for k in 1:n
+        extent = size(volumes[k]) .* voxel_sizes[k]
+        for corner in Iterators.product((0, extent[1]), (0, extent[2]), (0, extent[3]))
+            p = rotations[k] * collect(corner) .+ collect(offsets[k])
+            lo .= min.(lo, p)
+            hi .= max.(hi, p)
+        end
+    end
+    dims = Tuple(max.(ceil.(Int, (hi .- lo) ./ grid_mm), 1))
+
+    merged = falses(dims)
+    overlap = zeros(Int, n, n)
+    disagree = zeros(Int, n, n)
+    samples = zeros(Int8, n)  # -1 outside the part, else its voxel
+    for c in CartesianIndices(dims)
+        p = ntuple(d -> lo[d] + (c[d] - 0.5) * grid_mm, 3)
+        for k in 1:n
+            R, q = rotations[k], p .- offsets[k]
+            # Back into the part's own frame: R' * q
+            local_mm = ntuple(i -> R[1, i] * q[1] + R[2, i] * q[2] + R[3, i] * q[3], 3)
+            idx = floor.(Int, local_mm ./ voxel_sizes[k]) .+ 1
+            samples[k] = checkbounds(Bool, volumes[k], idx...) ? Int8(volumes[k][idx...]) : Int8(-1)
+        end
+        merged[c] = any(==(1), samples)
+        for a in 1:n, b in a+1:n
+            (samples[a] < 0 || samples[b] < 0) && continue
+            overlap[a, b] += 1
+            samples[a] != samples[b] && (disagree[a, b] += 1)
+        end
+    end
+
+    conflicts = [(a, b, overlap[a, b] * grid_mm^3) for a in 1:n for b in a+1:n
+                 if disagree[a, b] > MERGE_CONFLICT_FRACTION * overlap[a, b]]
+    return merged, lo, conflicts
+end
+
+@post "/scaffold/merge" function(req::HTTP.Request)
+    try
+        data = json(req)
+        output_name = data["output_name"]
+        parts = data["parts"]
+        if length(parts) < 2
+            return HTTP.Response(400, JSON.json(Dict("error" => "merging needs at least 2 scaffolds")))
+        end
+
+        volumes = [segment_scaffold(preprocess_image(load_image(part["file_path"]))) for part in parts]
+        voxel_sizes = [Tuple(Float64.(part["voxel_size_mm"])) for part in parts]
+        transforms = [part["transform"] for part in parts]
+        # The desktop has checked that all parts share (about) one voxel size
+        grid_mm = minimum(voxel_sizes[1])
+        merged, origin_mm, conflicts = merge_volumes(volumes, voxel_sizes, transforms, grid_mm)
+
+        if !isempty(conflicts)
+            return HTTP.Response(409, JSON.json(Dict(
+                "error" => "merged scaffolds overlap with different structure",
+                "conflicts" => [Dict("file_ids" => [parts[a]["file_id"], parts[b]["file_id"]],
+                                     "overlap_mm3" => volume_mm3) for (a, b, volume_mm3) in conflicts]
+            )))
+        end
+
+        safe_name = replace(output_name, r"[^A-Za-z0-9_-]" => "_")
+        output_path = "/tmp/merged_$(safe_name)_$(time()).nii"
+        niwrite(output_path, NIVolume(Float32.(merged); voxel_size=(grid_mm, grid_mm, grid_mm)))
+
+        return Dict(
+            "file_path" => output_path,
+            "volume_shape" => size(merged),
+            "origin_mm" => origin_mm
+        )
+    catch e
+        @error "Scaffold merge failed" exception=(e, catch_backtrace())
+        return HTTP.Response(500, JSON.json(Dict("error" => string(e))))
+    end
+end
+
 # ============================================================================
 # Phase 2: Export Endpoints
 # ============================================================================
 
+# Format conversion; darwin-server has already checked the pair against its matrix
+@post "/convert" function(req::HTTP.Request)
+    try
+        data = json(req)
+        input_path = data["input_path"]
+        output_path = data["output_path"]
+        target = data["target_format"]
+
+        if data["source_format"] in ("stl", "obj", "ply")
+            # MeshIO picks reader and writer from the file extensions
+            save(output_path, load(input_path))
+        else
+            volume = load_image(input_path)
+            scaled = volume ./ max(maximum(volume), eps())
+            if target == "nifti"
+                niwrite(output_path, NIVolume(Float32.(volume)))
+            elseif target == "tiff"
+                save(output_path, Gray.(scaled))
+            elseif target == "raw"
+                write(output_path, round.(UInt8, clamp.(scaled, 0, 1) .* 255))
+            elseif target == "stl"
+                binary = segment_scaffold(preprocess_image(volume))
+                export_stl(create_mesh(binary, Float64(get(data, "voxel_size", 10.0))), output_path)
+            else
+                return HTTP.Response(400, JSON.json(Dict("error" => "Unsupported target format $(target)")))
+            end
+        end
+
+        return Dict(
+            "file_path" => output_path,
+            "size_bytes" => filesize(output_path)
+        )
+    catch e
+        @error "Conversion failed" exception=(e, catch_backtrace())
+        return HTTP.Response(500, JSON.json(Dict("error" => string(e))))
+    end
+end
+
 @post "/export/stl" function(req::HTTP.Request)
     try
         data = json(req)
@@ -697,24 +1956,45 @@ end
         smoothing = get(data, "smoothing", true)
         binary_format = get(data, "binary", true)
 
+        mesh_revision = get(data, "mesh_revision", nothing)
+
         ws = get_workspace(workspace_id)
         if isnothing(ws) || isnothing(ws.volume)
             return HTTP.Response(404, JSON.json(Dict("error" => "No volume data")))
         end
 
-        volume = ws.volume
-        if smoothing
-            volume = smooth_volume(volume)
-        end
-
-        voxel_size = 10.0
-        mesh = create_mesh(volume, voxel_size; quality=quality)
-
         output_path = "/tmp/export_$(ws.id)_$(time()).stl"
-        export_stl(mesh, output_path; binary=binary_format)
+        if !isnothing(mesh_revision)
+            # A simplified mesh from /mesh/simplify instead of the full voxel surface
+            revisions = get(MESH_REVISIONS, workspace_id, [])
+            if !(1 <= mesh_revision <= length(revisions))
+                return HTTP.Response(404, JSON.json(Dict("error" => "Unknown mesh revision $(mesh_revision)")))
+            end
+            vertices, faces = revisions[mesh_revision]
+            DarwinScaffoldStudio.Export.export_stl_simple(vertices, faces, output_path)
+        else
+            volume = ws.volume
+            if smoothing
+                volume = smooth_volume(volume)
+            end
+
+            voxel_size = 10.0
+            mesh = create_mesh(volume, voxel_size; quality=quality)
+            export_stl(mesh, output_path; binary=binary_format)
+        end
 
         file_size = filesize(output_path)
 
+        # Streamed clients write the file themselves and track progress against X-Export-Size
+        if get(data, "stream", false)
+            body = read(output_path)
+            rm(output_path; force=true)
+            return HTTP.Response(200, [
+                "Content-Type" => "model/stl",
+                "X-Export-Size" => string(file_size)
+            ], body)
+        end
+
         return Dict(
             "file_path" => output_path,
             "size_bytes" => file_size
@@ -981,9 +2261,18 @@ function build_agent_system_prompt(agent_type::String, context::Dict)
     Current scaffold context:
     - Material: $(get(context, "material", "Not specified"))
     - Target tissue: $(get(context, "tissue", "Not specified"))
-    - Workspace ID: $(get(context, "workspace_id", "None"))
+    - Workspace ID: $(something(get(context, "workspace_id", nothing), "None"))
     """
 
+    selected_metrics = get(context, "selected_metrics", [])
+    if !isempty(selected_metrics)
+        base_context *= "- The user is asking about: $(join(selected_metrics, ", "))\n"
+    end
+    attached_files = get(context, "attached_files", [])
+    if !isempty(attached_files)
+        base_context *= "- Attached files: $(join(basename.(attached_files), ", "))\n"
+    end
+
     if haskey(context, "metrics") && !isnothing(context["metrics"])
         metrics = context["metrics"]
         base_context *= """
@@ -1209,9 +2498,14 @@ port = 8081
 @info "API Documentation:"
 @info "  POST /workspace/create - Create new workspace"
 @info "  GET  /workspace/{id}/metrics - Get workspace metrics"
+@info "  GET  /workspace/{id}/snapshot - Serialize workspace state"
+@info "  POST /workspace/restore - Restore a workspace snapshot"
 @info "  POST /tpms/generate - Generate TPMS scaffold"
 @info "  POST /validation/check - Validate scaffold against literature"
+@info "  GET  /progress/stream - Progress of /analyze and /optimize, NDJSON"
+@info "  GET  /logs/stream - Recent and new server log records, NDJSON"
 @info "  POST /agents/chat - Chat with AI agent"
+@info "  POST /agents/chat/stream - Chat with AI agent, NDJSON events"
 @info "  POST /export/stl - Export STL mesh"
 @info "  POST /export/gcode - Generate G-code"
 serve(port=port, middleware=[add_cors])